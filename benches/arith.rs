@@ -0,0 +1,111 @@
+//! Runs examples/arith.cool on its test input with boxed and with unboxed
+//! Ints and Bools: with the JIT, reporting the objects each allocates and
+//! the time each takes, and as x86-64 executables where a C compiler can
+//! link them, reporting the time each takes. Run with `cargo bench`.
+
+use coolc::codegen::{emit_x86_64, link_x86_64, Representation};
+use coolc::interpreter::STACK_SIZE;
+use coolc::jit::Jit;
+use coolc::lexer::lex_tokens;
+use coolc::parser::parse_program;
+use coolc::semant::check_program;
+use std::fs::{read_to_string, remove_file};
+use std::io::{sink, Write};
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+const RUNS: u32 = 20;
+
+const REPRESENTATIONS: [Representation; 2] =
+    [Representation::Boxed, Representation::Unboxed];
+
+fn main() {
+    thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(bench)
+        .unwrap()
+        .join()
+        .unwrap();
+}
+
+// The best and mean of some times, in milliseconds.
+fn summary(times: &[Duration]) -> (f64, f64) {
+    let best = times.iter().min().unwrap().as_secs_f64() * 1000.0;
+    let mean =
+        times.iter().sum::<Duration>().as_secs_f64() * 1000.0 / RUNS as f64;
+    (best, mean)
+}
+
+fn bench() {
+    let root = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    let source_code = read_to_string(root.join("examples/arith.cool")).unwrap();
+    let input = read_to_string(root.join("tests/resources/arith.in")).unwrap();
+    let (_, tokens) = lex_tokens(&source_code, "arith.cool").unwrap();
+    let (_, mut program) = parse_program(&tokens).unwrap();
+    let hierarchy = check_program(&mut program).unwrap();
+
+    println!("JIT");
+    println!(
+        "{:<14}  {:>11}  {:>10}  {:>10}",
+        "Representation", "Allocations", "Best (ms)", "Mean (ms)"
+    );
+    for representation in REPRESENTATIONS {
+        let jit =
+            Jit::with_representation(&program, &hierarchy, representation)
+                .unwrap();
+        let times: Vec<Duration> = (0..RUNS)
+            .map(|_| {
+                let start = Instant::now();
+                jit.run(&mut input.as_bytes(), &mut sink()).unwrap();
+                start.elapsed()
+            })
+            .collect();
+        let (best, mean) = summary(&times);
+        println!(
+            "{:<14}  {:>11}  {best:>10.3}  {mean:>10.3}",
+            format!("{representation:?}"),
+            jit.allocations(),
+        );
+    }
+
+    if !cfg!(all(target_arch = "x86_64", target_os = "linux"))
+        || Command::new("cc").arg("--version").output().is_err()
+    {
+        println!("\nSkipping x86-64: no x86-64 Linux C compiler.");
+        return;
+    }
+    println!("\nx86-64");
+    println!(
+        "{:<14}  {:>10}  {:>10}",
+        "Representation", "Best (ms)", "Mean (ms)"
+    );
+    for representation in REPRESENTATIONS {
+        let assembly = emit_x86_64(&program, &hierarchy, representation);
+        let executable = std::env::temp_dir()
+            .join(format!("coolc-arith-{}", std::process::id()));
+        link_x86_64(&assembly, &executable).unwrap();
+        let times: Vec<Duration> = (0..RUNS)
+            .map(|_| {
+                let start = Instant::now();
+                let mut child = Command::new(&executable)
+                    .stdin(Stdio::piped())
+                    .stdout(Stdio::null())
+                    .spawn()
+                    .unwrap();
+                let mut stdin = child.stdin.take().unwrap();
+                stdin.write_all(input.as_bytes()).unwrap();
+                drop(stdin);
+                assert!(child.wait().unwrap().success());
+                start.elapsed()
+            })
+            .collect();
+        remove_file(&executable).unwrap();
+        let (best, mean) = summary(&times);
+        println!(
+            "{:<14}  {best:>10.3}  {mean:>10.3}",
+            format!("{representation:?}"),
+        );
+    }
+}
@@ -0,0 +1,463 @@
+//! Compilation of a typed parse tree into bytecode.
+
+use super::*;
+use crate::hierarchy::*;
+use crate::ptree::*;
+use crate::semant::SymbolTable;
+use ExpressionData::*;
+
+/// Compile a program that has passed semantic analysis, which provides the
+/// static types used to select vtable slots.
+pub fn compile<'a>(
+    program: &Program<'a>,
+    hierarchy: &ClassHierarchy<'a>,
+) -> Module<'a> {
+    let mut compiler = Compiler {
+        module: Module {
+            constants: ConstantPool::default(),
+            classes: Vec::new(),
+            functions: Vec::new(),
+        },
+        class_ids: HashMap::new(),
+    };
+    compiler.lay_out_classes(program, hierarchy);
+    for class in program.classes.iter() {
+        compiler.compile_class(class);
+    }
+    compiler.module
+}
+
+struct Compiler<'a> {
+    module: Module<'a>,
+    class_ids: HashMap<String, ClassId>,
+}
+
+impl<'a> Compiler<'a> {
+    // Classes are laid out parents first, so that each class can start from
+    // the attributes and vtable of its parent. Functions are allocated here
+    // and their code is compiled once all classes are known.
+    fn lay_out_classes(
+        &mut self,
+        program: &Program<'a>,
+        hierarchy: &ClassHierarchy<'a>,
+    ) {
+        let definitions: HashMap<&str, &Class> = program
+            .classes
+            .iter()
+            .map(|class| (class.name.as_str(), class))
+            .collect();
+        let mut infos: Vec<&ClassInfo> = hierarchy.classes().collect();
+        infos.sort_by_key(|info| hierarchy.depth(&info.name));
+
+        for info in infos {
+            let class_id = self.module.classes.len();
+            let parent = info.parent.as_ref().map(|name| self.class_ids[name]);
+            let (mut attributes, mut vtable, parent_init) = match parent {
+                Some(parent_id) => {
+                    let parent = &self.module.classes[parent_id];
+                    (
+                        parent.attributes.clone(),
+                        parent.vtable.clone(),
+                        parent.init,
+                    )
+                }
+                None => (Vec::new(), Vec::new(), None),
+            };
+            let definition = definitions.get(info.name.as_str());
+
+            for attr in info.attributes.iter() {
+                attributes.push((attr.name.clone(), attr.type_id.clone()));
+            }
+            for method in info.methods.iter() {
+                let method_ref = match definition {
+                    Some(_) => MethodRef::Function(self.allocate_function(
+                        format!("{}.{}", info.name, method.name),
+                        method.formals.len(),
+                    )),
+                    None => MethodRef::Builtin(
+                        Builtin::find(&info.name, &method.name)
+                            .expect("basic class method"),
+                    ),
+                };
+                let entry = VtableEntry {
+                    name: method.name.clone(),
+                    owner: class_id,
+                    method: method_ref,
+                };
+                match vtable.iter().position(|e| e.name == method.name) {
+                    Some(slot) => vtable[slot] = entry,
+                    None => vtable.push(entry),
+                }
+            }
+
+            // Classes without initialisers of their own share the
+            // initialiser function of their parent.
+            let init = if definition
+                .is_some_and(|class| has_initialisers(class))
+            {
+                Some(self.allocate_function(format!("{}.<init>", info.name), 0))
+            } else {
+                parent_init
+            };
+
+            self.class_ids.insert(info.name.clone(), class_id);
+            self.module.classes.push(ClassDescriptor {
+                name: info.name.clone(),
+                parent,
+                attributes,
+                vtable,
+                init,
+            });
+        }
+    }
+
+    fn allocate_function(&mut self, name: String, arity: usize) -> FunctionId {
+        self.module.functions.push(Function::new(name, arity));
+        self.module.functions.len() - 1
+    }
+
+    fn compile_class(&mut self, class: &Class<'a>) {
+        let class_id = self.class_ids[&class.name];
+        if has_initialisers(class) {
+            let descriptor = &self.module.classes[class_id];
+            let function_id = descriptor.init.expect("initialiser function");
+            let parent_init = descriptor
+                .parent
+                .filter(|parent| self.module.classes[*parent].init.is_some());
+            let mut builder = self.function_builder(class_id, function_id);
+            if let Some(parent) = parent_init {
+                builder.emit(Instruction::Initialize(parent as u16));
+                builder.emit(Instruction::Pop);
+            }
+            for feature in class.features.iter() {
+                if let FeatureData::Attribute(name, _, Some(init)) =
+                    &feature.data
+                {
+                    builder.expression(init);
+                    let index = builder.attribute_index(name);
+                    builder.emit(Instruction::StoreAttribute(index));
+                }
+            }
+            builder.emit(Instruction::LoadSelf);
+            builder.emit(Instruction::Return);
+            builder.finish();
+        }
+
+        for feature in class.features.iter() {
+            if let FeatureData::Method(name, _, formals, body) = &feature.data {
+                let descriptor = &self.module.classes[class_id];
+                let slot = descriptor.slot(name).expect("method slot");
+                let function_id = match descriptor.vtable[slot].method {
+                    MethodRef::Function(function_id) => function_id,
+                    MethodRef::Builtin(_) => unreachable!(),
+                };
+                let mut builder = self.function_builder(class_id, function_id);
+                builder.scopes.enter_scope();
+                for formal in formals.iter() {
+                    let slot = builder.allocate_slot();
+                    builder.scopes.insert(formal.name.clone(), slot);
+                }
+                builder.expression(body);
+                builder.emit(Instruction::Return);
+                builder.finish();
+            }
+        }
+    }
+
+    fn function_builder(
+        &mut self,
+        class_id: ClassId,
+        function_id: FunctionId,
+    ) -> FunctionBuilder<'_, 'a> {
+        let function = &self.module.functions[function_id];
+        let function = Function::new(function.name.clone(), function.arity);
+        FunctionBuilder {
+            compiler: self,
+            class_id,
+            function_id,
+            function,
+            scopes: SymbolTable::new(),
+            next_slot: 0,
+        }
+    }
+}
+
+fn has_initialisers(class: &Class) -> bool {
+    class.features.iter().any(|feature| {
+        matches!(feature.data, FeatureData::Attribute(_, _, Some(_)))
+    })
+}
+
+enum Variable {
+    SelfObject,
+    Local(u16),
+    Attribute(u16),
+}
+
+struct FunctionBuilder<'c, 'a> {
+    compiler: &'c mut Compiler<'a>,
+    class_id: ClassId,
+    function_id: FunctionId,
+    function: Function<'a>,
+    scopes: SymbolTable<String, u16>,
+    next_slot: usize,
+}
+
+impl<'c, 'a> FunctionBuilder<'c, 'a> {
+    fn finish(self) {
+        self.compiler.module.functions[self.function_id] = self.function;
+    }
+
+    fn offset(&self) -> u32 {
+        self.function.code.len() as u32
+    }
+
+    fn emit(&mut self, instruction: Instruction) -> usize {
+        self.function.code.push(instruction);
+        self.function.code.len() - 1
+    }
+
+    // Emit an instruction that can fail at runtime, recording its location.
+    fn emit_at(&mut self, instruction: Instruction, location: Span<'a>) {
+        let offset = self.offset();
+        self.function.locations.push((offset, location));
+        self.emit(instruction);
+    }
+
+    // Point a forward jump at the next instruction to be emitted.
+    fn patch(&mut self, jump: usize) {
+        let target = self.offset();
+        match &mut self.function.code[jump] {
+            Instruction::Jump(offset) | Instruction::JumpIfFalse(offset) => {
+                *offset = target
+            }
+            _ => unreachable!("not a jump"),
+        }
+    }
+
+    // Local slots are reused once the scope of their binding ends.
+    fn allocate_slot(&mut self) -> u16 {
+        let slot = self.next_slot;
+        self.next_slot += 1;
+        self.function.locals = self.function.locals.max(self.next_slot);
+        slot as u16
+    }
+
+    fn release_slot(&mut self) {
+        self.next_slot -= 1;
+    }
+
+    fn constant(&mut self, constant: Constant) {
+        let index = self.compiler.module.constants.intern(constant);
+        self.emit(Instruction::Constant(index));
+    }
+
+    fn class_id(&self, type_id: &str) -> ClassId {
+        if type_id == SELF_TYPE {
+            self.class_id
+        } else {
+            self.compiler.class_ids[type_id]
+        }
+    }
+
+    fn attribute_index(&self, name: &str) -> u16 {
+        self.compiler.module.classes[self.class_id]
+            .attributes
+            .iter()
+            .position(|(attr, _)| attr == name)
+            .expect("declared attribute") as u16
+    }
+
+    fn variable(&self, name: &str) -> Variable {
+        if name == SELF {
+            Variable::SelfObject
+        } else if let Some(slot) = self.scopes.lookup(name) {
+            Variable::Local(*slot)
+        } else {
+            Variable::Attribute(self.attribute_index(name))
+        }
+    }
+
+    fn default_value(&mut self, type_id: &str) {
+        match type_id {
+            INT => self.constant(Constant::Int(0)),
+            STRING => self.constant(Constant::Str(String::new())),
+            BOOL => {
+                self.emit(Instruction::False);
+            }
+            _ => {
+                self.emit(Instruction::Void);
+            }
+        }
+    }
+
+    // Compile an expression, leaving its value on the stack.
+    fn expression(&mut self, expr: &Expression<'a>) {
+        match &expr.data {
+            Block(expressions) => {
+                for (index, expression) in expressions.iter().enumerate() {
+                    if index > 0 {
+                        self.emit(Instruction::Pop);
+                    }
+                    self.expression(expression);
+                }
+            }
+            Conditional(if_expr, then_expr, else_expr) => {
+                self.expression(if_expr);
+                let to_else = self.emit(Instruction::JumpIfFalse(0));
+                self.expression(then_expr);
+                let to_end = self.emit(Instruction::Jump(0));
+                self.patch(to_else);
+                self.expression(else_expr);
+                self.patch(to_end);
+            }
+            Loop(cond_expr, loop_expr) => {
+                let start = self.offset();
+                self.expression(cond_expr);
+                let to_end = self.emit(Instruction::JumpIfFalse(0));
+                self.expression(loop_expr);
+                self.emit(Instruction::Pop);
+                self.emit(Instruction::Jump(start));
+                self.patch(to_end);
+                self.emit(Instruction::Void);
+            }
+            Case(case_expr, branches) => {
+                self.expression(case_expr);
+                let slot = self.allocate_slot();
+                let table = self.function.case_tables.len();
+                self.function.case_tables.push(CaseTable {
+                    slot,
+                    branches: Vec::new(),
+                });
+                self.emit_at(Instruction::Case(table as u16), expr.location);
+                let mut to_end = Vec::with_capacity(branches.len());
+                for branch in branches.iter() {
+                    let class_id = self.class_id(&branch.type_id);
+                    let target = self.offset();
+                    self.function.case_tables[table]
+                        .branches
+                        .push((class_id, target));
+                    self.scopes.enter_scope();
+                    self.scopes.insert(branch.ident.clone(), slot);
+                    self.expression(&branch.expression);
+                    self.scopes.exit_scope();
+                    to_end.push(self.emit(Instruction::Jump(0)));
+                }
+                for jump in to_end {
+                    self.patch(jump);
+                }
+                self.release_slot();
+            }
+            Let(ident, type_id, opt_bind, body) => {
+                match &**opt_bind {
+                    Some(bind) => self.expression(bind),
+                    None => self.default_value(type_id),
+                }
+                let slot = self.allocate_slot();
+                self.emit(Instruction::StoreLocal(slot));
+                self.scopes.enter_scope();
+                self.scopes.insert(ident.clone(), slot);
+                self.expression(body);
+                self.scopes.exit_scope();
+                self.release_slot();
+            }
+            New(type_id) => {
+                if type_id == SELF_TYPE {
+                    self.emit(Instruction::NewSelfType);
+                } else {
+                    let class_id = self.class_id(type_id);
+                    self.emit(Instruction::New(class_id as u16));
+                }
+            }
+            Assign(ident, value) => {
+                self.expression(value);
+                self.emit(Instruction::Dup);
+                match self.variable(ident) {
+                    Variable::Local(slot) => {
+                        self.emit(Instruction::StoreLocal(slot));
+                    }
+                    Variable::Attribute(index) => {
+                        self.emit(Instruction::StoreAttribute(index));
+                    }
+                    Variable::SelfObject => unreachable!("assignment to self"),
+                }
+            }
+            UnaryOperation(operator, operand) => {
+                self.expression(operand);
+                self.emit(match operator {
+                    UnaryOperator::Not => Instruction::Not,
+                    UnaryOperator::Negative => Instruction::Negate,
+                    UnaryOperator::IsVoid => Instruction::IsVoid,
+                });
+            }
+            BinaryOperation(operator, operand1, operand2) => {
+                self.expression(operand1);
+                self.expression(operand2);
+                let instruction = match operator {
+                    BinaryOperator::Equals => Instruction::Equals,
+                    BinaryOperator::LessThanOrEquals => {
+                        Instruction::LessThanOrEquals
+                    }
+                    BinaryOperator::LessThan => Instruction::LessThan,
+                    BinaryOperator::Add => Instruction::Add,
+                    BinaryOperator::Subtract => Instruction::Subtract,
+                    BinaryOperator::Multiply => Instruction::Multiply,
+                    BinaryOperator::Divide => Instruction::Divide,
+                };
+                if instruction == Instruction::Divide {
+                    self.emit_at(instruction, expr.location);
+                } else {
+                    self.emit(instruction);
+                }
+            }
+            MethodCall(callee, static_type, ident, params) => {
+                // Arguments are evaluated before the receiver.
+                for param in params.iter() {
+                    self.expression(param);
+                }
+                self.expression(callee);
+                let dispatch_type = match static_type {
+                    Some(type_id) => type_id.as_str(),
+                    None => callee.static_type.as_deref().expect("typed tree"),
+                };
+                let class_id = self.class_id(dispatch_type);
+                let slot = self.compiler.module.classes[class_id]
+                    .slot(ident)
+                    .expect("method slot") as u16;
+                let argc = params.len() as u16;
+                let instruction = match static_type {
+                    Some(_) => Instruction::StaticDispatch {
+                        class: class_id as u16,
+                        slot,
+                        argc,
+                    },
+                    None => Instruction::Dispatch {
+                        class: class_id as u16,
+                        slot,
+                        argc,
+                    },
+                };
+                self.emit_at(instruction, expr.location);
+            }
+            Object(ident) => {
+                match self.variable(ident) {
+                    Variable::SelfObject => self.emit(Instruction::LoadSelf),
+                    Variable::Local(slot) => {
+                        self.emit(Instruction::LoadLocal(slot))
+                    }
+                    Variable::Attribute(index) => {
+                        self.emit(Instruction::LoadAttribute(index))
+                    }
+                };
+            }
+            IntLiteral(integer) => self.constant(Constant::Int(*integer)),
+            StrLiteral(string) => self.constant(Constant::Str(string.clone())),
+            BoolLiteral(true) => {
+                self.emit(Instruction::True);
+            }
+            BoolLiteral(false) => {
+                self.emit(Instruction::False);
+            }
+        }
+    }
+}
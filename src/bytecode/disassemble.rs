@@ -0,0 +1,174 @@
+//! A human-readable listing of a bytecode module.
+
+use super::*;
+use crate::util::escape_str;
+use std::fmt::{Display, Formatter, Result};
+
+pub struct Disassembly<'m, 'a> {
+    module: &'m Module<'a>,
+}
+
+impl<'m, 'a> Disassembly<'m, 'a> {
+    pub fn new(module: &'m Module<'a>) -> Self {
+        Self { module }
+    }
+
+    fn class_name(&self, class_id: impl Into<usize>) -> &str {
+        &self.module.classes[class_id.into()].name
+    }
+
+    fn method_name(&self, class_id: u16, slot: u16) -> &str {
+        &self.module.classes[class_id as usize].vtable[slot as usize].name
+    }
+
+    fn fmt_constant(
+        &self,
+        f: &mut Formatter<'_>,
+        constant: &Constant,
+    ) -> Result {
+        match constant {
+            Constant::Int(integer) => write!(f, "Int {integer}"),
+            Constant::Str(string) => {
+                write!(f, "String \"{}\"", escape_str(string))
+            }
+        }
+    }
+
+    fn fmt_class(
+        &self,
+        f: &mut Formatter<'_>,
+        class: &ClassDescriptor,
+    ) -> Result {
+        write!(f, "class {}", class.name)?;
+        if let Some(parent) = class.parent {
+            write!(f, " inherits {}", self.class_name(parent))?;
+        }
+        writeln!(f)?;
+        if let Some(init) = class.init {
+            writeln!(f, "    init: fn {init}")?;
+        }
+        for (index, (name, type_id)) in class.attributes.iter().enumerate() {
+            writeln!(f, "    attribute {index:<3} {name} : {type_id}")?;
+        }
+        for (slot, entry) in class.vtable.iter().enumerate() {
+            let owner = self.class_name(entry.owner);
+            write!(f, "    method {slot:<3} {owner}.{}", entry.name)?;
+            match entry.method {
+                MethodRef::Builtin(_) => writeln!(f, " (builtin)")?,
+                MethodRef::Function(function) => {
+                    writeln!(f, " -> fn {function}")?
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn fmt_instruction(
+        &self,
+        f: &mut Formatter<'_>,
+        instruction: Instruction,
+    ) -> Result {
+        match instruction {
+            Instruction::Constant(index) => {
+                write!(f, "constant #{index:<9} ; ")?;
+                self.fmt_constant(f, self.module.constants.get(index))
+            }
+            Instruction::True => write!(f, "true"),
+            Instruction::False => write!(f, "false"),
+            Instruction::Void => write!(f, "void"),
+            Instruction::LoadSelf => write!(f, "load_self"),
+            Instruction::LoadLocal(slot) => write!(f, "load_local {slot}"),
+            Instruction::StoreLocal(slot) => write!(f, "store_local {slot}"),
+            Instruction::LoadAttribute(index) => {
+                write!(f, "load_attribute {index}")
+            }
+            Instruction::StoreAttribute(index) => {
+                write!(f, "store_attribute {index}")
+            }
+            Instruction::Pop => write!(f, "pop"),
+            Instruction::Dup => write!(f, "dup"),
+            Instruction::New(class_id) => {
+                write!(f, "new {}", self.class_name(class_id))
+            }
+            Instruction::NewSelfType => write!(f, "new_self_type"),
+            Instruction::Initialize(class_id) => {
+                write!(f, "initialize {}", self.class_name(class_id))
+            }
+            Instruction::Dispatch { class, slot, argc } => write!(
+                f,
+                "dispatch {}.{} slot {slot} argc {argc}",
+                self.class_name(class),
+                self.method_name(class, slot)
+            ),
+            Instruction::StaticDispatch { class, slot, argc } => write!(
+                f,
+                "static_dispatch {}.{} slot {slot} argc {argc}",
+                self.class_name(class),
+                self.method_name(class, slot)
+            ),
+            Instruction::Jump(target) => write!(f, "jump {target:04}"),
+            Instruction::JumpIfFalse(target) => {
+                write!(f, "jump_if_false {target:04}")
+            }
+            Instruction::Case(table) => write!(f, "case table {table}"),
+            Instruction::Add => write!(f, "add"),
+            Instruction::Subtract => write!(f, "subtract"),
+            Instruction::Multiply => write!(f, "multiply"),
+            Instruction::Divide => write!(f, "divide"),
+            Instruction::Negate => write!(f, "negate"),
+            Instruction::LessThan => write!(f, "less_than"),
+            Instruction::LessThanOrEquals => write!(f, "less_than_or_equals"),
+            Instruction::Equals => write!(f, "equals"),
+            Instruction::Not => write!(f, "not"),
+            Instruction::IsVoid => write!(f, "is_void"),
+            Instruction::Return => write!(f, "return"),
+        }
+    }
+
+    fn fmt_function(
+        &self,
+        f: &mut Formatter<'_>,
+        function_id: FunctionId,
+        function: &Function,
+    ) -> Result {
+        writeln!(
+            f,
+            "fn {function_id} {} (arity {}, locals {})",
+            function.name, function.arity, function.locals
+        )?;
+        for (offset, instruction) in function.code.iter().enumerate() {
+            write!(f, "    {offset:04}  ")?;
+            self.fmt_instruction(f, *instruction)?;
+            writeln!(f)?;
+        }
+        for (index, table) in function.case_tables.iter().enumerate() {
+            writeln!(f, "    case table {index}, slot {}", table.slot)?;
+            for (class_id, target) in table.branches.iter() {
+                let class_name = self.class_name(*class_id);
+                writeln!(f, "        {class_name} -> {target:04}")?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Display for Disassembly<'_, '_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        writeln!(f, "constants:")?;
+        for (index, constant) in self.module.constants.iter().enumerate() {
+            write!(f, "    #{index:<4} ")?;
+            self.fmt_constant(f, constant)?;
+            writeln!(f)?;
+        }
+        for class in self.module.classes.iter() {
+            writeln!(f)?;
+            self.fmt_class(f, class)?;
+        }
+        for (function_id, function) in self.module.functions.iter().enumerate()
+        {
+            writeln!(f)?;
+            self.fmt_function(f, function_id, function)?;
+        }
+        Ok(())
+    }
+}
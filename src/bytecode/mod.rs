@@ -0,0 +1,213 @@
+//! A compact bytecode for Cool and a stack-based virtual machine to run it.
+//!
+//! A typed program is compiled into a [`Module`]: one function per method
+//! and per attribute initialiser, a constant pool holding every distinct
+//! literal, and a descriptor per class with its attribute layout and
+//! dispatch table (vtable). Methods are dispatched through vtable slots, which
+//! are laid out so that a method keeps the slot it has in its defining class
+//! in all subclasses.
+
+mod compiler;
+mod disassemble;
+mod vm;
+
+pub use self::compiler::compile;
+pub use self::disassemble::Disassembly;
+pub use self::vm::Machine;
+use crate::interpreter::{Builtin, ClassId};
+use crate::tokens::Span;
+use std::collections::HashMap;
+
+#[cfg(test)]
+mod tests;
+
+pub type FunctionId = usize;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Instruction {
+    /// Push a constant from the constant pool.
+    Constant(u32),
+    True,
+    False,
+    Void,
+    LoadSelf,
+    LoadLocal(u16),
+    /// Pop a value into a local slot.
+    StoreLocal(u16),
+    LoadAttribute(u16),
+    /// Pop a value into an attribute of self.
+    StoreAttribute(u16),
+    Pop,
+    Dup,
+    /// Create an object of a class, running its attribute initialisers.
+    New(u16),
+    /// Create an object of the class of self.
+    NewSelfType,
+    /// Run the attribute initialisers of a class on self, pushing self.
+    Initialize(u16),
+    /// Pop the receiver and `argc` arguments, which are pushed before the
+    /// receiver, and call the method in a slot of the receiver's vtable.
+    /// `class` is the static type of the receiver.
+    Dispatch {
+        class: u16,
+        slot: u16,
+        argc: u16,
+    },
+    /// Like `Dispatch`, using the vtable of `class`.
+    StaticDispatch {
+        class: u16,
+        slot: u16,
+        argc: u16,
+    },
+    Jump(u32),
+    /// Pop a Bool and jump if it is false.
+    JumpIfFalse(u32),
+    /// Pop a value and jump to the branch of a case table matching its class.
+    Case(u16),
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Negate,
+    LessThan,
+    LessThanOrEquals,
+    Equals,
+    Not,
+    IsVoid,
+    Return,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Constant {
+    Int(i32),
+    Str(String),
+}
+
+/// Literals of a program, each stored once.
+#[derive(Debug, Default)]
+pub struct ConstantPool {
+    constants: Vec<Constant>,
+    indices: HashMap<Constant, u32>,
+}
+
+impl ConstantPool {
+    /// Add a constant to the pool unless it is already there, returning its
+    /// index.
+    pub fn intern(&mut self, constant: Constant) -> u32 {
+        if let Some(index) = self.indices.get(&constant) {
+            return *index;
+        }
+        let index = self.constants.len() as u32;
+        self.constants.push(constant.clone());
+        self.indices.insert(constant, index);
+        index
+    }
+
+    pub fn get(&self, index: u32) -> &Constant {
+        &self.constants[index as usize]
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Constant> {
+        self.constants.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.constants.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.constants.is_empty()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MethodRef {
+    Builtin(Builtin),
+    Function(FunctionId),
+}
+
+#[derive(Clone, Debug)]
+pub struct VtableEntry {
+    pub name: String,
+    /// The class that provides the implementation.
+    pub owner: ClassId,
+    pub method: MethodRef,
+}
+
+#[derive(Clone, Debug)]
+pub struct ClassDescriptor {
+    pub name: String,
+    pub parent: Option<ClassId>,
+    /// Names and types of the attributes of the class and its ancestors, in
+    /// inheritance order.
+    pub attributes: Vec<(String, String)>,
+    pub vtable: Vec<VtableEntry>,
+    /// The attribute initialisers of the class and its ancestors, if any
+    /// attribute has an initialiser.
+    pub init: Option<FunctionId>,
+}
+
+impl ClassDescriptor {
+    pub fn slot(&self, method: &str) -> Option<usize> {
+        self.vtable.iter().position(|entry| entry.name == method)
+    }
+}
+
+/// Branches of a `case` expression. The matched value is stored in `slot`
+/// before jumping to the branch.
+#[derive(Clone, Debug, Default)]
+pub struct CaseTable {
+    pub slot: u16,
+    pub branches: Vec<(ClassId, u32)>,
+}
+
+#[derive(Debug)]
+pub struct Function<'a> {
+    pub name: String,
+    pub arity: usize,
+    /// Number of local slots, formal parameters included.
+    pub locals: usize,
+    pub code: Vec<Instruction>,
+    pub case_tables: Vec<CaseTable>,
+    /// Source locations of the instructions that can fail, sorted by
+    /// instruction offset.
+    pub locations: Vec<(u32, Span<'a>)>,
+}
+
+impl<'a> Function<'a> {
+    pub fn new(name: String, arity: usize) -> Self {
+        Self {
+            name,
+            arity,
+            locals: arity,
+            code: Vec::new(),
+            case_tables: Vec::new(),
+            locations: Vec::new(),
+        }
+    }
+
+    pub fn location(&self, offset: usize) -> Option<Span<'a>> {
+        self.locations
+            .binary_search_by_key(&(offset as u32), |(at, _)| *at)
+            .ok()
+            .map(|index| self.locations[index].1)
+    }
+}
+
+#[derive(Debug)]
+pub struct Module<'a> {
+    pub constants: ConstantPool,
+    /// Classes sorted so that every class comes after its parent.
+    pub classes: Vec<ClassDescriptor>,
+    pub functions: Vec<Function<'a>>,
+}
+
+impl<'a> Module<'a> {
+    pub fn class_id(&self, name: &str) -> Option<ClassId> {
+        self.classes.iter().position(|class| class.name == name)
+    }
+
+    pub fn disassemble(&self) -> Disassembly<'_, 'a> {
+        Disassembly::new(self)
+    }
+}
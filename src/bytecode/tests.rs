@@ -0,0 +1,345 @@
+use super::*;
+use crate::interpreter::{Interpreter, Limits};
+use crate::lexer::lex_tokens;
+use crate::parser::parse_program;
+use crate::semant::check_program;
+
+// Compile a program and pass the module to `f`, since the module borrows
+// from the source.
+fn with_module<T>(source: &str, f: impl FnOnce(&Module) -> T) -> T {
+    let (_, tokens) = lex_tokens(source, "test.cl").unwrap();
+    let (_, mut program) = parse_program(&tokens).unwrap();
+    let hierarchy = check_program(&mut program).unwrap();
+    f(&compile(&program, &hierarchy))
+}
+
+fn run(source: &str) -> (String, Option<(String, i32)>) {
+    run_with_limits(Limits::default(), source)
+}
+
+fn run_with_limits(
+    limits: Limits,
+    source: &str,
+) -> (String, Option<(String, i32)>) {
+    with_module(source, |module| {
+        let mut output = Vec::new();
+        let mut machine = Machine::new(
+            module,
+            Box::new("".as_bytes()),
+            Box::new(&mut output),
+        );
+        machine.set_limits(limits);
+        let result = machine.run();
+        drop(machine);
+        let error = result.err().map(|err| (err.to_string(), err.exit_code()));
+        (String::from_utf8(output).unwrap(), error)
+    })
+}
+
+fn function<'m, 'a>(module: &'m Module<'a>, name: &str) -> &'m Function<'a> {
+    module
+        .functions
+        .iter()
+        .find(|function| function.name == name)
+        .unwrap()
+}
+
+#[test]
+fn test_constants_are_interned() {
+    with_module(
+        "class Main inherits IO { main() : Object {{\
+             out_string(\"a\"); out_int(1); out_string(\"a\");\
+             out_int(1); out_int(2); out_string(\"b\");\
+         }}; };",
+        |module| {
+            let constants: Vec<&Constant> = module.constants.iter().collect();
+            assert_eq!(
+                constants,
+                [
+                    &Constant::Str("a".to_string()),
+                    &Constant::Int(1),
+                    &Constant::Int(2),
+                    &Constant::Str("b".to_string()),
+                ]
+            );
+        },
+    );
+}
+
+#[test]
+fn test_vtable_layout() {
+    with_module(
+        "class A { f() : Int { 1 }; g() : Int { 2 }; };\
+         class B inherits A { g() : Int { 3 }; h() : Int { 4 }; };\
+         class Main { main() : Object { 0 }; };",
+        |module| {
+            let class_a = &module.classes[module.class_id("A").unwrap()];
+            let class_b = &module.classes[module.class_id("B").unwrap()];
+            let object = &module.classes[module.class_id("Object").unwrap()];
+
+            // Inherited and overridden methods keep their slots
+            assert_eq!(class_a.slot("abort"), object.slot("abort"));
+            assert_eq!(class_a.slot("f"), class_b.slot("f"));
+            assert_eq!(class_a.slot("g"), class_b.slot("g"));
+            assert_eq!(class_b.slot("h"), Some(class_a.vtable.len()));
+
+            let owner = |class: &ClassDescriptor, method| {
+                let entry = &class.vtable[class.slot(method).unwrap()];
+                module.classes[entry.owner].name.as_str()
+            };
+            assert_eq!(owner(class_b, "f"), "A");
+            assert_eq!(owner(class_b, "g"), "B");
+            assert_eq!(owner(class_b, "type_name"), "Object");
+        },
+    );
+}
+
+#[test]
+fn test_classes_follow_their_parent() {
+    with_module(
+        "class C inherits B {}; class B inherits A {}; class A {};\
+         class Main { main() : Object { 0 }; };",
+        |module| {
+            for (class_id, class) in module.classes.iter().enumerate() {
+                if let Some(parent) = class.parent {
+                    assert!(parent < class_id, "{} before parent", class.name);
+                }
+            }
+        },
+    );
+}
+
+#[test]
+fn test_initialisers() {
+    with_module(
+        "class A { a : Int <- 1; };\
+         class B inherits A { b : Int; };\
+         class C inherits B { c : Int <- a + 1; };\
+         class Main { main() : Object { new C }; };",
+        |module| {
+            let class = |name| &module.classes[module.class_id(name).unwrap()];
+            assert_eq!(class("Main").init, None);
+            assert_eq!(class("B").init, class("A").init);
+            assert_eq!(
+                function(module, "C.<init>").code,
+                [
+                    Instruction::Initialize(
+                        module.class_id("B").unwrap() as u16
+                    ),
+                    Instruction::Pop,
+                    Instruction::LoadAttribute(0),
+                    Instruction::Constant(0),
+                    Instruction::Add,
+                    Instruction::StoreAttribute(2),
+                    Instruction::LoadSelf,
+                    Instruction::Return,
+                ]
+            );
+        },
+    );
+}
+
+#[test]
+fn test_local_slots_are_reused() {
+    with_module(
+        "class Main { main() : Object { 0 }; f(x : Int) : Int {{\
+             let a : Int <- x in let b : Int <- a in b;\
+             let c : Int in c;\
+         }}; };",
+        |module| {
+            let f = function(module, "Main.f");
+            assert_eq!(f.arity, 1);
+            assert_eq!(f.locals, 3);
+            assert!(f.code.contains(&Instruction::StoreLocal(2)));
+            assert!(!f.code.contains(&Instruction::StoreLocal(3)));
+        },
+    );
+}
+
+#[test]
+fn test_disassembly() {
+    let listing = with_module(
+        "class Main inherits IO { main() : Object {\
+             if true then out_string(\"yes\") else abort() fi\
+         }; };",
+        |module| module.disassemble().to_string(),
+    );
+    let main = listing.split("fn 0 ").nth(1).unwrap();
+    assert_eq!(
+        main,
+        "Main.main (arity 0, locals 0)
+    0000  true
+    0001  jump_if_false 0006
+    0002  constant #0         ; String \"yes\"
+    0003  load_self
+    0004  dispatch Main.out_string slot 3 argc 1
+    0005  jump 0008
+    0006  load_self
+    0007  dispatch Main.abort slot 0 argc 0
+    0008  return
+"
+    );
+    assert!(listing.starts_with("constants:\n    #0    String \"yes\"\n"));
+    assert!(listing.contains("\nclass Main inherits IO\n"));
+    assert!(listing.contains("    method 7   Main.main -> fn 0\n"));
+}
+
+#[test]
+fn test_execution() {
+    let (output, error) = run("\
+        class A inherits IO {\
+            n : Int <- 1;\
+            name() : String { \"A\" };\
+            show() : SELF_TYPE {{ out_string(name()); out_int(n); }};\
+        };\
+        class B inherits A { name() : String { \"B\" }; };\
+        class Main inherits IO { main() : Object {\
+            let x : A <- new B, i : Int in {\
+                x.show(); x@A.name(); out_string(x@A.name());\
+                while i < 3 loop { i <- i + 1; out_int(i * 10 / 2); } pool;\
+                out_string(case x of a : A => \"a\"; b : B => \"b\"; esac);\
+                out_string(case i of o : Object => \"o\"; esac);\
+                out_string((new SELF_TYPE).type_name().substr(1, 3));\
+                out_string(if x = x.copy() then \"=\" else \"/\" fi);\
+            }\
+        }; };");
+    assert_eq!(error, None);
+    assert_eq!(output, "B1A51015boain/");
+}
+
+#[test]
+fn test_many_arguments() {
+    // More arguments than fit in a byte.
+    let formals: Vec<String> =
+        (0..256).map(|i| format!("a{i} : Int")).collect();
+    let arguments: Vec<String> = (0..256).map(|i| i.to_string()).collect();
+    let source = format!(
+        "class Main inherits IO {{\
+            f({}) : Int {{ a255 }};\
+            main() : Object {{ out_int(f({})) }};\
+        }};",
+        formals.join(", "),
+        arguments.join(", ")
+    );
+
+    let (_, tokens) = lex_tokens(&source, "test.cl").unwrap();
+    let (_, mut program) = parse_program(&tokens).unwrap();
+    let hierarchy = check_program(&mut program).unwrap();
+    let mut expected = Vec::new();
+    Interpreter::new(
+        &program,
+        &hierarchy,
+        Box::new("".as_bytes()),
+        Box::new(&mut expected),
+    )
+    .run()
+    .unwrap();
+
+    let (output, error) = run(&source);
+    assert_eq!(error, None);
+    assert_eq!(output.as_bytes(), expected);
+    assert_eq!(output, "255");
+}
+
+#[test]
+fn test_runtime_errors() {
+    assert_eq!(
+        run("class Main { a : Main; main() : Object {\n a.main() }; };").1,
+        Some((
+            "test.cl:2: Dispatch to void calling method main.".to_string(),
+            5
+        ))
+    );
+    assert_eq!(
+        run("class Main { a : Main; main() : Object { a@Main.main() }; };").1,
+        Some((
+            "test.cl:1: Static dispatch to void calling method main."
+                .to_string(),
+            6
+        ))
+    );
+    assert_eq!(
+        run("class Main { main() : Object {\n\
+                 case 1 of s : String => s; esac }; };")
+        .1,
+        Some((
+            "test.cl:2: No match in case statement for Class Int.".to_string(),
+            8
+        ))
+    );
+    assert_eq!(
+        run("class A { f(s : String) : String {\n s.substr(0, 10) }; };\
+             class Main { main() : Object { (new A).f(\"short\") }; };")
+        .1,
+        Some(("test.cl:2: Index out of range in substr.".to_string(), 10))
+    );
+    assert_eq!(
+        run("class Main { x : Int <- 1 / 0; main() : Object { 0 }; };").1,
+        Some(("test.cl:1: Division by zero.".to_string(), 9))
+    );
+    assert_eq!(
+        run("class Main { main() : Object { abort() }; };").1,
+        Some(("Abort called from class Main".to_string(), 11))
+    );
+}
+
+#[test]
+fn test_limits() {
+    let (output, error) = run_with_limits(
+        Limits {
+            steps: Some(100),
+            ..Limits::default()
+        },
+        "class Main inherits IO { main() : Object {\
+             while true loop out_string(\"x\") pool\
+         }; };",
+    );
+    assert_eq!(output.len(), 14);
+    assert_eq!(error, Some(("Step limit of 100 exceeded.".to_string(), 14)));
+
+    let (_, error) = run_with_limits(
+        Limits {
+            objects: Some(3),
+            ..Limits::default()
+        },
+        "class Main { main() : Object {{ new Main; copy(); new Main; }}; };",
+    );
+    assert_eq!(error, Some(("Object limit of 3 exceeded.".to_string(), 15)));
+
+    let (_, error) = run_with_limits(
+        Limits {
+            call_depth: Some(50),
+            ..Limits::default()
+        },
+        "class Main { f(n : Int) : Int { f(n + 1) }; main() : Object {\
+             f(0)\
+         }; };",
+    );
+    assert_eq!(
+        error,
+        Some(("Call depth limit of 50 exceeded.".to_string(), 16))
+    );
+
+    let (output, error) = run_with_limits(
+        Limits {
+            output_bytes: Some(10),
+            ..Limits::default()
+        },
+        "class Main inherits IO { main() : Object {\
+             { out_string(\"hello \"); out_int(12345); }\
+         }; };",
+    );
+    assert_eq!(output, "hello 1234");
+    assert_eq!(
+        error,
+        Some(("Output limit of 10 bytes exceeded.".to_string(), 17))
+    );
+
+    // Frames live on the heap, so without a depth limit recursion is not
+    // bounded by the stack of the thread.
+    let (output, error) = run("class Main inherits IO {\
+             f(n : Int) : Int { if n = 0 then 0 else 1 + f(n - 1) fi };\
+             main() : Object { out_int(f(100000)) };\
+         };");
+    assert_eq!((output.as_str(), error), ("100000", None));
+}
@@ -0,0 +1,455 @@
+//! A stack-based virtual machine running bytecode modules. Locals and
+//! temporaries of all active calls share a single value stack; each call
+//! frame records where its local slots start.
+
+use super::*;
+use crate::hierarchy::{BOOL, INT, MAIN, MAIN_METHOD, STRING};
+use crate::interpreter::{
+    Builtin, Console, Limits, Meter, RuntimeError, RuntimeErrorKind,
+    Transcript, Value,
+};
+use std::io::{BufRead, Write};
+
+type ExecResult<'a> = Result<Value, RuntimeError<'a>>;
+
+struct Frame {
+    function: FunctionId,
+    /// Offset of the next instruction to execute.
+    pc: usize,
+    /// Position of the first local slot in the value stack.
+    base: usize,
+    self_value: Value,
+}
+
+pub struct Machine<'m, 'a> {
+    module: &'m Module<'a>,
+    constants: Vec<Value>,
+    /// Attribute values of newly created objects, for each class.
+    prototypes: Vec<Vec<Value>>,
+    int_class: ClassId,
+    bool_class: ClassId,
+    string_class: ClassId,
+    stack: Vec<Value>,
+    frames: Vec<Frame>,
+    console: Console<'m>,
+    meter: Meter,
+}
+
+impl<'m, 'a> Machine<'m, 'a> {
+    pub fn new(
+        module: &'m Module<'a>,
+        input: Box<dyn BufRead + 'm>,
+        output: Box<dyn Write + 'm>,
+    ) -> Self {
+        let constants = module
+            .constants
+            .iter()
+            .map(|constant| match constant {
+                Constant::Int(integer) => Value::Int(*integer),
+                Constant::Str(string) => Value::Str(string.as_str().into()),
+            })
+            .collect();
+        let prototypes = module
+            .classes
+            .iter()
+            .map(|class| {
+                class
+                    .attributes
+                    .iter()
+                    .map(|(_, type_id)| Value::default_for(type_id))
+                    .collect()
+            })
+            .collect();
+        let class_id = |name| module.class_id(name).expect("basic class");
+        Self {
+            module,
+            constants,
+            prototypes,
+            int_class: class_id(INT),
+            bool_class: class_id(BOOL),
+            string_class: class_id(STRING),
+            stack: Vec::new(),
+            frames: Vec::new(),
+            console: Console::new(input, output),
+            meter: Meter::default(),
+        }
+    }
+
+    /// Stop the program with an error once it exceeds some limits.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.meter = Meter::new(limits);
+        self.console.set_output_limit(limits.output_bytes);
+    }
+
+    /// Write a transcript of the input read and the output written.
+    pub fn record(&mut self, transcript: Box<dyn Write + 'm>) {
+        self.console.record(transcript);
+    }
+
+    /// Read the input of a transcript, and stop with an error if the output
+    /// differs from that of the transcript.
+    pub fn replay(&mut self, transcript: Transcript) {
+        self.console.replay(transcript);
+    }
+
+    /// Run a program by creating an object of class Main and calling its
+    /// main method.
+    pub fn run(&mut self) -> ExecResult<'a> {
+        let main_class = self.module.class_id(MAIN).expect("class Main");
+        let class = &self.module.classes[main_class];
+        let main_object = self.allocate(main_class)?;
+        let main_object = match class.init {
+            Some(init) => self.invoke(init, main_object)?,
+            None => main_object,
+        };
+        let slot = class.slot(MAIN_METHOD).expect("method main");
+        let result = match class.vtable[slot].method {
+            MethodRef::Function(main) => self.invoke(main, main_object),
+            MethodRef::Builtin(_) => unreachable!("main is not a builtin"),
+        };
+        self.console.flush()?;
+        let value = result?;
+        self.console.finish()?;
+        Ok(value)
+    }
+
+    /// The dynamic class of a value. Void has no class.
+    pub fn class_of(&self, value: &Value) -> Option<ClassId> {
+        match value {
+            Value::Void => None,
+            Value::Int(_) => Some(self.int_class),
+            Value::Bool(_) => Some(self.bool_class),
+            Value::Str(_) => Some(self.string_class),
+            Value::Object(object) => Some(object.borrow().class),
+        }
+    }
+
+    // Call a function without arguments and run it to completion.
+    fn invoke(
+        &mut self,
+        function: FunctionId,
+        self_value: Value,
+    ) -> ExecResult<'a> {
+        let depth = self.frames.len();
+        self.push_frame(function, self_value, 0)?;
+        let result = self.execute(depth);
+        if result.is_err() {
+            self.frames.truncate(depth);
+        }
+        result
+    }
+
+    // Create an object with attributes set to their default values. Basic
+    // classes have no attributes and their objects are plain values.
+    fn allocate(&mut self, class_id: ClassId) -> ExecResult<'a> {
+        let class = &self.module.classes[class_id];
+        match class.name.as_str() {
+            INT | BOOL | STRING => Ok(Value::default_for(&class.name)),
+            _ => {
+                self.meter.allocate()?;
+                let attributes = self.prototypes[class_id].clone();
+                Ok(Value::new_object(class_id, attributes))
+            }
+        }
+    }
+
+    // Start executing a function whose `argc` arguments are on top of the
+    // stack.
+    fn push_frame(
+        &mut self,
+        function: FunctionId,
+        self_value: Value,
+        argc: usize,
+    ) -> Result<(), RuntimeError<'a>> {
+        self.meter.enter(self.frames.len())?;
+        let base = self.stack.len() - argc;
+        let locals = self.module.functions[function].locals;
+        self.stack.resize(base + locals, Value::Void);
+        self.frames.push(Frame {
+            function,
+            pc: 0,
+            base,
+            self_value,
+        });
+        Ok(())
+    }
+
+    // Create an object, pushing it once its initialisers have run.
+    fn new_object(
+        &mut self,
+        class_id: ClassId,
+    ) -> Result<(), RuntimeError<'a>> {
+        let object = self.allocate(class_id)?;
+        match self.module.classes[class_id].init {
+            Some(init) => self.push_frame(init, object, 0)?,
+            None => self.stack.push(object),
+        }
+        Ok(())
+    }
+
+    fn frame(&self) -> &Frame {
+        self.frames.last().expect("no active frame")
+    }
+
+    fn pop(&mut self) -> Value {
+        self.stack.pop().expect("operand stack underflow")
+    }
+
+    fn pop_int(&mut self) -> i32 {
+        match self.pop() {
+            Value::Int(integer) => integer,
+            other => panic!("expected Int, found {other}"),
+        }
+    }
+
+    fn pop_bool(&mut self) -> bool {
+        match self.pop() {
+            Value::Bool(boolean) => boolean,
+            other => panic!("expected Bool, found {other}"),
+        }
+    }
+
+    // The location of the instruction being executed.
+    fn location(&self) -> Option<Span<'a>> {
+        let frame = self.frame();
+        self.module.functions[frame.function].location(frame.pc - 1)
+    }
+
+    fn error(&self, kind: RuntimeErrorKind) -> RuntimeError<'a> {
+        RuntimeError {
+            kind,
+            location: self.location(),
+        }
+    }
+
+    // Call a method on the receiver on top of the stack. `class_id` is the
+    // static type of the receiver, whose vtable is used by static dispatch.
+    fn dispatch(
+        &mut self,
+        class_id: ClassId,
+        is_static: bool,
+        slot: u16,
+        argc: u16,
+    ) -> Result<(), RuntimeError<'a>> {
+        let receiver = self.pop();
+        let argc = argc as usize;
+        let class_id = match self.class_of(&receiver) {
+            Some(_) if is_static => class_id,
+            Some(receiver_class) => receiver_class,
+            None => {
+                let vtable = &self.module.classes[class_id].vtable;
+                let method_name = vtable[slot as usize].name.clone();
+                let kind = if is_static {
+                    RuntimeErrorKind::StaticDispatchOnVoid(method_name)
+                } else {
+                    RuntimeErrorKind::DispatchOnVoid(method_name)
+                };
+                return Err(self.error(kind));
+            }
+        };
+        match self.module.classes[class_id].vtable[slot as usize].method {
+            MethodRef::Function(function) => {
+                self.push_frame(function, receiver, argc)?;
+            }
+            MethodRef::Builtin(builtin) => {
+                if builtin == Builtin::Copy
+                    && matches!(receiver, Value::Object(_))
+                {
+                    self.meter.allocate()?;
+                }
+                let args = self.stack.split_off(self.stack.len() - argc);
+                // The class of the receiver, not the static dispatch class
+                let receiver_class =
+                    self.class_of(&receiver).expect("non-void");
+                let class_name = &self.module.classes[receiver_class].name;
+                let result = builtin
+                    .call(receiver, args, class_name, &mut self.console)
+                    .map_err(|err| match self.location() {
+                        Some(location) => err.or_at(location),
+                        None => err,
+                    })?;
+                self.stack.push(result);
+            }
+        }
+        Ok(())
+    }
+
+    fn case(&mut self, table: u16) -> Result<(), RuntimeError<'a>> {
+        let value = self.pop();
+        let class_id = match self.class_of(&value) {
+            Some(class_id) => class_id,
+            None => return Err(self.error(RuntimeErrorKind::CaseOnVoid)),
+        };
+        let frame = self.frame();
+        let function = &self.module.functions[frame.function];
+        let table = &function.case_tables[table as usize];
+        let mut ancestor = Some(class_id);
+        while let Some(current) = ancestor {
+            if let Some((_, target)) =
+                table.branches.iter().find(|(class, _)| *class == current)
+            {
+                let slot = frame.base + table.slot as usize;
+                let target = *target as usize;
+                self.stack[slot] = value;
+                self.frames.last_mut().expect("no active frame").pc = target;
+                return Ok(());
+            }
+            ancestor = self.module.classes[current].parent;
+        }
+        let class_name = self.module.classes[class_id].name.clone();
+        Err(self.error(RuntimeErrorKind::NoMatchingBranch(class_name)))
+    }
+
+    // Execute instructions until the frame at `depth` returns.
+    fn execute(&mut self, depth: usize) -> ExecResult<'a> {
+        loop {
+            self.meter.step()?;
+            let frame = self.frames.last_mut().expect("no active frame");
+            let instruction =
+                self.module.functions[frame.function].code[frame.pc];
+            frame.pc += 1;
+            let base = frame.base;
+
+            match instruction {
+                Instruction::Constant(index) => {
+                    self.stack.push(self.constants[index as usize].clone())
+                }
+                Instruction::True => self.stack.push(Value::Bool(true)),
+                Instruction::False => self.stack.push(Value::Bool(false)),
+                Instruction::Void => self.stack.push(Value::Void),
+                Instruction::LoadSelf => {
+                    let self_value = self.frame().self_value.clone();
+                    self.stack.push(self_value);
+                }
+                Instruction::LoadLocal(slot) => {
+                    let value = self.stack[base + slot as usize].clone();
+                    self.stack.push(value);
+                }
+                Instruction::StoreLocal(slot) => {
+                    let value = self.pop();
+                    self.stack[base + slot as usize] = value;
+                }
+                Instruction::LoadAttribute(index) => {
+                    let value = match &self.frame().self_value {
+                        Value::Object(object) => {
+                            object.borrow().attributes[index as usize].clone()
+                        }
+                        _ => panic!("attribute of a basic value"),
+                    };
+                    self.stack.push(value);
+                }
+                Instruction::StoreAttribute(index) => {
+                    let value = self.pop();
+                    match &self.frame().self_value {
+                        Value::Object(object) => {
+                            object.borrow_mut().attributes[index as usize] =
+                                value
+                        }
+                        _ => panic!("attribute of a basic value"),
+                    }
+                }
+                Instruction::Pop => {
+                    self.pop();
+                }
+                Instruction::Dup => {
+                    let value = self.stack.last().expect("empty stack").clone();
+                    self.stack.push(value);
+                }
+                Instruction::New(class_id) => {
+                    self.new_object(class_id as usize)?
+                }
+                Instruction::NewSelfType => {
+                    let self_value = &self.frame().self_value;
+                    let class_id =
+                        self.class_of(self_value).expect("self is never void");
+                    self.new_object(class_id)?;
+                }
+                Instruction::Initialize(class_id) => {
+                    let init = self.module.classes[class_id as usize]
+                        .init
+                        .expect("initialiser function");
+                    let self_value = self.frame().self_value.clone();
+                    self.push_frame(init, self_value, 0)?;
+                }
+                Instruction::Dispatch { class, slot, argc } => {
+                    self.dispatch(class as usize, false, slot, argc)?
+                }
+                Instruction::StaticDispatch { class, slot, argc } => {
+                    self.dispatch(class as usize, true, slot, argc)?
+                }
+                Instruction::Jump(target) => {
+                    self.frames.last_mut().expect("no active frame").pc =
+                        target as usize
+                }
+                Instruction::JumpIfFalse(target) => {
+                    if !self.pop_bool() {
+                        self.frames.last_mut().expect("no active frame").pc =
+                            target as usize
+                    }
+                }
+                Instruction::Case(table) => self.case(table)?,
+                Instruction::Add => {
+                    let int2 = self.pop_int();
+                    let int1 = self.pop_int();
+                    self.stack.push(Value::Int(int1.wrapping_add(int2)));
+                }
+                Instruction::Subtract => {
+                    let int2 = self.pop_int();
+                    let int1 = self.pop_int();
+                    self.stack.push(Value::Int(int1.wrapping_sub(int2)));
+                }
+                Instruction::Multiply => {
+                    let int2 = self.pop_int();
+                    let int1 = self.pop_int();
+                    self.stack.push(Value::Int(int1.wrapping_mul(int2)));
+                }
+                Instruction::Divide => {
+                    let int2 = self.pop_int();
+                    let int1 = self.pop_int();
+                    if int2 == 0 {
+                        return Err(
+                            self.error(RuntimeErrorKind::DivisionByZero)
+                        );
+                    }
+                    self.stack.push(Value::Int(int1.wrapping_div(int2)));
+                }
+                Instruction::Negate => {
+                    let integer = self.pop_int();
+                    self.stack.push(Value::Int(integer.wrapping_neg()));
+                }
+                Instruction::LessThan => {
+                    let int2 = self.pop_int();
+                    let int1 = self.pop_int();
+                    self.stack.push(Value::Bool(int1 < int2));
+                }
+                Instruction::LessThanOrEquals => {
+                    let int2 = self.pop_int();
+                    let int1 = self.pop_int();
+                    self.stack.push(Value::Bool(int1 <= int2));
+                }
+                Instruction::Equals => {
+                    let value2 = self.pop();
+                    let value1 = self.pop();
+                    self.stack.push(Value::Bool(value1.equals(&value2)));
+                }
+                Instruction::Not => {
+                    let boolean = self.pop_bool();
+                    self.stack.push(Value::Bool(!boolean));
+                }
+                Instruction::IsVoid => {
+                    let value = self.pop();
+                    self.stack.push(Value::Bool(value.is_void()));
+                }
+                Instruction::Return => {
+                    let result = self.pop();
+                    let frame = self.frames.pop().expect("no active frame");
+                    self.stack.truncate(frame.base);
+                    if self.frames.len() == depth {
+                        return Ok(result);
+                    }
+                    self.stack.push(result);
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,821 @@
+//! Readable C99, for any C compiler and for students to inspect.
+//!
+//! The output is a single file starting with the runtime, [`C_RUNTIME`].
+//! Each class becomes a struct holding a pointer to its table of methods
+//! followed by its attributes, inherited attributes first, and a struct for
+//! the table, whose first member points to a descriptor of the class giving
+//! its name, parent and size. Methods of class `A` are the functions `A_m`,
+//! taking self as an `Object *`, and `A_New`, `A_Init`, `A_Class` and
+//! `A_Vtable` create and initialise objects and describe the class.
+//!
+//! Expressions become statements, with their intermediate values in
+//! temporaries named `_t1`, `_t2` and so on, so no compiler extensions are
+//! needed. Identifiers which are C keywords or could clash with the runtime
+//! are prefixed with an underscore, which Cool identifiers never start with.
+
+use super::*;
+use crate::ptree::*;
+use crate::semant::SymbolTable;
+use std::fmt::Display;
+use ExpressionData::*;
+
+/// The runtime included at the start of generated programs.
+pub const C_RUNTIME: &str = include_str!("runtime/c.c");
+
+const C_KEYWORDS: &str = "auto break case char const continue default do \
+    double else enum extern float for goto if inline int long register \
+    restrict return short signed sizeof static struct switch typedef union \
+    unsigned void volatile while _Bool _Complex _Imaginary";
+
+/// Generate C for a program that has passed semantic analysis.
+pub fn emit_c(program: &Program, hierarchy: &ClassHierarchy) -> String {
+    let mut generator = Generator {
+        hierarchy,
+        layout: Layout::new(hierarchy),
+        constants: Constants::default(),
+        body: String::new(),
+        indent: 1,
+        temps: 0,
+        class: String::new(),
+        scopes: SymbolTable::new(),
+    };
+    let mut functions = String::new();
+    for class in program.classes.iter() {
+        generator.class = class.name.clone();
+        functions.push_str(&generator.constructor(class));
+        functions.push_str(&generator.initialiser(class));
+        for feature in class.features.iter() {
+            if let FeatureData::Method(name, _, formals, body) = &feature.data {
+                functions.push_str(&generator.method(name, formals, body));
+            }
+        }
+    }
+    generator.finish(program, &functions)
+}
+
+// A variable or attribute name in C.
+fn name(ident: &str) -> String {
+    if C_KEYWORDS
+        .split_whitespace()
+        .any(|keyword| keyword == ident)
+        || ["errno", "stdin", "stdout", "stderr", "vtable"].contains(&ident)
+        || ident.starts_with("cool_")
+    {
+        format!("_{ident}")
+    } else {
+        ident.to_string()
+    }
+}
+
+// A class name in C. Names in capitals with underscores might be macros of
+// the C library.
+fn type_name(class: &str) -> String {
+    let macro_like = class.contains('_')
+        && class
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_');
+    let library = ["FILE", "NULL", "EOF", "BUFSIZ", "L_tmpnam", "P_tmpdir"];
+    if macro_like || library.contains(&class) {
+        format!("cool_{class}")
+    } else {
+        class.to_string()
+    }
+}
+
+// A C string literal. A question mark after another is escaped so that it
+// cannot start a trigraph.
+fn string_literal(string: &str) -> String {
+    let mut literal = String::from("\"");
+    let mut previous = 0;
+    for &byte in string.as_bytes() {
+        match byte {
+            b'"' => literal.push_str("\\\""),
+            b'\\' => literal.push_str("\\\\"),
+            b'\n' => literal.push_str("\\n"),
+            b'\t' => literal.push_str("\\t"),
+            b'?' if previous == b'?' => literal.push_str("\\?"),
+            b' '..=b'~' => literal.push(byte as char),
+            _ => literal.push_str(&format!("\\{byte:03o}")),
+        }
+        previous = byte;
+    }
+    literal.push('"');
+    literal
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Kind {
+    /// Self and constants, which are never void.
+    Constant,
+    /// Variables and attributes, which later expressions may change.
+    Variable,
+    /// Temporaries and void.
+    Temp,
+}
+
+// The value of an expression, with its C type.
+struct Value {
+    code: String,
+    ctype: String,
+    kind: Kind,
+}
+
+impl Value {
+    fn new(
+        code: impl Into<String>,
+        ctype: impl Into<String>,
+        kind: Kind,
+    ) -> Self {
+        Self {
+            code: code.into(),
+            ctype: ctype.into(),
+            kind,
+        }
+    }
+
+    fn void() -> Self {
+        Self::new("NULL", "Object *", Kind::Temp)
+    }
+
+    // The value as another C type, casting if needed.
+    fn to(&self, ctype: &str) -> String {
+        if self.ctype == ctype || self.code == "NULL" {
+            self.code.clone()
+        } else {
+            format!("({ctype}){}", self.code)
+        }
+    }
+
+    // The value as another C type, for use as the operand of `->`.
+    fn operand(&self, ctype: &str) -> String {
+        if self.ctype == ctype && self.code.starts_with('&') {
+            format!("({})", self.code)
+        } else if self.ctype == ctype {
+            self.code.clone()
+        } else {
+            format!("(({ctype}){})", self.code)
+        }
+    }
+}
+
+struct Generator<'h> {
+    hierarchy: &'h ClassHierarchy<'h>,
+    layout: Layout,
+    constants: Constants,
+    // The statements of the function being generated.
+    body: String,
+    indent: usize,
+    temps: usize,
+    // The class and the C names and types of the variables of the method
+    // being generated.
+    class: String,
+    scopes: SymbolTable<String, (String, String)>,
+}
+
+impl Generator<'_> {
+    fn line(&mut self, text: impl Display) {
+        let indent = 4 * self.indent;
+        writeln!(self.body, "{:indent$}{text}", "").unwrap();
+    }
+
+    // Declare a temporary, initialised if `code` is given.
+    fn temp(&mut self, ctype: &str, code: Option<String>) -> Value {
+        self.temps += 1;
+        let temp = format!("_t{}", self.temps);
+        match code {
+            Some(code) => self.line(format_args!("{ctype}{temp} = {code};")),
+            None => self.line(format_args!("{ctype}{temp};")),
+        }
+        Value::new(temp, ctype, Kind::Temp)
+    }
+
+    // Declare a variable for the statements generated by `body`, leaving
+    // the declaration out if they do not use it.
+    fn declare(
+        &mut self,
+        c_name: &str,
+        declaration: impl Display,
+        body: impl FnOnce(&mut Self),
+    ) {
+        let start = self.body.len();
+        self.line(declaration);
+        let end = self.body.len();
+        body(self);
+        let used = self.body[end..]
+            .split(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .any(|word| word == c_name);
+        if !used {
+            self.body.replace_range(start..end, "");
+        }
+    }
+
+    // The C type of a value of a Cool type in the current class.
+    fn ctype(&self, type_id: &str) -> String {
+        if type_id == SELF_TYPE {
+            format!("{} *", type_name(&self.class))
+        } else {
+            format!("{} *", type_name(type_id))
+        }
+    }
+
+    // The C type of a value of a Cool type in a declaration shared by
+    // subclasses.
+    fn declared_ctype(type_id: &str) -> String {
+        if type_id == SELF_TYPE {
+            "Object *".to_string()
+        } else {
+            format!("{} *", type_name(type_id))
+        }
+    }
+
+    fn static_ctype(&self, expr: &Expression) -> String {
+        self.ctype(expr.static_type.as_deref().expect("typed tree"))
+    }
+
+    fn string_constant(&mut self, string: &str) -> String {
+        format!("&cool_str_{}", self.constants.string(string))
+    }
+
+    fn int_constant(&mut self, int: i32) -> String {
+        format!("&cool_int_{}", self.constants.int(int))
+    }
+
+    fn default_value(&mut self, type_id: &str) -> String {
+        match type_id {
+            INT => self.int_constant(0),
+            STRING => self.string_constant(""),
+            BOOL => "&cool_false".to_string(),
+            _ => "NULL".to_string(),
+        }
+    }
+
+    // The return type and parameters of the function of a method.
+    fn signature(&self, owner: &str, method: &str) -> (String, String) {
+        let signature = self
+            .hierarchy
+            .lookup_method(owner, method)
+            .expect("method signature");
+        let mut parameters = vec!["Object *_self".to_string()];
+        for (formal, type_id) in signature.formals.iter() {
+            let ctype = Self::declared_ctype(type_id);
+            parameters.push(format!("{ctype}{}", name(formal)));
+        }
+        (
+            Self::declared_ctype(&signature.return_type),
+            parameters.join(", "),
+        )
+    }
+
+    fn prototype(&self, owner: &str, method: &str) -> String {
+        let (ctype, parameters) = self.signature(owner, method);
+        format!("{ctype}{}_{method}({parameters})", type_name(owner))
+    }
+
+    // Take the generated statements as the body of a function.
+    fn function(
+        &mut self,
+        header: &str,
+        body: impl FnOnce(&mut Self),
+    ) -> String {
+        self.body.clear();
+        self.indent = 1;
+        self.temps = 0;
+        body(self);
+        format!("static {header}\n{{\n{}}}\n\n", self.body)
+    }
+
+    // Constructors set every attribute to its default before running the
+    // initialisers.
+    fn constructor(&mut self, class: &Class) -> String {
+        let c_name = type_name(&class.name);
+        self.function(&format!("Object *{c_name}_New(void)"), |g| {
+            g.line(format_args!(
+                "{c_name} *self = cool_allocate(sizeof({c_name}));"
+            ));
+            g.line(format_args!("self->vtable = &{c_name}_Vtable;"));
+            let attributes = g.layout.class(&class.name).attributes.clone();
+            for (attribute, type_id) in attributes.iter() {
+                let value = g.default_value(type_id);
+                g.line(format_args!("self->{} = {value};", name(attribute)));
+            }
+            g.line(format_args!("{c_name}_Init(self);"));
+            g.line("return (Object *)self;");
+        })
+    }
+
+    fn initialiser(&mut self, class: &Class) -> String {
+        let c_name = type_name(&class.name);
+        let header = format!("void {c_name}_Init({c_name} *self)");
+        self.function(&header, |g| {
+            let parent = &class.super_class_name;
+            // Programs can only inherit Object and IO of the basic classes,
+            // which have no attributes.
+            if ![OBJECT, IO].contains(&parent.as_str()) {
+                let parent = type_name(parent);
+                g.line(format_args!("{parent}_Init(({parent} *)self);"));
+            }
+            for feature in class.features.iter() {
+                if let FeatureData::Attribute(attribute, _, Some(init)) =
+                    &feature.data
+                {
+                    let value = g.expression(init);
+                    g.assign(attribute, &value);
+                }
+            }
+        })
+    }
+
+    fn method(
+        &mut self,
+        method: &str,
+        formals: &[Formal],
+        body: &Expression,
+    ) -> String {
+        let class = self.class.clone();
+        let header = self.prototype(&class, method);
+        self.scopes.enter_scope();
+        for formal in formals.iter() {
+            let ctype = Self::declared_ctype(&formal.type_id);
+            self.scopes
+                .insert(formal.name.clone(), (name(&formal.name), ctype));
+        }
+        let signature = self
+            .hierarchy
+            .lookup_method(&class, method)
+            .expect("method signature");
+        let return_ctype = Self::declared_ctype(&signature.return_type);
+        let code = self.function(&header, |g| {
+            let c_name = type_name(&class);
+            let declaration = format!("{c_name} *self = ({c_name} *)_self;");
+            g.declare("self", declaration, |g| {
+                let value = g.expression(body);
+                g.line(format_args!("return {};", value.to(&return_ctype)));
+            });
+        });
+        self.scopes.exit_scope();
+        code
+    }
+
+    fn variable(&self, ident: &str) -> Value {
+        if ident == SELF {
+            return Value::new("self", self.ctype(SELF_TYPE), Kind::Constant);
+        }
+        if let Some((c_name, ctype)) = self.scopes.lookup(ident) {
+            return Value::new(c_name.clone(), ctype.clone(), Kind::Variable);
+        }
+        let class = self.layout.class(&self.class);
+        let index = class.attribute_index(ident).expect("declared attribute");
+        let ctype = Self::declared_ctype(&class.attributes[index].1);
+        Value::new(format!("self->{}", name(ident)), ctype, Kind::Variable)
+    }
+
+    fn assign(&mut self, ident: &str, value: &Value) -> Value {
+        let variable = self.variable(ident);
+        let code = value.to(&variable.ctype);
+        self.line(format_args!("{} = {code};", variable.code));
+        variable
+    }
+
+    // Evaluate expressions in order, copying the value of a variable that
+    // a later expression might change.
+    fn operands(&mut self, exprs: &[&Expression]) -> Vec<Value> {
+        let mut values = Vec::new();
+        for (index, expr) in exprs.iter().enumerate() {
+            let value = self.expression(expr);
+            let changing = exprs[index + 1..].iter().any(|later| {
+                !matches!(
+                    later.data,
+                    IntLiteral(_) | StrLiteral(_) | BoolLiteral(_) | Object(_)
+                )
+            });
+            if value.kind == Kind::Variable && changing {
+                let ctype = value.ctype.clone();
+                values.push(self.temp(&ctype, Some(value.code)));
+            } else {
+                values.push(value);
+            }
+        }
+        values
+    }
+
+    // Generate statements for an expression, returning its value.
+    fn expression(&mut self, expr: &Expression) -> Value {
+        self.evaluate(expr, false)
+    }
+
+    // Generate statements for an expression whose value is not used.
+    fn statement(&mut self, expr: &Expression) {
+        self.evaluate(expr, true);
+    }
+
+    // Declare a temporary holding the result of an expression, unless it is
+    // discarded.
+    fn result(&mut self, expr: &Expression, discard: bool) -> Option<Value> {
+        if discard {
+            None
+        } else {
+            Some(self.temp(&self.static_ctype(expr), None))
+        }
+    }
+
+    // A value computed by `code`, which is only run for its effects if the
+    // value is discarded.
+    fn computed(&mut self, ctype: &str, code: String, discard: bool) -> Value {
+        if discard {
+            self.line(format_args!("{code};"));
+            Value::void()
+        } else {
+            self.temp(ctype, Some(code))
+        }
+    }
+
+    fn evaluate(&mut self, expr: &Expression, discard: bool) -> Value {
+        match &expr.data {
+            Block(expressions) => {
+                let (last, rest) = expressions.split_last().expect("block");
+                for expression in rest.iter() {
+                    self.statement(expression);
+                }
+                self.evaluate(last, discard)
+            }
+            Conditional(if_expr, then_expr, else_expr) => {
+                let condition = self.condition(if_expr);
+                let result = self.result(expr, discard);
+                self.line(format_args!("if ({condition}) {{"));
+                self.branch(then_expr, result.as_ref());
+                self.line("} else {");
+                self.branch(else_expr, result.as_ref());
+                self.line("}");
+                result.unwrap_or_else(Value::void)
+            }
+            Loop(cond_expr, loop_expr) => {
+                // The condition is tested in the loop if it needs
+                // statements.
+                let start = self.body.len();
+                self.indent += 1;
+                let condition = self.condition(cond_expr);
+                let statements = self.body.split_off(start);
+                if statements.is_empty() {
+                    self.indent -= 1;
+                    self.line(format_args!("while ({condition}) {{"));
+                    self.indent += 1;
+                } else {
+                    self.indent -= 1;
+                    self.line("while (1) {");
+                    self.body.push_str(&statements);
+                    self.indent += 1;
+                    self.line(format_args!("if (!({condition}))"));
+                    self.indent += 1;
+                    self.line("break;");
+                    self.indent -= 1;
+                }
+                self.statement(loop_expr);
+                self.indent -= 1;
+                self.line("}");
+                Value::void()
+            }
+            Case(case_expr, branches) => {
+                self.case(expr, case_expr, branches, discard)
+            }
+            Let(ident, type_id, opt_bind, body) => {
+                let ctype = self.ctype(type_id);
+                let init = match &**opt_bind {
+                    Some(bind) => self.expression(bind).to(&ctype),
+                    None => self.default_value(type_id),
+                };
+                let result = self.result(expr, discard);
+                self.line("{");
+                self.indent += 1;
+                let c_name = name(ident);
+                let declaration = format!("{ctype}{c_name} = {init};");
+                self.scopes.enter_scope();
+                self.scopes.insert(ident.clone(), (c_name.clone(), ctype));
+                self.declare(&c_name, declaration, |g| {
+                    g.set_result(body, result.as_ref());
+                });
+                self.scopes.exit_scope();
+                self.indent -= 1;
+                self.line("}");
+                result.unwrap_or_else(Value::void)
+            }
+            New(type_id) if type_id == SELF_TYPE => {
+                let ctype = self.ctype(SELF_TYPE);
+                let code = format!("({ctype})self->vtable->class->new()");
+                self.computed(&ctype, code, discard)
+            }
+            New(type_id) => {
+                let ctype = self.ctype(type_id);
+                let code = format!("({ctype}){}_New()", type_name(type_id));
+                self.computed(&ctype, code, discard)
+            }
+            Assign(ident, value) => {
+                let value = self.expression(value);
+                self.assign(ident, &value)
+            }
+            UnaryOperation(UnaryOperator::Negative, operand) => {
+                let value = self.expression(operand);
+                let code = format!("cool_neg({})", value.to("Int *"));
+                self.computed("Int *", code, discard)
+            }
+            UnaryOperation(_, _)
+            | BinaryOperation(
+                BinaryOperator::LessThan
+                | BinaryOperator::LessThanOrEquals
+                | BinaryOperator::Equals,
+                _,
+                _,
+            ) => {
+                let condition = self.condition(expr);
+                let code = format!("cool_bool({condition})");
+                self.computed("Bool *", code, discard)
+            }
+            BinaryOperation(operator, operand1, operand2) => {
+                let values = self.operands(&[operand1, operand2]);
+                let (a, b) = (values[0].to("Int *"), values[1].to("Int *"));
+                let code = match operator {
+                    BinaryOperator::Add => format!("cool_add({a}, {b})"),
+                    BinaryOperator::Subtract => format!("cool_sub({a}, {b})"),
+                    BinaryOperator::Multiply => format!("cool_mul({a}, {b})"),
+                    _ => format!(
+                        "cool_div({a}, {b}, {}, {})",
+                        string_literal(expr.location.extra),
+                        expr.location.location_line()
+                    ),
+                };
+                self.computed("Int *", code, discard)
+            }
+            MethodCall(..) => self.dispatch(expr, discard),
+            Object(ident) => self.variable(ident),
+            IntLiteral(integer) => {
+                let code = self.int_constant(*integer);
+                Value::new(code, "Int *", Kind::Constant)
+            }
+            StrLiteral(string) => {
+                let code = self.string_constant(string);
+                Value::new(code, "String *", Kind::Constant)
+            }
+            BoolLiteral(value) => {
+                let code = if *value { "&cool_true" } else { "&cool_false" };
+                Value::new(code, "Bool *", Kind::Constant)
+            }
+        }
+    }
+
+    // Generate statements for an expression, setting the result if there
+    // is one.
+    fn set_result(&mut self, expr: &Expression, result: Option<&Value>) {
+        match result {
+            Some(result) => {
+                let value = self.expression(expr);
+                let code = value.to(&result.ctype);
+                self.line(format_args!("{} = {code};", result.code));
+            }
+            None => self.statement(expr),
+        }
+    }
+
+    // Generate an arm of a conditional.
+    fn branch(&mut self, expr: &Expression, result: Option<&Value>) {
+        self.indent += 1;
+        self.set_result(expr, result);
+        self.indent -= 1;
+    }
+
+    // Generate statements for a Bool expression, returning a C condition.
+    fn condition(&mut self, expr: &Expression) -> String {
+        match &expr.data {
+            BinaryOperation(
+                operator @ (BinaryOperator::LessThan
+                | BinaryOperator::LessThanOrEquals),
+                operand1,
+                operand2,
+            ) => {
+                let values = self.operands(&[operand1, operand2]);
+                let a = values[0].operand("Int *");
+                let b = values[1].operand("Int *");
+                if *operator == BinaryOperator::LessThan {
+                    format!("{a}->value < {b}->value")
+                } else {
+                    format!("{a}->value <= {b}->value")
+                }
+            }
+            BinaryOperation(BinaryOperator::Equals, operand1, operand2) => {
+                let values = self.operands(&[operand1, operand2]);
+                let a = values[0].to("Object *");
+                let b = values[1].to("Object *");
+                format!("cool_equals({a}, {b})")
+            }
+            UnaryOperation(UnaryOperator::Not, operand) => {
+                format!("!({})", self.condition(operand))
+            }
+            UnaryOperation(UnaryOperator::IsVoid, operand) => {
+                let value = self.expression(operand);
+                if value.kind == Kind::Constant {
+                    "0".to_string()
+                } else {
+                    format!("{} == NULL", value.code)
+                }
+            }
+            BoolLiteral(value) => (*value as u8).to_string(),
+            _ => {
+                let value = self.expression(expr);
+                format!("{}->value", value.operand("Bool *"))
+            }
+        }
+    }
+
+    // Arguments are evaluated before the receiver.
+    fn dispatch(&mut self, expr: &Expression, discard: bool) -> Value {
+        let (callee, static_type, ident, params) = match &expr.data {
+            MethodCall(callee, static_type, ident, params) => {
+                (callee, static_type, ident, params)
+            }
+            _ => unreachable!("not a dispatch"),
+        };
+        let mut exprs: Vec<&Expression> = params.iter().collect();
+        exprs.push(callee);
+        let mut values = self.operands(&exprs);
+        let receiver = values.pop().expect("receiver");
+
+        let class = dispatch_class(static_type, callee, &self.class);
+        let signature = self
+            .hierarchy
+            .lookup_method(&class, ident)
+            .expect("method signature");
+        let object = receiver.operand(&self.ctype(&class));
+        if receiver.kind != Kind::Constant {
+            let location = expr.location;
+            self.line(format_args!("if ({object} == NULL)"));
+            self.indent += 1;
+            self.line(format_args!(
+                "cool_dispatch_abort({}, {}, {}, {});",
+                string_literal(location.extra),
+                location.location_line(),
+                string_literal(ident),
+                static_type.is_some() as u8
+            ));
+            self.indent -= 1;
+        }
+        let mut arguments = vec![receiver.to("Object *")];
+        for (value, (_, type_id)) in values.iter().zip(&signature.formals) {
+            arguments.push(value.to(&Self::declared_ctype(type_id)));
+        }
+        let layout = self.layout.class(&class);
+        let slot = layout.slot(ident).expect("method slot");
+        let function = match layout.direct_call(slot, static_type.is_some()) {
+            Some(owner) => format!("{}_{ident}", type_name(owner)),
+            None => format!("{object}->vtable->{}", name(ident)),
+        };
+        let call = format!("{function}({})", arguments.join(", "));
+        if discard {
+            self.line(format_args!("{call};"));
+            return Value::void();
+        }
+        let returned = Value::new(
+            call,
+            Self::declared_ctype(&signature.return_type),
+            Kind::Temp,
+        );
+        let ctype = self.static_ctype(expr);
+        self.temp(&ctype, Some(returned.to(&ctype)))
+    }
+
+    // The runtime selects the branch for the class of the object, and
+    // reports a void object or a missing branch.
+    fn case(
+        &mut self,
+        expr: &Expression,
+        case_expr: &Expression,
+        branches: &[CaseBranch],
+        discard: bool,
+    ) -> Value {
+        let value = self.expression(case_expr);
+        let result = self.result(expr, discard);
+        let classes: Vec<String> = branches
+            .iter()
+            .map(|branch| format!("&{}_Class", type_name(&branch.type_id)))
+            .collect();
+        self.line(format_args!(
+            "switch (cool_case({}, {}, {}, {}, {})) {{",
+            value.to("Object *"),
+            string_literal(expr.location.extra),
+            expr.location.location_line(),
+            branches.len(),
+            classes.join(", ")
+        ));
+        for (index, branch) in branches.iter().enumerate() {
+            self.line(format_args!("case {index}: {{"));
+            self.indent += 1;
+            let ctype = self.ctype(&branch.type_id);
+            let c_name = name(&branch.ident);
+            let declaration =
+                format!("{ctype}{c_name} = {};", value.to(&ctype));
+            self.scopes.enter_scope();
+            self.scopes
+                .insert(branch.ident.clone(), (c_name.clone(), ctype));
+            self.declare(&c_name, declaration, |g| {
+                g.set_result(&branch.expression, result.as_ref());
+            });
+            self.scopes.exit_scope();
+            self.line("break;");
+            self.indent -= 1;
+            self.line("}");
+        }
+        self.line("}");
+        result.unwrap_or_else(Value::void)
+    }
+
+    // Put the runtime, the declarations of the classes and the constants
+    // before the functions.
+    fn finish(&mut self, program: &Program, functions: &str) -> String {
+        let mut out = String::from(C_RUNTIME);
+        writeln!(out, "\n/* The program. */\n").unwrap();
+        for class in program.classes.iter() {
+            let c_name = type_name(&class.name);
+            writeln!(out, "typedef struct {c_name} {c_name};").unwrap();
+        }
+        for class in program.classes.iter() {
+            let layout = self.layout.class(&class.name);
+            let c_name = type_name(&class.name);
+            writeln!(out, "\nstruct {c_name} {{").unwrap();
+            writeln!(out, "    const struct {c_name}_Vtable *vtable;").unwrap();
+            for (attribute, type_id) in layout.attributes.iter() {
+                let ctype = Self::declared_ctype(type_id);
+                writeln!(out, "    {ctype}{};", name(attribute)).unwrap();
+            }
+            writeln!(out, "}};\n\nstruct {c_name}_Vtable {{").unwrap();
+            writeln!(out, "    const cool_class *class;").unwrap();
+            for (method, owner) in layout.methods.iter() {
+                let (ctype, parameters) = self.signature(owner, method);
+                let member = name(method);
+                writeln!(out, "    {ctype}(*{member})({parameters});").unwrap();
+            }
+            writeln!(out, "}};").unwrap();
+        }
+
+        writeln!(out).unwrap();
+        for class in program.classes.iter() {
+            let c_name = type_name(&class.name);
+            writeln!(out, "static Object *{c_name}_New(void);").unwrap();
+            writeln!(out, "static void {c_name}_Init({c_name} *self);")
+                .unwrap();
+            for feature in class.features.iter() {
+                if let FeatureData::Method(method, ..) = &feature.data {
+                    let prototype = self.prototype(&class.name, method);
+                    writeln!(out, "static {prototype};").unwrap();
+                }
+            }
+        }
+
+        // A descriptor refers to the descriptor of the parent, so parents
+        // come first.
+        let mut classes: Vec<&Class> = program.classes.iter().collect();
+        classes.sort_by_key(|class| self.layout.tag(&class.name));
+        for class in classes {
+            let layout = self.layout.class(&class.name);
+            let c_name = type_name(&class.name);
+            let parent = type_name(&class.super_class_name);
+            writeln!(
+                out,
+                "\nstatic const cool_class {c_name}_Class = {{\n    \
+                 {}, &{parent}_Class, sizeof({c_name}), {c_name}_New\n}};",
+                string_literal(&class.name)
+            )
+            .unwrap();
+            writeln!(
+                out,
+                "static const struct {c_name}_Vtable {c_name}_Vtable = {{"
+            )
+            .unwrap();
+            writeln!(out, "    &{c_name}_Class,").unwrap();
+            for (method, owner) in layout.methods.iter() {
+                writeln!(out, "    {}_{method},", type_name(owner)).unwrap();
+            }
+            writeln!(out, "}};").unwrap();
+        }
+
+        writeln!(out).unwrap();
+        for (index, int) in self.constants.ints().iter().enumerate() {
+            writeln!(out, "Int cool_int_{index} = {{&Int_Vtable, {int}}};")
+                .unwrap();
+        }
+        for (index, string) in self.constants.strings().iter().enumerate() {
+            writeln!(
+                out,
+                "String cool_str_{index} = {{&String_Vtable, {}, {}}};",
+                string.len(),
+                string_literal(string)
+            )
+            .unwrap();
+        }
+
+        writeln!(out).unwrap();
+        out.push_str(functions);
+        let main = type_name(MAIN);
+        writeln!(out, "int main(void)\n{{").unwrap();
+        writeln!(out, "    {main} *object = ({main} *){main}_New();").unwrap();
+        writeln!(out, "    object->vtable->main((Object *)object);").unwrap();
+        writeln!(out, "    return 0;\n}}").unwrap();
+        out
+    }
+}
@@ -0,0 +1,798 @@
+//! LLVM IR, to compare our code with LLVM's optimisations.
+//!
+//! The output is a complete module including its runtime, [`LLVM_RUNTIME`],
+//! so it can be checked with `opt -verify`, run with `lli` or compiled with
+//! `clang`. Pointers are opaque, which LLVM 17 and later require; LLVM 15
+//! and 16 read them by default and LLVM 14 with `-opaque-pointers`.
+//!
+//! Every Cool value is a `ptr` to an object. Class `A` has the struct type
+//! `%A`, holding a pointer to its table of methods followed by its
+//! attributes, inherited attributes first. The table, `@A.class.vtable`, has
+//! the type `%A.vtable` and starts with a pointer to the descriptor of the
+//! class, `@A.class`. Methods are the functions `@A.m`, and `@A.new` and
+//! `@A.new.init` create and initialise objects; `new` and `class` are
+//! keywords, so these names cannot clash with methods. Variables live in
+//! stack slots, which `mem2reg` turns into registers.
+
+use super::*;
+use crate::ptree::*;
+use crate::semant::SymbolTable;
+use std::fmt::Display;
+use ExpressionData::*;
+
+/// The runtime included at the start of generated modules.
+pub const LLVM_RUNTIME: &str = include_str!("runtime/llvm.ll");
+
+/// Generate an LLVM module for a program that has passed semantic analysis.
+pub fn emit_llvm(program: &Program, hierarchy: &ClassHierarchy) -> String {
+    let mut generator = Generator {
+        layout: Layout::new(hierarchy),
+        constants: Constants::default(),
+        files: Vec::new(),
+        body: String::new(),
+        allocas: String::new(),
+        slots: HashMap::new(),
+        block: String::new(),
+        temps: 0,
+        labels: 0,
+        class: String::new(),
+        scopes: SymbolTable::new(),
+    };
+    let mut functions = String::new();
+    for class in program.classes.iter() {
+        generator.class = class.name.clone();
+        functions.push_str(&generator.constructor(class));
+        functions.push_str(&generator.initialiser(class));
+        for feature in class.features.iter() {
+            if let FeatureData::Method(name, _, formals, body) = &feature.data {
+                functions.push_str(&generator.method(name, formals, body));
+            }
+        }
+    }
+    generator.finish(program, &functions)
+}
+
+// An LLVM string constant, with its terminator, and its length.
+fn string_constant(string: &str) -> (String, usize) {
+    let mut constant = String::from("c\"");
+    for &byte in string.as_bytes() {
+        match byte {
+            b'"' | b'\\' => write!(constant, "\\{byte:02X}").unwrap(),
+            b' '..=b'~' => constant.push(byte as char),
+            _ => write!(constant, "\\{byte:02X}").unwrap(),
+        }
+    }
+    constant.push_str("\\00\"");
+    (constant, string.len() + 1)
+}
+
+// The size of a struct type, as a constant.
+fn size_of(struct_type: &str) -> String {
+    format!(
+        "ptrtoint (ptr getelementptr ({struct_type}, ptr null, i32 1) to \
+         i64)"
+    )
+}
+
+// The table of a class, as stored in objects.
+fn vtable(class: &str) -> String {
+    format!("@{class}.class.vtable")
+}
+
+// The name of a parameter. Cool identifiers never start with an underscore,
+// and the entry block is called `entry`.
+fn parameter(ident: &str) -> String {
+    if ident == "entry" {
+        format!("%_{ident}")
+    } else {
+        format!("%{ident}")
+    }
+}
+
+// The value of an expression, a `ptr` to an object.
+struct Value {
+    code: String,
+    /// Whether the value is self or a constant, which are never void.
+    constant: bool,
+}
+
+impl Value {
+    fn new(code: impl Into<String>, constant: bool) -> Self {
+        Self {
+            code: code.into(),
+            constant,
+        }
+    }
+
+    fn void() -> Self {
+        Self::new("null", false)
+    }
+}
+
+struct Generator {
+    layout: Layout,
+    constants: Constants,
+    // Names of source files, referred to by runtime errors.
+    files: Vec<String>,
+    // The instructions of the function being generated, its stack slots and
+    // the label of the current block.
+    body: String,
+    allocas: String,
+    slots: HashMap<String, usize>,
+    block: String,
+    temps: usize,
+    labels: usize,
+    // The class and the stack slots of the variables of the method being
+    // generated.
+    class: String,
+    scopes: SymbolTable<String, String>,
+}
+
+impl Generator {
+    fn line(&mut self, text: impl Display) {
+        writeln!(self.body, "  {text}").unwrap();
+    }
+
+    // A fresh name for a temporary. Cool identifiers contain no dots.
+    fn fresh(&mut self) -> String {
+        self.temps += 1;
+        format!("%t.{}", self.temps)
+    }
+
+    // Define a temporary with the result of an instruction.
+    fn temp(&mut self, instruction: impl Display) -> String {
+        let temp = self.fresh();
+        self.line(format_args!("{temp} = {instruction}"));
+        temp
+    }
+
+    // A fresh label for each of some blocks of a construct.
+    fn labels<const N: usize>(&mut self, names: [&str; N]) -> [String; N] {
+        self.labels += 1;
+        names.map(|name| format!("{name}.{}", self.labels))
+    }
+
+    fn start_block(&mut self, label: &str) {
+        writeln!(self.body, "{label}:").unwrap();
+        self.block = label.to_string();
+    }
+
+    // A stack slot for a variable, allocated on entry to the function.
+    fn alloca(&mut self, ident: &str) -> String {
+        let count = self.slots.entry(ident.to_string()).or_default();
+        *count += 1;
+        let slot = match *count {
+            1 => format!("%{ident}.addr"),
+            count => format!("%{ident}.addr{count}"),
+        };
+        writeln!(self.allocas, "  {slot} = alloca ptr").unwrap();
+        slot
+    }
+
+    // The location of an expression, for runtime errors.
+    fn location(&mut self, expr: &Expression) -> String {
+        let file = expr.location.extra;
+        let index = match self.files.iter().position(|f| f == file) {
+            Some(index) => index,
+            None => {
+                self.files.push(file.to_string());
+                self.files.len() - 1
+            }
+        };
+        format!("ptr @.file.{index}, i32 {}", expr.location.location_line())
+    }
+
+    fn string(&mut self, string: &str) -> String {
+        let index = self.constants.string(string);
+        format!("@str.{index}")
+    }
+
+    fn int(&mut self, int: i32) -> String {
+        let index = self.constants.int(int);
+        format!("@int.{index}")
+    }
+
+    fn bool(value: bool) -> String {
+        let global = if value { "@cool_true" } else { "@cool_false" };
+        global.to_string()
+    }
+
+    fn default_value(&mut self, type_id: &str) -> String {
+        match type_id {
+            INT => self.int(0),
+            STRING => self.string(""),
+            BOOL => Self::bool(false),
+            _ => "null".to_string(),
+        }
+    }
+
+    // The value held by an Int or Bool.
+    fn unboxed(&mut self, value: &Value) -> String {
+        let field = self.temp(format_args!(
+            "getelementptr inbounds %Int, ptr {}, i32 0, i32 1",
+            value.code
+        ));
+        self.temp(format_args!("load i32, ptr {field}"))
+    }
+
+    fn boxed_int(&mut self, int: &str) -> Value {
+        let code = self.temp(format_args!("call ptr @cool_int(i32 {int})"));
+        Value::new(code, false)
+    }
+
+    fn boxed_bool(&mut self, condition: &str) -> Value {
+        let code = self.temp(format_args!(
+            "select i1 {condition}, ptr {}, ptr {}",
+            Self::bool(true),
+            Self::bool(false)
+        ));
+        Value::new(code, false)
+    }
+
+    // The address of an attribute of self in the current class.
+    fn attribute(&mut self, ident: &str) -> String {
+        let class = self.class.clone();
+        let index = self
+            .layout
+            .class(&class)
+            .attribute_index(ident)
+            .expect("declared attribute");
+        self.temp(format_args!(
+            "getelementptr inbounds %{class}, ptr %self, i32 0, i32 {}",
+            index + 1
+        ))
+    }
+
+    // The address of a variable or attribute.
+    fn address(&mut self, ident: &str) -> String {
+        match self.scopes.lookup(ident) {
+            Some(slot) => slot.clone(),
+            None => self.attribute(ident),
+        }
+    }
+
+    // Take the generated instructions as the body of a function.
+    fn function(
+        &mut self,
+        header: &str,
+        body: impl FnOnce(&mut Self),
+    ) -> String {
+        self.body.clear();
+        self.allocas.clear();
+        self.slots.clear();
+        self.block = "entry".to_string();
+        self.temps = 0;
+        self.labels = 0;
+        body(self);
+        format!(
+            "define {header} {{\nentry:\n{}{}}}\n\n",
+            self.allocas, self.body
+        )
+    }
+
+    // Constructors set every attribute to its default before running the
+    // initialisers.
+    fn constructor(&mut self, class: &Class) -> String {
+        let name = class.name.clone();
+        self.function(&format!("ptr @{name}.new()"), |g| {
+            let object = g.temp(format_args!(
+                "call ptr @cool_allocate(i64 {})",
+                size_of(&format!("%{name}"))
+            ));
+            let field = g.temp(format_args!(
+                "getelementptr inbounds %{name}, ptr {object}, i32 0, i32 0"
+            ));
+            g.line(format_args!("store ptr {}, ptr {field}", vtable(&name)));
+            let attributes = g.layout.class(&name).attributes.clone();
+            for (index, (_, type_id)) in attributes.iter().enumerate() {
+                let value = g.default_value(type_id);
+                let field = g.temp(format_args!(
+                    "getelementptr inbounds %{name}, ptr {object}, i32 0, \
+                     i32 {}",
+                    index + 1
+                ));
+                g.line(format_args!("store ptr {value}, ptr {field}"));
+            }
+            g.line(format_args!("call void @{name}.new.init(ptr {object})"));
+            g.line(format_args!("ret ptr {object}"));
+        })
+    }
+
+    fn initialiser(&mut self, class: &Class) -> String {
+        let header = format!("void @{}.new.init(ptr %self)", class.name);
+        self.function(&header, |g| {
+            let parent = &class.super_class_name;
+            // Programs can only inherit Object and IO of the basic classes,
+            // which have no attributes.
+            if ![OBJECT, IO].contains(&parent.as_str()) {
+                g.line(format_args!("call void @{parent}.new.init(ptr %self)"));
+            }
+            for feature in class.features.iter() {
+                if let FeatureData::Attribute(attribute, _, Some(init)) =
+                    &feature.data
+                {
+                    let value = g.expression(init);
+                    let field = g.attribute(attribute);
+                    g.line(format_args!(
+                        "store ptr {}, ptr {field}",
+                        value.code
+                    ));
+                }
+            }
+            g.line("ret void");
+        })
+    }
+
+    fn method(
+        &mut self,
+        method: &str,
+        formals: &[Formal],
+        body: &Expression,
+    ) -> String {
+        let mut parameters = vec!["ptr %self".to_string()];
+        for formal in formals.iter() {
+            parameters.push(format!("ptr {}", parameter(&formal.name)));
+        }
+        let header =
+            format!("ptr @{}.{method}({})", self.class, parameters.join(", "));
+        self.scopes.enter_scope();
+        let code = self.function(&header, |g| {
+            for formal in formals.iter() {
+                let slot = g.alloca(&formal.name);
+                g.line(format_args!(
+                    "store ptr {}, ptr {slot}",
+                    parameter(&formal.name)
+                ));
+                g.scopes.insert(formal.name.clone(), slot);
+            }
+            let value = g.expression(body);
+            g.line(format_args!("ret ptr {}", value.code));
+        });
+        self.scopes.exit_scope();
+        code
+    }
+
+    // Generate instructions for an expression, returning its value.
+    fn expression(&mut self, expr: &Expression) -> Value {
+        match &expr.data {
+            Block(expressions) => {
+                let (last, rest) = expressions.split_last().expect("block");
+                for expression in rest.iter() {
+                    self.expression(expression);
+                }
+                self.expression(last)
+            }
+            Conditional(if_expr, then_expr, else_expr) => {
+                let condition = self.condition(if_expr);
+                let [then_label, else_label, end] =
+                    self.labels(["then", "else", "end"]);
+                self.line(format_args!(
+                    "br i1 {condition}, label %{then_label}, label \
+                     %{else_label}"
+                ));
+                self.start_block(&then_label);
+                let then_value = self.expression(then_expr);
+                let then_end = self.block.clone();
+                self.line(format_args!("br label %{end}"));
+                self.start_block(&else_label);
+                let else_value = self.expression(else_expr);
+                let else_end = self.block.clone();
+                self.line(format_args!("br label %{end}"));
+                self.start_block(&end);
+                let code = self.temp(format_args!(
+                    "phi ptr [ {}, %{then_end} ], [ {}, %{else_end} ]",
+                    then_value.code, else_value.code
+                ));
+                Value::new(code, false)
+            }
+            Loop(cond_expr, loop_expr) => {
+                let [test, body, end] = self.labels(["while", "loop", "done"]);
+                self.line(format_args!("br label %{test}"));
+                self.start_block(&test);
+                let condition = self.condition(cond_expr);
+                self.line(format_args!(
+                    "br i1 {condition}, label %{body}, label %{end}"
+                ));
+                self.start_block(&body);
+                self.expression(loop_expr);
+                self.line(format_args!("br label %{test}"));
+                self.start_block(&end);
+                Value::void()
+            }
+            Case(case_expr, branches) => self.case(expr, case_expr, branches),
+            Let(ident, type_id, opt_bind, body) => {
+                let init = match &**opt_bind {
+                    Some(bind) => self.expression(bind).code,
+                    None => self.default_value(type_id),
+                };
+                let slot = self.alloca(ident);
+                self.line(format_args!("store ptr {init}, ptr {slot}"));
+                self.scopes.enter_scope();
+                self.scopes.insert(ident.clone(), slot);
+                let value = self.expression(body);
+                self.scopes.exit_scope();
+                value
+            }
+            New(type_id) if type_id == SELF_TYPE => {
+                let class = self.temp("call ptr @cool_class_of(ptr %self)");
+                let field = self.temp(format_args!(
+                    "getelementptr inbounds %cool_class, ptr \
+                     {class}, i32 0, i32 3"
+                ));
+                let new = self.temp(format_args!("load ptr, ptr {field}"));
+                let code = self.temp(format_args!("call ptr {new}()"));
+                Value::new(code, false)
+            }
+            New(type_id) => {
+                let code = self.temp(format_args!("call ptr @{type_id}.new()"));
+                Value::new(code, false)
+            }
+            Assign(ident, value) => {
+                let value = self.expression(value);
+                let address = self.address(ident);
+                self.line(format_args!(
+                    "store ptr {}, ptr {address}",
+                    value.code
+                ));
+                value
+            }
+            UnaryOperation(UnaryOperator::Negative, operand) => {
+                let value = self.expression(operand);
+                let int = self.unboxed(&value);
+                let negated = self.temp(format_args!("sub i32 0, {int}"));
+                self.boxed_int(&negated)
+            }
+            UnaryOperation(_, _)
+            | BinaryOperation(
+                BinaryOperator::LessThan
+                | BinaryOperator::LessThanOrEquals
+                | BinaryOperator::Equals,
+                _,
+                _,
+            ) => {
+                let condition = self.condition(expr);
+                self.boxed_bool(&condition)
+            }
+            BinaryOperation(operator, operand1, operand2) => {
+                let value1 = self.expression(operand1);
+                let value2 = self.expression(operand2);
+                let (a, b) = (self.unboxed(&value1), self.unboxed(&value2));
+                let result = match operator {
+                    BinaryOperator::Add => {
+                        self.temp(format_args!("add i32 {a}, {b}"))
+                    }
+                    BinaryOperator::Subtract => {
+                        self.temp(format_args!("sub i32 {a}, {b}"))
+                    }
+                    BinaryOperator::Multiply => {
+                        self.temp(format_args!("mul i32 {a}, {b}"))
+                    }
+                    _ => {
+                        let location = self.location(expr);
+                        self.temp(format_args!(
+                            "call i32 @cool_div(i32 {a}, i32 {b}, {location})"
+                        ))
+                    }
+                };
+                self.boxed_int(&result)
+            }
+            MethodCall(..) => self.dispatch(expr),
+            Object(ident) if ident == SELF => Value::new("%self", true),
+            Object(ident) => {
+                let address = self.address(ident);
+                let code = self.temp(format_args!("load ptr, ptr {address}"));
+                Value::new(code, false)
+            }
+            IntLiteral(integer) => Value::new(self.int(*integer), true),
+            StrLiteral(string) => Value::new(self.string(string), true),
+            BoolLiteral(value) => Value::new(Self::bool(*value), true),
+        }
+    }
+
+    // Generate instructions for a Bool expression, returning an `i1`.
+    fn condition(&mut self, expr: &Expression) -> String {
+        match &expr.data {
+            BinaryOperation(
+                operator @ (BinaryOperator::LessThan
+                | BinaryOperator::LessThanOrEquals),
+                operand1,
+                operand2,
+            ) => {
+                let value1 = self.expression(operand1);
+                let value2 = self.expression(operand2);
+                let (a, b) = (self.unboxed(&value1), self.unboxed(&value2));
+                let predicate = match operator {
+                    BinaryOperator::LessThan => "slt",
+                    _ => "sle",
+                };
+                self.temp(format_args!("icmp {predicate} i32 {a}, {b}"))
+            }
+            BinaryOperation(BinaryOperator::Equals, operand1, operand2) => {
+                let a = self.expression(operand1).code;
+                let b = self.expression(operand2).code;
+                self.temp(format_args!(
+                    "call i1 @cool_equals(ptr {a}, ptr {b})"
+                ))
+            }
+            UnaryOperation(UnaryOperator::Not, operand) => {
+                let condition = self.condition(operand);
+                self.temp(format_args!("xor i1 {condition}, true"))
+            }
+            UnaryOperation(UnaryOperator::IsVoid, operand) => {
+                let value = self.expression(operand);
+                if value.constant {
+                    "false".to_string()
+                } else {
+                    self.temp(format_args!("icmp eq ptr {}, null", value.code))
+                }
+            }
+            BoolLiteral(value) => value.to_string(),
+            _ => {
+                let value = self.expression(expr);
+                let int = self.unboxed(&value);
+                self.temp(format_args!("icmp ne i32 {int}, 0"))
+            }
+        }
+    }
+
+    // Branch to a block reporting an error if a value is void, calling the
+    // abort routine with the given arguments, which start with the location.
+    fn check_void(&mut self, value: &Value, abort: &str, arguments: &str) {
+        if value.constant {
+            return;
+        }
+        let [void, valid] = self.labels(["void", "valid"]);
+        let is_void =
+            self.temp(format_args!("icmp eq ptr {}, null", value.code));
+        self.line(format_args!(
+            "br i1 {is_void}, label %{void}, label %{valid}"
+        ));
+        self.start_block(&void);
+        self.line(format_args!("call void @{abort}({arguments})"));
+        self.line("unreachable");
+        self.start_block(&valid);
+    }
+
+    // Arguments are evaluated before the receiver.
+    fn dispatch(&mut self, expr: &Expression) -> Value {
+        let (callee, static_type, ident, params) = match &expr.data {
+            MethodCall(callee, static_type, ident, params) => {
+                (callee, static_type, ident, params)
+            }
+            _ => unreachable!("not a dispatch"),
+        };
+        let mut arguments = Vec::new();
+        for param in params.iter() {
+            let value = self.expression(param);
+            arguments.push(format!("ptr {}", value.code));
+        }
+        let receiver = self.expression(callee);
+        // The abort routine also takes the method name and whether the
+        // dispatch is static.
+        let method = self.constants.string(ident);
+        let abort_arguments = format!(
+            "{}, ptr @str.{method}.chars, i1 {}",
+            self.location(expr),
+            static_type.is_some()
+        );
+        self.check_void(&receiver, "cool_dispatch_abort", &abort_arguments);
+        arguments.insert(0, format!("ptr {}", receiver.code));
+
+        let class = dispatch_class(static_type, callee, &self.class);
+        let layout = self.layout.class(&class);
+        let slot = layout.slot(ident).expect("method slot");
+        let function = match layout.direct_call(slot, static_type.is_some()) {
+            Some(owner) => format!("@{owner}.{ident}"),
+            None => {
+                let field = self.temp(format_args!(
+                    "getelementptr inbounds %Object, ptr {}, i32 0, \
+                     i32 0",
+                    receiver.code
+                ));
+                let table = self.temp(format_args!("load ptr, ptr {field}"));
+                let entry = self.temp(format_args!(
+                    "getelementptr inbounds %{class}.vtable, \
+                     ptr {table}, i32 0, i32 {}",
+                    slot + 1
+                ));
+                self.temp(format_args!("load ptr, ptr {entry}"))
+            }
+        };
+        let code = self.temp(format_args!(
+            "call ptr {function}({})",
+            arguments.join(", ")
+        ));
+        Value::new(code, false)
+    }
+
+    // Compare the class of the object and then its ancestors with the
+    // classes of the branches, so the branch for the closest ancestor is
+    // taken.
+    fn case(
+        &mut self,
+        expr: &Expression,
+        case_expr: &Expression,
+        branches: &[CaseBranch],
+    ) -> Value {
+        let value = self.expression(case_expr);
+        let location = self.location(expr);
+        self.check_void(&value, "cool_case_abort", &location);
+        let [test, parent, no_match, end] =
+            self.labels(["case", "parent", "nomatch", "esac"]);
+        let class = self
+            .temp(format_args!("call ptr @cool_class_of(ptr {})", value.code));
+        let start = self.block.clone();
+        self.line(format_args!("br label %{test}"));
+        self.start_block(&test);
+        let (current, next) = (self.fresh(), self.fresh());
+        self.line(format_args!(
+            "{current} = phi ptr [ {class}, %{start} ], \
+             [ {next}, %{parent} ]"
+        ));
+        let mut targets = Vec::new();
+        for (index, branch) in branches.iter().enumerate() {
+            let [target, otherwise] = self.labels(["branch", "next"]);
+            let matches = self.temp(format_args!(
+                "icmp eq ptr {current}, @{}.class",
+                branch.type_id
+            ));
+            let otherwise = if index + 1 == branches.len() {
+                parent.clone()
+            } else {
+                otherwise
+            };
+            self.line(format_args!(
+                "br i1 {matches}, label %{target}, label %{otherwise}"
+            ));
+            if index + 1 < branches.len() {
+                self.start_block(&otherwise);
+            }
+            targets.push(target);
+        }
+        self.start_block(&parent);
+        let field = self.temp(format_args!(
+            "getelementptr inbounds %cool_class, ptr {current}, \
+             i32 0, i32 1"
+        ));
+        self.line(format_args!("{next} = load ptr, ptr {field}"));
+        let top = self.temp(format_args!("icmp eq ptr {next}, null"));
+        self.line(format_args!(
+            "br i1 {top}, label %{no_match}, label %{test}"
+        ));
+        self.start_block(&no_match);
+        self.line(format_args!(
+            "call void @cool_case_no_match(ptr {})",
+            value.code
+        ));
+        self.line("unreachable");
+
+        let mut incoming = Vec::new();
+        for (branch, target) in branches.iter().zip(targets) {
+            self.start_block(&target);
+            let slot = self.alloca(&branch.ident);
+            self.line(format_args!("store ptr {}, ptr {slot}", value.code));
+            self.scopes.enter_scope();
+            self.scopes.insert(branch.ident.clone(), slot);
+            let result = self.expression(&branch.expression);
+            self.scopes.exit_scope();
+            incoming.push(format!("[ {}, %{} ]", result.code, self.block));
+            self.line(format_args!("br label %{end}"));
+        }
+        self.start_block(&end);
+        let code = self.temp(format_args!("phi ptr {}", incoming.join(", ")));
+        Value::new(code, false)
+    }
+
+    // Put the runtime, the types, descriptors, tables and constants before
+    // the functions.
+    fn finish(&mut self, program: &Program, functions: &str) -> String {
+        let mut out = String::from(LLVM_RUNTIME);
+        writeln!(out, "\n; The program.\n").unwrap();
+        for class in program.classes.iter() {
+            let layout = self.layout.class(&class.name);
+            let mut members = vec!["ptr"];
+            members.extend(layout.attributes.iter().map(|_| "ptr"));
+            writeln!(
+                out,
+                "%{} = type {{ {} }}",
+                class.name,
+                members.join(", ")
+            )
+            .unwrap();
+        }
+        for layout in self.layout.classes.iter() {
+            // The descriptor, then a function for each method.
+            let members = vec!["ptr"; layout.methods.len() + 1];
+            writeln!(
+                out,
+                "%{}.vtable = type {{ {} }}",
+                layout.name,
+                members.join(", ")
+            )
+            .unwrap();
+        }
+
+        for layout in self.layout.classes.iter() {
+            let name = &layout.name;
+            let (string, length) = string_constant(name);
+            let parent = match layout.parent {
+                Some(parent) => {
+                    format!("@{}.class", self.layout.classes[parent].name)
+                }
+                None => "null".to_string(),
+            };
+            writeln!(
+                out,
+                "\n@{name}.class.name = private unnamed_addr constant \
+                 [{length} x i8] {string}"
+            )
+            .unwrap();
+            writeln!(
+                out,
+                "@{name}.class = constant %cool_class {{\n  ptr \
+                 @{name}.class.name,\n  ptr {parent},\n  i64 {},\n  ptr \
+                 @{name}.new\n}}",
+                size_of(&format!("%{name}"))
+            )
+            .unwrap();
+            writeln!(
+                out,
+                "@{name}.class.vtable = constant %{name}.vtable {{\n  \
+                 ptr @{name}.class"
+            )
+            .unwrap();
+            for (method, owner) in layout.methods.iter() {
+                writeln!(out, "  , ptr @{owner}.{method}").unwrap();
+            }
+            writeln!(out, "}}").unwrap();
+        }
+
+        writeln!(out).unwrap();
+        for (index, int) in self.constants.ints().iter().enumerate() {
+            writeln!(
+                out,
+                "@int.{index} = constant %Int {{ ptr {}, \
+                 i32 {int} }}",
+                vtable(INT)
+            )
+            .unwrap();
+        }
+        for (index, string) in self.constants.strings().iter().enumerate() {
+            let (constant, length) = string_constant(string);
+            writeln!(
+                out,
+                "@str.{index}.chars = private unnamed_addr constant \
+                 [{length} x i8] {constant}"
+            )
+            .unwrap();
+            writeln!(
+                out,
+                "@str.{index} = constant %String {{ ptr {}, i32 {}, \
+                 ptr @str.{index}.chars }}",
+                vtable(STRING),
+                string.len()
+            )
+            .unwrap();
+        }
+        for (index, file) in self.files.iter().enumerate() {
+            let (constant, length) = string_constant(file);
+            writeln!(
+                out,
+                "@.file.{index} = private unnamed_addr constant [{length} x \
+                 i8] {constant}"
+            )
+            .unwrap();
+        }
+
+        writeln!(out).unwrap();
+        out.push_str(functions);
+        let layout = self.layout.class(MAIN);
+        let slot = layout.slot("main").expect("Main.main");
+        let owner = &layout.methods[slot].1;
+        writeln!(out, "define i32 @main() {{").unwrap();
+        writeln!(out, "entry:\n  %main = call ptr @Main.new()").unwrap();
+        writeln!(out, "  %result = call ptr @{owner}.main(ptr %main)").unwrap();
+        writeln!(out, "  ret i32 0\n}}").unwrap();
+        out
+    }
+}
@@ -0,0 +1,620 @@
+//! MIPS assembly for SPIM, linked with the course runtime (`trap.handler`).
+//!
+//! The runtime provides the methods of the basic classes, object copying,
+//! the garbage collectors and the entry point, which creates a Main object
+//! and calls `Main.main`. Generated code follows its conventions: the
+//! receiver is passed in `$a0` and kept in `$s0`, arguments are pushed on
+//! the stack in order, and results are returned in `$a0`. Intermediate
+//! values are pushed on the stack, and variables bound by let and case live
+//! in the frame of the method.
+
+use super::*;
+use crate::ptree::*;
+use crate::semant::SymbolTable;
+use crate::tokens::Span;
+use std::fmt::Write;
+use ExpressionData::*;
+
+/// The garbage collector the runtime runs with.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Collector {
+    #[default]
+    None,
+    /// The generational collector, which is told about every assignment to
+    /// an attribute.
+    Generational,
+}
+
+/// Generate MIPS assembly for a program that has passed semantic analysis.
+pub fn emit_mips(
+    program: &Program,
+    hierarchy: &ClassHierarchy,
+    collector: Collector,
+) -> String {
+    let mut generator = Generator {
+        layout: Layout::new(hierarchy),
+        constants: Constants::default(),
+        collector,
+        code: String::new(),
+        labels: 0,
+        class: String::new(),
+        scopes: SymbolTable::new(),
+        next_temp: 0,
+        temps: 0,
+    };
+    for class in generator.layout.classes.iter() {
+        generator.constants.string(&class.name);
+    }
+    generator.constants.string("");
+    generator.constants.int(0);
+    generator.initialisers(program);
+    for class in program.classes.iter() {
+        generator.class = class.name.clone();
+        for feature in class.features.iter() {
+            if let FeatureData::Method(name, _, formals, body) = &feature.data {
+                generator.method(&class.name, name, formals, body);
+            }
+        }
+    }
+    generator.finish()
+}
+
+// Where a variable is stored.
+enum Variable {
+    SelfObject,
+    /// An offset from the frame pointer.
+    Frame(i32),
+    Attribute(usize),
+}
+
+struct Generator {
+    layout: Layout,
+    constants: Constants,
+    collector: Collector,
+    code: String,
+    labels: usize,
+    // The class and the variables of the method being generated.
+    class: String,
+    scopes: SymbolTable<String, i32>,
+    next_temp: usize,
+    temps: usize,
+}
+
+impl Generator {
+    fn label(&mut self) -> String {
+        self.labels += 1;
+        format!("label{}", self.labels - 1)
+    }
+
+    fn place_label(&mut self, label: &str) {
+        writeln!(self.code, "{label}:").unwrap();
+    }
+
+    // Frame slots for let and case variables are reused once their scope
+    // ends.
+    fn allocate_temp(&mut self) -> i32 {
+        self.next_temp += 1;
+        self.temps = self.temps.max(self.next_temp);
+        -((WORD_SIZE * self.next_temp) as i32)
+    }
+
+    fn release_temp(&mut self) {
+        self.next_temp -= 1;
+    }
+
+    fn push(&mut self) {
+        emit!(self.code, "sw\t$a0 0($sp)");
+        emit!(self.code, "addiu\t$sp $sp -4");
+    }
+
+    fn pop(&mut self, register: &str) {
+        emit!(self.code, "lw\t{register} 4($sp)");
+        emit!(self.code, "addiu\t$sp $sp 4");
+    }
+
+    fn string_label(&mut self, string: &str) -> String {
+        format!("str_const{}", self.constants.string(string))
+    }
+
+    fn int_label(&mut self, int: i32) -> String {
+        format!("int_const{}", self.constants.int(int))
+    }
+
+    // Generate a function around the code emitted by `body`, which leaves
+    // its result in $a0. The frame pointer points at the saved return
+    // address, with the arguments above it and let and case variables below.
+    fn function(
+        &mut self,
+        label: &str,
+        arity: usize,
+        body: impl FnOnce(&mut Self),
+    ) {
+        let outer = std::mem::take(&mut self.code);
+        self.next_temp = 0;
+        self.temps = 0;
+        body(self);
+        let body = std::mem::replace(&mut self.code, outer);
+
+        self.place_label(label);
+        emit!(self.code, "addiu\t$sp $sp -12");
+        emit!(self.code, "sw\t$fp 12($sp)");
+        emit!(self.code, "sw\t$s0 8($sp)");
+        emit!(self.code, "sw\t$ra 4($sp)");
+        emit!(self.code, "addiu\t$fp $sp 4");
+        emit!(self.code, "move\t$s0 $a0");
+        if self.temps > 0 {
+            emit!(self.code, "addiu\t$sp $sp -{}", WORD_SIZE * self.temps);
+        }
+        self.code.push_str(&body);
+        if self.temps > 0 {
+            emit!(self.code, "addiu\t$sp $sp {}", WORD_SIZE * self.temps);
+        }
+        emit!(self.code, "lw\t$fp 12($sp)");
+        emit!(self.code, "lw\t$s0 8($sp)");
+        emit!(self.code, "lw\t$ra 4($sp)");
+        emit!(self.code, "addiu\t$sp $sp {}", 12 + WORD_SIZE * arity);
+        emit!(self.code, "jr\t$ra");
+    }
+
+    fn initialisers(&mut self, program: &Program) {
+        for initialiser in self.layout.initialisers(program) {
+            self.class = initialiser.class.clone();
+            let label = format!("{}_init", initialiser.class);
+            self.function(&label, 0, |g| {
+                if let Some(parent) = &initialiser.parent {
+                    emit!(g.code, "jal\t{parent}_init");
+                }
+                for (attr, init) in initialiser.attributes.iter() {
+                    g.expression(init);
+                    g.store_attribute(attr);
+                }
+                emit!(g.code, "move\t$a0 $s0");
+            });
+        }
+    }
+
+    fn method(
+        &mut self,
+        class: &str,
+        name: &str,
+        formals: &[Formal],
+        body: &Expression,
+    ) {
+        self.scopes.enter_scope();
+        for (index, formal) in formals.iter().enumerate() {
+            // The first argument is pushed first, so it is the highest.
+            let offset = 8 + WORD_SIZE * (formals.len() - index);
+            self.scopes.insert(formal.name.clone(), offset as i32);
+        }
+        self.function(&format!("{class}.{name}"), formals.len(), |g| {
+            g.expression(body)
+        });
+        self.scopes.exit_scope();
+    }
+
+    fn variable(&self, name: &str) -> Variable {
+        if name == SELF {
+            Variable::SelfObject
+        } else if let Some(offset) = self.scopes.lookup(name) {
+            Variable::Frame(*offset)
+        } else {
+            let index = self
+                .layout
+                .class(&self.class)
+                .attribute_index(name)
+                .expect("declared attribute");
+            Variable::Attribute(index)
+        }
+    }
+
+    fn store_attribute(&mut self, name: &str) {
+        match self.variable(name) {
+            Variable::Attribute(index) => {
+                let offset = attribute_offset(index, WORD_SIZE);
+                emit!(self.code, "sw\t$a0 {offset}($s0)");
+                if self.collector == Collector::Generational {
+                    emit!(self.code, "addiu\t$a1 $s0 {offset}");
+                    emit!(self.code, "jal\t_GenGC_Assign");
+                }
+            }
+            _ => unreachable!("not an attribute"),
+        }
+    }
+
+    fn default_value(&mut self, type_id: &str) {
+        let label = match type_id {
+            INT => self.int_label(0),
+            STRING => self.string_label(""),
+            BOOL => "bool_const0".to_string(),
+            _ => {
+                emit!(self.code, "move\t$a0 $zero");
+                return;
+            }
+        };
+        emit!(self.code, "la\t$a0 {label}");
+    }
+
+    // Call a runtime routine reporting an error at a location, which takes
+    // the file name in $a0 and the line in $t1.
+    fn abort_at(&mut self, routine: &str, location: Span) {
+        let filename = self.string_label(location.extra);
+        emit!(self.code, "la\t$a0 {filename}");
+        emit!(self.code, "li\t$t1 {}", location.location_line());
+        emit!(self.code, "jal\t{routine}");
+    }
+
+    // Leave the Bool result of a comparison in $a0, given the instruction
+    // that branches when it is true.
+    fn set_bool(&mut self, branch: std::fmt::Arguments) {
+        let done = self.label();
+        emit!(self.code, "la\t$a0 bool_const1");
+        emit!(self.code, "{branch} {done}");
+        emit!(self.code, "la\t$a0 bool_const0");
+        self.place_label(&done);
+    }
+
+    // Generate code for an expression, leaving its value in $a0.
+    fn expression(&mut self, expr: &Expression) {
+        match &expr.data {
+            Block(expressions) => {
+                for expression in expressions.iter() {
+                    self.expression(expression);
+                }
+            }
+            Conditional(if_expr, then_expr, else_expr) => {
+                let else_label = self.label();
+                let end = self.label();
+                self.expression(if_expr);
+                emit!(self.code, "lw\t$t1 12($a0)");
+                emit!(self.code, "beqz\t$t1 {else_label}");
+                self.expression(then_expr);
+                emit!(self.code, "b\t{end}");
+                self.place_label(&else_label);
+                self.expression(else_expr);
+                self.place_label(&end);
+            }
+            Loop(cond_expr, loop_expr) => {
+                let start = self.label();
+                let end = self.label();
+                self.place_label(&start);
+                self.expression(cond_expr);
+                emit!(self.code, "lw\t$t1 12($a0)");
+                emit!(self.code, "beqz\t$t1 {end}");
+                self.expression(loop_expr);
+                emit!(self.code, "b\t{start}");
+                self.place_label(&end);
+                emit!(self.code, "move\t$a0 $zero");
+            }
+            Case(case_expr, branches) => self.case(expr, case_expr, branches),
+            Let(ident, type_id, opt_bind, body) => {
+                match &**opt_bind {
+                    Some(bind) => self.expression(bind),
+                    None => self.default_value(type_id),
+                }
+                let offset = self.allocate_temp();
+                emit!(self.code, "sw\t$a0 {offset}($fp)");
+                self.scopes.enter_scope();
+                self.scopes.insert(ident.clone(), offset);
+                self.expression(body);
+                self.scopes.exit_scope();
+                self.release_temp();
+            }
+            New(type_id) if type_id == SELF_TYPE => {
+                // class_objTab holds the prototype object and initialiser
+                // of each class, by tag.
+                emit!(self.code, "la\t$t1 class_objTab");
+                emit!(self.code, "lw\t$t2 0($s0)");
+                emit!(self.code, "sll\t$t2 $t2 3");
+                emit!(self.code, "addu\t$t1 $t1 $t2");
+                emit!(self.code, "sw\t$t1 0($sp)");
+                emit!(self.code, "addiu\t$sp $sp -4");
+                emit!(self.code, "lw\t$a0 0($t1)");
+                emit!(self.code, "jal\tObject.copy");
+                self.pop("$t1");
+                emit!(self.code, "lw\t$t1 4($t1)");
+                emit!(self.code, "jalr\t$t1");
+            }
+            New(type_id) => {
+                emit!(self.code, "la\t$a0 {type_id}_protObj");
+                emit!(self.code, "jal\tObject.copy");
+                emit!(self.code, "jal\t{type_id}_init");
+            }
+            Assign(ident, value) => {
+                self.expression(value);
+                match self.variable(ident) {
+                    Variable::Frame(offset) => {
+                        emit!(self.code, "sw\t$a0 {offset}($fp)");
+                    }
+                    Variable::Attribute(_) => self.store_attribute(ident),
+                    Variable::SelfObject => unreachable!("assignment to self"),
+                }
+            }
+            UnaryOperation(UnaryOperator::Negative, operand) => {
+                self.expression(operand);
+                emit!(self.code, "jal\tObject.copy");
+                emit!(self.code, "lw\t$t1 12($a0)");
+                emit!(self.code, "subu\t$t1 $zero $t1");
+                emit!(self.code, "sw\t$t1 12($a0)");
+            }
+            UnaryOperation(UnaryOperator::Not, operand) => {
+                self.expression(operand);
+                emit!(self.code, "lw\t$t1 12($a0)");
+                self.set_bool(format_args!("beqz\t$t1"));
+            }
+            UnaryOperation(UnaryOperator::IsVoid, operand) => {
+                self.expression(operand);
+                emit!(self.code, "move\t$t1 $a0");
+                self.set_bool(format_args!("beqz\t$t1"));
+            }
+            BinaryOperation(operator, operand1, operand2) => {
+                self.expression(operand1);
+                self.push();
+                self.expression(operand2);
+                self.binary_operation(operator);
+            }
+            MethodCall(callee, static_type, ident, params) => {
+                // Arguments are evaluated before the receiver.
+                for param in params.iter() {
+                    self.expression(param);
+                    self.push();
+                }
+                self.expression(callee);
+                let class = dispatch_class(static_type, callee, &self.class);
+                let slot =
+                    self.layout.class(&class).slot(ident).expect("method slot");
+                let dispatch = self.label();
+                emit!(self.code, "bnez\t$a0 {dispatch}");
+                // Our runtime also takes the method name in $t2 and whether
+                // the dispatch is static in $t3, which trap.handler ignores.
+                let method = self.string_label(ident);
+                emit!(self.code, "la\t$t2 {method}");
+                emit!(self.code, "li\t$t3 {}", static_type.is_some() as u8);
+                self.abort_at("_dispatch_abort", expr.location);
+                self.place_label(&dispatch);
+                let layout = self.layout.class(&class);
+                match layout.direct_call(slot, static_type.is_some()) {
+                    Some(owner) => emit!(self.code, "jal\t{owner}.{ident}"),
+                    None => {
+                        emit!(self.code, "lw\t$t1 8($a0)");
+                        emit!(self.code, "lw\t$t1 {}($t1)", WORD_SIZE * slot);
+                        emit!(self.code, "jalr\t$t1");
+                    }
+                }
+            }
+            Object(ident) => match self.variable(ident) {
+                Variable::SelfObject => emit!(self.code, "move\t$a0 $s0"),
+                Variable::Frame(offset) => {
+                    emit!(self.code, "lw\t$a0 {offset}($fp)")
+                }
+                Variable::Attribute(index) => {
+                    let offset = attribute_offset(index, WORD_SIZE);
+                    emit!(self.code, "lw\t$a0 {offset}($s0)")
+                }
+            },
+            IntLiteral(integer) => {
+                let label = self.int_label(*integer);
+                emit!(self.code, "la\t$a0 {label}");
+            }
+            StrLiteral(string) => {
+                let label = self.string_label(string);
+                emit!(self.code, "la\t$a0 {label}");
+            }
+            BoolLiteral(value) => {
+                emit!(self.code, "la\t$a0 bool_const{}", *value as u8);
+            }
+        }
+    }
+
+    // With the first operand pushed and the second in $a0.
+    fn binary_operation(&mut self, operator: &BinaryOperator) {
+        let instruction = match operator {
+            BinaryOperator::Equals => {
+                let done = self.label();
+                emit!(self.code, "move\t$t2 $a0");
+                self.pop("$t1");
+                emit!(self.code, "la\t$a0 bool_const1");
+                emit!(self.code, "beq\t$t1 $t2 {done}");
+                emit!(self.code, "la\t$a1 bool_const0");
+                emit!(self.code, "jal\tequality_test");
+                self.place_label(&done);
+                return;
+            }
+            BinaryOperator::LessThan | BinaryOperator::LessThanOrEquals => {
+                self.pop("$t1");
+                emit!(self.code, "lw\t$t1 12($t1)");
+                emit!(self.code, "lw\t$t2 12($a0)");
+                if *operator == BinaryOperator::LessThan {
+                    self.set_bool(format_args!("blt\t$t1 $t2"));
+                } else {
+                    self.set_bool(format_args!("ble\t$t1 $t2"));
+                }
+                return;
+            }
+            BinaryOperator::Add => "addu",
+            BinaryOperator::Subtract => "subu",
+            BinaryOperator::Multiply => "mul",
+            BinaryOperator::Divide => "div",
+        };
+        // The result is a copy of the second operand.
+        emit!(self.code, "jal\tObject.copy");
+        self.pop("$t1");
+        emit!(self.code, "lw\t$t1 12($t1)");
+        emit!(self.code, "lw\t$t2 12($a0)");
+        emit!(self.code, "{instruction}\t$t1 $t1 $t2");
+        emit!(self.code, "sw\t$t1 12($a0)");
+    }
+
+    fn case(
+        &mut self,
+        expr: &Expression,
+        case_expr: &Expression,
+        branches: &[CaseBranch],
+    ) {
+        let end = self.label();
+        let matched = self.label();
+        self.expression(case_expr);
+        emit!(self.code, "bnez\t$a0 {matched}");
+        self.abort_at("_case_abort2", expr.location);
+        self.place_label(&matched);
+        let offset = self.allocate_temp();
+        emit!(self.code, "sw\t$a0 {offset}($fp)");
+        emit!(self.code, "lw\t$t2 0($a0)");
+
+        for branch in self.layout.case_branches(branches) {
+            let class = self.layout.class(&branch.type_id);
+            let (first, last) = (class.tag, class.last_descendant);
+            let next = self.label();
+            emit!(self.code, "blt\t$t2 {first} {next}");
+            emit!(self.code, "bgt\t$t2 {last} {next}");
+            self.scopes.enter_scope();
+            self.scopes.insert(branch.ident.clone(), offset);
+            self.expression(&branch.expression);
+            self.scopes.exit_scope();
+            emit!(self.code, "b\t{end}");
+            self.place_label(&next);
+        }
+        // No branch matches, with the object still in $a0.
+        emit!(self.code, "jal\t_case_abort");
+        self.place_label(&end);
+        self.release_temp();
+    }
+
+    // Emit the data segment followed by the code.
+    fn finish(mut self) -> String {
+        let mut out = String::new();
+        emit!(out, ".data");
+        emit!(out, ".align\t2");
+        for global in [
+            "class_nameTab",
+            "class_objTab",
+            "Main_protObj",
+            "Int_protObj",
+            "String_protObj",
+            "bool_const0",
+            "bool_const1",
+            "_int_tag",
+            "_bool_tag",
+            "_string_tag",
+        ] {
+            emit!(out, ".globl\t{global}");
+        }
+        for (label, class) in [
+            ("_int_tag", INT),
+            ("_bool_tag", BOOL),
+            ("_string_tag", STRING),
+        ] {
+            writeln!(out, "{label}:").unwrap();
+            emit!(out, ".word\t{}", self.layout.tag(class));
+        }
+        let (initializer, collect) = match self.collector {
+            Collector::None => ("_NoGC_Init", "_NoGC_Collect"),
+            Collector::Generational => ("_GenGC_Init", "_GenGC_Collect"),
+        };
+        for (label, value) in [
+            ("_MemMgr_INITIALIZER", initializer),
+            ("_MemMgr_COLLECTOR", collect),
+            ("_MemMgr_TEST", "0"),
+        ] {
+            emit!(out, ".globl\t{label}");
+            writeln!(out, "{label}:").unwrap();
+            emit!(out, ".word\t{value}");
+        }
+
+        self.constants(&mut out);
+        self.tables(&mut out);
+
+        emit!(out, ".globl\theap_start");
+        writeln!(out, "heap_start:").unwrap();
+        emit!(out, ".word\t0");
+        emit!(out, ".text");
+        for global in ["Main_init", "Int_init", "String_init", "Bool_init"] {
+            emit!(out, ".globl\t{global}");
+        }
+        emit!(out, ".globl\tMain.main");
+        out.push_str(&self.code);
+        out
+    }
+
+    // Constants are objects preceded by the -1 eye catcher of the
+    // collectors.
+    fn constants(&mut self, out: &mut String) {
+        let int_tag = self.layout.tag(INT);
+        let string_tag = self.layout.tag(STRING);
+        let bool_tag = self.layout.tag(BOOL);
+        let strings = self.constants.strings().to_vec();
+        for (index, string) in strings.iter().enumerate() {
+            let length = self.int_label(string.len() as i32);
+            let words = HEADER_WORDS + 1 + (string.len() + WORD_SIZE) / 4;
+            emit!(out, ".word\t-1");
+            writeln!(out, "str_const{index}:").unwrap();
+            emit!(out, ".word\t{string_tag}");
+            emit!(out, ".word\t{words}");
+            emit!(out, ".word\tString_dispTab");
+            emit!(out, ".word\t{length}");
+            ascii(out, string.as_bytes());
+            emit!(out, ".byte\t0");
+            emit!(out, ".align\t2");
+        }
+        for (index, int) in self.constants.ints().iter().enumerate() {
+            emit!(out, ".word\t-1");
+            writeln!(out, "int_const{index}:").unwrap();
+            emit!(out, ".word\t{int_tag}");
+            emit!(out, ".word\t4");
+            emit!(out, ".word\tInt_dispTab");
+            emit!(out, ".word\t{int}");
+        }
+        for value in 0..2 {
+            emit!(out, ".word\t-1");
+            writeln!(out, "bool_const{value}:").unwrap();
+            emit!(out, ".word\t{bool_tag}");
+            emit!(out, ".word\t4");
+            emit!(out, ".word\tBool_dispTab");
+            emit!(out, ".word\t{value}");
+        }
+    }
+
+    fn tables(&mut self, out: &mut String) {
+        writeln!(out, "class_nameTab:").unwrap();
+        for index in 0..self.layout.classes.len() {
+            let name = self.layout.classes[index].name.clone();
+            let label = self.string_label(&name);
+            emit!(out, ".word\t{label}");
+        }
+        writeln!(out, "class_objTab:").unwrap();
+        for class in self.layout.classes.iter() {
+            emit!(out, ".word\t{}_protObj", class.name);
+            emit!(out, ".word\t{}_init", class.name);
+        }
+        for class in self.layout.classes.iter() {
+            writeln!(out, "{}_dispTab:", class.name).unwrap();
+            for (method, owner) in class.methods.iter() {
+                emit!(out, ".word\t{owner}.{method}");
+            }
+        }
+        let empty = self.string_label("");
+        let zero = self.int_label(0);
+        for class in self.layout.classes.iter() {
+            emit!(out, ".word\t-1");
+            writeln!(out, "{}_protObj:", class.name).unwrap();
+            emit!(out, ".word\t{}", class.tag);
+            emit!(out, ".word\t{}", class.size());
+            emit!(out, ".word\t{}_dispTab", class.name);
+            match class.name.as_str() {
+                INT | BOOL => emit!(out, ".word\t0"),
+                STRING => {
+                    emit!(out, ".word\t{zero}");
+                    emit!(out, ".word\t0");
+                }
+                _ => {}
+            }
+            for (_, type_id) in class.attributes.iter() {
+                match type_id.as_str() {
+                    INT => emit!(out, ".word\t{zero}"),
+                    STRING => emit!(out, ".word\t{empty}"),
+                    BOOL => emit!(out, ".word\tbool_const0"),
+                    _ => emit!(out, ".word\t0"),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,325 @@
+//! Native code generation for Cool.
+//!
+//! Every target shares the object layout of the course runtime. An object
+//! has a header of three words, the class tag, the size of the object in
+//! words and a pointer to the dispatch table of its class, followed by its
+//! attributes in inheritance order. Ints and Bools hold their value in the
+//! first attribute, and Strings hold a pointer to their length, an Int,
+//! followed by their characters and a terminating zero. Words are four
+//! bytes on MIPS and eight on x86-64.
+//!
+//! Class tags are allocated depth first from Object, so that the tags of a
+//! class and its descendants form a range and a case branch is selected with
+//! two comparisons. A method keeps the dispatch table slot it has in its
+//! defining class in all subclasses. Dispatches of a method that no
+//! descendant of the class of the receiver overrides call the method
+//! directly, rather than through the dispatch table.
+//!
+//! The initialiser of a class runs the initialiser of its parent and then
+//! the initialisations of the attributes of the class, in order, returning
+//! self. A case tries its branches from the most specific class, each
+//! matching the range of tags of its class and descendants.
+
+// Emit an instruction or directive.
+macro_rules! emit {
+    ($out:expr, $($arg:tt)*) => {
+        writeln!($out, "\t{}", format_args!($($arg)*)).unwrap()
+    };
+}
+
+mod c;
+mod llvm;
+mod mips;
+mod wasm;
+mod x86_64;
+
+pub use self::c::{emit_c, C_RUNTIME};
+pub use self::llvm::{emit_llvm, LLVM_RUNTIME};
+pub use self::mips::{emit_mips, Collector};
+pub use self::wasm::{assemble_wasm, emit_wasm, WASM_RUNTIME};
+pub use self::x86_64::{emit_x86_64, link_x86_64, X86_64_RUNTIME};
+use crate::hierarchy::*;
+use crate::ptree::{CaseBranch, Expression, FeatureData, Program};
+use std::collections::HashMap;
+use std::fmt::Write;
+
+#[cfg(test)]
+mod tests;
+
+/// The size in bytes of a machine word on MIPS.
+pub const WORD_SIZE: usize = 4;
+/// The number of words in an object header.
+pub const HEADER_WORDS: usize = 3;
+
+/// How generated code represents values of static type Int and Bool.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Representation {
+    /// Every value is an object, so that arithmetic allocates an Int.
+    Boxed,
+    /// Values are only boxed where an object is needed.
+    Unboxed,
+}
+
+pub struct ClassLayout {
+    pub name: String,
+    pub tag: usize,
+    pub parent: Option<usize>,
+    /// The number of ancestors of the class.
+    pub depth: usize,
+    /// The highest tag of the class and its descendants.
+    pub last_descendant: usize,
+    /// Names and types of the attributes, inherited attributes first.
+    pub attributes: Vec<(String, String)>,
+    /// Names of the methods in the dispatch table and the classes that
+    /// define them.
+    pub methods: Vec<(String, String)>,
+    /// Whether each method in the dispatch table is overridden by a
+    /// descendant of the class.
+    pub overridden: Vec<bool>,
+}
+
+impl ClassLayout {
+    pub fn slot(&self, method: &str) -> Option<usize> {
+        self.methods.iter().position(|(name, _)| name == method)
+    }
+
+    /// The class defining the method in a slot that a dispatch on an object
+    /// of the class can call directly, which a static dispatch always can.
+    pub fn direct_call(&self, slot: usize, is_static: bool) -> Option<&str> {
+        (is_static || !self.overridden[slot])
+            .then(|| self.methods[slot].1.as_str())
+    }
+
+    pub fn attribute_index(&self, attribute: &str) -> Option<usize> {
+        self.attributes
+            .iter()
+            .position(|(name, _)| name == attribute)
+    }
+
+    /// The size of an object of the class in words. Ints, Bools and Strings
+    /// hold more than their attributes.
+    pub fn size(&self) -> usize {
+        let extra = match self.name.as_str() {
+            INT | BOOL => 1,
+            STRING => 2,
+            _ => 0,
+        };
+        HEADER_WORDS + self.attributes.len() + extra
+    }
+}
+
+/// The layout of every class of a program, indexed by tag.
+pub struct Layout {
+    pub classes: Vec<ClassLayout>,
+    tags: HashMap<String, usize>,
+}
+
+impl Layout {
+    pub fn new(hierarchy: &ClassHierarchy) -> Self {
+        let mut layout = Self {
+            classes: Vec::new(),
+            tags: HashMap::new(),
+        };
+        layout.add_class(hierarchy, OBJECT, None);
+        layout
+    }
+
+    // Add a class after its parent and before its subclasses.
+    fn add_class(
+        &mut self,
+        hierarchy: &ClassHierarchy,
+        name: &str,
+        parent: Option<usize>,
+    ) {
+        let info = hierarchy.get(name).expect("known class");
+        let tag = self.classes.len();
+        let (mut attributes, mut methods) = match parent {
+            Some(parent) => (
+                self.classes[parent].attributes.clone(),
+                self.classes[parent].methods.clone(),
+            ),
+            None => (Vec::new(), Vec::new()),
+        };
+        for attr in info.attributes.iter() {
+            attributes.push((attr.name.clone(), attr.type_id.clone()));
+        }
+        for method in info.methods.iter() {
+            let entry = (method.name.clone(), name.to_string());
+            match methods.iter().position(|(m, _)| *m == method.name) {
+                Some(slot) => methods[slot] = entry,
+                None => methods.push(entry),
+            }
+        }
+        let overridden = methods
+            .iter()
+            .map(|(method, _)| hierarchy.is_overridden(name, method))
+            .collect();
+        self.tags.insert(name.to_string(), tag);
+        self.classes.push(ClassLayout {
+            name: name.to_string(),
+            tag,
+            parent,
+            depth: parent.map_or(0, |parent| self.classes[parent].depth + 1),
+            last_descendant: tag,
+            attributes,
+            methods,
+            overridden,
+        });
+        for subclass in hierarchy.subclasses(name) {
+            self.add_class(hierarchy, &subclass.name, Some(tag));
+        }
+        self.classes[tag].last_descendant = self.classes.len() - 1;
+    }
+
+    pub fn tag(&self, class: &str) -> usize {
+        self.tags[class]
+    }
+
+    pub fn class(&self, class: &str) -> &ClassLayout {
+        &self.classes[self.tag(class)]
+    }
+
+    /// The initialiser of every class, by tag.
+    pub fn initialisers<'p, 'a>(
+        &self,
+        program: &'p Program<'a>,
+    ) -> Vec<Initialiser<'p, 'a>> {
+        let definitions: HashMap<&str, _> = program
+            .classes
+            .iter()
+            .map(|class| (class.name.as_str(), class))
+            .collect();
+        self.classes
+            .iter()
+            .map(|class| Initialiser {
+                class: class.name.clone(),
+                parent: class
+                    .parent
+                    .map(|parent| self.classes[parent].name.clone()),
+                attributes: definitions
+                    .get(class.name.as_str())
+                    .iter()
+                    .flat_map(|definition| &definition.features)
+                    .filter_map(|feature| match &feature.data {
+                        FeatureData::Attribute(name, _, Some(init)) => {
+                            Some((name.as_str(), init))
+                        }
+                        _ => None,
+                    })
+                    .collect(),
+            })
+            .collect()
+    }
+
+    /// The branches of a case in the order they are tried, most specific
+    /// class first.
+    pub fn case_branches<'p, 'a>(
+        &self,
+        branches: &'p [CaseBranch<'a>],
+    ) -> Vec<&'p CaseBranch<'a>> {
+        let mut branches: Vec<&CaseBranch> = branches.iter().collect();
+        branches.sort_by_key(|branch| {
+            std::cmp::Reverse(self.class(&branch.type_id).depth)
+        });
+        branches
+    }
+}
+
+/// What the initialiser of a class runs.
+pub struct Initialiser<'p, 'a> {
+    pub class: String,
+    pub parent: Option<String>,
+    /// The attributes the class defines with an initialisation, and their
+    /// initialisations.
+    pub attributes: Vec<(&'p str, &'p Expression<'a>)>,
+}
+
+/// The class whose method a dispatch calls: the class of a static dispatch,
+/// or else the static type of the receiver, with SELF_TYPE standing for
+/// `class`, the class being generated.
+pub fn dispatch_class(
+    static_type: &Option<String>,
+    callee: &Expression,
+    class: &str,
+) -> String {
+    let dispatch_type = match static_type {
+        Some(type_id) => type_id.as_str(),
+        None => callee.static_type.as_deref().expect("typed tree"),
+    };
+    if dispatch_type == SELF_TYPE {
+        class.to_string()
+    } else {
+        dispatch_type.to_string()
+    }
+}
+
+/// The offset in bytes of an attribute of an object, given its index among
+/// the attributes of the class and the size of a word.
+pub fn attribute_offset(index: usize, word: usize) -> usize {
+    word * (HEADER_WORDS + index)
+}
+
+/// The string and integer constants of a program, each stored once.
+#[derive(Default)]
+pub struct Constants {
+    strings: Vec<String>,
+    ints: Vec<i32>,
+}
+
+impl Constants {
+    /// The index of a string constant. Its length is added as an integer
+    /// constant.
+    pub fn string(&mut self, string: &str) -> usize {
+        self.int(string.len() as i32);
+        match self.strings.iter().position(|s| s == string) {
+            Some(index) => index,
+            None => {
+                self.strings.push(string.to_string());
+                self.strings.len() - 1
+            }
+        }
+    }
+
+    pub fn int(&mut self, int: i32) -> usize {
+        match self.ints.iter().position(|i| *i == int) {
+            Some(index) => index,
+            None => {
+                self.ints.push(int);
+                self.ints.len() - 1
+            }
+        }
+    }
+
+    pub fn strings(&self) -> &[String] {
+        &self.strings
+    }
+
+    pub fn ints(&self) -> &[i32] {
+        &self.ints
+    }
+}
+
+// Emit the characters of a string, printable characters as .ascii
+// directives and others as bytes.
+fn ascii(out: &mut String, bytes: &[u8]) {
+    let mut run = String::new();
+    for &byte in bytes {
+        match byte {
+            b'"' => run.push_str("\\\""),
+            b'\\' => run.push_str("\\\\"),
+            b'\n' => run.push_str("\\n"),
+            b'\t' => run.push_str("\\t"),
+            b' '..=b'~' => run.push(byte as char),
+            _ => {
+                if !run.is_empty() {
+                    emit!(out, ".ascii\t\"{run}\"");
+                    run.clear();
+                }
+                emit!(out, ".byte\t{byte}");
+            }
+        }
+    }
+    if !run.is_empty() {
+        emit!(out, ".ascii\t\"{run}\"");
+    }
+}
@@ -0,0 +1,421 @@
+/*
+ * Runtime for Cool programs translated to C.
+ *
+ * Every object starts with a pointer to the table of methods of its class,
+ * which in turn starts with a pointer to the class descriptor. The tables of
+ * a subclass extend the table of its parent, so an object can be used
+ * through a pointer to any of its ancestors. The basic classes Object, IO,
+ * Int, Bool and String are defined here, along with the helpers the
+ * translated code calls. Objects are never freed.
+ *
+ * Runtime errors are written to standard error and exit with the status
+ * the interpreter uses for them.
+ */
+
+#include <stdarg.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+typedef struct cool_class cool_class;
+typedef struct Object Object;
+typedef struct IO IO;
+typedef struct Int Int;
+typedef struct Bool Bool;
+typedef struct String String;
+
+struct cool_class {
+    const char *name;
+    const cool_class *parent;
+    size_t size;
+    /* Create an initialised object of the class. */
+    Object *(*new)(void);
+};
+
+struct Object_Vtable {
+    const cool_class *class;
+    Object *(*abort)(Object *self);
+    String *(*type_name)(Object *self);
+    Object *(*copy)(Object *self);
+};
+
+struct IO_Vtable {
+    const cool_class *class;
+    Object *(*abort)(Object *self);
+    String *(*type_name)(Object *self);
+    Object *(*copy)(Object *self);
+    Object *(*out_string)(Object *self, String *x);
+    Object *(*out_int)(Object *self, Int *x);
+    String *(*in_string)(Object *self);
+    Int *(*in_int)(Object *self);
+};
+
+struct String_Vtable {
+    const cool_class *class;
+    Object *(*abort)(Object *self);
+    String *(*type_name)(Object *self);
+    Object *(*copy)(Object *self);
+    Int *(*length)(Object *self);
+    String *(*concat)(Object *self, String *s);
+    String *(*substr)(Object *self, Int *i, Int *l);
+};
+
+struct Object {
+    const struct Object_Vtable *vtable;
+};
+
+struct IO {
+    const struct IO_Vtable *vtable;
+};
+
+struct Int {
+    const struct Object_Vtable *vtable;
+    int32_t value;
+};
+
+struct Bool {
+    const struct Object_Vtable *vtable;
+    int32_t value;
+};
+
+struct String {
+    const struct String_Vtable *vtable;
+    int32_t length;
+    const char *chars;
+};
+
+Object *Object_abort(Object *self);
+String *Object_type_name(Object *self);
+Object *Object_copy(Object *self);
+Object *IO_out_string(Object *self, String *x);
+Object *IO_out_int(Object *self, Int *x);
+String *IO_in_string(Object *self);
+Int *IO_in_int(Object *self);
+Int *String_length(Object *self);
+String *String_concat(Object *self, String *s);
+String *String_substr(Object *self, Int *i, Int *l);
+Object *Object_New(void);
+Object *IO_New(void);
+Object *Int_New(void);
+Object *Bool_New(void);
+Object *String_New(void);
+
+const cool_class Object_Class = {"Object", NULL, sizeof(Object), Object_New};
+const cool_class IO_Class = {"IO", &Object_Class, sizeof(IO), IO_New};
+const cool_class Int_Class = {"Int", &Object_Class, sizeof(Int), Int_New};
+const cool_class Bool_Class = {"Bool", &Object_Class, sizeof(Bool), Bool_New};
+const cool_class String_Class = {
+    "String", &Object_Class, sizeof(String), String_New
+};
+
+const struct Object_Vtable Object_Vtable = {
+    &Object_Class, Object_abort, Object_type_name, Object_copy
+};
+const struct IO_Vtable IO_Vtable = {
+    &IO_Class, Object_abort, Object_type_name, Object_copy,
+    IO_out_string, IO_out_int, IO_in_string, IO_in_int
+};
+const struct Object_Vtable Int_Vtable = {
+    &Int_Class, Object_abort, Object_type_name, Object_copy
+};
+const struct Object_Vtable Bool_Vtable = {
+    &Bool_Class, Object_abort, Object_type_name, Object_copy
+};
+const struct String_Vtable String_Vtable = {
+    &String_Class, Object_abort, Object_type_name, Object_copy,
+    String_length, String_concat, String_substr
+};
+
+Bool cool_false = {&Bool_Vtable, 0};
+Bool cool_true = {&Bool_Vtable, 1};
+
+void *cool_allocate(size_t size)
+{
+    void *memory = malloc(size);
+
+    if (memory == NULL) {
+        fflush(stdout);
+        fputs("Out of memory.\n", stderr);
+        exit(19);
+    }
+    return memory;
+}
+
+Int *cool_int(int32_t value)
+{
+    Int *i = cool_allocate(sizeof(Int));
+
+    i->vtable = &Int_Vtable;
+    i->value = value;
+    return i;
+}
+
+Bool *cool_bool(int value)
+{
+    return value ? &cool_true : &cool_false;
+}
+
+/* Make a String of length characters, taking over a terminated buffer. */
+static String *cool_wrap_string(char *chars, size_t length)
+{
+    String *s = cool_allocate(sizeof(String));
+
+    chars[length] = '\0';
+    s->vtable = &String_Vtable;
+    s->length = (int32_t)length;
+    s->chars = chars;
+    return s;
+}
+
+/* Make a String of the first length characters at chars. */
+String *cool_string(const char *chars, size_t length)
+{
+    char *copy = cool_allocate(length + 1);
+
+    memcpy(copy, chars, length);
+    return cool_wrap_string(copy, length);
+}
+
+const cool_class *cool_class_of(Object *object)
+{
+    return object->vtable->class;
+}
+
+/* Arithmetic wraps around on overflow. */
+Int *cool_add(Int *a, Int *b)
+{
+    return cool_int((int32_t)((uint32_t)a->value + (uint32_t)b->value));
+}
+
+Int *cool_sub(Int *a, Int *b)
+{
+    return cool_int((int32_t)((uint32_t)a->value - (uint32_t)b->value));
+}
+
+Int *cool_mul(Int *a, Int *b)
+{
+    return cool_int((int32_t)((uint32_t)a->value * (uint32_t)b->value));
+}
+
+Int *cool_neg(Int *a)
+{
+    return cool_int((int32_t)(0u - (uint32_t)a->value));
+}
+
+static void cool_error_at(const char *file, int line, const char *message)
+{
+    fflush(stdout);
+    fprintf(stderr, "%s:%d: %s\n", file, line, message);
+}
+
+Int *cool_div(Int *a, Int *b, const char *file, int line)
+{
+    if (b->value == 0) {
+        cool_error_at(file, line, "Division by zero.");
+        exit(9);
+    }
+    if (b->value == -1)
+        return cool_neg(a);
+    return cool_int(a->value / b->value);
+}
+
+/* Ints, Bools and Strings are equal when their values are. */
+int cool_equals(Object *a, Object *b)
+{
+    if (a == b)
+        return 1;
+    if (a == NULL || b == NULL || a->vtable != b->vtable)
+        return 0;
+    if (cool_class_of(a) == &Int_Class || cool_class_of(a) == &Bool_Class)
+        return ((Int *)a)->value == ((Int *)b)->value;
+    if (cool_class_of(a) == &String_Class) {
+        String *s = (String *)a, *t = (String *)b;
+        return s->length == t->length
+               && memcmp(s->chars, t->chars, s->length) == 0;
+    }
+    return 0;
+}
+
+/* Report a dispatch on void, which is static when is_static is set. */
+void cool_dispatch_abort(const char *file, int line, const char *method,
+                         int is_static)
+{
+    fflush(stdout);
+    fprintf(stderr, "%s:%d: %s to void calling method %s.\n", file, line,
+            is_static ? "Static dispatch" : "Dispatch", method);
+    exit(is_static ? 6 : 5);
+}
+
+/*
+ * Select the branch of a case for an object, given the classes of the
+ * branches: the one whose class is the closest ancestor of the class of the
+ * object.
+ */
+int cool_case(Object *object, const char *file, int line, int count, ...)
+{
+    const cool_class *class;
+    va_list classes;
+    int branch;
+
+    if (object == NULL) {
+        cool_error_at(file, line, "Match on void in case statement.");
+        exit(7);
+    }
+    for (class = cool_class_of(object); class != NULL; class = class->parent) {
+        va_start(classes, count);
+        for (branch = 0; branch < count; branch++) {
+            if (va_arg(classes, const cool_class *) == class) {
+                va_end(classes);
+                return branch;
+            }
+        }
+        va_end(classes);
+    }
+    fflush(stdout);
+    fprintf(stderr, "No match in case statement for Class %s.\n",
+            cool_class_of(object)->name);
+    exit(8);
+}
+
+Object *Object_abort(Object *self)
+{
+    fflush(stdout);
+    fprintf(stderr, "Abort called from class %s\n", cool_class_of(self)->name);
+    exit(11);
+}
+
+String *Object_type_name(Object *self)
+{
+    const char *name = cool_class_of(self)->name;
+
+    return cool_string(name, strlen(name));
+}
+
+Object *Object_copy(Object *self)
+{
+    size_t size = cool_class_of(self)->size;
+
+    return memcpy(cool_allocate(size), self, size);
+}
+
+Object *IO_out_string(Object *self, String *x)
+{
+    fwrite(x->chars, 1, x->length, stdout);
+    return self;
+}
+
+Object *IO_out_int(Object *self, Int *x)
+{
+    printf("%ld", (long)x->value);
+    return self;
+}
+
+/* Read a line without its line terminator, which may be CRLF. */
+static String *cool_read_line(void)
+{
+    size_t length = 0, capacity = 64;
+    char *line = cool_allocate(capacity);
+    int c;
+
+    fflush(stdout);
+    while ((c = getchar()) != EOF && c != '\n') {
+        /* Leave room for the terminator. */
+        if (length + 1 == capacity) {
+            char *larger = cool_allocate(capacity *= 2);
+            memcpy(larger, line, length);
+            free(line);
+            line = larger;
+        }
+        line[length++] = (char)c;
+    }
+    if (c == '\n' && length > 0 && line[length - 1] == '\r')
+        length--;
+    return cool_wrap_string(line, length);
+}
+
+String *IO_in_string(Object *self)
+{
+    (void)self;
+    return cool_read_line();
+}
+
+/*
+ * Leading whitespace is skipped, then an optional sign and digits are read,
+ * wrapping on overflow. Anything else yields 0.
+ */
+Int *IO_in_int(Object *self)
+{
+    const char *c = cool_read_line()->chars;
+    uint32_t value = 0;
+    int negative = 0;
+
+    (void)self;
+    while (*c == ' ' || (*c >= '\t' && *c <= '\r'))
+        c++;
+    if (*c == '-' || *c == '+')
+        negative = *c++ == '-';
+    while (*c >= '0' && *c <= '9')
+        value = value * 10 + (uint32_t)(*c++ - '0');
+    return cool_int((int32_t)(negative ? 0u - value : value));
+}
+
+Int *String_length(Object *self)
+{
+    return cool_int(((String *)self)->length);
+}
+
+String *String_concat(Object *self, String *s)
+{
+    String *t = (String *)self;
+    size_t length = (size_t)t->length + (size_t)s->length;
+    char *chars = cool_allocate(length + 1);
+
+    memcpy(chars, t->chars, t->length);
+    memcpy(chars + t->length, s->chars, s->length);
+    return cool_wrap_string(chars, length);
+}
+
+String *String_substr(Object *self, Int *i, Int *l)
+{
+    String *s = (String *)self;
+    int64_t start = i->value, length = l->value;
+
+    if (start < 0 || length < 0 || start + length > s->length) {
+        fflush(stdout);
+        fputs("Index out of range in substr.\n", stderr);
+        exit(10);
+    }
+    return cool_string(s->chars + start, (size_t)length);
+}
+
+Object *Object_New(void)
+{
+    Object *object = cool_allocate(sizeof(Object));
+
+    object->vtable = &Object_Vtable;
+    return object;
+}
+
+Object *IO_New(void)
+{
+    IO *io = cool_allocate(sizeof(IO));
+
+    io->vtable = &IO_Vtable;
+    return (Object *)io;
+}
+
+Object *Int_New(void)
+{
+    return (Object *)cool_int(0);
+}
+
+Object *Bool_New(void)
+{
+    return (Object *)&cool_false;
+}
+
+Object *String_New(void)
+{
+    return (Object *)cool_string("", 0);
+}
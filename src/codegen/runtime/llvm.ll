@@ -0,0 +1,468 @@
+; Runtime for Cool programs compiled to LLVM IR.
+;
+; Every object starts with a pointer to the table of methods of its class,
+; which in turn starts with a pointer to the descriptor of the class, giving
+; its name, parent, size and a function creating an initialised object. The
+; types, descriptors and tables of all classes, including the basic ones,
+; are generated with the program. This file defines the layout of the basic
+; classes, their methods and the helpers the generated code calls. Objects
+; are never freed.
+;
+; Runtime errors are written to standard error and exit with the status the
+; interpreter uses for them.
+
+%cool_class = type { ptr, ptr, i64, ptr }
+%cool_vtable = type { ptr }
+%Object = type { ptr }
+%IO = type { ptr }
+%Int = type { ptr, i32 }
+%Bool = type { ptr, i32 }
+%String = type { ptr, i32, ptr }
+
+declare ptr @malloc(i64)
+declare ptr @realloc(ptr, i64)
+declare ptr @memcpy(ptr, ptr, i64)
+declare i32 @memcmp(ptr, ptr, i64)
+declare i64 @strlen(ptr)
+declare i32 @getchar()
+declare i32 @printf(ptr, ...)
+declare i32 @dprintf(i32, ptr, ...)
+declare i32 @fflush(ptr)
+declare void @exit(i32) noreturn
+
+@cool_true = constant %Bool {
+  ptr @Bool.class.vtable, i32 1
+}
+@cool_false = constant %Bool {
+  ptr @Bool.class.vtable, i32 0
+}
+
+@.empty = private unnamed_addr constant [1 x i8] zeroinitializer
+@.int_format = private unnamed_addr constant [3 x i8] c"%d\00"
+@.string_format = private unnamed_addr constant [5 x i8] c"%.*s\00"
+@.error_format = private unnamed_addr constant [3 x i8] c"%s\00"
+@.error_at_format = private unnamed_addr constant [11 x i8] c"%s:%d: %s\0A\00"
+@.out_of_memory = private unnamed_addr constant [16 x i8] c"Out of memory.\0A\00"
+@.division_by_zero = private unnamed_addr constant [18 x i8] c"Division by zero.\00"
+@.dispatch_to_void = private unnamed_addr constant [38 x i8] c"%s:%d: %s to void calling method %s.\0A\00"
+@.dispatch = private unnamed_addr constant [9 x i8] c"Dispatch\00"
+@.static_dispatch = private unnamed_addr constant [16 x i8] c"Static dispatch\00"
+@.case_on_void = private unnamed_addr constant [33 x i8] c"Match on void in case statement.\00"
+@.no_match = private unnamed_addr constant [42 x i8] c"No match in case statement for Class %s.\0A\00"
+@.abort = private unnamed_addr constant [28 x i8] c"Abort called from class %s\0A\00"
+@.substr_range = private unnamed_addr constant [31 x i8] c"Index out of range in substr.\0A\00"
+
+; Write a message to standard error and exit.
+define internal void @cool_error(ptr %message, i32 %status) noreturn {
+  %flushed = call i32 @fflush(ptr null)
+  %written = call i32 (i32, ptr, ...) @dprintf(i32 2, ptr @.error_format, ptr %message)
+  call void @exit(i32 %status)
+  unreachable
+}
+
+; Report an error at a line of a source file and exit.
+define internal void @cool_error_at(ptr %file, i32 %line, ptr %message, i32 %status) noreturn {
+  %flushed = call i32 @fflush(ptr null)
+  %written = call i32 (i32, ptr, ...) @dprintf(i32 2, ptr @.error_at_format, ptr %file, i32 %line, ptr %message)
+  call void @exit(i32 %status)
+  unreachable
+}
+
+define ptr @cool_allocate(i64 %size) {
+  %memory = call ptr @malloc(i64 %size)
+  %failed = icmp eq ptr %memory, null
+  br i1 %failed, label %fail, label %done
+fail:
+  call void @cool_error(ptr @.out_of_memory, i32 19)
+  unreachable
+done:
+  ret ptr %memory
+}
+
+define ptr @cool_int(i32 %value) {
+  %memory = call ptr @cool_allocate(i64 ptrtoint (ptr getelementptr (%Int, ptr null, i32 1) to i64))
+  %vtable = getelementptr inbounds %Int, ptr %memory, i32 0, i32 0
+  store ptr @Int.class.vtable, ptr %vtable
+  %field = getelementptr inbounds %Int, ptr %memory, i32 0, i32 1
+  store i32 %value, ptr %field
+  ret ptr %memory
+}
+
+define ptr @cool_bool(i1 %value) {
+  %object = select i1 %value, ptr @cool_true, ptr @cool_false
+  ret ptr %object
+}
+
+; Make a String of length characters, taking over a buffer with room for a
+; terminator.
+define internal ptr @cool_wrap_string(ptr %chars, i32 %length) {
+  %end = getelementptr inbounds i8, ptr %chars, i32 %length
+  store i8 0, ptr %end
+  %memory = call ptr @cool_allocate(i64 ptrtoint (ptr getelementptr (%String, ptr null, i32 1) to i64))
+  %vtable = getelementptr inbounds %String, ptr %memory, i32 0, i32 0
+  store ptr @String.class.vtable, ptr %vtable
+  %length_field = getelementptr inbounds %String, ptr %memory, i32 0, i32 1
+  store i32 %length, ptr %length_field
+  %chars_field = getelementptr inbounds %String, ptr %memory, i32 0, i32 2
+  store ptr %chars, ptr %chars_field
+  ret ptr %memory
+}
+
+; Make a String of the first length characters at chars.
+define ptr @cool_string(ptr %chars, i32 %length) {
+  %size = zext i32 %length to i64
+  %buffer_size = add i64 %size, 1
+  %copy = call ptr @cool_allocate(i64 %buffer_size)
+  %copied = call ptr @memcpy(ptr %copy, ptr %chars, i64 %size)
+  %string = call ptr @cool_wrap_string(ptr %copy, i32 %length)
+  ret ptr %string
+}
+
+define ptr @cool_class_of(ptr %object) {
+  %vtable_field = getelementptr inbounds %Object, ptr %object, i32 0, i32 0
+  %vtable = load ptr, ptr %vtable_field
+  %class_field = getelementptr inbounds %cool_vtable, ptr %vtable, i32 0, i32 0
+  %class = load ptr, ptr %class_field
+  ret ptr %class
+}
+
+define internal i32 @cool_value(ptr %object) {
+  %field = getelementptr inbounds %Int, ptr %object, i32 0, i32 1
+  %value = load i32, ptr %field
+  ret i32 %value
+}
+
+define internal i32 @cool_length(ptr %object) {
+  %field = getelementptr inbounds %String, ptr %object, i32 0, i32 1
+  %length = load i32, ptr %field
+  ret i32 %length
+}
+
+define internal ptr @cool_chars(ptr %object) {
+  %field = getelementptr inbounds %String, ptr %object, i32 0, i32 2
+  %chars = load ptr, ptr %field
+  ret ptr %chars
+}
+
+; Division truncates, and dividing the least Int by -1 wraps around.
+define i32 @cool_div(i32 %a, i32 %b, ptr %file, i32 %line) {
+  %zero = icmp eq i32 %b, 0
+  br i1 %zero, label %fail, label %nonzero
+fail:
+  call void @cool_error_at(ptr %file, i32 %line, ptr @.division_by_zero, i32 9)
+  unreachable
+nonzero:
+  %negate = icmp eq i32 %b, -1
+  br i1 %negate, label %negative, label %divide
+negative:
+  %negated = sub i32 0, %a
+  ret i32 %negated
+divide:
+  %quotient = sdiv i32 %a, %b
+  ret i32 %quotient
+}
+
+; Ints, Bools and Strings are equal when their values are.
+define i1 @cool_equals(ptr %a, ptr %b) {
+  %same = icmp eq ptr %a, %b
+  br i1 %same, label %equal, label %distinct
+distinct:
+  %a_void = icmp eq ptr %a, null
+  %b_void = icmp eq ptr %b, null
+  %either_void = or i1 %a_void, %b_void
+  br i1 %either_void, label %unequal, label %objects
+objects:
+  %class = call ptr @cool_class_of(ptr %a)
+  %b_class = call ptr @cool_class_of(ptr %b)
+  %same_class = icmp eq ptr %class, %b_class
+  br i1 %same_class, label %compare, label %unequal
+compare:
+  %is_int = icmp eq ptr %class, @Int.class
+  %is_bool = icmp eq ptr %class, @Bool.class
+  %has_value = or i1 %is_int, %is_bool
+  br i1 %has_value, label %values, label %not_value
+values:
+  %a_value = call i32 @cool_value(ptr %a)
+  %b_value = call i32 @cool_value(ptr %b)
+  %values_equal = icmp eq i32 %a_value, %b_value
+  ret i1 %values_equal
+not_value:
+  %is_string = icmp eq ptr %class, @String.class
+  br i1 %is_string, label %strings, label %unequal
+strings:
+  %a_length = call i32 @cool_length(ptr %a)
+  %b_length = call i32 @cool_length(ptr %b)
+  %same_length = icmp eq i32 %a_length, %b_length
+  br i1 %same_length, label %characters, label %unequal
+characters:
+  %a_chars = call ptr @cool_chars(ptr %a)
+  %b_chars = call ptr @cool_chars(ptr %b)
+  %size = zext i32 %a_length to i64
+  %order = call i32 @memcmp(ptr %a_chars, ptr %b_chars, i64 %size)
+  %chars_equal = icmp eq i32 %order, 0
+  ret i1 %chars_equal
+equal:
+  ret i1 true
+unequal:
+  ret i1 false
+}
+
+define void @cool_dispatch_abort(ptr %file, i32 %line, ptr %method, i1 %static) noreturn {
+  %kind = select i1 %static, ptr @.static_dispatch, ptr @.dispatch
+  %status = select i1 %static, i32 6, i32 5
+  %flushed = call i32 @fflush(ptr null)
+  %written = call i32 (i32, ptr, ...) @dprintf(i32 2, ptr @.dispatch_to_void, ptr %file, i32 %line, ptr %kind, ptr %method)
+  call void @exit(i32 %status)
+  unreachable
+}
+
+define void @cool_case_abort(ptr %file, i32 %line) noreturn {
+  call void @cool_error_at(ptr %file, i32 %line, ptr @.case_on_void, i32 7)
+  unreachable
+}
+
+define void @cool_case_no_match(ptr %object) noreturn {
+  %class = call ptr @cool_class_of(ptr %object)
+  %name_field = getelementptr inbounds %cool_class, ptr %class, i32 0, i32 0
+  %name = load ptr, ptr %name_field
+  %flushed = call i32 @fflush(ptr null)
+  %written = call i32 (i32, ptr, ...) @dprintf(i32 2, ptr @.no_match, ptr %name)
+  call void @exit(i32 8)
+  unreachable
+}
+
+define ptr @Object.abort(ptr %self) {
+  %class = call ptr @cool_class_of(ptr %self)
+  %name_field = getelementptr inbounds %cool_class, ptr %class, i32 0, i32 0
+  %name = load ptr, ptr %name_field
+  %flushed = call i32 @fflush(ptr null)
+  %written = call i32 (i32, ptr, ...) @dprintf(i32 2, ptr @.abort, ptr %name)
+  call void @exit(i32 11)
+  unreachable
+}
+
+define ptr @Object.type_name(ptr %self) {
+  %class = call ptr @cool_class_of(ptr %self)
+  %name_field = getelementptr inbounds %cool_class, ptr %class, i32 0, i32 0
+  %name = load ptr, ptr %name_field
+  %length = call i64 @strlen(ptr %name)
+  %length32 = trunc i64 %length to i32
+  %string = call ptr @cool_string(ptr %name, i32 %length32)
+  ret ptr %string
+}
+
+define ptr @Object.copy(ptr %self) {
+  %class = call ptr @cool_class_of(ptr %self)
+  %size_field = getelementptr inbounds %cool_class, ptr %class, i32 0, i32 2
+  %size = load i64, ptr %size_field
+  %copy = call ptr @cool_allocate(i64 %size)
+  %copied = call ptr @memcpy(ptr %copy, ptr %self, i64 %size)
+  ret ptr %copy
+}
+
+define ptr @IO.out_string(ptr %self, ptr %x) {
+  %length = call i32 @cool_length(ptr %x)
+  %chars = call ptr @cool_chars(ptr %x)
+  %written = call i32 (ptr, ...) @printf(ptr @.string_format, i32 %length, ptr %chars)
+  ret ptr %self
+}
+
+define ptr @IO.out_int(ptr %self, ptr %x) {
+  %value = call i32 @cool_value(ptr %x)
+  %written = call i32 (ptr, ...) @printf(ptr @.int_format, i32 %value)
+  ret ptr %self
+}
+
+; Read a line without its line terminator, which may be CRLF.
+define internal ptr @cool_read_line() {
+entry:
+  %buffer = alloca ptr
+  %length = alloca i64
+  %capacity = alloca i64
+  %flushed = call i32 @fflush(ptr null)
+  %initial = call ptr @cool_allocate(i64 64)
+  store ptr %initial, ptr %buffer
+  store i64 0, ptr %length
+  store i64 64, ptr %capacity
+  br label %read
+read:
+  %c = call i32 @getchar()
+  %eof = icmp eq i32 %c, -1
+  %newline = icmp eq i32 %c, 10
+  %end = or i1 %eof, %newline
+  br i1 %end, label %done, label %check
+check:
+  %used = load i64, ptr %length
+  %size = load i64, ptr %capacity
+  ; Leave room for the terminator.
+  %needed = add i64 %used, 1
+  %full = icmp eq i64 %needed, %size
+  br i1 %full, label %grow, label %append
+grow:
+  %larger = mul i64 %size, 2
+  store i64 %larger, ptr %capacity
+  %old = load ptr, ptr %buffer
+  %new = call ptr @realloc(ptr %old, i64 %larger)
+  %failed = icmp eq ptr %new, null
+  br i1 %failed, label %out_of_memory, label %grown
+out_of_memory:
+  call void @cool_error(ptr @.out_of_memory, i32 19)
+  unreachable
+grown:
+  store ptr %new, ptr %buffer
+  br label %append
+append:
+  %line = load ptr, ptr %buffer
+  %position = load i64, ptr %length
+  %slot = getelementptr inbounds i8, ptr %line, i64 %position
+  %char = trunc i32 %c to i8
+  store i8 %char, ptr %slot
+  %next = add i64 %position, 1
+  store i64 %next, ptr %length
+  br label %read
+done:
+  %chars = load ptr, ptr %buffer
+  %count = load i64, ptr %length
+  %nonempty = icmp ugt i64 %count, 0
+  %ends_line = and i1 %newline, %nonempty
+  br i1 %ends_line, label %check_return, label %finish
+check_return:
+  %last_index = sub i64 %count, 1
+  %last = getelementptr inbounds i8, ptr %chars, i64 %last_index
+  %last_char = load i8, ptr %last
+  %is_return = icmp eq i8 %last_char, 13
+  br i1 %is_return, label %strip, label %finish
+strip:
+  br label %finish
+finish:
+  %final = phi i64 [ %count, %done ], [ %count, %check_return ], [ %last_index, %strip ]
+  %final32 = trunc i64 %final to i32
+  %string = call ptr @cool_wrap_string(ptr %chars, i32 %final32)
+  ret ptr %string
+}
+
+define ptr @IO.in_string(ptr %self) {
+  %string = call ptr @cool_read_line()
+  ret ptr %string
+}
+
+; Leading whitespace is skipped, then an optional sign and digits are read,
+; wrapping on overflow. Anything else yields 0.
+define ptr @IO.in_int(ptr %self) {
+entry:
+  %line = call ptr @cool_read_line()
+  %start = call ptr @cool_chars(ptr %line)
+  br label %space
+space:
+  %c = phi ptr [ %start, %entry ], [ %after_space, %skip ]
+  %char = load i8, ptr %c
+  %is_space = icmp eq i8 %char, 32
+  %control = sub i8 %char, 9
+  %is_control = icmp ult i8 %control, 5
+  %whitespace = or i1 %is_space, %is_control
+  br i1 %whitespace, label %skip, label %sign
+skip:
+  %after_space = getelementptr inbounds i8, ptr %c, i64 1
+  br label %space
+sign:
+  %is_minus = icmp eq i8 %char, 45
+  %is_plus = icmp eq i8 %char, 43
+  %signed = or i1 %is_minus, %is_plus
+  %after_sign = getelementptr inbounds i8, ptr %c, i64 1
+  %first = select i1 %signed, ptr %after_sign, ptr %c
+  br label %digits
+digits:
+  %d = phi ptr [ %first, %sign ], [ %next, %digit ]
+  %value = phi i32 [ 0, %sign ], [ %sum, %digit ]
+  %digit_char = load i8, ptr %d
+  %digit_value = sub i8 %digit_char, 48
+  %is_digit = icmp ult i8 %digit_value, 10
+  br i1 %is_digit, label %digit, label %done
+digit:
+  %times_ten = mul i32 %value, 10
+  %extended = zext i8 %digit_value to i32
+  %sum = add i32 %times_ten, %extended
+  %next = getelementptr inbounds i8, ptr %d, i64 1
+  br label %digits
+done:
+  %negated = sub i32 0, %value
+  %result = select i1 %is_minus, i32 %negated, i32 %value
+  %int = call ptr @cool_int(i32 %result)
+  ret ptr %int
+}
+
+define ptr @String.length(ptr %self) {
+  %length = call i32 @cool_length(ptr %self)
+  %int = call ptr @cool_int(i32 %length)
+  ret ptr %int
+}
+
+define ptr @String.concat(ptr %self, ptr %s) {
+  %length = call i32 @cool_length(ptr %self)
+  %other_length = call i32 @cool_length(ptr %s)
+  %size = zext i32 %length to i64
+  %other_size = zext i32 %other_length to i64
+  %total = add i64 %size, %other_size
+  %buffer_size = add i64 %total, 1
+  %chars = call ptr @cool_allocate(i64 %buffer_size)
+  %self_chars = call ptr @cool_chars(ptr %self)
+  %copied = call ptr @memcpy(ptr %chars, ptr %self_chars, i64 %size)
+  %rest = getelementptr inbounds i8, ptr %chars, i64 %size
+  %other_chars = call ptr @cool_chars(ptr %s)
+  %copied_rest = call ptr @memcpy(ptr %rest, ptr %other_chars, i64 %other_size)
+  %total32 = trunc i64 %total to i32
+  %string = call ptr @cool_wrap_string(ptr %chars, i32 %total32)
+  ret ptr %string
+}
+
+define ptr @String.substr(ptr %self, ptr %i, ptr %l) {
+  %start32 = call i32 @cool_value(ptr %i)
+  %count32 = call i32 @cool_value(ptr %l)
+  %length32 = call i32 @cool_length(ptr %self)
+  %start = sext i32 %start32 to i64
+  %count = sext i32 %count32 to i64
+  %length = sext i32 %length32 to i64
+  %end = add i64 %start, %count
+  %negative_start = icmp slt i64 %start, 0
+  %negative_count = icmp slt i64 %count, 0
+  %past_end = icmp sgt i64 %end, %length
+  %negative = or i1 %negative_start, %negative_count
+  %invalid = or i1 %negative, %past_end
+  br i1 %invalid, label %fail, label %copy
+fail:
+  call void @cool_error(ptr @.substr_range, i32 10)
+  unreachable
+copy:
+  %chars = call ptr @cool_chars(ptr %self)
+  %first = getelementptr inbounds i8, ptr %chars, i64 %start
+  %string = call ptr @cool_string(ptr %first, i32 %count32)
+  ret ptr %string
+}
+
+define ptr @Object.new() {
+  %memory = call ptr @cool_allocate(i64 ptrtoint (ptr getelementptr (%Object, ptr null, i32 1) to i64))
+  %vtable = getelementptr inbounds %Object, ptr %memory, i32 0, i32 0
+  store ptr @Object.class.vtable, ptr %vtable
+  ret ptr %memory
+}
+
+define ptr @IO.new() {
+  %memory = call ptr @cool_allocate(i64 ptrtoint (ptr getelementptr (%IO, ptr null, i32 1) to i64))
+  %vtable = getelementptr inbounds %IO, ptr %memory, i32 0, i32 0
+  store ptr @IO.class.vtable, ptr %vtable
+  ret ptr %memory
+}
+
+define ptr @Int.new() {
+  %int = call ptr @cool_int(i32 0)
+  ret ptr %int
+}
+
+define ptr @Bool.new() {
+  ret ptr @cool_false
+}
+
+define ptr @String.new() {
+  %string = call ptr @cool_string(ptr @.empty, i32 0)
+  ret ptr %string
+}
@@ -0,0 +1,457 @@
+  ;; Runtime for Cool programs compiled to WebAssembly.
+  ;;
+  ;; This text is spliced into the generated module, which provides the
+  ;; memory, the function table, the constants and tables of the program and
+  ;; the globals the runtime refers to: the tags of Int, Bool and String,
+  ;; their prototype objects and dispatch tables, the Bool constants, the
+  ;; table of class names and the heap pointer. Objects use the layout of the
+  ;; course runtime and are never freed. Address 0 is void.
+  ;;
+  ;; The host interface is three functions of WASI preview 1, so modules run
+  ;; under any WASI implementation. Only features of the first version of
+  ;; WebAssembly are used.
+  ;;
+  ;; The first 2048 bytes of memory are reserved for the runtime:
+  ;;
+  ;;   8     the buffer of fd_write and fd_read, address and length
+  ;;   16    the number of bytes written or read
+  ;;   32    the digits of an Int being written, ending at 64
+  ;;   64    messages, every 64 bytes
+  ;;   1024  buffered input
+  ;;
+  ;; Runtime errors are written to standard error and exit with the status
+  ;; the interpreter uses for them.
+
+  (import "wasi_snapshot_preview1" "fd_write"
+    (func $fd_write (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "fd_read"
+    (func $fd_read (param i32 i32 i32 i32) (result i32)))
+  (import "wasi_snapshot_preview1" "proc_exit"
+    (func $proc_exit (param i32)))
+
+  (data (i32.const 64) "Abort called from class ")
+  (data (i32.const 128) "\n")
+  (data (i32.const 192) ": ")
+  (data (i32.const 256) ":")
+  (data (i32.const 320) "Dispatch")
+  (data (i32.const 384) "Match on void in case statement.")
+  (data (i32.const 448) "No match in case statement for Class ")
+  (data (i32.const 512) ".\n")
+  (data (i32.const 576) "Division by zero.")
+  (data (i32.const 640) "Index out of range in substr.\n")
+  (data (i32.const 704) "Out of memory.\n")
+  (data (i32.const 768) "Static dispatch")
+  (data (i32.const 832) " to void calling method ")
+
+  ;; The unread part of the input buffer.
+  (global $input_next (mut i32) (i32.const 1024))
+  (global $input_end (mut i32) (i32.const 1024))
+
+  (func $exit (param $status i32)
+    (call $proc_exit (local.get $status))
+    (unreachable))
+
+  ;; Write length bytes of memory to a file descriptor.
+  (func $write (param $fd i32) (param $address i32) (param $length i32)
+    (block $done
+      (loop $more
+        (br_if $done (i32.le_s (local.get $length) (i32.const 0)))
+        (i32.store (i32.const 8) (local.get $address))
+        (i32.store (i32.const 12) (local.get $length))
+        (br_if $done
+          (call $fd_write
+            (local.get $fd) (i32.const 8) (i32.const 1) (i32.const 16)))
+        (br_if $done (i32.eqz (i32.load (i32.const 16))))
+        (local.set $address
+          (i32.add (local.get $address) (i32.load (i32.const 16))))
+        (local.set $length
+          (i32.sub (local.get $length) (i32.load (i32.const 16))))
+        (br $more))))
+
+  (func $write_string (param $fd i32) (param $string i32)
+    (call $write
+      (local.get $fd)
+      (i32.add (local.get $string) (i32.const 16))
+      (call $String.length_value (local.get $string))))
+
+  (func $write_int (param $fd i32) (param $value i32)
+    (local $next i32)
+    (local $magnitude i32)
+    (local.set $next (i32.const 64))
+    (local.set $magnitude (local.get $value))
+    (if (i32.lt_s (local.get $value) (i32.const 0))
+      (then
+        (local.set $magnitude (i32.sub (i32.const 0) (local.get $value)))))
+    (loop $digit
+      (local.set $next (i32.sub (local.get $next) (i32.const 1)))
+      (i32.store8
+        (local.get $next)
+        (i32.add
+          (i32.const 48)
+          (i32.rem_u (local.get $magnitude) (i32.const 10))))
+      (local.set $magnitude
+        (i32.div_u (local.get $magnitude) (i32.const 10)))
+      (br_if $digit (local.get $magnitude)))
+    (if (i32.lt_s (local.get $value) (i32.const 0))
+      (then
+        (local.set $next (i32.sub (local.get $next) (i32.const 1)))
+        (i32.store8 (local.get $next) (i32.const 45))))
+    (call $write
+      (local.get $fd)
+      (local.get $next)
+      (i32.sub (i32.const 64) (local.get $next))))
+
+  ;; Report an error at a location, given the file name as a String.
+  (func $error_at
+    (param $file i32) (param $line i32) (param $message i32)
+    (param $length i32)
+    (call $write_string (i32.const 2) (local.get $file))
+    (call $write (i32.const 2) (i32.const 256) (i32.const 1))
+    (call $write_int (i32.const 2) (local.get $line))
+    (call $write (i32.const 2) (i32.const 192) (i32.const 2))
+    (call $write (i32.const 2) (local.get $message) (local.get $length))
+    (call $write (i32.const 2) (i32.const 128) (i32.const 1)))
+
+  ;; Report a dispatch on void, given the name of the method as a String and
+  ;; whether the dispatch is static.
+  (func $dispatch_abort
+    (param $file i32) (param $line i32) (param $method i32) (param $static i32)
+    (call $write_string (i32.const 2) (local.get $file))
+    (call $write (i32.const 2) (i32.const 256) (i32.const 1))
+    (call $write_int (i32.const 2) (local.get $line))
+    (call $write (i32.const 2) (i32.const 192) (i32.const 2))
+    (if (local.get $static)
+      (then (call $write (i32.const 2) (i32.const 768) (i32.const 15)))
+      (else (call $write (i32.const 2) (i32.const 320) (i32.const 8))))
+    (call $write (i32.const 2) (i32.const 832) (i32.const 24))
+    (call $write_string (i32.const 2) (local.get $method))
+    (call $write (i32.const 2) (i32.const 512) (i32.const 2))
+    (call $exit
+      (select (i32.const 6) (i32.const 5) (local.get $static))))
+
+  (func $case_abort2 (param $file i32) (param $line i32)
+    (call $error_at
+      (local.get $file) (local.get $line) (i32.const 384) (i32.const 32))
+    (call $exit (i32.const 7)))
+
+  (func $case_abort (param $object i32)
+    (call $write (i32.const 2) (i32.const 448) (i32.const 37))
+    (call $write_string
+      (i32.const 2) (call $Object.type_name (local.get $object)))
+    (call $write (i32.const 2) (i32.const 512) (i32.const 2))
+    (call $exit (i32.const 8)))
+
+  ;; Make sure memory extends to an address.
+  (func $reserve (param $end i32)
+    (if (i32.gt_u (local.get $end) (i32.shl (memory.size) (i32.const 16)))
+      (then
+        (if (i32.eq
+              (memory.grow
+                (i32.sub
+                  (i32.shr_u
+                    (i32.add (local.get $end) (i32.const 65535))
+                    (i32.const 16))
+                  (memory.size)))
+              (i32.const -1))
+          (then
+            (call $write (i32.const 2) (i32.const 704) (i32.const 15))
+            (call $exit (i32.const 19)))))))
+
+  ;; Allocate a number of bytes, a multiple of four.
+  (func $allocate (param $bytes i32) (result i32)
+    (local $address i32)
+    (local.set $address (global.get $heap))
+    (global.set $heap (i32.add (global.get $heap) (local.get $bytes)))
+    (call $reserve (global.get $heap))
+    (local.get $address))
+
+  (func $copy_bytes (param $to i32) (param $from i32) (param $length i32)
+    (local $i i32)
+    (block $done
+      (loop $next
+        (br_if $done (i32.ge_s (local.get $i) (local.get $length)))
+        (i32.store8
+          (i32.add (local.get $to) (local.get $i))
+          (i32.load8_u (i32.add (local.get $from) (local.get $i))))
+        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+        (br $next))))
+
+  (func $int (param $value i32) (result i32)
+    (local $int i32)
+    (local.set $int (call $Object.copy (global.get $Int_protObj)))
+    (i32.store offset=12 (local.get $int) (local.get $value))
+    (local.get $int))
+
+  (func $bool (param $value i32) (result i32)
+    (select
+      (global.get $bool_const1)
+      (global.get $bool_const0)
+      (local.get $value)))
+
+  ;; Make a String of a length, whose characters are filled in by the
+  ;; caller. They start 16 bytes after the address of the heap pointer.
+  (func $make_string (param $length i32) (result i32)
+    (local $string i32)
+    (local $words i32)
+    (local.set $words
+      (i32.add
+        (i32.const 4)
+        (i32.div_u
+          (i32.add (local.get $length) (i32.const 4))
+          (i32.const 4))))
+    (local.set $string
+      (call $allocate (i32.shl (local.get $words) (i32.const 2))))
+    (i32.store (local.get $string) (global.get $_string_tag))
+    (i32.store offset=4 (local.get $string) (local.get $words))
+    (i32.store offset=8 (local.get $string) (global.get $String_dispTab))
+    (i32.store offset=12 (local.get $string) (call $int (local.get $length)))
+    (i32.store8
+      (i32.add (i32.add (local.get $string) (i32.const 16)) (local.get $length))
+      (i32.const 0))
+    (local.get $string))
+
+  (func $String.length_value (param $string i32) (result i32)
+    (i32.load offset=12 (i32.load offset=12 (local.get $string))))
+
+  ;; Ints, Bools and Strings are equal when their values are. The result is
+  ;; a Bool.
+  (func $equals (param $a i32) (param $b i32) (result i32)
+    (local $tag i32)
+    (local $length i32)
+    (local $i i32)
+    (if (i32.eq (local.get $a) (local.get $b))
+      (then (return (global.get $bool_const1))))
+    (if (i32.or (i32.eqz (local.get $a)) (i32.eqz (local.get $b)))
+      (then (return (global.get $bool_const0))))
+    (local.set $tag (i32.load (local.get $a)))
+    (if (i32.ne (local.get $tag) (i32.load (local.get $b)))
+      (then (return (global.get $bool_const0))))
+    (if (i32.or
+          (i32.eq (local.get $tag) (global.get $_int_tag))
+          (i32.eq (local.get $tag) (global.get $_bool_tag)))
+      (then
+        (return
+          (call $bool
+            (i32.eq
+              (i32.load offset=12 (local.get $a))
+              (i32.load offset=12 (local.get $b)))))))
+    (if (i32.ne (local.get $tag) (global.get $_string_tag))
+      (then (return (global.get $bool_const0))))
+    (local.set $length (call $String.length_value (local.get $a)))
+    (if (i32.ne (local.get $length) (call $String.length_value (local.get $b)))
+      (then (return (global.get $bool_const0))))
+    (block $done
+      (loop $next
+        (br_if $done (i32.ge_s (local.get $i) (local.get $length)))
+        (if (i32.ne
+              (i32.load8_u offset=16 (i32.add (local.get $a) (local.get $i)))
+              (i32.load8_u offset=16 (i32.add (local.get $b) (local.get $i))))
+          (then (return (global.get $bool_const0))))
+        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+        (br $next)))
+    (global.get $bool_const1))
+
+  ;; Division truncates, and wraps around when it overflows. The result is
+  ;; the value of an Int.
+  (func $divide
+    (param $a i32) (param $b i32) (param $file i32) (param $line i32)
+    (result i32)
+    (if (i32.eqz (local.get $b))
+      (then
+        (call $error_at
+          (local.get $file) (local.get $line)
+          (i32.const 576) (i32.const 17))
+        (call $exit (i32.const 9))))
+    (if (i32.eq (local.get $b) (i32.const -1))
+      (then (return (i32.sub (i32.const 0) (local.get $a)))))
+    (i32.div_s (local.get $a) (local.get $b)))
+
+  (func $Object.abort (param $self i32) (result i32)
+    (call $write (i32.const 2) (i32.const 64) (i32.const 24))
+    (call $write_string
+      (i32.const 2) (call $Object.type_name (local.get $self)))
+    (call $write (i32.const 2) (i32.const 128) (i32.const 1))
+    (call $exit (i32.const 11))
+    (unreachable))
+
+  (func $Object.type_name (param $self i32) (result i32)
+    (i32.load
+      (i32.add
+        (global.get $class_nameTab)
+        (i32.shl (i32.load (local.get $self)) (i32.const 2)))))
+
+  (func $Object.copy (param $self i32) (result i32)
+    (local $bytes i32)
+    (local $copy i32)
+    (local $i i32)
+    (local.set $bytes
+      (i32.shl (i32.load offset=4 (local.get $self)) (i32.const 2)))
+    (local.set $copy (call $allocate (local.get $bytes)))
+    (block $done
+      (loop $next
+        (br_if $done (i32.ge_u (local.get $i) (local.get $bytes)))
+        (i32.store
+          (i32.add (local.get $copy) (local.get $i))
+          (i32.load (i32.add (local.get $self) (local.get $i))))
+        (local.set $i (i32.add (local.get $i) (i32.const 4)))
+        (br $next)))
+    (local.get $copy))
+
+  (func $IO.out_string (param $self i32) (param $x i32) (result i32)
+    (call $write_string (i32.const 1) (local.get $x))
+    (local.get $self))
+
+  (func $IO.out_int (param $self i32) (param $x i32) (result i32)
+    (call $write_int (i32.const 1) (i32.load offset=12 (local.get $x)))
+    (local.get $self))
+
+  ;; The next byte of input, or -1 at its end.
+  (func $read_byte (result i32)
+    (if (i32.eq (global.get $input_next) (global.get $input_end))
+      (then
+        (i32.store (i32.const 8) (i32.const 1024))
+        (i32.store (i32.const 12) (i32.const 1024))
+        (if (i32.or
+              (call $fd_read
+                (i32.const 0) (i32.const 8) (i32.const 1) (i32.const 16))
+              (i32.eqz (i32.load (i32.const 16))))
+          (then (return (i32.const -1))))
+        (global.set $input_next (i32.const 1024))
+        (global.set $input_end
+          (i32.add (i32.const 1024) (i32.load (i32.const 16))))))
+    (global.set $input_next (i32.add (global.get $input_next) (i32.const 1)))
+    (i32.load8_u (i32.sub (global.get $input_next) (i32.const 1))))
+
+  ;; Read a line without its line terminator, which may be CRLF. The
+  ;; characters are read to where $make_string expects them.
+  (func $read_line (result i32)
+    (local $chars i32)
+    (local $length i32)
+    (local $c i32)
+    (local.set $chars (i32.add (global.get $heap) (i32.const 16)))
+    (block $done
+      (loop $next
+        (local.set $c (call $read_byte))
+        (br_if $done
+          (i32.or
+            (i32.eq (local.get $c) (i32.const -1))
+            (i32.eq (local.get $c) (i32.const 10))))
+        (call $reserve
+          (i32.add
+            (i32.add (local.get $chars) (local.get $length))
+            (i32.const 1)))
+        (i32.store8
+          (i32.add (local.get $chars) (local.get $length))
+          (local.get $c))
+        (local.set $length (i32.add (local.get $length) (i32.const 1)))
+        (br $next)))
+    (if (i32.and
+          (i32.eq (local.get $c) (i32.const 10))
+          (i32.gt_s (local.get $length) (i32.const 0)))
+      (then
+        (if (i32.eq
+              (i32.load8_u
+                (i32.sub
+                  (i32.add (local.get $chars) (local.get $length))
+                  (i32.const 1)))
+              (i32.const 13))
+          (then
+            (local.set $length
+              (i32.sub (local.get $length) (i32.const 1)))))))
+    (call $make_string (local.get $length)))
+
+  (func $IO.in_string (param $self i32) (result i32)
+    (call $read_line))
+
+  ;; Leading whitespace is skipped, then an optional sign and digits are
+  ;; read, wrapping on overflow. Anything else yields 0.
+  (func $IO.in_int (param $self i32) (result i32)
+    (local $next i32)
+    (local $value i32)
+    (local $negative i32)
+    (local $digit i32)
+    (local.set $next (i32.add (call $read_line) (i32.const 16)))
+    (loop $space
+      (if (i32.or
+            (i32.eq (i32.load8_u (local.get $next)) (i32.const 32))
+            (i32.le_u
+              (i32.sub (i32.load8_u (local.get $next)) (i32.const 9))
+              (i32.const 4)))
+        (then
+          (local.set $next (i32.add (local.get $next) (i32.const 1)))
+          (br $space))))
+    (if (i32.or
+          (i32.eq (i32.load8_u (local.get $next)) (i32.const 45))
+          (i32.eq (i32.load8_u (local.get $next)) (i32.const 43)))
+      (then
+        (local.set $negative
+          (i32.eq (i32.load8_u (local.get $next)) (i32.const 45)))
+        (local.set $next (i32.add (local.get $next) (i32.const 1)))))
+    (loop $digits
+      (local.set $digit
+        (i32.sub (i32.load8_u (local.get $next)) (i32.const 48)))
+      (if (i32.le_u (local.get $digit) (i32.const 9))
+        (then
+          (local.set $value
+            (i32.add
+              (i32.mul (local.get $value) (i32.const 10))
+              (local.get $digit)))
+          (local.set $next (i32.add (local.get $next) (i32.const 1)))
+          (br $digits))))
+    (call $int
+      (select
+        (i32.sub (i32.const 0) (local.get $value))
+        (local.get $value)
+        (local.get $negative))))
+
+  (func $String.length (param $self i32) (result i32)
+    (i32.load offset=12 (local.get $self)))
+
+  (func $String.concat (param $self i32) (param $s i32) (result i32)
+    (local $length i32)
+    (local $result i32)
+    (local.set $length (call $String.length_value (local.get $self)))
+    (local.set $result
+      (call $make_string
+        (i32.add
+          (local.get $length)
+          (call $String.length_value (local.get $s)))))
+    (call $copy_bytes
+      (i32.add (local.get $result) (i32.const 16))
+      (i32.add (local.get $self) (i32.const 16))
+      (local.get $length))
+    (call $copy_bytes
+      (i32.add
+        (i32.add (local.get $result) (i32.const 16))
+        (local.get $length))
+      (i32.add (local.get $s) (i32.const 16))
+      (call $String.length_value (local.get $s)))
+    (local.get $result))
+
+  (func $String.substr
+    (param $self i32) (param $i i32) (param $l i32) (result i32)
+    (local $start i32)
+    (local $length i32)
+    (local $result i32)
+    (local.set $start (i32.load offset=12 (local.get $i)))
+    (local.set $length (i32.load offset=12 (local.get $l)))
+    (if (i32.or
+          (i32.or
+            (i32.lt_s (local.get $start) (i32.const 0))
+            (i32.lt_s (local.get $length) (i32.const 0)))
+          (i32.gt_s
+            (local.get $start)
+            (i32.sub
+              (call $String.length_value (local.get $self))
+              (local.get $length))))
+      (then
+        (call $write (i32.const 2) (i32.const 640) (i32.const 30))
+        (call $exit (i32.const 10))))
+    (local.set $result (call $make_string (local.get $length)))
+    (call $copy_bytes
+      (i32.add (local.get $result) (i32.const 16))
+      (i32.add
+        (i32.add (local.get $self) (i32.const 16))
+        (local.get $start))
+      (local.get $length))
+    (local.get $result))
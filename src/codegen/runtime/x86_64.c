@@ -0,0 +1,252 @@
+/*
+ * The runtime library for Cool programs compiled to x86-64 assembly.
+ *
+ * It provides the entry point, the methods of the basic classes, object
+ * copying, equality of Ints, Bools and Strings, and the routines reporting
+ * runtime errors. Objects are allocated with malloc and never freed.
+ * Errors are written to standard error and exit with the status the
+ * interpreter uses for them.
+ */
+
+#define _POSIX_C_SOURCE 200809L
+
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+typedef struct Object {
+    int64_t tag;
+    /* The size of the object in eight byte words. */
+    int64_t size;
+    void **dispatch;
+} Object;
+
+typedef struct Int {
+    Object header;
+    int32_t value;
+    int32_t padding;
+} Int;
+
+typedef struct String {
+    Object header;
+    Int *length;
+    char chars[];
+} String;
+
+/* Defined by the generated code. */
+extern int64_t _int_tag, _bool_tag, _string_tag;
+extern String *class_nameTab[];
+extern Object Main_protObj;
+extern Int Int_protObj;
+extern String String_protObj;
+extern Int bool_const0, bool_const1;
+extern Object *Main_init(Object *self);
+extern Object *Main_main(Object *self) __asm__("Main.main");
+
+static void *allocate(size_t bytes)
+{
+    void *memory = malloc(bytes);
+    if (memory == NULL) {
+        fflush(stdout);
+        fputs("Out of memory.\n", stderr);
+        exit(19);
+    }
+    return memory;
+}
+
+static Int *new_int(int32_t value)
+{
+    Int *i = allocate(sizeof(Int));
+    *i = Int_protObj;
+    i->value = value;
+    return i;
+}
+
+static String *new_string(const char *chars, size_t length)
+{
+    int64_t words = 4 + (length + 8) / 8;
+    String *s = allocate(words * 8);
+    s->header = String_protObj.header;
+    s->header.size = words;
+    s->length = new_int((int32_t)length);
+    memcpy(s->chars, chars, length);
+    s->chars[length] = '\0';
+    return s;
+}
+
+static void report(const char *message)
+{
+    fflush(stdout);
+    fputs(message, stderr);
+}
+
+Object *Object_copy(Object *self)
+{
+    size_t bytes = self->size * 8;
+    Object *copy = allocate(bytes);
+    memcpy(copy, self, bytes);
+    return copy;
+}
+
+Object *Object_abort(Object *self)
+{
+    fflush(stdout);
+    fprintf(stderr, "Abort called from class %s\n",
+            class_nameTab[self->tag]->chars);
+    exit(11);
+}
+
+String *Object_type_name(Object *self)
+{
+    return class_nameTab[self->tag];
+}
+
+Object *IO_out_string(Object *self, String *s)
+{
+    fwrite(s->chars, 1, s->length->value, stdout);
+    return self;
+}
+
+Object *IO_out_int(Object *self, Int *i)
+{
+    printf("%d", i->value);
+    return self;
+}
+
+/* Read a line without its line terminator, which may be CRLF. */
+static char *read_line(size_t *length)
+{
+    char *line = NULL;
+    size_t capacity = 0;
+    ssize_t read;
+
+    fflush(stdout);
+    read = getline(&line, &capacity, stdin);
+    *length = read < 0 ? 0 : (size_t)read;
+    if (*length > 0 && line[*length - 1] == '\n') {
+        (*length)--;
+        if (*length > 0 && line[*length - 1] == '\r')
+            (*length)--;
+    }
+    return line;
+}
+
+String *IO_in_string(Object *self)
+{
+    size_t length;
+    char *line = read_line(&length);
+    String *s = new_string(line, length);
+
+    (void)self;
+    free(line);
+    return s;
+}
+
+/*
+ * Leading whitespace is skipped, then an optional sign and digits are read,
+ * wrapping on overflow. Anything else yields 0.
+ */
+Int *IO_in_int(Object *self)
+{
+    size_t length;
+    char *line = read_line(&length);
+    const char *c = line ? line : "";
+    uint32_t value = 0;
+    int negative = 0;
+
+    (void)self;
+    while (*c == ' ' || (*c >= '\t' && *c <= '\r'))
+        c++;
+    if (*c == '-' || *c == '+')
+        negative = *c++ == '-';
+    while (*c >= '0' && *c <= '9')
+        value = value * 10 + (uint32_t)(*c++ - '0');
+    free(line);
+    return new_int((int32_t)(negative ? -value : value));
+}
+
+Int *String_length(String *self)
+{
+    return self->length;
+}
+
+String *String_concat(String *self, String *other)
+{
+    size_t length = self->length->value;
+    size_t other_length = other->length->value;
+    String *s = new_string(self->chars, length + other_length);
+
+    memcpy(s->chars + length, other->chars, other_length);
+    return s;
+}
+
+String *String_substr(String *self, Int *start, Int *length)
+{
+    int64_t first = start->value, count = length->value;
+
+    if (first < 0 || count < 0 || first + count > self->length->value) {
+        report("Index out of range in substr.\n");
+        exit(10);
+    }
+    return new_string(self->chars + first, count);
+}
+
+/* Compare two distinct objects, at least one of them not void. */
+Int *equality_test(Object *a, Object *b)
+{
+    int equal = 0;
+
+    if (a != NULL && b != NULL && a->tag == b->tag) {
+        if (a->tag == _int_tag || a->tag == _bool_tag) {
+            equal = ((Int *)a)->value == ((Int *)b)->value;
+        } else if (a->tag == _string_tag) {
+            String *s = (String *)a, *t = (String *)b;
+            equal = s->length->value == t->length->value
+                    && memcmp(s->chars, t->chars, s->length->value) == 0;
+        }
+    }
+    return equal ? &bool_const1 : &bool_const0;
+}
+
+static void report_at(String *filename, int line, const char *message)
+{
+    fflush(stdout);
+    fprintf(stderr, "%s:%d: %s\n", filename->chars, line, message);
+}
+
+/* Report a dispatch on void, which is static when is_static is set. */
+void _dispatch_abort(String *filename, int line, String *method, int is_static)
+{
+    fflush(stdout);
+    fprintf(stderr, "%s:%d: %s to void calling method %s.\n",
+            filename->chars, line, is_static ? "Static dispatch" : "Dispatch",
+            method->chars);
+    exit(is_static ? 6 : 5);
+}
+
+void _case_abort2(String *filename, int line)
+{
+    report_at(filename, line, "Match on void in case statement.");
+    exit(7);
+}
+
+void _case_abort(Object *object)
+{
+    fflush(stdout);
+    fprintf(stderr, "No match in case statement for Class %s.\n",
+            class_nameTab[object->tag]->chars);
+    exit(8);
+}
+
+void _divide_abort(String *filename, int line)
+{
+    report_at(filename, line, "Division by zero.");
+    exit(9);
+}
+
+int main(void)
+{
+    Main_main(Main_init(Object_copy(&Main_protObj)));
+    return 0;
+}
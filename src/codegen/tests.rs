@@ -0,0 +1,374 @@
+use super::*;
+use crate::lexer::lex_tokens;
+use crate::parser::parse_program;
+use crate::semant::check_program;
+
+const SOURCE: &str = "\
+class A { a : Int; f() : Int { a }; g() : Int { 1 }; };
+class B inherits A { b : String; g() : Int { 2 }; };
+class C inherits A { c : Bool; };
+class D inherits B { d : A; };
+class Main inherits IO {
+  main() : Object {
+    case new D of
+      a : A => out_int(a.g());
+      b : B => out_string(\"B\\n\");
+    esac
+  };
+};
+";
+
+fn layout(source: &str) -> Layout {
+    let (_, tokens) = lex_tokens(source, "test.cl").unwrap();
+    let (_, mut program) = parse_program(&tokens).unwrap();
+    let hierarchy = check_program(&mut program).unwrap();
+    Layout::new(&hierarchy)
+}
+
+fn mips(source: &str) -> String {
+    let (_, tokens) = lex_tokens(source, "test.cl").unwrap();
+    let (_, mut program) = parse_program(&tokens).unwrap();
+    let hierarchy = check_program(&mut program).unwrap();
+    emit_mips(&program, &hierarchy, Collector::None)
+}
+
+fn x86_64(source: &str, representation: Representation) -> String {
+    let (_, tokens) = lex_tokens(source, "test.cl").unwrap();
+    let (_, mut program) = parse_program(&tokens).unwrap();
+    let hierarchy = check_program(&mut program).unwrap();
+    emit_x86_64(&program, &hierarchy, representation)
+}
+
+fn c(source: &str) -> String {
+    let (_, tokens) = lex_tokens(source, "test.cl").unwrap();
+    let (_, mut program) = parse_program(&tokens).unwrap();
+    let hierarchy = check_program(&mut program).unwrap();
+    emit_c(&program, &hierarchy)
+}
+
+fn llvm(source: &str) -> String {
+    let (_, tokens) = lex_tokens(source, "test.cl").unwrap();
+    let (_, mut program) = parse_program(&tokens).unwrap();
+    let hierarchy = check_program(&mut program).unwrap();
+    emit_llvm(&program, &hierarchy)
+}
+
+// The lines of a labelled block of assembly, up to the next label.
+fn block<'s>(assembly: &'s str, label: &str) -> Vec<&'s str> {
+    assembly
+        .lines()
+        .skip_while(|line| *line != format!("{label}:"))
+        .skip(1)
+        .take_while(|line| line.starts_with('\t'))
+        .map(str::trim)
+        .collect()
+}
+
+#[test]
+fn test_tags_are_depth_first() {
+    let layout = layout(SOURCE);
+    let tags: Vec<(&str, usize, usize)> = layout
+        .classes
+        .iter()
+        .map(|class| (class.name.as_str(), class.tag, class.last_descendant))
+        .collect();
+    assert_eq!(
+        tags,
+        [
+            ("Object", 0, 9),
+            ("IO", 1, 2),
+            ("Main", 2, 2),
+            ("Int", 3, 3),
+            ("String", 4, 4),
+            ("Bool", 5, 5),
+            ("A", 6, 9),
+            ("B", 7, 8),
+            ("D", 8, 8),
+            ("C", 9, 9),
+        ]
+    );
+}
+
+#[test]
+fn test_attributes_and_slots_are_inherited() {
+    let layout = layout(SOURCE);
+    let d = layout.class("D");
+    let attributes: Vec<&str> =
+        d.attributes.iter().map(|(name, _)| name.as_str()).collect();
+    assert_eq!(attributes, ["a", "b", "d"]);
+    assert_eq!(d.size(), 6);
+    let methods: Vec<String> = d
+        .methods
+        .iter()
+        .map(|(name, owner)| format!("{owner}.{name}"))
+        .collect();
+    assert_eq!(
+        methods,
+        [
+            "Object.abort",
+            "Object.type_name",
+            "Object.copy",
+            "A.f",
+            "B.g"
+        ]
+    );
+    assert_eq!(layout.class("A").slot("g"), d.slot("g"));
+    assert_eq!(layout.class(STRING).size(), 5);
+}
+
+#[test]
+fn test_constants_are_interned() {
+    let mut constants = Constants::default();
+    assert_eq!(constants.string("ab"), 0);
+    assert_eq!(constants.int(7), 1);
+    assert_eq!(constants.string("c"), 1);
+    assert_eq!(constants.string("ab"), 0);
+    assert_eq!(constants.strings(), ["ab", "c"]);
+    assert_eq!(constants.ints(), [2, 7, 1]);
+}
+
+#[test]
+fn test_tables_and_prototypes() {
+    let assembly = mips(SOURCE);
+    let prototype = block(&assembly, "D_protObj");
+    assert_eq!(prototype[..3], [".word\t8", ".word\t6", ".word\tD_dispTab"]);
+    // Attributes start with the default values of their types.
+    let constant = |line: &str| block(&assembly, &line[6..]);
+    assert_eq!(constant(prototype[3])[3], ".word\t0");
+    assert_eq!(constant(prototype[4])[4], ".byte\t0");
+    assert_eq!(prototype[5], ".word\t0");
+    assert_eq!(
+        block(&assembly, "B_dispTab"),
+        [
+            ".word\tObject.abort",
+            ".word\tObject.type_name",
+            ".word\tObject.copy",
+            ".word\tA.f",
+            ".word\tB.g",
+        ]
+    );
+    assert_eq!(block(&assembly, "class_objTab").len(), 20);
+    assert_eq!(block(&assembly, "_int_tag"), [".word\t3"]);
+    assert!(assembly.contains("\t.word\t_NoGC_Init\n"));
+}
+
+#[test]
+fn test_case_tries_specific_classes_first() {
+    let assembly = mips(SOURCE);
+    let ranges: Vec<&str> = assembly
+        .lines()
+        .skip_while(|line| *line != "Main.main:")
+        .map(str::trim)
+        .filter(|line| line.starts_with("blt\t$t2") || line.starts_with("bgt"))
+        .map(|line| line.rsplit_once(' ').unwrap().0)
+        .collect();
+    assert_eq!(
+        ranges,
+        ["blt\t$t2 7", "bgt\t$t2 8", "blt\t$t2 6", "bgt\t$t2 9"]
+    );
+}
+
+#[test]
+fn test_x86_64_calls() {
+    let assembly = x86_64(
+        "class Main {
+           f(a : Int, b : Int, c : Int, d : Int, e : Int, f : Int, g : Int,
+             h : Int) : Int { h };
+           main() : Int { f(1, 2, 3, 4, 5, 6, 7, 8) };
+         };
+         class N inherits Main {
+           f(a : Int, b : Int, c : Int, d : Int, e : Int, f : Int, g : Int,
+             h : Int) : Int { a };
+         };",
+        Representation::Unboxed,
+    );
+    // The last argument is above the return address and the saved %rbp.
+    let f = block(&assembly, "Main.f");
+    assert_eq!(f[3], "subq\t$40, %rsp");
+    assert_eq!(f[f.len() - 4], "movq\t32(%rbp), %rax");
+    // Arguments past the fifth are pushed last to first, keeping the stack
+    // aligned.
+    let main: Vec<&str> = assembly
+        .lines()
+        .skip_while(|line| *line != "Main.main:")
+        .map(str::trim)
+        .collect();
+    let call = main.iter().position(|line| *line == "subq\t$8, %rsp");
+    assert_eq!(
+        main[call.unwrap()..][..5],
+        [
+            "subq\t$8, %rsp",
+            "pushq\t-72(%rbp)",
+            "pushq\t-64(%rbp)",
+            "pushq\t-56(%rbp)",
+            "movq\t-16(%rbp), %rsi",
+        ]
+    );
+    assert!(main.contains(&"call\t*24(%rax)"));
+    assert!(main.contains(&"addq\t$32, %rsp"));
+    assert_eq!(
+        block(&assembly, "Main_dispTab"),
+        [
+            ".quad\tObject_abort",
+            ".quad\tObject_type_name",
+            ".quad\tObject_copy",
+            ".quad\tMain.f",
+            ".quad\tMain.main",
+        ]
+    );
+}
+
+#[test]
+fn test_x86_64_unboxed() {
+    let source = "\
+class Main {
+  main() : Int {
+    let x : Int <- 1, b : Bool <- x < 2 in if b then x + 2 * x else ~x fi
+  };
+};
+";
+    // Unboxed code only boxes the result of main, while boxed code boxes
+    // the result of each operation.
+    let main = |representation| -> Vec<String> {
+        x86_64(source, representation)
+            .lines()
+            .skip_while(|line| *line != "Main.main:")
+            .take_while(|line| *line != "\tret")
+            .map(|line| line.trim().to_string())
+            .collect()
+    };
+    let copies = |code: &[String]| {
+        code.iter().filter(|l| *l == "call\tObject_copy").count()
+    };
+    assert_eq!(copies(&main(Representation::Boxed)), 3);
+    let main = main(Representation::Unboxed);
+    assert_eq!(copies(&main), 1);
+    for instruction in
+        ["movl\t%eax, -16(%rbp)", "setl\t%al", "imull\t%ecx, %eax"]
+    {
+        assert!(main.iter().any(|l| l == instruction), "{instruction}");
+    }
+}
+
+#[test]
+fn test_c_names_and_declaration_order() {
+    let c = c("\
+class EOF inherits A { static : Int; f() : Int { static }; };
+class A { int : Bool; };
+class Main { main() : Object { new EOF.f() }; };
+");
+    assert!(c.contains("struct cool_EOF {"));
+    assert!(c.contains("    Bool *_int;\n    Int *_static;\n"));
+    assert!(c.contains("return self->_static;"));
+    // A descriptor refers to the descriptor of the parent.
+    let parent = c.find("static const cool_class A_Class").unwrap();
+    let child = c.find("static const cool_class cool_EOF_Class").unwrap();
+    assert!(parent < child);
+    assert!(c.contains("\"EOF\", &A_Class, sizeof(cool_EOF), cool_EOF_New"));
+}
+
+#[test]
+fn test_c_dispatch_on_void() {
+    let c = c("\
+class A { f() : Int { 0 }; };
+class Main { a : A; main() : Object { { a.f(); a@A.f(); } }; };
+");
+    assert!(c.contains("cool_dispatch_abort(\"test.cl\", 2, \"f\", 0);"));
+    assert!(c.contains("cool_dispatch_abort(\"test.cl\", 2, \"f\", 1);"));
+}
+
+#[test]
+fn test_llvm_names_and_dispatch() {
+    let llvm = llvm(
+        "\
+class A { init(entry : Int, t1 : Int) : Int { entry + t1 }; };
+class B inherits A { init(entry : Int, t1 : Int) : Int { entry }; };
+class Main { main() : Object { new A.init(1, 2) }; };
+",
+    );
+    // Methods cannot clash with the constructor, the parameters with the
+    // entry block or the temporaries.
+    assert!(llvm.contains("define void @A.new.init(ptr %self)"));
+    assert!(
+        llvm.contains("define ptr @A.init(ptr %self, ptr %_entry, ptr %t1)")
+    );
+    assert!(llvm.contains("store ptr %_entry, ptr %entry.addr"));
+    assert!(llvm.contains("%A.vtable = type { ptr, ptr, ptr, ptr, ptr }"));
+    assert!(llvm
+        .contains("getelementptr inbounds %A.vtable, ptr %t.4, i32 0, i32 4"));
+}
+
+#[test]
+fn test_llvm_dispatch_on_void() {
+    let llvm = llvm(
+        "\
+class A { f() : Int { 0 }; };
+class Main { a : A; main() : Object { a@A.f() }; };
+",
+    );
+    assert!(llvm.contains(
+        "call void @cool_dispatch_abort(ptr @.file.0, i32 2, \
+         ptr @str.0.chars, i1 true)"
+    ));
+}
+
+fn wasm(source: &str) -> String {
+    let (_, tokens) = lex_tokens(source, "test.cl").unwrap();
+    let (_, mut program) = parse_program(&tokens).unwrap();
+    let hierarchy = check_program(&mut program).unwrap();
+    emit_wasm(&program, &hierarchy)
+}
+
+#[test]
+fn test_wasm_dispatch() {
+    let wasm = wasm(
+        "\
+class A { init(t : Int) : Int { let t : Int <- t in t }; };
+class B inherits A { init(t : Int) : Int { t }; };
+class Main { main() : Object { (new A)@A.init(new A.init(1)) }; };
+",
+    );
+    // Methods cannot clash with initialisers, or variables with the
+    // parameters.
+    assert!(wasm.contains(
+        "(func $A.init (type $method1) (param $self i32) (param $t i32)"
+    ));
+    assert!(wasm.contains("(local $t.1 i32)"));
+    assert!(wasm.contains("(func $A_init (type $method0)"));
+    // Dynamic dispatch goes through the slot of the method, after the three
+    // methods of Object, and static dispatch calls the method.
+    let main: Vec<&str> = wasm
+        .lines()
+        .skip_while(|line| !line.contains("(func $Main.main"))
+        .map(str::trim)
+        .collect();
+    let dynamic = main.iter().position(|l| *l == "i32.load offset=12");
+    assert_eq!(main[dynamic.unwrap() + 1], "call_indirect (type $method1)");
+    assert!(main.contains(&"call $A.init"));
+    assert!(!assemble_wasm(&wasm).is_empty());
+}
+
+#[test]
+fn test_direct_calls() {
+    // Main and IO methods are not overridden, so their dispatches call them
+    // directly, while A.g is overridden in B.
+    let direct = [
+        (
+            x86_64(SOURCE, Representation::Unboxed),
+            "call\tIO_out_int",
+            "call\t*32(%rax)",
+        ),
+        (mips(SOURCE), "jal\tIO.out_int", "lw\t$t1 16($t1)"),
+        (c(SOURCE), "IO_out_int(", "->vtable->g("),
+        (llvm(SOURCE), "call ptr @IO.out_int(", "%B.vtable"),
+        (
+            wasm(SOURCE),
+            "call $IO.out_int",
+            "call_indirect (type $method0)",
+        ),
+    ];
+    for (code, direct, dynamic) in direct {
+        assert!(code.contains(direct), "{direct}");
+        assert!(code.contains(dynamic), "{dynamic}");
+    }
+}
@@ -0,0 +1,726 @@
+//! WebAssembly modules in the text format, with a runtime written in it.
+//!
+//! Objects live in linear memory with the layout of the course runtime, and
+//! the constants, prototype objects and tables of a program are placed in
+//! data segments after the memory the runtime reserves. Dispatch tables
+//! hold indices into the function table, and methods are called with
+//! `call_indirect`. Every value is an `i32` address, and methods take the
+//! receiver as their first parameter. Variables bound by let and case are
+//! locals of the method.
+//!
+//! The module exports its memory and `_start`, which creates a Main object
+//! and calls `main`, and imports `fd_write`, `fd_read` and `proc_exit` from
+//! WASI preview 1.
+
+use super::*;
+use crate::ptree::*;
+use crate::semant::SymbolTable;
+use crate::tokens::Span;
+use std::fmt::Write;
+use ExpressionData::*;
+
+/// The runtime, spliced into every module.
+pub const WASM_RUNTIME: &str = include_str!("runtime/wasm.wat");
+
+// The first address after the memory reserved by the runtime.
+const DATA_START: usize = 2048;
+
+// The size of a page of memory.
+const PAGE_SIZE: usize = 65536;
+
+/// Generate a WebAssembly module in the text format for a program that has
+/// passed semantic analysis.
+pub fn emit_wasm(program: &Program, hierarchy: &ClassHierarchy) -> String {
+    let mut generator = Generator {
+        layout: Layout::new(hierarchy),
+        constants: Constants::default(),
+        code: String::new(),
+        labels: 0,
+        class: String::new(),
+        scopes: SymbolTable::new(),
+        locals: Vec::new(),
+        depth: 0,
+        max_arity: 2,
+    };
+    for class in generator.layout.classes.iter() {
+        generator.constants.string(&class.name);
+    }
+    generator.constants.string("");
+    generator.constants.int(0);
+    generator.initialisers(program);
+    for class in program.classes.iter() {
+        generator.class = class.name.clone();
+        for feature in class.features.iter() {
+            if let FeatureData::Method(name, _, formals, body) = &feature.data {
+                generator.method(&class.name, name, formals, body);
+            }
+        }
+    }
+    generator.finish()
+}
+
+/// Translate a module from the text format to the binary format.
+pub fn assemble_wasm(module: &str) -> Vec<u8> {
+    wat::parse_str(module).expect("valid module")
+}
+
+// Where a variable is stored.
+enum Variable {
+    SelfObject,
+    Local(String),
+    Attribute(usize),
+}
+
+struct Generator {
+    layout: Layout,
+    constants: Constants,
+    code: String,
+    labels: usize,
+    // The class and the variables of the method being generated.
+    class: String,
+    scopes: SymbolTable<String, String>,
+    locals: Vec<String>,
+    // The nesting of the instruction being generated.
+    depth: usize,
+    // The most parameters of a method, which is at least those of
+    // String.substr.
+    max_arity: usize,
+}
+
+impl Generator {
+    fn label(&mut self) -> usize {
+        self.labels += 1;
+        self.labels - 1
+    }
+
+    fn emit(&mut self, instruction: &str) {
+        if instruction == "end" || instruction == "else" {
+            self.depth -= 1;
+        }
+        writeln!(self.code, "{}{instruction}", "  ".repeat(self.depth))
+            .unwrap();
+        if instruction.starts_with("if")
+            || instruction.starts_with("block")
+            || instruction.starts_with("loop")
+            || instruction == "else"
+        {
+            self.depth += 1;
+        }
+    }
+
+    // A new local of the function being generated, named after a variable.
+    fn local(&mut self, name: &str) -> String {
+        let local = format!("${name}.{}", self.locals.len() + 1);
+        self.locals.push(local.clone());
+        local
+    }
+
+    fn string_global(&mut self, string: &str) -> String {
+        format!("$str_const{}", self.constants.string(string))
+    }
+
+    fn int_global(&mut self, int: i32) -> String {
+        format!("$int_const{}", self.constants.int(int))
+    }
+
+    // Generate a function taking self and parameters around the code
+    // emitted by `body`, which leaves its result on the stack.
+    fn function(
+        &mut self,
+        name: &str,
+        parameters: &[&str],
+        body: impl FnOnce(&mut Self),
+    ) {
+        let outer = std::mem::take(&mut self.code);
+        self.max_arity = self.max_arity.max(parameters.len());
+        self.locals.clear();
+        self.depth = 2;
+        body(self);
+        let body = std::mem::replace(&mut self.code, outer);
+
+        write!(
+            self.code,
+            "  (func ${name} (type $method{}) (param $self i32)",
+            parameters.len()
+        )
+        .unwrap();
+        for parameter in parameters {
+            write!(self.code, " (param ${parameter} i32)").unwrap();
+        }
+        writeln!(self.code, " (result i32)").unwrap();
+        for local in self.locals.iter() {
+            writeln!(self.code, "    (local {local} i32)").unwrap();
+        }
+        self.code.push_str(&body);
+        writeln!(self.code, "  )").unwrap();
+    }
+
+    fn initialisers(&mut self, program: &Program) {
+        for initialiser in self.layout.initialisers(program) {
+            self.class = initialiser.class.clone();
+            let label = format!("{}_init", initialiser.class);
+            self.function(&label, &[], |g| {
+                if let Some(parent) = &initialiser.parent {
+                    g.emit("local.get $self");
+                    g.emit(&format!("call ${parent}_init"));
+                    g.emit("drop");
+                }
+                for (attr, init) in initialiser.attributes.iter() {
+                    g.emit("local.get $self");
+                    g.expression(init);
+                    g.store_attribute(attr);
+                }
+                g.emit("local.get $self");
+            });
+        }
+    }
+
+    fn method(
+        &mut self,
+        class: &str,
+        name: &str,
+        formals: &[Formal],
+        body: &Expression,
+    ) {
+        self.scopes.enter_scope();
+        for formal in formals.iter() {
+            self.scopes
+                .insert(formal.name.clone(), format!("${}", formal.name));
+        }
+        let parameters: Vec<&str> =
+            formals.iter().map(|formal| formal.name.as_str()).collect();
+        self.function(&format!("{class}.{name}"), &parameters, |g| {
+            g.expression(body)
+        });
+        self.scopes.exit_scope();
+    }
+
+    fn variable(&self, name: &str) -> Variable {
+        if name == SELF {
+            Variable::SelfObject
+        } else if let Some(local) = self.scopes.lookup(name) {
+            Variable::Local(local.clone())
+        } else {
+            let index = self
+                .layout
+                .class(&self.class)
+                .attribute_index(name)
+                .expect("declared attribute");
+            Variable::Attribute(index)
+        }
+    }
+
+    // Store the value on the stack in an attribute of the object below it.
+    fn store_attribute(&mut self, name: &str) {
+        match self.variable(name) {
+            Variable::Attribute(index) => {
+                let offset = attribute_offset(index, WORD_SIZE);
+                self.emit(&format!("i32.store offset={offset}"));
+            }
+            _ => unreachable!("not an attribute"),
+        }
+    }
+
+    fn default_value(&mut self, type_id: &str) {
+        let global = match type_id {
+            INT => self.int_global(0),
+            STRING => self.string_global(""),
+            BOOL => "$bool_const0".to_string(),
+            _ => {
+                self.emit("i32.const 0");
+                return;
+            }
+        };
+        self.emit(&format!("global.get {global}"));
+    }
+
+    // Push the file name and the line of a location, the first arguments of
+    // the runtime functions reporting errors.
+    fn location(&mut self, location: Span) {
+        let filename = self.string_global(location.extra);
+        self.emit(&format!("global.get {filename}"));
+        self.emit(&format!("i32.const {}", location.location_line()));
+    }
+
+    // Call a runtime function reporting an error at a location, which takes
+    // the file name and the line.
+    fn abort_at(&mut self, function: &str, location: Span) {
+        self.location(location);
+        self.emit(&format!("call ${function}"));
+    }
+
+    // Generate code for an expression, leaving its value on the stack.
+    fn expression(&mut self, expr: &Expression) {
+        match &expr.data {
+            Block(expressions) => {
+                for (index, expression) in expressions.iter().enumerate() {
+                    if index > 0 {
+                        self.emit("drop");
+                    }
+                    self.expression(expression);
+                }
+            }
+            Conditional(if_expr, then_expr, else_expr) => {
+                self.expression(if_expr);
+                self.emit("i32.load offset=12");
+                self.emit("if (result i32)");
+                self.expression(then_expr);
+                self.emit("else");
+                self.expression(else_expr);
+                self.emit("end");
+            }
+            Loop(cond_expr, loop_expr) => {
+                let label = self.label();
+                self.emit(&format!("block $done{label}"));
+                self.emit(&format!("loop $loop{label}"));
+                self.expression(cond_expr);
+                self.emit("i32.load offset=12");
+                self.emit("i32.eqz");
+                self.emit(&format!("br_if $done{label}"));
+                self.expression(loop_expr);
+                self.emit("drop");
+                self.emit(&format!("br $loop{label}"));
+                self.emit("end");
+                self.emit("end");
+                self.emit("i32.const 0");
+            }
+            Case(case_expr, branches) => self.case(expr, case_expr, branches),
+            Let(ident, type_id, opt_bind, body) => {
+                match &**opt_bind {
+                    Some(bind) => self.expression(bind),
+                    None => self.default_value(type_id),
+                }
+                let local = self.local(ident);
+                self.emit(&format!("local.set {local}"));
+                self.scopes.enter_scope();
+                self.scopes.insert(ident.clone(), local);
+                self.expression(body);
+                self.scopes.exit_scope();
+            }
+            New(type_id) if type_id == SELF_TYPE => {
+                // class_objTab holds the prototype object and the table
+                // index of the initialiser of each class, by tag.
+                let entry = self.local("t");
+                self.emit("global.get $class_objTab");
+                self.emit("local.get $self");
+                self.emit("i32.load");
+                self.emit("i32.const 3");
+                self.emit("i32.shl");
+                self.emit("i32.add");
+                self.emit(&format!("local.tee {entry}"));
+                self.emit("i32.load");
+                self.emit("call $Object.copy");
+                self.emit(&format!("local.get {entry}"));
+                self.emit("i32.load offset=4");
+                self.emit("call_indirect (type $method0)");
+            }
+            New(type_id) => {
+                self.emit(&format!("global.get ${type_id}_protObj"));
+                self.emit("call $Object.copy");
+                self.emit(&format!("call ${type_id}_init"));
+            }
+            Assign(ident, value) => match self.variable(ident) {
+                Variable::Local(local) => {
+                    self.expression(value);
+                    self.emit(&format!("local.tee {local}"));
+                }
+                Variable::Attribute(_) => {
+                    let result = self.local("t");
+                    self.emit("local.get $self");
+                    self.expression(value);
+                    self.emit(&format!("local.tee {result}"));
+                    self.store_attribute(ident);
+                    self.emit(&format!("local.get {result}"));
+                }
+                Variable::SelfObject => unreachable!("assignment to self"),
+            },
+            UnaryOperation(UnaryOperator::Negative, operand) => {
+                self.emit("i32.const 0");
+                self.expression(operand);
+                self.emit("i32.load offset=12");
+                self.emit("i32.sub");
+                self.emit("call $int");
+            }
+            UnaryOperation(UnaryOperator::Not, operand) => {
+                self.expression(operand);
+                self.emit("i32.load offset=12");
+                self.emit("i32.eqz");
+                self.emit("call $bool");
+            }
+            UnaryOperation(UnaryOperator::IsVoid, operand) => {
+                self.expression(operand);
+                self.emit("i32.eqz");
+                self.emit("call $bool");
+            }
+            BinaryOperation(BinaryOperator::Equals, operand1, operand2) => {
+                self.expression(operand1);
+                self.expression(operand2);
+                self.emit("call $equals");
+            }
+            BinaryOperation(operator, operand1, operand2) => {
+                self.expression(operand1);
+                self.emit("i32.load offset=12");
+                self.expression(operand2);
+                self.emit("i32.load offset=12");
+                match operator {
+                    BinaryOperator::Add => self.emit("i32.add"),
+                    BinaryOperator::Subtract => self.emit("i32.sub"),
+                    BinaryOperator::Multiply => self.emit("i32.mul"),
+                    BinaryOperator::Divide => {
+                        self.abort_at("divide", expr.location)
+                    }
+                    BinaryOperator::LessThan => {
+                        self.emit("i32.lt_s");
+                        self.emit("call $bool");
+                        return;
+                    }
+                    BinaryOperator::LessThanOrEquals => {
+                        self.emit("i32.le_s");
+                        self.emit("call $bool");
+                        return;
+                    }
+                    BinaryOperator::Equals => unreachable!(),
+                }
+                self.emit("call $int");
+            }
+            MethodCall(callee, static_type, ident, params) => {
+                // Arguments are evaluated before the receiver, so they are
+                // kept in locals until it is known.
+                let mut arguments = Vec::new();
+                for param in params.iter() {
+                    self.expression(param);
+                    let argument = self.local("t");
+                    self.emit(&format!("local.set {argument}"));
+                    arguments.push(argument);
+                }
+                self.expression(callee);
+                let receiver = self.local("t");
+                let class = dispatch_class(static_type, callee, &self.class);
+                let class = self.layout.class(&class);
+                let slot = class.slot(ident).expect("method slot");
+                let owner = class
+                    .direct_call(slot, static_type.is_some())
+                    .map(str::to_string);
+                self.emit(&format!("local.tee {receiver}"));
+                self.emit("i32.eqz");
+                self.emit("if");
+                // The abort routine also takes the method name and whether
+                // the dispatch is static.
+                self.location(expr.location);
+                let method = self.string_global(ident);
+                self.emit(&format!("global.get {method}"));
+                let is_static = static_type.is_some() as u8;
+                self.emit(&format!("i32.const {is_static}"));
+                self.emit("call $dispatch_abort");
+                self.emit("end");
+                self.emit(&format!("local.get {receiver}"));
+                for argument in arguments.iter() {
+                    self.emit(&format!("local.get {argument}"));
+                }
+                match owner {
+                    Some(owner) => self.emit(&format!("call ${owner}.{ident}")),
+                    None => {
+                        self.emit(&format!("local.get {receiver}"));
+                        self.emit("i32.load offset=8");
+                        self.emit(&format!(
+                            "i32.load offset={}",
+                            WORD_SIZE * slot
+                        ));
+                        self.emit(&format!(
+                            "call_indirect (type $method{})",
+                            params.len()
+                        ));
+                    }
+                }
+            }
+            Object(ident) => match self.variable(ident) {
+                Variable::SelfObject => self.emit("local.get $self"),
+                Variable::Local(local) => {
+                    self.emit(&format!("local.get {local}"))
+                }
+                Variable::Attribute(index) => {
+                    let offset = attribute_offset(index, WORD_SIZE);
+                    self.emit("local.get $self");
+                    self.emit(&format!("i32.load offset={offset}"));
+                }
+            },
+            IntLiteral(integer) => {
+                let global = self.int_global(*integer);
+                self.emit(&format!("global.get {global}"));
+            }
+            StrLiteral(string) => {
+                let global = self.string_global(string);
+                self.emit(&format!("global.get {global}"));
+            }
+            BoolLiteral(value) => {
+                self.emit(&format!("global.get $bool_const{}", *value as u8));
+            }
+        }
+    }
+
+    fn case(
+        &mut self,
+        expr: &Expression,
+        case_expr: &Expression,
+        branches: &[CaseBranch],
+    ) {
+        let label = self.label();
+        let object = self.local("t");
+        let tag = self.local("t");
+        self.expression(case_expr);
+        self.emit(&format!("local.tee {object}"));
+        self.emit("i32.eqz");
+        self.emit("if");
+        self.abort_at("case_abort2", expr.location);
+        self.emit("end");
+        self.emit(&format!("local.get {object}"));
+        self.emit("i32.load");
+        self.emit(&format!("local.set {tag}"));
+        self.emit(&format!("block $case{label} (result i32)"));
+
+        for branch in self.layout.case_branches(branches) {
+            let class = self.layout.class(&branch.type_id);
+            let (first, last) = (class.tag, class.last_descendant);
+            self.emit(&format!("local.get {tag}"));
+            self.emit(&format!("i32.const {first}"));
+            self.emit("i32.ge_s");
+            self.emit(&format!("local.get {tag}"));
+            self.emit(&format!("i32.const {last}"));
+            self.emit("i32.le_s");
+            self.emit("i32.and");
+            self.emit("if");
+            let local = self.local(&branch.ident);
+            self.emit(&format!("local.get {object}"));
+            self.emit(&format!("local.set {local}"));
+            self.scopes.enter_scope();
+            self.scopes.insert(branch.ident.clone(), local);
+            self.expression(&branch.expression);
+            self.scopes.exit_scope();
+            self.emit(&format!("br $case{label}"));
+            self.emit("end");
+        }
+        self.emit(&format!("local.get {object}"));
+        self.emit("call $case_abort");
+        self.emit("unreachable");
+        self.emit("end");
+    }
+
+    // Emit the module: the runtime, the declarations, the data and the
+    // code.
+    fn finish(mut self) -> String {
+        let data = self.data();
+        let functions = self.functions();
+        let heap_start: usize =
+            DATA_START + data.iter().map(|d| d.1.len()).sum::<usize>();
+        let pages = heap_start.div_ceil(PAGE_SIZE);
+
+        let mut out = String::new();
+        writeln!(out, "(module").unwrap();
+        out.push_str(WASM_RUNTIME);
+        writeln!(out).unwrap();
+        for arity in 0..=self.max_arity {
+            write!(out, "  (type $method{arity} (func (param i32)").unwrap();
+            for _ in 0..arity {
+                write!(out, " (param i32)").unwrap();
+            }
+            writeln!(out, " (result i32)))").unwrap();
+        }
+        writeln!(out, "  (memory (export \"memory\") {pages})").unwrap();
+        writeln!(out, "  (global $heap (mut i32) (i32.const {heap_start}))")
+            .unwrap();
+        for (global, class) in [
+            ("_int_tag", INT),
+            ("_bool_tag", BOOL),
+            ("_string_tag", STRING),
+        ] {
+            let tag = self.layout.tag(class);
+            writeln!(out, "  (global ${global} i32 (i32.const {tag}))")
+                .unwrap();
+        }
+        let mut address = DATA_START;
+        for (label, bytes) in data.iter() {
+            writeln!(out, "  (global ${label} i32 (i32.const {address}))")
+                .unwrap();
+            address += bytes.len();
+        }
+        writeln!(out, "  (table {} funcref)", functions.len()).unwrap();
+        write!(out, "  (elem (i32.const 0)").unwrap();
+        for function in functions.iter() {
+            write!(out, "\n    ${function}").unwrap();
+        }
+        writeln!(out, ")").unwrap();
+        let mut address = DATA_START;
+        for (label, bytes) in data.iter() {
+            writeln!(out, "  ;; {label}").unwrap();
+            writeln!(
+                out,
+                "  (data (i32.const {address}) \"{}\")",
+                escape(bytes)
+            )
+            .unwrap();
+            address += bytes.len();
+        }
+        out.push_str(&self.code);
+        let main = self.layout.class(MAIN);
+        let owner = &main.methods[main.slot("main").expect("main")].1;
+        writeln!(out, "  (func (export \"_start\")").unwrap();
+        writeln!(out, "    global.get $Main_protObj").unwrap();
+        writeln!(out, "    call $Object.copy").unwrap();
+        writeln!(out, "    call $Main_init").unwrap();
+        writeln!(out, "    call ${owner}.main").unwrap();
+        writeln!(out, "    drop").unwrap();
+        writeln!(out, "  )").unwrap();
+        writeln!(out, ")").unwrap();
+        out
+    }
+
+    // The functions of the function table: the initialisers, by tag, and
+    // the methods of the dispatch tables.
+    fn functions(&self) -> Vec<String> {
+        let mut functions: Vec<String> = self
+            .layout
+            .classes
+            .iter()
+            .map(|class| format!("{}_init", class.name))
+            .collect();
+        for class in self.layout.classes.iter() {
+            for (method, owner) in class.methods.iter() {
+                let function = format!("{owner}.{method}");
+                if !functions.contains(&function) {
+                    functions.push(function);
+                }
+            }
+        }
+        functions
+    }
+
+    // The labelled objects and tables of the data segments, in order of
+    // address. Addresses of objects referred to before they are placed are
+    // computed from the sizes of what precedes them.
+    fn data(&mut self) -> Vec<(String, Vec<u8>)> {
+        let functions = self.functions();
+        let index = |function: String| {
+            functions.iter().position(|f| *f == function).unwrap() as u32
+        };
+        let strings = self.constants.strings().to_vec();
+        let ints = self.constants.ints().to_vec();
+        let classes = self.layout.classes.len();
+
+        let bool_address = |value: usize| (DATA_START + 16 * value) as u32;
+        let int_address = |i: usize| (DATA_START + 32 + 16 * i) as u32;
+        let mut string_addresses = Vec::new();
+        let mut address = DATA_START + 32 + 16 * ints.len();
+        for string in strings.iter() {
+            string_addresses.push(address as u32);
+            address += 16 + (string.len() + WORD_SIZE) / 4 * 4;
+        }
+        let name_table = address;
+        let object_table = name_table + WORD_SIZE * classes;
+        let mut dispatch_tables = Vec::new();
+        let mut address = object_table + 2 * WORD_SIZE * classes;
+        for class in self.layout.classes.iter() {
+            dispatch_tables.push(address as u32);
+            address += WORD_SIZE * class.methods.len();
+        }
+        let mut prototypes = Vec::new();
+        for class in self.layout.classes.iter() {
+            prototypes.push(address as u32);
+            address += WORD_SIZE * class.size();
+        }
+
+        let int_tag = self.layout.tag(INT) as u32;
+        let string_tag = self.layout.tag(STRING) as u32;
+        let bool_tag = self.layout.tag(BOOL) as u32;
+        let mut data = Vec::new();
+        for value in 0..2 {
+            let words =
+                [bool_tag, 4, dispatch_tables[bool_tag as usize], value];
+            data.push((format!("bool_const{value}"), bytes(&words)));
+        }
+        for (index, int) in ints.iter().enumerate() {
+            let words =
+                [int_tag, 4, dispatch_tables[int_tag as usize], *int as u32];
+            data.push((format!("int_const{index}"), bytes(&words)));
+        }
+        for (index, string) in strings.iter().enumerate() {
+            let length = int_address(self.constants.int(string.len() as i32));
+            let words = HEADER_WORDS + 1 + (string.len() + WORD_SIZE) / 4;
+            let header = [
+                string_tag,
+                words as u32,
+                dispatch_tables[string_tag as usize],
+                length,
+            ];
+            let mut object = bytes(&header);
+            object.extend(string.as_bytes());
+            object.resize(WORD_SIZE * words, 0);
+            data.push((format!("str_const{index}"), object));
+        }
+        let names: Vec<u32> = self
+            .layout
+            .classes
+            .iter()
+            .map(|class| string_addresses[self.constants.string(&class.name)])
+            .collect();
+        data.push(("class_nameTab".to_string(), bytes(&names)));
+        let objects: Vec<u32> = self
+            .layout
+            .classes
+            .iter()
+            .flat_map(|class| {
+                [prototypes[class.tag], index(format!("{}_init", class.name))]
+            })
+            .collect();
+        data.push(("class_objTab".to_string(), bytes(&objects)));
+        for class in self.layout.classes.iter() {
+            let methods: Vec<u32> = class
+                .methods
+                .iter()
+                .map(|(method, owner)| index(format!("{owner}.{method}")))
+                .collect();
+            data.push((format!("{}_dispTab", class.name), bytes(&methods)));
+        }
+        let empty = string_addresses[self.constants.string("")];
+        let zero = int_address(self.constants.int(0));
+        for class in self.layout.classes.iter() {
+            let mut words = vec![
+                class.tag as u32,
+                class.size() as u32,
+                dispatch_tables[class.tag],
+            ];
+            match class.name.as_str() {
+                INT | BOOL => words.push(0),
+                STRING => words.extend([zero, 0]),
+                _ => {}
+            }
+            for (_, type_id) in class.attributes.iter() {
+                words.push(match type_id.as_str() {
+                    INT => zero,
+                    STRING => empty,
+                    BOOL => bool_address(0),
+                    _ => 0,
+                });
+            }
+            data.push((format!("{}_protObj", class.name), bytes(&words)));
+        }
+        data
+    }
+}
+
+// Words in little-endian order.
+fn bytes(words: &[u32]) -> Vec<u8> {
+    words.iter().flat_map(|word| word.to_le_bytes()).collect()
+}
+
+// Escape bytes for a string in the text format.
+fn escape(bytes: &[u8]) -> String {
+    let mut escaped = String::new();
+    for &byte in bytes {
+        match byte {
+            b'"' | b'\\' => write!(escaped, "\\{}", byte as char).unwrap(),
+            b' '..=b'~' => escaped.push(byte as char),
+            _ => write!(escaped, "\\{byte:02x}").unwrap(),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,834 @@
+//! x86-64 assembly for Linux in the syntax of the GNU assembler, linked with
+//! a small runtime library written in C.
+//!
+//! Objects have the layout of the other targets with eight byte words,
+//! although the value of an Int or Bool is 32 bits wide. Methods follow the
+//! System V calling convention: the receiver is passed in `%rdi` and the
+//! arguments in the following registers and then on the stack, and results
+//! are returned in `%rax`. The receiver is kept in `%rbx`. Intermediate
+//! values and variables live in the frame of the method rather than being
+//! pushed, so the stack stays aligned for calls into the runtime. The
+//! methods of the basic classes are the C functions `Class_method`.
+//!
+//! With [`Representation::Unboxed`], expressions of static type Int or Bool
+//! leave their value in `%eax` rather than an object in `%rax`, and `let`
+//! variables of those types hold the value in the frame. A value is only
+//! boxed where an object is needed: when stored in an attribute, passed as
+//! an argument, returned, used as a receiver or case expression, or joined
+//! with values of other types. Bools are boxed by picking `bool_const0` or
+//! `bool_const1`, which lie next to each other, so only Ints allocate.
+
+use super::*;
+use crate::ptree::*;
+use crate::semant::SymbolTable;
+use crate::tokens::Span;
+use std::fs::{create_dir_all, remove_dir_all, write};
+use std::io;
+use std::path::Path;
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use ExpressionData::*;
+
+/// The runtime library linked with generated programs.
+pub const X86_64_RUNTIME: &str = include_str!("runtime/x86_64.c");
+
+const WORD: usize = 8;
+/// The registers holding the first arguments after the receiver.
+const ARGUMENT_REGISTERS: [&str; 5] = ["%rsi", "%rdx", "%rcx", "%r8", "%r9"];
+
+/// Generate x86-64 assembly for a program that has passed semantic analysis,
+/// representing Ints and Bools as given.
+pub fn emit_x86_64(
+    program: &Program,
+    hierarchy: &ClassHierarchy,
+    representation: Representation,
+) -> String {
+    let mut generator = Generator {
+        layout: Layout::new(hierarchy),
+        representation,
+        constants: Constants::default(),
+        code: String::new(),
+        labels: 0,
+        class: String::new(),
+        scopes: SymbolTable::new(),
+        next_temp: 0,
+        temps: 0,
+    };
+    for class in generator.layout.classes.iter() {
+        generator.constants.string(&class.name);
+    }
+    generator.constants.string("");
+    generator.constants.int(0);
+    generator.initialisers(program);
+    for class in program.classes.iter() {
+        generator.class = class.name.clone();
+        for feature in class.features.iter() {
+            if let FeatureData::Method(name, _, formals, body) = &feature.data {
+                generator.method(&class.name, name, formals, body);
+            }
+        }
+    }
+    generator.finish()
+}
+
+/// Assemble generated assembly and link it with the runtime into an
+/// executable, using the system C compiler.
+pub fn link_x86_64(assembly: &str, output: &Path) -> io::Result<()> {
+    // Programs may be linked from several threads at once.
+    static BUILDS: AtomicUsize = AtomicUsize::new(0);
+    let build = BUILDS.fetch_add(1, Ordering::Relaxed);
+    let dir = std::env::temp_dir()
+        .join(format!("coolc-{}-{build}", std::process::id()));
+    create_dir_all(&dir)?;
+    let (program, runtime) = (dir.join("program.s"), dir.join("runtime.c"));
+    let status = write(&program, assembly)
+        .and_then(|_| write(&runtime, X86_64_RUNTIME))
+        .and_then(|_| {
+            Command::new("cc")
+                .arg("-O2")
+                .arg("-o")
+                .arg(output)
+                .arg(&program)
+                .arg(&runtime)
+                .status()
+        });
+    remove_dir_all(&dir)?;
+    match status? {
+        status if status.success() => Ok(()),
+        status => Err(io::Error::other(format!("cc failed with {status}"))),
+    }
+}
+
+// How a value is held in %rax.
+#[derive(Clone, Copy, PartialEq)]
+enum Repr {
+    Object,
+    // The value of an Int, in %eax.
+    Int,
+    // The value of a Bool, 0 or 1 in %eax.
+    Bool,
+}
+
+// Where a variable is stored.
+enum Variable {
+    SelfObject,
+    /// An offset from the frame pointer, and how the value is held there.
+    Frame(i32, Repr),
+    Attribute(usize),
+}
+
+struct Generator {
+    layout: Layout,
+    representation: Representation,
+    constants: Constants,
+    code: String,
+    labels: usize,
+    // The class and the variables of the method being generated.
+    class: String,
+    scopes: SymbolTable<String, (i32, Repr)>,
+    next_temp: usize,
+    temps: usize,
+}
+
+impl Generator {
+    fn label(&mut self) -> String {
+        self.labels += 1;
+        format!(".L{}", self.labels - 1)
+    }
+
+    fn place_label(&mut self, label: &str) {
+        writeln!(self.code, "{label}:").unwrap();
+    }
+
+    // Frame slots sit below the saved %rbx and are reused once the value
+    // they hold is no longer needed.
+    fn allocate_temp(&mut self) -> i32 {
+        self.next_temp += 1;
+        self.temps = self.temps.max(self.next_temp);
+        -((WORD * (self.next_temp + 1)) as i32)
+    }
+
+    fn release_temp(&mut self) {
+        self.next_temp -= 1;
+    }
+
+    fn string_label(&mut self, string: &str) -> String {
+        format!("str_const{}", self.constants.string(string))
+    }
+
+    fn int_label(&mut self, int: i32) -> String {
+        format!("int_const{}", self.constants.int(int))
+    }
+
+    // The basic classes are implemented by the runtime, whose functions
+    // cannot have a period in their names.
+    fn method_label(owner: &str, method: &str) -> String {
+        match owner {
+            OBJECT | IO | STRING => format!("{owner}_{method}"),
+            _ => format!("{owner}.{method}"),
+        }
+    }
+
+    // Generate a function around the code emitted by `body`, which leaves
+    // its result in %rax. The frame is a multiple of 16 bytes, as calls
+    // require.
+    fn function(&mut self, label: &str, body: impl FnOnce(&mut Self)) {
+        let outer = std::mem::take(&mut self.code);
+        self.next_temp = 0;
+        self.temps = 0;
+        body(self);
+        let body = std::mem::replace(&mut self.code, outer);
+
+        self.place_label(label);
+        emit!(self.code, "pushq\t%rbp");
+        emit!(self.code, "movq\t%rsp, %rbp");
+        emit!(self.code, "pushq\t%rbx");
+        emit!(self.code, "subq\t${}, %rsp", WORD * (self.temps | 1));
+        emit!(self.code, "movq\t%rdi, %rbx");
+        self.code.push_str(&body);
+        emit!(self.code, "movq\t-8(%rbp), %rbx");
+        emit!(self.code, "leave");
+        emit!(self.code, "ret");
+    }
+
+    fn initialisers(&mut self, program: &Program) {
+        for initialiser in self.layout.initialisers(program) {
+            self.class = initialiser.class.clone();
+            let label = format!("{}_init", initialiser.class);
+            self.function(&label, |g| {
+                if let Some(parent) = &initialiser.parent {
+                    emit!(g.code, "movq\t%rbx, %rdi");
+                    emit!(g.code, "call\t{parent}_init");
+                }
+                for (attr, init) in initialiser.attributes.iter() {
+                    g.expression_in(init, Repr::Object);
+                    g.store(attr);
+                }
+                emit!(g.code, "movq\t%rbx, %rax");
+            });
+        }
+    }
+
+    fn method(
+        &mut self,
+        class: &str,
+        name: &str,
+        formals: &[Formal],
+        body: &Expression,
+    ) {
+        self.scopes.enter_scope();
+        let label = Self::method_label(class, name);
+        self.function(&label, |g| {
+            // Arguments passed in registers are saved in the frame, and the
+            // rest are above the return address.
+            for (index, formal) in formals.iter().enumerate() {
+                let offset = match ARGUMENT_REGISTERS.get(index) {
+                    Some(register) => {
+                        let offset = g.allocate_temp();
+                        emit!(g.code, "movq\t{register}, {offset}(%rbp)");
+                        offset
+                    }
+                    None => (2 * WORD + WORD * (index - 5)) as i32,
+                };
+                g.scopes.insert(formal.name.clone(), (offset, Repr::Object));
+            }
+            g.expression_in(body, Repr::Object);
+        });
+        self.scopes.exit_scope();
+    }
+
+    fn variable(&self, name: &str) -> Variable {
+        if name == SELF {
+            Variable::SelfObject
+        } else if let Some(&(offset, repr)) = self.scopes.lookup(name) {
+            Variable::Frame(offset, repr)
+        } else {
+            let index = self
+                .layout
+                .class(&self.class)
+                .attribute_index(name)
+                .expect("declared attribute");
+            Variable::Attribute(index)
+        }
+    }
+
+    // Store %rax in a frame slot holding values as `repr` does.
+    fn save(&mut self, offset: i32, repr: Repr) {
+        match repr {
+            Repr::Object => emit!(self.code, "movq\t%rax, {offset}(%rbp)"),
+            _ => emit!(self.code, "movl\t%eax, {offset}(%rbp)"),
+        }
+    }
+
+    // Store %rax in a variable.
+    fn store(&mut self, name: &str) {
+        match self.variable(name) {
+            Variable::Frame(offset, repr) => self.save(offset, repr),
+            Variable::Attribute(index) => {
+                let offset = attribute_offset(index, WORD);
+                emit!(self.code, "movq\t%rax, {offset}(%rbx)");
+            }
+            Variable::SelfObject => unreachable!("assignment to self"),
+        }
+    }
+
+    fn default_value(&mut self, type_id: &str) {
+        let label = match type_id {
+            INT => self.int_label(0),
+            STRING => self.string_label(""),
+            BOOL => "bool_const0".to_string(),
+            _ => {
+                emit!(self.code, "xorl\t%eax, %eax");
+                return;
+            }
+        };
+        emit!(self.code, "leaq\t{label}(%rip), %rax");
+    }
+
+    // Call a runtime function reporting an error at a location, which takes
+    // the file name and the line.
+    fn abort_at(&mut self, function: &str, location: Span) {
+        let filename = self.string_label(location.extra);
+        emit!(self.code, "leaq\t{filename}(%rip), %rdi");
+        emit!(self.code, "movl\t${}, %esi", location.location_line());
+        emit!(self.code, "call\t{function}");
+    }
+
+    // Leave the Bool result of a comparison in %eax, given the condition
+    // code that holds when it is true.
+    fn set_bool(&mut self, condition: &str) {
+        emit!(self.code, "set{condition}\t%al");
+        emit!(self.code, "movzbl\t%al, %eax");
+    }
+
+    // How values of a static type are held.
+    fn repr(&self, type_id: &str) -> Repr {
+        match type_id {
+            INT if self.representation == Representation::Unboxed => Repr::Int,
+            BOOL if self.representation == Representation::Unboxed => {
+                Repr::Bool
+            }
+            _ => Repr::Object,
+        }
+    }
+
+    // Convert the value in %rax to another representation. Only values of
+    // static type Int or Bool are unboxed, which are never void.
+    fn convert(&mut self, from: Repr, to: Repr) {
+        match (from, to) {
+            (from, to) if from == to => {}
+            (Repr::Object, _) => emit!(self.code, "movl\t24(%rax), %eax"),
+            (Repr::Int, Repr::Object) => {
+                let offset = self.allocate_temp();
+                emit!(self.code, "movl\t%eax, {offset}(%rbp)");
+                emit!(self.code, "leaq\tInt_protObj(%rip), %rdi");
+                emit!(self.code, "call\tObject_copy");
+                emit!(self.code, "movl\t{offset}(%rbp), %ecx");
+                emit!(self.code, "movl\t%ecx, 24(%rax)");
+                self.release_temp();
+            }
+            (Repr::Bool, Repr::Object) => {
+                // Each Bool constant is four words long.
+                emit!(self.code, "shll\t$5, %eax");
+                emit!(self.code, "leaq\tbool_const0(%rip), %rcx");
+                emit!(self.code, "addq\t%rcx, %rax");
+            }
+            _ => unreachable!("Int and Bool values are not converted"),
+        }
+    }
+
+    // Generate code for an expression, leaving its value in %rax as held
+    // by `repr`. Literals needed as objects are constants.
+    fn expression_in(&mut self, expr: &Expression, repr: Repr) {
+        match (&expr.data, repr) {
+            (IntLiteral(integer), Repr::Object) => {
+                let label = self.int_label(*integer);
+                emit!(self.code, "leaq\t{label}(%rip), %rax");
+            }
+            (BoolLiteral(value), Repr::Object) => {
+                let value = *value as u8;
+                emit!(self.code, "leaq\tbool_const{value}(%rip), %rax");
+            }
+            _ => {
+                let from = self.expression(expr);
+                self.convert(from, repr);
+            }
+        }
+    }
+
+    // Generate code for an expression, leaving its value in %rax and
+    // returning how it is held. Boxed code boxes every value.
+    fn expression(&mut self, expr: &Expression) -> Repr {
+        let repr = self.value(expr);
+        if self.representation == Representation::Boxed {
+            self.convert(repr, Repr::Object);
+            return Repr::Object;
+        }
+        repr
+    }
+
+    // Generate code for an expression, returning how the value it leaves
+    // is held, which for Ints and Bools depends on the expression.
+    fn value(&mut self, expr: &Expression) -> Repr {
+        match &expr.data {
+            Block(expressions) => {
+                let (last, rest) = expressions.split_last().expect("block");
+                for expression in rest.iter() {
+                    self.expression(expression);
+                }
+                self.expression(last)
+            }
+            Conditional(if_expr, then_expr, else_expr) => {
+                let repr = self.repr(expr.static_type.as_deref().unwrap());
+                let else_label = self.label();
+                let end = self.label();
+                self.expression_in(if_expr, Repr::Bool);
+                emit!(self.code, "testl\t%eax, %eax");
+                emit!(self.code, "je\t{else_label}");
+                self.expression_in(then_expr, repr);
+                emit!(self.code, "jmp\t{end}");
+                self.place_label(&else_label);
+                self.expression_in(else_expr, repr);
+                self.place_label(&end);
+                repr
+            }
+            Loop(cond_expr, loop_expr) => {
+                let start = self.label();
+                let end = self.label();
+                self.place_label(&start);
+                self.expression_in(cond_expr, Repr::Bool);
+                emit!(self.code, "testl\t%eax, %eax");
+                emit!(self.code, "je\t{end}");
+                self.expression(loop_expr);
+                emit!(self.code, "jmp\t{start}");
+                self.place_label(&end);
+                emit!(self.code, "xorl\t%eax, %eax");
+                Repr::Object
+            }
+            Case(case_expr, branches) => self.case(expr, case_expr, branches),
+            Let(ident, type_id, opt_bind, body) => {
+                let repr = self.repr(type_id);
+                match (&**opt_bind, repr) {
+                    (Some(bind), _) => self.expression_in(bind, repr),
+                    (None, Repr::Object) => self.default_value(type_id),
+                    (None, _) => emit!(self.code, "xorl\t%eax, %eax"),
+                }
+                let offset = self.allocate_temp();
+                self.save(offset, repr);
+                self.scopes.enter_scope();
+                self.scopes.insert(ident.clone(), (offset, repr));
+                let result = self.expression(body);
+                self.scopes.exit_scope();
+                self.release_temp();
+                result
+            }
+            New(type_id) if type_id == SELF_TYPE => {
+                // class_objTab holds the prototype object and initialiser
+                // of each class, by tag.
+                let offset = self.allocate_temp();
+                emit!(self.code, "movq\t(%rbx), %rax");
+                emit!(self.code, "shlq\t$4, %rax");
+                emit!(self.code, "leaq\tclass_objTab(%rip), %rcx");
+                emit!(self.code, "addq\t%rcx, %rax");
+                emit!(self.code, "movq\t%rax, {offset}(%rbp)");
+                emit!(self.code, "movq\t(%rax), %rdi");
+                emit!(self.code, "call\tObject_copy");
+                emit!(self.code, "movq\t%rax, %rdi");
+                emit!(self.code, "movq\t{offset}(%rbp), %rcx");
+                emit!(self.code, "call\t*8(%rcx)");
+                self.release_temp();
+                Repr::Object
+            }
+            New(type_id) => {
+                emit!(self.code, "leaq\t{type_id}_protObj(%rip), %rdi");
+                emit!(self.code, "call\tObject_copy");
+                emit!(self.code, "movq\t%rax, %rdi");
+                emit!(self.code, "call\t{type_id}_init");
+                Repr::Object
+            }
+            Assign(ident, value) => {
+                let repr = match self.variable(ident) {
+                    Variable::Frame(_, repr) => repr,
+                    _ => Repr::Object,
+                };
+                self.expression_in(value, repr);
+                self.store(ident);
+                repr
+            }
+            UnaryOperation(UnaryOperator::Negative, operand) => {
+                self.expression_in(operand, Repr::Int);
+                emit!(self.code, "negl\t%eax");
+                Repr::Int
+            }
+            UnaryOperation(UnaryOperator::Not, operand) => {
+                self.expression_in(operand, Repr::Bool);
+                emit!(self.code, "xorl\t$1, %eax");
+                Repr::Bool
+            }
+            UnaryOperation(UnaryOperator::IsVoid, operand) => {
+                if self.expression(operand) == Repr::Object {
+                    emit!(self.code, "testq\t%rax, %rax");
+                    self.set_bool("e");
+                } else {
+                    emit!(self.code, "xorl\t%eax, %eax");
+                }
+                Repr::Bool
+            }
+            BinaryOperation(operator, operand1, operand2) => {
+                self.binary_operation(expr, operator, operand1, operand2)
+            }
+            MethodCall(callee, static_type, ident, params) => {
+                self.dispatch(expr, callee, static_type, ident, params);
+                Repr::Object
+            }
+            Object(ident) => match self.variable(ident) {
+                Variable::SelfObject => {
+                    emit!(self.code, "movq\t%rbx, %rax");
+                    Repr::Object
+                }
+                Variable::Frame(offset, Repr::Object) => {
+                    emit!(self.code, "movq\t{offset}(%rbp), %rax");
+                    Repr::Object
+                }
+                Variable::Frame(offset, repr) => {
+                    emit!(self.code, "movl\t{offset}(%rbp), %eax");
+                    repr
+                }
+                Variable::Attribute(index) => {
+                    let offset = attribute_offset(index, WORD);
+                    emit!(self.code, "movq\t{offset}(%rbx), %rax");
+                    Repr::Object
+                }
+            },
+            IntLiteral(integer) if self.repr(INT) == Repr::Int => {
+                emit!(self.code, "movl\t${integer}, %eax");
+                Repr::Int
+            }
+            IntLiteral(integer) => {
+                let label = self.int_label(*integer);
+                emit!(self.code, "leaq\t{label}(%rip), %rax");
+                Repr::Object
+            }
+            StrLiteral(string) => {
+                let label = self.string_label(string);
+                emit!(self.code, "leaq\t{label}(%rip), %rax");
+                Repr::Object
+            }
+            BoolLiteral(value) if self.repr(BOOL) == Repr::Bool => {
+                emit!(self.code, "movl\t${}, %eax", *value as u8);
+                Repr::Bool
+            }
+            BoolLiteral(value) => {
+                let value = *value as u8;
+                emit!(self.code, "leaq\tbool_const{value}(%rip), %rax");
+                Repr::Object
+            }
+        }
+    }
+
+    // Arguments are evaluated into the frame before the receiver, and
+    // those that do not fit in registers are pushed last to first.
+    fn dispatch(
+        &mut self,
+        expr: &Expression,
+        callee: &Expression,
+        static_type: &Option<String>,
+        ident: &str,
+        params: &[Expression],
+    ) {
+        let mut arguments = Vec::new();
+        for param in params.iter() {
+            self.expression_in(param, Repr::Object);
+            let offset = self.allocate_temp();
+            emit!(self.code, "movq\t%rax, {offset}(%rbp)");
+            arguments.push(offset);
+        }
+        self.expression_in(callee, Repr::Object);
+        let class = dispatch_class(static_type, callee, &self.class);
+        let slot = self.layout.class(&class).slot(ident).expect("method slot");
+        let dispatch = self.label();
+        emit!(self.code, "testq\t%rax, %rax");
+        emit!(self.code, "jne\t{dispatch}");
+        // The abort routine also takes the method name and whether the
+        // dispatch is static.
+        let method = self.string_label(ident);
+        emit!(self.code, "leaq\t{method}(%rip), %rdx");
+        emit!(self.code, "movl\t${}, %ecx", static_type.is_some() as u8);
+        self.abort_at("_dispatch_abort", expr.location);
+        self.place_label(&dispatch);
+
+        let stacked = arguments.len().saturating_sub(ARGUMENT_REGISTERS.len());
+        let stack_size = WORD * (stacked + stacked % 2);
+        if stacked % 2 == 1 {
+            emit!(self.code, "subq\t$8, %rsp");
+        }
+        for offset in arguments.iter().skip(ARGUMENT_REGISTERS.len()).rev() {
+            emit!(self.code, "pushq\t{offset}(%rbp)");
+        }
+        for (register, offset) in ARGUMENT_REGISTERS.iter().zip(&arguments) {
+            emit!(self.code, "movq\t{offset}(%rbp), {register}");
+        }
+        emit!(self.code, "movq\t%rax, %rdi");
+        let layout = self.layout.class(&class);
+        match layout.direct_call(slot, static_type.is_some()) {
+            Some(owner) => {
+                emit!(self.code, "call\t{}", Self::method_label(owner, ident))
+            }
+            None => {
+                emit!(self.code, "movq\t16(%rdi), %rax");
+                emit!(self.code, "call\t*{}(%rax)", WORD * slot);
+            }
+        }
+        if stack_size > 0 {
+            emit!(self.code, "addq\t${stack_size}, %rsp");
+        }
+        for _ in arguments {
+            self.release_temp();
+        }
+    }
+
+    // Arithmetic and comparisons work on the values of Ints, as does Equals
+    // on Ints and Bools, which are never compared with other types. Equals
+    // compares other objects with the runtime, giving a Bool object.
+    fn binary_operation(
+        &mut self,
+        expr: &Expression,
+        operator: &BinaryOperator,
+        operand1: &Expression,
+        operand2: &Expression,
+    ) -> Repr {
+        let operands = match operator {
+            BinaryOperator::Equals => {
+                self.repr(operand1.static_type.as_deref().unwrap())
+            }
+            _ => Repr::Int,
+        };
+        self.expression_in(operand1, operands);
+        let offset = self.allocate_temp();
+        self.save(offset, operands);
+        self.expression_in(operand2, operands);
+        let repr = if operands == Repr::Object {
+            let done = self.label();
+            emit!(self.code, "movq\t{offset}(%rbp), %rdi");
+            emit!(self.code, "movq\t%rax, %rsi");
+            emit!(self.code, "leaq\tbool_const1(%rip), %rax");
+            emit!(self.code, "cmpq\t%rdi, %rsi");
+            emit!(self.code, "je\t{done}");
+            emit!(self.code, "call\tequality_test");
+            self.place_label(&done);
+            Repr::Object
+        } else {
+            emit!(self.code, "movl\t%eax, %ecx");
+            emit!(self.code, "movl\t{offset}(%rbp), %eax");
+            self.values_operation(expr, operator)
+        };
+        self.release_temp();
+        repr
+    }
+
+    // With the first value in %eax and the second in %ecx.
+    fn values_operation(
+        &mut self,
+        expr: &Expression,
+        operator: &BinaryOperator,
+    ) -> Repr {
+        let (instruction, condition) = match operator {
+            BinaryOperator::Add => ("addl", None),
+            BinaryOperator::Subtract => ("subl", None),
+            BinaryOperator::Multiply => ("imull", None),
+            BinaryOperator::LessThan => ("cmpl", Some("l")),
+            BinaryOperator::LessThanOrEquals => ("cmpl", Some("le")),
+            BinaryOperator::Equals => ("cmpl", Some("e")),
+            BinaryOperator::Divide => {
+                self.divide(expr);
+                return Repr::Int;
+            }
+        };
+        emit!(self.code, "{instruction}\t%ecx, %eax");
+        match condition {
+            Some(condition) => {
+                self.set_bool(condition);
+                Repr::Bool
+            }
+            None => Repr::Int,
+        }
+    }
+
+    // Division by -1 negates, since idiv traps on the one quotient that
+    // overflows.
+    fn divide(&mut self, expr: &Expression) {
+        let (nonzero, divide, done) =
+            (self.label(), self.label(), self.label());
+        emit!(self.code, "testl\t%ecx, %ecx");
+        emit!(self.code, "jne\t{nonzero}");
+        self.abort_at("_divide_abort", expr.location);
+        self.place_label(&nonzero);
+        emit!(self.code, "cmpl\t$-1, %ecx");
+        emit!(self.code, "jne\t{divide}");
+        emit!(self.code, "negl\t%eax");
+        emit!(self.code, "jmp\t{done}");
+        self.place_label(&divide);
+        emit!(self.code, "cltd");
+        emit!(self.code, "idivl\t%ecx");
+        self.place_label(&done);
+    }
+
+    fn case(
+        &mut self,
+        expr: &Expression,
+        case_expr: &Expression,
+        branches: &[CaseBranch],
+    ) -> Repr {
+        let repr = self.repr(expr.static_type.as_deref().unwrap());
+        let end = self.label();
+        let matched = self.label();
+        self.expression_in(case_expr, Repr::Object);
+        emit!(self.code, "testq\t%rax, %rax");
+        emit!(self.code, "jne\t{matched}");
+        self.abort_at("_case_abort2", expr.location);
+        self.place_label(&matched);
+        let offset = self.allocate_temp();
+        emit!(self.code, "movq\t%rax, {offset}(%rbp)");
+        emit!(self.code, "movq\t(%rax), %rcx");
+
+        for branch in self.layout.case_branches(branches) {
+            let class = self.layout.class(&branch.type_id);
+            let (first, last) = (class.tag, class.last_descendant);
+            let next = self.label();
+            emit!(self.code, "cmpq\t${first}, %rcx");
+            emit!(self.code, "jl\t{next}");
+            emit!(self.code, "cmpq\t${last}, %rcx");
+            emit!(self.code, "jg\t{next}");
+            self.scopes.enter_scope();
+            self.scopes
+                .insert(branch.ident.clone(), (offset, Repr::Object));
+            self.expression_in(&branch.expression, repr);
+            self.scopes.exit_scope();
+            emit!(self.code, "jmp\t{end}");
+            self.place_label(&next);
+        }
+        emit!(self.code, "movq\t{offset}(%rbp), %rdi");
+        emit!(self.code, "call\t_case_abort");
+        self.place_label(&end);
+        self.release_temp();
+        repr
+    }
+
+    // Emit the data section followed by the code. The tables hold absolute
+    // addresses, so they are writable data even in position independent
+    // executables.
+    fn finish(mut self) -> String {
+        let mut out = String::new();
+        emit!(out, ".data");
+        emit!(out, ".p2align\t3");
+        for global in [
+            "class_nameTab",
+            "class_objTab",
+            "Main_protObj",
+            "Int_protObj",
+            "String_protObj",
+            "bool_const0",
+            "bool_const1",
+            "_int_tag",
+            "_bool_tag",
+            "_string_tag",
+        ] {
+            emit!(out, ".globl\t{global}");
+        }
+        for (label, class) in [
+            ("_int_tag", INT),
+            ("_bool_tag", BOOL),
+            ("_string_tag", STRING),
+        ] {
+            writeln!(out, "{label}:").unwrap();
+            emit!(out, ".quad\t{}", self.layout.tag(class));
+        }
+
+        self.constants(&mut out);
+        self.tables(&mut out);
+
+        emit!(out, ".text");
+        emit!(out, ".globl\tMain_init");
+        emit!(out, ".globl\tMain.main");
+        out.push_str(&self.code);
+        emit!(out, ".section\t.note.GNU-stack,\"\",@progbits");
+        out
+    }
+
+    fn constants(&mut self, out: &mut String) {
+        let int_tag = self.layout.tag(INT);
+        let string_tag = self.layout.tag(STRING);
+        let bool_tag = self.layout.tag(BOOL);
+        let strings = self.constants.strings().to_vec();
+        for (index, string) in strings.iter().enumerate() {
+            let length = self.int_label(string.len() as i32);
+            let words = HEADER_WORDS + 1 + (string.len() + WORD) / WORD;
+            writeln!(out, "str_const{index}:").unwrap();
+            emit!(out, ".quad\t{string_tag}");
+            emit!(out, ".quad\t{words}");
+            emit!(out, ".quad\tString_dispTab");
+            emit!(out, ".quad\t{length}");
+            ascii(out, string.as_bytes());
+            emit!(out, ".byte\t0");
+            emit!(out, ".p2align\t3");
+        }
+        for (index, int) in self.constants.ints().iter().enumerate() {
+            writeln!(out, "int_const{index}:").unwrap();
+            emit!(out, ".quad\t{int_tag}");
+            emit!(out, ".quad\t4");
+            emit!(out, ".quad\tInt_dispTab");
+            emit!(out, ".long\t{int}, 0");
+        }
+        for value in 0..2 {
+            writeln!(out, "bool_const{value}:").unwrap();
+            emit!(out, ".quad\t{bool_tag}");
+            emit!(out, ".quad\t4");
+            emit!(out, ".quad\tBool_dispTab");
+            emit!(out, ".long\t{value}, 0");
+        }
+    }
+
+    fn tables(&mut self, out: &mut String) {
+        writeln!(out, "class_nameTab:").unwrap();
+        for index in 0..self.layout.classes.len() {
+            let name = self.layout.classes[index].name.clone();
+            let label = self.string_label(&name);
+            emit!(out, ".quad\t{label}");
+        }
+        writeln!(out, "class_objTab:").unwrap();
+        for class in self.layout.classes.iter() {
+            emit!(out, ".quad\t{}_protObj", class.name);
+            emit!(out, ".quad\t{}_init", class.name);
+        }
+        for class in self.layout.classes.iter() {
+            writeln!(out, "{}_dispTab:", class.name).unwrap();
+            for (method, owner) in class.methods.iter() {
+                emit!(out, ".quad\t{}", Self::method_label(owner, method));
+            }
+        }
+        let empty = self.string_label("");
+        let zero = self.int_label(0);
+        for class in self.layout.classes.iter() {
+            writeln!(out, "{}_protObj:", class.name).unwrap();
+            emit!(out, ".quad\t{}", class.tag);
+            emit!(out, ".quad\t{}", class.size());
+            emit!(out, ".quad\t{}_dispTab", class.name);
+            match class.name.as_str() {
+                INT | BOOL => emit!(out, ".quad\t0"),
+                STRING => {
+                    emit!(out, ".quad\t{zero}");
+                    emit!(out, ".quad\t0");
+                }
+                _ => {}
+            }
+            for (_, type_id) in class.attributes.iter() {
+                match type_id.as_str() {
+                    INT => emit!(out, ".quad\t{zero}"),
+                    STRING => emit!(out, ".quad\t{empty}"),
+                    BOOL => emit!(out, ".quad\tbool_const0"),
+                    _ => emit!(out, ".quad\t0"),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,290 @@
+//! Line and branch coverage of Cool source, for the tree-walking
+//! interpreter.
+//!
+//! Every expression of a program is counted each time it is evaluated. A
+//! line is covered when an expression starting on it was evaluated. The arms
+//! of conditionals, the branches of case expressions and the bodies of loops
+//! are branches, the exit of a loop being the other branch of a loop. The
+//! counts are reported as an lcov tracefile, and as a listing of a source
+//! file with the count of every line, in the style of gcov:
+//!
+//! ```text
+//!         3:    4:    if n = 0 then 1 else n * fact(n - 1) fi
+//! branch  0 taken 1
+//! branch  1 taken 2
+//!     #####:    5:    abort()
+//! ```
+
+use crate::interpreter::*;
+use crate::ptree::*;
+use crate::tokens::Span;
+use std::collections::{BTreeMap, HashMap};
+use std::io::{self, Write};
+
+#[cfg(test)]
+mod tests;
+
+/// An arm of a branching expression.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Branch {
+    pub line: u32,
+    /// Branching expressions are numbered in the order of the program.
+    pub block: usize,
+    /// The arm within the branching expression.
+    pub arm: usize,
+    /// Number of times the arm was taken, or `None` if the branching
+    /// expression was never evaluated.
+    pub taken: Option<u64>,
+}
+
+// A branching expression and the expressions that start each of its arms,
+// which for a loop are the body and the loop itself.
+struct BranchPoint {
+    expression: usize,
+    arms: Vec<usize>,
+}
+
+// Where something starts in the source.
+#[derive(Clone, Copy, PartialEq)]
+struct Position {
+    file: usize,
+    line: u32,
+}
+
+// A method, with the expression of its body.
+struct Function {
+    name: String,
+    position: Position,
+    body: usize,
+}
+
+pub struct Coverage {
+    files: Vec<String>,
+    /// Positions of the expressions of the program, in source order within
+    /// each feature.
+    expressions: Vec<Position>,
+    /// Index of every expression, by node.
+    indices: HashMap<NodeId, usize>,
+    hits: Vec<u64>,
+    branch_points: Vec<BranchPoint>,
+    functions: Vec<Function>,
+}
+
+impl Coverage {
+    pub fn new(program: &Program) -> Self {
+        let mut coverage = Self {
+            files: Vec::new(),
+            expressions: Vec::new(),
+            indices: HashMap::new(),
+            hits: Vec::new(),
+            branch_points: Vec::new(),
+            functions: Vec::new(),
+        };
+        for class in program.classes.iter() {
+            for feature in class.features.iter() {
+                match &feature.data {
+                    FeatureData::Attribute(_, _, Some(init)) => {
+                        coverage.add(init);
+                    }
+                    FeatureData::Attribute(_, _, None) => {}
+                    FeatureData::Method(name, _, _, body) => {
+                        let body = coverage.add(body);
+                        let position = coverage.position(feature.location);
+                        coverage.functions.push(Function {
+                            name: format!("{}.{name}", class.name),
+                            position,
+                            body,
+                        });
+                    }
+                }
+            }
+        }
+        coverage
+    }
+
+    // Number an expression and those it contains, and record the branches
+    // of those that branch.
+    fn add(&mut self, expr: &Expression) -> usize {
+        let index = self.expressions.len();
+        let position = self.position(expr.location);
+        self.expressions.push(position);
+        self.indices.insert(expr.id, index);
+        self.hits.push(0);
+        let subexpressions: Vec<usize> = expr
+            .subexpressions()
+            .into_iter()
+            .map(|subexpression| self.add(subexpression))
+            .collect();
+        let arms = match &expr.data {
+            ExpressionData::Conditional(..) => subexpressions[1..].to_vec(),
+            ExpressionData::Case(..) => subexpressions[1..].to_vec(),
+            ExpressionData::Loop(..) => vec![subexpressions[1], index],
+            _ => return index,
+        };
+        self.branch_points.push(BranchPoint {
+            expression: index,
+            arms,
+        });
+        index
+    }
+
+    fn position(&mut self, location: Span) -> Position {
+        let file = match self.files.iter().position(|f| f == location.extra) {
+            Some(file) => file,
+            None => {
+                self.files.push(location.extra.to_string());
+                self.files.len() - 1
+            }
+        };
+        let line = location.location_line();
+        Position { file, line }
+    }
+
+    fn file_index(&self, file: &str) -> Option<usize> {
+        self.files.iter().position(|f| f == file)
+    }
+
+    /// Source files of the program, in order of appearance.
+    pub fn files(&self) -> Vec<&str> {
+        self.files.iter().map(String::as_str).collect()
+    }
+
+    /// The lines of a file where expressions start, with the number of
+    /// times the most evaluated of them was evaluated.
+    pub fn lines(&self, file: &str) -> BTreeMap<u32, u64> {
+        let file = self.file_index(file);
+        let mut lines = BTreeMap::new();
+        for (position, &hits) in self.expressions.iter().zip(&self.hits) {
+            if Some(position.file) == file {
+                let count = lines.entry(position.line).or_default();
+                *count = hits.max(*count);
+            }
+        }
+        lines
+    }
+
+    /// The arms of the branching expressions of a file.
+    pub fn branches(&self, file: &str) -> Vec<Branch> {
+        let file = self.file_index(file);
+        let mut branches = Vec::new();
+        for (block, point) in self.branch_points.iter().enumerate() {
+            let position = self.expressions[point.expression];
+            if Some(position.file) != file {
+                continue;
+            }
+            let reached = self.hits[point.expression] > 0;
+            for (arm, &expression) in point.arms.iter().enumerate() {
+                branches.push(Branch {
+                    line: position.line,
+                    block,
+                    arm,
+                    taken: reached.then_some(self.hits[expression]),
+                });
+            }
+        }
+        branches
+    }
+
+    /// Write the coverage of all files as an lcov tracefile.
+    pub fn write_lcov(&self, output: &mut dyn Write) -> io::Result<()> {
+        writeln!(output, "TN:")?;
+        for (index, file) in self.files.iter().enumerate() {
+            writeln!(output, "SF:{file}")?;
+            let functions: Vec<&Function> = self
+                .functions
+                .iter()
+                .filter(|function| function.position.file == index)
+                .collect();
+            for function in functions.iter() {
+                let line = function.position.line;
+                writeln!(output, "FN:{line},{}", function.name)?;
+            }
+            for function in functions.iter() {
+                let calls = self.hits[function.body];
+                writeln!(output, "FNDA:{calls},{}", function.name)?;
+            }
+            writeln!(output, "FNF:{}", functions.len())?;
+            let called = functions
+                .iter()
+                .filter(|function| self.hits[function.body] > 0)
+                .count();
+            writeln!(output, "FNH:{called}")?;
+
+            let branches = self.branches(file);
+            for branch in branches.iter() {
+                let taken = match branch.taken {
+                    Some(taken) => taken.to_string(),
+                    None => "-".to_string(),
+                };
+                writeln!(
+                    output,
+                    "BRDA:{},{},{},{taken}",
+                    branch.line, branch.block, branch.arm
+                )?;
+            }
+            writeln!(output, "BRF:{}", branches.len())?;
+            let taken = branches
+                .iter()
+                .filter(|branch| branch.taken.unwrap_or_default() > 0)
+                .count();
+            writeln!(output, "BRH:{taken}")?;
+
+            let lines = self.lines(file);
+            for (line, hits) in lines.iter() {
+                writeln!(output, "DA:{line},{hits}")?;
+            }
+            writeln!(output, "LF:{}", lines.len())?;
+            let hit = lines.values().filter(|&&hits| hits > 0).count();
+            writeln!(output, "LH:{hit}")?;
+            writeln!(output, "end_of_record")?;
+        }
+        Ok(())
+    }
+
+    /// Write the source of a file with the count of each line where
+    /// expressions start, followed by the counts of the branches on it.
+    /// Lines that were never evaluated are marked with `#####`.
+    pub fn write_listing(
+        &self,
+        file: &str,
+        source: &str,
+        output: &mut dyn Write,
+    ) -> io::Result<()> {
+        let lines = self.lines(file);
+        let branches = self.branches(file);
+        for (line, text) in (1..).zip(source.lines()) {
+            let count = match lines.get(&line) {
+                Some(0) => "#####".to_string(),
+                Some(hits) => hits.to_string(),
+                None => "-".to_string(),
+            };
+            writeln!(output, "{count:>9}:{line:>5}:{text}")?;
+            let branches_on_line =
+                branches.iter().filter(|branch| branch.line == line);
+            for (number, branch) in branches_on_line.enumerate() {
+                match branch.taken {
+                    Some(taken) => {
+                        writeln!(output, "branch {number:>2} taken {taken}")?
+                    }
+                    None => {
+                        writeln!(output, "branch {number:>2} never executed")?
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<'p> Observer<'p> for Coverage {
+    fn expression(
+        &mut self,
+        _interpreter: &Interpreter<'p>,
+        expr: &'p Expression<'p>,
+    ) -> Result<(), RuntimeError<'p>> {
+        if let Some(&index) = self.indices.get(&expr.id) {
+            self.hits[index] += 1;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,118 @@
+use super::*;
+use crate::lexer::lex_tokens;
+use crate::parser::parse_program;
+use crate::semant::check_program;
+
+const SOURCE: &str = "\
+class Main inherits IO {
+  fact(n : Int) : Int {
+    if n = 0 then 1 else n * fact(n - 1) fi
+  };
+  unused() : Object { abort() };
+  main() : Object {
+    let i : Int <- 0 in {
+      while i < 2 loop i <- i + 1 pool;
+      case fact(2) of
+        x : Int => out_int(x);
+        o : Object => abort();
+      esac;
+    }
+  };
+};
+";
+
+// Runs a program under coverage and applies f to what was recorded.
+fn with_coverage<T>(source: &str, f: impl FnOnce(&Coverage) -> T) -> T {
+    let (_, tokens) = lex_tokens(source, "test.cl").unwrap();
+    let (_, mut program) = parse_program(&tokens).unwrap();
+    let hierarchy = check_program(&mut program).unwrap();
+    let mut coverage = Coverage::new(&program);
+    let mut interpreter = Interpreter::new(
+        &program,
+        &hierarchy,
+        Box::new("".as_bytes()),
+        Box::new(Vec::new()),
+    );
+    interpreter.set_observer(Box::new(&mut coverage));
+    interpreter.run().unwrap();
+    drop(interpreter);
+    f(&coverage)
+}
+
+#[test]
+fn test_lines_and_branches() {
+    with_coverage(SOURCE, |coverage| {
+        assert_eq!(coverage.files(), ["test.cl"]);
+        assert_eq!(
+            coverage.lines("test.cl").into_iter().collect::<Vec<_>>(),
+            [(3, 3), (5, 0), (7, 1), (8, 3), (9, 1), (10, 1), (11, 0)]
+        );
+        let taken: Vec<(u32, usize, usize, Option<u64>)> = coverage
+            .branches("test.cl")
+            .into_iter()
+            .map(|branch| (branch.line, branch.block, branch.arm, branch.taken))
+            .collect();
+        assert_eq!(
+            taken,
+            [
+                (3, 0, 0, Some(1)),
+                (3, 0, 1, Some(2)),
+                (8, 1, 0, Some(2)),
+                (8, 1, 1, Some(1)),
+                (9, 2, 0, Some(1)),
+                (9, 2, 1, Some(0)),
+            ]
+        );
+    });
+}
+
+#[test]
+fn test_lcov() {
+    let mut lcov = Vec::new();
+    with_coverage(SOURCE, |coverage| coverage.write_lcov(&mut lcov)).unwrap();
+    assert_eq!(
+        String::from_utf8(lcov).unwrap(),
+        "TN:\nSF:test.cl\n\
+         FN:2,Main.fact\nFN:5,Main.unused\nFN:6,Main.main\n\
+         FNDA:3,Main.fact\nFNDA:0,Main.unused\nFNDA:1,Main.main\n\
+         FNF:3\nFNH:2\n\
+         BRDA:3,0,0,1\nBRDA:3,0,1,2\nBRDA:8,1,0,2\nBRDA:8,1,1,1\n\
+         BRDA:9,2,0,1\nBRDA:9,2,1,0\nBRF:6\nBRH:5\n\
+         DA:3,3\nDA:5,0\nDA:7,1\nDA:8,3\nDA:9,1\nDA:10,1\nDA:11,0\n\
+         LF:7\nLH:5\nend_of_record\n"
+    );
+}
+
+#[test]
+fn test_listing() {
+    let mut listing = Vec::new();
+    with_coverage(SOURCE, |coverage| {
+        coverage.write_listing("test.cl", SOURCE, &mut listing)
+    })
+    .unwrap();
+    let listing = String::from_utf8(listing).unwrap();
+    let lines: Vec<&str> = listing.lines().collect();
+    assert_eq!(lines.len(), 21);
+    assert_eq!(lines[0], "        -:    1:class Main inherits IO {");
+    assert_eq!(
+        lines[2..7],
+        [
+            "        3:    3:    if n = 0 then 1 else n * fact(n - 1) fi",
+            "branch  0 taken 1",
+            "branch  1 taken 2",
+            "        -:    4:  };",
+            "    #####:    5:  unused() : Object { abort() };",
+        ]
+    );
+}
+
+#[test]
+fn test_unreached_branches() {
+    let source = "class Main { main() : Object { 0 }; \
+                  f() : Int { if true then 1 else 2 fi }; };";
+    with_coverage(source, |coverage| {
+        let branches = coverage.branches("test.cl");
+        assert_eq!(branches.len(), 2);
+        assert!(branches.iter().all(|branch| branch.taken.is_none()));
+    });
+}
@@ -0,0 +1,148 @@
+//! The command-line front end of the debugger.
+
+use super::*;
+use std::io::{BufRead, Write};
+
+const PROMPT: &str = "(cdb) ";
+
+const HELP: &str = "\
+Commands:
+  break, b <file:line | Class.method>  Set a breakpoint
+  delete, d <number>                   Delete a breakpoint
+  breakpoints                          List breakpoints
+  continue, c                          Run until the next breakpoint
+  step, s                              Run to the next line, entering calls
+  next, n                              Run to the next line, over calls
+  finish                               Run until the current method returns
+  backtrace, bt                        Show the chain of dispatches
+  self                                 Show the attributes of self
+  locals                               Show formals and let-bound locals
+  print, p <name>                      Show the value of a variable
+  quit, q                              Stop the program
+An empty line repeats the previous command that resumed the program.
+";
+
+/// Reads commands from a prompt and reports in plain text.
+pub struct Cli<'a> {
+    input: Box<dyn BufRead + 'a>,
+    output: Box<dyn Write + 'a>,
+    previous: Option<Command>,
+}
+
+impl<'a> Cli<'a> {
+    pub fn new(
+        input: Box<dyn BufRead + 'a>,
+        output: Box<dyn Write + 'a>,
+    ) -> Self {
+        Self {
+            input,
+            output,
+            previous: None,
+        }
+    }
+}
+
+impl Frontend for Cli<'_> {
+    fn stopped(&mut self, stop: &Stop) -> io::Result<()> {
+        let Stop {
+            file, line, method, ..
+        } = stop;
+        match stop.reason {
+            StopReason::Entry => write!(self.output, "Program starts in ")?,
+            StopReason::Breakpoint(id) => {
+                write!(self.output, "Breakpoint {id}, ")?
+            }
+            StopReason::Step => {}
+        }
+        writeln!(self.output, "{method} at {file}:{line}")
+    }
+
+    fn command(&mut self) -> io::Result<Option<Command>> {
+        loop {
+            write!(self.output, "{PROMPT}")?;
+            self.output.flush()?;
+            let mut line = String::new();
+            if self.input.read_line(&mut line)? == 0 {
+                writeln!(self.output)?;
+                return Ok(None);
+            }
+            let command = match line.trim() {
+                "" => match &self.previous {
+                    Some(command) => Ok(command.clone()),
+                    None => continue,
+                },
+                "h" | "help" => {
+                    write!(self.output, "{HELP}")?;
+                    continue;
+                }
+                input => input.parse(),
+            };
+            match command {
+                Ok(command) => {
+                    if matches!(
+                        command,
+                        Command::Continue
+                            | Command::StepInto
+                            | Command::StepOver
+                            | Command::StepOut
+                    ) {
+                        self.previous = Some(command.clone());
+                    }
+                    return Ok(Some(command));
+                }
+                Err(message) => writeln!(self.output, "{message}")?,
+            }
+        }
+    }
+
+    fn respond(&mut self, response: Response) -> io::Result<()> {
+        match response {
+            Response::BreakpointSet(id, breakpoint) => {
+                writeln!(self.output, "Breakpoint {id} at {breakpoint}")
+            }
+            Response::BreakpointDeleted(id) => {
+                writeln!(self.output, "Deleted breakpoint {id}")
+            }
+            Response::Breakpoints(breakpoints) if breakpoints.is_empty() => {
+                writeln!(self.output, "No breakpoints.")
+            }
+            Response::Breakpoints(breakpoints) => {
+                for (id, breakpoint) in breakpoints {
+                    writeln!(self.output, "{id:<4}{breakpoint}")?;
+                }
+                Ok(())
+            }
+            Response::Backtrace(frames) => {
+                for (index, frame) in frames.iter().enumerate() {
+                    write!(
+                        self.output,
+                        "#{index:<3}{} (self: {})",
+                        frame.method, frame.receiver
+                    )?;
+                    if let Some((file, line)) = &frame.location {
+                        write!(self.output, " at {file}:{line}")?;
+                    }
+                    writeln!(self.output)?;
+                }
+                Ok(())
+            }
+            Response::Variables(variables) if variables.is_empty() => {
+                writeln!(self.output, "No variables.")
+            }
+            Response::Variables(variables) => {
+                for variable in variables {
+                    writeln!(
+                        self.output,
+                        "{} = {}",
+                        variable.name, variable.value
+                    )?;
+                }
+                Ok(())
+            }
+            Response::Value(name, value) => {
+                writeln!(self.output, "{name} = {value}")
+            }
+            Response::Error(message) => writeln!(self.output, "{message}"),
+        }
+    }
+}
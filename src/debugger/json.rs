@@ -0,0 +1,203 @@
+//! A front end for editors, exchanging one JSON object per line.
+//!
+//! Requests name a command of the command-line front end, with its argument
+//! if it takes one, and may carry an id that is copied into the response:
+//!
+//! ```text
+//! {"id": 1, "command": "break", "argument": "A.f"}
+//! {"body": {"breakpoint": {"id": 1, "location": "A.f"}}, "id": 1, ...}
+//! ```
+//!
+//! Failed requests get `"success": false` and a `"message"`. Events are
+//! sent when the program stops, writes output or exits:
+//!
+//! ```text
+//! {"breakpoint": 1, "event": "stopped", "file": "a.cl", "line": 3, ...}
+//! {"event": "output", "text": "Hello, World.\n"}
+//! {"event": "exited", "exitCode": 0}
+//! ```
+
+use super::*;
+use serde_json::{json, Value as JsonValue};
+use std::io::{BufRead, Write};
+
+/// Reads requests and writes responses and events as lines of JSON.
+pub struct Json<'a> {
+    input: Box<dyn BufRead + 'a>,
+    output: Box<dyn Write + 'a>,
+    /// The id of the request being answered.
+    request_id: JsonValue,
+}
+
+impl<'a> Json<'a> {
+    pub fn new(
+        input: Box<dyn BufRead + 'a>,
+        output: Box<dyn Write + 'a>,
+    ) -> Self {
+        Self {
+            input,
+            output,
+            request_id: JsonValue::Null,
+        }
+    }
+
+    fn send(&mut self, message: JsonValue) -> io::Result<()> {
+        writeln!(self.output, "{message}")?;
+        self.output.flush()
+    }
+
+    fn succeed(&mut self, body: JsonValue) -> io::Result<()> {
+        let id = self.request_id.take();
+        self.send(json!({"id": id, "success": true, "body": body}))
+    }
+
+    fn fail(&mut self, message: String) -> io::Result<()> {
+        let id = self.request_id.take();
+        self.send(json!({"id": id, "success": false, "message": message}))
+    }
+}
+
+// Requests are turned into the commands of the command-line front end.
+fn parse_request(
+    line: &str,
+) -> Result<(JsonValue, Command), (JsonValue, String)> {
+    let request: JsonValue = serde_json::from_str(line)
+        .map_err(|err| (JsonValue::Null, format!("Invalid request: {err}.")))?;
+    let id = request.get("id").cloned().unwrap_or_default();
+    let command = match request.get("command").and_then(JsonValue::as_str) {
+        Some(command) => command,
+        None => return Err((id, "Missing command.".to_string())),
+    };
+    let command = match request.get("argument") {
+        Some(JsonValue::String(argument)) => format!("{command} {argument}"),
+        Some(JsonValue::Number(argument)) => format!("{command} {argument}"),
+        _ => command.to_string(),
+    };
+    match command.parse() {
+        Ok(command) => Ok((id, command)),
+        Err(message) => Err((id, message)),
+    }
+}
+
+impl Frontend for Json<'_> {
+    fn stopped(&mut self, stop: &Stop) -> io::Result<()> {
+        let (reason, breakpoint) = match stop.reason {
+            StopReason::Entry => ("entry", None),
+            StopReason::Breakpoint(id) => ("breakpoint", Some(id)),
+            StopReason::Step => ("step", None),
+        };
+        let mut event = json!({
+            "event": "stopped",
+            "reason": reason,
+            "file": stop.file,
+            "line": stop.line,
+            "method": stop.method,
+        });
+        if let Some(id) = breakpoint {
+            event["breakpoint"] = json!(id);
+        }
+        self.send(event)
+    }
+
+    fn command(&mut self) -> io::Result<Option<Command>> {
+        loop {
+            let mut line = String::new();
+            if self.input.read_line(&mut line)? == 0 {
+                return Ok(None);
+            }
+            if line.trim().is_empty() {
+                continue;
+            }
+            match parse_request(&line) {
+                Ok((id, command)) => {
+                    self.request_id = id;
+                    return Ok(Some(command));
+                }
+                Err((id, message)) => {
+                    self.request_id = id;
+                    self.fail(message)?;
+                }
+            }
+        }
+    }
+
+    fn respond(&mut self, response: Response) -> io::Result<()> {
+        let body = match response {
+            Response::BreakpointSet(id, breakpoint) => {
+                json!({"breakpoint": breakpoint_json(id, &breakpoint)})
+            }
+            Response::BreakpointDeleted(_) => json!({}),
+            Response::Breakpoints(breakpoints) => json!({
+                "breakpoints": breakpoints
+                    .iter()
+                    .map(|(id, breakpoint)| breakpoint_json(*id, breakpoint))
+                    .collect::<Vec<_>>()
+            }),
+            Response::Backtrace(frames) => json!({
+                "frames": frames
+                    .iter()
+                    .map(|frame| {
+                        let (file, line) = frame.location.clone().unzip();
+                        json!({
+                            "method": frame.method,
+                            "receiver": frame.receiver,
+                            "file": file,
+                            "line": line,
+                        })
+                    })
+                    .collect::<Vec<_>>()
+            }),
+            Response::Variables(variables) => json!({
+                "variables": variables
+                    .iter()
+                    .map(|variable| json!({
+                        "name": variable.name,
+                        "kind": variable.kind,
+                        "value": variable.value,
+                    }))
+                    .collect::<Vec<_>>()
+            }),
+            Response::Value(name, value) => {
+                json!({"name": name, "value": value})
+            }
+            Response::Error(message) => return self.fail(message),
+        };
+        self.succeed(body)
+    }
+
+    fn resumed(&mut self) -> io::Result<()> {
+        self.succeed(json!({}))
+    }
+}
+
+fn breakpoint_json(id: usize, breakpoint: &Breakpoint) -> JsonValue {
+    json!({"id": id, "location": breakpoint.to_string()})
+}
+
+/// Output of the program being debugged, sent as output events.
+pub struct JsonOutput<'a> {
+    output: Box<dyn Write + 'a>,
+}
+
+impl<'a> JsonOutput<'a> {
+    pub fn new(output: Box<dyn Write + 'a>) -> Self {
+        Self { output }
+    }
+}
+
+impl Write for JsonOutput<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let text = String::from_utf8_lossy(buf);
+        writeln!(self.output, "{}", json!({"event": "output", "text": text}))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+/// The event that ends a debugging session.
+pub fn exited_event(exit_code: i32) -> String {
+    json!({"event": "exited", "exitCode": exit_code}).to_string()
+}
@@ -0,0 +1,435 @@
+//! A source-level debugger for the tree-walking interpreter.
+//!
+//! The debugger observes the interpreter and stops the program at
+//! breakpoints, set on a source line or on entry to a method, and after each
+//! step. While the program is stopped, commands read by a [`Frontend`] show
+//! the chain of dispatches and the variables in scope. There are two front
+//! ends: a command-line prompt, and JSON messages for editors.
+
+mod cli;
+mod json;
+
+pub use self::cli::Cli;
+pub use self::json::{exited_event, Json, JsonOutput};
+use crate::interpreter::*;
+use crate::ptree::Expression;
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+#[cfg(test)]
+mod tests;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Breakpoint {
+    /// Stop when evaluation reaches a line. The file may be given by the
+    /// last components of its path.
+    Line { file: String, line: u32 },
+    /// Stop on entry to a method, of the class that defines it or of the
+    /// class of the receiver.
+    Method { class: String, method: String },
+}
+
+impl FromStr for Breakpoint {
+    type Err = String;
+
+    fn from_str(location: &str) -> Result<Self, Self::Err> {
+        if let Some((file, line)) = location.rsplit_once(':') {
+            if let Ok(line) = line.parse() {
+                let file = file.to_string();
+                return Ok(Self::Line { file, line });
+            }
+        }
+        match location.split_once('.') {
+            Some((class, method))
+                if class.starts_with(char::is_uppercase)
+                    && !method.is_empty() =>
+            {
+                Ok(Self::Method {
+                    class: class.to_string(),
+                    method: method.to_string(),
+                })
+            }
+            _ => Err(format!(
+                "Invalid breakpoint {location}, \
+                 expected file:line or Class.method."
+            )),
+        }
+    }
+}
+
+impl Display for Breakpoint {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Line { file, line } => write!(f, "{file}:{line}"),
+            Self::Method { class, method } => write!(f, "{class}.{method}"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+    Break(Breakpoint),
+    Delete(usize),
+    Breakpoints,
+    Continue,
+    StepInto,
+    StepOver,
+    StepOut,
+    Backtrace,
+    /// Show the attributes of self.
+    SelfAttributes,
+    /// Show formal parameters and let-bound locals.
+    Locals,
+    Print(String),
+    Quit,
+}
+
+// Commands have a short and a long name, and the names used by the JSON
+// protocol for stepping.
+impl FromStr for Command {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        let mut words = input.split_whitespace();
+        let name = words.next().unwrap_or_default();
+        let argument = words.next();
+        let command = match (name, argument) {
+            ("b" | "break", Some(location)) => Self::Break(location.parse()?),
+            ("d" | "delete", Some(id)) => Self::Delete(
+                id.parse()
+                    .map_err(|_| format!("Invalid breakpoint number {id}."))?,
+            ),
+            ("breakpoints", None) => Self::Breakpoints,
+            ("c" | "continue", None) => Self::Continue,
+            ("s" | "step" | "stepIn", None) => Self::StepInto,
+            ("n" | "next" | "stepOver", None) => Self::StepOver,
+            ("finish" | "stepOut", None) => Self::StepOut,
+            ("bt" | "backtrace", None) => Self::Backtrace,
+            ("self", None) => Self::SelfAttributes,
+            ("locals", None) => Self::Locals,
+            ("p" | "print", Some(name)) => Self::Print(name.to_string()),
+            ("q" | "quit", None) => Self::Quit,
+            _ => return Err(format!("Invalid command: {}.", input.trim())),
+        };
+        match words.next() {
+            Some(_) => Err(format!("Too many arguments: {}.", input.trim())),
+            None => Ok(command),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StopReason {
+    /// The program is about to start.
+    Entry,
+    Breakpoint(usize),
+    Step,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stop {
+    pub reason: StopReason,
+    pub file: String,
+    pub line: u32,
+    /// The method being run, as Class.method.
+    pub method: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct FrameInfo {
+    /// The method, as Class.method with the class that defines it.
+    pub method: String,
+    /// The class of self.
+    pub receiver: String,
+    pub location: Option<(String, u32)>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Variable {
+    pub name: String,
+    /// One of "attribute", "formal" or "local".
+    pub kind: &'static str,
+    pub value: String,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Response {
+    BreakpointSet(usize, Breakpoint),
+    BreakpointDeleted(usize),
+    Breakpoints(Vec<(usize, Breakpoint)>),
+    /// Frames from the innermost outwards.
+    Backtrace(Vec<FrameInfo>),
+    Variables(Vec<Variable>),
+    Value(String, String),
+    Error(String),
+}
+
+/// Where the debugger reads commands from and reports to.
+pub trait Frontend {
+    /// Report that the program stopped.
+    fn stopped(&mut self, stop: &Stop) -> io::Result<()>;
+
+    /// Read the next command, or None once there are no more.
+    fn command(&mut self) -> io::Result<Option<Command>>;
+
+    /// Report the outcome of a command that leaves the program stopped.
+    fn respond(&mut self, response: Response) -> io::Result<()>;
+
+    /// Report that the program resumes, or is ended, after a command.
+    fn resumed(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Mode {
+    Entry,
+    Continue,
+    // Stepping from a stop at some depth of the stack
+    StepInto(usize),
+    StepOver(usize),
+    StepOut(usize),
+}
+
+pub struct Debugger<'p> {
+    frontend: Box<dyn Frontend + 'p>,
+    breakpoints: Vec<(usize, Breakpoint)>,
+    next_id: usize,
+    mode: Mode,
+    /// The method breakpoint hit by the last dispatch, if any.
+    entered: Option<usize>,
+    /// The line last reached by each active frame, outermost first.
+    lines: Vec<Option<(&'p str, u32)>>,
+}
+
+impl<'p> Debugger<'p> {
+    /// Create a debugger that stops before the program starts.
+    pub fn new(frontend: Box<dyn Frontend + 'p>) -> Self {
+        Self {
+            frontend,
+            breakpoints: Vec::new(),
+            next_id: 1,
+            mode: Mode::Entry,
+            entered: None,
+            lines: Vec::new(),
+        }
+    }
+
+    fn line_breakpoint(&self, file: &str, line_num: u32) -> Option<usize> {
+        self.breakpoints
+            .iter()
+            .find_map(|(id, breakpoint)| match breakpoint {
+                Breakpoint::Line { file: f, line }
+                    if Path::new(file).ends_with(f) && *line == line_num =>
+                {
+                    Some(*id)
+                }
+                _ => None,
+            })
+    }
+
+    fn method_breakpoint(
+        &self,
+        interpreter: &Interpreter<'p>,
+        call: &Call,
+    ) -> Option<usize> {
+        let owner = &interpreter.class(call.owner).name;
+        let receiver = interpreter
+            .class_of(call.receiver)
+            .map(|class_id| interpreter.class(class_id).name.as_str());
+        self.breakpoints
+            .iter()
+            .find_map(|(id, breakpoint)| match breakpoint {
+                Breakpoint::Method { class, method }
+                    if method == call.method
+                        && (class == owner
+                            || Some(class.as_str()) == receiver) =>
+                {
+                    Some(*id)
+                }
+                _ => None,
+            })
+    }
+
+    // Report a stop and carry out commands until one resumes the program.
+    fn stop(
+        &mut self,
+        interpreter: &Interpreter<'p>,
+        expr: &Expression,
+        reason: StopReason,
+    ) -> Result<(), RuntimeError<'p>> {
+        let frames = interpreter.frames();
+        let depth = frames.len();
+        let stop = Stop {
+            reason,
+            file: expr.location.extra.to_string(),
+            line: expr.location.location_line(),
+            method: method_name(interpreter, &frames[depth - 1]),
+        };
+        self.frontend.stopped(&stop)?;
+
+        loop {
+            let response = match self.frontend.command()? {
+                Some(Command::Break(breakpoint)) => {
+                    let id = self.next_id;
+                    self.next_id += 1;
+                    self.breakpoints.push((id, breakpoint.clone()));
+                    Response::BreakpointSet(id, breakpoint)
+                }
+                Some(Command::Delete(id)) => {
+                    let count = self.breakpoints.len();
+                    self.breakpoints.retain(|(other, _)| *other != id);
+                    if self.breakpoints.len() < count {
+                        Response::BreakpointDeleted(id)
+                    } else {
+                        Response::Error(format!("No breakpoint number {id}."))
+                    }
+                }
+                Some(Command::Breakpoints) => {
+                    Response::Breakpoints(self.breakpoints.clone())
+                }
+                Some(Command::Backtrace) => {
+                    Response::Backtrace(backtrace(interpreter))
+                }
+                Some(Command::SelfAttributes) => {
+                    let self_value = &frames[depth - 1].self_value;
+                    let attributes = interpreter
+                        .attributes(self_value)
+                        .into_iter()
+                        .map(|(name, value)| Variable {
+                            name: name.to_string(),
+                            kind: "attribute",
+                            value: interpreter.describe(&value),
+                        })
+                        .collect();
+                    Response::Variables(attributes)
+                }
+                Some(Command::Locals) => {
+                    let frame = &frames[depth - 1];
+                    let locals = frame
+                        .locals
+                        .iter()
+                        .enumerate()
+                        .map(|(index, (name, value))| Variable {
+                            name: name.to_string(),
+                            kind: if index < frame.arity {
+                                "formal"
+                            } else {
+                                "local"
+                            },
+                            value: interpreter.describe(value),
+                        })
+                        .collect();
+                    Response::Variables(locals)
+                }
+                Some(Command::Print(name)) => match interpreter.variable(&name)
+                {
+                    Some(value) => {
+                        Response::Value(name, interpreter.describe(&value))
+                    }
+                    None => {
+                        Response::Error(format!("No variable {name} in scope."))
+                    }
+                },
+                Some(command) => {
+                    self.mode = match command {
+                        Command::StepInto => Mode::StepInto(depth),
+                        Command::StepOver => Mode::StepOver(depth),
+                        Command::StepOut => Mode::StepOut(depth),
+                        _ => Mode::Continue,
+                    };
+                    self.frontend.resumed()?;
+                    return match command {
+                        Command::Quit => Err(RuntimeError::without_location(
+                            RuntimeErrorKind::Interrupted,
+                        )),
+                        _ => Ok(()),
+                    };
+                }
+                None => {
+                    return Err(RuntimeError::without_location(
+                        RuntimeErrorKind::Interrupted,
+                    ))
+                }
+            };
+            self.frontend.respond(response)?;
+        }
+    }
+}
+
+impl<'p> Observer<'p> for Debugger<'p> {
+    // Stops happen on the first expression evaluated on a line, so that
+    // the other expressions on the line, and returning to it from a call,
+    // do not stop the program again.
+    fn expression(
+        &mut self,
+        interpreter: &Interpreter<'p>,
+        expr: &'p Expression<'p>,
+    ) -> Result<(), RuntimeError<'p>> {
+        let depth = interpreter.frames().len();
+        let position = (expr.location.extra, expr.location.location_line());
+        self.lines.resize(depth, None);
+        let new_line = self.lines[depth - 1] != Some(position);
+        self.lines[depth - 1] = Some(position);
+
+        let reason = match self.entered.take() {
+            Some(id) => Some(StopReason::Breakpoint(id)),
+            None => new_line
+                .then(|| self.line_breakpoint(position.0, position.1))
+                .flatten()
+                .map(StopReason::Breakpoint),
+        };
+        let reason = reason.or(match self.mode {
+            Mode::Entry => Some(StopReason::Entry),
+            Mode::Continue => None,
+            Mode::StepInto(from) => {
+                (new_line || depth < from).then_some(StopReason::Step)
+            }
+            Mode::StepOver(from) => (depth < from
+                || (depth == from && new_line))
+                .then_some(StopReason::Step),
+            Mode::StepOut(from) => (depth < from).then_some(StopReason::Step),
+        });
+        match reason {
+            Some(reason) => self.stop(interpreter, expr, reason),
+            None => Ok(()),
+        }
+    }
+
+    fn call(
+        &mut self,
+        interpreter: &Interpreter<'p>,
+        call: &Call,
+    ) -> Result<(), RuntimeError<'p>> {
+        if !call.builtin {
+            // The callee starts on a fresh line
+            self.lines.truncate(interpreter.frames().len());
+            self.entered = self.method_breakpoint(interpreter, call);
+        }
+        Ok(())
+    }
+}
+
+fn method_name(interpreter: &Interpreter, frame: &Frame) -> String {
+    format!("{}.{}", interpreter.class(frame.class).name, frame.method)
+}
+
+fn backtrace(interpreter: &Interpreter) -> Vec<FrameInfo> {
+    interpreter
+        .frames()
+        .iter()
+        .rev()
+        .map(|frame| FrameInfo {
+            method: method_name(interpreter, frame),
+            receiver: interpreter
+                .class_of(&frame.self_value)
+                .map(|class_id| interpreter.class(class_id).name.clone())
+                .unwrap_or_default(),
+            location: frame.location.map(|location| {
+                (location.extra.to_string(), location.location_line())
+            }),
+        })
+        .collect()
+}
@@ -0,0 +1,168 @@
+use super::*;
+use crate::lexer::lex_tokens;
+use crate::parser::parse_program;
+use crate::semant::check_program;
+
+const SOURCE: &str = "\
+class A inherits IO {
+  n : Int <- 5;
+  f(x : Int) : Int {
+    let y : Int <- x * 2 in
+      y + n
+  };
+};
+class Main inherits IO {
+  main() : Object {{
+    out_int((new A).f(3));
+    out_int((new A).f(4));
+    out_string(\"\\n\");
+  }};
+};
+";
+
+// Runs SOURCE under the debugger with some commands, returning what the
+// front end printed and the output of the program.
+fn debug(frontend: &str, commands: &str) -> (String, String) {
+    let (_, tokens) = lex_tokens(SOURCE, "dir/test.cl").unwrap();
+    let (_, mut program) = parse_program(&tokens).unwrap();
+    let hierarchy = check_program(&mut program).unwrap();
+    let mut transcript = Vec::new();
+    let mut output = Vec::new();
+    {
+        let frontend: Box<dyn Frontend> = match frontend {
+            "cli" => Box::new(Cli::new(
+                Box::new(commands.as_bytes()),
+                Box::new(&mut transcript),
+            )),
+            _ => Box::new(Json::new(
+                Box::new(commands.as_bytes()),
+                Box::new(&mut transcript),
+            )),
+        };
+        let mut interpreter = Interpreter::new(
+            &program,
+            &hierarchy,
+            Box::new("".as_bytes()),
+            Box::new(&mut output),
+        );
+        interpreter.set_observer(Box::new(Debugger::new(frontend)));
+        let _ = interpreter.run();
+    }
+    (
+        String::from_utf8(transcript).unwrap().replace("(cdb) ", ""),
+        String::from_utf8(output).unwrap(),
+    )
+}
+
+#[test]
+fn test_parse_commands() {
+    assert_eq!(
+        "b test.cl:12".parse(),
+        Ok(Command::Break(Breakpoint::Line {
+            file: "test.cl".to_string(),
+            line: 12
+        }))
+    );
+    assert_eq!(
+        "break A.f".parse(),
+        Ok(Command::Break(Breakpoint::Method {
+            class: "A".to_string(),
+            method: "f".to_string()
+        }))
+    );
+    assert_eq!("stepOver".parse(), Ok(Command::StepOver));
+    assert_eq!("p x".parse(), Ok(Command::Print("x".to_string())));
+    assert!("break f".parse::<Command>().is_err());
+    assert!("continue now".parse::<Command>().is_err());
+    assert!("delete one".parse::<Command>().is_err());
+}
+
+#[test]
+fn test_breakpoints() {
+    let (transcript, output) = debug(
+        "cli",
+        "break A.f\nbreak test.cl:5\nbreakpoints\nc\nc\ndelete 1\nc\nc\n",
+    );
+    assert_eq!(
+        transcript,
+        "Program starts in Main.main at dir/test.cl:9
+Breakpoint 1 at A.f
+Breakpoint 2 at test.cl:5
+1   A.f
+2   test.cl:5
+Breakpoint 1, A.f at dir/test.cl:4
+Breakpoint 2, A.f at dir/test.cl:5
+Deleted breakpoint 1
+Breakpoint 2, A.f at dir/test.cl:5
+"
+    );
+    assert_eq!(output, "1113\n");
+}
+
+#[test]
+fn test_stepping() {
+    let (transcript, _) = debug("cli", "s\ns\ns\n\nfinish\nn\nn\nn\n");
+    assert_eq!(
+        transcript,
+        "Program starts in Main.main at dir/test.cl:9
+Main.main at dir/test.cl:10
+A.<init> at dir/test.cl:2
+A.f at dir/test.cl:4
+A.f at dir/test.cl:5
+Main.main at dir/test.cl:10
+Main.main at dir/test.cl:11
+Main.main at dir/test.cl:12
+"
+    );
+}
+
+#[test]
+fn test_inspection() {
+    let (transcript, _) = debug(
+        "cli",
+        "break test.cl:5\nc\nbt\nlocals\nself\np y\np n\np self\np z\n\
+         help me\nq\n",
+    );
+    assert_eq!(
+        transcript,
+        "Program starts in Main.main at dir/test.cl:9
+Breakpoint 1 at test.cl:5
+Breakpoint 1, A.f at dir/test.cl:5
+#0  A.f (self: A) at dir/test.cl:5
+#1  Main.main (self: Main) at dir/test.cl:10
+x = 3
+y = 6
+n = 5
+y = 6
+n = 5
+self = <A object>
+No variable z in scope.
+Invalid command: help me.
+"
+    );
+}
+
+#[test]
+fn test_json_protocol() {
+    let (transcript, _) = debug(
+        "json",
+        "{\"id\": 1, \"command\": \"break\", \"argument\": \"A.f\"}\n\
+         {\"id\": 2, \"command\": \"continue\"}\n\
+         {\"id\": 3, \"command\": \"locals\"}\n\
+         {\"id\": 4, \"command\": \"delete\", \"argument\": 7}\n\
+         {\"command\": \"stepOut\"}\n",
+    );
+    assert_eq!(
+        transcript.lines().collect::<Vec<_>>(),
+        [
+            r#"{"event":"stopped","file":"dir/test.cl","line":9,"method":"Main.main","reason":"entry"}"#,
+            r#"{"body":{"breakpoint":{"id":1,"location":"A.f"}},"id":1,"success":true}"#,
+            r#"{"body":{},"id":2,"success":true}"#,
+            r#"{"breakpoint":1,"event":"stopped","file":"dir/test.cl","line":4,"method":"A.f","reason":"breakpoint"}"#,
+            r#"{"body":{"variables":[{"kind":"formal","name":"x","value":"3"}]},"id":3,"success":true}"#,
+            r#"{"id":4,"message":"No breakpoint number 7.","success":false}"#,
+            r#"{"body":{},"id":null,"success":true}"#,
+            r#"{"event":"stopped","file":"dir/test.cl","line":10,"method":"Main.main","reason":"step"}"#,
+        ]
+    );
+}
@@ -0,0 +1,433 @@
+//! The class hierarchy collects every class of a program, including the
+//! basic classes, along with the signatures of their attributes and methods.
+//! It answers the inheritance questions needed by semantic analysis and
+//! later passes, and can be built from a parse tree on its own.
+
+use crate::ptree::{FeatureData, Program};
+use crate::semant::SemanticError;
+use crate::tokens::{Ident, Span, TypeId};
+use std::collections::HashMap;
+
+#[cfg(test)]
+mod tests;
+
+pub const OBJECT: &str = "Object";
+pub const IO: &str = "IO";
+pub const INT: &str = "Int";
+pub const STRING: &str = "String";
+pub const BOOL: &str = "Bool";
+pub const SELF_TYPE: &str = "SELF_TYPE";
+pub const SELF: &str = "self";
+pub const MAIN: &str = "Main";
+pub const MAIN_METHOD: &str = "main";
+
+const BASIC_CLASSES: [&str; 5] = [OBJECT, IO, INT, STRING, BOOL];
+
+// Name, formal parameters and return type of a method of a basic class.
+type BasicMethod<'s> = (&'s str, &'s [(&'s str, &'s str)], &'s str);
+
+pub struct AttributeSignature<'a> {
+    pub name: Ident,
+    pub type_id: TypeId,
+    pub location: Option<Span<'a>>,
+}
+
+pub struct MethodSignature<'a> {
+    pub name: Ident,
+    pub formals: Vec<(Ident, TypeId)>,
+    pub return_type: TypeId,
+    pub location: Option<Span<'a>>,
+}
+
+pub struct ClassInfo<'a> {
+    pub name: TypeId,
+    pub parent: Option<TypeId>,
+    pub attributes: Vec<AttributeSignature<'a>>,
+    pub methods: Vec<MethodSignature<'a>>,
+    pub location: Option<Span<'a>>,
+}
+
+impl<'a> ClassInfo<'a> {
+    fn basic(
+        name: &str,
+        parent: Option<&str>,
+        methods: &[BasicMethod],
+    ) -> Self {
+        let methods = methods
+            .iter()
+            .map(|(name, formals, return_type)| MethodSignature {
+                name: name.to_string(),
+                formals: formals
+                    .iter()
+                    .map(|(n, t)| (n.to_string(), t.to_string()))
+                    .collect(),
+                return_type: return_type.to_string(),
+                location: None,
+            })
+            .collect();
+        Self {
+            name: name.to_string(),
+            parent: parent.map(str::to_string),
+            attributes: Vec::new(),
+            methods,
+            location: None,
+        }
+    }
+
+    pub fn attribute(&self, name: &str) -> Option<&AttributeSignature<'a>> {
+        self.attributes.iter().find(|attr| attr.name == name)
+    }
+
+    pub fn method(&self, name: &str) -> Option<&MethodSignature<'a>> {
+        self.methods.iter().find(|method| method.name == name)
+    }
+}
+
+pub struct ClassHierarchy<'a> {
+    classes: HashMap<TypeId, ClassInfo<'a>>,
+    // Class names, basic classes first and then in order of definition
+    order: Vec<TypeId>,
+}
+
+impl<'a> ClassHierarchy<'a> {
+    /// Build the class hierarchy of a program, checking that classes are not
+    /// redefined and form a well-formed inheritance tree rooted at Object.
+    /// Only the first definition of a feature duplicated within a class is
+    /// recorded.
+    pub fn new(program: &Program<'a>) -> Result<Self, Vec<SemanticError<'a>>> {
+        let mut table = Self::basic_classes();
+        let mut errors = Vec::new();
+
+        for class in program.classes.iter() {
+            if BASIC_CLASSES.contains(&class.name.as_str())
+                || class.name == SELF_TYPE
+            {
+                errors.push(SemanticError::new(
+                    format!("Redefinition of basic class {}.", class.name),
+                    class.location,
+                ));
+                continue;
+            }
+            if table.classes.contains_key(&class.name) {
+                errors.push(SemanticError::new(
+                    format!("Class {} was previously defined.", class.name),
+                    class.location,
+                ));
+                continue;
+            }
+
+            let mut info = ClassInfo {
+                name: class.name.clone(),
+                parent: Some(class.super_class_name.clone()),
+                attributes: Vec::new(),
+                methods: Vec::new(),
+                location: Some(class.location),
+            };
+            for feature in class.features.iter() {
+                match &feature.data {
+                    FeatureData::Attribute(name, type_id, _) => {
+                        if info.attribute(name).is_some() {
+                            continue;
+                        }
+                        info.attributes.push(AttributeSignature {
+                            name: name.clone(),
+                            type_id: type_id.clone(),
+                            location: Some(feature.location),
+                        });
+                    }
+                    FeatureData::Method(name, return_type, formals, _) => {
+                        if info.method(name).is_some() {
+                            continue;
+                        }
+                        info.methods.push(MethodSignature {
+                            name: name.clone(),
+                            formals: formals
+                                .iter()
+                                .map(|f| (f.name.clone(), f.type_id.clone()))
+                                .collect(),
+                            return_type: return_type.clone(),
+                            location: Some(feature.location),
+                        });
+                    }
+                }
+            }
+            table.order.push(class.name.clone());
+            table.classes.insert(class.name.clone(), info);
+        }
+
+        for class in program.classes.iter() {
+            let parent = &class.super_class_name;
+            if [INT, STRING, BOOL, SELF_TYPE].contains(&parent.as_str()) {
+                errors.push(SemanticError::new(
+                    format!(
+                        "Class {} cannot inherit class {parent}.",
+                        class.name
+                    ),
+                    class.location,
+                ));
+            } else if !table.classes.contains_key(parent) {
+                errors.push(SemanticError::new(
+                    format!(
+                        "Class {} inherits from an undefined class {parent}.",
+                        class.name
+                    ),
+                    class.location,
+                ));
+            }
+        }
+
+        if errors.is_empty() {
+            for class in program.classes.iter() {
+                if table.in_cycle(&class.name) {
+                    errors.push(SemanticError::new(
+                        format!(
+                            "Class {0}, or an ancestor of {0}, is involved \
+                            in an inheritance cycle.",
+                            class.name
+                        ),
+                        class.location,
+                    ));
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(table)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// A hierarchy of classes that are known to form a well-formed
+    /// inheritance tree, such as those an IR module declares, basic classes
+    /// included, in order of definition. Nothing is checked.
+    pub fn from_classes(
+        classes: impl IntoIterator<Item = ClassInfo<'a>>,
+    ) -> Self {
+        let mut table = Self {
+            classes: HashMap::new(),
+            order: Vec::new(),
+        };
+        for info in classes {
+            table.order.push(info.name.clone());
+            table.classes.insert(info.name.clone(), info);
+        }
+        table
+    }
+
+    fn basic_classes() -> Self {
+        let basic = [
+            ClassInfo::basic(
+                OBJECT,
+                None,
+                &[
+                    ("abort", &[], OBJECT),
+                    ("type_name", &[], STRING),
+                    ("copy", &[], SELF_TYPE),
+                ],
+            ),
+            ClassInfo::basic(
+                IO,
+                Some(OBJECT),
+                &[
+                    ("out_string", &[("x", STRING)], SELF_TYPE),
+                    ("out_int", &[("x", INT)], SELF_TYPE),
+                    ("in_string", &[], STRING),
+                    ("in_int", &[], INT),
+                ],
+            ),
+            ClassInfo::basic(INT, Some(OBJECT), &[]),
+            ClassInfo::basic(
+                STRING,
+                Some(OBJECT),
+                &[
+                    ("length", &[], INT),
+                    ("concat", &[("s", STRING)], STRING),
+                    ("substr", &[("i", INT), ("l", INT)], STRING),
+                ],
+            ),
+            ClassInfo::basic(BOOL, Some(OBJECT), &[]),
+        ];
+        Self {
+            order: basic.iter().map(|info| info.name.clone()).collect(),
+            classes: basic
+                .into_iter()
+                .map(|info| (info.name.clone(), info))
+                .collect(),
+        }
+    }
+
+    fn in_cycle(&self, name: &str) -> bool {
+        let mut current = self.parent(name);
+        let mut steps = 0;
+        while let Some(class) = current {
+            if class == name || steps > self.classes.len() {
+                return true;
+            }
+            current = self.parent(class);
+            steps += 1;
+        }
+        false
+    }
+
+    /// Iterate over all classes, basic classes first and then program
+    /// classes in order of definition.
+    pub fn classes(&self) -> impl Iterator<Item = &ClassInfo<'a>> {
+        self.order.iter().map(|name| &self.classes[name])
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.classes.contains_key(name)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ClassInfo<'a>> {
+        self.classes.get(name)
+    }
+
+    pub fn parent(&self, name: &str) -> Option<&str> {
+        self.classes
+            .get(name)
+            .and_then(|info| info.parent.as_deref())
+    }
+
+    /// Iterate over a class and all its ancestors, up to Object.
+    pub fn ancestors<'t>(
+        &'t self,
+        name: &str,
+    ) -> impl Iterator<Item = &'t ClassInfo<'a>> {
+        let mut current = self.classes.get(name);
+        std::iter::from_fn(move || {
+            let info = current?;
+            current = info
+                .parent
+                .as_deref()
+                .and_then(|parent| self.classes.get(parent));
+            Some(info)
+        })
+    }
+
+    /// The classes that directly inherit from a class, in order of
+    /// definition.
+    pub fn subclasses(&self, name: &str) -> Vec<&ClassInfo<'a>> {
+        self.classes()
+            .filter(|info| info.parent.as_deref() == Some(name))
+            .collect()
+    }
+
+    /// All classes that inherit from a class, directly or indirectly, in
+    /// order of definition. The class itself is not included.
+    pub fn descendants(&self, name: &str) -> Vec<&ClassInfo<'a>> {
+        self.classes()
+            .filter(|info| {
+                info.name != name
+                    && self.ancestors(&info.name).any(|c| c.name == name)
+            })
+            .collect()
+    }
+
+    /// The number of ancestors of a class, Object having depth 0.
+    pub fn depth(&self, name: &str) -> Option<usize> {
+        self.contains(name)
+            .then(|| self.ancestors(name).count().saturating_sub(1))
+    }
+
+    /// Whether a type is either SELF_TYPE or the name of a known class.
+    pub fn is_valid_type(&self, type_id: &str) -> bool {
+        type_id == SELF_TYPE || self.contains(type_id)
+    }
+
+    /// Whether class `sub` is `sup` or inherits from it.
+    pub fn conforms(&self, sub: &str, sup: &str) -> bool {
+        self.ancestors(sub).any(|info| info.name == sup)
+    }
+
+    /// Whether type `sub` conforms to type `sup`, where SELF_TYPE refers to
+    /// `current_class`.
+    pub fn conforms_in(
+        &self,
+        sub: &str,
+        sup: &str,
+        current_class: &str,
+    ) -> bool {
+        match (sub == SELF_TYPE, sup == SELF_TYPE) {
+            (true, true) => true,
+            (false, true) => false,
+            (true, false) => self.conforms(current_class, sup),
+            (false, false) => self.conforms(sub, sup),
+        }
+    }
+
+    /// The least upper bound of two classes, that is, their closest common
+    /// ancestor.
+    pub fn lub(&self, class1: &str, class2: &str) -> TypeId {
+        self.ancestors(class1)
+            .find(|info| self.conforms(class2, &info.name))
+            .map(|info| info.name.clone())
+            .unwrap_or_else(|| OBJECT.to_string())
+    }
+
+    /// The least upper bound of two types, where SELF_TYPE refers to
+    /// `current_class`.
+    pub fn lub_in(
+        &self,
+        type1: &str,
+        type2: &str,
+        current_class: &str,
+    ) -> TypeId {
+        if type1 == SELF_TYPE && type2 == SELF_TYPE {
+            return SELF_TYPE.to_string();
+        }
+        let resolve = |t| if t == SELF_TYPE { current_class } else { t };
+        self.lub(resolve(type1), resolve(type2))
+    }
+
+    /// Find a method defined in a class or inherited from an ancestor.
+    pub fn lookup_method(
+        &self,
+        class: &str,
+        method: &str,
+    ) -> Option<&MethodSignature<'a>> {
+        self.ancestors(class).find_map(|info| info.method(method))
+    }
+
+    /// The class where the implementation of a method used by a class is
+    /// defined, which is either the class itself or its closest ancestor
+    /// defining the method.
+    pub fn method_owner(&self, class: &str, method: &str) -> Option<&str> {
+        self.ancestors(class)
+            .find(|info| info.method(method).is_some())
+            .map(|info| info.name.as_str())
+    }
+
+    /// Whether a class inheriting from a class defines a method, overriding
+    /// the implementation the class has.
+    pub fn is_overridden(&self, class: &str, method: &str) -> bool {
+        self.descendants(class)
+            .iter()
+            .any(|info| info.method(method).is_some())
+    }
+
+    /// The class defining the only implementation of a method that a
+    /// dispatch on an object of a class can run, as no descendant overrides
+    /// it, so that the dispatch can call it directly.
+    pub fn sole_implementation(
+        &self,
+        class: &str,
+        method: &str,
+    ) -> Option<&str> {
+        if self.is_overridden(class, method) {
+            return None;
+        }
+        self.method_owner(class, method)
+    }
+
+    /// Find an attribute defined in a class or inherited from an ancestor.
+    pub fn lookup_attribute(
+        &self,
+        class: &str,
+        attribute: &str,
+    ) -> Option<&AttributeSignature<'a>> {
+        self.ancestors(class)
+            .find_map(|info| info.attribute(attribute))
+    }
+}
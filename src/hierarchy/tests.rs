@@ -0,0 +1,244 @@
+use super::*;
+use crate::lexer::lex_tokens;
+use crate::parser::parse_program;
+use crate::tokens::Token;
+
+fn tokens(input: &str) -> Vec<Token<'_>> {
+    let (_, tokens) = lex_tokens(input, "").unwrap();
+    tokens
+}
+
+// A
+// ├── B
+// │   └── C
+// └── D
+const CLASSES: &str = "\
+    class A { a : Int; f() : Int { 0 }; g() : Object { 0 }; };\
+    class B inherits A { b : Int; f() : Int { 1 }; };\
+    class C inherits B { h() : Int { 2 }; };\
+    class D inherits A {};";
+
+macro_rules! hierarchy {
+    ($name: ident) => {
+        let tokens = tokens(CLASSES);
+        let (_, program) = parse_program(&tokens).unwrap();
+        let $name = ClassHierarchy::new(&program).ok().unwrap();
+    };
+}
+
+#[test]
+fn test_classes() {
+    hierarchy!(hierarchy);
+    let names: Vec<_> =
+        hierarchy.classes().map(|info| info.name.as_str()).collect();
+    assert_eq!(
+        names,
+        ["Object", "IO", "Int", "String", "Bool", "A", "B", "C", "D"]
+    );
+    assert!(hierarchy.contains("C"));
+    assert!(!hierarchy.contains("E"));
+    assert!(hierarchy.is_valid_type("SELF_TYPE"));
+    assert_eq!(hierarchy.parent("C"), Some("B"));
+    assert_eq!(hierarchy.parent("Object"), None);
+}
+
+#[test]
+fn test_bad_hierarchy() {
+    [
+        "class Object {};",
+        "class SELF_TYPE {};",
+        "class A {}; class A {};",
+        "class A inherits Bool {};",
+        "class A inherits B {};",
+        "class A inherits A {};",
+        "class A inherits C {}; class B inherits A {}; class C inherits B {};",
+        "class A inherits B {}; class B inherits C {}; class C inherits B {};",
+    ]
+    .iter()
+    .for_each(|input| {
+        let tokens = tokens(input);
+        let (_, program) = parse_program(&tokens).unwrap();
+        assert!(ClassHierarchy::new(&program).is_err(), "{input}")
+    })
+}
+
+#[test]
+fn test_ancestors() {
+    hierarchy!(hierarchy);
+    [
+        ("C", vec!["C", "B", "A", "Object"]),
+        ("D", vec!["D", "A", "Object"]),
+        ("IO", vec!["IO", "Object"]),
+        ("Object", vec!["Object"]),
+        ("E", vec![]),
+    ]
+    .iter()
+    .for_each(|(class, expected)| {
+        let ancestors: Vec<_> = hierarchy
+            .ancestors(class)
+            .map(|info| info.name.as_str())
+            .collect();
+        assert_eq!(ancestors, *expected)
+    })
+}
+
+#[test]
+fn test_subclasses() {
+    hierarchy!(hierarchy);
+    [
+        ("A", vec!["B", "D"], vec!["B", "C", "D"]),
+        ("B", vec!["C"], vec!["C"]),
+        ("C", vec![], vec![]),
+        (
+            "Object",
+            vec!["IO", "Int", "String", "Bool", "A"],
+            vec!["IO", "Int", "String", "Bool", "A", "B", "C", "D"],
+        ),
+    ]
+    .iter()
+    .for_each(|(class, subclasses, descendants)| {
+        let names = |classes: Vec<&ClassInfo>| -> Vec<String> {
+            classes.iter().map(|info| info.name.clone()).collect()
+        };
+        assert_eq!(names(hierarchy.subclasses(class)), *subclasses);
+        assert_eq!(names(hierarchy.descendants(class)), *descendants);
+    })
+}
+
+#[test]
+fn test_depth() {
+    hierarchy!(hierarchy);
+    [
+        ("Object", Some(0)),
+        ("String", Some(1)),
+        ("A", Some(1)),
+        ("B", Some(2)),
+        ("C", Some(3)),
+        ("E", None),
+    ]
+    .iter()
+    .for_each(|(class, depth)| assert_eq!(hierarchy.depth(class), *depth))
+}
+
+#[test]
+fn test_conforms() {
+    hierarchy!(hierarchy);
+    [
+        ("C", "A", true),
+        ("C", "C", true),
+        ("A", "C", false),
+        ("D", "B", false),
+        ("Int", "Object", true),
+        ("Object", "Int", false),
+        ("E", "Object", false),
+    ]
+    .iter()
+    .for_each(|(sub, sup, expected)| {
+        assert_eq!(hierarchy.conforms(sub, sup), *expected, "{sub} <= {sup}")
+    })
+}
+
+#[test]
+fn test_conforms_in() {
+    hierarchy!(hierarchy);
+    [
+        ("SELF_TYPE", "A", "B", true),
+        ("SELF_TYPE", "C", "B", false),
+        ("SELF_TYPE", "SELF_TYPE", "B", true),
+        ("B", "SELF_TYPE", "B", false),
+        ("C", "B", "A", true),
+    ]
+    .iter()
+    .for_each(|(sub, sup, current, expected)| {
+        assert_eq!(
+            hierarchy.conforms_in(sub, sup, current),
+            *expected,
+            "{sub} <= {sup} in {current}"
+        )
+    })
+}
+
+#[test]
+fn test_lub() {
+    hierarchy!(hierarchy);
+    [
+        ("C", "D", "A"),
+        ("C", "B", "B"),
+        ("B", "C", "B"),
+        ("C", "C", "C"),
+        ("Int", "A", "Object"),
+        ("Int", "String", "Object"),
+    ]
+    .iter()
+    .for_each(|(class1, class2, expected)| {
+        assert_eq!(hierarchy.lub(class1, class2), *expected)
+    })
+}
+
+#[test]
+fn test_lub_in() {
+    hierarchy!(hierarchy);
+    [
+        ("SELF_TYPE", "D", "C", "A"),
+        ("B", "SELF_TYPE", "C", "B"),
+        ("SELF_TYPE", "SELF_TYPE", "C", "SELF_TYPE"),
+    ]
+    .iter()
+    .for_each(|(type1, type2, current, expected)| {
+        assert_eq!(hierarchy.lub_in(type1, type2, current), *expected)
+    })
+}
+
+#[test]
+fn test_lookup_method() {
+    hierarchy!(hierarchy);
+    [
+        ("C", "f", Some("B"), Some("Int")),
+        ("C", "g", Some("A"), Some("Object")),
+        ("C", "h", Some("C"), Some("Int")),
+        ("D", "f", Some("A"), Some("Int")),
+        ("D", "copy", Some("Object"), Some("SELF_TYPE")),
+        ("IO", "out_string", Some("IO"), Some("SELF_TYPE")),
+        ("A", "h", None, None),
+    ]
+    .iter()
+    .for_each(|(class, method, owner, return_type)| {
+        assert_eq!(hierarchy.method_owner(class, method), *owner);
+        assert_eq!(
+            hierarchy
+                .lookup_method(class, method)
+                .map(|m| m.return_type.as_str()),
+            *return_type
+        );
+    })
+}
+
+#[test]
+fn test_sole_implementation() {
+    hierarchy!(hierarchy);
+    [
+        ("A", "f", None),
+        ("B", "f", Some("B")),
+        ("D", "f", Some("A")),
+        ("A", "g", Some("A")),
+        ("C", "h", Some("C")),
+        ("A", "h", None),
+        ("Object", "copy", Some("Object")),
+        ("IO", "out_string", Some("IO")),
+    ]
+    .iter()
+    .for_each(|(class, method, owner)| {
+        assert_eq!(hierarchy.sole_implementation(class, method), *owner);
+    });
+    assert!(hierarchy.is_overridden("A", "f"));
+    assert!(!hierarchy.is_overridden("B", "f"));
+}
+
+#[test]
+fn test_lookup_attribute() {
+    hierarchy!(hierarchy);
+    assert!(hierarchy.lookup_attribute("C", "a").is_some());
+    assert!(hierarchy.lookup_attribute("C", "b").is_some());
+    assert!(hierarchy.lookup_attribute("D", "b").is_none());
+    assert!(hierarchy.lookup_attribute("Object", "a").is_none());
+}
@@ -0,0 +1,263 @@
+//! Methods of the basic classes, which are implemented natively by the
+//! interpreter.
+
+use super::transcript::{Recorder, Replayer, Tape};
+use super::*;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Builtin {
+    Abort,
+    TypeName,
+    Copy,
+    OutString,
+    OutInt,
+    InString,
+    InInt,
+    Length,
+    Concat,
+    Substr,
+}
+
+impl Builtin {
+    pub fn find(class: &str, method: &str) -> Option<Self> {
+        match (class, method) {
+            (OBJECT, "abort") => Some(Self::Abort),
+            (OBJECT, "type_name") => Some(Self::TypeName),
+            (OBJECT, "copy") => Some(Self::Copy),
+            (IO, "out_string") => Some(Self::OutString),
+            (IO, "out_int") => Some(Self::OutInt),
+            (IO, "in_string") => Some(Self::InString),
+            (IO, "in_int") => Some(Self::InInt),
+            (STRING, "length") => Some(Self::Length),
+            (STRING, "concat") => Some(Self::Concat),
+            (STRING, "substr") => Some(Self::Substr),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Abort => "abort",
+            Self::TypeName => "type_name",
+            Self::Copy => "copy",
+            Self::OutString => "out_string",
+            Self::OutInt => "out_int",
+            Self::InString => "in_string",
+            Self::InInt => "in_int",
+            Self::Length => "length",
+            Self::Concat => "concat",
+            Self::Substr => "substr",
+        }
+    }
+}
+
+/// Parse an integer the way the reference runtime does: leading whitespace
+/// is skipped, then an optional sign and digits are read. Anything else
+/// yields 0.
+pub fn parse_int(line: &str) -> i32 {
+    let line = line.trim_start();
+    let (negative, digits) = match line.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, line.strip_prefix('+').unwrap_or(line)),
+    };
+    let value = digits
+        .chars()
+        .map_while(|c| c.to_digit(10))
+        .fold(0_i32, |acc, digit| {
+            acc.wrapping_mul(10).wrapping_add(digit as i32)
+        });
+    if negative {
+        value.wrapping_neg()
+    } else {
+        value
+    }
+}
+
+/// The standard input and output of a running program.
+pub struct Console<'p> {
+    input: Box<dyn BufRead + 'p>,
+    output: Box<dyn Write + 'p>,
+    /// Bytes written so far, and the most that may be written.
+    written: u64,
+    output_limit: Option<u64>,
+    tape: Option<Tape<'p>>,
+}
+
+impl<'p> Console<'p> {
+    pub fn new(
+        input: Box<dyn BufRead + 'p>,
+        output: Box<dyn Write + 'p>,
+    ) -> Self {
+        Self {
+            input,
+            output,
+            written: 0,
+            output_limit: None,
+            tape: None,
+        }
+    }
+
+    pub fn set_output_limit(&mut self, limit: Option<u64>) {
+        self.output_limit = limit;
+    }
+
+    /// Write a transcript of the input and output to another output.
+    pub fn record(&mut self, transcript: Box<dyn Write + 'p>) {
+        self.tape = Some(Tape::Record(Recorder::new(transcript)));
+    }
+
+    /// Take the input from a transcript instead of the input, and check
+    /// that the output is that of the transcript.
+    pub fn replay(&mut self, transcript: Transcript) {
+        self.tape = Some(Tape::Replay(Replayer::new(transcript)));
+    }
+
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        if let Some(Tape::Record(recorder)) = &mut self.tape {
+            recorder.flush()?;
+        }
+        self.output.flush()
+    }
+
+    /// Flush the output once the program has completed, and check that a
+    /// replayed transcript was followed to the end.
+    pub fn finish<'a>(&mut self) -> Result<(), RuntimeError<'a>> {
+        self.flush()?;
+        if let Some(Tape::Replay(replayer)) = &mut self.tape {
+            replayer.finish()?;
+        }
+        Ok(())
+    }
+
+    // Write to the output, as much as the output limit allows.
+    fn write<'a>(&mut self, text: &str) -> Result<(), RuntimeError<'a>> {
+        let allowed = match self.output_limit {
+            Some(limit) => text.len().min((limit - self.written) as usize),
+            None => text.len(),
+        };
+        // The output is cut at the limit, even within a character.
+        self.output.write_all(&text.as_bytes()[..allowed])?;
+        self.written += allowed as u64;
+        let mut end = allowed;
+        while !text.is_char_boundary(end) {
+            end -= 1;
+        }
+        match &mut self.tape {
+            Some(Tape::Record(recorder)) => recorder.write(&text[..end]),
+            Some(Tape::Replay(replayer)) => replayer.write(&text[..end])?,
+            None => {}
+        }
+        match self.output_limit {
+            Some(limit) if allowed < text.len() => {
+                self.output.flush()?;
+                Err(RuntimeErrorKind::OutputLimit(limit).into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Read a line from the input, without the line terminator. Output is
+    // flushed first so that prompts are shown before waiting for input.
+    fn read_line<'a>(&mut self) -> Result<String, RuntimeError<'a>> {
+        self.flush()?;
+        if let Some(Tape::Replay(replayer)) = &mut self.tape {
+            return replayer.read();
+        }
+        let mut line = String::new();
+        self.input.read_line(&mut line)?;
+        if line.ends_with('\n') {
+            line.pop();
+            if line.ends_with('\r') {
+                line.pop();
+            }
+        }
+        if let Some(Tape::Record(recorder)) = &mut self.tape {
+            recorder.read(&line)?;
+        }
+        Ok(line)
+    }
+}
+
+impl Builtin {
+    /// Call a builtin method on a receiver whose dynamic class is
+    /// `class_name`. The receiver must not be void.
+    pub fn call<'a>(
+        self,
+        receiver: Value,
+        args: Vec<Value>,
+        class_name: &str,
+        console: &mut Console,
+    ) -> Result<Value, RuntimeError<'a>> {
+        let mut args = args.into_iter();
+        match self {
+            Self::Abort => {
+                console.flush()?;
+                Err(RuntimeError::without_location(RuntimeErrorKind::Abort(
+                    class_name.to_string(),
+                )))
+            }
+            Self::TypeName => Ok(Value::Str(class_name.into())),
+            Self::Copy => match &receiver {
+                Value::Object(object) => {
+                    let object = object.borrow();
+                    Ok(Value::new_object(
+                        object.class,
+                        object.attributes.clone(),
+                    ))
+                }
+                _ => Ok(receiver),
+            },
+            Self::OutString => {
+                if let Some(Value::Str(string)) = args.next() {
+                    console.write(&string)?;
+                }
+                Ok(receiver)
+            }
+            Self::OutInt => {
+                if let Some(Value::Int(integer)) = args.next() {
+                    console.write(&integer.to_string())?;
+                }
+                Ok(receiver)
+            }
+            Self::InString => {
+                let line = console.read_line()?;
+                Ok(Value::Str(line.into()))
+            }
+            Self::InInt => {
+                let line = console.read_line()?;
+                Ok(Value::Int(parse_int(&line)))
+            }
+            Self::Length => match &receiver {
+                Value::Str(string) => Ok(Value::Int(string.len() as i32)),
+                _ => panic!("length called on a non-String"),
+            },
+            Self::Concat => match (&receiver, args.next()) {
+                (Value::Str(string), Some(Value::Str(other))) => {
+                    Ok(Value::Str(format!("{string}{other}").into()))
+                }
+                _ => panic!("concat called with non-String"),
+            },
+            Self::Substr => match (&receiver, args.next(), args.next()) {
+                (
+                    Value::Str(string),
+                    Some(Value::Int(start)),
+                    Some(Value::Int(length)),
+                ) => {
+                    let (start, length) = (start as usize, length as usize);
+                    string
+                        .len()
+                        .checked_sub(start)
+                        .filter(|available| length <= *available)
+                        .and_then(|_| string.get(start..start + length))
+                        .map(|substr| Value::Str(substr.into()))
+                        .ok_or_else(|| {
+                            RuntimeError::without_location(
+                                RuntimeErrorKind::SubstrOutOfRange,
+                            )
+                        })
+                }
+                _ => panic!("substr called with wrong arguments"),
+            },
+        }
+    }
+}
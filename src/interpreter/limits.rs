@@ -0,0 +1,103 @@
+//! Bounds on the resources used by a running program, so that programs that
+//! do not terminate, or grow without bound, can be stopped.
+
+use super::*;
+
+/// The stack size of the threads `coolc` runs programs on. Cool programs
+/// tend to recurse deeply, so it is much larger than a default thread's.
+pub const STACK_SIZE: usize = 1 << 30;
+
+// The fraction of the stack kept free for the deepest frames, whose bodies
+// nest expressions far more than typical ones.
+const STACK_MARGIN: usize = 16;
+
+/// Limits on the resources a program may use. Nothing is limited by
+/// default.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Limits {
+    /// Evaluation steps: expressions evaluated by the interpreter, or
+    /// instructions executed by the virtual machine.
+    pub steps: Option<u64>,
+    /// Objects created, not counting values of the basic classes.
+    pub objects: Option<u64>,
+    /// Frames active at once, for method calls and attribute
+    /// initialisation.
+    pub call_depth: Option<usize>,
+    /// Bytes written to the output.
+    pub output_bytes: Option<u64>,
+    /// Bytes of stack the interpreter may use, from its outermost frame.
+    /// Calls are refused as overflowing the stack once the rest would not
+    /// leave a safe margin.
+    pub stack_bytes: Option<usize>,
+}
+
+impl Limits {
+    /// Bound the stack the interpreter uses by `stack_size`, the size of
+    /// the stack of the thread it runs on, so that runaway recursion is
+    /// reported rather than overflowing the stack. The virtual machine
+    /// keeps its frames on the heap and is not bounded by it.
+    pub fn with_stack_size(self, stack_size: usize) -> Self {
+        Self {
+            stack_bytes: Some(stack_size),
+            ..self
+        }
+    }
+}
+
+/// Usage of the resources counted against limits. Output is counted by the
+/// console.
+#[derive(Debug, Default)]
+pub(crate) struct Meter {
+    limits: Limits,
+    steps: u64,
+    objects: u64,
+    /// The address of the stack when the outermost frame was entered.
+    stack_base: Option<usize>,
+}
+
+impl Meter {
+    pub fn new(limits: Limits) -> Self {
+        Self {
+            limits,
+            ..Self::default()
+        }
+    }
+
+    pub fn step(&mut self) -> Result<(), RuntimeErrorKind> {
+        self.steps += 1;
+        match self.limits.steps {
+            Some(max) if self.steps > max => {
+                Err(RuntimeErrorKind::StepLimit(max))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn allocate(&mut self) -> Result<(), RuntimeErrorKind> {
+        self.objects += 1;
+        match self.limits.objects {
+            Some(max) if self.objects > max => {
+                Err(RuntimeErrorKind::ObjectLimit(max))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Check that a frame can be pushed onto `depth` active ones.
+    pub fn enter(&mut self, depth: usize) -> Result<(), RuntimeErrorKind> {
+        if let Some(max) = self.limits.call_depth {
+            if depth >= max {
+                return Err(RuntimeErrorKind::CallDepthLimit(max));
+            }
+        }
+        if let Some(size) = self.limits.stack_bytes {
+            let marker = 0u8;
+            let address = std::hint::black_box(&marker) as *const u8 as usize;
+            let base = *self.stack_base.get_or_insert(address);
+            if base.abs_diff(address) + size / STACK_MARGIN > size {
+                return Err(RuntimeErrorKind::StackOverflow);
+            }
+        }
+        Ok(())
+    }
+}
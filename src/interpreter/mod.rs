@@ -0,0 +1,848 @@
+//! A tree-walking interpreter that runs Cool programs directly from the parse
+//! tree. Programs are expected to have passed semantic analysis.
+
+mod builtins;
+mod limits;
+mod observer;
+mod transcript;
+mod value;
+
+pub use self::builtins::{parse_int, Builtin, Console};
+pub(crate) use self::limits::Meter;
+pub use self::limits::{Limits, STACK_SIZE};
+pub use self::observer::{Call, Observer};
+pub use self::transcript::{Event, Transcript};
+pub use self::value::*;
+use crate::hierarchy::*;
+use crate::ptree::*;
+use crate::tokens::Span;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::io::{BufRead, Write};
+use ExpressionData::*;
+
+#[cfg(test)]
+mod tests;
+
+#[derive(Debug)]
+pub enum RuntimeErrorKind {
+    DispatchOnVoid(String),
+    StaticDispatchOnVoid(String),
+    CaseOnVoid,
+    NoMatchingBranch(String),
+    DivisionByZero,
+    SubstrOutOfRange,
+    Abort(String),
+    Io(std::io::Error),
+    /// Execution was stopped from outside the program, e.g. by a debugger.
+    Interrupted,
+    StepLimit(u64),
+    ObjectLimit(u64),
+    CallDepthLimit(usize),
+    OutputLimit(u64),
+    /// The program did not read or write what a replayed transcript says.
+    TranscriptMismatch(String),
+    /// The interpreter ran out of stack before any call depth limit was
+    /// reached.
+    StackOverflow,
+}
+
+impl RuntimeErrorKind {
+    /// The process exit status for each kind of failure. Statuses 1 to 4 are
+    /// used by the compiler front end, and 19 by compiled programs that run
+    /// out of memory.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::DispatchOnVoid(_) => 5,
+            Self::StaticDispatchOnVoid(_) => 6,
+            Self::CaseOnVoid => 7,
+            Self::NoMatchingBranch(_) => 8,
+            Self::DivisionByZero => 9,
+            Self::SubstrOutOfRange => 10,
+            Self::Abort(_) => 11,
+            Self::Io(_) => 12,
+            Self::Interrupted => 13,
+            Self::StepLimit(_) => 14,
+            Self::ObjectLimit(_) => 15,
+            Self::CallDepthLimit(_) => 16,
+            Self::OutputLimit(_) => 17,
+            Self::TranscriptMismatch(_) => 18,
+            Self::StackOverflow => 20,
+        }
+    }
+
+    // Whether the error is caused by a particular expression, rather than
+    // by the program as a whole or from outside it.
+    pub(crate) fn is_locatable(&self) -> bool {
+        !matches!(
+            self,
+            Self::Abort(_)
+                | Self::Io(_)
+                | Self::Interrupted
+                | Self::StepLimit(_)
+                | Self::ObjectLimit(_)
+                | Self::CallDepthLimit(_)
+                | Self::OutputLimit(_)
+                | Self::StackOverflow
+        )
+    }
+}
+
+// Messages follow those of the runtime system used in the Compilers course.
+impl Display for RuntimeErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::DispatchOnVoid(method) => {
+                write!(f, "Dispatch to void calling method {method}.")
+            }
+            Self::StaticDispatchOnVoid(method) => {
+                write!(f, "Static dispatch to void calling method {method}.")
+            }
+            Self::CaseOnVoid => write!(f, "Match on void in case statement."),
+            Self::NoMatchingBranch(class) => {
+                write!(f, "No match in case statement for Class {class}.")
+            }
+            Self::DivisionByZero => write!(f, "Division by zero."),
+            Self::SubstrOutOfRange => {
+                write!(f, "Index out of range in substr.")
+            }
+            Self::Abort(class) => write!(f, "Abort called from class {class}"),
+            Self::Io(err) => write!(f, "I/O error: {err}."),
+            Self::Interrupted => write!(f, "Execution interrupted."),
+            Self::StepLimit(max) => write!(f, "Step limit of {max} exceeded."),
+            Self::ObjectLimit(max) => {
+                write!(f, "Object limit of {max} exceeded.")
+            }
+            Self::CallDepthLimit(max) => {
+                write!(f, "Call depth limit of {max} exceeded.")
+            }
+            Self::OutputLimit(max) => {
+                write!(f, "Output limit of {max} bytes exceeded.")
+            }
+            Self::TranscriptMismatch(message) => {
+                write!(f, "Transcript mismatch: {message}.")
+            }
+            Self::StackOverflow => write!(f, "Stack overflow."),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct RuntimeError<'a> {
+    pub kind: RuntimeErrorKind,
+    /// The expression that failed. Calls to abort(), I/O errors,
+    /// interruptions and exceeded limits are not reported with a location.
+    pub location: Option<Span<'a>>,
+}
+
+impl<'a> RuntimeError<'a> {
+    pub fn new(kind: RuntimeErrorKind, location: Span<'a>) -> Self {
+        Self {
+            kind,
+            location: Some(location),
+        }
+    }
+
+    pub fn without_location(kind: RuntimeErrorKind) -> Self {
+        Self {
+            kind,
+            location: None,
+        }
+    }
+
+    pub fn exit_code(&self) -> i32 {
+        self.kind.exit_code()
+    }
+
+    // Errors raised by builtin methods are located at the dispatch that
+    // called them, which is the innermost expression with a location.
+    pub(crate) fn or_at(mut self, location: Span<'a>) -> Self {
+        if self.kind.is_locatable() && self.location.is_none() {
+            self.location = Some(location);
+        }
+        self
+    }
+}
+
+impl Display for RuntimeError<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.location {
+            Some(location) => {
+                let filename = location.extra;
+                let line_num = location.location_line();
+                write!(f, "{filename}:{line_num}: {}", self.kind)
+            }
+            None => write!(f, "{}", self.kind),
+        }
+    }
+}
+
+impl From<std::io::Error> for RuntimeError<'_> {
+    fn from(err: std::io::Error) -> Self {
+        Self::without_location(RuntimeErrorKind::Io(err))
+    }
+}
+
+impl From<RuntimeErrorKind> for RuntimeError<'_> {
+    fn from(kind: RuntimeErrorKind) -> Self {
+        Self::without_location(kind)
+    }
+}
+
+type EvalResult<'a> = Result<Value, RuntimeError<'a>>;
+type ObserverResult<'a> = Result<(), RuntimeError<'a>>;
+
+#[derive(Clone)]
+pub enum Method<'p> {
+    Builtin(Builtin),
+    Defined(&'p [Formal<'p>], &'p Expression<'p>),
+}
+
+#[derive(Clone)]
+pub struct AttributeSlot<'p> {
+    pub name: &'p str,
+    pub type_id: &'p str,
+    pub init: Option<&'p Expression<'p>>,
+}
+
+pub struct RuntimeClass<'p> {
+    pub name: String,
+    pub parent: Option<ClassId>,
+    /// Attributes of the class and its ancestors, in inheritance order.
+    pub attributes: Vec<AttributeSlot<'p>>,
+    /// Methods of the class and its ancestors, along with the class that
+    /// provides the implementation.
+    pub methods: HashMap<&'p str, (ClassId, Method<'p>)>,
+}
+
+impl RuntimeClass<'_> {
+    fn attribute_index(&self, name: &str) -> Option<usize> {
+        self.attributes.iter().position(|attr| attr.name == name)
+    }
+}
+
+/// The activation of a method, or of the attribute initialisers of a new
+/// object.
+pub struct Frame<'p> {
+    pub self_value: Value,
+    /// Formal parameters followed by the let and case bindings in scope.
+    pub locals: Vec<(&'p str, Value)>,
+    /// The class that provides the method.
+    pub class: ClassId,
+    /// The method name, or `<init>` for attribute initialisers.
+    pub method: &'p str,
+    /// Number of formal parameters at the start of `locals`.
+    pub arity: usize,
+    /// The expression being evaluated.
+    pub location: Option<Span<'p>>,
+}
+
+impl<'p> Frame<'p> {
+    fn new(
+        self_value: Value,
+        locals: Vec<(&'p str, Value)>,
+        class: ClassId,
+        method: &'p str,
+    ) -> Self {
+        let arity = locals.len();
+        Self {
+            self_value,
+            locals,
+            class,
+            method,
+            arity,
+            location: None,
+        }
+    }
+}
+
+pub struct Interpreter<'p> {
+    classes: Vec<RuntimeClass<'p>>,
+    class_ids: HashMap<String, ClassId>,
+    frames: Vec<Frame<'p>>,
+    console: Console<'p>,
+    meter: Meter,
+    observer: Option<Box<dyn Observer<'p> + 'p>>,
+}
+
+impl<'p> Interpreter<'p> {
+    pub fn new(
+        program: &'p Program<'p>,
+        hierarchy: &ClassHierarchy,
+        input: Box<dyn BufRead + 'p>,
+        output: Box<dyn Write + 'p>,
+    ) -> Self {
+        let mut interpreter = Self {
+            classes: Vec::new(),
+            class_ids: HashMap::new(),
+            frames: Vec::new(),
+            console: Console::new(input, output),
+            meter: Meter::default(),
+            observer: None,
+        };
+        interpreter.load_classes(program, hierarchy);
+        interpreter
+    }
+
+    // Classes are loaded parents first, so that each class can start from a
+    // copy of the attributes and methods of its parent.
+    fn load_classes(
+        &mut self,
+        program: &'p Program<'p>,
+        hierarchy: &ClassHierarchy,
+    ) {
+        let definitions: HashMap<&str, &'p Class<'p>> = program
+            .classes
+            .iter()
+            .map(|class| (class.name.as_str(), class))
+            .collect();
+        let mut infos: Vec<&ClassInfo> = hierarchy.classes().collect();
+        infos.sort_by_key(|info| hierarchy.depth(&info.name));
+
+        for info in infos {
+            let class_id = self.classes.len();
+            let parent = info.parent.as_ref().map(|name| self.class_ids[name]);
+            let (mut attributes, mut methods) = match parent {
+                Some(parent_id) => {
+                    let parent = &self.classes[parent_id];
+                    let methods = parent
+                        .methods
+                        .iter()
+                        .map(|(name, (owner, method))| {
+                            (*name, (*owner, method.clone()))
+                        })
+                        .collect();
+                    (parent.attributes.clone(), methods)
+                }
+                None => (Vec::new(), HashMap::new()),
+            };
+
+            if let Some(class) = definitions.get(info.name.as_str()) {
+                for feature in class.features.iter() {
+                    match &feature.data {
+                        FeatureData::Attribute(name, type_id, init) => {
+                            attributes.push(AttributeSlot {
+                                name,
+                                type_id,
+                                init: init.as_ref(),
+                            })
+                        }
+                        FeatureData::Method(name, _, formals, body) => {
+                            let method = Method::Defined(formals, body);
+                            methods.insert(name, (class_id, method));
+                        }
+                    }
+                }
+            } else {
+                for method in info.methods.iter() {
+                    if let Some(builtin) =
+                        Builtin::find(&info.name, &method.name)
+                    {
+                        methods.insert(
+                            builtin.name(),
+                            (class_id, Method::Builtin(builtin)),
+                        );
+                    }
+                }
+            }
+
+            self.class_ids.insert(info.name.clone(), class_id);
+            self.classes.push(RuntimeClass {
+                name: info.name.clone(),
+                parent,
+                attributes,
+                methods,
+            });
+        }
+    }
+
+    /// Stop the program with an error once it exceeds some limits.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.meter = Meter::new(limits);
+        self.console.set_output_limit(limits.output_bytes);
+    }
+
+    /// Write a transcript of the input read and the output written.
+    pub fn record(&mut self, transcript: Box<dyn Write + 'p>) {
+        self.console.record(transcript);
+    }
+
+    /// Read the input of a transcript, and stop with an error if the output
+    /// differs from that of the transcript.
+    pub fn replay(&mut self, transcript: Transcript) {
+        self.console.replay(transcript);
+    }
+
+    /// Have an observer notified of the progress of evaluation.
+    pub fn set_observer(&mut self, observer: Box<dyn Observer<'p> + 'p>) {
+        self.observer = Some(observer);
+    }
+
+    /// Run a program by creating an object of class Main and calling its
+    /// main method.
+    pub fn run(&mut self) -> EvalResult<'p> {
+        self.call(MAIN, MAIN_METHOD)
+    }
+
+    /// Create an object of a class and call one of its methods, which takes
+    /// no arguments. Output is flushed once the method returns.
+    pub fn call(&mut self, class_name: &str, method: &str) -> EvalResult<'p> {
+        let object = self.instantiate(self.class_ids[class_name])?;
+        let result = self.dispatch(object, None, method, Vec::new());
+        self.console.flush()?;
+        let value = result?;
+        self.console.finish()?;
+        Ok(value)
+    }
+
+    pub fn class_id(&self, name: &str) -> Option<ClassId> {
+        self.class_ids.get(name).copied()
+    }
+
+    pub fn class(&self, class_id: ClassId) -> &RuntimeClass<'p> {
+        &self.classes[class_id]
+    }
+
+    /// The dynamic class of a value. Void has no class.
+    pub fn class_of(&self, value: &Value) -> Option<ClassId> {
+        match value {
+            Value::Void => None,
+            Value::Int(_) => self.class_id(INT),
+            Value::Bool(_) => self.class_id(BOOL),
+            Value::Str(_) => self.class_id(STRING),
+            Value::Object(object) => Some(object.borrow().class),
+        }
+    }
+
+    /// Active frames, the innermost last.
+    pub fn frames(&self) -> &[Frame<'p>] {
+        &self.frames
+    }
+
+    /// The value of a variable in the innermost frame, if it is in scope.
+    pub fn variable(&self, name: &str) -> Option<Value> {
+        let frame = self.frames.last()?;
+        if name == SELF {
+            return Some(frame.self_value.clone());
+        }
+        if let Some((_, value)) =
+            frame.locals.iter().rev().find(|(local, _)| *local == name)
+        {
+            return Some(value.clone());
+        }
+        match &frame.self_value {
+            Value::Object(object) => {
+                let object = object.borrow();
+                let index = self.classes[object.class].attribute_index(name)?;
+                Some(object.attributes[index].clone())
+            }
+            _ => None,
+        }
+    }
+
+    /// Names and values of the attributes of an object, in inheritance
+    /// order. Basic values and void have none.
+    pub fn attributes(&self, value: &Value) -> Vec<(&'p str, Value)> {
+        match value {
+            Value::Object(object) => {
+                let object = object.borrow();
+                self.classes[object.class]
+                    .attributes
+                    .iter()
+                    .map(|attr| attr.name)
+                    .zip(object.attributes.iter().cloned())
+                    .collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// A value as shown to users: objects are shown by class, since their
+    /// identity means little.
+    pub fn describe(&self, value: &Value) -> String {
+        match (value, self.class_of(value)) {
+            (Value::Object(_), Some(class_id)) => {
+                format!("<{} object>", self.classes[class_id].name)
+            }
+            _ => value.to_string(),
+        }
+    }
+
+    // Hand the interpreter to the observer, if there is one. The observer
+    // is taken out while it runs so that it can inspect the interpreter.
+    fn notify(
+        &mut self,
+        f: impl FnOnce(&mut dyn Observer<'p>, &Self) -> ObserverResult<'p>,
+    ) -> ObserverResult<'p> {
+        match self.observer.take() {
+            Some(mut observer) => {
+                let result = f(observer.as_mut(), self);
+                self.observer = Some(observer);
+                result
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn frame(&self) -> &Frame<'p> {
+        self.frames.last().expect("no active frame")
+    }
+
+    fn frame_mut(&mut self) -> &mut Frame<'p> {
+        self.frames.last_mut().expect("no active frame")
+    }
+
+    // Create an object of a class, with attributes set to their default
+    // values and then initialised in inheritance order.
+    fn instantiate(&mut self, class_id: ClassId) -> EvalResult<'p> {
+        let class = &self.classes[class_id];
+        match class.name.as_str() {
+            INT | BOOL | STRING => return Ok(Value::default_for(&class.name)),
+            _ => {}
+        }
+        self.meter.allocate()?;
+        let defaults = class
+            .attributes
+            .iter()
+            .map(|attr| Value::default_for(attr.type_id))
+            .collect();
+        let object = Value::new_object(class_id, defaults);
+
+        let initialisers: Vec<(usize, &'p Expression<'p>)> = class
+            .attributes
+            .iter()
+            .enumerate()
+            .filter_map(|(index, attr)| attr.init.map(|expr| (index, expr)))
+            .collect();
+        if self.observer.is_some() {
+            self.notify(|observer, interpreter| {
+                observer.allocated(interpreter, &object)
+            })?;
+        }
+        if !initialisers.is_empty() {
+            self.meter.enter(self.frames.len())?;
+            self.frames.push(Frame::new(
+                object.clone(),
+                Vec::new(),
+                class_id,
+                "<init>",
+            ));
+            for (index, expr) in initialisers {
+                let value = self.eval(expr)?;
+                if let Value::Object(obj) = &object {
+                    obj.borrow_mut().attributes[index] = value;
+                }
+            }
+            self.frames.pop();
+        }
+        Ok(object)
+    }
+
+    fn dispatch(
+        &mut self,
+        receiver: Value,
+        static_class: Option<ClassId>,
+        method_name: &str,
+        args: Vec<Value>,
+    ) -> EvalResult<'p> {
+        let class_id = match static_class.or_else(|| self.class_of(&receiver)) {
+            Some(class_id) if !receiver.is_void() => class_id,
+            _ => {
+                let method_name = method_name.to_string();
+                let kind = match static_class {
+                    Some(_) => RuntimeErrorKind::StaticDispatchOnVoid,
+                    None => RuntimeErrorKind::DispatchOnVoid,
+                };
+                return Err(RuntimeError::without_location(kind(method_name)));
+            }
+        };
+        let (&method_name, (owner, method)) = self.classes[class_id]
+            .methods
+            .get_key_value(method_name)
+            .expect("undefined method");
+        let (owner, method) = (*owner, method.clone());
+        if self.observer.is_some() {
+            let call = Call {
+                receiver: &receiver,
+                owner,
+                method: method_name,
+                args: &args,
+                builtin: matches!(method, Method::Builtin(_)),
+            };
+            self.notify(|observer, interpreter| {
+                observer.call(interpreter, &call)
+            })?;
+        }
+        let value = match method {
+            Method::Builtin(builtin) => {
+                self.call_builtin(builtin, receiver, args)?
+            }
+            Method::Defined(formals, body) => {
+                let locals = formals
+                    .iter()
+                    .map(|formal| formal.name.as_str())
+                    .zip(args)
+                    .collect();
+                self.meter.enter(self.frames.len())?;
+                self.frames.push(Frame::new(
+                    receiver,
+                    locals,
+                    owner,
+                    method_name,
+                ));
+                let result = self.eval(body);
+                self.frames.pop();
+                result?
+            }
+        };
+        if self.observer.is_some() {
+            self.notify(|observer, interpreter| {
+                observer.returned(interpreter, &value)
+            })?;
+        }
+        Ok(value)
+    }
+
+    fn call_builtin(
+        &mut self,
+        builtin: Builtin,
+        receiver: Value,
+        args: Vec<Value>,
+    ) -> EvalResult<'p> {
+        let copies_object =
+            builtin == Builtin::Copy && matches!(receiver, Value::Object(_));
+        if copies_object {
+            self.meter.allocate()?;
+        }
+        // The class of the receiver, not the static dispatch class
+        let class_id = self.class_of(&receiver).expect("non-void");
+        let class_name = &self.classes[class_id].name;
+        let value =
+            builtin.call(receiver, args, class_name, &mut self.console)?;
+        if copies_object && self.observer.is_some() {
+            self.notify(|observer, interpreter| {
+                observer.allocated(interpreter, &value)
+            })?;
+        }
+        Ok(value)
+    }
+
+    fn lookup(&self, name: &str) -> Value {
+        self.variable(name)
+            .unwrap_or_else(|| panic!("undeclared identifier {name}"))
+    }
+
+    fn assign(&mut self, name: &'p str, value: Value) -> ObserverResult<'p> {
+        let frame = self.frames.last_mut().expect("no active frame");
+        if let Some((_, local)) = frame
+            .locals
+            .iter_mut()
+            .rev()
+            .find(|(local, _)| *local == name)
+        {
+            *local = value;
+            return Ok(());
+        }
+        match &frame.self_value {
+            Value::Object(object) => {
+                let mut object = object.borrow_mut();
+                let index = self.classes[object.class]
+                    .attribute_index(name)
+                    .expect("undeclared identifier");
+                object.attributes[index] = value.clone();
+            }
+            _ => panic!("undeclared identifier {name}"),
+        }
+        if self.observer.is_some() {
+            self.notify(|observer, interpreter| {
+                observer.assigned(interpreter, name, &value)
+            })?;
+        }
+        Ok(())
+    }
+
+    // Evaluate an expression with a local binding in scope.
+    fn eval_with_local(
+        &mut self,
+        name: &'p str,
+        value: Value,
+        expr: &'p Expression<'p>,
+    ) -> EvalResult<'p> {
+        self.frame_mut().locals.push((name, value));
+        let result = self.eval(expr);
+        self.frame_mut().locals.pop();
+        result
+    }
+
+    fn eval_int(
+        &mut self,
+        expr: &'p Expression<'p>,
+    ) -> Result<i32, RuntimeError<'p>> {
+        match self.eval(expr)? {
+            Value::Int(integer) => Ok(integer),
+            other => panic!("expected Int, found {other}"),
+        }
+    }
+
+    fn eval_bool(
+        &mut self,
+        expr: &'p Expression<'p>,
+    ) -> Result<bool, RuntimeError<'p>> {
+        match self.eval(expr)? {
+            Value::Bool(boolean) => Ok(boolean),
+            other => panic!("expected Bool, found {other}"),
+        }
+    }
+
+    // Evaluate the branch of the closest ancestor of the class of a value.
+    fn eval_case(
+        &mut self,
+        expr: &'p Expression<'p>,
+        case_expr: &'p Expression<'p>,
+        branches: &'p [CaseBranch<'p>],
+    ) -> EvalResult<'p> {
+        let value = self.eval(case_expr)?;
+        let class_id = self.class_of(&value).ok_or_else(|| {
+            RuntimeError::new(RuntimeErrorKind::CaseOnVoid, expr.location)
+        })?;
+        let mut ancestor = Some(class_id);
+        while let Some(current) = ancestor {
+            let class = &self.classes[current];
+            if let Some(branch) =
+                branches.iter().find(|b| b.type_id == class.name)
+            {
+                return self.eval_with_local(
+                    &branch.ident,
+                    value,
+                    &branch.expression,
+                );
+            }
+            ancestor = class.parent;
+        }
+        let class_name = self.classes[class_id].name.clone();
+        Err(RuntimeError::new(
+            RuntimeErrorKind::NoMatchingBranch(class_name),
+            expr.location,
+        ))
+    }
+
+    // Evaluate an operation on integers other than equality.
+    fn eval_arithmetic(
+        &mut self,
+        expr: &'p Expression<'p>,
+        operator: &BinaryOperator,
+        operand1: &'p Expression<'p>,
+        operand2: &'p Expression<'p>,
+    ) -> EvalResult<'p> {
+        let int1 = self.eval_int(operand1)?;
+        let int2 = self.eval_int(operand2)?;
+        Ok(match operator {
+            BinaryOperator::LessThanOrEquals => Value::Bool(int1 <= int2),
+            BinaryOperator::LessThan => Value::Bool(int1 < int2),
+            BinaryOperator::Add => Value::Int(int1.wrapping_add(int2)),
+            BinaryOperator::Subtract => Value::Int(int1.wrapping_sub(int2)),
+            BinaryOperator::Multiply => Value::Int(int1.wrapping_mul(int2)),
+            BinaryOperator::Divide => {
+                if int2 == 0 {
+                    return Err(RuntimeError::new(
+                        RuntimeErrorKind::DivisionByZero,
+                        expr.location,
+                    ));
+                }
+                Value::Int(int1.wrapping_div(int2))
+            }
+            BinaryOperator::Equals => unreachable!(),
+        })
+    }
+
+    fn eval(&mut self, expr: &'p Expression<'p>) -> EvalResult<'p> {
+        self.meter.step()?;
+        self.frame_mut().location = Some(expr.location);
+        if self.observer.is_some() {
+            self.notify(|observer, interpreter| {
+                observer.expression(interpreter, expr)
+            })?;
+        }
+        match &expr.data {
+            Block(expressions) => {
+                let mut value = Value::Void;
+                for expression in expressions.iter() {
+                    value = self.eval(expression)?;
+                }
+                Ok(value)
+            }
+            Conditional(if_expr, then_expr, else_expr) => {
+                if self.eval_bool(if_expr)? {
+                    self.eval(then_expr)
+                } else {
+                    self.eval(else_expr)
+                }
+            }
+            Loop(cond_expr, loop_expr) => {
+                while self.eval_bool(cond_expr)? {
+                    self.eval(loop_expr)?;
+                }
+                Ok(Value::Void)
+            }
+            Case(case_expr, branches) => {
+                self.eval_case(expr, case_expr, branches)
+            }
+            Let(ident, type_id, opt_bind, body) => {
+                let value = match &**opt_bind {
+                    Some(bind) => self.eval(bind)?,
+                    None => Value::default_for(type_id),
+                };
+                self.eval_with_local(ident, value, body)
+            }
+            New(type_id) => {
+                let class_id = if type_id == SELF_TYPE {
+                    let self_value = &self.frame().self_value;
+                    self.class_of(self_value).expect("self is never void")
+                } else {
+                    self.class_ids[type_id]
+                };
+                self.instantiate(class_id)
+            }
+            Assign(ident, expr) => {
+                let value = self.eval(expr)?;
+                self.assign(ident, value.clone())?;
+                Ok(value)
+            }
+            UnaryOperation(operator, operand) => match operator {
+                UnaryOperator::Not => {
+                    Ok(Value::Bool(!self.eval_bool(operand)?))
+                }
+                UnaryOperator::Negative => {
+                    Ok(Value::Int(self.eval_int(operand)?.wrapping_neg()))
+                }
+                UnaryOperator::IsVoid => {
+                    Ok(Value::Bool(self.eval(operand)?.is_void()))
+                }
+            },
+            BinaryOperation(BinaryOperator::Equals, operand1, operand2) => {
+                let value1 = self.eval(operand1)?;
+                let value2 = self.eval(operand2)?;
+                Ok(Value::Bool(value1.equals(&value2)))
+            }
+            BinaryOperation(operator, operand1, operand2) => {
+                self.eval_arithmetic(expr, operator, operand1, operand2)
+            }
+            MethodCall(callee, static_type, ident, params) => {
+                // Arguments are evaluated before the receiver.
+                let mut args = Vec::with_capacity(params.len());
+                for param in params.iter() {
+                    args.push(self.eval(param)?);
+                }
+                let receiver = self.eval(callee)?;
+                let static_class =
+                    static_type.as_ref().map(|name| self.class_ids[name]);
+                self.dispatch(receiver, static_class, ident, args)
+                    .map_err(|err| err.or_at(expr.location))
+            }
+            Object(ident) => Ok(self.lookup(ident)),
+            IntLiteral(integer) => Ok(Value::Int(*integer)),
+            StrLiteral(string) => Ok(Value::Str(string.as_str().into())),
+            BoolLiteral(boolean) => Ok(Value::Bool(*boolean)),
+        }
+    }
+}
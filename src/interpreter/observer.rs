@@ -0,0 +1,114 @@
+//! A hook into evaluation, for tools that follow a running program.
+
+use super::*;
+
+/// A dispatch about to take place.
+pub struct Call<'c> {
+    pub receiver: &'c Value,
+    /// The class that provides the method.
+    pub owner: ClassId,
+    pub method: &'c str,
+    pub args: &'c [Value],
+    pub builtin: bool,
+}
+
+/// Notified by the interpreter as a program runs. An observer can inspect
+/// the state of the interpreter, and stop the program by returning an
+/// error.
+pub trait Observer<'p> {
+    /// Called before an expression is evaluated, once the frame it is
+    /// evaluated in is active.
+    fn expression(
+        &mut self,
+        _interpreter: &Interpreter<'p>,
+        _expr: &'p Expression<'p>,
+    ) -> Result<(), RuntimeError<'p>> {
+        Ok(())
+    }
+
+    /// Called before a method is dispatched, while the frame of the caller
+    /// is still the innermost one.
+    fn call(
+        &mut self,
+        _interpreter: &Interpreter<'p>,
+        _call: &Call,
+    ) -> Result<(), RuntimeError<'p>> {
+        Ok(())
+    }
+
+    /// Called once a dispatched method returns a value, after its frame is
+    /// gone. Methods that fail do not return.
+    fn returned(
+        &mut self,
+        _interpreter: &Interpreter<'p>,
+        _value: &Value,
+    ) -> Result<(), RuntimeError<'p>> {
+        Ok(())
+    }
+
+    /// Called once an attribute of self is assigned a value.
+    fn assigned(
+        &mut self,
+        _interpreter: &Interpreter<'p>,
+        _attribute: &'p str,
+        _value: &Value,
+    ) -> Result<(), RuntimeError<'p>> {
+        Ok(())
+    }
+
+    /// Called when an object is created, by `new` or by `copy`, before its
+    /// attributes are initialised. Values of the basic classes are not
+    /// objects.
+    fn allocated(
+        &mut self,
+        _interpreter: &Interpreter<'p>,
+        _object: &Value,
+    ) -> Result<(), RuntimeError<'p>> {
+        Ok(())
+    }
+}
+
+// An observer can be lent to the interpreter, to be looked at after the
+// program has run.
+impl<'p, O: Observer<'p> + ?Sized> Observer<'p> for &mut O {
+    fn expression(
+        &mut self,
+        interpreter: &Interpreter<'p>,
+        expr: &'p Expression<'p>,
+    ) -> Result<(), RuntimeError<'p>> {
+        (**self).expression(interpreter, expr)
+    }
+
+    fn call(
+        &mut self,
+        interpreter: &Interpreter<'p>,
+        call: &Call,
+    ) -> Result<(), RuntimeError<'p>> {
+        (**self).call(interpreter, call)
+    }
+
+    fn returned(
+        &mut self,
+        interpreter: &Interpreter<'p>,
+        value: &Value,
+    ) -> Result<(), RuntimeError<'p>> {
+        (**self).returned(interpreter, value)
+    }
+
+    fn assigned(
+        &mut self,
+        interpreter: &Interpreter<'p>,
+        attribute: &'p str,
+        value: &Value,
+    ) -> Result<(), RuntimeError<'p>> {
+        (**self).assigned(interpreter, attribute, value)
+    }
+
+    fn allocated(
+        &mut self,
+        interpreter: &Interpreter<'p>,
+        object: &Value,
+    ) -> Result<(), RuntimeError<'p>> {
+        (**self).allocated(interpreter, object)
+    }
+}
@@ -0,0 +1,437 @@
+use super::builtins::parse_int;
+use super::*;
+use crate::lexer::lex_tokens;
+use crate::parser::parse_program;
+use crate::semant::check_program;
+use std::thread;
+
+// Runs a program, returning its output along with the message and exit code
+// of the runtime error that stopped it, if any.
+fn run_with_input(
+    input: &str,
+    source: &str,
+) -> (String, Option<(String, i32)>) {
+    run_with_limits(Limits::default(), input, source)
+}
+
+fn run_with_limits(
+    limits: Limits,
+    input: &str,
+    source: &str,
+) -> (String, Option<(String, i32)>) {
+    let (_, tokens) = lex_tokens(source, "test.cl").unwrap();
+    let (_, mut program) = parse_program(&tokens).unwrap();
+    let hierarchy = check_program(&mut program).unwrap();
+    let mut output = Vec::new();
+    let mut interpreter = Interpreter::new(
+        &program,
+        &hierarchy,
+        Box::new(input.as_bytes()),
+        Box::new(&mut output),
+    );
+    interpreter.set_limits(limits);
+    let result = interpreter.run();
+    drop(interpreter);
+    let error = result.err().map(|err| (err.to_string(), err.exit_code()));
+    (String::from_utf8(output).unwrap(), error)
+}
+
+fn run(source: &str) -> String {
+    let (output, error) = run_with_input("", source);
+    assert_eq!(error, None);
+    output
+}
+
+fn run_error(source: &str) -> (String, i32) {
+    run_with_input("", source).1.expect("a runtime error")
+}
+
+#[test]
+fn test_arithmetic_and_comparisons() {
+    assert_eq!(
+        run("class Main inherits IO { main() : Object {{\
+                 out_int(1 + 2 * 3 - 8 / 3); out_string(\" \");\
+                 out_int(~5); out_string(\" \");\
+                 out_int(7 / ~2); out_string(\" \");\
+                 out_int(2147483647 + 1); out_string(\" \");\
+                 out_string(if 1 < 2 then \"lt\" else \"ge\" fi);\
+                 out_string(if 2 <= 2 then \" le\" else \" gt\" fi);\
+                 out_string(if not (1 = 2) then \" ne\" else \" eq\" fi);\
+             }}; };"),
+        "5 -5 -3 -2147483648 lt le ne"
+    );
+}
+
+#[test]
+fn test_dispatch() {
+    let source = "\
+        class A { name() : String { \"A\" }; who() : String { name() }; };\
+        class B inherits A { name() : String { \"B\" }; };\
+        class Main inherits IO { main() : Object {{\
+            out_string((new A).who());\
+            out_string((new B).who());\
+            out_string((new B)@A.name());\
+            out_string(let a : A <- new B in a.name());\
+        }}; };";
+    assert_eq!(run(source), "ABAB");
+}
+
+#[test]
+fn test_attribute_initialisation() {
+    let source = "\
+        class A inherits IO {\
+            a : Int <- { out_string(\"a\"); 1; };\
+            b : Int <- { out_string(\"b\"); a + 1; };\
+        };\
+        class B inherits A {\
+            c : Int <- { out_string(\"c\"); b + 1; };\
+            s : String; i : Int; f : Bool; o : Object;\
+            show() : Object {{\
+                out_int(c); out_string(s); out_int(i);\
+                out_string(if f then \"t\" else \"f\" fi);\
+                out_string(if isvoid o then \"void\" else \"set\" fi);\
+            }};\
+        };\
+        class Main { main() : Object { (new B).show() }; };";
+    assert_eq!(run(source), "abc30fvoid");
+}
+
+#[test]
+fn test_self_type_and_copy() {
+    let source = "\
+        class Counter {\
+            n : Int;\
+            inc() : SELF_TYPE {{ n <- n + 1; self; }};\
+            get() : Int { n };\
+            clone() : SELF_TYPE { new SELF_TYPE };\
+        };\
+        class Main inherits IO { main() : Object {\
+            let c : Counter <- (new Counter).inc().inc(), d : Counter <- c.copy() in {\
+                d.inc();\
+                out_int(c.get()); out_int(d.get()); out_int(c.clone().get());\
+                out_string(c.clone().type_name());\
+                out_string(if c = c then \"same\" else \"different\" fi);\
+                out_string(if c = d then \"same\" else \"different\" fi);\
+            }\
+        }; };";
+    assert_eq!(run(source), "230Countersamedifferent");
+}
+
+#[test]
+fn test_case_selects_closest_ancestor() {
+    let source = "\
+        class A {}; class B inherits A {}; class C inherits B {};\
+        class Main inherits IO {\
+            test(x : Object) : Object {\
+                out_string(case x of \
+                    a : A => \"A\";\
+                    b : B => \"B\";\
+                    o : Object => \"Object\";\
+                    s : String => s;\
+                    i : Int => \"Int\";\
+                esac)\
+            };\
+            main() : Object {{\
+                test(new A); test(new C); test(new Main); test(\"str\"); test(3);\
+            }};\
+        };";
+    assert_eq!(run(source), "ABObjectstrInt");
+}
+
+#[test]
+fn test_loops_and_let() {
+    let source = "\
+        class Main inherits IO { main() : Object {\
+            let i : Int, sum : Int in {\
+                while i < 5 loop { i <- i + 1; sum <- sum + i; } pool;\
+                let i : Int <- 100 in out_int(i);\
+                out_string(\" \");\
+                out_int(i); out_string(\" \"); out_int(sum);\
+            }\
+        }; };";
+    assert_eq!(run(source), "100 5 15");
+}
+
+#[test]
+fn test_string_methods() {
+    let source = "\
+        class Main inherits IO { main() : Object {\
+            let s : String <- \"hello\".concat(\" world\") in {\
+                out_int(s.length()); out_string(s.substr(6, 5));\
+                out_string(s.substr(11, 0)); out_string(\"\\n\");\
+            }\
+        }; };";
+    assert_eq!(run(source), "11world\n");
+}
+
+#[test]
+fn test_input() {
+    let source = "\
+        class Main inherits IO { main() : Object {{\
+            out_string(in_string().concat(\"!\"));\
+            out_int(in_int() + 1);\
+            out_int(in_int());\
+            out_string(in_string());\
+            out_int(in_int());\
+        }}; };";
+    let (output, error) = run_with_input("line\r\n  -42 rest\nxyz\n", source);
+    assert_eq!(error, None);
+    assert_eq!(output, "line!-4100");
+}
+
+#[test]
+fn test_runtime_errors() {
+    assert_eq!(
+        run_error(
+            "class Main { a : Main; main() : Object {\n\
+                 a.main()\n\
+             }; };"
+        ),
+        (
+            "test.cl:2: Dispatch to void calling method main.".to_string(),
+            5
+        )
+    );
+    assert_eq!(
+        run_error(
+            "class Main { a : Main; main() : Object { a@Main.main() }; };"
+        ),
+        (
+            "test.cl:1: Static dispatch to void calling method main."
+                .to_string(),
+            6
+        )
+    );
+    assert_eq!(
+        run_error(
+            "class Main { a : Main; main() : Object {\n\
+                 case a of m : Main => m; esac }; };"
+        ),
+        ("test.cl:2: Match on void in case statement.".to_string(), 7)
+    );
+    assert_eq!(
+        run_error(
+            "class Main { main() : Object {\n\n\
+                 case 1 of s : String => s; esac }; };"
+        ),
+        (
+            "test.cl:3: No match in case statement for Class Int.".to_string(),
+            8
+        )
+    );
+    assert_eq!(
+        run_error("class Main { main() : Int { 1 / 0 }; };"),
+        ("test.cl:1: Division by zero.".to_string(), 9)
+    );
+    assert_eq!(
+        run_error(
+            "class Main { main() : String {\n\
+                 \"abc\".substr(2, 2) }; };"
+        ),
+        ("test.cl:2: Index out of range in substr.".to_string(), 10)
+    );
+}
+
+#[test]
+fn test_error_location_is_innermost_expression() {
+    let source = "\
+        class A { f(s : String) : String {\n\
+            s.substr(0, 10)\n\
+        }; };\n\
+        class Main { main() : Object { (new A).f(\"short\") }; };";
+    assert_eq!(
+        run_error(source),
+        ("test.cl:2: Index out of range in substr.".to_string(), 10)
+    );
+}
+
+#[test]
+fn test_abort() {
+    let (output, error) = run_with_input(
+        "",
+        "class Main inherits IO { main() : Object {{\
+             out_string(\"before\"); abort(); out_string(\"after\");\
+         }}; };",
+    );
+    assert_eq!(output, "before");
+    assert_eq!(
+        error,
+        Some(("Abort called from class Main".to_string(), 11))
+    );
+}
+
+#[test]
+fn test_parse_int() {
+    assert_eq!(parse_int("42"), 42);
+    assert_eq!(parse_int("  -7x"), -7);
+    assert_eq!(parse_int("+3"), 3);
+    assert_eq!(parse_int("abc"), 0);
+    assert_eq!(parse_int(""), 0);
+}
+
+#[test]
+fn test_limits() {
+    let limited = |limits, source| run_with_limits(limits, "", source);
+    let (output, error) = limited(
+        Limits {
+            steps: Some(100),
+            ..Limits::default()
+        },
+        "class Main inherits IO { main() : Object {\
+             while true loop out_string(\"x\") pool\
+         }; };",
+    );
+    assert_eq!(output.len(), 24);
+    assert_eq!(error, Some(("Step limit of 100 exceeded.".to_string(), 14)));
+
+    let (_, error) = limited(
+        Limits {
+            objects: Some(3),
+            ..Limits::default()
+        },
+        "class Main { main() : Object {{ new Main; copy(); new Main; }}; };",
+    );
+    assert_eq!(error, Some(("Object limit of 3 exceeded.".to_string(), 15)));
+
+    let (_, error) = limited(
+        Limits {
+            call_depth: Some(50),
+            ..Limits::default()
+        },
+        "class Main { f(n : Int) : Int { f(n + 1) }; main() : Object {\
+             f(0)\
+         }; };",
+    );
+    assert_eq!(
+        error,
+        Some(("Call depth limit of 50 exceeded.".to_string(), 16))
+    );
+
+    let (output, error) = limited(
+        Limits {
+            output_bytes: Some(10),
+            ..Limits::default()
+        },
+        "class Main inherits IO { main() : Object {\
+             { out_string(\"hello \"); out_int(12345); }\
+         }; };",
+    );
+    assert_eq!(output, "hello 1234");
+    assert_eq!(
+        error,
+        Some(("Output limit of 10 bytes exceeded.".to_string(), 17))
+    );
+
+    // Without a depth limit, the stack size bounds recursion however much
+    // stack each frame uses, and running out of it is reported as such.
+    let stack_size = 64 << 20;
+    let (_, error) = thread::scope(|scope| {
+        thread::Builder::new()
+            .stack_size(stack_size)
+            .spawn_scoped(scope, || {
+                limited(
+                    Limits::default().with_stack_size(stack_size),
+                    "class Main { f(n : Int) : Int { let a : Int <- 1 in \
+                         a + (1 + (2 + (3 + (4 + (5 + { f(n + 1); })))))\
+                     }; main() : Object { f(0) }; };",
+                )
+            })
+            .unwrap()
+            .join()
+            .unwrap()
+    });
+    assert_eq!(error, Some(("Stack overflow.".to_string(), 20)));
+
+    // Programs within their limits are not affected
+    let (output, error) = limited(
+        Limits {
+            steps: Some(10),
+            objects: Some(1),
+            call_depth: Some(1),
+            output_bytes: Some(2),
+            stack_bytes: None,
+        },
+        "class Main inherits IO { main() : Object { out_int(42) }; };",
+    );
+    assert_eq!((output.as_str(), error), ("42", None));
+}
+
+// Runs a program with a transcript, recorded from the input or replayed,
+// returning the transcript and the error that stopped the program.
+fn run_with_transcript(
+    input: &str,
+    replayed: Option<&str>,
+    source: &str,
+) -> (String, Option<(String, i32)>) {
+    let (_, tokens) = lex_tokens(source, "test.cl").unwrap();
+    let (_, mut program) = parse_program(&tokens).unwrap();
+    let hierarchy = check_program(&mut program).unwrap();
+    let mut transcript = Vec::new();
+    let mut interpreter = Interpreter::new(
+        &program,
+        &hierarchy,
+        Box::new(input.as_bytes()),
+        Box::new(Vec::new()),
+    );
+    match replayed {
+        Some(replayed) => interpreter.replay(replayed.parse().unwrap()),
+        None => interpreter.record(Box::new(&mut transcript)),
+    }
+    let result = interpreter.run();
+    drop(interpreter);
+    let error = result.err().map(|err| (err.to_string(), err.exit_code()));
+    (String::from_utf8(transcript).unwrap(), error)
+}
+
+#[test]
+fn test_transcript() {
+    let source = "class Main inherits IO { main() : Object {{\
+                      out_string(\"Name? \"); out_string(\"\\\"\");\
+                      out_string(in_string().concat(\"\\\"\\n\"));\
+                      out_int(in_int() + 1);\
+                  }}; };";
+    let recorded = "out \"Name? \\\"\"\nin \"a\\tb\"\n\
+                    out \"a\\tb\\\"\\n\"\nin \"41\"\nout \"42\"\n";
+    assert_eq!(
+        run_with_transcript("a\tb\n41\n", None, source),
+        (recorded.to_string(), None)
+    );
+    assert_eq!(
+        recorded.parse::<Transcript>().unwrap().to_string(),
+        recorded
+    );
+    assert_eq!(
+        run_with_transcript("", Some(recorded), source),
+        (String::new(), None)
+    );
+
+    for (replayed, message) in [
+        (
+            "out \"Name? \\\"\"\nin \"x\"\nout \"y\"",
+            "test.cl:1: Transcript mismatch: \
+             expected output \"y\", found \"x\\\"\\n\".",
+        ),
+        (
+            "out \"Name? \"\nout \"\\\"\"\nin \"x\"\nout \"x\\\"\\n\"",
+            "test.cl:1: Transcript mismatch: \
+             expected the end, found a read.",
+        ),
+        (
+            "out \"Name? \\\"\"\nin \"x\"\nout \"x\\\"\\n\"\nin \"41\"\n\
+             out \"42\"\nout \"!\"",
+            "Transcript mismatch: expected output \"!\" before the end.",
+        ),
+        (
+            "out \"Name? \\\"\"\nin \"x\"\nout \"x\\\"\\n4\"\nin \"41\"",
+            "test.cl:1: Transcript mismatch: \
+             expected output \"4\" before reading input.",
+        ),
+    ] {
+        assert_eq!(
+            run_with_transcript("", Some(replayed), source).1,
+            Some((message.to_string(), 18))
+        );
+    }
+    assert!("in x".parse::<Transcript>().is_err());
+    assert!("read \"x\"".parse::<Transcript>().is_err());
+}
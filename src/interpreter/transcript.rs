@@ -0,0 +1,230 @@
+//! Transcripts of the input read and the output written by a program, for
+//! replaying interactive programs as tests. A transcript has a line for
+//! every line of input and for the output written in between:
+//!
+//! ```text
+//! out "How many? "
+//! in "3"
+//! out "6\n"
+//! ```
+
+use super::*;
+use crate::util::escape_str;
+use std::collections::VecDeque;
+use std::str::FromStr;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    /// A line read, without its terminator.
+    Input(String),
+    Output(String),
+}
+
+impl Display for Event {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Input(line) => write!(f, "in \"{}\"", escape_str(line)),
+            Self::Output(text) => write!(f, "out \"{}\"", escape_str(text)),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Transcript {
+    pub events: Vec<Event>,
+}
+
+impl Display for Transcript {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for event in self.events.iter() {
+            writeln!(f, "{event}")?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Transcript {
+    type Err = String;
+
+    fn from_str(transcript: &str) -> Result<Self, Self::Err> {
+        let mut events = Vec::new();
+        for (line, line_num) in transcript.split('\n').zip(1..) {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let event = line
+                .split_once(' ')
+                .and_then(|(kind, text)| Some((kind, unquote(text)?)))
+                .and_then(|(kind, text)| match kind {
+                    "in" => Some(Event::Input(text)),
+                    "out" => Some(Event::Output(text)),
+                    _ => None,
+                })
+                .ok_or_else(|| {
+                    format!("Invalid transcript line {line_num}.")
+                })?;
+            events.push(event);
+        }
+        Ok(Self { events })
+    }
+}
+
+// Read back a string written with escape_str between double quotes.
+fn unquote(text: &str) -> Option<String> {
+    let text = text.strip_prefix('"')?.strip_suffix('"')?;
+    let mut string = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(ch) = chars.next() {
+        string.push(match ch {
+            '\\' => match chars.next()? {
+                'n' => '\n',
+                't' => '\t',
+                'b' => '\u{08}',
+                'f' => '\u{0C}',
+                other => other,
+            },
+            '"' => return None,
+            other => other,
+        });
+    }
+    Some(string)
+}
+
+fn mismatch<'a>(message: String) -> RuntimeError<'a> {
+    RuntimeErrorKind::TranscriptMismatch(message).into()
+}
+
+/// Writes the events of a running program as a transcript. Output is
+/// written once the program reads or flushes it, so that consecutive writes
+/// make a single event.
+pub(crate) struct Recorder<'p> {
+    transcript: Box<dyn Write + 'p>,
+    output: String,
+}
+
+impl<'p> Recorder<'p> {
+    pub fn new(transcript: Box<dyn Write + 'p>) -> Self {
+        Self {
+            transcript,
+            output: String::new(),
+        }
+    }
+
+    pub fn write(&mut self, text: &str) {
+        self.output.push_str(text);
+    }
+
+    pub fn read(&mut self, line: &str) -> std::io::Result<()> {
+        self.flush()?;
+        writeln!(self.transcript, "{}", Event::Input(line.to_string()))
+    }
+
+    pub fn flush(&mut self) -> std::io::Result<()> {
+        if !self.output.is_empty() {
+            let output = std::mem::take(&mut self.output);
+            writeln!(self.transcript, "{}", Event::Output(output))?;
+        }
+        self.transcript.flush()
+    }
+}
+
+/// Supplies the input of a transcript to a running program, and checks that
+/// it writes the output of the transcript.
+pub(crate) struct Replayer {
+    events: VecDeque<Event>,
+    /// What remains to be written of the current output event.
+    output: String,
+}
+
+impl Replayer {
+    pub fn new(transcript: Transcript) -> Self {
+        Self {
+            events: transcript.events.into(),
+            output: String::new(),
+        }
+    }
+
+    pub fn write<'a>(
+        &mut self,
+        mut text: &str,
+    ) -> Result<(), RuntimeError<'a>> {
+        while !text.is_empty() {
+            if self.output.is_empty() {
+                match self.events.pop_front() {
+                    Some(Event::Output(output)) => self.output = output,
+                    Some(Event::Input(line)) => {
+                        return Err(mismatch(format!(
+                            "expected a read of \"{}\", found output \"{}\"",
+                            escape_str(&line),
+                            escape_str(text)
+                        )))
+                    }
+                    None => {
+                        return Err(mismatch(format!(
+                            "expected the end, found output \"{}\"",
+                            escape_str(text)
+                        )))
+                    }
+                }
+            }
+            let common = text.len().min(self.output.len());
+            if text.as_bytes()[..common] != self.output.as_bytes()[..common] {
+                return Err(mismatch(format!(
+                    "expected output \"{}\", found \"{}\"",
+                    escape_str(&self.output),
+                    escape_str(text)
+                )));
+            }
+            // Both are valid strings with the same bytes up to `common`, so
+            // it is a character boundary in both.
+            self.output.drain(..common);
+            text = &text[common..];
+        }
+        Ok(())
+    }
+
+    pub fn read<'a>(&mut self) -> Result<String, RuntimeError<'a>> {
+        self.expect_output("reading input")?;
+        match self.events.pop_front() {
+            Some(Event::Input(line)) => Ok(line),
+            Some(Event::Output(output)) => Err(mismatch(format!(
+                "expected output \"{}\" before reading input",
+                escape_str(&output)
+            ))),
+            None => Err(mismatch("expected the end, found a read".into())),
+        }
+    }
+
+    /// Check that the whole transcript was replayed.
+    pub fn finish<'a>(&mut self) -> Result<(), RuntimeError<'a>> {
+        self.expect_output("the end")?;
+        match self.events.pop_front() {
+            Some(Event::Input(line)) => Err(mismatch(format!(
+                "expected a read of \"{}\" before the end",
+                escape_str(&line)
+            ))),
+            Some(Event::Output(output)) => Err(mismatch(format!(
+                "expected output \"{}\" before the end",
+                escape_str(&output)
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    fn expect_output<'a>(&self, before: &str) -> Result<(), RuntimeError<'a>> {
+        if self.output.is_empty() {
+            Ok(())
+        } else {
+            Err(mismatch(format!(
+                "expected output \"{}\" before {before}",
+                escape_str(&self.output)
+            )))
+        }
+    }
+}
+
+/// How the input and output of a program are followed.
+pub(crate) enum Tape<'p> {
+    Record(Recorder<'p>),
+    Replay(Replayer),
+}
@@ -0,0 +1,77 @@
+//! Runtime values manipulated by the interpreter.
+
+use crate::hierarchy::{BOOL, INT, STRING};
+use crate::util::escape_str;
+use std::cell::RefCell;
+use std::fmt::{Display, Formatter};
+use std::rc::Rc;
+
+/// Index of a class in the interpreter's class table.
+pub type ClassId = usize;
+
+/// Values of the basic classes Int, Bool and String are stored unboxed;
+/// every other object is shared by reference.
+#[derive(Clone, Debug)]
+pub enum Value {
+    Void,
+    Int(i32),
+    Bool(bool),
+    Str(Rc<str>),
+    Object(ObjectRef),
+}
+
+pub type ObjectRef = Rc<RefCell<ObjectData>>;
+
+#[derive(Clone, Debug)]
+pub struct ObjectData {
+    pub class: ClassId,
+    pub attributes: Vec<Value>,
+}
+
+impl Value {
+    pub fn new_object(class: ClassId, attributes: Vec<Value>) -> Self {
+        Self::Object(Rc::new(RefCell::new(ObjectData { class, attributes })))
+    }
+
+    /// The value of a variable of the given type that has not been
+    /// initialised.
+    pub fn default_for(type_id: &str) -> Self {
+        match type_id {
+            INT => Self::Int(0),
+            BOOL => Self::Bool(false),
+            STRING => Self::Str("".into()),
+            _ => Self::Void,
+        }
+    }
+
+    pub fn is_void(&self) -> bool {
+        matches!(self, Self::Void)
+    }
+
+    /// Equality as defined by the `=` operator: basic values are compared by
+    /// value and other objects by identity.
+    pub fn equals(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Self::Void, Self::Void) => true,
+            (Self::Int(i), Self::Int(j)) => i == j,
+            (Self::Bool(a), Self::Bool(b)) => a == b,
+            (Self::Str(s), Self::Str(t)) => s == t,
+            (Self::Object(a), Self::Object(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Void => write!(f, "void"),
+            Self::Int(integer) => write!(f, "{integer}"),
+            Self::Bool(boolean) => write!(f, "{boolean}"),
+            Self::Str(string) => write!(f, "\"{}\"", escape_str(string)),
+            Self::Object(object) => {
+                write!(f, "<object #{:x}>", Rc::as_ptr(object) as usize)
+            }
+        }
+    }
+}
@@ -0,0 +1,47 @@
+//! The class hierarchy of a module, as looked up by the verifier and the
+//! passes.
+
+use super::*;
+use crate::hierarchy::{
+    AttributeSignature, ClassHierarchy, ClassInfo, MethodSignature,
+};
+
+/// The hierarchy of the classes a module declares. A class declared twice
+/// is found by its last declaration, which `verify` rejects.
+pub(super) fn hierarchy(classes: &[ClassDecl]) -> ClassHierarchy<'static> {
+    ClassHierarchy::from_classes(classes.iter().map(class_info))
+}
+
+fn class_info(class: &ClassDecl) -> ClassInfo<'static> {
+    let attributes = class
+        .attributes
+        .iter()
+        .map(|(name, type_id)| AttributeSignature {
+            name: name.clone(),
+            type_id: type_id.clone(),
+            location: None,
+        })
+        .collect();
+    let methods = class
+        .methods
+        .iter()
+        .map(|method| MethodSignature {
+            name: method.name.clone(),
+            // Modules declare parameters by their types alone.
+            formals: method
+                .parameters
+                .iter()
+                .map(|type_id| (String::new(), type_id.clone()))
+                .collect(),
+            return_type: method.return_type.clone(),
+            location: None,
+        })
+        .collect();
+    ClassInfo {
+        name: class.name.clone(),
+        parent: class.parent.clone(),
+        attributes,
+        methods,
+        location: None,
+    }
+}
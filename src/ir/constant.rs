@@ -0,0 +1,222 @@
+//! Constant folding and propagation over a function in SSA form.
+//!
+//! Instructions whose operands are constants are replaced by their value,
+//! as are phis given the same constant on every edge, and unboxing a value
+//! just boxed takes the value itself. Registers known never to be void,
+//! such as self and new objects, make void tests and checks on them
+//! constant, as a known nonzero divisor does the division by zero check.
+//! Branches on constants become jumps.
+
+use super::simplify::remove_phi_edges;
+use super::*;
+use std::collections::HashMap;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Value {
+    Int(i32),
+    Bool(bool),
+}
+
+#[derive(Default)]
+struct Facts {
+    constants: HashMap<Register, Value>,
+    // The register of the value each boxed register was made from.
+    boxed: HashMap<Register, Register>,
+    // Whether registers are known to be void or not.
+    void: HashMap<Register, bool>,
+}
+
+pub fn fold_constants(function: &mut Function) -> bool {
+    let mut facts = Facts::default();
+    if let Some(receiver) = function.parameters.first() {
+        facts.void.insert(*receiver, false);
+    }
+    let mut changed = false;
+    // Definitions dominate their uses, so a pass in reverse postorder sees
+    // them first, apart from those reaching phis along back edges.
+    for block_id in super::dominance::reverse_postorder(function) {
+        let block = function.block_mut(block_id);
+        let mut index = 0;
+        while index < block.instructions.len() {
+            match facts.fold(&block.instructions[index]) {
+                Fold::Keep => index += 1,
+                Fold::Replace(instruction) => {
+                    // What replaces a phi goes after the phis.
+                    if let Instruction::Phi(..) = block.instructions[index] {
+                        block.instructions.remove(index);
+                        let phis = block.instructions[index..]
+                            .iter()
+                            .take_while(|i| matches!(i, Instruction::Phi(..)))
+                            .count();
+                        block.instructions.insert(index + phis, instruction);
+                    } else {
+                        block.instructions[index] = instruction;
+                    }
+                    changed = true;
+                }
+                Fold::Remove => {
+                    block.instructions.remove(index);
+                    changed = true;
+                }
+            }
+        }
+        if let Terminator::Branch(condition, then_block, else_block) =
+            block.terminator
+        {
+            if let Some(Value::Bool(value)) = facts.constants.get(&condition) {
+                let target = if *value { then_block } else { else_block };
+                block.terminator = Terminator::Jump(target);
+                remove_phi_edges(function, block_id);
+                changed = true;
+            }
+        }
+    }
+    changed
+}
+
+enum Fold {
+    Keep,
+    Replace(Instruction),
+    Remove,
+}
+
+impl Facts {
+    // Record what an instruction tells about its result, and how it can be
+    // folded. Folded instructions are looked at again.
+    fn fold(&mut self, instruction: &Instruction) -> Fold {
+        use Instruction::*;
+        let constant = |register: &Register| self.constants.get(register);
+        let folded = match instruction {
+            Int(result, value) => {
+                self.constants.insert(*result, Value::Int(*value));
+                None
+            }
+            Bool(result, value) => {
+                self.constants.insert(*result, Value::Bool(*value));
+                None
+            }
+            String(result, _)
+            | New(result, _)
+            | NewSelfType(result, _)
+            | Box(result, _) => {
+                if let Box(result, value) = instruction {
+                    self.boxed.insert(*result, *value);
+                }
+                self.void.insert(*result, false);
+                None
+            }
+            Void(result) => {
+                self.void.insert(*result, true);
+                None
+            }
+            Copy(result, source) | Cast(result, source) => {
+                if let Some(void) = self.void.get(source) {
+                    self.void.insert(*result, *void);
+                }
+                if let Copy(..) = instruction {
+                    if let Some(value) = constant(source) {
+                        return Fold::Replace(value.instruction(*result));
+                    }
+                }
+                None
+            }
+            Unary(result, operator, operand) => {
+                match (operator, constant(operand)) {
+                    (UnaryOperator::Negate, Some(Value::Int(value))) => {
+                        Some(Int(*result, value.wrapping_neg()))
+                    }
+                    (UnaryOperator::Not, Some(Value::Bool(value))) => {
+                        Some(Bool(*result, !value))
+                    }
+                    _ => None,
+                }
+            }
+            Binary(result, operator, left, right) => {
+                match (constant(left), constant(right)) {
+                    (Some(left), Some(right)) => {
+                        binary(*operator, *left, *right)
+                            .map(|value| value.instruction(*result))
+                    }
+                    _ => None,
+                }
+            }
+            IsVoid(result, object) => {
+                self.void.get(object).map(|void| Bool(*result, *void))
+            }
+            Unbox(result, object) => {
+                self.boxed.get(object).map(|value| Copy(*result, *value))
+            }
+            Phi(result, incoming) => {
+                let mut values = incoming
+                    .iter()
+                    .map(|(_, register)| constant(register).copied());
+                match values.next().flatten() {
+                    Some(value) if values.all(|other| other == Some(value)) => {
+                        Some(value.instruction(*result))
+                    }
+                    _ => None,
+                }
+            }
+            CheckVoid(object, _, _) => {
+                if self.void.get(object) == Some(&false) {
+                    return Fold::Remove;
+                }
+                None
+            }
+            CheckZero(value, _) => {
+                match constant(value) {
+                    Some(Value::Int(value)) if *value != 0 => {
+                        return Fold::Remove;
+                    }
+                    _ => {}
+                }
+                None
+            }
+            Equal(..)
+            | GetAttribute(..)
+            | SetAttribute(..)
+            | Dispatch { .. }
+            | Call { .. }
+            | InstanceOf(..) => None,
+        };
+        match folded {
+            Some(instruction) => Fold::Replace(instruction),
+            None => Fold::Keep,
+        }
+    }
+}
+
+impl Value {
+    fn instruction(self, result: Register) -> Instruction {
+        match self {
+            Value::Int(value) => Instruction::Int(result, value),
+            Value::Bool(value) => Instruction::Bool(result, value),
+        }
+    }
+}
+
+// The value of an operator on constants, with arithmetic wrapping around
+// as at run time. Division by zero is left to the check before it.
+fn binary(
+    operator: BinaryOperator,
+    left: Value,
+    right: Value,
+) -> Option<Value> {
+    use BinaryOperator::*;
+    Some(match (operator, left, right) {
+        (Add, Value::Int(l), Value::Int(r)) => Value::Int(l.wrapping_add(r)),
+        (Subtract, Value::Int(l), Value::Int(r)) => {
+            Value::Int(l.wrapping_sub(r))
+        }
+        (Multiply, Value::Int(l), Value::Int(r)) => {
+            Value::Int(l.wrapping_mul(r))
+        }
+        (Divide, Value::Int(l), Value::Int(r)) if r != 0 => {
+            Value::Int(l.wrapping_div(r))
+        }
+        (LessThan, Value::Int(l), Value::Int(r)) => Value::Bool(l < r),
+        (LessThanOrEquals, Value::Int(l), Value::Int(r)) => Value::Bool(l <= r),
+        (Equals, l, r) => Value::Bool(l == r),
+        _ => return None,
+    })
+}
@@ -0,0 +1,72 @@
+//! Copy propagation over a function in SSA form: uses of a copy read the
+//! register copied instead, and so do uses of a phi given the same register
+//! on every edge, or itself. The copies and phis are left to dead code
+//! elimination. The object of an attribute store keeps its type, since a
+//! SELF_TYPE attribute takes values of that type.
+
+use super::*;
+
+pub fn propagate_copies(function: &mut Function) -> bool {
+    // The register each register is a copy of, if any.
+    let mut sources: Vec<Option<Register>> =
+        vec![None; function.registers.len()];
+    for block in function.blocks.iter() {
+        for instruction in block.instructions.iter() {
+            match instruction {
+                Instruction::Copy(result, source) if result != source => {
+                    sources[result.0 as usize] = Some(*source);
+                }
+                Instruction::Phi(result, incoming) => {
+                    let mut others = incoming
+                        .iter()
+                        .map(|(_, register)| *register)
+                        .filter(|register| register != result);
+                    if let Some(first) = others.next() {
+                        if others.all(|other| other == first) {
+                            sources[result.0 as usize] = Some(first);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+    let source = |mut register: Register| {
+        // Phis of each other can form cycles, followed only so far.
+        for _ in 0..sources.len() {
+            match sources[register.0 as usize] {
+                Some(source) => register = source,
+                None => break,
+            }
+        }
+        register
+    };
+
+    let mut changed = false;
+    for block in function.blocks.iter_mut() {
+        for instruction in block.instructions.iter_mut() {
+            let kept = match instruction {
+                Instruction::Copy(..) => continue,
+                Instruction::SetAttribute(object, ..) => Some(*object),
+                _ => None,
+            };
+            for operand in instruction.operands_mut() {
+                let mut replacement = source(*operand);
+                if Some(*operand) == kept
+                    && function.registers[replacement.0 as usize]
+                        != function.registers[operand.0 as usize]
+                {
+                    replacement = *operand;
+                }
+                changed |= replacement != *operand;
+                *operand = replacement;
+            }
+        }
+        for operand in block.terminator.operands_mut() {
+            let replacement = source(*operand);
+            changed |= replacement != *operand;
+            *operand = replacement;
+        }
+    }
+    changed
+}
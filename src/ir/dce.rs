@@ -0,0 +1,45 @@
+//! Dead code elimination: pure instructions whose results are never used,
+//! directly or through other instructions that are, are removed.
+
+use super::*;
+
+pub fn eliminate_dead_code(function: &mut Function) -> bool {
+    // The instructions defining each register.
+    let mut definitions = vec![Vec::new(); function.registers.len()];
+    let mut live = Vec::new();
+    let mut work = Vec::new();
+    for (block_index, block) in function.blocks.iter().enumerate() {
+        live.push(vec![false; block.instructions.len()]);
+        for (index, instruction) in block.instructions.iter().enumerate() {
+            if let Some(result) = instruction.result() {
+                definitions[result.0 as usize].push((block_index, index));
+            }
+            if !instruction.is_pure() {
+                live[block_index][index] = true;
+                work.extend(instruction.operands());
+            }
+        }
+        work.extend(block.terminator.operands());
+    }
+    let mut used = vec![false; function.registers.len()];
+    while let Some(register) = work.pop() {
+        if std::mem::replace(&mut used[register.0 as usize], true) {
+            continue;
+        }
+        for &(block, index) in definitions[register.0 as usize].iter() {
+            if !std::mem::replace(&mut live[block][index], true) {
+                let instruction = &function.blocks[block].instructions[index];
+                work.extend(instruction.operands());
+            }
+        }
+    }
+
+    let mut changed = false;
+    for (block, live) in function.blocks.iter_mut().zip(live) {
+        let mut live = live.into_iter();
+        let before = block.instructions.len();
+        block.instructions.retain(|_| live.next().unwrap());
+        changed |= block.instructions.len() != before;
+    }
+    changed
+}
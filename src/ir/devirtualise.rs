@@ -0,0 +1,48 @@
+//! Devirtualisation by class hierarchy analysis: a dispatch on a receiver
+//! whose static class has no subclass overriding the method always runs the
+//! method the class defines or inherits, so it becomes a direct call of
+//! that method. Receivers are checked for void before dispatches, which
+//! calls leave to the check.
+
+use super::*;
+use crate::hierarchy::ClassHierarchy;
+
+/// Make direct calls of the dispatches that can only reach one method,
+/// returning how many there were.
+pub fn devirtualise(
+    function: &mut Function,
+    hierarchy: &ClassHierarchy,
+) -> usize {
+    let mut count = 0;
+    for block in function.blocks.iter_mut() {
+        for instruction in block.instructions.iter_mut() {
+            let Instruction::Dispatch {
+                result,
+                receiver,
+                method,
+                arguments,
+            } = instruction
+            else {
+                continue;
+            };
+            let class = match &function.registers[receiver.0 as usize] {
+                Type::Object(class) => class,
+                _ => continue,
+            };
+            let Some(owner) = hierarchy.sole_implementation(class, method)
+            else {
+                continue;
+            };
+            *instruction = Instruction::Call {
+                result: *result,
+                function: format!("{owner}.{method}"),
+                arguments: [*receiver]
+                    .into_iter()
+                    .chain(arguments.iter().copied())
+                    .collect(),
+            };
+            count += 1;
+        }
+    }
+    count
+}
@@ -0,0 +1,149 @@
+//! Dominators and dominance frontiers of the blocks of a function.
+//!
+//! Immediate dominators are found with the iterative algorithm of Cooper,
+//! Harvey and Kennedy, "A Simple, Fast Dominance Algorithm", over the
+//! blocks reachable from the entry block in reverse postorder.
+
+use super::*;
+
+pub struct Dominance {
+    /// The reachable blocks in reverse postorder, starting with the entry.
+    pub order: Vec<BlockId>,
+    pub predecessors: Vec<Vec<BlockId>>,
+    /// The immediate dominator of each reachable block but the entry.
+    idom: Vec<Option<BlockId>>,
+    /// The blocks each block immediately dominates.
+    pub children: Vec<Vec<BlockId>>,
+}
+
+impl Dominance {
+    pub fn new(function: &Function) -> Dominance {
+        let order = reverse_postorder(function);
+        let predecessors = function.predecessors();
+        let count = function.blocks.len();
+        let mut position = vec![usize::MAX; count];
+        for (index, block) in order.iter().enumerate() {
+            position[block.0 as usize] = index;
+        }
+
+        let mut idom: Vec<Option<BlockId>> = vec![None; count];
+        idom[0] = Some(BlockId(0));
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &block in order.iter().skip(1) {
+                let mut new_idom = None;
+                for &predecessor in predecessors[block.0 as usize].iter() {
+                    if idom[predecessor.0 as usize].is_none() {
+                        continue;
+                    }
+                    new_idom = Some(match new_idom {
+                        None => predecessor,
+                        Some(other) => {
+                            intersect(&idom, &position, predecessor, other)
+                        }
+                    });
+                }
+                if new_idom.is_some() && idom[block.0 as usize] != new_idom {
+                    idom[block.0 as usize] = new_idom;
+                    changed = true;
+                }
+            }
+        }
+        idom[0] = None;
+
+        let mut children = vec![Vec::new(); count];
+        for &block in order.iter().skip(1) {
+            if let Some(parent) = idom[block.0 as usize] {
+                children[parent.0 as usize].push(block);
+            }
+        }
+        Dominance {
+            order,
+            predecessors,
+            idom,
+            children,
+        }
+    }
+
+    pub fn idom(&self, block: BlockId) -> Option<BlockId> {
+        self.idom[block.0 as usize]
+    }
+
+    pub fn is_reachable(&self, block: BlockId) -> bool {
+        block.0 == 0 || self.idom(block).is_some()
+    }
+
+    /// The dominance frontier of each block: the blocks it does not
+    /// strictly dominate but dominates a predecessor of.
+    pub fn frontiers(&self) -> Vec<Vec<BlockId>> {
+        let mut frontiers = vec![Vec::new(); self.idom.len()];
+        for &block in self.order.iter() {
+            let predecessors = &self.predecessors[block.0 as usize];
+            if predecessors.len() < 2 {
+                continue;
+            }
+            for &predecessor in predecessors.iter() {
+                if !self.is_reachable(predecessor) {
+                    continue;
+                }
+                let mut runner = predecessor;
+                while Some(runner) != self.idom(block) {
+                    let frontier: &mut Vec<BlockId> =
+                        &mut frontiers[runner.0 as usize];
+                    if !frontier.contains(&block) {
+                        frontier.push(block);
+                    }
+                    match self.idom(runner) {
+                        Some(parent) => runner = parent,
+                        None => break,
+                    }
+                }
+            }
+        }
+        frontiers
+    }
+}
+
+// The nearest common dominator of two blocks, walking up from the later of
+// the two in reverse postorder.
+fn intersect(
+    idom: &[Option<BlockId>],
+    position: &[usize],
+    mut a: BlockId,
+    mut b: BlockId,
+) -> BlockId {
+    while a != b {
+        while position[a.0 as usize] > position[b.0 as usize] {
+            a = idom[a.0 as usize].unwrap();
+        }
+        while position[b.0 as usize] > position[a.0 as usize] {
+            b = idom[b.0 as usize].unwrap();
+        }
+    }
+    a
+}
+
+/// The blocks reachable from the entry block, in reverse postorder.
+pub fn reverse_postorder(function: &Function) -> Vec<BlockId> {
+    let mut visited = vec![false; function.blocks.len()];
+    let mut order = Vec::new();
+    // Blocks with the index of the next successor to visit.
+    let mut stack = vec![(BlockId(0), 0)];
+    visited[0] = true;
+    while let Some((block, next)) = stack.pop() {
+        let successors = function.block(block).terminator.successors();
+        match successors.get(next) {
+            Some(&successor) => {
+                stack.push((block, next + 1));
+                if !visited[successor.0 as usize] {
+                    visited[successor.0 as usize] = true;
+                    stack.push((successor, 0));
+                }
+            }
+            None => order.push(block),
+        }
+    }
+    order.reverse();
+    order
+}
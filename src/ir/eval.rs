@@ -0,0 +1,619 @@
+//! An evaluator running a module directly, to check that lowering and the
+//! optimisation passes keep the behaviour of programs.
+//!
+//! Objects are reference counted and never freed while in a cycle, and
+//! methods are called on the Rust stack, so it is meant for testing rather
+//! than running long programs. Runtime errors are reported as the native
+//! backends report them.
+
+use super::*;
+use crate::hierarchy::{BOOL, INT, MAIN, STRING};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::io::{BufRead, Write};
+use std::rc::Rc;
+
+#[derive(Debug, PartialEq)]
+pub struct EvalError {
+    pub message: String,
+    /// The exit status the native backends stop with.
+    pub status: i32,
+}
+
+impl Display for EvalError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+type EvalResult<T> = Result<T, EvalError>;
+
+fn error<T>(message: String, status: i32) -> EvalResult<T> {
+    Err(EvalError { message, status })
+}
+
+fn error_at<T>(
+    location: &Location,
+    message: &str,
+    status: i32,
+) -> EvalResult<T> {
+    error(
+        format!("{}:{}: {message}", location.file, location.line),
+        status,
+    )
+}
+
+/// Run the main method of a module on a new Main object.
+pub fn evaluate(
+    module: &Module,
+    input: &mut dyn BufRead,
+    output: &mut dyn Write,
+) -> EvalResult<()> {
+    let mut evaluator = Evaluator::new(module, input, output);
+    let main = evaluator.new_object(evaluator.class_index(MAIN))?;
+    evaluator.dispatch(main, "main", Vec::new())?;
+    evaluator.output.flush().map_err(io_error)?;
+    Ok(())
+}
+
+fn io_error(error: std::io::Error) -> EvalError {
+    EvalError {
+        message: format!("I/O error: {error}."),
+        status: 12,
+    }
+}
+
+#[derive(Clone, Debug)]
+enum Value {
+    Int(i32),
+    Bool(bool),
+    Object(Option<Rc<Object>>),
+}
+
+impl Value {
+    fn int(&self) -> i32 {
+        match self {
+            Value::Int(value) => *value,
+            _ => panic!("expected an integer"),
+        }
+    }
+
+    fn bool(&self) -> bool {
+        match self {
+            Value::Bool(value) => *value,
+            _ => panic!("expected a boolean"),
+        }
+    }
+
+    fn object(&self) -> Option<&Rc<Object>> {
+        match self {
+            Value::Object(object) => object.as_ref(),
+            _ => panic!("expected an object"),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Object {
+    class: usize,
+    attributes: RefCell<Vec<Value>>,
+    data: Data,
+}
+
+// The value of an object of a basic class.
+#[derive(Clone, Debug, PartialEq)]
+enum Data {
+    None,
+    Int(i32),
+    Bool(bool),
+    Str(String),
+}
+
+impl Object {
+    fn int(&self) -> i32 {
+        match self.data {
+            Data::Int(value) => value,
+            _ => panic!("expected an Int"),
+        }
+    }
+
+    fn str(&self) -> &str {
+        match &self.data {
+            Data::Str(string) => string,
+            _ => panic!("expected a String"),
+        }
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Method<'m> {
+    Function(&'m Function),
+    // A method of a basic class, by class and name.
+    Builtin(&'m str, &'m str),
+}
+
+struct Class<'m> {
+    name: &'m str,
+    parent: Option<usize>,
+    // The types of the attributes, inherited ones first, with the slot of
+    // each attribute by name.
+    attributes: Vec<&'m str>,
+    slots: HashMap<&'m str, usize>,
+    methods: HashMap<&'m str, Method<'m>>,
+    init: Option<&'m Function>,
+}
+
+struct Evaluator<'m, 'io> {
+    module: &'m Module,
+    classes: Vec<Class<'m>>,
+    indices: HashMap<&'m str, usize>,
+    functions: HashMap<&'m str, &'m Function>,
+    input: &'io mut dyn BufRead,
+    output: &'io mut dyn Write,
+}
+
+impl<'m, 'io> Evaluator<'m, 'io> {
+    fn new(
+        module: &'m Module,
+        input: &'io mut dyn BufRead,
+        output: &'io mut dyn Write,
+    ) -> Self {
+        let functions: HashMap<&str, &Function> = module
+            .functions
+            .iter()
+            .map(|function| (function.name.as_str(), function))
+            .collect();
+        let mut classes: Vec<Class> = Vec::new();
+        let mut indices = HashMap::new();
+        for decl in module.classes.iter() {
+            let parent =
+                decl.parent.as_ref().map(|parent| indices[parent.as_str()]);
+            let (mut attributes, mut slots, mut methods) = match parent {
+                Some(parent) => {
+                    let parent: &Class = &classes[parent];
+                    (
+                        parent.attributes.clone(),
+                        parent.slots.clone(),
+                        parent.methods.clone(),
+                    )
+                }
+                None => Default::default(),
+            };
+            for (name, type_id) in decl.attributes.iter() {
+                slots.insert(name.as_str(), attributes.len());
+                attributes.push(type_id.as_str());
+            }
+            for method in decl.methods.iter() {
+                let name = format!("{}.{}", decl.name, method.name);
+                let method_impl = match functions.get(name.as_str()) {
+                    Some(function) => Method::Function(function),
+                    None => Method::Builtin(&decl.name, &method.name),
+                };
+                methods.insert(method.name.as_str(), method_impl);
+            }
+            let init = format!("{}.{INIT_METHOD}", decl.name);
+            indices.insert(decl.name.as_str(), classes.len());
+            classes.push(Class {
+                name: &decl.name,
+                parent,
+                attributes,
+                slots,
+                methods,
+                init: functions.get(init.as_str()).copied(),
+            });
+        }
+        Evaluator {
+            module,
+            classes,
+            indices,
+            functions,
+            input,
+            output,
+        }
+    }
+
+    fn class_index(&self, name: &str) -> usize {
+        self.indices[name]
+    }
+
+    fn conforms(&self, mut class: usize, ancestor: usize) -> bool {
+        loop {
+            if class == ancestor {
+                return true;
+            }
+            match self.classes[class].parent {
+                Some(parent) => class = parent,
+                None => return false,
+            }
+        }
+    }
+
+    fn boxed(&self, class: &str, data: Data) -> Value {
+        Value::Object(Some(Rc::new(Object {
+            class: self.class_index(class),
+            attributes: RefCell::new(Vec::new()),
+            data,
+        })))
+    }
+
+    fn string(&self, string: String) -> Value {
+        self.boxed(STRING, Data::Str(string))
+    }
+
+    // An object of a class with its attributes set to their defaults, which
+    // is then initialised.
+    fn new_object(&mut self, class: usize) -> EvalResult<Value> {
+        let data = match self.classes[class].name {
+            INT => Data::Int(0),
+            BOOL => Data::Bool(false),
+            STRING => Data::Str(String::new()),
+            _ => Data::None,
+        };
+        let attributes = self.classes[class]
+            .attributes
+            .iter()
+            .map(|type_id| match *type_id {
+                INT => self.boxed(INT, Data::Int(0)),
+                BOOL => self.boxed(BOOL, Data::Bool(false)),
+                STRING => self.string(String::new()),
+                _ => Value::Object(None),
+            })
+            .collect();
+        let object = Value::Object(Some(Rc::new(Object {
+            class,
+            attributes: RefCell::new(attributes),
+            data,
+        })));
+        match self.classes[class].init {
+            Some(init) => self.call(init, vec![object]),
+            None => Ok(object),
+        }
+    }
+
+    fn dispatch(
+        &mut self,
+        receiver: Value,
+        method: &str,
+        arguments: Vec<Value>,
+    ) -> EvalResult<Value> {
+        // Receivers are checked before dispatches.
+        let class = receiver.object().expect("dispatch to void").class;
+        let method = self.classes[class].methods[method];
+        let arguments = std::iter::once(receiver).chain(arguments).collect();
+        self.invoke(method, arguments)
+    }
+
+    fn invoke(
+        &mut self,
+        method: Method<'m>,
+        arguments: Vec<Value>,
+    ) -> EvalResult<Value> {
+        match method {
+            Method::Function(function) => self.call(function, arguments),
+            Method::Builtin(class, name) => {
+                self.builtin(class, name, arguments)
+            }
+        }
+    }
+
+    fn builtin(
+        &mut self,
+        class: &str,
+        name: &str,
+        arguments: Vec<Value>,
+    ) -> EvalResult<Value> {
+        let receiver = arguments[0].object().unwrap().clone();
+        let argument = |index: usize| arguments[index].object().unwrap();
+        Ok(match (class, name) {
+            (_, "abort") => {
+                let name = self.classes[receiver.class].name;
+                self.output.flush().map_err(io_error)?;
+                return error(format!("Abort called from class {name}"), 11);
+            }
+            (_, "type_name") => {
+                self.string(self.classes[receiver.class].name.to_string())
+            }
+            (_, "copy") => Value::Object(Some(Rc::new(Object {
+                class: receiver.class,
+                attributes: receiver.attributes.clone(),
+                data: receiver.data.clone(),
+            }))),
+            (_, "out_string") => {
+                let string = argument(1).str().as_bytes();
+                self.output.write_all(string).map_err(io_error)?;
+                arguments[0].clone()
+            }
+            (_, "out_int") => {
+                let int = argument(1).int();
+                write!(self.output, "{int}").map_err(io_error)?;
+                arguments[0].clone()
+            }
+            (_, "in_string") => {
+                let line = self.read_line()?;
+                self.string(String::from_utf8_lossy(&line).into_owned())
+            }
+            (_, "in_int") => {
+                let line = self.read_line()?;
+                self.boxed(INT, Data::Int(parse_int(&line)))
+            }
+            (_, "length") => {
+                self.boxed(INT, Data::Int(receiver.str().len() as i32))
+            }
+            (_, "concat") => {
+                self.string(format!("{}{}", receiver.str(), argument(1).str()))
+            }
+            (_, "substr") => {
+                let string = receiver.str();
+                let start = argument(1).int() as i64;
+                let length = argument(2).int() as i64;
+                if start < 0
+                    || length < 0
+                    || start + length > string.len() as i64
+                {
+                    self.output.flush().map_err(io_error)?;
+                    return error("Index out of range in substr.".into(), 10);
+                }
+                let bytes = &string.as_bytes()
+                    [start as usize..(start + length) as usize];
+                self.string(String::from_utf8_lossy(bytes).into_owned())
+            }
+            _ => panic!("no method {class}.{name}"),
+        })
+    }
+
+    // Read a line without its line terminator, which may be CRLF.
+    fn read_line(&mut self) -> EvalResult<Vec<u8>> {
+        self.output.flush().map_err(io_error)?;
+        let mut line = Vec::new();
+        self.input.read_until(b'\n', &mut line).map_err(io_error)?;
+        if line.last() == Some(&b'\n') {
+            line.pop();
+            if line.last() == Some(&b'\r') {
+                line.pop();
+            }
+        }
+        Ok(line)
+    }
+
+    fn call(
+        &mut self,
+        function: &'m Function,
+        arguments: Vec<Value>,
+    ) -> EvalResult<Value> {
+        let mut registers = vec![Value::Object(None); function.registers.len()];
+        for (parameter, argument) in function.parameters.iter().zip(arguments) {
+            registers[parameter.0 as usize] = argument;
+        }
+        let mut block_id = BlockId(0);
+        let mut previous = None;
+        loop {
+            let block = function.block(block_id);
+            // Phis all read their registers before any is set.
+            let phis: Vec<(Register, Value)> = block
+                .instructions
+                .iter()
+                .map_while(|instruction| match instruction {
+                    Instruction::Phi(result, incoming) => {
+                        let (_, register) = incoming
+                            .iter()
+                            .find(|(block, _)| Some(*block) == previous)
+                            .expect("a phi has no register for a predecessor");
+                        Some((*result, registers[register.0 as usize].clone()))
+                    }
+                    _ => None,
+                })
+                .collect();
+            let count = phis.len();
+            for (result, value) in phis {
+                registers[result.0 as usize] = value;
+            }
+            for instruction in block.instructions[count..].iter() {
+                self.execute(instruction, &mut registers)?;
+            }
+            let get = |register: &Register| &registers[register.0 as usize];
+            previous = Some(block_id);
+            block_id = match &block.terminator {
+                Terminator::Jump(target) => *target,
+                Terminator::Branch(condition, then_block, else_block) => {
+                    if get(condition).bool() {
+                        *then_block
+                    } else {
+                        *else_block
+                    }
+                }
+                Terminator::Return(value) => return Ok(get(value).clone()),
+                Terminator::NoMatch(object) => {
+                    let object = get(object).object().unwrap();
+                    let name = self.classes[object.class].name;
+                    self.output.flush().map_err(io_error)?;
+                    return error(
+                        format!("No match in case statement for Class {name}."),
+                        8,
+                    );
+                }
+            };
+        }
+    }
+
+    fn execute(
+        &mut self,
+        instruction: &'m Instruction,
+        registers: &mut [Value],
+    ) -> EvalResult<()> {
+        use Instruction::*;
+        let get = |register: &Register| registers[register.0 as usize].clone();
+        let value = match instruction {
+            Int(_, value) => Value::Int(*value),
+            Bool(_, value) => Value::Bool(*value),
+            String(_, string) => self.string(string.clone()),
+            Void(_) => Value::Object(None),
+            Copy(_, source) | Cast(_, source) => get(source),
+            Unary(_, UnaryOperator::Negate, operand) => {
+                Value::Int(get(operand).int().wrapping_neg())
+            }
+            Unary(_, UnaryOperator::Not, operand) => {
+                Value::Bool(!get(operand).bool())
+            }
+            Binary(_, operator, left, right) => {
+                binary(*operator, get(left), get(right))
+            }
+            IsVoid(_, object) => Value::Bool(get(object).object().is_none()),
+            Equal(_, left, right) => {
+                let equal = match (get(left).object(), get(right).object()) {
+                    (None, None) => true,
+                    (Some(left), Some(right)) => {
+                        Rc::ptr_eq(left, right)
+                            || left.class == right.class
+                                && left.data != Data::None
+                                && left.data == right.data
+                    }
+                    _ => false,
+                };
+                Value::Bool(equal)
+            }
+            Box(_, value) => match get(value) {
+                Value::Int(value) => self.boxed(INT, Data::Int(value)),
+                Value::Bool(value) => self.boxed(BOOL, Data::Bool(value)),
+                Value::Object(_) => panic!("expected a value to box"),
+            },
+            Unbox(_, object) => match get(object).object().unwrap().data {
+                Data::Int(value) => Value::Int(value),
+                Data::Bool(value) => Value::Bool(value),
+                _ => panic!("expected an Int or Bool"),
+            },
+            GetAttribute(_, object, name) => {
+                let object = get(object);
+                let object = object.object().unwrap();
+                let slot = self.classes[object.class].slots[name.as_str()];
+                let value = object.attributes.borrow()[slot].clone();
+                value
+            }
+            SetAttribute(object, name, value) => {
+                let object = get(object);
+                let object = object.object().unwrap();
+                let slot = self.classes[object.class].slots[name.as_str()];
+                object.attributes.borrow_mut()[slot] = get(value);
+                return Ok(());
+            }
+            New(_, class) => self.new_object(self.class_index(class))?,
+            NewSelfType(_, object) => {
+                let class = get(object).object().unwrap().class;
+                self.new_object(class)?
+            }
+            Dispatch {
+                receiver,
+                method,
+                arguments,
+                ..
+            } => {
+                let arguments = arguments.iter().map(get).collect();
+                self.dispatch(get(receiver), method, arguments)?
+            }
+            Call {
+                function,
+                arguments,
+                ..
+            } => {
+                let arguments = arguments.iter().map(get).collect();
+                let method = match self.functions.get(function.as_str()) {
+                    Some(function) => Method::Function(function),
+                    None => {
+                        let (class, name) = function.rsplit_once('.').unwrap();
+                        let class = self.module.class(class).unwrap();
+                        let name = class
+                            .methods
+                            .iter()
+                            .find(|method| method.name == name)
+                            .unwrap();
+                        Method::Builtin(&class.name, &name.name)
+                    }
+                };
+                self.invoke(method, arguments)?
+            }
+            InstanceOf(_, object, class) => {
+                let object = get(object);
+                let object = object.object().unwrap();
+                Value::Bool(
+                    self.conforms(object.class, self.class_index(class)),
+                )
+            }
+            CheckVoid(object, check, location) => {
+                if get(object).object().is_some() {
+                    return Ok(());
+                }
+                self.output.flush().map_err(io_error)?;
+                return match check {
+                    VoidCheck::Dispatch(method) => error_at(
+                        location,
+                        &format!("Dispatch to void calling method {method}."),
+                        5,
+                    ),
+                    VoidCheck::StaticDispatch(method) => error_at(
+                        location,
+                        &format!(
+                            "Static dispatch to void calling method {method}."
+                        ),
+                        6,
+                    ),
+                    VoidCheck::Case => error_at(
+                        location,
+                        "Match on void in case statement.",
+                        7,
+                    ),
+                };
+            }
+            CheckZero(value, location) => {
+                if get(value).int() != 0 {
+                    return Ok(());
+                }
+                self.output.flush().map_err(io_error)?;
+                return error_at(location, "Division by zero.", 9);
+            }
+            Phi(..) => panic!("a phi is not at the start of its block"),
+        };
+        let result = instruction.result().unwrap();
+        registers[result.0 as usize] = value;
+        Ok(())
+    }
+}
+
+fn binary(operator: BinaryOperator, left: Value, right: Value) -> Value {
+    use BinaryOperator::*;
+    match (operator, left, right) {
+        (Equals, Value::Bool(left), Value::Bool(right)) => {
+            Value::Bool(left == right)
+        }
+        (operator, left, right) => {
+            let (left, right) = (left.int(), right.int());
+            match operator {
+                Add => Value::Int(left.wrapping_add(right)),
+                Subtract => Value::Int(left.wrapping_sub(right)),
+                Multiply => Value::Int(left.wrapping_mul(right)),
+                Divide => Value::Int(left.wrapping_div(right)),
+                LessThan => Value::Bool(left < right),
+                LessThanOrEquals => Value::Bool(left <= right),
+                Equals => Value::Bool(left == right),
+            }
+        }
+    }
+}
+
+// Leading whitespace is skipped, then an optional sign and digits are read,
+// wrapping on overflow. Anything else yields 0.
+fn parse_int(line: &[u8]) -> i32 {
+    let mut chars = line
+        .iter()
+        .skip_while(|c| **c == b' ' || (b'\t'..=b'\r').contains(*c))
+        .peekable();
+    let negative = chars.next_if(|c| **c == b'-' || **c == b'+') == Some(&b'-');
+    let mut value = 0i32;
+    for c in chars.take_while(|c| c.is_ascii_digit()) {
+        value = value.wrapping_mul(10).wrapping_add((c - b'0') as i32);
+    }
+    if negative {
+        value.wrapping_neg()
+    } else {
+        value
+    }
+}
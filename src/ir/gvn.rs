@@ -0,0 +1,135 @@
+//! Global value numbering over a function in SSA form, by walking the
+//! dominator tree with a table of the pure instructions seen on the way
+//! down. An instruction computing what one dominating it already has is
+//! dropped, and its uses read the earlier register instead. A void or zero
+//! check of a register checked on every path to it is dropped too.
+
+use super::dominance::Dominance;
+use super::*;
+use std::collections::HashMap;
+
+pub fn number_values(function: &mut Function) -> bool {
+    let dominance = Dominance::new(function);
+    let mut numbering = Numbering {
+        table: HashMap::new(),
+        scopes: Vec::new(),
+        replacements: HashMap::new(),
+        changed: false,
+    };
+    numbering.visit(function, &dominance, BlockId(0));
+    numbering.changed
+}
+
+// Instructions are looked up with the result replaced by this register,
+// along with the type of the result.
+const ANY: Register = Register(u32::MAX);
+
+// What an instruction is numbered by. A check passing on a register makes
+// any later check of it redundant, whatever error the later check would
+// report and where, so checks are numbered by the register they check.
+#[derive(Clone, PartialEq, Eq, Hash)]
+enum Key {
+    Value(Instruction, Type),
+    VoidCheck(Register),
+    ZeroCheck(Register),
+}
+
+struct Numbering {
+    table: HashMap<Key, Register>,
+    // The keys added in each block on the path from the entry.
+    scopes: Vec<Vec<Key>>,
+    // The register to read instead of each dropped one.
+    replacements: HashMap<Register, Register>,
+    changed: bool,
+}
+
+impl Numbering {
+    fn replace(&self, register: &mut Register) {
+        if let Some(replacement) = self.replacements.get(register) {
+            *register = *replacement;
+        }
+    }
+
+    fn visit(
+        &mut self,
+        function: &mut Function,
+        dominance: &Dominance,
+        block_id: BlockId,
+    ) {
+        let mut added = Vec::new();
+        let mut instructions =
+            std::mem::take(&mut function.block_mut(block_id).instructions);
+        instructions.retain_mut(|instruction| {
+            if !matches!(instruction, Instruction::Phi(..)) {
+                for operand in instruction.operands_mut() {
+                    self.replace(operand);
+                }
+            }
+            let key = match key(instruction, function) {
+                Some(key) => key,
+                None => return true,
+            };
+            match (self.table.get(&key), instruction.result()) {
+                (Some(existing), Some(result)) => {
+                    self.replacements.insert(result, *existing);
+                    self.changed = true;
+                    false
+                }
+                (Some(_), None) => {
+                    self.changed = true;
+                    false
+                }
+                (None, result) => {
+                    self.table.insert(key.clone(), result.unwrap_or(ANY));
+                    added.push(key);
+                    true
+                }
+            }
+        });
+        let block = function.block_mut(block_id);
+        block.instructions = instructions;
+        for operand in block.terminator.operands_mut() {
+            self.replace(operand);
+        }
+        // Phis read their registers at the end of this block.
+        for successor in block.terminator.successors() {
+            let mut phis =
+                std::mem::take(&mut function.block_mut(successor).instructions);
+            for phi in phis.iter_mut() {
+                if let Instruction::Phi(_, incoming) = phi {
+                    for (predecessor, register) in incoming.iter_mut() {
+                        if *predecessor == block_id {
+                            self.replace(register);
+                        }
+                    }
+                }
+            }
+            function.block_mut(successor).instructions = phis;
+        }
+
+        self.scopes.push(added);
+        for &child in dominance.children[block_id.0 as usize].iter() {
+            self.visit(function, dominance, child);
+        }
+        for key in self.scopes.pop().unwrap() {
+            self.table.remove(&key);
+        }
+    }
+}
+
+// The key of an instruction to number, if it is pure or a check.
+fn key(instruction: &Instruction, function: &Function) -> Option<Key> {
+    use Instruction::*;
+    let mut key = match instruction {
+        Int(..) | Bool(..) | String(..) | Unary(..) | Binary(..)
+        | IsVoid(..) | Equal(..) | Box(..) | Unbox(..) | InstanceOf(..)
+        | Cast(..) => instruction.clone(),
+        CheckVoid(object, _, _) => return Some(Key::VoidCheck(*object)),
+        CheckZero(value, _) => return Some(Key::ZeroCheck(*value)),
+        _ => return None,
+    };
+    let result = key.result_mut().expect("pure instructions have a result");
+    let type_id = function.register_type(*result).clone();
+    *result = ANY;
+    Some(Key::Value(key, type_id))
+}
@@ -0,0 +1,151 @@
+//! Inlining of direct calls to small functions. The block holding a call
+//! is split after it, the callee's blocks are copied in between with fresh
+//! registers, and its returns jump to the second half, which takes the
+//! value returned.
+
+use super::passes::Context;
+use super::ssa::to_ssa;
+use super::*;
+
+// The most instructions a function may have to be inlined.
+const SIZE_LIMIT: usize = 12;
+
+// The most calls inlined into a function at a time, so that functions
+// calling each other do not grow without bound.
+const CALL_LIMIT: usize = 16;
+
+/// Inline the calls of a function to small functions other than itself,
+/// returning how many were inlined.
+pub fn inline_calls(function: &mut Function, context: &Context) -> usize {
+    let mut count = 0;
+    while count < CALL_LIMIT {
+        let site = function.blocks.iter().enumerate().find_map(|(b, block)| {
+            block.instructions.iter().enumerate().find_map(|(i, call)| {
+                let callee = match call {
+                    Instruction::Call { function: name, .. } => {
+                        context.function(name)?
+                    }
+                    _ => return None,
+                };
+                let size: usize = callee
+                    .blocks
+                    .iter()
+                    .map(|block| block.instructions.len())
+                    .sum();
+                let returns = callee.blocks.iter().any(|block| {
+                    matches!(block.terminator, Terminator::Return(_))
+                });
+                (size <= SIZE_LIMIT && returns).then_some((b, i, callee))
+            })
+        });
+        match site {
+            Some((block, index, callee)) => {
+                inline(function, BlockId(block as u32), index, callee.clone());
+                count += 1;
+            }
+            None => break,
+        }
+    }
+    count
+}
+
+fn inline(
+    function: &mut Function,
+    block_id: BlockId,
+    index: usize,
+    mut callee: Function,
+) {
+    to_ssa(&mut callee);
+    let block = function.block_mut(block_id);
+    let after = block.instructions.split_off(index + 1);
+    let (result, arguments) = match block.instructions.pop() {
+        Some(Instruction::Call {
+            result, arguments, ..
+        }) => (result, arguments),
+        _ => unreachable!("inlining a call that is not there"),
+    };
+
+    // The callee's registers and blocks follow the caller's.
+    let registers: Vec<Register> = callee
+        .registers
+        .iter()
+        .map(|type_id| function.new_register(type_id.clone()))
+        .collect();
+    let register = |r: Register| registers[r.0 as usize];
+    let first_block = function.blocks.len() as u32;
+    let rest = BlockId(first_block + callee.blocks.len() as u32);
+
+    // The arguments are copied to the parameters, whose types may be less
+    // specific.
+    let block = function.block_mut(block_id);
+    for (parameter, argument) in callee.parameters.iter().zip(arguments) {
+        block
+            .instructions
+            .push(Instruction::Copy(register(*parameter), argument));
+    }
+    let terminator = std::mem::replace(
+        &mut block.terminator,
+        Terminator::Jump(BlockId(first_block)),
+    );
+    // The successors of the call's block are now those of the rest of it.
+    for successor in terminator.successors() {
+        for instruction in function.block_mut(successor).instructions.iter_mut()
+        {
+            if let Instruction::Phi(_, incoming) = instruction {
+                for (predecessor, _) in incoming.iter_mut() {
+                    if *predecessor == block_id {
+                        *predecessor = rest;
+                    }
+                }
+            }
+        }
+    }
+
+    let mut returns = Vec::new();
+    for (number, mut block) in callee.blocks.into_iter().enumerate() {
+        for instruction in block.instructions.iter_mut() {
+            if let Some(result) = instruction.result_mut() {
+                *result = register(*result);
+            }
+            for operand in instruction.operands_mut() {
+                *operand = register(*operand);
+            }
+            if let Instruction::Phi(_, incoming) = instruction {
+                for (predecessor, _) in incoming.iter_mut() {
+                    predecessor.0 += first_block;
+                }
+            }
+        }
+        for operand in block.terminator.operands_mut() {
+            *operand = register(*operand);
+        }
+        for successor in block.terminator.successors_mut() {
+            successor.0 += first_block;
+        }
+        if let Terminator::Return(value) = block.terminator {
+            returns.push((BlockId(first_block + number as u32), value));
+            block.terminator = Terminator::Jump(rest);
+        }
+        function.blocks.push(block);
+    }
+
+    // The value returned has the callee's return type, which the result of
+    // the call may be more specific than when it returns SELF_TYPE.
+    let value = function.new_register(callee.return_type.clone());
+    let mut instructions = vec![match returns.as_slice() {
+        [(_, returned)] => Instruction::Copy(value, *returned),
+        _ => Instruction::Phi(value, returns),
+    }];
+    instructions.push(
+        if *function.register_type(result) == callee.return_type {
+            Instruction::Copy(result, value)
+        } else {
+            Instruction::Cast(result, value)
+        },
+    );
+    instructions.extend(after);
+    function.blocks.push(Block {
+        instructions,
+        terminator,
+    });
+}
@@ -0,0 +1,595 @@
+//! Lowering of a typed program into a module.
+//!
+//! Every expression is lowered into a register holding an object, so that
+//! literals are boxed as soon as they are made and operators unbox their
+//! operands. Variables live in registers, which are assigned by copies, and
+//! reading a variable copies it so that later assignments do not change
+//! the value read.
+
+use super::*;
+use crate::codegen::Layout;
+use crate::hierarchy::*;
+use crate::ptree::{
+    self, CaseBranch, Class, Expression, ExpressionData, FeatureData, Formal,
+    Program,
+};
+use crate::semant::SymbolTable;
+use crate::tokens::Span;
+use std::collections::HashMap;
+use ExpressionData::*;
+
+/// Lower a program that has passed semantic analysis.
+pub fn lower(program: &Program, hierarchy: &ClassHierarchy) -> Module {
+    let layout = Layout::new(hierarchy);
+    let classes = layout
+        .classes
+        .iter()
+        .map(|class| {
+            let info = hierarchy.get(&class.name).expect("known class");
+            ClassDecl {
+                name: class.name.clone(),
+                parent: info.parent.clone(),
+                attributes: info
+                    .attributes
+                    .iter()
+                    .map(|attr| (attr.name.clone(), attr.type_id.clone()))
+                    .collect(),
+                methods: info
+                    .methods
+                    .iter()
+                    .map(|method| MethodDecl {
+                        name: method.name.clone(),
+                        parameters: method
+                            .formals
+                            .iter()
+                            .map(|(_, type_id)| type_id.clone())
+                            .collect(),
+                        return_type: method.return_type.clone(),
+                    })
+                    .collect(),
+            }
+        })
+        .collect();
+
+    let mut lowering = Lowering {
+        layout: &layout,
+        hierarchy,
+        initialised: HashMap::new(),
+        functions: Vec::new(),
+        builder: FunctionBuilder::default(),
+        class: String::new(),
+        scopes: SymbolTable::new(),
+    };
+    lowering.find_initialisers(program);
+    for class in layout.classes.iter() {
+        if let Some(class) = lowering.initialised.get(class.name.as_str()) {
+            lowering.initialiser(class);
+        }
+    }
+    for class in program.classes.iter() {
+        for feature in class.features.iter() {
+            if let FeatureData::Method(name, return_type, formals, body) =
+                &feature.data
+            {
+                lowering.method(&class.name, name, return_type, formals, body);
+            }
+        }
+    }
+    Module {
+        classes,
+        functions: lowering.functions,
+    }
+}
+
+// The function being built, with the block instructions are added to.
+#[derive(Default)]
+struct FunctionBuilder {
+    registers: Vec<Type>,
+    blocks: Vec<(Vec<Instruction>, Option<Terminator>)>,
+    current: usize,
+}
+
+impl FunctionBuilder {
+    fn register(&mut self, type_id: Type) -> Register {
+        self.registers.push(type_id);
+        Register(self.registers.len() as u32 - 1)
+    }
+
+    fn block(&mut self) -> BlockId {
+        self.blocks.push((Vec::new(), None));
+        BlockId(self.blocks.len() as u32 - 1)
+    }
+
+    fn switch_to(&mut self, block: BlockId) {
+        self.current = block.0 as usize;
+    }
+
+    fn add(&mut self, instruction: Instruction) {
+        self.blocks[self.current].0.push(instruction);
+    }
+
+    fn terminate(&mut self, terminator: Terminator) {
+        self.blocks[self.current].1 = Some(terminator);
+    }
+}
+
+struct Lowering<'l, 'a> {
+    layout: &'l Layout,
+    hierarchy: &'l ClassHierarchy<'a>,
+    // The user-defined classes with an initialiser, which are those
+    // initialising an attribute or inheriting from such a class.
+    initialised: HashMap<&'l str, &'l Class<'a>>,
+    functions: Vec<Function>,
+    builder: FunctionBuilder,
+    // The class and the variables of the function being lowered.
+    class: String,
+    scopes: SymbolTable<String, Register>,
+}
+
+impl<'l, 'a> Lowering<'l, 'a> {
+    fn find_initialisers(&mut self, program: &'l Program<'a>) {
+        let definitions: HashMap<&str, &Class> = program
+            .classes
+            .iter()
+            .map(|class| (class.name.as_str(), class))
+            .collect();
+        // Parents come before their subclasses in the layout.
+        for class in self.layout.classes.iter() {
+            let Some(definition) = definitions.get(class.name.as_str()) else {
+                continue;
+            };
+            let initialises = definition.features.iter().any(|feature| {
+                matches!(feature.data, FeatureData::Attribute(_, _, Some(_)))
+            });
+            let parent = &definition.super_class_name;
+            if initialises || self.initialised.contains_key(parent.as_str()) {
+                self.initialised.insert(&definition.name, definition);
+            }
+        }
+    }
+
+    fn object_type(&self, type_id: &str) -> Type {
+        if type_id == SELF_TYPE {
+            Type::Object(self.class.clone())
+        } else {
+            Type::Object(type_id.to_string())
+        }
+    }
+
+    fn static_type(&self, expr: &Expression) -> Type {
+        self.object_type(expr.static_type.as_deref().expect("typed tree"))
+    }
+
+    fn register(&mut self, type_id: Type) -> Register {
+        self.builder.register(type_id)
+    }
+
+    fn add(&mut self, instruction: Instruction) {
+        self.builder.add(instruction)
+    }
+
+    // Lower a function of a class taking self and parameters, whose body
+    // is lowered by `body` into the register it returns.
+    fn function(
+        &mut self,
+        name: String,
+        parameters: &[(&str, &str)],
+        return_type: &str,
+        body: impl FnOnce(&mut Self) -> Register,
+    ) {
+        self.builder = FunctionBuilder::default();
+        self.scopes.enter_scope();
+        let receiver = self.register(self.object_type(SELF_TYPE));
+        self.scopes.insert(SELF.to_string(), receiver);
+        let mut registers = vec![receiver];
+        for (name, type_id) in parameters {
+            let register = self.register(self.object_type(type_id));
+            self.scopes.insert(name.to_string(), register);
+            registers.push(register);
+        }
+        let entry = self.builder.block();
+        self.builder.switch_to(entry);
+        let result = body(self);
+        self.builder.terminate(Terminator::Return(result));
+        self.scopes.exit_scope();
+
+        let builder = std::mem::take(&mut self.builder);
+        let blocks = builder
+            .blocks
+            .into_iter()
+            .map(|(instructions, terminator)| super::Block {
+                instructions,
+                terminator: terminator.expect("terminated block"),
+            })
+            .collect();
+        self.functions.push(Function {
+            name,
+            parameters: registers,
+            return_type: self.object_type(return_type),
+            registers: builder.registers,
+            blocks,
+        });
+    }
+
+    // Initialisers run the initialiser of the parent, if it has one, and
+    // then the initialisations of the class in order, returning self.
+    fn initialiser(&mut self, class: &Class) {
+        self.class = class.name.clone();
+        let parent = &class.super_class_name;
+        let parent = self
+            .initialised
+            .contains_key(parent.as_str())
+            .then(|| format!("{parent}.{INIT_METHOD}"));
+        let name = format!("{}.{INIT_METHOD}", class.name);
+        self.function(name, &[], SELF_TYPE, |l| {
+            let receiver = *l.scopes.lookup(SELF).expect("self");
+            if let Some(parent) = parent {
+                let result = l.register(l.object_type(SELF_TYPE));
+                l.add(Instruction::Call {
+                    result,
+                    function: parent,
+                    arguments: vec![receiver],
+                });
+            }
+            for feature in class.features.iter() {
+                if let FeatureData::Attribute(attr, _, Some(init)) =
+                    &feature.data
+                {
+                    let value = l.expression(init);
+                    l.add(Instruction::SetAttribute(
+                        receiver,
+                        attr.clone(),
+                        value,
+                    ));
+                }
+            }
+            receiver
+        });
+    }
+
+    fn method(
+        &mut self,
+        class: &str,
+        name: &str,
+        return_type: &str,
+        formals: &[Formal],
+        body: &Expression,
+    ) {
+        self.class = class.to_string();
+        let parameters: Vec<(&str, &str)> = formals
+            .iter()
+            .map(|formal| (formal.name.as_str(), formal.type_id.as_str()))
+            .collect();
+        let name = format!("{class}.{name}");
+        self.function(name, &parameters, return_type, |l| l.expression(body));
+    }
+
+    fn location(span: Span) -> Location {
+        Location {
+            file: span.extra.to_string(),
+            line: span.location_line(),
+        }
+    }
+
+    fn int(&mut self, value: i32) -> Register {
+        let int = self.register(Type::Int);
+        self.add(Instruction::Int(int, value));
+        self.boxed(int, INT)
+    }
+
+    fn bool(&mut self, value: bool) -> Register {
+        let bool = self.register(Type::Bool);
+        self.add(Instruction::Bool(bool, value));
+        self.boxed(bool, BOOL)
+    }
+
+    fn boxed(&mut self, value: Register, class: &str) -> Register {
+        let object = self.register(self.object_type(class));
+        self.add(Instruction::Box(object, value));
+        object
+    }
+
+    fn unboxed(&mut self, object: Register, type_id: Type) -> Register {
+        let value = self.register(type_id);
+        self.add(Instruction::Unbox(value, object));
+        value
+    }
+
+    fn copy(&mut self, destination: Register, source: Register) {
+        self.add(Instruction::Copy(destination, source));
+    }
+
+    fn default_value(&mut self, type_id: &str) -> Register {
+        match type_id {
+            INT => self.int(0),
+            BOOL => self.bool(false),
+            STRING => {
+                let string = self.register(self.object_type(STRING));
+                self.add(Instruction::String(string, String::new()));
+                string
+            }
+            _ => {
+                let void = self.register(self.object_type(type_id));
+                self.add(Instruction::Void(void));
+                void
+            }
+        }
+    }
+
+    fn expression(&mut self, expr: &Expression) -> Register {
+        match &expr.data {
+            Block(expressions) => {
+                let mut result = None;
+                for expression in expressions.iter() {
+                    result = Some(self.expression(expression));
+                }
+                result.expect("non-empty block")
+            }
+            Conditional(if_expr, then_expr, else_expr) => {
+                let condition = self.expression(if_expr);
+                let condition = self.unboxed(condition, Type::Bool);
+                let result = self.register(self.static_type(expr));
+                let then_block = self.builder.block();
+                let else_block = self.builder.block();
+                let end = self.builder.block();
+                self.builder.terminate(Terminator::Branch(
+                    condition, then_block, else_block,
+                ));
+                for (block, branch) in
+                    [(then_block, then_expr), (else_block, else_expr)]
+                {
+                    self.builder.switch_to(block);
+                    let value = self.expression(branch);
+                    self.copy(result, value);
+                    self.builder.terminate(Terminator::Jump(end));
+                }
+                self.builder.switch_to(end);
+                result
+            }
+            Loop(cond_expr, loop_expr) => {
+                let header = self.builder.block();
+                let body = self.builder.block();
+                let end = self.builder.block();
+                self.builder.terminate(Terminator::Jump(header));
+                self.builder.switch_to(header);
+                let condition = self.expression(cond_expr);
+                let condition = self.unboxed(condition, Type::Bool);
+                self.builder
+                    .terminate(Terminator::Branch(condition, body, end));
+                self.builder.switch_to(body);
+                self.expression(loop_expr);
+                self.builder.terminate(Terminator::Jump(header));
+                self.builder.switch_to(end);
+                let result = self.register(self.object_type(OBJECT));
+                self.add(Instruction::Void(result));
+                result
+            }
+            Case(case_expr, branches) => self.case(expr, case_expr, branches),
+            Let(ident, type_id, opt_bind, body) => {
+                let value = match &**opt_bind {
+                    Some(bind) => self.expression(bind),
+                    None => self.default_value(type_id),
+                };
+                let variable = self.register(self.object_type(type_id));
+                self.copy(variable, value);
+                self.scopes.enter_scope();
+                self.scopes.insert(ident.clone(), variable);
+                let result = self.expression(body);
+                self.scopes.exit_scope();
+                result
+            }
+            New(type_id) => {
+                let result = self.register(self.object_type(type_id));
+                if type_id == SELF_TYPE {
+                    let receiver = *self.scopes.lookup(SELF).expect("self");
+                    self.add(Instruction::NewSelfType(result, receiver));
+                } else {
+                    self.add(Instruction::New(result, type_id.clone()));
+                }
+                result
+            }
+            Assign(ident, value) => {
+                let value = self.expression(value);
+                match self.scopes.lookup(ident) {
+                    Some(variable) => self.copy(*variable, value),
+                    None => {
+                        let receiver = *self.scopes.lookup(SELF).expect("self");
+                        self.add(Instruction::SetAttribute(
+                            receiver,
+                            ident.clone(),
+                            value,
+                        ));
+                    }
+                }
+                value
+            }
+            UnaryOperation(ptree::UnaryOperator::IsVoid, operand) => {
+                let operand = self.expression(operand);
+                let result = self.register(Type::Bool);
+                self.add(Instruction::IsVoid(result, operand));
+                self.boxed(result, BOOL)
+            }
+            UnaryOperation(operator, operand) => {
+                let (operator, type_id, class) = match operator {
+                    ptree::UnaryOperator::Negative => {
+                        (UnaryOperator::Negate, Type::Int, INT)
+                    }
+                    _ => (UnaryOperator::Not, Type::Bool, BOOL),
+                };
+                let operand = self.expression(operand);
+                let operand = self.unboxed(operand, type_id.clone());
+                let result = self.register(type_id);
+                self.add(Instruction::Unary(result, operator, operand));
+                self.boxed(result, class)
+            }
+            BinaryOperation(operator, operand1, operand2) => {
+                self.binary_operation(expr, operator, operand1, operand2)
+            }
+            MethodCall(callee, static_type, ident, params) => {
+                self.dispatch(expr, callee, static_type, ident, params)
+            }
+            Object(ident) => {
+                let result = self.register(self.static_type(expr));
+                match self.scopes.lookup(ident) {
+                    Some(variable) => self.copy(result, *variable),
+                    None => {
+                        let receiver = *self.scopes.lookup(SELF).expect("self");
+                        self.add(Instruction::GetAttribute(
+                            result,
+                            receiver,
+                            ident.clone(),
+                        ));
+                    }
+                }
+                result
+            }
+            IntLiteral(integer) => self.int(*integer),
+            StrLiteral(string) => {
+                let result = self.register(self.object_type(STRING));
+                self.add(Instruction::String(result, string.clone()));
+                result
+            }
+            BoolLiteral(value) => self.bool(*value),
+        }
+    }
+
+    fn binary_operation(
+        &mut self,
+        expr: &Expression,
+        operator: &ptree::BinaryOperator,
+        operand1: &Expression,
+        operand2: &Expression,
+    ) -> Register {
+        use BinaryOperator::*;
+        let value1 = self.expression(operand1);
+        let value2 = self.expression(operand2);
+        let (operator, class) = match operator {
+            ptree::BinaryOperator::Add => (Add, INT),
+            ptree::BinaryOperator::Subtract => (Subtract, INT),
+            ptree::BinaryOperator::Multiply => (Multiply, INT),
+            ptree::BinaryOperator::Divide => (Divide, INT),
+            ptree::BinaryOperator::LessThan => (LessThan, BOOL),
+            ptree::BinaryOperator::LessThanOrEquals => (LessThanOrEquals, BOOL),
+            ptree::BinaryOperator::Equals => {
+                // Ints and Bools are only compared with objects of the same
+                // class, so their values are compared unboxed.
+                let operand_type = match operand1.type_name() {
+                    INT => Type::Int,
+                    BOOL => Type::Bool,
+                    _ => {
+                        let result = self.register(Type::Bool);
+                        self.add(Instruction::Equal(result, value1, value2));
+                        return self.boxed(result, BOOL);
+                    }
+                };
+                let value1 = self.unboxed(value1, operand_type.clone());
+                let value2 = self.unboxed(value2, operand_type);
+                let result = self.register(Type::Bool);
+                self.add(Instruction::Binary(result, Equals, value1, value2));
+                return self.boxed(result, BOOL);
+            }
+        };
+        let value1 = self.unboxed(value1, Type::Int);
+        let value2 = self.unboxed(value2, Type::Int);
+        if operator == Divide {
+            let location = Self::location(expr.location);
+            self.add(Instruction::CheckZero(value2, location));
+        }
+        let result = self.register(match class {
+            INT => Type::Int,
+            _ => Type::Bool,
+        });
+        self.add(Instruction::Binary(result, operator, value1, value2));
+        self.boxed(result, class)
+    }
+
+    // Arguments are evaluated before the receiver. Static dispatches call
+    // the implementation in the class they name.
+    fn dispatch(
+        &mut self,
+        expr: &Expression,
+        callee: &Expression,
+        static_type: &Option<String>,
+        ident: &str,
+        params: &[Expression],
+    ) -> Register {
+        let arguments: Vec<Register> =
+            params.iter().map(|param| self.expression(param)).collect();
+        let receiver = self.expression(callee);
+        let location = Self::location(expr.location);
+        let check = match static_type {
+            Some(_) => VoidCheck::StaticDispatch(ident.to_string()),
+            None => VoidCheck::Dispatch(ident.to_string()),
+        };
+        self.add(Instruction::CheckVoid(receiver, check, location));
+        let result = self.register(self.static_type(expr));
+        let instruction = match static_type {
+            Some(type_id) => {
+                let class = self.layout.class(type_id);
+                let slot = class.slot(ident).expect("method slot");
+                let owner = &class.methods[slot].1;
+                Instruction::Call {
+                    result,
+                    function: format!("{owner}.{ident}"),
+                    arguments: [receiver]
+                        .into_iter()
+                        .chain(arguments)
+                        .collect(),
+                }
+            }
+            None => Instruction::Dispatch {
+                result,
+                receiver,
+                method: ident.to_string(),
+                arguments,
+            },
+        };
+        self.add(instruction);
+        result
+    }
+
+    // Branches are tried from the most specific class, so that the first
+    // branch the object is an instance of is the closest match.
+    fn case(
+        &mut self,
+        expr: &Expression,
+        case_expr: &Expression,
+        branches: &[CaseBranch],
+    ) -> Register {
+        let object = self.expression(case_expr);
+        let location = Self::location(expr.location);
+        self.add(Instruction::CheckVoid(object, VoidCheck::Case, location));
+        let result = self.register(self.static_type(expr));
+        let end = self.builder.block();
+
+        let mut branches: Vec<&CaseBranch> = branches.iter().collect();
+        branches.sort_by_key(|branch| {
+            std::cmp::Reverse(self.hierarchy.depth(&branch.type_id))
+        });
+        for branch in branches {
+            let matches = self.register(Type::Bool);
+            self.add(Instruction::InstanceOf(
+                matches,
+                object,
+                branch.type_id.clone(),
+            ));
+            let matched = self.builder.block();
+            let next = self.builder.block();
+            self.builder
+                .terminate(Terminator::Branch(matches, matched, next));
+            self.builder.switch_to(matched);
+            let variable = self.register(self.object_type(&branch.type_id));
+            self.add(Instruction::Cast(variable, object));
+            self.scopes.enter_scope();
+            self.scopes.insert(branch.ident.clone(), variable);
+            let value = self.expression(&branch.expression);
+            self.scopes.exit_scope();
+            self.copy(result, value);
+            self.builder.terminate(Terminator::Jump(end));
+            self.builder.switch_to(next);
+        }
+        self.builder.terminate(Terminator::NoMatch(object));
+        self.builder.switch_to(end);
+        result
+    }
+}
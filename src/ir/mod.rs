@@ -0,0 +1,444 @@
+//! A mid-level intermediate representation between the typed parse tree and
+//! the backends: a control flow graph of basic blocks holding three-address
+//! instructions over typed virtual registers.
+//!
+//! Registers hold either unboxed values, of type `i32` or `bool`, or
+//! references to objects, typed by their static class. Values of Cool's Int
+//! and Bool classes are objects, so arithmetic unboxes its operands and
+//! boxes its result explicitly. Allocation, dispatch, void checks and the
+//! division by zero check are also explicit instructions.
+//!
+//! A [`Module`] declares every class of the program, with the attributes and
+//! method signatures it defines, and holds a [`Function`] per method and
+//! per class with attribute initialisers. [`lower`] builds one from a
+//! program that has passed semantic analysis. Modules are printed in a
+//! textual form, which [`parse_module`] reads back, and are checked with
+//! [`verify`].
+//!
+//! Functions can be put in SSA form, with phis joining the registers
+//! defined along different paths, and optimised by the passes of a
+//! [`Pipeline`].
+
+mod classes;
+mod constant;
+mod copy;
+mod dce;
+mod devirtualise;
+mod dominance;
+mod eval;
+mod gvn;
+mod inline;
+mod lower;
+mod parse;
+mod passes;
+mod print;
+mod simplify;
+mod ssa;
+mod verify;
+
+pub use self::eval::{evaluate, EvalError};
+pub use self::lower::lower;
+pub use self::parse::{parse_module, ParseError};
+pub use self::passes::{Dumps, Pass, Pipeline, Statistics};
+pub use self::ssa::to_ssa;
+pub use self::verify::{verify, VerifyError};
+
+#[cfg(test)]
+mod tests;
+
+/// The name of the method initialising the attributes of a class, which
+/// cannot clash with a Cool method.
+pub const INIT_METHOD: &str = "_init";
+
+#[derive(Debug, PartialEq)]
+pub struct Module {
+    /// Every class of the program, each after its parent.
+    pub classes: Vec<ClassDecl>,
+    pub functions: Vec<Function>,
+}
+
+impl Module {
+    pub fn class(&self, name: &str) -> Option<&ClassDecl> {
+        self.classes.iter().find(|class| class.name == name)
+    }
+
+    pub fn function(&self, name: &str) -> Option<&Function> {
+        self.functions.iter().find(|function| function.name == name)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ClassDecl {
+    pub name: String,
+    pub parent: Option<String>,
+    /// Names and types of the attributes defined by the class.
+    pub attributes: Vec<(String, String)>,
+    /// The methods defined by the class. Those without a function are
+    /// provided by the runtime.
+    pub methods: Vec<MethodDecl>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct MethodDecl {
+    pub name: String,
+    pub parameters: Vec<String>,
+    pub return_type: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Type {
+    /// An unboxed integer.
+    Int,
+    /// An unboxed boolean.
+    Bool,
+    /// A reference to an object of the class or a subclass, or void.
+    Object(String),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Register(pub u32);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockId(pub u32);
+
+/// A method or initialiser, named `Class.method`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Function {
+    pub name: String,
+    /// The registers holding self and the arguments on entry.
+    pub parameters: Vec<Register>,
+    pub return_type: Type,
+    /// The type of each register, by number.
+    pub registers: Vec<Type>,
+    /// The basic blocks, starting with the entry block.
+    pub blocks: Vec<Block>,
+}
+
+impl Function {
+    pub fn register_type(&self, register: Register) -> &Type {
+        &self.registers[register.0 as usize]
+    }
+
+    pub fn block(&self, block: BlockId) -> &Block {
+        &self.blocks[block.0 as usize]
+    }
+
+    pub fn block_mut(&mut self, block: BlockId) -> &mut Block {
+        &mut self.blocks[block.0 as usize]
+    }
+
+    /// Add a register of a type.
+    pub fn new_register(&mut self, type_id: Type) -> Register {
+        self.registers.push(type_id);
+        Register(self.registers.len() as u32 - 1)
+    }
+
+    /// The predecessors of each block, each listed once and in order.
+    pub fn predecessors(&self) -> Vec<Vec<BlockId>> {
+        let mut predecessors = vec![Vec::new(); self.blocks.len()];
+        for (index, block) in self.blocks.iter().enumerate() {
+            for successor in block.terminator.successors() {
+                let list: &mut Vec<BlockId> =
+                    &mut predecessors[successor.0 as usize];
+                if !list.contains(&BlockId(index as u32)) {
+                    list.push(BlockId(index as u32));
+                }
+            }
+        }
+        predecessors
+    }
+
+    /// Whether a register is defined at most once, by a parameter or an
+    /// instruction, as passes after `to_ssa` rely on.
+    pub fn is_ssa(&self) -> bool {
+        let mut defined = vec![false; self.registers.len()];
+        let results = self.blocks.iter().flat_map(|block| {
+            block.instructions.iter().filter_map(Instruction::result)
+        });
+        for register in self.parameters.iter().copied().chain(results) {
+            if std::mem::replace(&mut defined[register.0 as usize], true) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Block {
+    pub instructions: Vec<Instruction>,
+    pub terminator: Terminator,
+}
+
+/// Where in the source a runtime error is reported.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Location {
+    pub file: String,
+    pub line: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum UnaryOperator {
+    Negate,
+    Not,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BinaryOperator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    LessThan,
+    LessThanOrEquals,
+    /// Equality of two integers or two booleans.
+    Equals,
+}
+
+/// What checks for void, which for a dispatch names the method called.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum VoidCheck {
+    Dispatch(String),
+    StaticDispatch(String),
+    Case,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Instruction {
+    Int(Register, i32),
+    Bool(Register, bool),
+    String(Register, String),
+    Void(Register),
+    Copy(Register, Register),
+    Unary(Register, UnaryOperator, Register),
+    Binary(Register, BinaryOperator, Register, Register),
+    IsVoid(Register, Register),
+    /// Compare two objects as Cool's `=` does, by value for Ints, Bools
+    /// and Strings and by identity otherwise.
+    Equal(Register, Register, Register),
+    /// Make an Int or Bool object from an unboxed value.
+    Box(Register, Register),
+    /// Take the value out of an Int or Bool object.
+    Unbox(Register, Register),
+    GetAttribute(Register, Register, String),
+    /// Set an attribute of an object to a value.
+    SetAttribute(Register, String, Register),
+    /// Create an object of a class, running its initialiser.
+    New(Register, String),
+    /// Create an object of the class of another, running its initialiser.
+    NewSelfType(Register, Register),
+    /// Call a method through the dispatch table of the receiver.
+    Dispatch {
+        result: Register,
+        receiver: Register,
+        method: String,
+        arguments: Vec<Register>,
+    },
+    /// Call a function directly, with the receiver first.
+    Call {
+        result: Register,
+        function: String,
+        arguments: Vec<Register>,
+    },
+    /// Whether an object is of a class or one of its subclasses.
+    InstanceOf(Register, Register, String),
+    /// Give an object the static type of the result, which it is known to
+    /// conform to.
+    Cast(Register, Register),
+    /// Stop with a runtime error if an object is void.
+    CheckVoid(Register, VoidCheck, Location),
+    /// Stop with a runtime error if an integer is zero.
+    CheckZero(Register, Location),
+    /// Take the value of the register given for the block control came
+    /// from. Phis only appear at the start of a block, with one register
+    /// for each predecessor.
+    Phi(Register, Vec<(BlockId, Register)>),
+}
+
+impl Instruction {
+    /// The register the instruction defines, if any.
+    pub fn result(&self) -> Option<Register> {
+        use Instruction::*;
+        match self {
+            Int(result, _)
+            | Bool(result, _)
+            | String(result, _)
+            | Void(result)
+            | Copy(result, _)
+            | Unary(result, _, _)
+            | Binary(result, _, _, _)
+            | IsVoid(result, _)
+            | Equal(result, _, _)
+            | Box(result, _)
+            | Unbox(result, _)
+            | GetAttribute(result, _, _)
+            | New(result, _)
+            | NewSelfType(result, _)
+            | Dispatch { result, .. }
+            | Call { result, .. }
+            | InstanceOf(result, _, _)
+            | Cast(result, _)
+            | Phi(result, _) => Some(*result),
+            SetAttribute(..) | CheckVoid(..) | CheckZero(..) => None,
+        }
+    }
+
+    /// The registers the instruction reads, in order.
+    pub fn operands(&self) -> Vec<Register> {
+        use Instruction::*;
+        match self {
+            Int(..) | Bool(..) | String(..) | Void(_) | New(..) => Vec::new(),
+            Copy(_, operand)
+            | Unary(_, _, operand)
+            | IsVoid(_, operand)
+            | Box(_, operand)
+            | Unbox(_, operand)
+            | GetAttribute(_, operand, _)
+            | NewSelfType(_, operand)
+            | InstanceOf(_, operand, _)
+            | Cast(_, operand)
+            | CheckVoid(operand, _, _)
+            | CheckZero(operand, _) => vec![*operand],
+            Binary(_, _, left, right) | Equal(_, left, right) => {
+                vec![*left, *right]
+            }
+            SetAttribute(object, _, value) => vec![*object, *value],
+            Dispatch {
+                receiver,
+                arguments,
+                ..
+            } => [*receiver].into_iter().chain(arguments.clone()).collect(),
+            Call { arguments, .. } => arguments.clone(),
+            Phi(_, incoming) => {
+                incoming.iter().map(|(_, register)| *register).collect()
+            }
+        }
+    }
+
+    pub fn result_mut(&mut self) -> Option<&mut Register> {
+        use Instruction::*;
+        match self {
+            Int(result, _)
+            | Bool(result, _)
+            | String(result, _)
+            | Void(result)
+            | Copy(result, _)
+            | Unary(result, _, _)
+            | Binary(result, _, _, _)
+            | IsVoid(result, _)
+            | Equal(result, _, _)
+            | Box(result, _)
+            | Unbox(result, _)
+            | GetAttribute(result, _, _)
+            | New(result, _)
+            | NewSelfType(result, _)
+            | Dispatch { result, .. }
+            | Call { result, .. }
+            | InstanceOf(result, _, _)
+            | Cast(result, _)
+            | Phi(result, _) => Some(result),
+            SetAttribute(..) | CheckVoid(..) | CheckZero(..) => None,
+        }
+    }
+
+    /// The registers the instruction reads, in the order of `operands`.
+    pub fn operands_mut(&mut self) -> Vec<&mut Register> {
+        use Instruction::*;
+        match self {
+            Int(..) | Bool(..) | String(..) | Void(_) | New(..) => Vec::new(),
+            Copy(_, operand)
+            | Unary(_, _, operand)
+            | IsVoid(_, operand)
+            | Box(_, operand)
+            | Unbox(_, operand)
+            | GetAttribute(_, operand, _)
+            | NewSelfType(_, operand)
+            | InstanceOf(_, operand, _)
+            | Cast(_, operand)
+            | CheckVoid(operand, _, _)
+            | CheckZero(operand, _) => vec![operand],
+            Binary(_, _, left, right) | Equal(_, left, right) => {
+                vec![left, right]
+            }
+            SetAttribute(object, _, value) => vec![object, value],
+            Dispatch {
+                receiver,
+                arguments,
+                ..
+            } => [receiver].into_iter().chain(arguments.iter_mut()).collect(),
+            Call { arguments, .. } => arguments.iter_mut().collect(),
+            Phi(_, incoming) => {
+                incoming.iter_mut().map(|(_, register)| register).collect()
+            }
+        }
+    }
+
+    /// Whether the instruction does nothing but define its result, so that
+    /// it can be removed if the result is unused or computed already.
+    pub fn is_pure(&self) -> bool {
+        use Instruction::*;
+        match self {
+            Int(..) | Bool(..) | String(..) | Void(_) | Copy(..)
+            | Unary(..) | Binary(..) | IsVoid(..) | Equal(..) | Box(..)
+            | Unbox(..) | GetAttribute(..) | InstanceOf(..) | Cast(..)
+            | Phi(..) => true,
+            SetAttribute(..)
+            | New(..)
+            | NewSelfType(..)
+            | Dispatch { .. }
+            | Call { .. }
+            | CheckVoid(..)
+            | CheckZero(..) => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Terminator {
+    Jump(BlockId),
+    /// Branch on a boolean to the first block if it is true.
+    Branch(Register, BlockId, BlockId),
+    Return(Register),
+    /// Stop with a runtime error as no branch of a case matches an object.
+    NoMatch(Register),
+}
+
+impl Terminator {
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Terminator::Jump(target) => vec![*target],
+            Terminator::Branch(_, then_block, else_block) => {
+                vec![*then_block, *else_block]
+            }
+            Terminator::Return(_) | Terminator::NoMatch(_) => Vec::new(),
+        }
+    }
+
+    pub fn operands(&self) -> Vec<Register> {
+        match self {
+            Terminator::Jump(_) => Vec::new(),
+            Terminator::Branch(operand, _, _)
+            | Terminator::Return(operand)
+            | Terminator::NoMatch(operand) => vec![*operand],
+        }
+    }
+
+    pub fn successors_mut(&mut self) -> Vec<&mut BlockId> {
+        match self {
+            Terminator::Jump(target) => vec![target],
+            Terminator::Branch(_, then_block, else_block) => {
+                vec![then_block, else_block]
+            }
+            Terminator::Return(_) | Terminator::NoMatch(_) => Vec::new(),
+        }
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut Register> {
+        match self {
+            Terminator::Jump(_) => Vec::new(),
+            Terminator::Branch(operand, _, _)
+            | Terminator::Return(operand)
+            | Terminator::NoMatch(operand) => vec![operand],
+        }
+    }
+}
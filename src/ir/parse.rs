@@ -0,0 +1,651 @@
+//! A parser for the textual form of modules, as printed.
+//!
+//! The form is line based: each class header, attribute, method, function
+//! header, block label, instruction and closing brace is on a line of its
+//! own. Comments start with `;` and run to the end of the line.
+
+use super::*;
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, PartialEq)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for ParseError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// Parse a module in the textual form.
+pub fn parse_module(text: &str) -> Result<Module, ParseError> {
+    let mut lines = Vec::new();
+    for (line, number) in text.lines().zip(1..) {
+        let tokens = tokenize(line).map_err(|message| ParseError {
+            line: number,
+            message,
+        })?;
+        if !tokens.is_empty() {
+            lines.push(Line {
+                tokens,
+                position: 0,
+                number,
+            });
+        }
+    }
+    let mut parser = Parser {
+        lines: lines.into_iter(),
+        last_line: text.lines().count(),
+    };
+    let mut module = Module {
+        classes: Vec::new(),
+        functions: Vec::new(),
+    };
+    while let Some(mut line) = parser.lines.next() {
+        match line.word()? {
+            "class" => module.classes.push(parser.class(line)?),
+            "function" => module.functions.push(parser.function(line)?),
+            word => {
+                return Err(line.error(format!(
+                    "expected a class or function, found {word}"
+                )))
+            }
+        }
+    }
+    Ok(module)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token<'s> {
+    Word(&'s str),
+    Register(u32),
+    Int(i32),
+    Str(String),
+    Punctuation(char),
+}
+
+impl Display for Token<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Word(word) => write!(f, "{word}"),
+            Token::Register(register) => write!(f, "%{register}"),
+            Token::Int(int) => write!(f, "{int}"),
+            Token::Str(_) => write!(f, "a string"),
+            Token::Punctuation(c) => write!(f, "'{c}'"),
+        }
+    }
+}
+
+fn tokenize(line: &str) -> Result<Vec<Token<'_>>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = line.char_indices().peekable();
+    while let Some((start, c)) = chars.next() {
+        // The end of the token, which continues with the characters
+        // `continues` accepts.
+        let mut end = |continues: fn(&char) -> bool| {
+            let mut end = start + c.len_utf8();
+            while let Some((index, c)) = chars.next_if(|(_, c)| continues(c)) {
+                end = index + c.len_utf8();
+            }
+            end
+        };
+        let word =
+            |c: &char| c.is_ascii_alphanumeric() || *c == '_' || *c == '.';
+        match c {
+            ';' => break,
+            c if c.is_whitespace() => {}
+            '%' => {
+                let digits = &line[start + 1..end(char::is_ascii_digit)];
+                let register = digits
+                    .parse()
+                    .map_err(|_| format!("invalid register %{digits}"))?;
+                tokens.push(Token::Register(register));
+            }
+            '-' | '0'..='9' => {
+                let digits = &line[start..end(char::is_ascii_digit)];
+                let int = digits
+                    .parse()
+                    .map_err(|_| format!("invalid integer {digits}"))?;
+                tokens.push(Token::Int(int));
+            }
+            '"' => {
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        Some((_, '"')) => break,
+                        Some((_, '\\')) => match chars.next() {
+                            Some((_, 'n')) => string.push('\n'),
+                            Some((_, 't')) => string.push('\t'),
+                            Some((_, 'b')) => string.push('\u{08}'),
+                            Some((_, 'f')) => string.push('\u{0C}'),
+                            Some((_, 'r')) => string.push('\r'),
+                            Some((_, c)) => string.push(c),
+                            None => return Err("unterminated string".into()),
+                        },
+                        Some((_, c)) => string.push(c),
+                        None => return Err("unterminated string".into()),
+                    }
+                }
+                tokens.push(Token::Str(string));
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                tokens.push(Token::Word(&line[start..end(word)]))
+            }
+            '(' | ')' | ',' | ':' | '=' | '{' | '}' | '.' | '[' | ']' => {
+                tokens.push(Token::Punctuation(c))
+            }
+            c => return Err(format!("unexpected character {c:?}")),
+        }
+    }
+    Ok(tokens)
+}
+
+// The tokens of a line, read from the start.
+struct Line<'s> {
+    tokens: Vec<Token<'s>>,
+    position: usize,
+    number: usize,
+}
+
+impl<'s> Line<'s> {
+    fn error(&self, message: String) -> ParseError {
+        ParseError {
+            line: self.number,
+            message,
+        }
+    }
+
+    fn next(&mut self, expected: &str) -> Result<Token<'s>, ParseError> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token.ok_or_else(|| {
+            self.error(format!("expected {expected} at the end of the line"))
+        })
+    }
+
+    fn unexpected<T>(
+        &self,
+        expected: &str,
+        token: Token,
+    ) -> Result<T, ParseError> {
+        Err(self.error(format!("expected {expected}, found {token}")))
+    }
+
+    fn peek(&self) -> Option<&Token<'s>> {
+        self.tokens.get(self.position)
+    }
+
+    fn is_at(&self, c: char) -> bool {
+        self.peek() == Some(&Token::Punctuation(c))
+    }
+
+    fn word(&mut self) -> Result<&'s str, ParseError> {
+        match self.next("a name")? {
+            Token::Word(word) => Ok(word),
+            token => self.unexpected("a name", token),
+        }
+    }
+
+    fn punctuation(&mut self, c: char) -> Result<(), ParseError> {
+        match self.next(&format!("'{c}'"))? {
+            Token::Punctuation(found) if found == c => Ok(()),
+            token => self.unexpected(&format!("'{c}'"), token),
+        }
+    }
+
+    fn register(&mut self) -> Result<Register, ParseError> {
+        match self.next("a register")? {
+            Token::Register(register) => Ok(Register(register)),
+            token => self.unexpected("a register", token),
+        }
+    }
+
+    fn block(&mut self) -> Result<BlockId, ParseError> {
+        let word = self.word()?;
+        match word.strip_prefix("bb").and_then(|n| n.parse().ok()) {
+            Some(block) => Ok(BlockId(block)),
+            None => Err(self.error(format!("expected a block, found {word}"))),
+        }
+    }
+
+    fn int(&mut self) -> Result<i32, ParseError> {
+        match self.next("an integer")? {
+            Token::Int(int) => Ok(int),
+            token => self.unexpected("an integer", token),
+        }
+    }
+
+    fn string(&mut self) -> Result<String, ParseError> {
+        match self.next("a string")? {
+            Token::Str(string) => Ok(string),
+            token => self.unexpected("a string", token),
+        }
+    }
+
+    fn type_id(&mut self) -> Result<Type, ParseError> {
+        Ok(match self.word()? {
+            "i32" => Type::Int,
+            "bool" => Type::Bool,
+            class => Type::Object(class.to_string()),
+        })
+    }
+
+    fn location(&mut self) -> Result<Location, ParseError> {
+        let file = self.string()?;
+        self.punctuation(':')?;
+        let line = self.int()?;
+        Ok(Location {
+            file,
+            line: line as u32,
+        })
+    }
+
+    // A parenthesised list, whose items are parsed by `item`.
+    fn list<T>(
+        &mut self,
+        mut item: impl FnMut(&mut Self) -> Result<T, ParseError>,
+    ) -> Result<Vec<T>, ParseError> {
+        self.punctuation('(')?;
+        let mut items = Vec::new();
+        if self.is_at(')') {
+            self.position += 1;
+            return Ok(items);
+        }
+        loop {
+            items.push(item(self)?);
+            match self.next("')'")? {
+                Token::Punctuation(',') => {}
+                Token::Punctuation(')') => return Ok(items),
+                token => return self.unexpected("',' or ')'", token),
+            }
+        }
+    }
+
+    fn end(&self) -> Result<(), ParseError> {
+        match self.peek() {
+            Some(token) => {
+                Err(self.error(format!("unexpected {token} after the end")))
+            }
+            None => Ok(()),
+        }
+    }
+}
+
+struct Parser<'s> {
+    lines: std::vec::IntoIter<Line<'s>>,
+    last_line: usize,
+}
+
+// The registers of a function being parsed.
+#[derive(Default)]
+struct Registers {
+    types: HashMap<u32, Type>,
+    used: Vec<(Register, usize)>,
+}
+
+impl Registers {
+    fn define(
+        &mut self,
+        line: &Line,
+        register: Register,
+        type_id: Type,
+    ) -> Result<(), ParseError> {
+        match self.types.insert(register.0, type_id.clone()) {
+            Some(previous) if previous != type_id => Err(line.error(format!(
+                "{register} is {type_id} here but {previous} before"
+            ))),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<'s> Parser<'s> {
+    fn next_line(&mut self, inside: &str) -> Result<Line<'s>, ParseError> {
+        self.lines.next().ok_or_else(|| ParseError {
+            line: self.last_line,
+            message: format!("unterminated {inside}"),
+        })
+    }
+
+    fn class(&mut self, mut line: Line) -> Result<ClassDecl, ParseError> {
+        let name = line.word()?.to_string();
+        let parent = match line.peek() {
+            Some(Token::Word("inherits")) => {
+                line.position += 1;
+                Some(line.word()?.to_string())
+            }
+            _ => None,
+        };
+        line.punctuation('{')?;
+        line.end()?;
+        let mut class = ClassDecl {
+            name,
+            parent,
+            attributes: Vec::new(),
+            methods: Vec::new(),
+        };
+        loop {
+            let mut line = self.next_line("class")?;
+            if line.is_at('}') {
+                line.position += 1;
+                line.end()?;
+                return Ok(class);
+            }
+            match line.word()? {
+                "attribute" => {
+                    let name = line.word()?.to_string();
+                    line.punctuation(':')?;
+                    let type_id = line.word()?.to_string();
+                    class.attributes.push((name, type_id));
+                }
+                "method" => {
+                    let name = line.word()?.to_string();
+                    let parameters =
+                        line.list(|line| Ok(line.word()?.to_string()))?;
+                    line.punctuation(':')?;
+                    let return_type = line.word()?.to_string();
+                    class.methods.push(MethodDecl {
+                        name,
+                        parameters,
+                        return_type,
+                    });
+                }
+                word => {
+                    return Err(line.error(format!(
+                        "expected an attribute or method, found {word}"
+                    )))
+                }
+            }
+            line.end()?;
+        }
+    }
+
+    fn function(&mut self, mut line: Line) -> Result<Function, ParseError> {
+        let mut registers = Registers::default();
+        let name = line.word()?.to_string();
+        let parameters = line.list(|line| {
+            let register = line.register()?;
+            line.punctuation(':')?;
+            let type_id = line.type_id()?;
+            registers.define(line, register, type_id)?;
+            Ok(register)
+        })?;
+        line.punctuation(':')?;
+        let return_type = line.type_id()?;
+        line.punctuation('{')?;
+        line.end()?;
+
+        let mut blocks = Vec::new();
+        let mut instructions = Vec::new();
+        let mut in_block = false;
+        loop {
+            let mut line = self.next_line("function")?;
+            if !in_block {
+                if line.is_at('}') {
+                    line.position += 1;
+                    line.end()?;
+                    break;
+                }
+                let block = line.block()?;
+                if block.0 as usize != blocks.len() {
+                    let expected = BlockId(blocks.len() as u32);
+                    return Err(line
+                        .error(format!("expected {expected}, found {block}")));
+                }
+                line.punctuation(':')?;
+                line.end()?;
+                in_block = true;
+                continue;
+            }
+            if let Some(terminator) = terminator(&mut line, &mut registers)? {
+                blocks.push(Block {
+                    instructions: std::mem::take(&mut instructions),
+                    terminator,
+                });
+                in_block = false;
+            } else {
+                instructions.push(instruction(&mut line, &mut registers)?);
+            }
+            line.end()?;
+        }
+        if blocks.is_empty() {
+            return Err(line.error(format!("{name} has no blocks")));
+        }
+
+        // Registers that are never mentioned are given the type of any
+        // object.
+        let count = registers
+            .types
+            .keys()
+            .map(|register| register + 1)
+            .chain(registers.used.iter().map(|(register, _)| register.0 + 1))
+            .max()
+            .unwrap_or(0);
+        for (register, number) in registers.used.iter() {
+            if !registers.types.contains_key(&register.0) {
+                return Err(ParseError {
+                    line: *number,
+                    message: format!("{register} is never defined"),
+                });
+            }
+        }
+        let registers = (0..count)
+            .map(|register| {
+                registers
+                    .types
+                    .remove(&register)
+                    .unwrap_or_else(|| Type::Object("Object".to_string()))
+            })
+            .collect();
+        Ok(Function {
+            name,
+            parameters,
+            return_type,
+            registers,
+            blocks,
+        })
+    }
+}
+
+fn operand(
+    line: &mut Line,
+    registers: &mut Registers,
+) -> Result<Register, ParseError> {
+    let register = line.register()?;
+    registers.used.push((register, line.number));
+    Ok(register)
+}
+
+fn terminator(
+    line: &mut Line,
+    registers: &mut Registers,
+) -> Result<Option<Terminator>, ParseError> {
+    let keyword = match line.peek() {
+        Some(Token::Word(keyword)) => *keyword,
+        _ => return Ok(None),
+    };
+    let terminator = match keyword {
+        "jump" => {
+            line.position += 1;
+            Terminator::Jump(line.block()?)
+        }
+        "branch" => {
+            line.position += 1;
+            let condition = operand(line, registers)?;
+            line.punctuation(',')?;
+            let then_block = line.block()?;
+            line.punctuation(',')?;
+            Terminator::Branch(condition, then_block, line.block()?)
+        }
+        "return" => {
+            line.position += 1;
+            Terminator::Return(operand(line, registers)?)
+        }
+        "nomatch" => {
+            line.position += 1;
+            Terminator::NoMatch(operand(line, registers)?)
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(terminator))
+}
+
+fn instruction(
+    line: &mut Line,
+    registers: &mut Registers,
+) -> Result<Instruction, ParseError> {
+    use Instruction::*;
+    let result = match line.peek() {
+        Some(Token::Register(_)) => {
+            let result = line.register()?;
+            line.punctuation(':')?;
+            let type_id = line.type_id()?;
+            line.punctuation('=')?;
+            registers.define(line, result, type_id)?;
+            Some(result)
+        }
+        _ => None,
+    };
+    let keyword = line.word()?;
+    let instruction = match (keyword, result) {
+        ("setattr", None) => {
+            let object = operand(line, registers)?;
+            line.punctuation(',')?;
+            let name = line.word()?.to_string();
+            line.punctuation(',')?;
+            SetAttribute(object, name, operand(line, registers)?)
+        }
+        ("checkvoid", None) => {
+            let object = operand(line, registers)?;
+            line.punctuation(',')?;
+            let check = match line.word()? {
+                "dispatch" => VoidCheck::Dispatch(line.word()?.to_string()),
+                "static" => match line.word()? {
+                    "dispatch" => {
+                        VoidCheck::StaticDispatch(line.word()?.to_string())
+                    }
+                    word => {
+                        return Err(line.error(format!(
+                            "expected dispatch, found {word}"
+                        )))
+                    }
+                },
+                "case" => VoidCheck::Case,
+                word => {
+                    return Err(line.error(format!(
+                        "expected dispatch, static or case, found {word}"
+                    )))
+                }
+            };
+            line.punctuation(',')?;
+            CheckVoid(object, check, line.location()?)
+        }
+        ("checkzero", None) => {
+            let value = operand(line, registers)?;
+            line.punctuation(',')?;
+            CheckZero(value, line.location()?)
+        }
+        (_, None) => {
+            return Err(
+                line.error(format!("expected an instruction, found {keyword}"))
+            )
+        }
+        ("const", Some(result)) => match line.next("a constant")? {
+            Token::Int(int) => Int(result, int),
+            Token::Word("true") => Bool(result, true),
+            Token::Word("false") => Bool(result, false),
+            token => return line.unexpected("a constant", token),
+        },
+        ("string", Some(result)) => String(result, line.string()?),
+        ("void", Some(result)) => Void(result),
+        ("copy", Some(result)) => Copy(result, operand(line, registers)?),
+        ("neg", Some(result)) => {
+            Unary(result, UnaryOperator::Negate, operand(line, registers)?)
+        }
+        ("not", Some(result)) => {
+            Unary(result, UnaryOperator::Not, operand(line, registers)?)
+        }
+        (
+            "add" | "sub" | "mul" | "div" | "lt" | "le" | "eq" | "equal",
+            Some(result),
+        ) => {
+            let left = operand(line, registers)?;
+            line.punctuation(',')?;
+            let right = operand(line, registers)?;
+            let operator = match keyword {
+                "add" => BinaryOperator::Add,
+                "sub" => BinaryOperator::Subtract,
+                "mul" => BinaryOperator::Multiply,
+                "div" => BinaryOperator::Divide,
+                "lt" => BinaryOperator::LessThan,
+                "le" => BinaryOperator::LessThanOrEquals,
+                "eq" => BinaryOperator::Equals,
+                _ => return Ok(Equal(result, left, right)),
+            };
+            Binary(result, operator, left, right)
+        }
+        ("isvoid", Some(result)) => IsVoid(result, operand(line, registers)?),
+        ("box", Some(result)) => Box(result, operand(line, registers)?),
+        ("unbox", Some(result)) => Unbox(result, operand(line, registers)?),
+        ("getattr", Some(result)) => {
+            let object = operand(line, registers)?;
+            line.punctuation(',')?;
+            GetAttribute(result, object, line.word()?.to_string())
+        }
+        ("new", Some(result)) => match line.word()? {
+            "SELF_TYPE" => NewSelfType(result, operand(line, registers)?),
+            class => New(result, class.to_string()),
+        },
+        ("dispatch", Some(result)) => {
+            let receiver = operand(line, registers)?;
+            line.punctuation('.')?;
+            let method = line.word()?.to_string();
+            let arguments = line.list(|line| operand(line, registers))?;
+            Dispatch {
+                result,
+                receiver,
+                method,
+                arguments,
+            }
+        }
+        ("call", Some(result)) => {
+            let function = line.word()?.to_string();
+            let arguments = line.list(|line| operand(line, registers))?;
+            Call {
+                result,
+                function,
+                arguments,
+            }
+        }
+        ("instanceof", Some(result)) => {
+            let object = operand(line, registers)?;
+            line.punctuation(',')?;
+            InstanceOf(result, object, line.word()?.to_string())
+        }
+        ("cast", Some(result)) => Cast(result, operand(line, registers)?),
+        ("phi", Some(result)) => {
+            let mut incoming = Vec::new();
+            loop {
+                line.punctuation('[')?;
+                let block = line.block()?;
+                line.punctuation(':')?;
+                incoming.push((block, operand(line, registers)?));
+                line.punctuation(']')?;
+                if !line.is_at(',') {
+                    break;
+                }
+                line.position += 1;
+            }
+            Phi(result, incoming)
+        }
+        (_, Some(_)) => {
+            return Err(
+                line.error(format!("expected an instruction, found {keyword}"))
+            )
+        }
+    };
+    Ok(instruction)
+}
@@ -0,0 +1,211 @@
+//! The optimisation passes and the pipelines run at each level.
+//!
+//! Every pass but `ssa` works on functions in SSA form, into which they
+//! are put first if need be. Passes change one function at a time, but
+//! may look at the classes and other functions of the module.
+
+use super::classes::hierarchy;
+use super::constant::fold_constants;
+use super::copy::propagate_copies;
+use super::dce::eliminate_dead_code;
+use super::devirtualise::devirtualise;
+use super::gvn::number_values;
+use super::inline::inline_calls;
+use super::simplify::simplify;
+use super::ssa::to_ssa;
+use super::*;
+use crate::hierarchy::ClassHierarchy;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pass {
+    /// Conversion to SSA form.
+    Ssa,
+    /// Dispatches no subclass can override made direct calls.
+    Devirtualise,
+    /// Inlining of calls to small functions.
+    Inline,
+    /// Constant folding and propagation.
+    Constant,
+    /// Copy propagation.
+    Copy,
+    /// Global value numbering.
+    Gvn,
+    /// Dead code elimination.
+    Dce,
+    /// CFG simplification.
+    Simplify,
+}
+
+// How many times a repeating pipeline runs its passes at most.
+const MAX_ROUNDS: usize = 8;
+
+impl Pass {
+    pub const ALL: [Pass; 8] = [
+        Pass::Ssa,
+        Pass::Devirtualise,
+        Pass::Inline,
+        Pass::Constant,
+        Pass::Copy,
+        Pass::Gvn,
+        Pass::Dce,
+        Pass::Simplify,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Pass::Ssa => "ssa",
+            Pass::Devirtualise => "devirtualise",
+            Pass::Inline => "inline",
+            Pass::Constant => "constant",
+            Pass::Copy => "copy",
+            Pass::Gvn => "gvn",
+            Pass::Dce => "dce",
+            Pass::Simplify => "simplify",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Pass> {
+        Pass::ALL.into_iter().find(|pass| pass.name() == name)
+    }
+
+    // Run the pass over a function, returning whether it changed it.
+    fn run(
+        self,
+        function: &mut Function,
+        context: &Context,
+        statistics: &mut Statistics,
+    ) -> bool {
+        if self == Pass::Ssa {
+            let is_ssa = function.is_ssa();
+            to_ssa(function);
+            return !is_ssa;
+        }
+        to_ssa(function);
+        match self {
+            Pass::Ssa => unreachable!(),
+            Pass::Devirtualise => {
+                let count = devirtualise(function, context.hierarchy);
+                statistics.devirtualised += count;
+                count > 0
+            }
+            Pass::Inline => {
+                let count = inline_calls(function, context);
+                statistics.inlined += count;
+                count > 0
+            }
+            Pass::Constant => fold_constants(function),
+            Pass::Copy => propagate_copies(function),
+            Pass::Gvn => number_values(function),
+            Pass::Dce => eliminate_dead_code(function),
+            Pass::Simplify => simplify(function),
+        }
+    }
+}
+
+/// What the rest of a module is to a pass over one of its functions.
+pub(super) struct Context<'m> {
+    pub hierarchy: &'m ClassHierarchy<'static>,
+    // The functions before and after the one being changed.
+    others: [&'m [Function]; 2],
+}
+
+impl Context<'_> {
+    pub fn function(&self, name: &str) -> Option<&Function> {
+        self.others
+            .iter()
+            .flat_map(|functions| functions.iter())
+            .find(|function| function.name == name)
+    }
+}
+
+/// Counts of what passes changed, summed over a module.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Statistics {
+    /// Dispatches made direct calls.
+    pub devirtualised: usize,
+    /// Calls replaced by the body of the function called.
+    pub inlined: usize,
+}
+
+/// What to show of the functions of a module around each pass.
+#[derive(Clone, Debug, Default)]
+pub struct Dumps {
+    pub before: Vec<Pass>,
+    pub after: Vec<Pass>,
+}
+
+/// Passes to run over every function of a module, in order.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Pipeline {
+    pub passes: Vec<Pass>,
+    /// Whether to run the passes again while they change a function.
+    pub repeat: bool,
+}
+
+impl Pipeline {
+    /// The passes run at an optimisation level. Level 0 runs none, level 1
+    /// runs the cheap passes once and level 2 adds inlining and value
+    /// numbering and runs them all again while they change a function.
+    pub fn level(level: u32) -> Pipeline {
+        use Pass::*;
+        match level {
+            0 => Pipeline::new(Vec::new()),
+            1 => Pipeline::new(vec![
+                Ssa,
+                Devirtualise,
+                Constant,
+                Copy,
+                Dce,
+                Simplify,
+            ]),
+            _ => Pipeline {
+                passes: Pass::ALL.to_vec(),
+                repeat: true,
+            },
+        }
+    }
+
+    pub fn new(passes: Vec<Pass>) -> Pipeline {
+        Pipeline {
+            passes,
+            repeat: false,
+        }
+    }
+
+    /// Run the passes over every function of a module, in order, and
+    /// count what they changed. Functions are passed to `dump` before and
+    /// after the passes `dumps` names.
+    pub fn run(
+        &self,
+        module: &mut Module,
+        dumps: &Dumps,
+        dump: &mut dyn FnMut(String),
+    ) -> Statistics {
+        let mut statistics = Statistics::default();
+        let hierarchy = hierarchy(&module.classes);
+        for index in 0..module.functions.len() {
+            let (before, rest) = module.functions.split_at_mut(index);
+            let (function, after) = rest.split_first_mut().unwrap();
+            let context = Context {
+                hierarchy: &hierarchy,
+                others: [before, after],
+            };
+            for _ in 0..if self.repeat { MAX_ROUNDS } else { 1 } {
+                let mut changed = false;
+                for pass in self.passes.iter() {
+                    if dumps.before.contains(pass) {
+                        dump(format!("; before {}\n{function}", pass.name()));
+                    }
+                    changed |= pass.run(function, &context, &mut statistics);
+                    if dumps.after.contains(pass) {
+                        dump(format!("; after {}\n{function}", pass.name()));
+                    }
+                }
+                if !changed {
+                    break;
+                }
+            }
+        }
+        statistics
+    }
+}
@@ -0,0 +1,223 @@
+//! The textual form of modules, which `parse_module` reads back.
+//!
+//! Classes are declared first, then each function is printed with its
+//! blocks labelled `bbN`. Every instruction defining a register gives its
+//! type, as in `%3 : i32 = add %1, %2`.
+
+use super::*;
+use crate::util::escape_str;
+use std::fmt::{Display, Formatter, Result};
+
+impl Display for Module {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        for class in self.classes.iter() {
+            write!(f, "class {}", class.name)?;
+            if let Some(parent) = &class.parent {
+                write!(f, " inherits {parent}")?;
+            }
+            writeln!(f, " {{")?;
+            for (name, type_id) in class.attributes.iter() {
+                writeln!(f, "  attribute {name} : {type_id}")?;
+            }
+            for method in class.methods.iter() {
+                let parameters = method.parameters.join(", ");
+                writeln!(
+                    f,
+                    "  method {}({parameters}) : {}",
+                    method.name, method.return_type
+                )?;
+            }
+            writeln!(f, "}}")?;
+        }
+        for function in self.functions.iter() {
+            write!(f, "\n{function}")?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Function {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "function {}(", self.name)?;
+        for (index, parameter) in self.parameters.iter().enumerate() {
+            if index > 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{parameter} : {}", self.register_type(*parameter))?;
+        }
+        writeln!(f, ") : {} {{", self.return_type)?;
+        for (index, block) in self.blocks.iter().enumerate() {
+            writeln!(f, "{}:", BlockId(index as u32))?;
+            for instruction in block.instructions.iter() {
+                write!(f, "  ")?;
+                if let Some(result) = instruction.result() {
+                    write!(f, "{result} : {} = ", self.register_type(result))?;
+                }
+                writeln!(f, "{instruction}")?;
+            }
+            writeln!(f, "  {}", block.terminator)?;
+        }
+        writeln!(f, "}}")
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Type::Int => write!(f, "i32"),
+            Type::Bool => write!(f, "bool"),
+            Type::Object(class) => write!(f, "{class}"),
+        }
+    }
+}
+
+impl Display for Register {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "%{}", self.0)
+    }
+}
+
+impl Display for BlockId {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "bb{}", self.0)
+    }
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        write!(f, "\"{}\":{}", escape(&self.file), self.line)
+    }
+}
+
+// Strings are escaped as in Cool source, and carriage returns too so that
+// every instruction is on one line.
+fn escape(string: &str) -> String {
+    escape_str(string).replace('\r', r"\r")
+}
+
+impl UnaryOperator {
+    pub fn name(self) -> &'static str {
+        match self {
+            UnaryOperator::Negate => "neg",
+            UnaryOperator::Not => "not",
+        }
+    }
+}
+
+impl BinaryOperator {
+    pub fn name(self) -> &'static str {
+        match self {
+            BinaryOperator::Add => "add",
+            BinaryOperator::Subtract => "sub",
+            BinaryOperator::Multiply => "mul",
+            BinaryOperator::Divide => "div",
+            BinaryOperator::LessThan => "lt",
+            BinaryOperator::LessThanOrEquals => "le",
+            BinaryOperator::Equals => "eq",
+        }
+    }
+}
+
+impl Display for VoidCheck {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            VoidCheck::Dispatch(method) => write!(f, "dispatch {method}"),
+            VoidCheck::StaticDispatch(method) => {
+                write!(f, "static dispatch {method}")
+            }
+            VoidCheck::Case => write!(f, "case"),
+        }
+    }
+}
+
+fn write_list(f: &mut Formatter<'_>, registers: &[Register]) -> Result {
+    for (index, register) in registers.iter().enumerate() {
+        if index > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{register}")?;
+    }
+    Ok(())
+}
+
+// Instructions are printed without the register they define.
+impl Display for Instruction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        use Instruction::*;
+        match self {
+            Int(_, value) => write!(f, "const {value}"),
+            Bool(_, value) => write!(f, "const {value}"),
+            String(_, string) => write!(f, "string \"{}\"", escape(string)),
+            Void(_) => write!(f, "void"),
+            Copy(_, source) => write!(f, "copy {source}"),
+            Unary(_, operator, operand) => {
+                write!(f, "{} {operand}", operator.name())
+            }
+            Binary(_, operator, left, right) => {
+                write!(f, "{} {left}, {right}", operator.name())
+            }
+            IsVoid(_, operand) => write!(f, "isvoid {operand}"),
+            Equal(_, left, right) => write!(f, "equal {left}, {right}"),
+            Box(_, value) => write!(f, "box {value}"),
+            Unbox(_, object) => write!(f, "unbox {object}"),
+            GetAttribute(_, object, name) => {
+                write!(f, "getattr {object}, {name}")
+            }
+            SetAttribute(object, name, value) => {
+                write!(f, "setattr {object}, {name}, {value}")
+            }
+            New(_, class) => write!(f, "new {class}"),
+            NewSelfType(_, object) => write!(f, "new SELF_TYPE {object}"),
+            Dispatch {
+                receiver,
+                method,
+                arguments,
+                ..
+            } => {
+                write!(f, "dispatch {receiver}.{method}(")?;
+                write_list(f, arguments)?;
+                write!(f, ")")
+            }
+            Call {
+                function,
+                arguments,
+                ..
+            } => {
+                write!(f, "call {function}(")?;
+                write_list(f, arguments)?;
+                write!(f, ")")
+            }
+            InstanceOf(_, object, class) => {
+                write!(f, "instanceof {object}, {class}")
+            }
+            Cast(_, object) => write!(f, "cast {object}"),
+            CheckVoid(object, check, location) => {
+                write!(f, "checkvoid {object}, {check}, {location}")
+            }
+            CheckZero(value, location) => {
+                write!(f, "checkzero {value}, {location}")
+            }
+            Phi(_, incoming) => {
+                write!(f, "phi")?;
+                for (index, (block, register)) in incoming.iter().enumerate() {
+                    let separator = if index > 0 { "," } else { "" };
+                    write!(f, "{separator} [{block}: {register}]")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Display for Terminator {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result {
+        match self {
+            Terminator::Jump(target) => write!(f, "jump {target}"),
+            Terminator::Branch(condition, then_block, else_block) => {
+                write!(f, "branch {condition}, {then_block}, {else_block}")
+            }
+            Terminator::Return(value) => write!(f, "return {value}"),
+            Terminator::NoMatch(object) => write!(f, "nomatch {object}"),
+        }
+    }
+}
@@ -0,0 +1,176 @@
+//! CFG simplification: branches to a single block become jumps, blocks
+//! that only jump on are bypassed, a block is merged into its only
+//! predecessor when it is that block's only successor, and unreachable
+//! blocks are removed.
+
+use super::dominance::reverse_postorder;
+use super::*;
+
+pub fn simplify(function: &mut Function) -> bool {
+    let mut changed = false;
+    for block in function.blocks.iter_mut() {
+        if let Terminator::Branch(_, then_block, else_block) = block.terminator
+        {
+            if then_block == else_block {
+                block.terminator = Terminator::Jump(then_block);
+                changed = true;
+            }
+        }
+    }
+    // Blocks left unreachable are removed first, as they would still count
+    // as predecessors.
+    while remove_unreachable(function)
+        || bypass_empty_block(function)
+        || merge_block(function)
+    {
+        changed = true;
+    }
+    changed
+}
+
+// Send the predecessors of a block that only jumps to another straight to
+// that block, when it has no phis that would need a register for them.
+fn bypass_empty_block(function: &mut Function) -> bool {
+    let predecessors = function.predecessors();
+    for (index, block) in function.blocks.iter().enumerate().skip(1) {
+        let target = match block.terminator {
+            Terminator::Jump(target) if block.instructions.is_empty() => target,
+            _ => continue,
+        };
+        let has_phis = matches!(
+            function.block(target).instructions.first(),
+            Some(Instruction::Phi(..))
+        );
+        if target.0 as usize == index
+            || has_phis
+            || predecessors[index].is_empty()
+        {
+            continue;
+        }
+        let block_id = BlockId(index as u32);
+        for predecessor in predecessors[index].iter() {
+            let terminator = &mut function.block_mut(*predecessor).terminator;
+            for successor in terminator.successors_mut() {
+                if *successor == block_id {
+                    *successor = target;
+                }
+            }
+        }
+        return true;
+    }
+    false
+}
+
+// Append a block to its only predecessor when it is that block's only
+// successor. Its phis then have a single register each, which they copy.
+fn merge_block(function: &mut Function) -> bool {
+    let predecessors = function.predecessors();
+    for (index, block_predecessors) in predecessors.iter().enumerate().skip(1) {
+        let predecessor = match block_predecessors.as_slice() {
+            [predecessor] => *predecessor,
+            _ => continue,
+        };
+        let block_id = BlockId(index as u32);
+        if predecessor == block_id
+            || function.block(predecessor).terminator
+                != Terminator::Jump(block_id)
+        {
+            continue;
+        }
+        let block = function.block_mut(block_id);
+        let mut instructions = std::mem::take(&mut block.instructions);
+        let terminator = std::mem::replace(
+            &mut block.terminator,
+            Terminator::Jump(block_id),
+        );
+        for instruction in instructions.iter_mut() {
+            if let Instruction::Phi(result, incoming) = instruction {
+                *instruction = Instruction::Copy(*result, incoming[0].1);
+            }
+        }
+        for successor in terminator.successors() {
+            rename_predecessor(function, successor, block_id, predecessor);
+        }
+        let target = function.block_mut(predecessor);
+        target.instructions.extend(instructions);
+        target.terminator = terminator;
+        return true;
+    }
+    false
+}
+
+// Change the block phis of a block are given registers for.
+fn rename_predecessor(
+    function: &mut Function,
+    block: BlockId,
+    from: BlockId,
+    to: BlockId,
+) {
+    for instruction in function.block_mut(block).instructions.iter_mut() {
+        if let Instruction::Phi(_, incoming) = instruction {
+            for (predecessor, _) in incoming.iter_mut() {
+                if *predecessor == from {
+                    *predecessor = to;
+                }
+            }
+        }
+    }
+}
+
+/// Remove the registers phis are given for a block that is no longer a
+/// predecessor of theirs.
+pub fn remove_phi_edges(function: &mut Function, block: BlockId) {
+    let predecessors = function.predecessors();
+    for (index, other) in function.blocks.iter_mut().enumerate() {
+        for instruction in other.instructions.iter_mut() {
+            if let Instruction::Phi(_, incoming) = instruction {
+                incoming.retain(|(predecessor, _)| {
+                    *predecessor != block
+                        || predecessors[index].contains(&block)
+                });
+            }
+        }
+    }
+}
+
+/// Remove the blocks that cannot be reached from the entry block,
+/// numbering the others in order. Returns whether any were removed.
+pub fn remove_unreachable(function: &mut Function) -> bool {
+    let mut reachable = vec![false; function.blocks.len()];
+    for block in reverse_postorder(function) {
+        reachable[block.0 as usize] = true;
+    }
+    if reachable.iter().all(|reachable| *reachable) {
+        return false;
+    }
+    let mut numbers = Vec::new();
+    let mut next = 0;
+    for reachable in reachable.iter() {
+        numbers.push(BlockId(next));
+        next += *reachable as u32;
+    }
+    let blocks = std::mem::take(&mut function.blocks);
+    for (block, _) in blocks
+        .into_iter()
+        .zip(reachable.iter())
+        .filter(|(_, reachable)| **reachable)
+    {
+        function.blocks.push(block);
+    }
+    for block in function.blocks.iter_mut() {
+        for successor in block.terminator.successors_mut() {
+            *successor = numbers[successor.0 as usize];
+        }
+        for instruction in block.instructions.iter_mut() {
+            if let Instruction::Phi(_, incoming) = instruction {
+                incoming.retain(|(predecessor, _)| {
+                    reachable[predecessor.0 as usize]
+                });
+                for (predecessor, _) in incoming.iter_mut() {
+                    *predecessor = numbers[predecessor.0 as usize];
+                }
+            }
+        }
+    }
+    true
+}
@@ -0,0 +1,231 @@
+//! Conversion to static single assignment form, in which every register is
+//! defined once, by the method of Cytron et al., "Efficiently Computing
+//! Static Single Assignment Form and the Control Dependence Graph".
+//!
+//! Registers defined more than once, such as those of variables assigned
+//! to, are given phis in the dominance frontiers of the blocks defining
+//! them, where they are live, and each definition is then given a register
+//! of its own by walking the dominator tree.
+
+use super::dominance::Dominance;
+use super::simplify::remove_unreachable;
+use super::*;
+use std::collections::HashSet;
+
+/// Put a function into SSA form. Unreachable blocks are removed first.
+pub fn to_ssa(function: &mut Function) {
+    if function.is_ssa() {
+        return;
+    }
+    remove_unreachable(function);
+    let dominance = Dominance::new(function);
+
+    // The registers to rename, and the blocks defining each.
+    let mut definitions: Vec<Vec<BlockId>> =
+        vec![Vec::new(); function.registers.len()];
+    for parameter in function.parameters.iter() {
+        definitions[parameter.0 as usize].push(BlockId(0));
+    }
+    for (index, block) in function.blocks.iter().enumerate() {
+        for result in block.instructions.iter().filter_map(Instruction::result)
+        {
+            definitions[result.0 as usize].push(BlockId(index as u32));
+        }
+    }
+    let variables: Vec<bool> =
+        definitions.iter().map(|blocks| blocks.len() > 1).collect();
+
+    // Place the phis, each given the variable as its result and registers
+    // for now.
+    let live = live_in(function, &variables);
+    let frontiers = dominance.frontiers();
+    let mut phis: Vec<Vec<Register>> = vec![Vec::new(); function.blocks.len()];
+    for (variable, blocks) in definitions.iter().enumerate() {
+        if !variables[variable] {
+            continue;
+        }
+        let variable = Register(variable as u32);
+        let mut work = blocks.clone();
+        while let Some(block) = work.pop() {
+            for &frontier in frontiers[block.0 as usize].iter() {
+                let index = frontier.0 as usize;
+                if live[index].contains(&variable)
+                    && !phis[index].contains(&variable)
+                {
+                    phis[index].push(variable);
+                    work.push(frontier);
+                }
+            }
+        }
+    }
+    for (index, variables) in phis.iter().enumerate() {
+        let incoming: Vec<(BlockId, Register)> = dominance.predecessors[index]
+            .iter()
+            .map(|predecessor| (*predecessor, Register(u32::MAX)))
+            .collect();
+        let block = &mut function.blocks[index];
+        block.instructions.splice(
+            0..0,
+            variables
+                .iter()
+                .map(|variable| Instruction::Phi(*variable, incoming.clone())),
+        );
+    }
+
+    let mut renamer = Renamer {
+        function,
+        dominance: &dominance,
+        phis: &phis,
+        stacks: vec![Vec::new(); variables.len()],
+        renamed: vec![false; variables.len()],
+        variables,
+    };
+    for parameter in renamer.function.parameters.clone() {
+        if renamer.variables[parameter.0 as usize] {
+            renamer.stacks[parameter.0 as usize].push(parameter);
+            renamer.renamed[parameter.0 as usize] = true;
+        }
+    }
+    renamer.rename(BlockId(0));
+}
+
+// The registers of `variables` live on entry to each block, that is used
+// there or later before being defined.
+fn live_in(function: &Function, variables: &[bool]) -> Vec<HashSet<Register>> {
+    let is_variable = |register: &&Register| variables[register.0 as usize];
+    // The registers each block uses before defining them, and defines.
+    let mut uses = Vec::new();
+    let mut definitions = Vec::new();
+    for block in function.blocks.iter() {
+        let mut used = HashSet::new();
+        let mut defined = HashSet::new();
+        for instruction in block.instructions.iter() {
+            for operand in instruction.operands().iter().filter(is_variable) {
+                if !defined.contains(operand) {
+                    used.insert(*operand);
+                }
+            }
+            defined.extend(
+                instruction.result().filter(|r| variables[r.0 as usize]),
+            );
+        }
+        for operand in block.terminator.operands().iter().filter(is_variable) {
+            if !defined.contains(operand) {
+                used.insert(*operand);
+            }
+        }
+        uses.push(used);
+        definitions.push(defined);
+    }
+
+    let mut live: Vec<HashSet<Register>> = uses.clone();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (index, block) in function.blocks.iter().enumerate().rev() {
+            let mut registers = live[index].clone();
+            for successor in block.terminator.successors() {
+                for register in live[successor.0 as usize].iter() {
+                    if !definitions[index].contains(register) {
+                        registers.insert(*register);
+                    }
+                }
+            }
+            if registers.len() != live[index].len() {
+                live[index] = registers;
+                changed = true;
+            }
+        }
+    }
+    live
+}
+
+struct Renamer<'r> {
+    function: &'r mut Function,
+    dominance: &'r Dominance,
+    // The variable of each phi placed at the start of each block.
+    phis: &'r [Vec<Register>],
+    // Whether each register is to be renamed.
+    variables: Vec<bool>,
+    // The register holding the value of each variable at this point.
+    stacks: Vec<Vec<Register>>,
+    // Whether each variable has been defined already, so that the first
+    // definition can keep the register.
+    renamed: Vec<bool>,
+}
+
+impl Renamer<'_> {
+    fn current(&self, register: Register) -> Register {
+        match self.variables.get(register.0 as usize) {
+            Some(true) => self.stacks[register.0 as usize]
+                .last()
+                .copied()
+                .unwrap_or(register),
+            _ => register,
+        }
+    }
+
+    fn define(&mut self, variable: Register) -> Register {
+        let index = variable.0 as usize;
+        let register = if std::mem::replace(&mut self.renamed[index], true) {
+            let type_id = self.function.register_type(variable).clone();
+            self.function.new_register(type_id)
+        } else {
+            variable
+        };
+        self.stacks[index].push(register);
+        register
+    }
+
+    fn rename(&mut self, block_id: BlockId) {
+        let mut defined = Vec::new();
+        let mut instructions =
+            std::mem::take(&mut self.function.block_mut(block_id).instructions);
+        for instruction in instructions.iter_mut() {
+            if !matches!(instruction, Instruction::Phi(..)) {
+                for operand in instruction.operands_mut() {
+                    *operand = self.current(*operand);
+                }
+            }
+            if let Some(result) = instruction.result_mut() {
+                if self.variables[result.0 as usize] {
+                    defined.push(*result);
+                    *result = self.define(*result);
+                }
+            }
+        }
+        let block = self.function.block_mut(block_id);
+        block.instructions = instructions;
+        let mut terminator = block.terminator.clone();
+        for operand in terminator.operands_mut() {
+            *operand = self.current(*operand);
+        }
+        let successors = terminator.successors();
+        self.function.block_mut(block_id).terminator = terminator;
+
+        for successor in successors {
+            let variables = &self.phis[successor.0 as usize];
+            let values: Vec<Register> =
+                variables.iter().map(|v| self.current(*v)).collect();
+            let block = self.function.block_mut(successor);
+            for (instruction, value) in
+                block.instructions.iter_mut().zip(values)
+            {
+                if let Instruction::Phi(_, incoming) = instruction {
+                    for (predecessor, register) in incoming.iter_mut() {
+                        if *predecessor == block_id {
+                            *register = value;
+                        }
+                    }
+                }
+            }
+        }
+
+        for child in self.dominance.children[block_id.0 as usize].clone() {
+            self.rename(child);
+        }
+        for variable in defined {
+            self.stacks[variable.0 as usize].pop();
+        }
+    }
+}
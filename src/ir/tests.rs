@@ -0,0 +1,578 @@
+use super::*;
+use crate::lexer::lex_tokens;
+use crate::parser::parse_program;
+use crate::semant::check_program;
+
+const SOURCE: &str = "\
+class A {
+  a : Int <- 1;
+  b : SELF_TYPE;
+  f(x : Int) : Int { a / x };
+  g() : SELF_TYPE { b <- new SELF_TYPE };
+};
+class B inherits A {
+  c : Bool <- true;
+  f(x : Int) : Int { x };
+};
+class Main inherits IO {
+  main() : Object {
+    let b : A <- new B, i : Int in {
+      while i < 3 loop i <- i + 1 pool;
+      out_int(b@A.f(2) - b.f(~i));
+      case b.g() of
+        a : A => out_string(a.type_name());
+        b : B => if isvoid b then 0 else not (1 = 2) fi;
+      esac;
+    }
+  };
+};
+";
+
+fn lower_source(source: &str) -> Module {
+    let (_, tokens) = lex_tokens(source, "test.cl").unwrap();
+    let (_, mut program) = parse_program(&tokens).unwrap();
+    let hierarchy = check_program(&mut program).unwrap();
+    lower(&program, &hierarchy)
+}
+
+// A function taking an Int and returning it unboxed and boxed again.
+const FUNCTION: &str = "\
+class Object {
+}
+class Int inherits Object {
+}
+class Bool inherits Object {
+}
+class String inherits Object {
+}
+class Main inherits Object {
+  method f(Int) : Int
+}
+
+function Main.f(%0 : Main, %1 : Int) : Int {
+bb0:
+  %2 : i32 = unbox %1
+  %3 : bool = const true
+  branch %3, bb1, bb2
+bb1:
+  %4 : i32 = add %2, %2
+  jump bb2
+bb2:
+  %5 : Int = box %2
+  return %5
+}
+";
+
+#[test]
+fn test_lower() {
+    let module = lower_source(SOURCE);
+    verify(&module).unwrap();
+    let names: Vec<&str> = module
+        .functions
+        .iter()
+        .map(|function| function.name.as_str())
+        .collect();
+    assert_eq!(
+        names,
+        ["A._init", "B._init", "A.f", "A.g", "B.f", "Main.main"]
+    );
+
+    let text = module.to_string();
+    assert!(text.contains("class B inherits A {\n  attribute c : Bool\n"));
+    assert!(text.contains(
+        "function B._init(%0 : B) : B {
+bb0:
+  %1 : B = call A._init(%0)
+  %2 : bool = const true
+  %3 : Bool = box %2
+  setattr %0, c, %3
+  return %0
+}"
+    ));
+    assert!(text.contains(
+        "function A.f(%0 : A, %1 : Int) : Int {
+bb0:
+  %2 : Int = getattr %0, a
+  %3 : Int = copy %1
+  %4 : i32 = unbox %2
+  %5 : i32 = unbox %3
+  checkzero %5, \"test.cl\":4
+  %6 : i32 = div %4, %5
+  %7 : Int = box %6
+  return %7
+}"
+    ));
+    assert!(text.contains("%1 : A = new SELF_TYPE %0\n"));
+    assert!(text.contains("= call A.f(%"));
+    assert!(text.contains("= dispatch %"));
+    assert!(text.contains(", static dispatch f, \"test.cl\":15\n"));
+    assert!(text.contains(", dispatch g, \"test.cl\":16\n"));
+    assert!(text.contains("nomatch %"));
+}
+
+#[test]
+fn test_case_order() {
+    let text = lower_source(SOURCE).to_string();
+    let b = text.find("instanceof %39, B").unwrap();
+    let a = text.find("instanceof %39, A").unwrap();
+    assert!(b < a, "{text}");
+}
+
+#[test]
+fn test_round_trip() {
+    let module = lower_source(SOURCE);
+    let text = module.to_string();
+    let parsed = parse_module(&text).unwrap();
+    assert_eq!(parsed, module);
+    assert_eq!(parsed.to_string(), text);
+}
+
+#[test]
+fn test_parse() {
+    let module = parse_module(FUNCTION).unwrap();
+    verify(&module).unwrap();
+    let function = module.function("Main.f").unwrap();
+    assert_eq!(function.parameters, [Register(0), Register(1)]);
+    assert_eq!(function.registers[4], Type::Int);
+    assert_eq!(
+        function.blocks[0].terminator,
+        Terminator::Branch(Register(3), BlockId(1), BlockId(2))
+    );
+    assert_eq!(module.to_string(), FUNCTION);
+
+    let module = parse_module(
+        "; A comment\nclass Object { ; with another\n}\n\n\
+         function Object.f(%0 : Object) : Object {\nbb0:\n  \
+         %7 : String = string \"a\\\"b\\n\\r\"\n  return %0\n}\n",
+    )
+    .unwrap();
+    let function = &module.functions[0];
+    assert_eq!(function.registers.len(), 8);
+    assert_eq!(
+        function.blocks[0].instructions[0],
+        Instruction::String(Register(7), "a\"b\n\r".to_string())
+    );
+    assert!(module.to_string().contains("string \"a\\\"b\\n\\r\""));
+}
+
+#[test]
+fn test_parse_errors() {
+    for (text, line, message) in [
+        (
+            "klass A {\n}\n",
+            1,
+            "expected a class or function, found klass",
+        ),
+        (
+            "class A {\n  method f(Int : Int\n}\n",
+            2,
+            "expected ',' or ')', found ':'",
+        ),
+        ("class A {\n", 1, "unterminated class"),
+        (
+            "function A.f(%0 : A) : A {\nbb1:\n  return %0\n}\n",
+            2,
+            "expected bb0, found bb1",
+        ),
+        (
+            "function A.f(%0 : A) : A {\nbb0:\n  %1 : i32 = frob %0\n}\n",
+            3,
+            "expected an instruction, found frob",
+        ),
+        (
+            "function A.f(%0 : A) : A {\nbb0:\n  %1 : i32 = const 1\n  \
+             %1 : bool = const true\n  return %0\n}\n",
+            4,
+            "%1 is bool here but i32 before",
+        ),
+        (
+            "function A.f(%0 : A) : A {\nbb0:\n  return %2\n}\n",
+            3,
+            "%2 is never defined",
+        ),
+        (
+            "function A.f(%0 : A) : A {\nbb0:\n  \
+             %1 : i32 = const 99999999999\n",
+            3,
+            "invalid integer 99999999999",
+        ),
+        (
+            "function A.f(%0 : A) : A {\nbb0:\n  return %0 %0\n}\n",
+            3,
+            "unexpected %0 after the end",
+        ),
+    ] {
+        let error = parse_module(text).unwrap_err();
+        assert_eq!(
+            error,
+            ParseError {
+                line,
+                message: message.to_string()
+            },
+            "{text}"
+        );
+    }
+}
+
+#[test]
+fn test_verify_errors() {
+    for (from, to, message) in [
+        (
+            "%4 : i32 = add %2, %2",
+            "%4 : i32 = add %2, %3",
+            "in Main.f: bb1: %3 is bool, not i32",
+        ),
+        (
+            "%5 : Int = box %2",
+            "%5 : Int = box %4",
+            "in Main.f: bb2: %4 may be used before it is defined",
+        ),
+        ("jump bb2", "jump bb3", "in Main.f: bb1: bb3 does not exist"),
+        (
+            "%2 : i32 = unbox %1",
+            "%2 : i32 = unbox %0",
+            "in Main.f: bb0: %0 is Main, not Int or Bool",
+        ),
+        (
+            "%5 : Int = box %2",
+            "%5 : Main = box %2",
+            "in Main.f: bb2: %5 is Main, which Int is not",
+        ),
+        (
+            "return %5",
+            "return %0",
+            "in Main.f: bb2: %0 is Main, not Int",
+        ),
+        (
+            "(%0 : Main, %1 : Int)",
+            "(%0 : Main, %1 : Bool)",
+            "in Main.f: the parameters do not match those of Main.f",
+        ),
+        (
+            "class Main inherits Object",
+            "class Main inherits Nothing",
+            "class Main inherits from unknown class Nothing",
+        ),
+        (
+            "function Main.f",
+            "function Main.g",
+            "in Main.g: Main declares no method g",
+        ),
+        (
+            "%4 : i32 = add %2, %2",
+            "%4 : Main = dispatch %0.f(%1)",
+            "in Main.f: bb1: %4 is Main, which Int is not",
+        ),
+        (
+            "%4 : i32 = add %2, %2",
+            "%4 : Int = call Main.f(%0)",
+            "in Main.f: bb1: f takes 1 arguments, not 0",
+        ),
+        (
+            "%5 : Int = box %2",
+            "%6 : i32 = phi [bb0: %2]\n  %5 : Int = box %6",
+            "in Main.f: bb2: the phi for %6 does not have a register for \
+             each predecessor",
+        ),
+        (
+            "%4 : i32 = add %2, %2",
+            "%4 : i32 = add %2, %2\n  %6 : i32 = phi [bb0: %2]",
+            "in Main.f: bb1: the phi for %6 is not at the start of the block",
+        ),
+        (
+            "%5 : Int = box %2",
+            "%6 : i32 = phi [bb0: %4], [bb1: %4]\n  %5 : Int = box %6",
+            "in Main.f: bb0: %4 may be used before it is defined",
+        ),
+        (
+            "%5 : Int = box %2",
+            "%6 : Int = phi [bb0: %2], [bb1: %4]\n  %5 : Int = box %2",
+            "in Main.f: bb2: %2 is i32, which cannot be stored in %6 of type \
+             Int",
+        ),
+    ] {
+        assert!(FUNCTION.contains(from), "{from}");
+        let module = parse_module(&FUNCTION.replace(from, to)).unwrap();
+        let error = verify(&module).unwrap_err();
+        assert_eq!(error.to_string(), message);
+    }
+}
+
+// The classes of FUNCTION with another function of Main.f.
+fn with_function(function: &str) -> Module {
+    let classes = &FUNCTION[..FUNCTION.find("\nfunction").unwrap()];
+    parse_module(&format!("{classes}\n{function}")).unwrap()
+}
+
+// Run passes over the only function of a module, checking the result.
+fn run_passes(module: &mut Module, passes: &[Pass]) -> String {
+    let pipeline = Pipeline::new(passes.to_vec());
+    pipeline.run(module, &Dumps::default(), &mut |_| {});
+    verify(module).unwrap();
+    assert!(module.functions[0].is_ssa());
+    module.functions[0].to_string()
+}
+
+const LOOP: &str = "\
+function Main.f(%0 : Main, %1 : Int) : Int {
+bb0:
+  %2 : i32 = const 0
+  jump bb1
+bb1:
+  %3 : i32 = unbox %1
+  %4 : bool = lt %2, %3
+  branch %4, bb2, bb3
+bb2:
+  %5 : i32 = const 1
+  %2 : i32 = add %2, %5
+  jump bb1
+bb3:
+  %6 : Int = box %2
+  return %6
+}
+";
+
+#[test]
+fn test_ssa() {
+    let mut module = with_function(LOOP);
+    verify(&module).unwrap();
+    assert!(!module.functions[0].is_ssa());
+    let text = run_passes(&mut module, &[Pass::Ssa]);
+    assert_eq!(
+        text,
+        "function Main.f(%0 : Main, %1 : Int) : Int {
+bb0:
+  %2 : i32 = const 0
+  jump bb1
+bb1:
+  %7 : i32 = phi [bb0: %2], [bb2: %8]
+  %3 : i32 = unbox %1
+  %4 : bool = lt %7, %3
+  branch %4, bb2, bb3
+bb2:
+  %5 : i32 = const 1
+  %8 : i32 = add %7, %5
+  jump bb1
+bb3:
+  %6 : Int = box %7
+  return %6
+}
+"
+    );
+    let parsed = parse_module(&module.to_string()).unwrap();
+    assert_eq!(parsed.functions[0].to_string(), text);
+}
+
+#[test]
+fn test_constant() {
+    let mut module = with_function(
+        "function Main.f(%0 : Main, %1 : Int) : Int {
+bb0:
+  %2 : i32 = const 6
+  %3 : i32 = const 7
+  %4 : i32 = mul %2, %3
+  checkzero %3, \"f.cl\":1
+  %5 : i32 = div %4, %3
+  %6 : bool = lt %5, %2
+  checkvoid %0, dispatch f, \"f.cl\":2
+  branch %6, bb1, bb2
+bb1:
+  jump bb2
+bb2:
+  %7 : i32 = phi [bb0: %5], [bb1: %3]
+  %8 : Int = box %7
+  %9 : i32 = unbox %8
+  %10 : i32 = neg %9
+  %11 : Int = box %10
+  return %11
+}
+",
+    );
+    let text = run_passes(
+        &mut module,
+        &[Pass::Constant, Pass::Simplify, Pass::Constant, Pass::Dce],
+    );
+    assert_eq!(
+        text,
+        "function Main.f(%0 : Main, %1 : Int) : Int {
+bb0:
+  %10 : i32 = const -6
+  %11 : Int = box %10
+  return %11
+}
+"
+    );
+}
+
+#[test]
+fn test_copy_gvn_dce() {
+    let mut module = with_function(
+        "function Main.f(%0 : Main, %1 : Int) : Int {
+bb0:
+  %2 : Int = copy %1
+  %3 : i32 = unbox %2
+  %4 : i32 = unbox %1
+  %5 : i32 = add %3, %4
+  %6 : i32 = add %3, %4
+  %7 : i32 = mul %5, %6
+  %8 : i32 = sub %7, %7
+  %9 : Int = box %7
+  return %9
+}
+",
+    );
+    let text = run_passes(&mut module, &[Pass::Copy, Pass::Gvn, Pass::Dce]);
+    assert_eq!(
+        text,
+        "function Main.f(%0 : Main, %1 : Int) : Int {
+bb0:
+  %3 : i32 = unbox %1
+  %5 : i32 = add %3, %3
+  %7 : i32 = mul %5, %5
+  %9 : Int = box %7
+  return %9
+}
+"
+    );
+}
+
+#[test]
+fn test_simplify() {
+    let mut module = with_function(
+        "function Main.f(%0 : Main, %1 : Int) : Int {
+bb0:
+  %2 : i32 = unbox %1
+  %3 : bool = lt %2, %2
+  branch %3, bb1, bb1
+bb1:
+  jump bb2
+bb2:
+  %4 : Int = box %2
+  jump bb3
+bb3:
+  return %4
+bb4:
+  return %1
+}
+",
+    );
+    let text = run_passes(&mut module, &[Pass::Simplify]);
+    assert_eq!(
+        text,
+        "function Main.f(%0 : Main, %1 : Int) : Int {
+bb0:
+  %2 : i32 = unbox %1
+  %3 : bool = lt %2, %2
+  %4 : Int = box %2
+  return %4
+}
+"
+    );
+}
+
+#[test]
+fn test_pipeline() {
+    let mut module = with_function(LOOP);
+    let mut dumps = Vec::new();
+    Pipeline::level(2).run(
+        &mut module,
+        &Dumps {
+            before: vec![Pass::Ssa],
+            after: vec![Pass::Gvn],
+        },
+        &mut |text| dumps.push(text),
+    );
+    verify(&module).unwrap();
+    // The second round changes nothing, so there is no third.
+    assert_eq!(dumps.len(), 4);
+    assert!(dumps[0].starts_with("; before ssa\nfunction Main.f("));
+    assert!(!dumps[0].contains("phi"));
+    assert!(dumps[1].starts_with("; after gvn\nfunction Main.f("));
+    assert!(dumps[1].contains("phi"));
+    assert_eq!(Pipeline::level(0).passes, []);
+    assert_eq!(Pass::from_name("gvn"), Some(Pass::Gvn));
+    assert_eq!(Pass::from_name("licm"), None);
+}
+
+#[test]
+fn test_devirtualise() {
+    let mut module = lower_source(SOURCE);
+    let statistics = Pipeline::new(vec![Pass::Devirtualise]).run(
+        &mut module,
+        &Dumps::default(),
+        &mut |_| {},
+    );
+    verify(&module).unwrap();
+    let main = module
+        .functions
+        .iter()
+        .find(|function| function.name == "Main.main")
+        .unwrap()
+        .to_string();
+    // B overrides f, so only the dispatch on an A stays.
+    assert_eq!(main.matches(" = dispatch ").count(), 1);
+    assert!(main.contains(" = dispatch %") && main.contains(".f("));
+    assert!(main.contains(" = call A.g("));
+    assert!(main.contains(" = call Object.type_name("));
+    assert!(main.contains(" = call IO.out_int("));
+    assert_eq!(
+        statistics.devirtualised,
+        main.matches(" = call ").count() - 1
+    );
+    assert_eq!(statistics.inlined, 0);
+}
+
+#[test]
+fn test_inline() {
+    let mut module = lower_source(SOURCE);
+    let statistics =
+        Pipeline::level(2).run(&mut module, &Dumps::default(), &mut |_| {});
+    verify(&module).unwrap();
+    // Once copies of the new B are propagated, the dispatch of f on it
+    // goes to B.f, which is inlined like A.g.
+    assert!(statistics.devirtualised > 0 && statistics.inlined > 0);
+    let main = module
+        .functions
+        .iter()
+        .find(|function| function.name == "Main.main")
+        .unwrap()
+        .to_string();
+    assert!(!main.contains(" = call A.g("));
+    assert!(!main.contains(" = call B.f("));
+    assert!(!main.contains(" = dispatch "));
+}
+
+#[test]
+fn test_evaluate() {
+    for level in 0..=2 {
+        let mut module = lower_source(SOURCE);
+        Pipeline::level(level).run(&mut module, &Dumps::default(), &mut |_| {});
+        let mut output = Vec::new();
+        evaluate(&module, &mut "".as_bytes(), &mut output).unwrap();
+        assert_eq!(output, b"3");
+    }
+
+    let module =
+        lower_source("class Main { main() : Int { { 1 / (2 - 2); } }; };");
+    let error = evaluate(&module, &mut "".as_bytes(), &mut Vec::new());
+    assert_eq!(
+        error,
+        Err(EvalError {
+            message: "test.cl:1: Division by zero.".to_string(),
+            status: 9
+        })
+    );
+
+    let module = lower_source(
+        "class Main { main() : Int { (let m : Main in m)@Main.main() }; };",
+    );
+    let error = evaluate(&module, &mut "".as_bytes(), &mut Vec::new());
+    assert_eq!(
+        error,
+        Err(EvalError {
+            message: "test.cl:1: Static dispatch to void calling method main."
+                .to_string(),
+            status: 6
+        })
+    );
+}
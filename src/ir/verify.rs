@@ -0,0 +1,580 @@
+//! Checks that a module is well formed: the classes form a tree rooted at
+//! Object, every function implements a declared method or initialiser,
+//! instructions are given operands of the types they expect, phis are at
+//! the start of blocks with a register for each predecessor, and registers
+//! are defined on every path to their uses.
+
+use super::classes::hierarchy;
+use super::*;
+use crate::hierarchy::{
+    ClassHierarchy, MethodSignature, BOOL, INT, OBJECT, SELF_TYPE, STRING,
+};
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+
+#[derive(Debug, PartialEq)]
+pub struct VerifyError {
+    /// The function the error is in, if any.
+    pub function: Option<String>,
+    pub message: String,
+}
+
+impl Display for VerifyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.function {
+            Some(function) => write!(f, "in {function}: {}", self.message),
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// Check a module, returning the first problem found.
+pub fn verify(module: &Module) -> Result<(), VerifyError> {
+    let error = |message| VerifyError {
+        function: None,
+        message,
+    };
+    let mut names = HashSet::new();
+    for class in module.classes.iter() {
+        if !names.insert(class.name.as_str()) {
+            return Err(error(format!(
+                "class {} is declared twice",
+                class.name
+            )));
+        }
+    }
+    let hierarchy = hierarchy(&module.classes);
+    for class in module.classes.iter() {
+        match &class.parent {
+            None if class.name != OBJECT => {
+                return Err(error(format!(
+                    "class {} has no parent",
+                    class.name
+                )))
+            }
+            Some(parent) if !hierarchy.contains(parent) => {
+                return Err(error(format!(
+                    "class {} inherits from unknown class {parent}",
+                    class.name
+                )))
+            }
+            _ => {}
+        }
+        let ancestors = hierarchy.ancestors(&class.name);
+        if ancestors.take(module.classes.len() + 1).count()
+            > module.classes.len()
+        {
+            return Err(error(format!(
+                "class {} inherits from itself",
+                class.name
+            )));
+        }
+    }
+    for class in [OBJECT, INT, BOOL, STRING] {
+        if !hierarchy.contains(class) {
+            return Err(error(format!("class {class} is not declared")));
+        }
+    }
+
+    let mut names = HashSet::new();
+    for function in module.functions.iter() {
+        if !names.insert(function.name.as_str()) {
+            return Err(error(format!(
+                "function {} is defined twice",
+                function.name
+            )));
+        }
+    }
+    for function in module.functions.iter() {
+        let verifier = Verifier {
+            module,
+            hierarchy: &hierarchy,
+            function,
+        };
+        verifier.verify().map_err(|message| VerifyError {
+            function: Some(function.name.clone()),
+            message,
+        })?;
+    }
+    Ok(())
+}
+
+struct Verifier<'v, 'm> {
+    module: &'m Module,
+    hierarchy: &'v ClassHierarchy<'static>,
+    function: &'m Function,
+}
+
+type Check = Result<(), String>;
+
+impl<'m> Verifier<'_, 'm> {
+    fn verify(&self) -> Check {
+        let function = self.function;
+        for type_id in function.registers.iter() {
+            self.valid_type(type_id)?;
+        }
+        self.valid_type(&function.return_type)?;
+        self.signature()?;
+        if function.blocks.is_empty() {
+            return Err("the function has no blocks".to_string());
+        }
+        for (index, block) in function.blocks.iter().enumerate() {
+            let block_id = BlockId(index as u32);
+            for instruction in block.instructions.iter() {
+                self.instruction(instruction)
+                    .map_err(|message| format!("{block_id}: {message}"))?;
+            }
+            self.terminator(&block.terminator)
+                .map_err(|message| format!("{block_id}: {message}"))?;
+        }
+        self.phis()?;
+        self.definitions()
+    }
+
+    // Check that phis come first in their blocks, with a register for each
+    // predecessor.
+    fn phis(&self) -> Check {
+        let predecessors = self.function.predecessors();
+        for (index, block) in self.function.blocks.iter().enumerate() {
+            let block_id = BlockId(index as u32);
+            let mut phis = true;
+            for instruction in block.instructions.iter() {
+                let (result, incoming) = match instruction {
+                    Instruction::Phi(result, incoming) => (result, incoming),
+                    _ => {
+                        phis = false;
+                        continue;
+                    }
+                };
+                if !phis {
+                    return Err(format!(
+                        "{block_id}: the phi for {result} is not at the \
+                         start of the block"
+                    ));
+                }
+                let mut blocks: Vec<BlockId> =
+                    incoming.iter().map(|(block, _)| *block).collect();
+                blocks.sort();
+                let mut expected = predecessors[index].clone();
+                expected.sort();
+                if blocks != expected {
+                    return Err(format!(
+                        "{block_id}: the phi for {result} does not have a \
+                         register for each predecessor"
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn valid_type(&self, type_id: &Type) -> Check {
+        match type_id {
+            Type::Object(class) if !self.hierarchy.contains(class) => {
+                Err(format!("unknown class {class}"))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn register(&self, register: Register) -> Result<&'m Type, String> {
+        self.function
+            .registers
+            .get(register.0 as usize)
+            .ok_or_else(|| format!("{register} has no type"))
+    }
+
+    // The class of a register holding an object.
+    fn object(&self, register: Register) -> Result<&'m str, String> {
+        match self.register(register)? {
+            Type::Object(class) => Ok(class),
+            type_id => Err(format!("{register} is {type_id}, not an object")),
+        }
+    }
+
+    fn expect(&self, register: Register, expected: Type) -> Check {
+        let type_id = self.register(register)?;
+        if *type_id != expected {
+            return Err(format!("{register} is {type_id}, not {expected}"));
+        }
+        Ok(())
+    }
+
+    // Whether a value of a type can be stored in a register of another.
+    fn assignable(&self, from: &Type, to: &Type) -> bool {
+        match (from, to) {
+            (Type::Object(sub), Type::Object(sup)) => {
+                self.hierarchy.conforms(sub, sup)
+            }
+            (from, to) => from == to,
+        }
+    }
+
+    // Check that an object of a class can be stored in a register.
+    fn store(&self, class: &str, register: Register) -> Check {
+        let to = self.register(register)?;
+        if !self.assignable(&Type::Object(class.to_string()), to) {
+            return Err(format!("{register} is {to}, which {class} is not"));
+        }
+        Ok(())
+    }
+
+    fn store_register(&self, from: Register, to: Register) -> Check {
+        let (from_type, to_type) = (self.register(from)?, self.register(to)?);
+        if !self.assignable(from_type, to_type) {
+            return Err(format!(
+                "{from} is {from_type}, which cannot be stored in {to} of \
+                 type {to_type}"
+            ));
+        }
+        Ok(())
+    }
+
+    // The name of a function is that of the method it implements, whose
+    // parameters and return type it takes, or of an initialiser.
+    fn signature(&self) -> Check {
+        let function = self.function;
+        let (class, method) = function
+            .name
+            .rsplit_once('.')
+            .ok_or("the function is not named after a method")?;
+        if !self.hierarchy.contains(class) {
+            return Err(format!("unknown class {class}"));
+        }
+        let (parameters, return_type) = if method == INIT_METHOD {
+            (Vec::new(), SELF_TYPE)
+        } else {
+            let declaration = self
+                .hierarchy
+                .get(class)
+                .and_then(|info| info.method(method))
+                .ok_or_else(|| {
+                    format!("{class} declares no method {method}")
+                })?;
+            (
+                declaration
+                    .formals
+                    .iter()
+                    .map(|(_, type_id)| type_id.as_str())
+                    .collect(),
+                declaration.return_type.as_str(),
+            )
+        };
+        let resolve = |type_id: &str| match type_id {
+            SELF_TYPE => Type::Object(class.to_string()),
+            type_id => Type::Object(type_id.to_string()),
+        };
+        let expected: Vec<Type> =
+            [class].into_iter().chain(parameters).map(resolve).collect();
+        let found = function
+            .parameters
+            .iter()
+            .map(|parameter| self.register(*parameter).cloned())
+            .collect::<Result<Vec<Type>, String>>()?;
+        if found != expected {
+            return Err(format!(
+                "the parameters do not match those of {class}.{method}"
+            ));
+        }
+        if function.return_type != resolve(return_type) {
+            return Err(format!(
+                "the return type does not match that of {class}.{method}"
+            ));
+        }
+        Ok(())
+    }
+
+    // Check the arguments and result of a call of a method on a receiver.
+    fn call(
+        &self,
+        method: &MethodSignature,
+        receiver: Register,
+        arguments: &[Register],
+        result: Register,
+    ) -> Check {
+        let receiver_class = self.object(receiver)?;
+        if arguments.len() != method.formals.len() {
+            return Err(format!(
+                "{} takes {} arguments, not {}",
+                method.name,
+                method.formals.len(),
+                arguments.len()
+            ));
+        }
+        for (argument, (_, parameter)) in arguments.iter().zip(&method.formals)
+        {
+            let type_id = self.register(*argument)?;
+            let parameter = Type::Object(parameter.clone());
+            if !self.assignable(type_id, &parameter) {
+                return Err(format!(
+                    "{argument} is {type_id}, not a {parameter} argument"
+                ));
+            }
+        }
+        match method.return_type.as_str() {
+            SELF_TYPE => self.store(receiver_class, result),
+            class => self.store(class, result),
+        }
+    }
+
+    // The type of an attribute a class defines or inherits.
+    fn attribute(&self, class: &str, name: &str) -> Option<&str> {
+        let attribute = self.hierarchy.lookup_attribute(class, name)?;
+        Some(attribute.type_id.as_str())
+    }
+
+    fn instruction(&self, instruction: &Instruction) -> Check {
+        use Instruction::*;
+        if let Some(result) = instruction.result() {
+            self.register(result)?;
+        }
+        for operand in instruction.operands() {
+            self.register(operand)?;
+        }
+        match instruction {
+            Int(result, _) => self.expect(*result, Type::Int),
+            Bool(result, _) => self.expect(*result, Type::Bool),
+            String(result, _) => self.store(STRING, *result),
+            Void(result) => self.object(*result).map(|_| ()),
+            Copy(result, source) => self.store_register(*source, *result),
+            Unary(result, operator, operand) => {
+                let type_id = match operator {
+                    UnaryOperator::Negate => Type::Int,
+                    UnaryOperator::Not => Type::Bool,
+                };
+                self.expect(*operand, type_id.clone())?;
+                self.expect(*result, type_id)
+            }
+            Binary(result, operator, left, right) => {
+                let (operands, result_type) = match operator {
+                    BinaryOperator::Equals => {
+                        (self.register(*left)?.clone(), Type::Bool)
+                    }
+                    BinaryOperator::LessThan
+                    | BinaryOperator::LessThanOrEquals => {
+                        (Type::Int, Type::Bool)
+                    }
+                    _ => (Type::Int, Type::Int),
+                };
+                if let Type::Object(_) = operands {
+                    return Err(format!("{left} is not i32 or bool"));
+                }
+                self.expect(*left, operands.clone())?;
+                self.expect(*right, operands)?;
+                self.expect(*result, result_type)
+            }
+            IsVoid(result, object) => {
+                self.object(*object)?;
+                self.expect(*result, Type::Bool)
+            }
+            Equal(result, left, right) => {
+                self.object(*left)?;
+                self.object(*right)?;
+                self.expect(*result, Type::Bool)
+            }
+            Box(result, value) => match self.register(*value)? {
+                Type::Int => self.store(INT, *result),
+                Type::Bool => self.store(BOOL, *result),
+                type_id => {
+                    Err(format!("{value} is {type_id}, not i32 or bool"))
+                }
+            },
+            Unbox(result, object) => match self.object(*object)? {
+                INT => self.expect(*result, Type::Int),
+                BOOL => self.expect(*result, Type::Bool),
+                class => Err(format!("{object} is {class}, not Int or Bool")),
+            },
+            GetAttribute(result, object, name) => {
+                let class = self.object(*object)?;
+                match self.attribute(class, name) {
+                    Some(SELF_TYPE) => self.store(class, *result),
+                    Some(type_id) => self.store(type_id, *result),
+                    None => Err(format!("{class} has no attribute {name}")),
+                }
+            }
+            SetAttribute(object, name, value) => {
+                let class = self.object(*object)?;
+                let type_id = match self.attribute(class, name) {
+                    Some(SELF_TYPE) => class,
+                    Some(type_id) => type_id,
+                    None => {
+                        return Err(format!("{class} has no attribute {name}"))
+                    }
+                };
+                let value_type = self.register(*value)?;
+                let attribute_type = Type::Object(type_id.to_string());
+                if !self.assignable(value_type, &attribute_type) {
+                    return Err(format!(
+                        "{value} is {value_type}, not {attribute_type}"
+                    ));
+                }
+                Ok(())
+            }
+            New(result, class) => {
+                if !self.hierarchy.contains(class) {
+                    return Err(format!("unknown class {class}"));
+                }
+                self.store(class, *result)
+            }
+            NewSelfType(result, object) => {
+                self.object(*object)?;
+                self.store_register(*object, *result)
+            }
+            Dispatch {
+                result,
+                receiver,
+                method,
+                arguments,
+            } => {
+                let class = self.object(*receiver)?;
+                let declaration = self.hierarchy.lookup_method(class, method);
+                let declaration = declaration
+                    .ok_or_else(|| format!("{class} has no method {method}"))?;
+                self.call(declaration, *receiver, arguments, *result)
+            }
+            Call {
+                result,
+                function,
+                arguments,
+            } => {
+                let (class, method) = function
+                    .rsplit_once('.')
+                    .ok_or_else(|| format!("unknown function {function}"))?;
+                let receiver = *arguments
+                    .first()
+                    .ok_or_else(|| format!("{function} needs a receiver"))?;
+                if !self.hierarchy.contains(class) {
+                    return Err(format!("unknown class {class}"));
+                }
+                let receiver_class = self.object(receiver)?;
+                if !self.hierarchy.conforms(receiver_class, class) {
+                    return Err(format!(
+                        "{receiver} is {receiver_class}, not {class}"
+                    ));
+                }
+                if method == INIT_METHOD {
+                    if self.module.function(function).is_none() {
+                        return Err(format!("unknown function {function}"));
+                    }
+                    if arguments.len() != 1 {
+                        return Err(format!("{function} takes no arguments"));
+                    }
+                    return self.store(receiver_class, *result);
+                }
+                let declaration = self
+                    .hierarchy
+                    .get(class)
+                    .and_then(|info| info.method(method))
+                    .ok_or_else(|| format!("{class} declares no {method}"))?;
+                self.call(declaration, receiver, &arguments[1..], *result)
+            }
+            InstanceOf(result, object, class) => {
+                self.object(*object)?;
+                if !self.hierarchy.contains(class) {
+                    return Err(format!("unknown class {class}"));
+                }
+                self.expect(*result, Type::Bool)
+            }
+            Cast(result, object) => {
+                self.object(*object)?;
+                self.object(*result).map(|_| ())
+            }
+            CheckVoid(object, _, _) => self.object(*object).map(|_| ()),
+            CheckZero(value, _) => self.expect(*value, Type::Int),
+            Phi(result, incoming) => {
+                for (_, value) in incoming.iter() {
+                    self.store_register(*value, *result)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn terminator(&self, terminator: &Terminator) -> Check {
+        for operand in terminator.operands() {
+            self.register(operand)?;
+        }
+        for target in terminator.successors() {
+            if target.0 as usize >= self.function.blocks.len() {
+                return Err(format!("{target} does not exist"));
+            }
+        }
+        match terminator {
+            Terminator::Jump(_) => Ok(()),
+            Terminator::Branch(condition, _, _) => {
+                self.expect(*condition, Type::Bool)
+            }
+            Terminator::Return(value) => {
+                let type_id = self.register(*value)?;
+                if !self.assignable(type_id, &self.function.return_type) {
+                    return Err(format!(
+                        "{value} is {type_id}, not {}",
+                        self.function.return_type
+                    ));
+                }
+                Ok(())
+            }
+            Terminator::NoMatch(object) => self.object(*object).map(|_| ()),
+        }
+    }
+
+    // Check that registers are defined on every path to their uses, by
+    // finding the registers defined on entry to each block reachable from
+    // the entry block. Those sets only shrink as paths are found, so a use
+    // outside one is never defined on some path. The registers of a phi
+    // are used at the end of the predecessors they are given for.
+    fn definitions(&self) -> Check {
+        let blocks = &self.function.blocks;
+        let mut defined: Vec<Option<HashSet<Register>>> =
+            vec![None; blocks.len()];
+        defined[0] = Some(self.function.parameters.iter().copied().collect());
+        let mut work = vec![BlockId(0)];
+        while let Some(block_id) = work.pop() {
+            let block = self.function.block(block_id);
+            let mut registers = defined[block_id.0 as usize].clone().unwrap();
+            let used = |registers: &HashSet<Register>,
+                        operands: Vec<Register>| {
+                match operands.into_iter().find(|r| !registers.contains(r)) {
+                    Some(register) => Err(format!(
+                        "{block_id}: {register} may be used before it is \
+                         defined"
+                    )),
+                    None => Ok(()),
+                }
+            };
+            for instruction in block.instructions.iter() {
+                if !matches!(instruction, Instruction::Phi(..)) {
+                    used(&registers, instruction.operands())?;
+                }
+                registers.extend(instruction.result());
+            }
+            used(&registers, block.terminator.operands())?;
+            for successor in block.terminator.successors() {
+                for instruction in
+                    self.function.block(successor).instructions.iter()
+                {
+                    if let Instruction::Phi(_, incoming) = instruction {
+                        let operands = incoming
+                            .iter()
+                            .filter(|(block, _)| *block == block_id)
+                            .map(|(_, register)| *register)
+                            .collect();
+                        used(&registers, operands)?;
+                    }
+                }
+                let entry = &mut defined[successor.0 as usize];
+                let narrowed = match entry {
+                    None => registers.clone(),
+                    Some(entry) => {
+                        entry.intersection(&registers).copied().collect()
+                    }
+                };
+                if entry.as_ref() != Some(&narrowed) {
+                    *entry = Some(narrowed);
+                    work.push(successor);
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,1382 @@
+//! A just-in-time compiler running programs in-process, built on Cranelift.
+//!
+//! Every method and initialiser is compiled to machine code when a program
+//! is loaded. Objects have the layout of the x86-64 backend, with eight byte
+//! words, and the constants, prototype objects and tables of the program are
+//! allocated by the compiler, which embeds their addresses in the code.
+//! Methods take the receiver and then the arguments, and are called through
+//! the dispatch table of the receiver, or directly where no subclass of the
+//! class dispatched on overrides the method. The methods of the basic
+//! classes and the other runtime routines are Rust functions in
+//! [`runtime`].
+//!
+//! Values of static type Int or Bool are not boxed, but kept as an i32 or a
+//! condition in Cranelift values and variables, and passed to and returned
+//! from methods declared to take or return them as such. Overriding methods
+//! have the types of the methods they override, so every method a dispatch
+//! may reach agrees on this. The runtime routines of the basic classes take
+//! and return objects, and are called through adapters where they take or
+//! return Ints. Values are only boxed where an object is needed: when stored
+//! in an attribute, passed as an object, used as a receiver or case
+//! expression, or joined with values of other types. Compiling with
+//! [`Representation::Boxed`] instead boxes every Int and Bool, as naive code
+//! does, which [`Jit::allocations`] measures the cost of.
+//!
+//! A runtime error sets a flag that compiled code checks after each call
+//! that may fail, returning at once, so that [`Jit::run`] returns the error
+//! as the interpreter does. Calls deeper than the stack `coolc` runs
+//! programs with holds are refused as overflowing the stack, counting the
+//! call depth in each function on entry and return.
+//!
+//! Programs only run on hosts Cranelift generates code for. Elsewhere,
+//! [`Jit::new`] returns [`Unsupported`] so that the caller can run the
+//! program with the interpreter instead.
+
+mod runtime;
+
+pub use crate::codegen::Representation;
+
+use self::runtime::{Globals, Run};
+use crate::codegen::{attribute_offset, dispatch_class, Layout, HEADER_WORDS};
+use crate::hierarchy::*;
+use crate::interpreter::{RuntimeError, STACK_SIZE};
+use crate::ptree::*;
+use crate::semant::SymbolTable;
+use crate::tokens::Span;
+use cranelift_codegen::ir::condcodes::IntCC;
+use cranelift_codegen::ir::{
+    types, AbiParam, Block, InstBuilder, InstructionData, MemFlags, Opcode,
+    Signature, UserFuncName, Value, ValueDef,
+};
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_codegen::Context;
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext, Variable};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{default_libcall_names, FuncId, Linkage, Module};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::io::{self, BufRead, Write};
+use std::time::{Duration, Instant};
+use ExpressionData::*;
+
+#[cfg(test)]
+mod tests;
+
+const WORD: usize = 8;
+const POINTER: types::Type = types::I64;
+
+// The stack used by a typical frame of compiled code, with room for the
+// runtime routines the deepest frames call.
+const FRAME_SIZE: usize = 4 << 10;
+
+// The frames active at once on a stack of STACK_SIZE bytes.
+const CALL_DEPTH: usize = STACK_SIZE / FRAME_SIZE;
+
+/// Why a program cannot be compiled, so that it is interpreted instead.
+#[derive(Debug)]
+pub struct Unsupported(String);
+
+impl Display for Unsupported {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// A compiled program, ready to run.
+pub struct Jit<'p> {
+    // The module owns the code.
+    _module: JITModule,
+    // Constants, prototype objects and tables, which the code refers to.
+    _data: Data,
+    globals: Globals,
+    // The locations runtime errors are reported at, by index.
+    locations: Vec<Span<'p>>,
+    // The prototype of Main, its initialiser and its main method.
+    entry: (*const u8, *const u8, *const u8),
+    times: Vec<(String, Duration)>,
+    // The objects allocated by the last run.
+    allocations: Cell<u64>,
+}
+
+impl<'p> Jit<'p> {
+    /// Compile a program that has passed semantic analysis, with unboxed
+    /// Ints and Bools.
+    pub fn new(
+        program: &Program<'p>,
+        hierarchy: &ClassHierarchy,
+    ) -> Result<Self, Unsupported> {
+        Self::with_representation(program, hierarchy, Representation::Unboxed)
+    }
+
+    /// Compile a program that has passed semantic analysis, representing
+    /// Ints and Bools as given.
+    pub fn with_representation(
+        program: &Program<'p>,
+        hierarchy: &ClassHierarchy,
+        representation: Representation,
+    ) -> Result<Self, Unsupported> {
+        let mut flags = settings::builder();
+        flags.set("use_colocated_libcalls", "false").unwrap();
+        flags.set("is_pic", "false").unwrap();
+        flags.set("opt_level", "speed").unwrap();
+        let isa = cranelift_native::builder()
+            .map_err(|err| Unsupported(format!("unsupported host: {err}")))?
+            .finish(settings::Flags::new(flags))
+            .map_err(|err| Unsupported(format!("unsupported host: {err}")))?;
+        if isa.pointer_type() != POINTER {
+            return Err(Unsupported("unsupported host: not 64-bit".into()));
+        }
+        let mut builder = JITBuilder::with_isa(isa, default_libcall_names());
+        for (name, function) in runtime::symbols() {
+            builder.symbol(name, function);
+        }
+
+        let mut compiler = Compiler {
+            module: JITModule::new(builder),
+            layout: Layout::new(hierarchy),
+            data: Data::default(),
+            functions: HashMap::new(),
+            signatures: HashMap::new(),
+            times: Vec::new(),
+            representation,
+            locations: Vec::new(),
+        };
+        compiler.declare(program)?;
+        compiler.tables();
+        compiler.initialisers(program)?;
+        for class in program.classes.iter() {
+            for feature in class.features.iter() {
+                if let FeatureData::Method(name, _, formals, body) =
+                    &feature.data
+                {
+                    compiler.method(&class.name, name, formals, body)?;
+                }
+            }
+        }
+        compiler.finish()
+    }
+
+    /// The time taken to compile each method and initialiser, in order.
+    pub fn compile_times(&self) -> &[(String, Duration)] {
+        &self.times
+    }
+
+    /// The number of objects the last run of the program allocated, not
+    /// counting the constants allocated by the compiler.
+    pub fn allocations(&self) -> u64 {
+        self.allocations.get()
+    }
+
+    /// Write the compilation times as a table.
+    pub fn write_times(&self, output: &mut dyn Write) -> io::Result<()> {
+        let width = self
+            .times
+            .iter()
+            .map(|(name, _)| name.len())
+            .chain([6])
+            .max()
+            .unwrap_or_default();
+        writeln!(output, "{:<width$}  {:>12}", "Method", "Compile (ms)")?;
+        for (name, time) in self.times.iter() {
+            let time = time.as_secs_f64() * 1000.0;
+            writeln!(output, "{name:<width$}  {time:>12.3}")?;
+        }
+        Ok(())
+    }
+
+    /// Run the program on this thread, which needs a stack of
+    /// [`STACK_SIZE`] bytes for the calls allowed. The objects it allocates
+    /// are freed once it ends.
+    pub fn run(
+        &self,
+        input: &mut dyn BufRead,
+        output: &mut dyn Write,
+    ) -> Result<(), RuntimeError<'p>> {
+        type Init = extern "C" fn(*const u8) -> *const u8;
+        // The result of main, which may be unboxed, is not used.
+        type Main = extern "C" fn(*const u8);
+        let run = Run::start(self.globals, input, &mut *output);
+        // SAFETY: the flag and the call depth are allocated by the compiler.
+        // The functions are initialisers and methods of the program taking
+        // a receiver, and the prototype is that of Main.
+        let failure = unsafe {
+            let state = self.globals.failed;
+            (*state, *state.add(1)) = (0, 0);
+            let (prototype, init, main) = self.entry;
+            let init = std::mem::transmute::<*const u8, Init>(init);
+            let main = std::mem::transmute::<*const u8, Main>(main);
+            let object = init(runtime::copy(prototype));
+            if *state == 0 {
+                main(object);
+            }
+            runtime::take_failure()
+        };
+        self.allocations.set(runtime::allocations());
+        drop(run);
+        match failure {
+            Some(failure) => Err(match failure.location {
+                Some(index) => {
+                    RuntimeError::new(failure.kind, self.locations[index])
+                }
+                None => RuntimeError::without_location(failure.kind),
+            }),
+            None => Ok(output.flush()?),
+        }
+    }
+}
+
+// Objects allocated by the compiler. Each is boxed, so that its address
+// does not change as more are added.
+#[derive(Default)]
+struct Data {
+    objects: Vec<Box<[u64]>>,
+    strings: HashMap<String, u64>,
+    ints: HashMap<i32, u64>,
+    bools: [u64; 2],
+    // Whether the run has failed and the call depth, in two words.
+    state: u64,
+    // The dispatch tables and prototype objects by tag, and the indices of
+    // the dispatch tables and of class_objTab in `objects`.
+    dispatch_tables: Vec<(u64, usize)>,
+    prototypes: Vec<u64>,
+    class_names: u64,
+    class_objects: (u64, usize),
+    int_tag: usize,
+    string_tag: usize,
+    bool_tag: usize,
+}
+
+impl Data {
+    fn object(&mut self, words: Vec<u64>) -> u64 {
+        self.objects.push(words.into_boxed_slice());
+        self.objects.last().unwrap().as_ptr() as u64
+    }
+
+    // The words of an Int or Bool with a value.
+    fn boxed(tag: usize, dispatch: u64, value: i32) -> Vec<u64> {
+        let mut value_word = [0; 8];
+        value_word[..4].copy_from_slice(&value.to_ne_bytes());
+        vec![tag as u64, 4, dispatch, u64::from_ne_bytes(value_word)]
+    }
+
+    fn int(&mut self, int: i32) -> u64 {
+        if let Some(address) = self.ints.get(&int) {
+            return *address;
+        }
+        let dispatch = self.dispatch_tables[self.int_tag].0;
+        let address = self.object(Self::boxed(self.int_tag, dispatch, int));
+        self.ints.insert(int, address);
+        address
+    }
+
+    fn string(&mut self, string: &str) -> u64 {
+        if let Some(address) = self.strings.get(string) {
+            return *address;
+        }
+        let length = self.int(string.len() as i32);
+        let words = HEADER_WORDS + 1 + (string.len() + WORD) / WORD;
+        let dispatch = self.dispatch_tables[self.string_tag].0;
+        let mut object =
+            vec![self.string_tag as u64, words as u64, dispatch, length];
+        let mut chars = string.as_bytes().to_vec();
+        chars.resize(WORD * (words - HEADER_WORDS - 1), 0);
+        object.extend(chars.chunks(WORD).map(|chunk| {
+            u64::from_ne_bytes(chunk.try_into().expect("a word"))
+        }));
+        let address = self.object(object);
+        self.strings.insert(string.to_string(), address);
+        address
+    }
+}
+
+struct Compiler<'p> {
+    module: JITModule,
+    layout: Layout,
+    data: Data,
+    // Compiled functions and runtime routines by name, with the routines
+    // called through adapters as NAME_boxed.
+    functions: HashMap<String, FuncId>,
+    // How the functions that take a receiver represent their parameters and
+    // result, by name.
+    signatures: HashMap<String, (Vec<Repr>, Repr)>,
+    times: Vec<(String, Duration)>,
+    representation: Representation,
+    locations: Vec<Span<'p>>,
+}
+
+// The methods of the basic classes and the equality test of the runtime,
+// with the types of their parameters and result.
+const ROUTINES: [(&str, &[&str], &str); 11] = [
+    ("Object.abort", &[], OBJECT),
+    ("Object.type_name", &[], STRING),
+    ("Object.copy", &[], SELF_TYPE),
+    ("IO.out_string", &[STRING], SELF_TYPE),
+    ("IO.out_int", &[INT], SELF_TYPE),
+    ("IO.in_string", &[], STRING),
+    ("IO.in_int", &[], INT),
+    ("String.length", &[], INT),
+    ("String.concat", &[STRING], STRING),
+    ("String.substr", &[INT, INT], STRING),
+    ("equality_test", &[OBJECT], OBJECT),
+];
+
+fn error(err: impl Display) -> Unsupported {
+    Unsupported(format!("compilation failed: {err}"))
+}
+
+impl<'p> Compiler<'p> {
+    // A signature taking a receiver and some arguments and returning a
+    // result, represented as given.
+    fn method_signature(
+        module: &JITModule,
+        parameters: &[Repr],
+        result: Repr,
+    ) -> Signature {
+        let mut signature = module.make_signature();
+        signature.params.push(AbiParam::new(POINTER));
+        for parameter in parameters {
+            signature.params.push(AbiParam::new(parameter.type_of()));
+        }
+        signature.returns.push(AbiParam::new(result.type_of()));
+        signature
+    }
+
+    // How a function with parameters and a result of some types represents
+    // them.
+    fn reprs(&self, parameters: &[&str], result: &str) -> (Vec<Repr>, Repr) {
+        let unbox = self.representation == Representation::Unboxed;
+        let parameters = parameters
+            .iter()
+            .map(|type_id| Repr::of(type_id, unbox))
+            .collect();
+        (parameters, Repr::of(result, unbox))
+    }
+
+    // Declare the runtime routines and the functions to be compiled.
+    fn declare(&mut self, program: &Program<'p>) -> Result<(), Unsupported> {
+        let mut functions = Vec::new();
+        for class in self.layout.classes.iter() {
+            let name = format!("{}_init", class.name);
+            functions.push((name, (Vec::new(), Repr::Object)));
+        }
+        for class in program.classes.iter() {
+            for feature in class.features.iter() {
+                if let FeatureData::Method(name, result, formals, _) =
+                    &feature.data
+                {
+                    let parameters: Vec<&str> = formals
+                        .iter()
+                        .map(|formal| formal.type_id.as_str())
+                        .collect();
+                    let name = format!("{}.{name}", class.name);
+                    functions.push((name, self.reprs(&parameters, result)));
+                }
+            }
+        }
+        // Routines taking or returning Ints have variants for unboxed Ints.
+        for (name, parameters, result) in ROUTINES {
+            let (parameters, result) = self.reprs(parameters, result);
+            let symbol = if parameters
+                .iter()
+                .chain([&result])
+                .all(|repr| *repr == Repr::Object)
+            {
+                name.to_string()
+            } else {
+                format!("{name}_unboxed")
+            };
+            let signature =
+                Self::method_signature(&self.module, &parameters, result);
+            let id = self
+                .module
+                .declare_function(&symbol, Linkage::Import, &signature)
+                .map_err(error)?;
+            self.functions.insert(name.to_string(), id);
+            self.signatures
+                .insert(name.to_string(), (parameters, result));
+        }
+        // The error routines take the index of a location first, but for
+        // that of a stack overflow.
+        for (name, parameters) in [
+            ("_dispatch_abort", &[types::I32, POINTER, types::I32][..]),
+            ("_case_abort2", &[types::I32]),
+            ("_divide_abort", &[types::I32]),
+            ("_case_abort", &[types::I32, POINTER]),
+            ("_depth_abort", &[]),
+            ("_fail_at", &[types::I32]),
+        ] {
+            let mut signature = self.module.make_signature();
+            for parameter in parameters {
+                signature.params.push(AbiParam::new(*parameter));
+            }
+            let id = self
+                .module
+                .declare_function(name, Linkage::Import, &signature)
+                .map_err(error)?;
+            self.functions.insert(name.to_string(), id);
+        }
+        for (name, (parameters, result)) in functions {
+            let signature =
+                Self::method_signature(&self.module, &parameters, result);
+            let id = self
+                .module
+                .declare_function(&name, Linkage::Local, &signature)
+                .map_err(error)?;
+            self.functions.insert(name.clone(), id);
+            self.signatures.insert(name, (parameters, result));
+        }
+        Ok(())
+    }
+
+    // Allocate the dispatch tables, filled in once the code is finalized,
+    // the Bool constants, the prototype objects and the class tables.
+    fn tables(&mut self) {
+        let data = &mut self.data;
+        data.int_tag = self.layout.tag(INT);
+        data.string_tag = self.layout.tag(STRING);
+        data.bool_tag = self.layout.tag(BOOL);
+        for class in self.layout.classes.iter() {
+            let address = data.object(vec![0; class.methods.len()]);
+            data.dispatch_tables.push((address, data.objects.len() - 1));
+        }
+        for value in 0..2 {
+            let dispatch = data.dispatch_tables[data.bool_tag].0;
+            let words = Data::boxed(data.bool_tag, dispatch, value as i32);
+            data.bools[value] = data.object(words);
+        }
+        let zero = data.int(0);
+        let empty = data.string("");
+        for class in self.layout.classes.iter() {
+            let mut words = vec![
+                class.tag as u64,
+                class.size() as u64,
+                data.dispatch_tables[class.tag].0,
+            ];
+            match class.name.as_str() {
+                INT | BOOL => words.push(0),
+                STRING => words.extend([zero, 0]),
+                _ => {}
+            }
+            for (_, type_id) in class.attributes.iter() {
+                words.push(match type_id.as_str() {
+                    INT => zero,
+                    STRING => empty,
+                    BOOL => data.bools[0],
+                    _ => 0,
+                });
+            }
+            let address = data.object(words);
+            data.prototypes.push(address);
+        }
+        let names = self
+            .layout
+            .classes
+            .iter()
+            .map(|class| data.string(&class.name))
+            .collect();
+        data.class_names = data.object(names);
+        let objects = vec![0; 2 * self.layout.classes.len()];
+        data.class_objects = (data.object(objects), data.objects.len() - 1);
+        data.state = data.object(vec![0, 0]);
+    }
+
+    // Compile a function taking self and parameters, whose body is
+    // generated by `body`, timing it.
+    fn function(
+        &mut self,
+        name: &str,
+        class: &str,
+        parameters: &[&str],
+        body: impl FnOnce(&mut Translator<'_, 'p>),
+    ) -> Result<(), Unsupported> {
+        let start = Instant::now();
+        let id = self.functions[name];
+        let (reprs, result) = &self.signatures[name];
+        let mut context = self.module.make_context();
+        context.func.signature =
+            Self::method_signature(&self.module, reprs, *result);
+        context.func.name = UserFuncName::user(0, id.as_u32());
+        let mut builder_context = FunctionBuilderContext::new();
+        let builder =
+            FunctionBuilder::new(&mut context.func, &mut builder_context);
+        let mut translator = Translator {
+            builder,
+            module: &mut self.module,
+            layout: &self.layout,
+            data: &mut self.data,
+            functions: &self.functions,
+            signatures: &self.signatures,
+            locations: &mut self.locations,
+            class: class.to_string(),
+            scopes: SymbolTable::new(),
+            variables: 0,
+            unbox: self.representation == Representation::Unboxed,
+            boxes: HashMap::new(),
+            result: *result,
+            unwind: None,
+        };
+        translator.entry(parameters, reprs);
+        body(&mut translator);
+        translator.finish();
+        self.define(id, &mut context)?;
+        self.times.push((name.to_string(), start.elapsed()));
+        Ok(())
+    }
+
+    fn define(
+        &mut self,
+        id: FuncId,
+        context: &mut Context,
+    ) -> Result<(), Unsupported> {
+        self.module.define_function(id, context).map_err(error)?;
+        self.module.clear_context(context);
+        Ok(())
+    }
+
+    fn initialisers(
+        &mut self,
+        program: &Program<'p>,
+    ) -> Result<(), Unsupported> {
+        for initialiser in self.layout.initialisers(program) {
+            let (class, parent) = (&initialiser.class, &initialiser.parent);
+            self.function(&format!("{class}_init"), class, &[], |t| {
+                let receiver = t.self_object();
+                if let Some(parent) = parent {
+                    t.call(&format!("{parent}_init"), &[receiver]);
+                    t.check(None);
+                }
+                for (attr, init) in initialiser.attributes.iter() {
+                    let value = t.expression(init);
+                    t.store(attr, value);
+                }
+                t.return_(receiver);
+            })?;
+        }
+        Ok(())
+    }
+
+    fn method(
+        &mut self,
+        class: &str,
+        name: &str,
+        formals: &[Formal],
+        body: &Expression<'p>,
+    ) -> Result<(), Unsupported> {
+        let parameters: Vec<&str> =
+            formals.iter().map(|formal| formal.name.as_str()).collect();
+        let name = format!("{class}.{name}");
+        let repr = self.signatures[&name].1;
+        self.function(&name, class, &parameters, |t| {
+            let result = t.expression(body);
+            let result = t.convert(result, repr);
+            t.return_(result);
+        })
+    }
+
+    // Link the code and fill in the tables of functions.
+    fn finish(mut self) -> Result<Jit<'p>, Unsupported> {
+        self.module.finalize_definitions().map_err(error)?;
+        let runtime: HashMap<&str, *const u8> =
+            runtime::symbols().into_iter().collect();
+        let address = |name: &str| {
+            let id = self.functions[name];
+            let declaration = self.module.declarations().get_function_decl(id);
+            match declaration.linkage {
+                Linkage::Import => {
+                    runtime[&*declaration.linkage_name(id)] as u64
+                }
+                _ => self.module.get_finalized_function(id) as u64,
+            }
+        };
+        for class in self.layout.classes.iter() {
+            let table = self.data.dispatch_tables[class.tag].1;
+            for (slot, (method, owner)) in class.methods.iter().enumerate() {
+                let function = address(&format!("{owner}.{method}"));
+                self.data.objects[table][slot] = function;
+            }
+            let objects = &mut self.data.objects[self.data.class_objects.1];
+            objects[2 * class.tag] = self.data.prototypes[class.tag];
+            objects[2 * class.tag + 1] =
+                address(&format!("{}_init", class.name));
+        }
+        let main = self.layout.class(MAIN);
+        let owner = &main.methods[main.slot("main").expect("main")].1;
+        let entry = (
+            self.data.prototypes[main.tag] as *const u8,
+            address(&format!("{MAIN}_init")) as *const u8,
+            address(&format!("{owner}.main")) as *const u8,
+        );
+        let data = &self.data;
+        let globals = Globals {
+            int_tag: data.int_tag as i64,
+            bool_tag: data.bool_tag as i64,
+            string_tag: data.string_tag as i64,
+            class_names: data.class_names as *const _,
+            int_prototype: data.prototypes[data.int_tag] as *const _,
+            string_prototype: data.prototypes[data.string_tag] as *const _,
+            bools: data.bools.map(|bool| bool as *const _),
+            failed: data.state as *mut _,
+        };
+        Ok(Jit {
+            _module: self.module,
+            _data: self.data,
+            globals,
+            locations: self.locations,
+            entry,
+            times: self.times,
+            allocations: Cell::new(0),
+        })
+    }
+}
+
+// Where a variable is stored, and how a variable represents its value.
+enum Place {
+    Variable(Variable, Repr),
+    Attribute(usize),
+}
+
+// How a value is represented in the code of a function.
+#[derive(Clone, Copy, PartialEq)]
+enum Repr {
+    Object,
+    // The value of an Int, as an i32.
+    Int,
+    // The value of a Bool, as a condition: an i8 that is 0 or 1.
+    Bool,
+}
+
+impl Repr {
+    // How values of a static type are represented, when Ints and Bools are
+    // unboxed or not.
+    fn of(type_id: &str, unbox: bool) -> Self {
+        match type_id {
+            INT if unbox => Repr::Int,
+            BOOL if unbox => Repr::Bool,
+            _ => Repr::Object,
+        }
+    }
+
+    fn type_of(self) -> types::Type {
+        match self {
+            Repr::Object => POINTER,
+            Repr::Int => types::I32,
+            Repr::Bool => types::I8,
+        }
+    }
+}
+
+// A value computed by an expression.
+#[derive(Clone, Copy)]
+struct Operand {
+    value: Value,
+    repr: Repr,
+}
+
+impl Operand {
+    fn object(value: Value) -> Self {
+        Operand {
+            value,
+            repr: Repr::Object,
+        }
+    }
+}
+
+// Translates the body of a function into Cranelift IR.
+struct Translator<'a, 'p> {
+    builder: FunctionBuilder<'a>,
+    module: &'a mut JITModule,
+    layout: &'a Layout,
+    data: &'a mut Data,
+    functions: &'a HashMap<String, FuncId>,
+    signatures: &'a HashMap<String, (Vec<Repr>, Repr)>,
+    locations: &'a mut Vec<Span<'p>>,
+    // The class and the variables of the function.
+    class: String,
+    scopes: SymbolTable<String, (Variable, Repr)>,
+    variables: usize,
+    // Whether Ints and Bools are unboxed.
+    unbox: bool,
+    // The Int each value unboxed from one was loaded from, which boxes it
+    // again wherever the value is used.
+    boxes: HashMap<Value, Value>,
+    // How the function returns its result, and the block returning from it
+    // once the run has failed.
+    result: Repr,
+    unwind: Option<Block>,
+}
+
+impl<'p> Translator<'_, 'p> {
+    fn variable(&mut self, value: Value, repr: Repr) -> (Variable, Repr) {
+        let variable = Variable::from_u32(self.variables as u32);
+        self.variables += 1;
+        self.builder.declare_var(variable, repr.type_of());
+        self.builder.def_var(variable, value);
+        (variable, repr)
+    }
+
+    // Start the function, with self and the parameters in variables, once
+    // it is counted in the call depth.
+    fn entry(&mut self, parameters: &[&str], reprs: &[Repr]) {
+        let entry = self.builder.create_block();
+        self.builder.append_block_params_for_function_params(entry);
+        self.builder.switch_to_block(entry);
+        let values = self.builder.block_params(entry).to_vec();
+        self.scopes.enter_scope();
+        let receiver = self.variable(values[0], Repr::Object);
+        self.scopes.insert(SELF.to_string(), receiver);
+        for ((name, repr), value) in
+            parameters.iter().zip(reprs).zip(&values[1..])
+        {
+            let variable = self.variable(*value, *repr);
+            self.scopes.insert(name.to_string(), variable);
+        }
+        let depth = self.count_depth(1);
+        let (exceeded, next) =
+            (self.builder.create_block(), self.builder.create_block());
+        let within = self.builder.ins().icmp_imm(
+            IntCC::UnsignedLessThanOrEqual,
+            depth,
+            CALL_DEPTH as i64,
+        );
+        self.builder.ins().brif(within, next, &[], exceeded, &[]);
+        self.builder.switch_to_block(exceeded);
+        self.call("_depth_abort", &[]);
+        self.unwind();
+        self.builder.switch_to_block(next);
+    }
+
+    // Add to the call depth, returning the new depth.
+    fn count_depth(&mut self, frames: i64) -> Value {
+        let state = self.constant(self.data.state);
+        let depth = self.load(state, WORD as i32);
+        let depth = self.builder.ins().iadd_imm(depth, frames);
+        self.builder.ins().store(
+            MemFlags::trusted(),
+            depth,
+            state,
+            WORD as i32,
+        );
+        depth
+    }
+
+    fn return_(&mut self, value: Value) {
+        self.count_depth(-1);
+        self.builder.ins().return_(&[value]);
+    }
+
+    // Continue in a new block unless the run has failed in the call just
+    // made, and otherwise return, once any error of a basic class method
+    // is located at the dispatch that called it.
+    fn check(&mut self, location: Option<Span<'p>>) {
+        let state = self.constant(self.data.state);
+        let failed = self.load(state, 0);
+        let (stop, next) =
+            (self.builder.create_block(), self.builder.create_block());
+        self.builder.ins().brif(failed, stop, &[], next, &[]);
+        self.builder.switch_to_block(stop);
+        if let Some(location) = location {
+            let location = self.location(location);
+            self.call("_fail_at", &[location]);
+        }
+        self.unwind();
+        self.builder.switch_to_block(next);
+    }
+
+    // Return from the function once the run has failed.
+    fn unwind(&mut self) {
+        let unwind = *self
+            .unwind
+            .get_or_insert_with(|| self.builder.create_block());
+        self.builder.ins().jump(unwind, &[]);
+    }
+
+    // The index of a location in the table of those errors are reported
+    // at.
+    fn location(&mut self, location: Span<'p>) -> Value {
+        self.locations.push(location);
+        let index = self.locations.len() - 1;
+        self.builder.ins().iconst(types::I32, index as i64)
+    }
+
+    fn finish(mut self) {
+        if let Some(unwind) = self.unwind {
+            self.builder.switch_to_block(unwind);
+            let zero = self.builder.ins().iconst(self.result.type_of(), 0);
+            self.return_(zero);
+        }
+        self.builder.seal_all_blocks();
+        self.builder.finalize();
+    }
+
+    fn self_object(&mut self) -> Value {
+        let (receiver, _) = *self.scopes.lookup(SELF).expect("self");
+        self.builder.use_var(receiver)
+    }
+
+    fn place(&self, name: &str) -> Place {
+        match self.scopes.lookup(name) {
+            Some((variable, repr)) => Place::Variable(*variable, *repr),
+            None => {
+                let index = self
+                    .layout
+                    .class(&self.class)
+                    .attribute_index(name)
+                    .expect("declared attribute");
+                Place::Attribute(index)
+            }
+        }
+    }
+
+    // Store a value in a variable or attribute, returning it as stored.
+    fn store(&mut self, name: &str, operand: Operand) -> Operand {
+        match self.place(name) {
+            Place::Variable(variable, repr) => {
+                let value = self.convert(operand, repr);
+                self.builder.def_var(variable, value);
+                Operand { value, repr }
+            }
+            Place::Attribute(index) => {
+                let value = self.boxed(operand);
+                let receiver = self.self_object();
+                let offset = attribute_offset(index, WORD) as i32;
+                self.builder.ins().store(
+                    MemFlags::trusted(),
+                    value,
+                    receiver,
+                    offset,
+                );
+                Operand::object(value)
+            }
+        }
+    }
+
+    fn constant(&mut self, address: u64) -> Value {
+        self.builder.ins().iconst(POINTER, address as i64)
+    }
+
+    fn load(&mut self, object: Value, offset: i32) -> Value {
+        self.builder
+            .ins()
+            .load(POINTER, MemFlags::trusted(), object, offset)
+    }
+
+    // The value of an Int or Bool.
+    fn value(&mut self, object: Value) -> Value {
+        let offset = (WORD * HEADER_WORDS) as i32;
+        self.builder
+            .ins()
+            .load(types::I32, MemFlags::trusted(), object, offset)
+    }
+
+    fn call(&mut self, function: &str, arguments: &[Value]) -> Option<Value> {
+        let id = self.functions[function];
+        let callee = self.module.declare_func_in_func(id, self.builder.func);
+        let call = self.builder.ins().call(callee, arguments);
+        self.builder.inst_results(call).first().copied()
+    }
+
+    fn call_indirect(
+        &mut self,
+        function: Value,
+        arguments: &[Value],
+        (parameters, result): &(Vec<Repr>, Repr),
+    ) -> Value {
+        let signature =
+            Compiler::method_signature(self.module, parameters, *result);
+        let signature = self.builder.import_signature(signature);
+        let call = self
+            .builder
+            .ins()
+            .call_indirect(signature, function, arguments);
+        self.builder.inst_results(call)[0]
+    }
+
+    // A new Int holding a value.
+    fn int(&mut self, value: Value) -> Value {
+        let prototype = self.data.prototypes[self.data.int_tag];
+        let prototype = self.constant(prototype);
+        let int = self.call("Object.copy", &[prototype]).unwrap();
+        let offset = (WORD * HEADER_WORDS) as i32;
+        self.builder
+            .ins()
+            .store(MemFlags::trusted(), value, int, offset);
+        int
+    }
+
+    // The Bool for a condition.
+    fn bool(&mut self, condition: Value) -> Value {
+        let [false_object, true_object] = self.data.bools;
+        let true_object = self.constant(true_object);
+        let false_object = self.constant(false_object);
+        self.builder
+            .ins()
+            .select(condition, true_object, false_object)
+    }
+
+    fn repr(&self, type_id: &str) -> Repr {
+        Repr::of(type_id, self.unbox)
+    }
+
+    // A value in another representation. Only values of static type Int or
+    // Bool are unboxed, which are never void.
+    fn convert(&mut self, operand: Operand, repr: Repr) -> Value {
+        match (operand.repr, repr) {
+            (from, to) if from == to => operand.value,
+            (Repr::Int, Repr::Object) => {
+                if let Some(object) = self.boxes.get(&operand.value) {
+                    return *object;
+                }
+                match self.constant_int(operand.value) {
+                    Some(int) => {
+                        let address = self.data.int(int);
+                        self.constant(address)
+                    }
+                    None => self.int(operand.value),
+                }
+            }
+            (Repr::Bool, Repr::Object) => self.bool(operand.value),
+            (Repr::Object, Repr::Int) => {
+                let value = self.value(operand.value);
+                self.boxes.insert(value, operand.value);
+                value
+            }
+            (Repr::Object, Repr::Bool) => {
+                let value = self.value(operand.value);
+                self.builder.ins().icmp_imm(IntCC::NotEqual, value, 0)
+            }
+            _ => unreachable!("an Int where a Bool is expected"),
+        }
+    }
+
+    // The value of an Int that is a constant, which is boxed by the
+    // compiler.
+    fn constant_int(&self, value: Value) -> Option<i32> {
+        let dfg = &self.builder.func.dfg;
+        match dfg.value_def(value) {
+            ValueDef::Result(instruction, _) => match dfg.insts[instruction] {
+                InstructionData::UnaryImm {
+                    opcode: Opcode::Iconst,
+                    imm,
+                } => Some(imm.bits() as i32),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn boxed(&mut self, operand: Operand) -> Value {
+        self.convert(operand, Repr::Object)
+    }
+
+    // A value of a Bool to branch on, which need not be 0 or 1.
+    fn condition(&mut self, operand: Operand) -> Value {
+        match operand.repr {
+            Repr::Object => self.value(operand.value),
+            _ => operand.value,
+        }
+    }
+
+    // Continue in a new block when an object is not void, and otherwise
+    // call a runtime routine reporting an error at a location, passing it
+    // further arguments after the location.
+    fn check_void(
+        &mut self,
+        object: Value,
+        routine: &str,
+        location: Span<'p>,
+        arguments: &[Value],
+    ) {
+        let (void, next) =
+            (self.builder.create_block(), self.builder.create_block());
+        self.builder.ins().brif(object, next, &[], void, &[]);
+        self.builder.switch_to_block(void);
+        self.abort_at(routine, location, arguments);
+        self.builder.switch_to_block(next);
+    }
+
+    // Call a runtime routine reporting an error at a location, which takes
+    // the index of the location, then any further arguments, and return.
+    fn abort_at(
+        &mut self,
+        routine: &str,
+        location: Span<'p>,
+        arguments: &[Value],
+    ) {
+        let mut all = vec![self.location(location)];
+        all.extend_from_slice(arguments);
+        self.call(routine, &all);
+        self.unwind();
+    }
+
+    fn default_value(&mut self, type_id: &str) -> Operand {
+        let repr = self.repr(type_id);
+        if repr != Repr::Object {
+            let value = self.builder.ins().iconst(repr.type_of(), 0);
+            return Operand { value, repr };
+        }
+        let address = match type_id {
+            INT => self.data.int(0),
+            STRING => self.data.string(""),
+            BOOL => self.data.bools[0],
+            _ => 0,
+        };
+        Operand::object(self.constant(address))
+    }
+
+    // Jump to a block with a value as its parameter, and continue there.
+    fn join(&mut self, block: Block, value: Value) -> Value {
+        self.builder.ins().jump(block, &[value]);
+        self.builder.switch_to_block(block);
+        self.builder.block_params(block)[0]
+    }
+
+    // How the values of an expression are represented where they join.
+    fn static_repr(&self, expr: &Expression<'p>) -> Repr {
+        self.repr(expr.static_type.as_deref().expect("typed tree"))
+    }
+
+    fn expression(&mut self, expr: &Expression<'p>) -> Operand {
+        let operand = self.operand(expr);
+        if self.unbox {
+            operand
+        } else {
+            Operand::object(self.boxed(operand))
+        }
+    }
+
+    fn operand(&mut self, expr: &Expression<'p>) -> Operand {
+        match &expr.data {
+            Block(expressions) => {
+                let mut value = None;
+                for expression in expressions.iter() {
+                    value = Some(self.expression(expression));
+                }
+                value.expect("a non-empty block")
+            }
+            Conditional(if_expr, then_expr, else_expr) => {
+                let condition = self.expression(if_expr);
+                let condition = self.condition(condition);
+                let repr = self.static_repr(expr);
+                let then_block = self.builder.create_block();
+                let else_block = self.builder.create_block();
+                let end = self.builder.create_block();
+                self.builder.append_block_param(end, repr.type_of());
+                self.builder.ins().brif(
+                    condition,
+                    then_block,
+                    &[],
+                    else_block,
+                    &[],
+                );
+                self.builder.switch_to_block(then_block);
+                let value = self.expression(then_expr);
+                let value = self.convert(value, repr);
+                self.builder.ins().jump(end, &[value]);
+                self.builder.switch_to_block(else_block);
+                let value = self.expression(else_expr);
+                let value = self.convert(value, repr);
+                let value = self.join(end, value);
+                Operand { value, repr }
+            }
+            Loop(cond_expr, loop_expr) => {
+                let start = self.builder.create_block();
+                let body = self.builder.create_block();
+                let end = self.builder.create_block();
+                self.builder.ins().jump(start, &[]);
+                self.builder.switch_to_block(start);
+                let condition = self.expression(cond_expr);
+                let condition = self.condition(condition);
+                self.builder.ins().brif(condition, body, &[], end, &[]);
+                self.builder.switch_to_block(body);
+                self.expression(loop_expr);
+                self.builder.ins().jump(start, &[]);
+                self.builder.switch_to_block(end);
+                Operand::object(self.constant(0))
+            }
+            Case(case_expr, branches) => self.case(expr, case_expr, branches),
+            Let(ident, type_id, opt_bind, body) => {
+                let value = match &**opt_bind {
+                    Some(bind) => self.expression(bind),
+                    None => self.default_value(type_id),
+                };
+                let repr = self.repr(type_id);
+                let value = self.convert(value, repr);
+                let variable = self.variable(value, repr);
+                self.scopes.enter_scope();
+                self.scopes.insert(ident.clone(), variable);
+                let value = self.expression(body);
+                self.scopes.exit_scope();
+                value
+            }
+            New(type_id) if type_id == SELF_TYPE => {
+                // class_objTab holds the prototype object and initialiser
+                // of each class, by tag.
+                let receiver = self.self_object();
+                let tag = self.load(receiver, 0);
+                let offset = self.builder.ins().ishl_imm(tag, 4);
+                let table = self.constant(self.data.class_objects.0);
+                let entry = self.builder.ins().iadd(table, offset);
+                let prototype = self.load(entry, 0);
+                let object = self.call("Object.copy", &[prototype]).unwrap();
+                let init = self.load(entry, WORD as i32);
+                let signature = (Vec::new(), Repr::Object);
+                let object = self.call_indirect(init, &[object], &signature);
+                self.check(None);
+                Operand::object(object)
+            }
+            New(type_id) => {
+                let tag = self.layout.tag(type_id);
+                let prototype = self.constant(self.data.prototypes[tag]);
+                let object = self.call("Object.copy", &[prototype]).unwrap();
+                let object = self.call(&format!("{type_id}_init"), &[object]);
+                self.check(None);
+                Operand::object(object.unwrap())
+            }
+            Assign(ident, value) => {
+                let value = self.expression(value);
+                self.store(ident, value)
+            }
+            UnaryOperation(UnaryOperator::Negative, operand) => {
+                let operand = self.expression(operand);
+                let value = self.convert(operand, Repr::Int);
+                let value = self.builder.ins().ineg(value);
+                Operand {
+                    value,
+                    repr: Repr::Int,
+                }
+            }
+            UnaryOperation(UnaryOperator::Not, operand) => {
+                let operand = self.expression(operand);
+                let value = self.condition(operand);
+                let value = self.builder.ins().icmp_imm(IntCC::Equal, value, 0);
+                Operand {
+                    value,
+                    repr: Repr::Bool,
+                }
+            }
+            UnaryOperation(UnaryOperator::IsVoid, operand) => {
+                let operand = self.expression(operand);
+                let value = match operand.repr {
+                    Repr::Object => self.builder.ins().icmp_imm(
+                        IntCC::Equal,
+                        operand.value,
+                        0,
+                    ),
+                    _ => self.builder.ins().iconst(types::I8, 0),
+                };
+                Operand {
+                    value,
+                    repr: Repr::Bool,
+                }
+            }
+            BinaryOperation(operator, operand1, operand2) => {
+                let operand1 = self.expression(operand1);
+                let operand2 = self.expression(operand2);
+                self.binary_operation(expr, operator, operand1, operand2)
+            }
+            MethodCall(callee, static_type, ident, params) => {
+                self.dispatch(expr, callee, static_type, ident, params)
+            }
+            Object(ident) => match self.place(ident) {
+                Place::Variable(variable, repr) => Operand {
+                    value: self.builder.use_var(variable),
+                    repr,
+                },
+                Place::Attribute(index) => {
+                    let receiver = self.self_object();
+                    let offset = attribute_offset(index, WORD) as i32;
+                    Operand::object(self.load(receiver, offset))
+                }
+            },
+            IntLiteral(integer) if self.unbox => Operand {
+                value: self.builder.ins().iconst(types::I32, *integer as i64),
+                repr: Repr::Int,
+            },
+            IntLiteral(integer) => {
+                let address = self.data.int(*integer);
+                Operand::object(self.constant(address))
+            }
+            StrLiteral(string) => {
+                let address = self.data.string(string);
+                Operand::object(self.constant(address))
+            }
+            BoolLiteral(value) if self.unbox => Operand {
+                value: self.builder.ins().iconst(types::I8, *value as i64),
+                repr: Repr::Bool,
+            },
+            BoolLiteral(value) => {
+                let address = self.data.bools[*value as usize];
+                Operand::object(self.constant(address))
+            }
+        }
+    }
+
+    // Arguments are evaluated before the receiver. The method found for
+    // the static type has the signature of any overriding it.
+    fn dispatch(
+        &mut self,
+        expr: &Expression<'p>,
+        callee: &Expression<'p>,
+        static_type: &Option<String>,
+        ident: &str,
+        params: &[Expression<'p>],
+    ) -> Operand {
+        let class = dispatch_class(static_type, callee, &self.class);
+        let class = self.layout.class(&class);
+        let slot = class.slot(ident).expect("method slot");
+        let function = format!("{}.{ident}", class.methods[slot].1);
+        let direct = class.direct_call(slot, static_type.is_some()).is_some();
+        let signature = &self.signatures[&function];
+        let mut arguments = Vec::new();
+        for (param, repr) in params.iter().zip(&signature.0) {
+            let argument = self.expression(param);
+            arguments.push(self.convert(argument, *repr));
+        }
+        let receiver = self.expression(callee);
+        let receiver = self.boxed(receiver);
+        arguments.insert(0, receiver);
+        // The abort routine also takes the method name and whether the
+        // dispatch is static.
+        let method = self.data.string(ident);
+        let method = self.constant(method);
+        let is_static = self
+            .builder
+            .ins()
+            .iconst(types::I32, static_type.is_some() as i64);
+        self.check_void(
+            receiver,
+            "_dispatch_abort",
+            expr.location,
+            &[method, is_static],
+        );
+        let value = if direct {
+            self.call(&function, &arguments).unwrap()
+        } else {
+            let table = self.load(receiver, 2 * WORD as i32);
+            let method = self.load(table, (WORD * slot) as i32);
+            self.call_indirect(method, &arguments, signature)
+        };
+        self.check(Some(expr.location));
+        Operand {
+            value,
+            repr: signature.1,
+        }
+    }
+
+    // Ints and Bools compared for equality have the same static type, so
+    // either being unboxed lets both be compared unboxed.
+    fn binary_operation(
+        &mut self,
+        expr: &Expression<'p>,
+        operator: &BinaryOperator,
+        operand1: Operand,
+        operand2: Operand,
+    ) -> Operand {
+        let bool = |value| Operand {
+            value,
+            repr: Repr::Bool,
+        };
+        if *operator == BinaryOperator::Equals {
+            let repr = match (operand1.repr, operand2.repr) {
+                (Repr::Object, Repr::Object) => {
+                    let operands = [operand1.value, operand2.value];
+                    let result = self.call("equality_test", &operands);
+                    return Operand::object(result.unwrap());
+                }
+                (Repr::Object, repr) | (repr, _) => repr,
+            };
+            let value1 = self.convert(operand1, repr);
+            let value2 = self.convert(operand2, repr);
+            return bool(self.builder.ins().icmp(IntCC::Equal, value1, value2));
+        }
+        let value1 = self.convert(operand1, Repr::Int);
+        let value2 = self.convert(operand2, Repr::Int);
+        let ins = self.builder.ins();
+        let value = match operator {
+            BinaryOperator::LessThan => {
+                return bool(ins.icmp(IntCC::SignedLessThan, value1, value2));
+            }
+            BinaryOperator::LessThanOrEquals => {
+                return bool(ins.icmp(
+                    IntCC::SignedLessThanOrEqual,
+                    value1,
+                    value2,
+                ));
+            }
+            BinaryOperator::Add => ins.iadd(value1, value2),
+            BinaryOperator::Subtract => ins.isub(value1, value2),
+            BinaryOperator::Multiply => ins.imul(value1, value2),
+            BinaryOperator::Divide => {
+                let (nonzero, zero) =
+                    (self.builder.create_block(), self.builder.create_block());
+                self.builder.ins().brif(value2, nonzero, &[], zero, &[]);
+                self.builder.switch_to_block(zero);
+                self.abort_at("_divide_abort", expr.location, &[]);
+                self.builder.switch_to_block(nonzero);
+                // Division by -1 negates, since sdiv traps on the one
+                // quotient that overflows.
+                let b = &mut self.builder;
+                let negate = b.ins().icmp_imm(IntCC::Equal, value2, -1);
+                let one = b.ins().iconst(types::I32, 1);
+                let divisor = b.ins().select(negate, one, value2);
+                let quotient = b.ins().sdiv(value1, divisor);
+                let negated = b.ins().ineg(value1);
+                b.ins().select(negate, negated, quotient)
+            }
+            BinaryOperator::Equals => unreachable!(),
+        };
+        Operand {
+            value,
+            repr: Repr::Int,
+        }
+    }
+
+    fn case(
+        &mut self,
+        expr: &Expression<'p>,
+        case_expr: &Expression<'p>,
+        branches: &[CaseBranch<'p>],
+    ) -> Operand {
+        let object = self.expression(case_expr);
+        let object = self.boxed(object);
+        self.check_void(object, "_case_abort2", expr.location, &[]);
+        let tag = self.load(object, 0);
+        let repr = self.static_repr(expr);
+        let end = self.builder.create_block();
+        self.builder.append_block_param(end, repr.type_of());
+
+        for branch in self.layout.case_branches(branches) {
+            let class = self.layout.class(&branch.type_id);
+            let (first, last) =
+                (class.tag as i64, class.last_descendant as i64);
+            let (matched, next) =
+                (self.builder.create_block(), self.builder.create_block());
+            let b = &mut self.builder;
+            let above =
+                b.ins()
+                    .icmp_imm(IntCC::SignedGreaterThanOrEqual, tag, first);
+            let below =
+                b.ins().icmp_imm(IntCC::SignedLessThanOrEqual, tag, last);
+            let within = b.ins().band(above, below);
+            b.ins().brif(within, matched, &[], next, &[]);
+            self.builder.switch_to_block(matched);
+            let branch_repr = self.repr(&branch.type_id);
+            let value = self.convert(Operand::object(object), branch_repr);
+            let variable = self.variable(value, branch_repr);
+            self.scopes.enter_scope();
+            self.scopes.insert(branch.ident.clone(), variable);
+            let value = self.expression(&branch.expression);
+            let value = self.convert(value, repr);
+            self.scopes.exit_scope();
+            self.builder.ins().jump(end, &[value]);
+            self.builder.switch_to_block(next);
+        }
+        self.abort_at("_case_abort", expr.location, &[object]);
+        self.builder.switch_to_block(end);
+        Operand {
+            value: self.builder.block_params(end)[0],
+            repr,
+        }
+    }
+}
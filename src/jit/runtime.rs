@@ -0,0 +1,484 @@
+//! The runtime of compiled code: the methods of the basic classes, object
+//! copying, equality and the routines reporting runtime errors, following
+//! the runtime of the x86-64 backend. The methods taking or returning Ints
+//! have variants taking and returning their values, for code keeping Ints
+//! unboxed.
+//!
+//! The runtime of the x86-64 backend is C, compiled with each program, and
+//! exits the process on a runtime error. Here a runtime error is recorded
+//! as the failure of the run and flagged to compiled code, which returns at
+//! once to the code that started the run. Routines failing return null or
+//! zero, which is never used. Objects are allocated from chunks that are
+//! freed when the run ends, as no object outlives it.
+
+use crate::interpreter::RuntimeErrorKind;
+use std::alloc::{alloc, dealloc, handle_alloc_error, Layout};
+use std::cell::{Cell, RefCell};
+use std::io::{BufRead, Write};
+use std::ptr;
+
+// The size of the chunks objects are allocated from, unless an object needs
+// more.
+const CHUNK_SIZE: usize = 1 << 20;
+
+#[repr(C)]
+pub(super) struct Object {
+    tag: i64,
+    /// The size of the object in eight byte words.
+    size: i64,
+    dispatch: *const usize,
+}
+
+#[repr(C)]
+struct Int {
+    header: Object,
+    value: i32,
+    padding: i32,
+}
+
+#[repr(C)]
+struct Str {
+    header: Object,
+    length: *mut Int,
+    chars: [u8; 0],
+}
+
+/// The objects of a program the runtime refers to.
+#[derive(Clone, Copy)]
+pub(super) struct Globals {
+    pub int_tag: i64,
+    pub bool_tag: i64,
+    pub string_tag: i64,
+    /// The name of each class as a String, by tag.
+    pub class_names: *const *const Object,
+    pub int_prototype: *const Object,
+    pub string_prototype: *const Object,
+    pub bools: [*const Object; 2],
+    /// The word compiled code checks after each call that may fail, which
+    /// is set once the run fails.
+    pub failed: *mut u64,
+}
+
+/// A runtime error of the running program, with the index of the location
+/// it is reported at, if any.
+pub(super) struct Failure {
+    pub kind: RuntimeErrorKind,
+    pub location: Option<usize>,
+}
+
+// The input and output of the running program.
+struct Console<'io> {
+    input: &'io mut dyn BufRead,
+    output: &'io mut dyn Write,
+}
+
+// The chunks objects are allocated from, the last of which has the first
+// `used` of its bytes allocated.
+struct Heap {
+    chunks: Vec<(*mut u8, Layout)>,
+    used: usize,
+}
+
+impl Heap {
+    fn allocate(&mut self, bytes: usize) -> *mut u8 {
+        let room = self.chunks.last().map_or(0, |(_, layout)| layout.size());
+        if self.used + bytes > room {
+            let layout = Layout::from_size_align(bytes.max(CHUNK_SIZE), 8)
+                .expect("object size");
+            // SAFETY: chunks are not empty.
+            let chunk = unsafe { alloc(layout) };
+            if chunk.is_null() {
+                handle_alloc_error(layout);
+            }
+            self.chunks.push((chunk, layout));
+            self.used = 0;
+        }
+        let (chunk, _) = self.chunks[self.chunks.len() - 1];
+        self.used += bytes;
+        // SAFETY: the chunk has room for the object.
+        unsafe { chunk.add(self.used - bytes) }
+    }
+
+    fn free(&mut self) {
+        for (chunk, layout) in self.chunks.drain(..) {
+            // SAFETY: the chunk was allocated with the layout.
+            unsafe { dealloc(chunk, layout) };
+        }
+        self.used = 0;
+    }
+}
+
+thread_local! {
+    static GLOBALS: Cell<Option<Globals>> = const { Cell::new(None) };
+    // The console of the running program, which its `Run` keeps alive.
+    static CONSOLE: Cell<*mut ()> = const { Cell::new(ptr::null_mut()) };
+    static HEAP: RefCell<Heap> = const {
+        RefCell::new(Heap {
+            chunks: Vec::new(),
+            used: 0,
+        })
+    };
+    static ALLOCATIONS: Cell<u64> = const { Cell::new(0) };
+    static FAILURE: RefCell<Option<Failure>> = const { RefCell::new(None) };
+}
+
+/// A program running on this thread, whose globals, input and output are
+/// available to the runtime until it is dropped. Dropping it frees the
+/// objects the program allocated.
+pub(super) struct Run<'io> {
+    // Boxed, so that it stays where the runtime finds it.
+    console: *mut Console<'io>,
+}
+
+impl<'io> Run<'io> {
+    /// Start a run, counting allocations from zero without any failure.
+    pub fn start(
+        globals: Globals,
+        input: &'io mut dyn BufRead,
+        output: &'io mut dyn Write,
+    ) -> Self {
+        let console = Box::into_raw(Box::new(Console { input, output }));
+        GLOBALS.set(Some(globals));
+        CONSOLE.set(console as *mut ());
+        ALLOCATIONS.set(0);
+        FAILURE.set(None);
+        Self { console }
+    }
+}
+
+impl Drop for Run<'_> {
+    fn drop(&mut self) {
+        GLOBALS.set(None);
+        CONSOLE.set(ptr::null_mut());
+        HEAP.with_borrow_mut(Heap::free);
+        // SAFETY: the console was boxed by `start`, and the runtime no
+        // longer finds it.
+        drop(unsafe { Box::from_raw(self.console) });
+    }
+}
+
+/// The runtime error the run failed with, if it has.
+pub(super) fn take_failure() -> Option<Failure> {
+    FAILURE.take()
+}
+
+/// The number of objects allocated since the run started.
+pub(super) fn allocations() -> u64 {
+    ALLOCATIONS.get()
+}
+
+fn globals() -> Globals {
+    GLOBALS.get().expect("a running program")
+}
+
+fn console<'c>() -> &'c mut Console<'c> {
+    let console = CONSOLE.get();
+    assert!(!console.is_null(), "a running program");
+    // SAFETY: the console is boxed by the `Run` of the program, which only
+    // the thread running it uses, and forgotten when the run ends.
+    unsafe { &mut *(console as *mut Console) }
+}
+
+fn output<'c>() -> &'c mut dyn Write {
+    console().output
+}
+
+fn input<'c>() -> &'c mut dyn BufRead {
+    console().input
+}
+
+/// The functions compiled code calls, with the names it calls them by.
+pub(super) fn symbols() -> Vec<(&'static str, *const u8)> {
+    vec![
+        ("Object.abort", object_abort as *const u8),
+        ("Object.type_name", object_type_name as *const u8),
+        ("Object.copy", object_copy as *const u8),
+        ("IO.out_string", io_out_string as *const u8),
+        ("IO.out_int", io_out_int as *const u8),
+        ("IO.out_int_unboxed", io_out_int_unboxed as *const u8),
+        ("IO.in_string", io_in_string as *const u8),
+        ("IO.in_int", io_in_int as *const u8),
+        ("IO.in_int_unboxed", io_in_int_unboxed as *const u8),
+        ("String.length", string_length as *const u8),
+        ("String.length_unboxed", string_length_unboxed as *const u8),
+        ("String.concat", string_concat as *const u8),
+        ("String.substr", string_substr as *const u8),
+        ("String.substr_unboxed", string_substr_unboxed as *const u8),
+        ("equality_test", equality_test as *const u8),
+        ("_dispatch_abort", dispatch_abort as *const u8),
+        ("_case_abort2", case_abort2 as *const u8),
+        ("_case_abort", case_abort as *const u8),
+        ("_divide_abort", divide_abort as *const u8),
+        ("_depth_abort", depth_abort as *const u8),
+        ("_fail_at", fail_at as *const u8),
+    ]
+}
+
+fn allocate(bytes: usize) -> *mut u8 {
+    ALLOCATIONS.set(ALLOCATIONS.get() + 1);
+    HEAP.with_borrow_mut(|heap| heap.allocate(bytes))
+}
+
+fn new_int(value: i32) -> *mut Int {
+    let int = object_copy(globals().int_prototype) as *mut Int;
+    // SAFETY: a copy of the Int prototype is an Int.
+    unsafe { (*int).value = value };
+    int
+}
+
+fn new_string(chars: &[u8]) -> *mut Str {
+    let words = 4 + (chars.len() + 8) / 8;
+    let prototype = globals().string_prototype;
+    // SAFETY: the String is allocated with room for its characters and
+    // terminator.
+    unsafe {
+        let string = allocate(8 * words) as *mut Str;
+        ptr::copy_nonoverlapping(prototype, &mut (*string).header, 1);
+        (*string).header.size = words as i64;
+        (*string).length = new_int(chars.len() as i32);
+        let first = ptr::addr_of_mut!((*string).chars) as *mut u8;
+        ptr::copy_nonoverlapping(chars.as_ptr(), first, chars.len());
+        *first.add(chars.len()) = 0;
+        string
+    }
+}
+
+// SAFETY: compiled code passes Strings, whose characters are as long as
+// their length.
+unsafe fn chars<'s>(string: *const Str) -> &'s [u8] {
+    let length = (*(*string).length).value as usize;
+    std::slice::from_raw_parts(
+        ptr::addr_of!((*string).chars) as *const u8,
+        length,
+    )
+}
+
+unsafe fn class_name<'s>(object: *const Object) -> &'s [u8] {
+    let names = globals().class_names;
+    chars(*names.add((*object).tag as usize) as *const Str)
+}
+
+// Fail the run, unless it has already failed.
+fn fail(kind: RuntimeErrorKind, location: Option<usize>) {
+    FAILURE.with_borrow_mut(|failure| {
+        failure.get_or_insert(Failure { kind, location });
+    });
+    // SAFETY: the word is allocated by the compiler, and outlives the run.
+    unsafe { *globals().failed = 1 };
+}
+
+fn output_failed(written: std::io::Result<()>) -> bool {
+    match written {
+        Ok(()) => false,
+        Err(err) => {
+            fail(RuntimeErrorKind::Io(err), None);
+            true
+        }
+    }
+}
+
+/// Copy an object, as `Object.copy` does.
+pub(super) fn copy(object: *const u8) -> *const u8 {
+    object_copy(object as *const Object) as *const u8
+}
+
+extern "C" fn object_copy(object: *const Object) -> *mut Object {
+    // SAFETY: objects are as large as the size in their header.
+    unsafe {
+        let words = (*object).size as usize;
+        let copy = allocate(8 * words) as *mut u64;
+        ptr::copy_nonoverlapping(object as *const u64, copy, words);
+        copy as *mut Object
+    }
+}
+
+extern "C" fn object_abort(object: *const Object) -> *mut Object {
+    // SAFETY: compiled code passes objects.
+    let name = unsafe { class_name(object) };
+    let name = String::from_utf8_lossy(name).into_owned();
+    fail(RuntimeErrorKind::Abort(name), None);
+    ptr::null_mut()
+}
+
+extern "C" fn object_type_name(object: *const Object) -> *const Object {
+    // SAFETY: compiled code passes objects, whose tags index the names.
+    unsafe { *globals().class_names.add((*object).tag as usize) }
+}
+
+extern "C" fn io_out_string(
+    object: *mut Object,
+    string: *const Str,
+) -> *mut Object {
+    // SAFETY: the argument is a String.
+    output_failed(output().write_all(unsafe { chars(string) }));
+    object
+}
+
+extern "C" fn io_out_int(object: *mut Object, int: *const Int) -> *mut Object {
+    // SAFETY: the argument is an Int.
+    io_out_int_unboxed(object, unsafe { (*int).value })
+}
+
+extern "C" fn io_out_int_unboxed(object: *mut Object, int: i32) -> *mut Object {
+    output_failed(write!(output(), "{int}"));
+    object
+}
+
+// Read a line without its line terminator, which may be CRLF.
+fn read_line() -> Vec<u8> {
+    let mut line = Vec::new();
+    if output_failed(output().flush()) {
+        return line;
+    }
+    if let Err(err) = input().read_until(b'\n', &mut line) {
+        fail(RuntimeErrorKind::Io(err), None);
+    }
+    if line.last() == Some(&b'\n') {
+        line.pop();
+        if line.last() == Some(&b'\r') {
+            line.pop();
+        }
+    }
+    line
+}
+
+extern "C" fn io_in_string(_: *const Object) -> *mut Str {
+    new_string(&read_line())
+}
+
+extern "C" fn io_in_int(object: *const Object) -> *mut Int {
+    new_int(io_in_int_unboxed(object))
+}
+
+// Leading whitespace is skipped, then an optional sign and digits are read,
+// wrapping on overflow. Anything else yields 0.
+extern "C" fn io_in_int_unboxed(_: *const Object) -> i32 {
+    let line = read_line();
+    let mut chars = line
+        .iter()
+        .skip_while(|c| **c == b' ' || (b'\t'..=b'\r').contains(*c))
+        .peekable();
+    let negative = chars.next_if(|c| **c == b'-' || **c == b'+') == Some(&b'-');
+    let mut value = 0i32;
+    for c in chars.take_while(|c| c.is_ascii_digit()) {
+        value = value.wrapping_mul(10).wrapping_add((c - b'0') as i32);
+    }
+    if negative {
+        value.wrapping_neg()
+    } else {
+        value
+    }
+}
+
+extern "C" fn string_length(string: *const Str) -> *mut Int {
+    // SAFETY: the receiver is a String.
+    unsafe { (*string).length }
+}
+
+extern "C" fn string_length_unboxed(string: *const Str) -> i32 {
+    // SAFETY: the receiver is a String, whose length is an Int.
+    unsafe { (*(*string).length).value }
+}
+
+extern "C" fn string_concat(string: *const Str, other: *const Str) -> *mut Str {
+    // SAFETY: both are Strings.
+    let chars = unsafe { [chars(string), chars(other)].concat() };
+    new_string(&chars)
+}
+
+extern "C" fn string_substr(
+    string: *const Str,
+    start: *const Int,
+    length: *const Int,
+) -> *mut Str {
+    // SAFETY: the arguments are Ints.
+    unsafe { string_substr_unboxed(string, (*start).value, (*length).value) }
+}
+
+extern "C" fn string_substr_unboxed(
+    string: *const Str,
+    start: i32,
+    length: i32,
+) -> *mut Str {
+    // SAFETY: the receiver is a String.
+    let chars = unsafe { chars(string) };
+    let (start, length) = (start as i64, length as i64);
+    if start < 0 || length < 0 || start + length > chars.len() as i64 {
+        fail(RuntimeErrorKind::SubstrOutOfRange, None);
+        return ptr::null_mut();
+    }
+    new_string(&chars[start as usize..(start + length) as usize])
+}
+
+/// Ints, Bools and Strings are equal when their values are.
+extern "C" fn equality_test(
+    a: *const Object,
+    b: *const Object,
+) -> *const Object {
+    let globals = globals();
+    // SAFETY: compiled code passes objects or void.
+    let equal = a == b
+        || unsafe {
+            !a.is_null()
+                && !b.is_null()
+                && (*a).tag == (*b).tag
+                && if (*a).tag == globals.int_tag
+                    || (*a).tag == globals.bool_tag
+                {
+                    (*(a as *const Int)).value == (*(b as *const Int)).value
+                } else if (*a).tag == globals.string_tag {
+                    chars(a as *const Str) == chars(b as *const Str)
+                } else {
+                    false
+                }
+        };
+    globals.bools[equal as usize]
+}
+
+// Report a dispatch on void at a location, which is static when
+// `is_static` is set.
+extern "C" fn dispatch_abort(
+    location: u32,
+    method: *const Str,
+    is_static: i32,
+) {
+    // SAFETY: the method name is a String.
+    let method = String::from_utf8_lossy(unsafe { chars(method) });
+    let method = method.into_owned();
+    let kind = match is_static {
+        0 => RuntimeErrorKind::DispatchOnVoid(method),
+        _ => RuntimeErrorKind::StaticDispatchOnVoid(method),
+    };
+    fail(kind, Some(location as usize));
+}
+
+extern "C" fn case_abort2(location: u32) {
+    fail(RuntimeErrorKind::CaseOnVoid, Some(location as usize));
+}
+
+extern "C" fn case_abort(location: u32, object: *const Object) {
+    // SAFETY: the case expression is an object.
+    let name = String::from_utf8_lossy(unsafe { class_name(object) });
+    let kind = RuntimeErrorKind::NoMatchingBranch(name.into_owned());
+    fail(kind, Some(location as usize));
+}
+
+extern "C" fn divide_abort(location: u32) {
+    fail(RuntimeErrorKind::DivisionByZero, Some(location as usize));
+}
+
+extern "C" fn depth_abort() {
+    fail(RuntimeErrorKind::StackOverflow, None);
+}
+
+// Errors raised by the methods of the basic classes are located at the
+// dispatch that called them, as the interpreter locates them.
+extern "C" fn fail_at(location: u32) {
+    FAILURE.with_borrow_mut(|failure| {
+        if let Some(failure) = failure {
+            if failure.kind.is_locatable() && failure.location.is_none() {
+                failure.location = Some(location as usize);
+            }
+        }
+    });
+}
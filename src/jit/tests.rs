@@ -0,0 +1,228 @@
+use super::*;
+use crate::lexer::lex_tokens;
+use crate::parser::parse_program;
+use crate::semant::check_program;
+
+// Compile a program and pass it to `f`, since the compiled program borrows
+// from the source.
+fn with_jit<T>(
+    source: &str,
+    representation: Representation,
+    f: impl FnOnce(&Jit) -> T,
+) -> T {
+    let (_, tokens) = lex_tokens(source, "test.cl").unwrap();
+    let (_, mut program) = parse_program(&tokens).unwrap();
+    let hierarchy = check_program(&mut program).unwrap();
+    let jit = Jit::with_representation(&program, &hierarchy, representation);
+    f(&jit.unwrap())
+}
+
+// Run a program, returning its output, or the error it fails with and its
+// exit status.
+fn run_with_input(source: &str, input: &str) -> Result<String, (String, i32)> {
+    with_jit(source, Representation::Unboxed, |jit| {
+        let mut output = Vec::new();
+        jit.run(&mut input.as_bytes(), &mut output)
+            .map(|_| String::from_utf8(output).unwrap())
+            .map_err(|err| (err.to_string(), err.exit_code()))
+    })
+}
+
+fn run(source: &str) -> String {
+    run_with_input(source, "").unwrap()
+}
+
+#[test]
+fn test_compile_times() {
+    let source = "\
+class A { a : Int <- 1; f(x : Int) : Int { x + a }; };
+class Main { main() : Object { new A.f(2) }; };
+";
+    with_jit(source, Representation::Unboxed, |jit| {
+        let names: Vec<&str> = jit
+            .compile_times()
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .collect();
+        assert_eq!(
+            names,
+            [
+                "Object_init",
+                "IO_init",
+                "Int_init",
+                "String_init",
+                "Bool_init",
+                "A_init",
+                "Main_init",
+                "A.f",
+                "Main.main",
+            ]
+        );
+        let mut table = Vec::new();
+        jit.write_times(&mut table).unwrap();
+        let table = String::from_utf8(table).unwrap();
+        assert!(table.starts_with("Method  "));
+        assert_eq!(table.lines().count(), 10);
+    });
+}
+
+#[test]
+fn test_dispatch_and_case() {
+    let output = run("\
+class A { g() : String { \"A\" }; };
+class B inherits A { g() : String { \"B\" }; };
+class Main inherits IO {
+  main() : Object {
+    let a : A <- new B in {
+      out_string(a.g());
+      out_string(a@A.g());
+      out_string(case a of x : Object => \"O\"; y : B => \"b\"; esac);
+      out_string(a.type_name());
+    }
+  };
+};
+");
+    assert_eq!(output, "BAbB");
+}
+
+#[test]
+fn test_arithmetic_and_comparison() {
+    let output = run("\
+class Main inherits IO {
+  main() : Object {
+    let min : Int <- ~2147483647 - 1 in {
+      out_int(min / ~1);
+      out_int(~7 / 2);
+      out_string(if 1 < 2 then \"<\" else \">\" fi);
+      out_string(if \"ab\" = \"a\".concat(\"b\") then \"=\" else \"/\" fi);
+      out_string(if not (3 <= 2) then \"!\" else \"?\" fi);
+      out_string(if isvoid self then \"v\" else \"o\" fi);
+    }
+  };
+};
+");
+    assert_eq!(output, "-2147483648-3<=!o");
+}
+
+#[test]
+fn test_representations() {
+    // Ints and Bools are passed to and returned from methods, one of which
+    // overrides out_int, joined with objects and matched by case.
+    let source = "\
+class Counter inherits IO {
+  count : Int;
+  out_int(i : Int) : SELF_TYPE { { count <- count + 1; self@IO.out_int(i); } };
+  even(i : Int) : Bool { if i = 0 then true else not even(i - 1) fi };
+};
+class Main {
+  io : IO <- new Counter;
+  main() : Object {
+    let i : Int <- io.in_int(), b : Bool <- 2 < i, o : Object in {
+      io.out_int(i * 2 + \"abc\".substr(1, 2).length());
+      io.out_string(if case io of c : Counter => c.even(i); esac
+        then \"e\" else \"o\" fi);
+      o <- if b then i else b fi;
+      io.out_string(case o of n : Int => \"i\"; c : Bool => \"b\"; esac);
+      io.out_string(if isvoid i then \"v\" else \"n\" fi);
+      io.out_string(if b = true then \"t\" else \"f\" fi);
+      io.out_string(if 0 = i - i then \"=\" else \"/\" fi);
+    }
+  };
+};
+";
+    let mut allocations = Vec::new();
+    for representation in [Representation::Boxed, Representation::Unboxed] {
+        with_jit(source, representation, |jit| {
+            let mut output = Vec::new();
+            jit.run(&mut "21\n".as_bytes(), &mut output).unwrap();
+            assert_eq!(String::from_utf8(output).unwrap(), "44oint=");
+            allocations.push(jit.allocations());
+        });
+    }
+    assert!(allocations[1] < allocations[0], "{allocations:?}");
+}
+
+#[test]
+fn test_runs_again() {
+    // Each run starts afresh, once the objects of the last have been freed.
+    let source = "\
+class Main inherits IO {
+  s : String <- \"a\";
+  main() : Object { out_string(s.concat(in_string())) };
+};
+";
+    with_jit(source, Representation::Unboxed, |jit| {
+        for (input, expected) in [("b\n", "ab"), ("cd\n", "acd")] {
+            let mut output = Vec::new();
+            jit.run(&mut input.as_bytes(), &mut output).unwrap();
+            assert_eq!(String::from_utf8(output).unwrap(), expected);
+            assert_eq!(jit.allocations(), 5);
+        }
+    });
+}
+
+#[test]
+fn test_runtime_errors() {
+    // Errors of the basic class methods are located at the dispatch, and
+    // the output written before an error is kept.
+    let source = "\
+class Main inherits IO {
+  main() : Object {
+    {
+      out_string(\"a\");
+      \"abc\".substr(2, 2);
+      out_string(\"b\");
+    }
+  };
+};
+";
+    with_jit(source, Representation::Unboxed, |jit| {
+        let mut output = Vec::new();
+        let err = jit.run(&mut "".as_bytes(), &mut output).unwrap_err();
+        assert_eq!(err.to_string(), "test.cl:5: Index out of range in substr.");
+        assert_eq!(err.exit_code(), 10);
+        assert_eq!(output, b"a");
+    });
+
+    // Runs are independent of any failed before.
+    let source = "\
+class Main inherits IO {
+  n : Int <- in_int();
+  main() : Object {
+    if n = 0 then abort() else out_int(n) fi
+  };
+};
+";
+    with_jit(source, Representation::Unboxed, |jit| {
+        for (input, result) in [
+            ("0\n", Err(("Abort called from class Main".to_string(), 11))),
+            ("7\n", Ok("7".to_string())),
+        ] {
+            let mut output = Vec::new();
+            let produced = jit
+                .run(&mut input.as_bytes(), &mut output)
+                .map(|_| String::from_utf8(output).unwrap())
+                .map_err(|err| (err.to_string(), err.exit_code()));
+            assert_eq!(produced, result);
+        }
+    });
+}
+
+#[test]
+fn test_stack_overflow() {
+    let source = "\
+class Main {
+  f(n : Int) : Int { f(n + 1) + 1 };
+  main() : Object { f(0) };
+};
+";
+    let produced = std::thread::scope(|scope| {
+        std::thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn_scoped(scope, || run_with_input(source, ""))
+            .unwrap()
+            .join()
+            .unwrap()
+    });
+    assert_eq!(produced, Err(("Stack overflow.".to_string(), 20)));
+}
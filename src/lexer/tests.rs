@@ -1,6 +1,6 @@
 use super::*;
 
-fn span(input: &str) -> Span {
+fn span(input: &str) -> Span<'_> {
     Span::new_extra(input, "")
 }
 
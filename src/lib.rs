@@ -1,5 +1,18 @@
+pub mod bytecode;
+pub mod codegen;
+pub mod coverage;
+pub mod debugger;
+pub mod hierarchy;
+pub mod interpreter;
+pub mod ir;
+pub mod jit;
 pub mod lexer;
 pub mod parser;
+pub mod profiler;
 pub mod ptree;
+pub mod repl;
+pub mod semant;
+pub mod simulator;
 pub mod tokens;
+pub mod tracer;
 pub mod util;
@@ -1,63 +1,763 @@
-use clap::{arg, command, crate_description, crate_version};
+use clap::{
+    arg, command, crate_description, crate_version, Command, Error, ErrorKind,
+};
+use coolc::bytecode::{compile, Machine};
+use coolc::codegen::{
+    assemble_wasm, emit_c, emit_llvm, emit_mips, emit_wasm, emit_x86_64,
+    link_x86_64, Collector,
+};
+use coolc::coverage::Coverage;
+use coolc::debugger::{exited_event, Cli, Debugger, Json, JsonOutput};
+use coolc::hierarchy::ClassHierarchy;
+use coolc::interpreter::{Interpreter, Limits, Transcript, STACK_SIZE};
+use coolc::ir::{lower, Dumps, Instruction, Pass, Pipeline};
+use coolc::jit::{Jit, Representation};
 use coolc::lexer::lex_tokens;
 use coolc::parser::parse_program;
-use std::fs::read_to_string;
+use coolc::profiler::Profiler;
+use coolc::ptree::Program;
+use coolc::repl;
+use coolc::semant::check_program;
+use coolc::simulator::{assemble, Simulator, RUNTIME};
+use coolc::tracer::{TraceFilter, Tracer};
+use std::fs::{create_dir_all, read_to_string, File};
+use std::io::{
+    self, stderr, stdin, stdout, BufRead, BufReader, BufWriter, Write,
+};
+use std::path::Path;
 use std::process::exit;
+use std::thread;
+
+// Dumps can be asked for around every pass at once.
+fn dump_names() -> impl Iterator<Item = &'static str> {
+    Pass::ALL.into_iter().map(Pass::name).chain(["all"])
+}
+
+// The options that only apply to the IR, which other targets do not use.
+const IR_OPTIONS: [&str; 5] = [
+    "optimise",
+    "pass",
+    "dump-before",
+    "dump-after",
+    "pass-stats",
+];
 
 fn main() {
     let args = command!()
         .arg_required_else_help(true)
+        .subcommand_negates_reqs(true)
+        .args_conflicts_with_subcommands(true)
         .args(&[
-            arg!(<SOURCE> "Cool source file"),
+            arg!(<SOURCE>... "Cool source files"),
             arg!(-l --lex "Run lexer only, print tokens and stop")
-                .conflicts_with("parse"),
-            arg!(-p --parse "Run lexer and parser, print parse tree and stop"),
+                .conflicts_with_all(&["parse", "semant", "dump-bytecode"]),
+            arg!(-p --parse "Run lexer and parser, print parse tree and stop")
+                .conflicts_with_all(&["semant", "dump-bytecode"]),
+            arg!(-s --semant "Run semantic analysis, print typed tree and stop")
+                .conflicts_with("dump-bytecode"),
+            arg!(--"dump-bytecode" "Compile to bytecode, print it and stop"),
+            arg!(--emit <TARGET> "Generate code for TARGET")
+                .required(false)
+                .possible_values([
+                    "mips",
+                    "x86_64-linux",
+                    "c",
+                    "llvm",
+                    "wat",
+                    "wasm",
+                    "ir",
+                ])
+                .conflicts_with_all(&[
+                    "lex",
+                    "parse",
+                    "semant",
+                    "dump-bytecode",
+                ]),
+            arg!(--target <TARGET> "Build an executable for TARGET")
+                .required(false)
+                .possible_values(["x86_64-linux"])
+                .requires("output")
+                .conflicts_with_all(&[
+                    "lex",
+                    "parse",
+                    "semant",
+                    "dump-bytecode",
+                    "emit",
+                ]),
+            arg!(-o --output <FILE> "Write generated code to FILE")
+                .required(false)
+                .conflicts_with_all(&[
+                    "lex",
+                    "parse",
+                    "semant",
+                    "dump-bytecode",
+                ]),
+            arg!(--gc "Run generated code with the generational collector"),
+            arg!(-O --optimise <LEVEL> "Optimise the IR at LEVEL")
+                .required(false)
+                .possible_values(["0", "1", "2"])
+                .requires("emit"),
+            arg!(--pass <PASS> "Run PASS over the IR, in the order given")
+                .required(false)
+                .multiple_occurrences(true)
+                .possible_values(Pass::ALL.map(Pass::name))
+                .requires("emit")
+                .conflicts_with("optimise"),
+            arg!(--"dump-before" <PASS> "Print each function before PASS")
+                .required(false)
+                .multiple_occurrences(true)
+                .possible_values(dump_names())
+                .requires("emit"),
+            arg!(--"dump-after" <PASS> "Print each function after PASS")
+                .required(false)
+                .multiple_occurrences(true)
+                .possible_values(dump_names())
+                .requires("emit"),
+            arg!(--"pass-stats" "Report what the IR passes changed on stderr")
+                .requires("emit"),
         ])
+        .subcommand(
+            Command::new("run")
+                .about("Run a program with the interpreter")
+                .args(&[
+                    arg!(<SOURCE>... "Cool source files"),
+                    arg!(--vm "Run on the bytecode virtual machine"),
+                    arg!(--debug "Run under the debugger")
+                        .conflicts_with_all(&["vm", "debug-json"]),
+                    arg!(--"debug-json" "Debug with JSON messages on stdio")
+                        .conflicts_with("vm"),
+                    arg!(--profile "Report time and allocations on stderr")
+                        .conflicts_with_all(&["vm", "debug", "debug-json"]),
+                    arg!(--"profile-collapsed" <FILE> "Write collapsed stacks")
+                        .required(false)
+                        .conflicts_with_all(&["vm", "debug", "debug-json"]),
+                    arg!(--coverage <DIR> "Write coverage to DIR")
+                        .required(false)
+                        .conflicts_with_all(&[
+                            "vm",
+                            "debug",
+                            "debug-json",
+                            "profile",
+                            "profile-collapsed",
+                        ]),
+                    arg!(--trace "Print dispatches, returns and assignments")
+                        .conflicts_with_all(&[
+                            "vm",
+                            "debug",
+                            "debug-json",
+                            "profile",
+                            "profile-collapsed",
+                            "coverage",
+                        ]),
+                    arg!(--jit "Compile methods to machine code and run them")
+                        .conflicts_with_all(&[
+                            "vm",
+                            "debug",
+                            "debug-json",
+                            "profile",
+                            "profile-collapsed",
+                            "coverage",
+                            "trace",
+                        ]),
+                    arg!(--"jit-times" "Report the time to compile each method")
+                        .requires("jit"),
+                    arg!(--"jit-boxed" "Allocate a box for every Int and Bool")
+                        .requires("jit"),
+                    arg!(--"jit-allocations" "Report the objects allocated")
+                        .requires("jit"),
+                    arg!(--"trace-class" <CLASS> "Only trace CLASS")
+                        .required(false)
+                        .multiple_occurrences(true)
+                        .requires("trace"),
+                    arg!(--"trace-method" <METHOD> "Only trace METHOD")
+                        .required(false)
+                        .multiple_occurrences(true)
+                        .requires("trace"),
+                    arg!(--record <FILE> "Write a transcript of the I/O")
+                        .required(false),
+                    arg!(--replay <FILE> "Replay a transcript, checking output")
+                        .required(false)
+                        .conflicts_with("record"),
+                    arg!(--"max-steps" <N> "Stop after N evaluation steps")
+                        .required(false)
+                        .validator(|value| value.parse::<u64>()),
+                    arg!(--"max-objects" <N> "Stop once N objects are created")
+                        .required(false)
+                        .validator(|value| value.parse::<u64>()),
+                    arg!(--"max-depth" <N> "Stop when N calls are active")
+                        .required(false)
+                        .validator(|value| value.parse::<usize>()),
+                    arg!(--"max-output" <BYTES> "Stop after writing BYTES")
+                        .required(false)
+                        .validator(|value| value.parse::<u64>()),
+                ]),
+        )
+        .subcommand(
+            Command::new("sim")
+                .about("Run MIPS assembly with the built-in simulator")
+                .arg(arg!(<FILE> "Assembly file, linked with the runtime")),
+        )
+        .subcommand(
+            Command::new("repl")
+                .about("Evaluate classes and expressions interactively"),
+        )
         .get_matches();
 
+    if args.is_present("emit") && args.value_of("emit") != Some("ir") {
+        if let Some(name) = IR_OPTIONS.iter().find(|name| args.is_present(name))
+        {
+            Error::raw(
+                ErrorKind::ArgumentConflict,
+                format!("--{name} can only be used with --emit ir\n"),
+            )
+            .exit();
+        }
+    }
+
     eprintln!("{} - {}", crate_description!(), crate_version!());
 
-    let filename = args.value_of("SOURCE").unwrap();
-    let source = match read_to_string(filename) {
-        Ok(contents) => contents,
-        Err(err) => {
-            eprintln!("Failed to read source file: {err}.");
-            exit(1);
-        }
+    if args.subcommand_name() == Some("repl") {
+        run_repl();
+    }
+    if let Some(("sim", sim_args)) = args.subcommand() {
+        run_simulator(sim_args.value_of("FILE").unwrap());
+    }
+
+    let (run, source_args) = match args.subcommand() {
+        Some(("run", run_args)) => (true, run_args),
+        _ => (false, &args),
     };
 
-    let tokens = match lex_tokens(&source, filename) {
-        Ok((_, tok)) => tok,
-        Err(err) => {
-            eprintln!("Scanner error: {err}.");
-            exit(2);
+    let filenames: Vec<&str> =
+        source_args.values_of("SOURCE").unwrap().collect();
+    let sources: Vec<String> = filenames
+        .iter()
+        .map(|filename| match read_to_string(filename) {
+            Ok(contents) => contents,
+            Err(err) => {
+                eprintln!("Failed to read source file: {err}.");
+                exit(1);
+            }
+        })
+        .collect();
+
+    let mut tokens = Vec::with_capacity(sources.len());
+    for (source, filename) in sources.iter().zip(filenames.iter()) {
+        match lex_tokens(source, filename) {
+            Ok((_, tok)) => tokens.push(tok),
+            Err(err) => {
+                eprintln!("Scanner error: {err}.");
+                exit(2);
+            }
         }
-    };
+    }
 
     if args.is_present("lex") {
         // Print tokens...
-        println!("#name \"{}\"", filename);
-        for token in tokens.iter() {
-            println!("{token}");
+        for (filename, file_tokens) in filenames.iter().zip(tokens.iter()) {
+            println!("#name \"{}\"", filename);
+            for token in file_tokens.iter() {
+                println!("{token}");
+            }
         }
         // ... and stop
         exit(0);
     }
 
-    let parse_tree = match parse_program(&tokens) {
-        Ok((_unparsed, tree)) => tree,
-        Err(err) => {
-            eprintln!("Parser error: {err}.");
-            exit(3);
+    // Classes of all source files make up a single program
+    let mut classes = Vec::new();
+    for file_tokens in tokens.iter() {
+        match parse_program(file_tokens) {
+            Ok((_unparsed, tree)) => classes.extend(tree.classes),
+            Err(err) => {
+                eprintln!("Parser error: {err}.");
+                exit(3);
+            }
         }
-    };
+    }
+    let mut parse_tree = Program::new(classes);
 
     if args.is_present("parse") {
-        // Print parse tree
+        // Print parse tree and stop
         print!("{}", parse_tree.format());
-    } else {
-        eprintln!("Program compiled successfully.");
+        exit(0);
+    }
+
+    let hierarchy = match check_program(&mut parse_tree) {
+        Ok(hierarchy) => hierarchy,
+        Err(errors) => {
+            for error in errors.iter() {
+                eprintln!("{error}");
+            }
+            eprintln!("Compilation halted due to static semantic errors.");
+            exit(4);
+        }
+    };
+
+    if args.is_present("semant") {
+        // Print parse tree annotated with types and stop
+        print!("{}", parse_tree.format());
+        exit(0);
+    }
+
+    if args.is_present("dump-bytecode") {
+        // Print bytecode and stop
+        print!("{}", compile(&parse_tree, &hierarchy).disassemble());
+        exit(0);
+    }
+
+    if let Some(target) = args.value_of("target") {
+        assert_eq!(target, "x86_64-linux");
+        let assembly =
+            emit_x86_64(&parse_tree, &hierarchy, Representation::Unboxed);
+        let output = Path::new(args.value_of("output").unwrap());
+        if let Err(err) = link_x86_64(&assembly, output) {
+            eprintln!("Failed to build executable: {err}.");
+            exit(1);
+        }
+        exit(0);
+    }
+
+    if args.is_present("emit") || args.is_present("output") {
+        let collector = if args.is_present("gc") {
+            Collector::Generational
+        } else {
+            Collector::None
+        };
+        let assembly = match args.value_of("emit") {
+            Some("x86_64-linux") => {
+                emit_x86_64(&parse_tree, &hierarchy, Representation::Unboxed)
+            }
+            Some("c") => emit_c(&parse_tree, &hierarchy),
+            Some("llvm") => emit_llvm(&parse_tree, &hierarchy),
+            Some("wat" | "wasm") => emit_wasm(&parse_tree, &hierarchy),
+            Some("ir") => {
+                let mut module = lower(&parse_tree, &hierarchy);
+                let pipeline = match args.values_of("pass") {
+                    Some(names) => Pipeline::new(
+                        names
+                            .map(|name| Pass::from_name(name).unwrap())
+                            .collect(),
+                    ),
+                    None => match args.value_of("optimise") {
+                        Some(level) => Pipeline::level(level.parse().unwrap()),
+                        None => Pipeline::level(0),
+                    },
+                };
+                let dumped = |name| match args.values_of(name) {
+                    Some(names) => names
+                        .flat_map(|name| match name {
+                            "all" => Pass::ALL.to_vec(),
+                            name => vec![Pass::from_name(name).unwrap()],
+                        })
+                        .collect(),
+                    None => Vec::new(),
+                };
+                let dumps = Dumps {
+                    before: dumped("dump-before"),
+                    after: dumped("dump-after"),
+                };
+                let statistics =
+                    pipeline.run(&mut module, &dumps, &mut |text| {
+                        eprint!("{text}")
+                    });
+                if args.is_present("pass-stats") {
+                    let dispatches = module
+                        .functions
+                        .iter()
+                        .flat_map(|function| function.blocks.iter())
+                        .flat_map(|block| block.instructions.iter())
+                        .filter(|i| matches!(i, Instruction::Dispatch { .. }))
+                        .count();
+                    eprintln!(
+                        "Devirtualised {} call sites, leaving {dispatches} \
+                         dispatches.",
+                        statistics.devirtualised
+                    );
+                    eprintln!("Inlined {} calls.", statistics.inlined);
+                }
+                module.to_string()
+            }
+            _ => emit_mips(&parse_tree, &hierarchy, collector),
+        };
+        // Modules are written in the binary format unless the text format
+        // is asked for.
+        let code = match args.value_of("emit") {
+            Some("wasm") => assemble_wasm(&assembly),
+            _ => assembly.into_bytes(),
+        };
+        let written = match args.value_of("output") {
+            Some(path) => std::fs::write(path, code),
+            None => stdout().lock().write_all(&code),
+        };
+        if let Err(err) = written {
+            eprintln!("Failed to write generated code: {err}.");
+            exit(1);
+        }
+        exit(0);
     }
 
+    if run {
+        let engine = if source_args.is_present("vm") {
+            Engine::VirtualMachine
+        } else if source_args.is_present("debug") {
+            Engine::Debugger
+        } else if source_args.is_present("debug-json") {
+            Engine::JsonDebugger
+        } else if let Some(path) = source_args.value_of("profile-collapsed") {
+            Engine::Profiler(Some(path.to_string()))
+        } else if source_args.is_present("profile") {
+            Engine::Profiler(None)
+        } else if let Some(dir) = source_args.value_of("coverage") {
+            Engine::Coverage(dir.to_string())
+        } else if source_args.is_present("trace") {
+            let values = |name| match source_args.values_of(name) {
+                Some(values) => values.map(String::from).collect(),
+                None => Vec::new(),
+            };
+            Engine::Tracer(TraceFilter {
+                classes: values("trace-class"),
+                methods: values("trace-method"),
+            })
+        } else if source_args.is_present("jit") {
+            Engine::Jit {
+                times: source_args.is_present("jit-times"),
+                representation: if source_args.is_present("jit-boxed") {
+                    Representation::Boxed
+                } else {
+                    Representation::Unboxed
+                },
+                allocations: source_args.is_present("jit-allocations"),
+            }
+        } else {
+            Engine::Interpreter
+        };
+        let limits = Limits {
+            steps: source_args.value_of_t("max-steps").ok(),
+            objects: source_args.value_of_t("max-objects").ok(),
+            call_depth: source_args.value_of_t("max-depth").ok(),
+            output_bytes: source_args.value_of_t("max-output").ok(),
+            stack_bytes: None,
+        };
+        let files: Vec<(&str, &str)> = filenames
+            .iter()
+            .copied()
+            .zip(sources.iter().map(String::as_str))
+            .collect();
+        let transcript = if let Some(path) = source_args.value_of("record") {
+            match File::create(path) {
+                Ok(file) => Some(TranscriptMode::Record(file)),
+                Err(err) => {
+                    eprintln!("Failed to create transcript: {err}.");
+                    exit(1);
+                }
+            }
+        } else if let Some(path) = source_args.value_of("replay") {
+            match read_to_string(path).map(|text| text.parse()) {
+                Ok(Ok(transcript)) => Some(TranscriptMode::Replay(transcript)),
+                Ok(Err(err)) => {
+                    eprintln!("{err}");
+                    exit(1);
+                }
+                Err(err) => {
+                    eprintln!("Failed to read transcript: {err}.");
+                    exit(1);
+                }
+            }
+        } else {
+            None
+        };
+        // Compiled code neither counts what it does nor records its I/O.
+        let engine = match engine {
+            Engine::Jit { .. }
+                if limits != Limits::default() || transcript.is_some() =>
+            {
+                eprintln!(
+                    "The JIT does not support limits or transcripts; \
+                     interpreting instead."
+                );
+                Engine::Interpreter
+            }
+            engine => engine,
+        };
+        run_program(
+            &parse_tree,
+            &hierarchy,
+            &files,
+            engine,
+            limits,
+            transcript,
+        );
+    }
+
+    eprintln!("Program compiled successfully.");
+
     exit(0);
 }
+
+#[derive(Clone, PartialEq)]
+enum Engine {
+    Interpreter,
+    VirtualMachine,
+    Debugger,
+    JsonDebugger,
+    /// Profile with the interpreter, and write collapsed stacks to a file
+    /// or a table to stderr.
+    Profiler(Option<String>),
+    /// Record coverage with the interpreter, and write it to a directory.
+    Coverage(String),
+    /// Trace some dispatches with the interpreter, on stderr.
+    Tracer(TraceFilter),
+    /// Compile with Cranelift, reporting the time to compile each method and
+    /// the number of objects allocated on stderr if asked to, or interpret
+    /// if the program cannot be compiled.
+    Jit {
+        times: bool,
+        representation: Representation,
+        allocations: bool,
+    },
+}
+
+/// Where the input and output of a program are recorded, or the transcript
+/// that is replayed.
+enum TranscriptMode {
+    Record(File),
+    Replay(Transcript),
+}
+
+fn run_program(
+    program: &Program,
+    hierarchy: &ClassHierarchy,
+    files: &[(&str, &str)],
+    engine: Engine,
+    limits: Limits,
+    transcript: Option<TranscriptMode>,
+) -> ! {
+    let mut profiler = Profiler::new();
+    let mut coverage =
+        matches!(engine, Engine::Coverage(_)).then(|| Coverage::new(program));
+    let result = thread::scope(|scope| {
+        thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn_scoped(scope, || {
+                if engine == Engine::VirtualMachine {
+                    let input = Box::new(stdin().lock());
+                    let output = Box::new(BufWriter::new(stdout().lock()));
+                    let module = compile(program, hierarchy);
+                    let mut machine = Machine::new(&module, input, output);
+                    machine.set_limits(limits);
+                    match transcript {
+                        Some(TranscriptMode::Record(file)) => {
+                            machine.record(Box::new(BufWriter::new(file)))
+                        }
+                        Some(TranscriptMode::Replay(transcript)) => {
+                            machine.replay(transcript)
+                        }
+                        None => {}
+                    }
+                    let result = machine.run();
+                    return result.map(|_| ());
+                }
+                if let Engine::Jit {
+                    times,
+                    representation,
+                    allocations,
+                } = engine
+                {
+                    match Jit::with_representation(
+                        program,
+                        hierarchy,
+                        representation,
+                    ) {
+                        Ok(jit) => {
+                            if times {
+                                let _ = jit.write_times(&mut stderr().lock());
+                            }
+                            let mut input = stdin().lock();
+                            let mut output = BufWriter::new(stdout().lock());
+                            let result = jit.run(&mut input, &mut output);
+                            if allocations {
+                                eprintln!(
+                                    "Allocated {} objects.",
+                                    jit.allocations()
+                                );
+                            }
+                            return result;
+                        }
+                        Err(err) => eprintln!("{err}; interpreting instead."),
+                    }
+                }
+
+                // The program and the debugger both read from standard
+                // input, through readers that do not buffer ahead.
+                let shared_stdin =
+                    || Box::new(BufReader::with_capacity(1, stdin()));
+                let (input, output): (Box<dyn BufRead>, Box<dyn Write>) =
+                    match engine {
+                        Engine::Debugger => {
+                            (shared_stdin(), Box::new(stdout()))
+                        }
+                        Engine::JsonDebugger => (
+                            shared_stdin(),
+                            Box::new(JsonOutput::new(Box::new(stdout()))),
+                        ),
+                        _ => (
+                            Box::new(stdin().lock()),
+                            Box::new(BufWriter::new(stdout().lock())),
+                        ),
+                    };
+                let mut interpreter =
+                    Interpreter::new(program, hierarchy, input, output);
+                interpreter.set_limits(limits.with_stack_size(STACK_SIZE));
+                match transcript {
+                    Some(TranscriptMode::Record(file)) => {
+                        interpreter.record(Box::new(BufWriter::new(file)))
+                    }
+                    Some(TranscriptMode::Replay(transcript)) => {
+                        interpreter.replay(transcript)
+                    }
+                    None => {}
+                }
+                match &engine {
+                    Engine::Interpreter
+                    | Engine::VirtualMachine
+                    | Engine::Jit { .. } => {}
+                    Engine::Debugger => {
+                        let frontend =
+                            Cli::new(shared_stdin(), Box::new(stdout()));
+                        let debugger = Debugger::new(Box::new(frontend));
+                        interpreter.set_observer(Box::new(debugger));
+                    }
+                    Engine::JsonDebugger => {
+                        let frontend =
+                            Json::new(shared_stdin(), Box::new(stdout()));
+                        let debugger = Debugger::new(Box::new(frontend));
+                        interpreter.set_observer(Box::new(debugger));
+                    }
+                    Engine::Profiler(_) => {
+                        interpreter.set_observer(Box::new(&mut profiler));
+                    }
+                    Engine::Coverage(_) => {
+                        let coverage = coverage.as_mut().expect("no coverage");
+                        interpreter.set_observer(Box::new(coverage));
+                    }
+                    Engine::Tracer(filter) => {
+                        let tracer =
+                            Tracer::new(Box::new(stderr()), filter.clone());
+                        interpreter.set_observer(Box::new(tracer));
+                    }
+                }
+                interpreter.run().map(|_| ())
+            })
+            .expect("failed to start the interpreter")
+            .join()
+    });
+
+    let exit_code = match result {
+        Ok(Ok(())) => 0,
+        Ok(Err(err)) => {
+            eprintln!("{err}");
+            err.exit_code()
+        }
+        // The panic message has already been printed
+        Err(_) => 101,
+    };
+    if engine == Engine::JsonDebugger {
+        println!("{}", exited_event(exit_code));
+    }
+    if let Engine::Profiler(collapsed) = &engine {
+        profiler.finish();
+        let written = match collapsed {
+            Some(path) => File::create(path).and_then(|file| {
+                let mut file = BufWriter::new(file);
+                profiler.write_collapsed(&mut file)?;
+                file.flush()
+            }),
+            None => profiler.write_table(&mut stderr().lock()),
+        };
+        if let Err(err) = written {
+            eprintln!("Failed to write profile: {err}.");
+            exit(1);
+        }
+    }
+    if let (Engine::Coverage(dir), Some(coverage)) = (&engine, &coverage) {
+        if let Err(err) = write_coverage(coverage, files, Path::new(dir)) {
+            eprintln!("Failed to write coverage: {err}.");
+            exit(1);
+        }
+    }
+    exit(exit_code)
+}
+
+// Write an lcov tracefile for all source files, and a listing of each of
+// them named after it.
+fn write_coverage(
+    coverage: &Coverage,
+    files: &[(&str, &str)],
+    dir: &Path,
+) -> io::Result<()> {
+    create_dir_all(dir)?;
+    let mut lcov = BufWriter::new(File::create(dir.join("coverage.info"))?);
+    coverage.write_lcov(&mut lcov)?;
+    lcov.flush()?;
+    for (filename, source) in files {
+        let name = Path::new(filename).file_name().unwrap_or_default();
+        let mut listing_name = name.to_os_string();
+        listing_name.push(".cov");
+        let mut listing = BufWriter::new(File::create(dir.join(listing_name))?);
+        coverage.write_listing(filename, source, &mut listing)?;
+        listing.flush()?;
+    }
+    Ok(())
+}
+
+fn run_simulator(path: &str) -> ! {
+    let source = match read_to_string(path) {
+        Ok(source) => source,
+        Err(err) => {
+            eprintln!("Failed to read assembly file: {err}.");
+            exit(1);
+        }
+    };
+    let image = match assemble(&[("runtime.s", RUNTIME), (path, &source)]) {
+        Ok(image) => image,
+        Err(err) => {
+            eprintln!("Assembler error: {err}.");
+            exit(2);
+        }
+    };
+    let mut simulator = Simulator::new(
+        &image,
+        Box::new(stdin().lock()),
+        Box::new(BufWriter::new(stdout().lock())),
+        Box::new(stderr()),
+    );
+    match simulator.run() {
+        Ok(status) => exit(status),
+        Err(fault) => {
+            eprintln!("{fault}");
+            exit(fault.exit_code());
+        }
+    }
+}
+
+fn run_repl() -> ! {
+    let result = thread::scope(|scope| {
+        thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn_scoped(scope, || {
+                repl::run(&mut stdin().lock(), &mut stdout().lock())
+            })
+            .expect("failed to start the interpreter")
+            .join()
+    });
+
+    match result {
+        Ok(Ok(())) => exit(0),
+        Ok(Err(err)) => {
+            eprintln!("I/O error: {err}.");
+            exit(1);
+        }
+        Err(_) => exit(101),
+    }
+}
@@ -24,6 +24,13 @@ pub fn parse_program<'a>(
     program(Tokens::new(tokens))
 }
 
+/// Parse a single expression that makes up all of the given tokens.
+pub fn parse_expression<'a>(
+    tokens: &'a [Token],
+) -> IResult<Tokens<'a>, Expression<'a>> {
+    terminated(expression, eof)(Tokens::new(tokens))
+}
+
 fn program(input: Tokens) -> IResult<Tokens, Program> {
     map(
         terminated(many1(terminated(class, semicolon_token)), eof),
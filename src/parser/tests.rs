@@ -4,7 +4,7 @@ use crate::tokens::Tokens;
 use nom::InputLength;
 use ExpressionData::*;
 
-fn tokens(input: &str) -> Vec<Token> {
+fn tokens(input: &str) -> Vec<Token<'_>> {
     let (_, tokens) = lex_tokens(input, "").unwrap();
     tokens
 }
@@ -28,6 +28,15 @@ fn test_parse_program() {
     })
 }
 
+#[test]
+fn test_parse_program_equality() {
+    let input = "class A { f(x : Int) : Int { x + 1 }; };";
+    let tokens = tokens(input);
+    let (_, first) = parse_program(&tokens).unwrap();
+    let (_, second) = parse_program(&tokens).unwrap();
+    assert_eq!(first.classes, second.classes);
+}
+
 #[test]
 fn test_bad_parse_program() {
     ["class A;", "class A {}", "class A {};;"]
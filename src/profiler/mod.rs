@@ -0,0 +1,232 @@
+//! A profiler for the tree-walking interpreter.
+//!
+//! The profiler observes dispatches and returns, and measures for every
+//! `Class.method` the number of calls, the time spent in the method itself
+//! and the time including the methods it calls. It also counts the objects
+//! created of every class. The profile is reported as a table, or as
+//! collapsed stacks for flame graph tools:
+//!
+//! ```text
+//! Main.main;A.f;IO.out_int 42
+//! ```
+//!
+//! where the count is the time spent in the last method of the stack, in
+//! microseconds.
+
+use crate::interpreter::*;
+use std::collections::HashMap;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
+
+#[cfg(test)]
+mod tests;
+
+/// What was measured of a method.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MethodProfile {
+    /// The class that provides the method, followed by the method name.
+    pub name: String,
+    pub calls: u64,
+    /// Time spent in the method, but not in the methods it called.
+    pub self_time: Duration,
+    /// Time spent in the method and in the methods it called. Recursive
+    /// calls are only counted once.
+    pub total_time: Duration,
+}
+
+// A method that has been called and has not returned yet.
+struct Activation {
+    method: usize,
+    start: Instant,
+    /// Time spent in the methods called so far.
+    callees: Duration,
+}
+
+#[derive(Default)]
+pub struct Profiler {
+    methods: Vec<MethodProfile>,
+    method_ids: HashMap<String, usize>,
+    allocations: HashMap<String, u64>,
+    activations: Vec<Activation>,
+    /// Self time by chain of methods, the outermost first.
+    stacks: HashMap<Vec<usize>, Duration>,
+}
+
+impl Profiler {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn method_id(&mut self, name: String) -> usize {
+        match self.method_ids.get(&name) {
+            Some(&id) => id,
+            None => {
+                let id = self.methods.len();
+                self.method_ids.insert(name.clone(), id);
+                self.methods.push(MethodProfile {
+                    name,
+                    ..Default::default()
+                });
+                id
+            }
+        }
+    }
+
+    fn leave(&mut self, now: Instant) {
+        let path: Vec<usize> =
+            self.activations.iter().map(|a| a.method).collect();
+        let activation = self.activations.pop().expect("no active method");
+        let elapsed = now - activation.start;
+        let self_time = elapsed.saturating_sub(activation.callees);
+        let method = &mut self.methods[activation.method];
+        method.self_time += self_time;
+        if !self
+            .activations
+            .iter()
+            .any(|a| a.method == activation.method)
+        {
+            method.total_time += elapsed;
+        }
+        if let Some(caller) = self.activations.last_mut() {
+            caller.callees += elapsed;
+        }
+        *self.stacks.entry(path).or_default() += self_time;
+    }
+
+    /// End the methods that are still active, when the program stopped
+    /// with an error.
+    pub fn finish(&mut self) {
+        let now = Instant::now();
+        while !self.activations.is_empty() {
+            self.leave(now);
+        }
+    }
+
+    /// Methods that were called, by decreasing self time.
+    pub fn methods(&self) -> Vec<&MethodProfile> {
+        let mut methods: Vec<&MethodProfile> = self.methods.iter().collect();
+        methods.sort_by(|a, b| {
+            b.self_time.cmp(&a.self_time).then(a.name.cmp(&b.name))
+        });
+        methods
+    }
+
+    /// Number of objects created by class, by decreasing number.
+    pub fn allocations(&self) -> Vec<(&str, u64)> {
+        let mut allocations: Vec<(&str, u64)> = self
+            .allocations
+            .iter()
+            .map(|(class, count)| (class.as_str(), *count))
+            .collect();
+        allocations.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        allocations
+    }
+
+    /// Write the methods and the allocations as tables.
+    pub fn write_table(&self, output: &mut dyn Write) -> io::Result<()> {
+        let methods = self.methods();
+        let width = methods
+            .iter()
+            .map(|method| method.name.len())
+            .chain([6])
+            .max()
+            .unwrap_or_default();
+        writeln!(
+            output,
+            "{:<width$}  {:>10}  {:>12}  {:>12}",
+            "Method", "Calls", "Self (ms)", "Total (ms)"
+        )?;
+        for method in methods {
+            writeln!(
+                output,
+                "{:<width$}  {:>10}  {:>12.3}  {:>12.3}",
+                method.name,
+                method.calls,
+                method.self_time.as_secs_f64() * 1000.0,
+                method.total_time.as_secs_f64() * 1000.0,
+            )?;
+        }
+
+        let allocations = self.allocations();
+        if allocations.is_empty() {
+            return Ok(());
+        }
+        let width = allocations
+            .iter()
+            .map(|(class, _)| class.len())
+            .chain([5])
+            .max()
+            .unwrap_or_default();
+        writeln!(output)?;
+        writeln!(output, "{:<width$}  {:>10}", "Class", "Objects")?;
+        for (class, count) in allocations {
+            writeln!(output, "{class:<width$}  {count:>10}")?;
+        }
+        Ok(())
+    }
+
+    /// Write the self time of every chain of calls, one per line, as
+    /// expected by flame graph tools.
+    pub fn write_collapsed(&self, output: &mut dyn Write) -> io::Result<()> {
+        let mut stacks: Vec<(String, Duration)> = self
+            .stacks
+            .iter()
+            .map(|(path, time)| {
+                let names: Vec<&str> = path
+                    .iter()
+                    .map(|&id| self.methods[id].name.as_str())
+                    .collect();
+                (names.join(";"), *time)
+            })
+            .collect();
+        stacks.sort();
+        for (stack, time) in stacks {
+            writeln!(output, "{stack} {}", time.as_micros())?;
+        }
+        Ok(())
+    }
+}
+
+impl<'p> Observer<'p> for Profiler {
+    fn call(
+        &mut self,
+        interpreter: &Interpreter<'p>,
+        call: &Call,
+    ) -> Result<(), RuntimeError<'p>> {
+        let class = &interpreter.class(call.owner).name;
+        let method = self.method_id(format!("{class}.{}", call.method));
+        self.methods[method].calls += 1;
+        self.activations.push(Activation {
+            method,
+            start: Instant::now(),
+            callees: Duration::ZERO,
+        });
+        Ok(())
+    }
+
+    fn returned(
+        &mut self,
+        _interpreter: &Interpreter<'p>,
+        _value: &Value,
+    ) -> Result<(), RuntimeError<'p>> {
+        self.leave(Instant::now());
+        Ok(())
+    }
+
+    fn allocated(
+        &mut self,
+        interpreter: &Interpreter<'p>,
+        object: &Value,
+    ) -> Result<(), RuntimeError<'p>> {
+        if let Some(class_id) = interpreter.class_of(object) {
+            let class = &interpreter.class(class_id).name;
+            match self.allocations.get_mut(class) {
+                Some(count) => *count += 1,
+                None => {
+                    self.allocations.insert(class.clone(), 1);
+                }
+            }
+        }
+        Ok(())
+    }
+}
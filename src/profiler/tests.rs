@@ -0,0 +1,123 @@
+use super::*;
+use crate::lexer::lex_tokens;
+use crate::parser::parse_program;
+use crate::semant::check_program;
+
+const SOURCE: &str = "\
+class A {
+  fact(n : Int) : Int {
+    if n = 0 then 1 else n * fact(n - 1) fi
+  };
+};
+class B inherits A {};
+class Main inherits IO {
+  a : A <- new A;
+  main() : Object {{
+    out_int(a.fact(3));
+    (new B).copy();
+    (new B).fact(1);
+  }};
+};
+";
+
+// Runs SOURCE under the profiler and returns what it measured.
+fn profile(source: &str) -> Profiler {
+    let (_, tokens) = lex_tokens(source, "test.cl").unwrap();
+    let (_, mut program) = parse_program(&tokens).unwrap();
+    let hierarchy = check_program(&mut program).unwrap();
+    let mut profiler = Profiler::new();
+    let mut interpreter = Interpreter::new(
+        &program,
+        &hierarchy,
+        Box::new("".as_bytes()),
+        Box::new(Vec::new()),
+    );
+    interpreter.set_observer(Box::new(&mut profiler));
+    let _ = interpreter.run();
+    drop(interpreter);
+    profiler.finish();
+    profiler
+}
+
+#[test]
+fn test_calls_and_allocations() {
+    let profiler = profile(SOURCE);
+    let mut calls: Vec<(&str, u64)> = profiler
+        .methods()
+        .iter()
+        .map(|method| (method.name.as_str(), method.calls))
+        .collect();
+    calls.sort();
+    assert_eq!(
+        calls,
+        [
+            ("A.fact", 6),
+            ("IO.out_int", 1),
+            ("Main.main", 1),
+            ("Object.copy", 1)
+        ]
+    );
+    assert_eq!(profiler.allocations(), [("B", 3), ("A", 1), ("Main", 1)]);
+}
+
+#[test]
+fn test_times() {
+    let profiler = profile(SOURCE);
+    let methods = profiler.methods();
+    for pair in methods.windows(2) {
+        assert!(pair[0].self_time >= pair[1].self_time);
+    }
+    for method in methods.iter() {
+        assert!(method.self_time <= method.total_time, "{}", method.name);
+    }
+    let main = methods.iter().find(|m| m.name == "Main.main").unwrap();
+    let total: Duration = methods.iter().map(|m| m.self_time).sum();
+    assert_eq!(main.total_time, total);
+}
+
+#[test]
+fn test_reports() {
+    let profiler = profile(SOURCE);
+    let mut collapsed = Vec::new();
+    profiler.write_collapsed(&mut collapsed).unwrap();
+    let stacks: Vec<String> = String::from_utf8(collapsed)
+        .unwrap()
+        .lines()
+        .map(|line| line.rsplit_once(' ').unwrap().0.to_string())
+        .collect();
+    assert_eq!(
+        stacks,
+        [
+            "Main.main",
+            "Main.main;A.fact",
+            "Main.main;A.fact;A.fact",
+            "Main.main;A.fact;A.fact;A.fact",
+            "Main.main;A.fact;A.fact;A.fact;A.fact",
+            "Main.main;IO.out_int",
+            "Main.main;Object.copy",
+        ]
+    );
+
+    let mut table = Vec::new();
+    profiler.write_table(&mut table).unwrap();
+    let table = String::from_utf8(table).unwrap();
+    let lines: Vec<&str> = table.lines().collect();
+    assert_eq!(lines.len(), 10);
+    assert!(lines[0].starts_with("Method"));
+    assert!(lines[6].starts_with("Class"));
+    assert_eq!(lines[7], "B               3");
+}
+
+#[test]
+fn test_unfinished_methods() {
+    let profiler = profile(
+        "class Main { main() : Object { f() }; f() : Int { 1 / 0 }; };",
+    );
+    let names: Vec<&str> = profiler
+        .methods()
+        .iter()
+        .map(|method| method.name.as_str())
+        .collect();
+    assert_eq!(names.len(), 2);
+    assert!(profiler.methods().iter().all(|m| m.calls == 1));
+}
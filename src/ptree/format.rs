@@ -195,19 +195,36 @@ impl Display for ExpressionFormatter<'_> {
         let empty = "";
         let indent = self.indent;
         let line_num = self.expression.location.location_line();
-        let expression = self.expression.data.format(indent);
+        let expression = ExpressionDataFormatter::with_type(
+            &self.expression.data,
+            self.expression.type_name(),
+            indent,
+        );
         write!(f, "{empty:indent$}#{line_num}\n{expression}")
     }
 }
 
 pub struct ExpressionDataFormatter<'a> {
     expression: &'a ExpressionData<'a>,
+    static_type: &'a str,
     indent: usize,
 }
 
 impl<'a> ExpressionDataFormatter<'a> {
     pub fn new(expression: &'a ExpressionData, indent: usize) -> Self {
-        Self { expression, indent }
+        Self::with_type(expression, "_no_type", indent)
+    }
+
+    pub fn with_type(
+        expression: &'a ExpressionData,
+        static_type: &'a str,
+        indent: usize,
+    ) -> Self {
+        Self {
+            expression,
+            static_type,
+            indent,
+        }
     }
 }
 
@@ -216,6 +233,7 @@ impl Display for ExpressionDataFormatter<'_> {
         let empty = "";
         let indent = self.indent;
         let next_indent = self.indent + INDENTATION;
+        let static_type = self.static_type;
 
         match self.expression {
             Block(expressions) => {
@@ -223,7 +241,7 @@ impl Display for ExpressionDataFormatter<'_> {
                 for expr in expressions.iter() {
                     write!(f, "{}", expr.format(next_indent))?;
                 }
-                writeln!(f, "{empty:indent$}: _no_type")
+                writeln!(f, "{empty:indent$}: {static_type}")
             }
             Conditional(if_expr, then_expr, else_expr) => {
                 let if_expression = if_expr.format(next_indent);
@@ -236,7 +254,7 @@ impl Display for ExpressionDataFormatter<'_> {
                     {if_expression}\
                     {then_expression}\
                     {else_expression}\
-                    {empty:indent$}: _no_type"
+                    {empty:indent$}: {static_type}"
                 )
             }
             Loop(cond_expr, loop_expr) => {
@@ -248,7 +266,7 @@ impl Display for ExpressionDataFormatter<'_> {
                     {empty:indent$}_loop\n\
                     {cond_expression}\
                     {loop_expression}\
-                    {empty:indent$}: _no_type"
+                    {empty:indent$}: {static_type}"
                 )
             }
             Case(case_expr, branches) => {
@@ -262,7 +280,7 @@ impl Display for ExpressionDataFormatter<'_> {
                 for branch in branches.iter() {
                     write!(f, "{}", branch.format(next_indent))?;
                 }
-                writeln!(f, "{empty:indent$}: _no_type")
+                writeln!(f, "{empty:indent$}: {static_type}")
             }
             Let(ident, type_id, opt_bind, expr) => {
                 writeln!(
@@ -281,7 +299,7 @@ impl Display for ExpressionDataFormatter<'_> {
                     f,
                     "\
                     {}\
-                    {empty:indent$}: _no_type",
+                    {empty:indent$}: {static_type}",
                     expr.format(next_indent),
                 )
             }
@@ -293,7 +311,7 @@ impl Display for ExpressionDataFormatter<'_> {
                     {empty:indent$}_assign\n\
                     {empty:next_indent$}{ident}\n\
                     {expression}\
-                    {empty:indent$}: _no_type"
+                    {empty:indent$}: {static_type}"
                 )
             }
             New(type_id) => {
@@ -302,7 +320,7 @@ impl Display for ExpressionDataFormatter<'_> {
                     "\
                     {empty:indent$}_new\n\
                     {empty:next_indent$}{type_id}\n\
-                    {empty:indent$}: _no_type"
+                    {empty:indent$}: {static_type}"
                 )
             }
             UnaryOperation(operator, operand) => {
@@ -317,7 +335,7 @@ impl Display for ExpressionDataFormatter<'_> {
                     "\
                     {empty:indent$}{oper}\n\
                     {op}\
-                    {empty:indent$}: _no_type"
+                    {empty:indent$}: {static_type}"
                 )
             }
             BinaryOperation(operator, operand1, operand2) => {
@@ -338,7 +356,7 @@ impl Display for ExpressionDataFormatter<'_> {
                     {empty:indent$}{oper}\n\
                     {op1}\
                     {op2}\
-                    {empty:indent$}: _no_type"
+                    {empty:indent$}: {static_type}"
                 )
             }
             Object(ident) => {
@@ -347,12 +365,12 @@ impl Display for ExpressionDataFormatter<'_> {
                     "\
                     {empty:indent$}_object\n\
                     {empty:next_indent$}{ident}\n\
-                    {empty:indent$}: _no_type"
+                    {empty:indent$}: {static_type}"
                 )
             }
-            MethodCall(object, static_type, ident, params) => {
+            MethodCall(object, dispatch_type, ident, params) => {
                 let expression = object.format(next_indent);
-                if let Some(type_id) = static_type {
+                if let Some(type_id) = dispatch_type {
                     writeln!(
                         f,
                         "\
@@ -379,7 +397,7 @@ impl Display for ExpressionDataFormatter<'_> {
                     f,
                     "\
                     {empty:next_indent$})\n\
-                    {empty:indent$}: _no_type"
+                    {empty:indent$}: {static_type}"
                 )
             }
             IntLiteral(integer) => {
@@ -388,7 +406,7 @@ impl Display for ExpressionDataFormatter<'_> {
                     "\
                     {empty:indent$}_int\n\
                     {empty:next_indent$}{integer}\n\
-                    {empty:indent$}: _no_type"
+                    {empty:indent$}: {static_type}"
                 )
             }
             StrLiteral(string) => {
@@ -398,7 +416,7 @@ impl Display for ExpressionDataFormatter<'_> {
                     "\
                     {empty:indent$}_string\n\
                     {empty:next_indent$}\"{escaped_str}\"\n\
-                    {empty:indent$}: _no_type"
+                    {empty:indent$}: {static_type}"
                 )
             }
             BoolLiteral(boolean) => {
@@ -408,7 +426,7 @@ impl Display for ExpressionDataFormatter<'_> {
                     "\
                     {empty:indent$}_bool\n\
                     {empty:next_indent$}{int_value}\n\
-                    {empty:indent$}: _no_type"
+                    {empty:indent$}: {static_type}"
                 )
             }
         }
@@ -1,10 +1,12 @@
 //! Parse tree structures, which are directly generated by the parser
-//! from Cool source code.
+//! from Cool source code. Expressions are later annotated with their static
+//! types by semantic analysis.
 
 mod format;
 
 use self::format::*;
 use crate::tokens::{Ident, Span, TokenKind, TypeId};
+use std::sync::atomic::{AtomicUsize, Ordering};
 use ExpressionData::*;
 
 pub struct Program<'a> {
@@ -62,7 +64,7 @@ impl<'a> Feature<'a> {
         Self { data, location }
     }
 
-    pub fn format(&self, indent: usize) -> FeatureFormatter {
+    pub fn format(&self, indent: usize) -> FeatureFormatter<'_> {
         FeatureFormatter::new(self, indent)
     }
 }
@@ -74,7 +76,7 @@ pub enum FeatureData<'a> {
 }
 
 impl FeatureData<'_> {
-    pub fn format(&self, indent: usize) -> FeatureDataFormatter {
+    pub fn format(&self, indent: usize) -> FeatureDataFormatter<'_> {
         FeatureDataFormatter::new(self, indent)
     }
 }
@@ -95,25 +97,85 @@ impl<'a> Formal<'a> {
         }
     }
 
-    pub fn format(&self, indent: usize) -> FormalFormatter {
+    pub fn format(&self, indent: usize) -> FormalFormatter<'_> {
         FormalFormatter::new(self, indent)
     }
 }
 
-#[derive(Debug, PartialEq)]
+/// Identifies an expression node. Identifiers are assigned when the parser
+/// creates the node and are never reused within a process, so they stay
+/// valid when the tree is moved or cloned and are distinct across programs
+/// parsed by the same REPL session.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct NodeId(usize);
+
+impl NodeId {
+    fn next() -> Self {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        Self(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+#[derive(Debug)]
 pub struct Expression<'a> {
+    pub id: NodeId,
     pub data: ExpressionData<'a>,
     pub location: Span<'a>,
+    pub static_type: Option<TypeId>,
 }
 
 impl<'a> Expression<'a> {
     pub fn new(data: ExpressionData<'a>, location: Span<'a>) -> Self {
-        Self { data, location }
+        Self {
+            id: NodeId::next(),
+            data,
+            location,
+            static_type: None,
+        }
     }
 
-    pub fn format(&self, indent: usize) -> ExpressionFormatter {
+    pub fn format(&self, indent: usize) -> ExpressionFormatter<'_> {
         ExpressionFormatter::new(self, indent)
     }
+
+    /// The static type of the expression, or `_no_type` if it has not been
+    /// type checked.
+    pub fn type_name(&self) -> &str {
+        self.static_type.as_deref().unwrap_or("_no_type")
+    }
+
+    /// The expressions directly contained in this one, in source order.
+    pub fn subexpressions(&self) -> Vec<&Expression<'a>> {
+        match &self.data {
+            Block(expressions) => expressions.iter().collect(),
+            Conditional(if_expr, then_expr, else_expr) => {
+                vec![if_expr, then_expr, else_expr]
+            }
+            Loop(cond_expr, loop_expr) => vec![cond_expr, loop_expr],
+            Case(case_expr, branches) => [&**case_expr]
+                .into_iter()
+                .chain(branches.iter().map(|branch| &branch.expression))
+                .collect(),
+            Let(_, _, bind_expr, expr) => {
+                (**bind_expr).iter().chain([&**expr]).collect()
+            }
+            Assign(_, expr) | UnaryOperation(_, expr) => vec![expr],
+            BinaryOperation(_, operand1, operand2) => vec![operand1, operand2],
+            MethodCall(callee, _, _, params) => {
+                [&**callee].into_iter().chain(params.iter()).collect()
+            }
+            New(_) | Object(_) | IntLiteral(_) | StrLiteral(_)
+            | BoolLiteral(_) => Vec::new(),
+        }
+    }
+}
+
+// Expressions are equal when they are parsed from the same source, whatever
+// their identifiers and static types.
+impl PartialEq for Expression<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.data == other.data && self.location == other.location
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -242,7 +304,7 @@ impl<'a> ExpressionData<'a> {
         MethodCall(Box::new(expr), static_type, ident, params)
     }
 
-    pub fn format(&self, indent: usize) -> ExpressionDataFormatter {
+    pub fn format(&self, indent: usize) -> ExpressionDataFormatter<'_> {
         ExpressionDataFormatter::new(self, indent)
     }
 }
@@ -270,7 +332,7 @@ impl<'a> CaseBranch<'a> {
         }
     }
 
-    pub fn format(&self, indent: usize) -> CaseBranchFormatter {
+    pub fn format(&self, indent: usize) -> CaseBranchFormatter<'_> {
         CaseBranchFormatter::new(self, indent)
     }
 }
@@ -0,0 +1,482 @@
+//! An interactive session that evaluates class definitions and expressions as
+//! they are entered.
+//!
+//! Classes defined in a session remain visible to everything entered after
+//! them. Each expression is checked and run as the body of a method of a
+//! class that inherits from IO, so it can call out_string() and friends on
+//! self directly.
+
+use crate::hierarchy::{ClassHierarchy, IO};
+use crate::interpreter::{Interpreter, Limits, STACK_SIZE};
+use crate::lexer::lex_tokens;
+use crate::parser::{parse_expression, parse_program};
+use crate::ptree::*;
+use crate::semant::check_classes;
+use crate::tokens::{Token, TokenKind, Tokens};
+use nom::Err;
+use std::io::{self, BufRead, Write};
+
+#[cfg(test)]
+mod tests;
+
+/// The class holding the expression being evaluated. Its name is not a valid
+/// type identifier, so it cannot clash with the classes of the session.
+const EVAL_CLASS: &str = "_Repl";
+const EVAL_METHOD: &str = "eval";
+const FILENAME: &str = "<repl>";
+
+const PROMPT: &str = "cool> ";
+const CONTINUATION_PROMPT: &str = "  ... ";
+
+const HELP: &str = "\
+Enter class definitions, each ending with ';', or expressions to evaluate.
+Input continues over several lines while it is incomplete; an empty line
+ends it.
+Commands:
+  :type <expression>  Print the static type of an expression
+  :tokens <input>     Print the tokens of some input
+  :ptree <input>      Print the parse tree of some input
+  :help               Print this message
+  :quit               End the session
+";
+
+type Errors = Vec<String>;
+
+/// The classes defined so far in a session.
+#[derive(Default)]
+pub struct Session {
+    class_sources: Vec<String>,
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Handle a complete input, which is a command, one or more class
+    /// definitions or an expression. Results and errors are written to
+    /// `output`, and expressions read from `input`. Returns false once the
+    /// session should end.
+    pub fn execute(
+        &mut self,
+        source: &str,
+        input: &mut dyn BufRead,
+        output: &mut dyn Write,
+    ) -> io::Result<bool> {
+        let source = source.trim();
+        let result = if let Some(command) = source.strip_prefix(':') {
+            let (name, argument) = command
+                .split_once(char::is_whitespace)
+                .unwrap_or((command, ""));
+            match name {
+                "type" => self.type_of(argument.trim(), output),
+                "tokens" => print_tokens(argument.trim(), output),
+                "ptree" => print_parse_tree(argument.trim(), output),
+                "help" => write!(output, "{HELP}").map(|_| Ok(())),
+                "quit" => return Ok(false),
+                _ => Ok(Err(vec![format!(
+                    "Unknown command :{name}. \
+                     Enter :help for a list of commands."
+                )])),
+            }
+        } else if source.is_empty() {
+            Ok(Ok(()))
+        } else {
+            match lex(source) {
+                Ok(tokens) if starts_class(&tokens) => {
+                    self.define(source, &tokens, output)
+                }
+                Ok(_) => self.evaluate(source, input, output),
+                Err(errors) => Ok(Err(errors)),
+            }
+        };
+
+        if let Err(errors) = result? {
+            for error in errors.iter() {
+                writeln!(output, "{error}")?;
+            }
+        }
+        Ok(true)
+    }
+
+    // Classes are only added to the session once they check together with
+    // the classes already defined.
+    fn define(
+        &mut self,
+        source: &str,
+        tokens: &[Token],
+        output: &mut dyn Write,
+    ) -> io::Result<Result<(), Errors>> {
+        let new_classes = match parse_classes(tokens) {
+            Ok(classes) => classes,
+            Err(errors) => return Ok(Err(errors)),
+        };
+        let new_count = new_classes.len();
+        let checked = self.with_classes(new_classes, |program, _| {
+            let count = program.classes.len();
+            program.classes[count - new_count..]
+                .iter()
+                .map(|class| class.name.clone())
+                .collect::<Vec<_>>()
+        });
+        let names = match checked {
+            Ok(names) => names,
+            Err(errors) => return Ok(Err(errors)),
+        };
+        self.class_sources.push(source.to_string());
+        for name in names {
+            writeln!(output, "Defined class {name}.")?;
+        }
+        Ok(Ok(()))
+    }
+
+    fn evaluate(
+        &self,
+        source: &str,
+        input: &mut dyn BufRead,
+        output: &mut dyn Write,
+    ) -> io::Result<Result<(), Errors>> {
+        let result = self.with_expression(source, |program, hierarchy| {
+            let mut interpreter = Interpreter::new(
+                program,
+                hierarchy,
+                Box::new(&mut *input),
+                Box::new(&mut *output),
+            );
+            interpreter
+                .set_limits(Limits::default().with_stack_size(STACK_SIZE));
+            interpreter
+                .call(EVAL_CLASS, EVAL_METHOD)
+                .map(|value| interpreter.describe(&value))
+                .map_err(|err| err.to_string())
+                .map(|value| {
+                    (value, eval_expression(program).type_name().to_string())
+                })
+        });
+        match result {
+            Ok(Ok((value, type_id))) => {
+                writeln!(output, "{value} : {type_id}")?;
+                Ok(Ok(()))
+            }
+            Ok(Err(error)) => Ok(Err(vec![error])),
+            Err(errors) => Ok(Err(errors)),
+        }
+    }
+
+    fn type_of(
+        &self,
+        source: &str,
+        output: &mut dyn Write,
+    ) -> io::Result<Result<(), Errors>> {
+        match self.with_expression(source, |program, _| {
+            eval_expression(program).type_name().to_string()
+        }) {
+            Ok(type_id) => writeln!(output, "{type_id}").map(Ok),
+            Err(errors) => Ok(Err(errors)),
+        }
+    }
+
+    // The expression becomes the body of a method of EVAL_CLASS, which is
+    // checked along with the classes of the session.
+    fn with_expression<T>(
+        &self,
+        source: &str,
+        f: impl FnOnce(&Program, &ClassHierarchy) -> T,
+    ) -> Result<T, Errors> {
+        let tokens = lex(source)?;
+        let expression = parse_single_expression(&tokens)?;
+        let location = expression.location;
+        let method = FeatureData::Method(
+            EVAL_METHOD.to_string(),
+            "Object".to_string(),
+            Vec::new(),
+            expression,
+        );
+        let class = Class::new(
+            EVAL_CLASS.to_string(),
+            Some(IO.to_string()),
+            vec![Feature::new(method, location)],
+            location,
+        );
+        self.with_classes(vec![class], f)
+    }
+
+    // Parse and check the classes of the session along with some others.
+    fn with_classes<'s, T>(
+        &'s self,
+        extra_classes: Vec<Class<'s>>,
+        f: impl FnOnce(&Program, &ClassHierarchy) -> T,
+    ) -> Result<T, Errors> {
+        let tokens = self
+            .class_sources
+            .iter()
+            .map(|source| lex(source))
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut classes = Vec::new();
+        for class_tokens in tokens.iter() {
+            classes.extend(parse_classes(class_tokens)?);
+        }
+        classes.extend(extra_classes);
+
+        let mut program = Program::new(classes);
+        let hierarchy = check_classes(&mut program).map_err(|errors| {
+            errors
+                .iter()
+                .map(|error| error.to_string())
+                .collect::<Vec<_>>()
+        })?;
+        Ok(f(&program, &hierarchy))
+    }
+}
+
+/// Whether some input needs more lines: it does not parse, and it ends inside
+/// a string, a comment or a nested construct, or with a token that must be
+/// followed by more. Commands always fit on a single line.
+pub fn is_incomplete(source: &str) -> bool {
+    if source.trim_start().starts_with(':') {
+        return false;
+    }
+    if ends_inside_literal(source) {
+        return true;
+    }
+    let tokens = match lex_tokens(source, FILENAME) {
+        Ok((_, tokens)) => tokens,
+        Err(_) => return false,
+    };
+    let last = match tokens.last() {
+        Some(last) => last,
+        None => return false,
+    };
+    if parse_classes(&tokens).is_ok()
+        || parse_single_expression(&tokens).is_ok()
+    {
+        return false;
+    }
+
+    let depth: i32 = tokens.iter().map(|token| nesting(&token.kind)).sum();
+    depth > 0
+        || expects_more(&last.kind)
+        || (starts_class(&tokens) && last.kind != TokenKind::SemiColon)
+}
+
+/// Run a session until its input ends or `:quit` is entered. Expressions read
+/// from the same input as the session, and are evaluated on the current
+/// thread, which should have a stack of `STACK_SIZE` bytes.
+pub fn run(input: &mut dyn BufRead, output: &mut dyn Write) -> io::Result<()> {
+    let mut session = Session::new();
+    let mut buffer = String::new();
+    loop {
+        let prompt = if buffer.is_empty() {
+            PROMPT
+        } else {
+            CONTINUATION_PROMPT
+        };
+        write!(output, "{prompt}")?;
+        output.flush()?;
+
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            writeln!(output)?;
+            if !buffer.is_empty() {
+                session.execute(&buffer, input, output)?;
+            }
+            return output.flush();
+        }
+        let ends_input = line.trim().is_empty();
+        buffer.push_str(&line);
+        if !ends_input && is_incomplete(&buffer) {
+            continue;
+        }
+
+        let source = std::mem::take(&mut buffer);
+        if !session.execute(&source, input, output)? {
+            return output.flush();
+        }
+    }
+}
+
+fn lex(source: &str) -> Result<Vec<Token<'_>>, Errors> {
+    match lex_tokens(source, FILENAME) {
+        Ok((_, tokens)) => Ok(tokens),
+        Err(Err::Error(err) | Err::Failure(err)) => {
+            let line_num = err.input.location_line();
+            Err(vec![format!("{FILENAME}:{line_num}: Scanner error.")])
+        }
+        Err(Err::Incomplete(_)) => {
+            Err(vec![format!("{FILENAME}: Scanner error.")])
+        }
+    }
+}
+
+fn parse_classes<'a>(tokens: &'a [Token]) -> Result<Vec<Class<'a>>, Errors> {
+    parse_program(tokens)
+        .map(|(_, program)| program.classes)
+        .map_err(syntax_error)
+}
+
+// A single semicolon may follow the expression.
+fn parse_single_expression<'a>(
+    tokens: &'a [Token],
+) -> Result<Expression<'a>, Errors> {
+    let tokens = match tokens.split_last() {
+        Some((last, rest)) if last.kind == TokenKind::SemiColon => rest,
+        _ => tokens,
+    };
+    parse_expression(tokens)
+        .map(|(_, expression)| expression)
+        .map_err(syntax_error)
+}
+
+fn syntax_error(err: Err<nom::error::Error<Tokens>>) -> Errors {
+    let token = match err {
+        Err::Error(err) | Err::Failure(err) => err.input.array.first(),
+        Err::Incomplete(_) => None,
+    };
+    let message = match token {
+        Some(token) => format!(
+            "{FILENAME}:{}: Syntax error at or near {}.",
+            token.location.location_line(),
+            token.kind
+        ),
+        None => format!("{FILENAME}: Syntax error at end of input."),
+    };
+    vec![message]
+}
+
+fn print_tokens(
+    source: &str,
+    output: &mut dyn Write,
+) -> io::Result<Result<(), Errors>> {
+    match lex(source) {
+        Ok(tokens) => {
+            for token in tokens.iter() {
+                writeln!(output, "{token}")?;
+            }
+            Ok(Ok(()))
+        }
+        Err(errors) => Ok(Err(errors)),
+    }
+}
+
+// Class definitions are printed as a program, anything else as an expression.
+fn print_parse_tree(
+    source: &str,
+    output: &mut dyn Write,
+) -> io::Result<Result<(), Errors>> {
+    let tokens = match lex(source) {
+        Ok(tokens) => tokens,
+        Err(errors) => return Ok(Err(errors)),
+    };
+    if starts_class(&tokens) {
+        match parse_classes(&tokens) {
+            Ok(classes) => {
+                write!(output, "{}", Program::new(classes).format())?;
+                Ok(Ok(()))
+            }
+            Err(errors) => Ok(Err(errors)),
+        }
+    } else {
+        match parse_single_expression(&tokens) {
+            Ok(expression) => {
+                write!(output, "{}", expression.format(0))?;
+                Ok(Ok(()))
+            }
+            Err(errors) => Ok(Err(errors)),
+        }
+    }
+}
+
+fn starts_class(tokens: &[Token]) -> bool {
+    tokens
+        .first()
+        .is_some_and(|token| token.kind == TokenKind::Class)
+}
+
+fn eval_expression<'p>(program: &'p Program) -> &'p Expression<'p> {
+    let class = program.classes.last().expect("no class to evaluate");
+    match &class.features[0].data {
+        FeatureData::Method(_, _, _, body) => body,
+        FeatureData::Attribute(..) => unreachable!("not an eval method"),
+    }
+}
+
+// The lexer reads an unterminated comment as symbols, so strings and comments
+// are followed here instead.
+fn ends_inside_literal(source: &str) -> bool {
+    let mut chars = source.chars().peekable();
+    let mut comment_depth = 0;
+    while let Some(c) = chars.next() {
+        match c {
+            '(' if chars.peek() == Some(&'*') => {
+                chars.next();
+                comment_depth += 1;
+            }
+            '*' if comment_depth > 0 && chars.peek() == Some(&')') => {
+                chars.next();
+                comment_depth -= 1;
+            }
+            _ if comment_depth > 0 => {}
+            '-' if chars.peek() == Some(&'-') => {
+                chars.by_ref().find(|&c| c == '\n');
+            }
+            '"' => loop {
+                match chars.next() {
+                    None => return true,
+                    Some('\\') => {
+                        chars.next();
+                    }
+                    Some('"') => break,
+                    Some(_) => {}
+                }
+            },
+            _ => {}
+        }
+    }
+    comment_depth > 0
+}
+
+fn nesting(kind: &TokenKind) -> i32 {
+    use TokenKind::*;
+    match kind {
+        OpenParens | OpenBraces | If | Case | While | Let => 1,
+        CloseParens | CloseBraces | Fi | Esac | Pool | In => -1,
+        _ => 0,
+    }
+}
+
+fn expects_more(kind: &TokenKind) -> bool {
+    use TokenKind::*;
+    matches!(
+        kind,
+        Class
+            | Inherits
+            | If
+            | Then
+            | Else
+            | Let
+            | In
+            | While
+            | Loop
+            | Case
+            | Of
+            | New
+            | IsVoid
+            | Not
+            | At
+            | Assign
+            | DoubleArrow
+            | OpenBraces
+            | OpenParens
+            | Dot
+            | Comma
+            | Colon
+            | Equals
+            | Add
+            | Subtract
+            | Multiply
+            | Divide
+            | Negative
+            | LessThanOrEquals
+            | LessThan
+    )
+}
@@ -0,0 +1,123 @@
+use super::*;
+
+// Runs a whole session and returns its output without the prompts.
+fn session(input: &str) -> String {
+    let mut output = Vec::new();
+    run(&mut input.as_bytes(), &mut output).unwrap();
+    String::from_utf8(output)
+        .unwrap()
+        .replace(PROMPT, "")
+        .replace(CONTINUATION_PROMPT, "")
+}
+
+#[test]
+fn test_expressions() {
+    assert_eq!(
+        session("1 + 2\n\"a\".concat(\"b\");\nnot true\nnew Object\n"),
+        "3 : Int\n\"ab\" : String\nfalse : Bool\n<Object object> : Object\n\n"
+    );
+}
+
+#[test]
+fn test_classes_persist() {
+    assert_eq!(
+        session(
+            "class A { f() : Int { 1 }; };\n\
+             class B inherits A { g() : Int { f() + 1 }; };\n\
+             (new B).g()\n\
+             class A {};\n\
+             (new A).f()\n"
+        ),
+        "Defined class A.\nDefined class B.\n2 : Int\n\
+         <repl>:1: Class A was previously defined.\n1 : Int\n\n"
+    );
+}
+
+#[test]
+fn test_multi_line_input() {
+    assert_eq!(
+        session(
+            "class A {\n  f() : Int {\n    2\n  };\n};\n\
+             let x : Int <- (new A).f() in\n  x * (\n  3)\n\
+             \"a\n b\"\n\
+             (* not\n done *) 4\n\
+             1 +\n\n\
+             5\n"
+        ),
+        "Defined class A.\n6 : Int\n\"a\\n b\" : String\n4 : Int\n\
+         <repl>:1: Syntax error at or near '+'.\n5 : Int\n\n"
+    );
+}
+
+#[test]
+fn test_incompleteness() {
+    for source in [
+        "class A {",
+        "class A {}",
+        "if true then 1",
+        "{ 1;",
+        "let x : Int <- 1",
+        "x <-",
+        "\"abc",
+        "(* (* *)",
+        "case x of",
+    ] {
+        assert!(is_incomplete(source), "{source}");
+    }
+    for source in [
+        "",
+        "1",
+        "x;",
+        "class A {};",
+        "1 + + 2",
+        "(1))",
+        ":type (",
+        "-- (",
+        "\"(\"",
+    ] {
+        assert!(!is_incomplete(source), "{source}");
+    }
+}
+
+#[test]
+fn test_output_and_input() {
+    let mut output = Vec::new();
+    let mut session = Session::new();
+    let mut input = "line\n".as_bytes();
+    session
+        .execute("out_string(in_string())", &mut input, &mut output)
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "line<_Repl object> : SELF_TYPE\n"
+    );
+}
+
+#[test]
+fn test_errors() {
+    assert_eq!(
+        session("1 +\n\nx\n1 / 0\nabort()\n#\n"),
+        "<repl>:1: Syntax error at or near '+'.\n\
+         <repl>:1: Undeclared identifier x.\n\
+         <repl>:1: Division by zero.\n\
+         Abort called from class _Repl\n\
+         <repl>:1: Scanner error.\n\n"
+    );
+}
+
+#[test]
+fn test_commands() {
+    assert_eq!(
+        session(
+            "class A {};\n:type new A\n:type if true then 1 else \"a\" fi\n\
+             :tokens x <- 1\n:ptree class B {};\n:ptree ~1\n:foo\n\
+             :quit\n1\n"
+        ),
+        "Defined class A.\nA\nObject\n\
+         #1 OBJECTID x\n#1 ASSIGN\n#1 INT_CONST 1\n\
+         #1\n_program\n  #1\n  _class\n    B\n    Object\n\
+         \x20   \"<repl>\"\n    (\n    )\n\
+         #1\n_neg\n  #1\n  _int\n    1\n  : _no_type\n: _no_type\n\
+         Unknown command :foo. Enter :help for a list of commands.\n"
+    );
+}
@@ -0,0 +1,720 @@
+//! Semantic analysis of a parse tree: the class hierarchy is built and
+//! checked for well-formed inheritance, identifiers are resolved, and every
+//! feature and expression is checked against the static type rules of Cool.
+
+mod resolve;
+mod symbols;
+
+pub use self::resolve::*;
+pub use self::symbols::SymbolTable;
+use crate::hierarchy::*;
+use crate::ptree::*;
+use crate::tokens::{Ident, Span, TypeId};
+use std::fmt::{Display, Formatter};
+use ExpressionData::*;
+
+#[cfg(test)]
+mod tests;
+
+#[derive(Debug, PartialEq)]
+pub struct SemanticError<'a> {
+    pub message: String,
+    pub location: Option<Span<'a>>,
+}
+
+impl<'a> SemanticError<'a> {
+    pub fn new(message: String, location: Span<'a>) -> Self {
+        Self {
+            message,
+            location: Some(location),
+        }
+    }
+
+    pub fn without_location(message: String) -> Self {
+        Self {
+            message,
+            location: None,
+        }
+    }
+}
+
+// The format used here mimics the error messages of the reference semantic
+// analyser used in the Compilers course.
+impl Display for SemanticError<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.location {
+            Some(location) => {
+                let filename = location.extra;
+                let line_num = location.location_line();
+                write!(f, "{filename}:{line_num}: {}", self.message)
+            }
+            None => write!(f, "{}", self.message),
+        }
+    }
+}
+
+/// Check a program for semantic errors, returning its class hierarchy when
+/// there are none. All errors found are reported, except when the
+/// inheritance graph is malformed, in which case checking stops before
+/// features and expressions are examined.
+pub fn check_program<'a>(
+    program: &mut Program<'a>,
+) -> Result<ClassHierarchy<'a>, Vec<SemanticError<'a>>> {
+    check(program, true)
+}
+
+/// Check a set of classes like [`check_program`], without requiring them to
+/// define a Main class.
+pub fn check_classes<'a>(
+    program: &mut Program<'a>,
+) -> Result<ClassHierarchy<'a>, Vec<SemanticError<'a>>> {
+    check(program, false)
+}
+
+fn check<'a>(
+    program: &mut Program<'a>,
+    require_main: bool,
+) -> Result<ClassHierarchy<'a>, Vec<SemanticError<'a>>> {
+    let hierarchy = ClassHierarchy::new(program)?;
+    let (resolutions, mut errors) = resolve_names(program, &hierarchy);
+
+    let mut checker = TypeChecker::new(&hierarchy, &resolutions);
+    if require_main {
+        check_main_class(&hierarchy, &mut errors);
+    }
+    for class in program.classes.iter_mut() {
+        checker.check_class(class);
+    }
+    errors.append(&mut checker.errors);
+
+    if errors.is_empty() {
+        Ok(hierarchy)
+    } else {
+        Err(errors)
+    }
+}
+
+fn check_main_class<'a>(
+    hierarchy: &ClassHierarchy<'a>,
+    errors: &mut Vec<SemanticError<'a>>,
+) {
+    let main_class = match hierarchy.get(MAIN) {
+        Some(class) => class,
+        None => {
+            errors.push(SemanticError::without_location(
+                "Class Main is not defined.".to_string(),
+            ));
+            return;
+        }
+    };
+    match (main_class.method(MAIN_METHOD), main_class.location) {
+        (None, Some(location)) => errors.push(SemanticError::new(
+            "No 'main' method in class Main.".to_string(),
+            location,
+        )),
+        (Some(method), Some(location)) if !method.formals.is_empty() => errors
+            .push(SemanticError::new(
+                "'main' method in class Main should have no arguments."
+                    .to_string(),
+                method.location.unwrap_or(location),
+            )),
+        _ => {}
+    }
+}
+
+fn defines_attribute(feature: &Feature, name: &str) -> bool {
+    matches!(&feature.data, FeatureData::Attribute(n, _, _) if n == name)
+}
+
+fn defines_method(feature: &Feature, name: &str) -> bool {
+    matches!(&feature.data, FeatureData::Method(n, _, _, _) if n == name)
+}
+
+struct TypeChecker<'a, 't> {
+    hierarchy: &'t ClassHierarchy<'a>,
+    resolutions: &'t Resolutions<'a>,
+    current_class: TypeId,
+    errors: Vec<SemanticError<'a>>,
+}
+
+impl<'a, 't> TypeChecker<'a, 't> {
+    fn new(
+        hierarchy: &'t ClassHierarchy<'a>,
+        resolutions: &'t Resolutions<'a>,
+    ) -> Self {
+        Self {
+            hierarchy,
+            resolutions,
+            current_class: OBJECT.to_string(),
+            errors: Vec::new(),
+        }
+    }
+
+    fn error(&mut self, message: String, location: Span<'a>) {
+        self.errors.push(SemanticError::new(message, location));
+    }
+
+    fn conforms(&self, sub: &str, sup: &str) -> bool {
+        self.hierarchy.conforms_in(sub, sup, &self.current_class)
+    }
+
+    fn lub(&self, type1: &str, type2: &str) -> TypeId {
+        self.hierarchy.lub_in(type1, type2, &self.current_class)
+    }
+
+    fn check_class(&mut self, class: &mut Class<'a>) {
+        self.current_class = class.name.clone();
+        for index in 0..class.features.len() {
+            let (previous, rest) = class.features.split_at_mut(index);
+            let feature = &mut rest[0];
+            match &mut feature.data {
+                FeatureData::Attribute(name, _, _)
+                    if previous.iter().any(|f| defines_attribute(f, name)) =>
+                {
+                    self.error(
+                        format!(
+                            "Attribute {name} is multiply defined in class."
+                        ),
+                        feature.location,
+                    );
+                }
+                FeatureData::Method(name, _, _, _)
+                    if previous.iter().any(|f| defines_method(f, name)) =>
+                {
+                    self.error(
+                        format!("Method {name} is multiply defined."),
+                        feature.location,
+                    );
+                }
+                FeatureData::Attribute(name, type_id, init) => self
+                    .check_attribute(
+                        name,
+                        type_id,
+                        init.as_mut(),
+                        feature.location,
+                    ),
+                FeatureData::Method(name, return_type, formals, body) => self
+                    .check_method(
+                        name,
+                        return_type,
+                        formals,
+                        body,
+                        feature.location,
+                    ),
+            }
+        }
+    }
+
+    fn check_attribute(
+        &mut self,
+        name: &Ident,
+        type_id: &TypeId,
+        init: Option<&mut Expression<'a>>,
+        location: Span<'a>,
+    ) {
+        if name == SELF {
+            self.error(
+                "'self' cannot be the name of an attribute.".to_string(),
+                location,
+            );
+        }
+        let hierarchy = self.hierarchy;
+        let inherited = hierarchy
+            .parent(&self.current_class)
+            .and_then(|parent| hierarchy.lookup_attribute(parent, name));
+        if inherited.is_some() {
+            self.error(
+                format!(
+                    "Attribute {name} is an attribute of an inherited class."
+                ),
+                location,
+            );
+        }
+        let declared_type = if hierarchy.is_valid_type(type_id) {
+            type_id.as_str()
+        } else {
+            self.error(
+                format!("Class {type_id} of attribute {name} is undefined."),
+                location,
+            );
+            OBJECT
+        };
+
+        if let Some(expr) = init {
+            let init_type = self.check_expression(expr);
+            if !self.conforms(&init_type, declared_type) {
+                self.error(
+                    format!(
+                        "Inferred type {init_type} of initialization of \
+                        attribute {name} does not conform to declared type \
+                        {declared_type}."
+                    ),
+                    location,
+                );
+            }
+        }
+    }
+
+    fn check_method(
+        &mut self,
+        name: &Ident,
+        return_type: &TypeId,
+        formals: &[Formal<'a>],
+        body: &mut Expression<'a>,
+        location: Span<'a>,
+    ) {
+        let hierarchy = self.hierarchy;
+        for (index, formal) in formals.iter().enumerate() {
+            let formal_name = &formal.name;
+            if formal_name == SELF {
+                self.error(
+                    "'self' cannot be the name of a formal parameter."
+                        .to_string(),
+                    formal.location,
+                );
+                continue;
+            }
+            if formals[..index].iter().any(|f| &f.name == formal_name) {
+                self.error(
+                    format!(
+                        "Formal parameter {formal_name} is multiply defined."
+                    ),
+                    formal.location,
+                );
+                continue;
+            }
+            if formal.type_id == SELF_TYPE {
+                self.error(
+                    format!(
+                        "Formal parameter {formal_name} cannot have type \
+                        SELF_TYPE."
+                    ),
+                    formal.location,
+                );
+            } else if !hierarchy.contains(&formal.type_id) {
+                self.error(
+                    format!(
+                        "Class {} of formal parameter {formal_name} is \
+                        undefined.",
+                        formal.type_id
+                    ),
+                    formal.location,
+                );
+            }
+        }
+
+        let declared_type = if hierarchy.is_valid_type(return_type) {
+            return_type.as_str()
+        } else {
+            self.error(
+                format!(
+                    "Undefined return type {return_type} in method {name}."
+                ),
+                location,
+            );
+            OBJECT
+        };
+
+        let overridden = hierarchy
+            .parent(&self.current_class)
+            .and_then(|parent| hierarchy.lookup_method(parent, name));
+        if let Some(original) = overridden {
+            if original.formals.len() != formals.len() {
+                self.error(
+                    format!(
+                        "Incompatible number of formal parameters in \
+                        redefined method {name}."
+                    ),
+                    location,
+                );
+            } else {
+                for (formal, (_, original_type)) in
+                    formals.iter().zip(original.formals.iter())
+                {
+                    if &formal.type_id != original_type {
+                        self.error(
+                            format!(
+                                "In redefined method {name}, parameter type \
+                                {} is different from original type \
+                                {original_type}.",
+                                formal.type_id
+                            ),
+                            formal.location,
+                        );
+                    }
+                }
+            }
+            if return_type != &original.return_type {
+                self.error(
+                    format!(
+                        "In redefined method {name}, return type \
+                        {return_type} is different from original return \
+                        type {}.",
+                        original.return_type
+                    ),
+                    location,
+                );
+            }
+        }
+
+        let body_type = self.check_expression(body);
+        if !self.conforms(&body_type, declared_type) {
+            self.error(
+                format!(
+                    "Inferred return type {body_type} of method {name} does \
+                    not conform to declared return type {declared_type}."
+                ),
+                location,
+            );
+        }
+    }
+
+    // The type of the declaration an identifier was resolved to, or Object
+    // if the identifier is undeclared or its declared type is invalid, in
+    // which case an error has already been reported.
+    fn declared_type(&self, expr: &Expression<'a>) -> TypeId {
+        match self.resolutions.get(expr) {
+            Some(declaration) => match &declaration.kind {
+                DeclarationKind::SelfObject => SELF_TYPE.to_string(),
+                DeclarationKind::Formal(_) | DeclarationKind::CaseBranch
+                    if declaration.type_id == SELF_TYPE =>
+                {
+                    OBJECT.to_string()
+                }
+                _ if !self.hierarchy.is_valid_type(&declaration.type_id) => {
+                    OBJECT.to_string()
+                }
+                _ => declaration.type_id.clone(),
+            },
+            None => OBJECT.to_string(),
+        }
+    }
+
+    /// Type check an expression, annotating it with its static type, which
+    /// is also returned. Errors are recorded and checking carries on with
+    /// type Object, so that as many errors as possible are reported.
+    fn check_expression(&mut self, expr: &mut Expression<'a>) -> TypeId {
+        let declared_type = match &expr.data {
+            Object(_) | Assign(_, _) => self.declared_type(expr),
+            _ => OBJECT.to_string(),
+        };
+        let static_type =
+            self.infer_type(&mut expr.data, expr.location, declared_type);
+        expr.static_type = Some(static_type.clone());
+        static_type
+    }
+
+    fn infer_type(
+        &mut self,
+        data: &mut ExpressionData<'a>,
+        location: Span<'a>,
+        declared_type: TypeId,
+    ) -> TypeId {
+        match data {
+            Block(expressions) => {
+                let mut block_type = OBJECT.to_string();
+                for expression in expressions.iter_mut() {
+                    block_type = self.check_expression(expression);
+                }
+                block_type
+            }
+            Conditional(if_expr, then_expr, else_expr) => {
+                if self.check_expression(if_expr) != BOOL {
+                    self.error(
+                        "Predicate of 'if' does not have type Bool."
+                            .to_string(),
+                        location,
+                    );
+                }
+                let then_type = self.check_expression(then_expr);
+                let else_type = self.check_expression(else_expr);
+                self.lub(&then_type, &else_type)
+            }
+            Loop(cond_expr, loop_expr) => {
+                if self.check_expression(cond_expr) != BOOL {
+                    self.error(
+                        "Loop condition does not have type Bool.".to_string(),
+                        location,
+                    );
+                }
+                self.check_expression(loop_expr);
+                OBJECT.to_string()
+            }
+            Case(case_expr, branches) => {
+                self.check_expression(case_expr);
+                let mut case_type: Option<TypeId> = None;
+                let mut seen_types: Vec<TypeId> = Vec::new();
+                for branch in branches.iter_mut() {
+                    let branch_type = self.check_case_branch(branch);
+                    if seen_types.contains(&branch.type_id) {
+                        self.error(
+                            format!(
+                                "Duplicate branch {} in case statement.",
+                                branch.type_id
+                            ),
+                            branch.location,
+                        );
+                    }
+                    seen_types.push(branch.type_id.clone());
+                    case_type = Some(match case_type {
+                        Some(t) => self.lub(&t, &branch_type),
+                        None => branch_type,
+                    });
+                }
+                case_type.unwrap_or_else(|| OBJECT.to_string())
+            }
+            Let(ident, type_id, opt_bind, body) => {
+                if ident == SELF {
+                    self.error(
+                        "'self' cannot be bound in a 'let' expression."
+                            .to_string(),
+                        location,
+                    );
+                }
+                let let_type = if self.hierarchy.is_valid_type(type_id) {
+                    type_id.clone()
+                } else {
+                    self.error(
+                        format!(
+                            "Class {type_id} of let-bound identifier {ident} \
+                            is undefined."
+                        ),
+                        location,
+                    );
+                    OBJECT.to_string()
+                };
+                if let Some(bind) = opt_bind.as_mut() {
+                    let bind_type = self.check_expression(bind);
+                    if !self.conforms(&bind_type, &let_type) {
+                        self.error(
+                            format!(
+                                "Inferred type {bind_type} of initialization \
+                                of {ident} does not conform to identifier's \
+                                declared type {let_type}."
+                            ),
+                            location,
+                        );
+                    }
+                }
+                self.check_expression(body)
+            }
+            New(type_id) => {
+                if self.hierarchy.is_valid_type(type_id) {
+                    type_id.clone()
+                } else {
+                    self.error(
+                        format!("'new' used with undefined class {type_id}."),
+                        location,
+                    );
+                    OBJECT.to_string()
+                }
+            }
+            Assign(ident, value) => {
+                let value_type = self.check_expression(value);
+                if ident != SELF && !self.conforms(&value_type, &declared_type)
+                {
+                    self.error(
+                        format!(
+                            "Type {value_type} of assigned expression does \
+                            not conform to declared type {declared_type} of \
+                            identifier {ident}."
+                        ),
+                        location,
+                    );
+                }
+                value_type
+            }
+            UnaryOperation(operator, operand) => {
+                let operand_type = self.check_expression(operand);
+                match operator {
+                    UnaryOperator::Not => {
+                        if operand_type != BOOL {
+                            self.error(
+                                format!(
+                                    "Argument of 'not' has type \
+                                    {operand_type} instead of Bool."
+                                ),
+                                location,
+                            );
+                        }
+                        BOOL.to_string()
+                    }
+                    UnaryOperator::Negative => {
+                        if operand_type != INT {
+                            self.error(
+                                format!(
+                                    "Argument of '~' has type {operand_type} \
+                                    instead of Int."
+                                ),
+                                location,
+                            );
+                        }
+                        INT.to_string()
+                    }
+                    UnaryOperator::IsVoid => BOOL.to_string(),
+                }
+            }
+            BinaryOperation(operator, operand1, operand2) => {
+                let type1 = self.check_expression(operand1);
+                let type2 = self.check_expression(operand2);
+                let symbol = match operator {
+                    BinaryOperator::Equals => {
+                        let basic = [INT, STRING, BOOL];
+                        if (basic.contains(&type1.as_str())
+                            || basic.contains(&type2.as_str()))
+                            && type1 != type2
+                        {
+                            self.error(
+                                "Illegal comparison with a basic type."
+                                    .to_string(),
+                                location,
+                            );
+                        }
+                        return BOOL.to_string();
+                    }
+                    BinaryOperator::LessThanOrEquals => "<=",
+                    BinaryOperator::LessThan => "<",
+                    BinaryOperator::Add => "+",
+                    BinaryOperator::Subtract => "-",
+                    BinaryOperator::Multiply => "*",
+                    BinaryOperator::Divide => "/",
+                };
+                if type1 != INT || type2 != INT {
+                    self.error(
+                        format!("non-Int arguments: {type1} {symbol} {type2}"),
+                        location,
+                    );
+                }
+                match operator {
+                    BinaryOperator::LessThanOrEquals
+                    | BinaryOperator::LessThan => BOOL.to_string(),
+                    _ => INT.to_string(),
+                }
+            }
+            MethodCall(callee, static_type, ident, params) => self
+                .check_method_call(
+                    callee,
+                    static_type.as_ref(),
+                    ident,
+                    params,
+                    location,
+                ),
+            Object(_) => declared_type,
+            IntLiteral(_) => INT.to_string(),
+            StrLiteral(_) => STRING.to_string(),
+            BoolLiteral(_) => BOOL.to_string(),
+        }
+    }
+
+    fn check_case_branch(&mut self, branch: &mut CaseBranch<'a>) -> TypeId {
+        let ident = &branch.ident;
+        let type_id = &branch.type_id;
+        if ident == SELF {
+            self.error("'self' bound in 'case'.".to_string(), branch.location);
+        }
+        if type_id == SELF_TYPE {
+            self.error(
+                format!(
+                    "Identifier {ident} declared with type SELF_TYPE in case \
+                    branch."
+                ),
+                branch.location,
+            );
+        } else if !self.hierarchy.contains(type_id) {
+            self.error(
+                format!("Class {type_id} of case branch is undefined."),
+                branch.location,
+            );
+        }
+        self.check_expression(&mut branch.expression)
+    }
+
+    fn check_method_call(
+        &mut self,
+        callee: &mut Expression<'a>,
+        static_type: Option<&TypeId>,
+        ident: &Ident,
+        params: &mut [Expression<'a>],
+        location: Span<'a>,
+    ) -> TypeId {
+        let callee_type = self.check_expression(callee);
+        let param_types: Vec<TypeId> = params
+            .iter_mut()
+            .map(|param| self.check_expression(param))
+            .collect();
+
+        let dispatch_class = match static_type {
+            Some(type_id) if type_id == SELF_TYPE => {
+                self.error(
+                    "Static dispatch to SELF_TYPE.".to_string(),
+                    location,
+                );
+                return OBJECT.to_string();
+            }
+            Some(type_id) if !self.hierarchy.contains(type_id) => {
+                self.error(
+                    format!("Static dispatch to undefined class {type_id}."),
+                    location,
+                );
+                return OBJECT.to_string();
+            }
+            Some(type_id) => {
+                if !self.conforms(&callee_type, type_id) {
+                    self.error(
+                        format!(
+                            "Expression type {callee_type} does not conform \
+                            to declared static dispatch type {type_id}."
+                        ),
+                        location,
+                    );
+                    return OBJECT.to_string();
+                }
+                type_id.clone()
+            }
+            None if callee_type == SELF_TYPE => self.current_class.clone(),
+            None => callee_type.clone(),
+        };
+
+        let hierarchy = self.hierarchy;
+        let method = match hierarchy.lookup_method(&dispatch_class, ident) {
+            Some(method) => method,
+            None => {
+                self.error(
+                    format!("Dispatch to undefined method {ident}."),
+                    location,
+                );
+                return OBJECT.to_string();
+            }
+        };
+
+        if method.formals.len() != params.len() {
+            self.error(
+                format!(
+                    "Method {ident} called with wrong number of arguments."
+                ),
+                location,
+            );
+        } else {
+            for (param_type, (formal_name, formal_type)) in
+                param_types.iter().zip(method.formals.iter())
+            {
+                if !self.conforms(param_type, formal_type) {
+                    self.error(
+                        format!(
+                            "In call of method {ident}, type {param_type} of \
+                            parameter {formal_name} does not conform to \
+                            declared type {formal_type}."
+                        ),
+                        location,
+                    );
+                }
+            }
+        }
+
+        if method.return_type == SELF_TYPE {
+            callee_type
+        } else {
+            method.return_type.clone()
+        }
+    }
+}
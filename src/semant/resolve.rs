@@ -0,0 +1,236 @@
+//! Name resolution binds every use of an identifier, in `Object` and `Assign`
+//! expressions, to the attribute, formal parameter, `let` or `case` binding
+//! it refers to. Bindings are recorded in a side table keyed by the
+//! expression's `NodeId`.
+
+use super::symbols::SymbolTable;
+use super::SemanticError;
+use crate::hierarchy::{ClassHierarchy, SELF, SELF_TYPE};
+use crate::ptree::*;
+use crate::tokens::{Ident, Span, TypeId};
+use std::collections::HashMap;
+use ExpressionData::*;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum DeclarationKind {
+    SelfObject,
+    /// An attribute, along with the name of the class that defines it.
+    Attribute(TypeId),
+    /// A formal parameter, along with its position in the method signature.
+    Formal(usize),
+    Let,
+    CaseBranch,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Declaration<'a> {
+    pub kind: DeclarationKind,
+    pub name: Ident,
+    pub type_id: TypeId,
+    pub location: Option<Span<'a>>,
+}
+
+#[derive(Default)]
+pub struct Resolutions<'a> {
+    bindings: HashMap<NodeId, Declaration<'a>>,
+}
+
+impl<'a> Resolutions<'a> {
+    /// The declaration an `Object` or `Assign` expression refers to.
+    pub fn get(&self, expression: &Expression) -> Option<&Declaration<'a>> {
+        self.bindings.get(&expression.id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&NodeId, &Declaration<'a>)> {
+        self.bindings.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.bindings.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.bindings.is_empty()
+    }
+}
+
+/// Resolve all identifiers used in a program, reporting undeclared
+/// identifiers and assignments to `self`. Identifiers that cannot be
+/// resolved are left out of the returned table.
+pub fn resolve_names<'a>(
+    program: &Program<'a>,
+    hierarchy: &ClassHierarchy<'a>,
+) -> (Resolutions<'a>, Vec<SemanticError<'a>>) {
+    let mut resolver = Resolver {
+        hierarchy,
+        current_class: String::new(),
+        symbols: SymbolTable::new(),
+        resolutions: Resolutions::default(),
+        errors: Vec::new(),
+    };
+    for class in program.classes.iter() {
+        resolver.resolve_class(class);
+    }
+    (resolver.resolutions, resolver.errors)
+}
+
+struct Resolver<'a, 't> {
+    hierarchy: &'t ClassHierarchy<'a>,
+    current_class: TypeId,
+    symbols: SymbolTable<Ident, Declaration<'a>>,
+    resolutions: Resolutions<'a>,
+    errors: Vec<SemanticError<'a>>,
+}
+
+impl<'a, 't> Resolver<'a, 't> {
+    fn resolve_class(&mut self, class: &Class<'a>) {
+        self.current_class = class.name.clone();
+        for feature in class.features.iter() {
+            match &feature.data {
+                FeatureData::Attribute(_, _, Some(init)) => {
+                    self.resolve_expression(init)
+                }
+                FeatureData::Attribute(_, _, None) => {}
+                FeatureData::Method(_, _, formals, body) => {
+                    self.symbols.enter_scope();
+                    for (index, formal) in formals.iter().enumerate() {
+                        self.bind(
+                            &formal.name,
+                            &formal.type_id,
+                            DeclarationKind::Formal(index),
+                            formal.location,
+                        );
+                    }
+                    self.resolve_expression(body);
+                    self.symbols.exit_scope();
+                }
+            }
+        }
+    }
+
+    // Bind an identifier in the current scope. 'self' cannot be bound and
+    // the first binding of a duplicated name is kept, as both are errors
+    // reported by the type checker.
+    fn bind(
+        &mut self,
+        name: &Ident,
+        type_id: &TypeId,
+        kind: DeclarationKind,
+        location: Span<'a>,
+    ) {
+        if name == SELF || self.symbols.probe(name).is_some() {
+            return;
+        }
+        let declaration = Declaration {
+            kind,
+            name: name.clone(),
+            type_id: type_id.clone(),
+            location: Some(location),
+        };
+        self.symbols.insert(name.clone(), declaration);
+    }
+
+    fn lookup(&self, name: &str) -> Option<Declaration<'a>> {
+        if name == SELF {
+            return Some(Declaration {
+                kind: DeclarationKind::SelfObject,
+                name: SELF.to_string(),
+                type_id: SELF_TYPE.to_string(),
+                location: None,
+            });
+        }
+        if let Some(declaration) = self.symbols.lookup(name) {
+            return Some(declaration.clone());
+        }
+        self.hierarchy
+            .ancestors(&self.current_class)
+            .find_map(|class| {
+                class.attribute(name).map(|attr| Declaration {
+                    kind: DeclarationKind::Attribute(class.name.clone()),
+                    name: attr.name.clone(),
+                    type_id: attr.type_id.clone(),
+                    location: attr.location,
+                })
+            })
+    }
+
+    fn resolve_expression(&mut self, expr: &Expression<'a>) {
+        match &expr.data {
+            Block(expressions) => {
+                for expression in expressions.iter() {
+                    self.resolve_expression(expression);
+                }
+            }
+            Conditional(if_expr, then_expr, else_expr) => {
+                self.resolve_expression(if_expr);
+                self.resolve_expression(then_expr);
+                self.resolve_expression(else_expr);
+            }
+            Loop(cond_expr, loop_expr) => {
+                self.resolve_expression(cond_expr);
+                self.resolve_expression(loop_expr);
+            }
+            Case(case_expr, branches) => {
+                self.resolve_expression(case_expr);
+                for branch in branches.iter() {
+                    self.symbols.enter_scope();
+                    self.bind(
+                        &branch.ident,
+                        &branch.type_id,
+                        DeclarationKind::CaseBranch,
+                        branch.location,
+                    );
+                    self.resolve_expression(&branch.expression);
+                    self.symbols.exit_scope();
+                }
+            }
+            Let(ident, type_id, opt_bind, body) => {
+                if let Some(bind) = &**opt_bind {
+                    self.resolve_expression(bind);
+                }
+                self.symbols.enter_scope();
+                self.bind(ident, type_id, DeclarationKind::Let, expr.location);
+                self.resolve_expression(body);
+                self.symbols.exit_scope();
+            }
+            Assign(ident, value) => {
+                self.resolve_expression(value);
+                if ident == SELF {
+                    self.errors.push(SemanticError::new(
+                        "Cannot assign to 'self'.".to_string(),
+                        expr.location,
+                    ));
+                } else if let Some(declaration) = self.lookup(ident) {
+                    self.resolutions.bindings.insert(expr.id, declaration);
+                } else {
+                    self.errors.push(SemanticError::new(
+                        format!("Assignment to undeclared variable {ident}."),
+                        expr.location,
+                    ));
+                }
+            }
+            UnaryOperation(_, operand) => self.resolve_expression(operand),
+            BinaryOperation(_, operand1, operand2) => {
+                self.resolve_expression(operand1);
+                self.resolve_expression(operand2);
+            }
+            MethodCall(callee, _, _, params) => {
+                self.resolve_expression(callee);
+                for param in params.iter() {
+                    self.resolve_expression(param);
+                }
+            }
+            Object(ident) => {
+                if let Some(declaration) = self.lookup(ident) {
+                    self.resolutions.bindings.insert(expr.id, declaration);
+                } else {
+                    self.errors.push(SemanticError::new(
+                        format!("Undeclared identifier {ident}."),
+                        expr.location,
+                    ));
+                }
+            }
+            New(_) | IntLiteral(_) | StrLiteral(_) | BoolLiteral(_) => {}
+        }
+    }
+}
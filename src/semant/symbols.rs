@@ -0,0 +1,67 @@
+//! A symbol table made of nested scopes, where inner scopes shadow the
+//! bindings of outer scopes.
+
+use std::borrow::Borrow;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+pub struct SymbolTable<K, V> {
+    scopes: Vec<HashMap<K, V>>,
+}
+
+impl<K: Eq + Hash, V> SymbolTable<K, V> {
+    /// Create a symbol table with a single, outermost scope.
+    pub fn new() -> Self {
+        Self {
+            scopes: vec![HashMap::new()],
+        }
+    }
+
+    pub fn enter_scope(&mut self) {
+        self.scopes.push(HashMap::new());
+    }
+
+    /// Leave the innermost scope, dropping its bindings. The outermost scope
+    /// is never removed.
+    pub fn exit_scope(&mut self) {
+        if self.scopes.len() > 1 {
+            self.scopes.pop();
+        }
+    }
+
+    /// Bind a symbol in the innermost scope, replacing any previous binding
+    /// of the same symbol in that scope.
+    pub fn insert(&mut self, key: K, value: V) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(key, value);
+        }
+    }
+
+    /// Find the innermost binding of a symbol.
+    pub fn lookup<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.scopes.iter().rev().find_map(|scope| scope.get(key))
+    }
+
+    /// Find a binding of a symbol in the innermost scope only.
+    pub fn probe<Q>(&self, key: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Eq + Hash + ?Sized,
+    {
+        self.scopes.last().and_then(|scope| scope.get(key))
+    }
+
+    pub fn depth(&self) -> usize {
+        self.scopes.len()
+    }
+}
+
+impl<K: Eq + Hash, V> Default for SymbolTable<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
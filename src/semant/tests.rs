@@ -0,0 +1,449 @@
+use super::*;
+use crate::hierarchy::ClassHierarchy;
+use crate::lexer::lex_tokens;
+use crate::parser::parse_program;
+use crate::tokens::Token;
+
+fn tokens(input: &str) -> Vec<Token<'_>> {
+    let (_, tokens) = lex_tokens(input, "").unwrap();
+    tokens
+}
+
+fn errors(input: &str) -> Vec<String> {
+    let tokens = tokens(input);
+    let (_, mut program) = parse_program(&tokens).unwrap();
+    match check_program(&mut program) {
+        Ok(_) => Vec::new(),
+        Err(errors) => errors.into_iter().map(|e| e.message).collect(),
+    }
+}
+
+const MAIN_CLASS: &str = "class Main { main() : Object { 0 }; };";
+
+#[test]
+fn test_valid_programs() {
+    [
+        MAIN_CLASS,
+        "class Main inherits IO { main() : SELF_TYPE { out_string(\"hi\") }; };",
+        "class A { f() : SELF_TYPE { self }; };\
+         class B inherits A {};\
+         class Main { b : B <- (new B).f(); main() : B { b.f() }; };",
+        "class A { x : Int; f(y : Int) : Int { x <- x + y }; };\
+         class B inherits A { f(z : Int) : Int { z }; };\
+         class Main { main() : Int { (new B)@A.f(1) }; };",
+        "class A {}; class B inherits A {}; class C inherits A {};\
+         class Main { main() : A { if true then new B else new C fi }; };",
+        "class A {}; class B inherits A {}; class C inherits A {};\
+         class Main { main() : A {\
+             case 0 of b : B => new B; c : C => new C; esac }; };",
+        "class Main { main() : Int {\
+             let x : Int <- 1 in let x : String <- \"s\" in x.length() }; };",
+        "class Main { main() : Bool { 1 = 2 }; };",
+        "class Main { main() : Bool { new Main = new Object }; };",
+        "class Main { main() : Object { while isvoid self loop 0 pool }; };",
+        "class Main { x : SELF_TYPE <- new SELF_TYPE;\
+             main() : SELF_TYPE { x.copy() }; };",
+    ]
+    .iter()
+    .for_each(|input| {
+        assert!(errors(input).is_empty(), "Unexpected errors for {input}")
+    })
+}
+
+#[test]
+fn test_class_errors() {
+    [
+        ("class Int {};", "Redefinition of basic class Int."),
+        ("class A {}; class A {};", "Class A was previously defined."),
+        (
+            "class A inherits String {};",
+            "Class A cannot inherit class String.",
+        ),
+        (
+            "class A inherits B {};",
+            "Class A inherits from an undefined class B.",
+        ),
+        (
+            "class A inherits B {}; class B inherits A {};",
+            "Class A, or an ancestor of A, is involved in an inheritance \
+            cycle.",
+        ),
+        ("class A {};", "Class Main is not defined."),
+        ("class Main {};", "No 'main' method in class Main."),
+    ]
+    .iter()
+    .for_each(|(input, message)| {
+        assert!(
+            errors(input).iter().any(|e| e == message),
+            "Expected \"{message}\" for {input}"
+        )
+    })
+}
+
+#[test]
+fn test_feature_errors() {
+    [
+        (
+            "class A { a : Int; a : Int; };",
+            "Attribute a is multiply defined in class.",
+        ),
+        (
+            "class A { a : Int; }; class B inherits A { a : Int; };",
+            "Attribute a is an attribute of an inherited class.",
+        ),
+        (
+            "class A { self : Int; };",
+            "'self' cannot be the name of an attribute.",
+        ),
+        (
+            "class A { a : B; };",
+            "Class B of attribute a is undefined.",
+        ),
+        (
+            "class A { f() : Int { 0 }; f() : Int { 0 }; };",
+            "Method f is multiply defined.",
+        ),
+        (
+            "class A { f(x : Int, x : Int) : Int { 0 }; };",
+            "Formal parameter x is multiply defined.",
+        ),
+        (
+            "class A { f(x : SELF_TYPE) : Int { 0 }; };",
+            "Formal parameter x cannot have type SELF_TYPE.",
+        ),
+        (
+            "class A { f() : B { 0 }; };",
+            "Undefined return type B in method f.",
+        ),
+        (
+            "class A { f(x : Int) : Int { 0 }; };\
+             class B inherits A { f() : Int { 0 }; };",
+            "Incompatible number of formal parameters in redefined method f.",
+        ),
+        (
+            "class A { f(x : Int) : Int { 0 }; };\
+             class B inherits A { f(x : Bool) : Int { 0 }; };",
+            "In redefined method f, parameter type Bool is different from \
+            original type Int.",
+        ),
+        (
+            "class A { f() : Int { 0 }; };\
+             class B inherits A { f() : Object { 0 }; };",
+            "In redefined method f, return type Object is different from \
+            original return type Int.",
+        ),
+        (
+            "class A { a : Int <- \"s\"; };",
+            "Inferred type String of initialization of attribute a does not \
+            conform to declared type Int.",
+        ),
+        (
+            "class A { f() : Int { true }; };",
+            "Inferred return type Bool of method f does not conform to \
+            declared return type Int.",
+        ),
+        (
+            "class A { f() : SELF_TYPE { new A }; };",
+            "Inferred return type A of method f does not conform to declared \
+            return type SELF_TYPE.",
+        ),
+    ]
+    .iter()
+    .for_each(|(input, message)| {
+        let input = format!("{input}{MAIN_CLASS}");
+        assert!(
+            errors(&input).iter().any(|e| e == message),
+            "Expected \"{message}\" for {input}"
+        )
+    })
+}
+
+#[test]
+fn test_expression_errors() {
+    [
+        ("x", "Undeclared identifier x."),
+        ("self <- 0", "Cannot assign to 'self'."),
+        ("x <- 0", "Assignment to undeclared variable x."),
+        (
+            "let x : Int in x <- \"s\"",
+            "Type String of assigned expression does not conform to declared \
+            type Int of identifier x.",
+        ),
+        ("new B", "'new' used with undefined class B."),
+        ("f()", "Dispatch to undefined method f."),
+        (
+            "type_name(0)",
+            "Method type_name called with wrong number of arguments.",
+        ),
+        (
+            "(new IO).out_int(\"s\")",
+            "In call of method out_int, type String of parameter x does not \
+            conform to declared type Int.",
+        ),
+        (
+            "0@String.length()",
+            "Expression type Int does not conform to declared static dispatch \
+            type String.",
+        ),
+        ("self@SELF_TYPE.copy()", "Static dispatch to SELF_TYPE."),
+        ("0@B.copy()", "Static dispatch to undefined class B."),
+        (
+            "if 0 then 0 else 0 fi",
+            "Predicate of 'if' does not have type Bool.",
+        ),
+        (
+            "while 0 loop 0 pool",
+            "Loop condition does not have type Bool.",
+        ),
+        (
+            "case 0 of x : Int => 0; y : Int => 0; esac",
+            "Duplicate branch Int in case statement.",
+        ),
+        (
+            "case 0 of x : B => 0; esac",
+            "Class B of case branch is undefined.",
+        ),
+        ("case 0 of self : Int => 0; esac", "'self' bound in 'case'."),
+        (
+            "case 0 of x : SELF_TYPE => 0; esac",
+            "Identifier x declared with type SELF_TYPE in case branch.",
+        ),
+        (
+            "let self : Int in 0",
+            "'self' cannot be bound in a 'let' expression.",
+        ),
+        (
+            "let x : B in 0",
+            "Class B of let-bound identifier x is undefined.",
+        ),
+        (
+            "let x : Int <- true in 0",
+            "Inferred type Bool of initialization of x does not conform to \
+            identifier's declared type Int.",
+        ),
+        ("not 0", "Argument of 'not' has type Int instead of Bool."),
+        ("~true", "Argument of '~' has type Bool instead of Int."),
+        ("1 + true", "non-Int arguments: Int + Bool"),
+        ("\"a\" < \"b\"", "non-Int arguments: String < String"),
+        ("1 = \"1\"", "Illegal comparison with a basic type."),
+        ("self = 1", "Illegal comparison with a basic type."),
+    ]
+    .iter()
+    .for_each(|(expr, message)| {
+        let input = format!("class Main {{ main() : Object {{ {expr} }}; }};");
+        let errors = errors(&input);
+        assert!(
+            errors.iter().any(|e| e == message),
+            "Expected \"{message}\" for {expr}, got {errors:?}"
+        )
+    })
+}
+
+#[test]
+fn test_static_types() {
+    [
+        ("0", "Int"),
+        ("\"s\".concat(\"t\")", "String"),
+        ("not true", "Bool"),
+        ("isvoid 0", "Bool"),
+        ("self", "SELF_TYPE"),
+        ("copy()", "SELF_TYPE"),
+        ("new SELF_TYPE", "SELF_TYPE"),
+        ("self@Object.copy()", "SELF_TYPE"),
+        ("(new A).copy()", "A"),
+        ("if true then new A else new B fi", "A"),
+        ("if true then self else new B fi", "Object"),
+        ("while false loop 0 pool", "Object"),
+        ("case 0 of a : A => new B; b : B => new B; esac", "B"),
+        ("let x : A <- new B in x", "A"),
+        ("{ 0; \"s\"; }", "String"),
+    ]
+    .iter()
+    .for_each(|(expr, expected)| {
+        let input = format!(
+            "class A {{}}; class B inherits A {{}};\
+             class Main {{ main() : Object {{ {expr} }}; }};"
+        );
+        let tokens = tokens(&input);
+        let (_, mut program) = parse_program(&tokens).unwrap();
+        check_program(&mut program).unwrap();
+        let main = program.classes.last().unwrap();
+        assert!(
+            matches!(
+                &main.features[0].data,
+                FeatureData::Method(_, _, _, body)
+                    if body.static_type.as_deref() == Some(*expected),
+            ),
+            "Expected type {expected} for {expr}"
+        )
+    })
+}
+
+#[test]
+fn test_symbol_table() {
+    let mut symbols = SymbolTable::new();
+    symbols.insert("a".to_string(), 1);
+    symbols.enter_scope();
+    assert_eq!(symbols.lookup("a"), Some(&1));
+    assert_eq!(symbols.probe("a"), None);
+    symbols.insert("a".to_string(), 2);
+    symbols.insert("b".to_string(), 3);
+    assert_eq!(symbols.lookup("a"), Some(&2));
+    assert_eq!(symbols.probe("b"), Some(&3));
+    assert_eq!(symbols.depth(), 2);
+    symbols.exit_scope();
+    assert_eq!(symbols.lookup("a"), Some(&1));
+    assert_eq!(symbols.lookup("b"), None);
+    symbols.exit_scope();
+    assert_eq!(symbols.depth(), 1);
+}
+
+fn method_body<'a>(program: &'a Program<'a>) -> &'a Expression<'a> {
+    match &program
+        .classes
+        .last()
+        .unwrap()
+        .features
+        .last()
+        .unwrap()
+        .data
+    {
+        FeatureData::Method(_, _, _, body) => body,
+        _ => panic!("Expected a method"),
+    }
+}
+
+#[test]
+fn test_resolve_names() {
+    [
+        ("self", DeclarationKind::SelfObject, "SELF_TYPE"),
+        ("x", DeclarationKind::Attribute("Main".to_string()), "Int"),
+        ("y", DeclarationKind::Attribute("A".to_string()), "String"),
+        ("z", DeclarationKind::Formal(1), "Bool"),
+        (
+            "x <- 1",
+            DeclarationKind::Attribute("Main".to_string()),
+            "Int",
+        ),
+    ]
+    .iter()
+    .for_each(|(expr, kind, type_id)| {
+        let input = format!(
+            "class A {{ y : String; }};\
+             class Main inherits A {{ x : Int; main() : Object {{ 0 }};\
+             f(w : Int, z : Bool) : Object {{ {expr} }}; }};"
+        );
+        let tokens = tokens(&input);
+        let (_, program) = parse_program(&tokens).unwrap();
+        let hierarchy = ClassHierarchy::new(&program).ok().unwrap();
+        let (resolutions, errors) = resolve_names(&program, &hierarchy);
+        assert!(errors.is_empty());
+        assert!(
+            matches!(
+                resolutions.get(method_body(&program)),
+                Some(d) if d.kind == *kind && d.type_id == *type_id,
+            ),
+            "Wrong resolution for {expr}"
+        )
+    })
+}
+
+#[test]
+fn test_resolve_shadowing() {
+    let tokens = tokens(
+        "class Main { x : Int; main() : Object {\
+             let x : String <- x.type_name() in \
+                 case x of x : Bool => x; esac }; };",
+    );
+    let (_, program) = parse_program(&tokens).unwrap();
+    let hierarchy = ClassHierarchy::new(&program).ok().unwrap();
+    let (resolutions, errors) = resolve_names(&program, &hierarchy);
+    assert!(errors.is_empty());
+    assert_eq!(resolutions.len(), 3);
+
+    let (init, body) = match &method_body(&program).data {
+        ExpressionData::Let(_, _, init, body) => {
+            (init.as_ref().as_ref().unwrap(), body)
+        }
+        _ => panic!("Expected a let expression"),
+    };
+    let (scrutinee, branch) = match &body.data {
+        ExpressionData::Case(scrutinee, branches) => {
+            (scrutinee, &branches[0].expression)
+        }
+        _ => panic!("Expected a case expression"),
+    };
+    let callee = match &init.data {
+        ExpressionData::MethodCall(callee, _, _, _) => callee,
+        _ => panic!("Expected a method call"),
+    };
+    [
+        (
+            &**callee,
+            DeclarationKind::Attribute("Main".to_string()),
+            "Int",
+        ),
+        (&**scrutinee, DeclarationKind::Let, "String"),
+        (branch, DeclarationKind::CaseBranch, "Bool"),
+    ]
+    .iter()
+    .for_each(|(expr, kind, type_id)| {
+        assert!(matches!(
+            resolutions.get(expr),
+            Some(d) if d.kind == *kind && d.type_id == *type_id,
+        ))
+    })
+}
+
+#[test]
+fn test_resolve_moved_tree() {
+    let tokens = tokens("class Main { x : Int; main() : Object { x }; };");
+    let (_, mut program) = parse_program(&tokens).unwrap();
+    let hierarchy = ClassHierarchy::new(&program).ok().unwrap();
+    let (resolutions, _) = resolve_names(&program, &hierarchy);
+    drop(hierarchy);
+
+    // Bindings are keyed by node, so they follow an expression moved out of
+    // the tree and are not inherited by the node left in its place.
+    let body = match &mut program.classes[0].features[1].data {
+        FeatureData::Method(_, _, _, body) => {
+            let location = body.location;
+            let placeholder =
+                Expression::new(ExpressionData::BoolLiteral(true), location);
+            std::mem::replace(body, placeholder)
+        }
+        _ => panic!("Expected a method"),
+    };
+    let moved = Box::new(body);
+    assert!(matches!(
+        resolutions.get(&moved),
+        Some(d) if d.kind == DeclarationKind::Attribute("Main".to_string()),
+    ));
+    assert!(resolutions.get(method_body(&program)).is_none());
+}
+
+#[test]
+fn test_resolve_errors() {
+    [
+        ("x", "Undeclared identifier x."),
+        ("self <- self", "Cannot assign to 'self'."),
+        ("x <- 0", "Assignment to undeclared variable x."),
+        ("let x : Int <- x in 0", "Undeclared identifier x."),
+        (
+            "{ case 0 of x : Int => 0; esac; x; }",
+            "Undeclared identifier x.",
+        ),
+    ]
+    .iter()
+    .for_each(|(expr, message)| {
+        let input = format!("class Main {{ main() : Object {{ {expr} }}; }};");
+        let tokens = tokens(&input);
+        let (_, program) = parse_program(&tokens).unwrap();
+        let hierarchy = ClassHierarchy::new(&program).ok().unwrap();
+        let (_, errors) = resolve_names(&program, &hierarchy);
+        assert!(
+            errors.iter().any(|e| e.message == *message),
+            "Expected \"{message}\" for {expr}"
+        )
+    })
+}
@@ -0,0 +1,551 @@
+//! Assembly of MIPS source into an image for the simulator.
+
+use super::*;
+use std::collections::HashMap;
+
+/// Assemble sources, given with their names, into a single image. Labels
+/// are shared between the sources, and execution starts at `__start`.
+pub fn assemble(sources: &[(&str, &str)]) -> Result<Image, AssemblyError> {
+    let mut assembler = Assembler {
+        sources,
+        symbols: HashMap::new(),
+        data: Vec::new(),
+        fixups: Vec::new(),
+        instructions: Vec::new(),
+        pending: Vec::new(),
+        in_text: true,
+    };
+    for (source, (_, text)) in sources.iter().enumerate() {
+        for (line, number) in text.lines().zip(1..) {
+            let location = Location {
+                source,
+                line: number,
+            };
+            assembler.line(line, location)?;
+        }
+    }
+    assembler.finish()
+}
+
+#[derive(Clone, Copy)]
+struct Location {
+    source: usize,
+    line: usize,
+}
+
+#[derive(Clone, Copy)]
+enum Symbol {
+    Text(Target),
+    /// An offset in the data segment.
+    Data(u32),
+}
+
+impl Symbol {
+    fn address(self) -> u32 {
+        match self {
+            Self::Text(target) => TEXT_BASE + 4 * target as u32,
+            Self::Data(offset) => DATA_BASE + offset,
+        }
+    }
+}
+
+struct Assembler<'s> {
+    sources: &'s [(&'s str, &'s str)],
+    symbols: HashMap<&'s str, Symbol>,
+    data: Vec<u8>,
+    /// Words of data holding the address of a label.
+    fixups: Vec<(usize, &'s str, Location)>,
+    /// Instructions are decoded once all labels are known.
+    instructions: Vec<(&'s str, Vec<&'s str>, Location)>,
+    /// Data labels are placed at the next item of data, once it is aligned.
+    pending: Vec<(&'s str, Location)>,
+    in_text: bool,
+}
+
+impl<'s> Assembler<'s> {
+    fn error(&self, location: Location, message: String) -> AssemblyError {
+        AssemblyError {
+            source: self.sources[location.source].0.to_string(),
+            line: location.line,
+            message,
+        }
+    }
+
+    fn define(
+        &mut self,
+        label: &'s str,
+        symbol: Symbol,
+        location: Location,
+    ) -> Result<(), AssemblyError> {
+        if self.symbols.insert(label, symbol).is_some() {
+            return Err(
+                self.error(location, format!("Label {label} is defined twice"))
+            );
+        }
+        Ok(())
+    }
+
+    fn line(
+        &mut self,
+        line: &'s str,
+        location: Location,
+    ) -> Result<(), AssemblyError> {
+        let mut rest = strip_comment(line).trim();
+        while let Some((label, after)) = split_label(rest) {
+            if self.in_text {
+                let target = self.instructions.len();
+                self.define(label, Symbol::Text(target), location)?;
+            } else {
+                self.pending.push((label, location));
+            }
+            rest = after.trim_start();
+        }
+        if rest.is_empty() {
+            return Ok(());
+        }
+        let (mnemonic, operands) = match rest.find(char::is_whitespace) {
+            Some(end) => (&rest[..end], rest[end..].trim()),
+            None => (rest, ""),
+        };
+        if mnemonic.starts_with('.') {
+            self.directive(mnemonic, operands, location)
+        } else if self.in_text {
+            let operands = operands
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|operand| !operand.is_empty())
+                .collect();
+            self.instructions.push((mnemonic, operands, location));
+            Ok(())
+        } else {
+            Err(self.error(location, "Instruction in data segment".into()))
+        }
+    }
+
+    fn align(&mut self, alignment: usize) {
+        while !self.data.len().is_multiple_of(alignment) {
+            self.data.push(0);
+        }
+    }
+
+    fn place_pending(&mut self) -> Result<(), AssemblyError> {
+        for (label, location) in std::mem::take(&mut self.pending) {
+            let offset = self.data.len() as u32;
+            self.define(label, Symbol::Data(offset), location)?;
+        }
+        Ok(())
+    }
+
+    fn directive(
+        &mut self,
+        directive: &str,
+        operands: &'s str,
+        location: Location,
+    ) -> Result<(), AssemblyError> {
+        let values = || {
+            operands
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|operand| !operand.is_empty())
+        };
+        match directive {
+            ".text" => self.in_text = true,
+            ".data" => self.in_text = false,
+            ".globl" => {}
+            _ if self.in_text => {
+                return Err(self.error(
+                    location,
+                    format!("Directive {directive} in text segment"),
+                ))
+            }
+            ".align" => {
+                let power = parse_number(operands)
+                    .filter(|power| (0..=8).contains(power))
+                    .ok_or_else(|| {
+                        self.error(location, "Invalid alignment".into())
+                    })?;
+                self.align(1 << power);
+            }
+            ".word" => {
+                self.align(4);
+                self.place_pending()?;
+                for value in values() {
+                    let word = match parse_number(value) {
+                        Some(int) => int as u32,
+                        None => {
+                            self.fixups.push((
+                                self.data.len(),
+                                value,
+                                location,
+                            ));
+                            0
+                        }
+                    };
+                    self.data.extend(word.to_le_bytes());
+                }
+            }
+            ".byte" => {
+                self.place_pending()?;
+                for value in values() {
+                    let byte = parse_number(value).ok_or_else(|| {
+                        self.error(location, format!("Invalid byte {value}"))
+                    })?;
+                    self.data.push(byte as u8);
+                }
+            }
+            ".space" => {
+                self.place_pending()?;
+                let size = parse_number(operands)
+                    .filter(|size| *size >= 0)
+                    .ok_or_else(|| {
+                        self.error(location, "Invalid size".into())
+                    })?;
+                self.data.resize(self.data.len() + size as usize, 0);
+            }
+            ".ascii" | ".asciiz" => {
+                self.place_pending()?;
+                let string = parse_string(operands).ok_or_else(|| {
+                    self.error(location, "Invalid string".into())
+                })?;
+                self.data.extend(string);
+                if directive == ".asciiz" {
+                    self.data.push(0);
+                }
+            }
+            _ => {
+                return Err(self
+                    .error(location, format!("Unknown directive {directive}")))
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<Image, AssemblyError> {
+        self.place_pending()?;
+        for (offset, label, location) in std::mem::take(&mut self.fixups) {
+            let address = self.symbol(label, location)?.address();
+            self.data[offset..offset + 4]
+                .copy_from_slice(&address.to_le_bytes());
+        }
+        let mut text = Vec::with_capacity(self.instructions.len());
+        for (mnemonic, operands, location) in self.instructions.iter() {
+            let decoder = Decoder {
+                assembler: &self,
+                operands,
+                location: *location,
+            };
+            text.push(decoder.decode(mnemonic)?);
+        }
+        let entry = match self.symbols.get("__start") {
+            Some(Symbol::Text(target)) => *target,
+            _ => {
+                return Err(AssemblyError {
+                    source: self.sources.last().map_or("", |s| s.0).into(),
+                    line: 0,
+                    message: "No __start label in the text segment".into(),
+                })
+            }
+        };
+        Ok(Image {
+            text,
+            data: self.data,
+            entry,
+        })
+    }
+
+    fn symbol(
+        &self,
+        label: &str,
+        location: Location,
+    ) -> Result<Symbol, AssemblyError> {
+        self.symbols.get(label).copied().ok_or_else(|| {
+            self.error(location, format!("Undefined label {label}"))
+        })
+    }
+}
+
+// Decodes the operands of an instruction.
+struct Decoder<'d, 's> {
+    assembler: &'d Assembler<'s>,
+    operands: &'d [&'s str],
+    location: Location,
+}
+
+impl Decoder<'_, '_> {
+    fn error(&self, message: String) -> AssemblyError {
+        self.assembler.error(self.location, message)
+    }
+
+    fn count(&self, count: usize) -> Result<(), AssemblyError> {
+        if self.operands.len() == count {
+            Ok(())
+        } else {
+            Err(self.error(format!(
+                "Expected {count} operands, found {}",
+                self.operands.len()
+            )))
+        }
+    }
+
+    fn register(&self, index: usize) -> Result<Register, AssemblyError> {
+        let operand = self.operands[index];
+        parse_register(operand)
+            .ok_or_else(|| self.error(format!("Invalid register {operand}")))
+    }
+
+    fn operand(&self, index: usize) -> Result<Operand, AssemblyError> {
+        let operand = self.operands[index];
+        if let Some(register) = parse_register(operand) {
+            Ok(Operand::Register(register))
+        } else if let Some(int) = parse_number(operand) {
+            Ok(Operand::Immediate(int))
+        } else {
+            Err(self.error(format!("Invalid operand {operand}")))
+        }
+    }
+
+    fn immediate(&self, index: usize) -> Result<i32, AssemblyError> {
+        let operand = self.operands[index];
+        parse_number(operand)
+            .ok_or_else(|| self.error(format!("Invalid immediate {operand}")))
+    }
+
+    fn address(&self, index: usize) -> Result<u32, AssemblyError> {
+        let operand = self.operands[index];
+        match parse_number(operand) {
+            Some(int) => Ok(int as u32),
+            None => {
+                Ok(self.assembler.symbol(operand, self.location)?.address())
+            }
+        }
+    }
+
+    fn target(&self, index: usize) -> Result<Target, AssemblyError> {
+        let label = self.operands[index];
+        match self.assembler.symbol(label, self.location)? {
+            Symbol::Text(target) => Ok(target),
+            Symbol::Data(_) => {
+                Err(self.error(format!("Label {label} is not in the text")))
+            }
+        }
+    }
+
+    // A memory operand, `offset($base)`, `($base)` or a label.
+    fn memory(&self, index: usize) -> Result<(i32, Register), AssemblyError> {
+        let operand = self.operands[index];
+        match operand.strip_suffix(')').and_then(|o| o.split_once('(')) {
+            Some((offset, base)) => {
+                let offset = match offset {
+                    "" => Some(0),
+                    offset => parse_number(offset),
+                };
+                offset.zip(parse_register(base)).ok_or_else(|| {
+                    self.error(format!("Invalid memory operand {operand}"))
+                })
+            }
+            None => Ok((self.address(index)? as i32, 0)),
+        }
+    }
+
+    fn decode(&self, mnemonic: &str) -> Result<Instruction, AssemblyError> {
+        use Instruction::*;
+        let alu = |op| -> Result<Instruction, AssemblyError> {
+            self.count(3)?;
+            Ok(Alu(
+                op,
+                self.register(0)?,
+                self.register(1)?,
+                self.operand(2)?,
+            ))
+        };
+        let memory =
+            |instruction: fn(Register, i32, Register) -> Instruction| {
+                self.count(2)?;
+                let (offset, base) = self.memory(1)?;
+                Ok(instruction(self.register(0)?, offset, base))
+            };
+        let branch = |condition| -> Result<Instruction, AssemblyError> {
+            self.count(3)?;
+            Ok(Branch(
+                condition,
+                self.register(0)?,
+                self.operand(1)?,
+                self.target(2)?,
+            ))
+        };
+        let branch_zero = |condition| -> Result<Instruction, AssemblyError> {
+            self.count(2)?;
+            Ok(Branch(
+                condition,
+                self.register(0)?,
+                Operand::Immediate(0),
+                self.target(1)?,
+            ))
+        };
+        match mnemonic {
+            "add" | "addi" => alu(AluOp::Add),
+            "addu" | "addiu" => alu(AluOp::Addu),
+            "sub" => alu(AluOp::Sub),
+            "subu" => alu(AluOp::Subu),
+            "mul" => alu(AluOp::Mul),
+            "div" => alu(AluOp::Div),
+            "rem" => alu(AluOp::Rem),
+            "and" | "andi" => alu(AluOp::And),
+            "or" | "ori" => alu(AluOp::Or),
+            "xor" | "xori" => alu(AluOp::Xor),
+            "nor" => alu(AluOp::Nor),
+            "slt" | "slti" => alu(AluOp::Slt),
+            "sltu" | "sltiu" => alu(AluOp::Sltu),
+            "sll" | "sllv" => alu(AluOp::Sll),
+            "srl" | "srlv" => alu(AluOp::Srl),
+            "sra" | "srav" => alu(AluOp::Sra),
+            "move" | "neg" | "negu" | "not" => {
+                self.count(2)?;
+                let (rd, rs) = (self.register(0)?, self.register(1)?);
+                Ok(match mnemonic {
+                    "move" => Alu(AluOp::Addu, rd, rs, Operand::Register(0)),
+                    "neg" => Alu(AluOp::Sub, rd, 0, Operand::Register(rs)),
+                    "negu" => Alu(AluOp::Subu, rd, 0, Operand::Register(rs)),
+                    _ => Alu(AluOp::Nor, rd, rs, Operand::Register(0)),
+                })
+            }
+            "li" | "la" => {
+                self.count(2)?;
+                Ok(Li(self.register(0)?, self.address(1)?))
+            }
+            "lui" => {
+                self.count(2)?;
+                Ok(Li(self.register(0)?, (self.immediate(1)? as u32) << 16))
+            }
+            "lw" => memory(Lw),
+            "lb" => memory(Lb),
+            "lbu" => memory(Lbu),
+            "sw" => memory(Sw),
+            "sb" => memory(Sb),
+            "beq" => branch(Condition::Eq),
+            "bne" => branch(Condition::Ne),
+            "blt" => branch(Condition::Lt),
+            "ble" => branch(Condition::Le),
+            "bgt" => branch(Condition::Gt),
+            "bge" => branch(Condition::Ge),
+            "bltu" => branch(Condition::LtUnsigned),
+            "bleu" => branch(Condition::LeUnsigned),
+            "bgtu" => branch(Condition::GtUnsigned),
+            "bgeu" => branch(Condition::GeUnsigned),
+            "beqz" => branch_zero(Condition::Eq),
+            "bnez" => branch_zero(Condition::Ne),
+            "bltz" => branch_zero(Condition::Lt),
+            "blez" => branch_zero(Condition::Le),
+            "bgtz" => branch_zero(Condition::Gt),
+            "bgez" => branch_zero(Condition::Ge),
+            "b" | "j" => {
+                self.count(1)?;
+                Ok(Jump(self.target(0)?))
+            }
+            "jal" => {
+                self.count(1)?;
+                Ok(Jal(self.target(0)?))
+            }
+            "jr" => {
+                self.count(1)?;
+                Ok(Jr(self.register(0)?))
+            }
+            "jalr" => match self.operands.len() {
+                1 => Ok(Jalr(31, self.register(0)?)),
+                _ => {
+                    self.count(2)?;
+                    Ok(Jalr(self.register(0)?, self.register(1)?))
+                }
+            },
+            "syscall" | "break" | "nop" => {
+                // break takes an optional code, which is ignored.
+                if mnemonic != "break" {
+                    self.count(0)?;
+                }
+                Ok(match mnemonic {
+                    "syscall" => Syscall,
+                    "break" => Break,
+                    _ => Nop,
+                })
+            }
+            _ => Err(self.error(format!("Unknown instruction {mnemonic}"))),
+        }
+    }
+}
+
+// Remove a comment, which starts with # outside of a string.
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (index, ch) in line.char_indices() {
+        match ch {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..index],
+            _ => {}
+        }
+    }
+    line
+}
+
+// Split a leading `label:` from a line.
+fn split_label(line: &str) -> Option<(&str, &str)> {
+    let end =
+        line.find(|c: char| !(c.is_ascii_alphanumeric() || "_.$".contains(c)))?;
+    let (label, rest) = line.split_at(end);
+    let rest = rest.strip_prefix(':')?;
+    (!label.is_empty()).then_some((label, rest))
+}
+
+fn parse_number(text: &str) -> Option<i32> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let value = match digits.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16).ok()? as i64,
+        None if digits.starts_with(|c: char| c.is_ascii_digit()) => {
+            digits.parse::<i64>().ok()?
+        }
+        None => return None,
+    };
+    let value = if negative { -value } else { value };
+    (i32::MIN as i64..=u32::MAX as i64)
+        .contains(&value)
+        .then_some(value as i32)
+}
+
+fn parse_register(text: &str) -> Option<Register> {
+    const NAMES: [&str; 32] = [
+        "zero", "at", "v0", "v1", "a0", "a1", "a2", "a3", "t0", "t1", "t2",
+        "t3", "t4", "t5", "t6", "t7", "s0", "s1", "s2", "s3", "s4", "s5", "s6",
+        "s7", "t8", "t9", "k0", "k1", "gp", "sp", "fp", "ra",
+    ];
+    let name = text.strip_prefix('$')?;
+    match name.parse::<u8>() {
+        Ok(number) => (number < 32).then_some(number),
+        Err(_) if name == "s8" => Some(30),
+        Err(_) => NAMES
+            .iter()
+            .position(|register| *register == name)
+            .map(|number| number as Register),
+    }
+}
+
+fn parse_string(text: &str) -> Option<Vec<u8>> {
+    let text = text.strip_prefix('"')?.strip_suffix('"')?;
+    let mut bytes = Vec::with_capacity(text.len());
+    let mut chars = text.bytes();
+    while let Some(byte) = chars.next() {
+        bytes.push(match byte {
+            b'\\' => match chars.next()? {
+                b'n' => b'\n',
+                b't' => b'\t',
+                b'0' => 0,
+                other => other,
+            },
+            b'"' => return None,
+            other => other,
+        });
+    }
+    Some(bytes)
+}
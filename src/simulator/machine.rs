@@ -0,0 +1,326 @@
+//! Execution of an assembled image.
+
+use super::*;
+use crate::interpreter::parse_int;
+use std::io::{BufRead, Write};
+
+const V0: usize = 2;
+const A0: usize = 4;
+const A1: usize = 5;
+const A2: usize = 6;
+const SP: usize = 29;
+const RA: usize = 31;
+
+pub struct Simulator<'i> {
+    text: &'i [Instruction],
+    registers: [u32; 32],
+    pc: Target,
+    /// The data segment followed by the heap, from `DATA_BASE`.
+    heap: Vec<u8>,
+    /// The stack, ending at `STACK_TOP`.
+    stack: Vec<u8>,
+    input: Box<dyn BufRead + 'i>,
+    output: Box<dyn Write + 'i>,
+    errors: Box<dyn Write + 'i>,
+}
+
+impl<'i> Simulator<'i> {
+    /// Create a simulator for an image, with standard input, output and
+    /// error.
+    pub fn new(
+        image: &'i Image,
+        input: Box<dyn BufRead + 'i>,
+        output: Box<dyn Write + 'i>,
+        errors: Box<dyn Write + 'i>,
+    ) -> Self {
+        let mut registers = [0; 32];
+        registers[SP] = STACK_TOP - 4;
+        registers[28] = DATA_BASE + 0x8000;
+        Self {
+            text: &image.text,
+            registers,
+            pc: image.entry,
+            heap: image.data.clone(),
+            stack: vec![0; STACK_SIZE as usize],
+            input,
+            output,
+            errors,
+        }
+    }
+
+    /// Run until the program exits, returning its exit status.
+    pub fn run(&mut self) -> Result<i32, Fault> {
+        let result = self.execute();
+        let flushed = self.output.flush().and_then(|_| self.errors.flush());
+        let status = result.map_err(|kind| Fault {
+            kind,
+            address: TEXT_BASE + 4 * self.pc as u32,
+        })?;
+        flushed.map_err(|err| Fault {
+            kind: FaultKind::Io(err),
+            address: TEXT_BASE + 4 * self.pc as u32,
+        })?;
+        Ok(status)
+    }
+
+    fn get(&self, register: Register) -> u32 {
+        self.registers[register as usize]
+    }
+
+    fn set(&mut self, register: Register, value: u32) {
+        if register != 0 {
+            self.registers[register as usize] = value;
+        }
+    }
+
+    fn operand(&self, operand: Operand) -> u32 {
+        match operand {
+            Operand::Register(register) => self.get(register),
+            Operand::Immediate(immediate) => immediate as u32,
+        }
+    }
+
+    // The memory holding `size` bytes at an aligned address.
+    fn memory(
+        &mut self,
+        address: u32,
+        size: u32,
+    ) -> Result<&mut [u8], FaultKind> {
+        let bad = FaultKind::BadAddress(address);
+        if !address.is_multiple_of(size) {
+            return Err(bad);
+        }
+        let (memory, offset) = if address >= STACK_TOP - STACK_SIZE {
+            let offset = address.checked_sub(STACK_TOP - STACK_SIZE);
+            (&mut self.stack, offset.ok_or(bad)?)
+        } else {
+            let offset = address.checked_sub(DATA_BASE);
+            (&mut self.heap, offset.ok_or(bad)?)
+        };
+        let (start, end) = (offset as usize, (offset + size) as usize);
+        memory
+            .get_mut(start..end)
+            .ok_or(FaultKind::BadAddress(address))
+    }
+
+    fn load(&mut self, address: u32) -> Result<u32, FaultKind> {
+        let bytes = self.memory(address, 4)?;
+        Ok(u32::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    fn store(&mut self, address: u32, value: u32) -> Result<(), FaultKind> {
+        self.memory(address, 4)?
+            .copy_from_slice(&value.to_le_bytes());
+        Ok(())
+    }
+
+    fn load_byte(&mut self, address: u32) -> Result<u8, FaultKind> {
+        Ok(self.memory(address, 1)?[0])
+    }
+
+    fn jump_target(&self, address: u32) -> Result<Target, FaultKind> {
+        let target = address.wrapping_sub(TEXT_BASE) as usize / 4;
+        if address.is_multiple_of(4)
+            && address >= TEXT_BASE
+            && target < self.text.len()
+        {
+            Ok(target)
+        } else {
+            Err(FaultKind::BadJump(address))
+        }
+    }
+
+    fn execute(&mut self) -> Result<i32, FaultKind> {
+        loop {
+            let instruction = match self.text.get(self.pc) {
+                Some(instruction) => *instruction,
+                None => {
+                    let address = TEXT_BASE + 4 * self.pc as u32;
+                    return Err(FaultKind::BadJump(address));
+                }
+            };
+            let mut next = self.pc + 1;
+            match instruction {
+                Instruction::Alu(op, rd, rs, operand) => {
+                    let value = alu(op, self.get(rs), self.operand(operand))?;
+                    self.set(rd, value);
+                }
+                Instruction::Li(rd, value) => self.set(rd, value),
+                Instruction::Lw(rt, offset, base) => {
+                    let address = self.get(base).wrapping_add(offset as u32);
+                    let value = self.load(address)?;
+                    self.set(rt, value);
+                }
+                Instruction::Lb(rt, offset, base) => {
+                    let address = self.get(base).wrapping_add(offset as u32);
+                    let value = self.load_byte(address)? as i8 as u32;
+                    self.set(rt, value);
+                }
+                Instruction::Lbu(rt, offset, base) => {
+                    let address = self.get(base).wrapping_add(offset as u32);
+                    let value = self.load_byte(address)? as u32;
+                    self.set(rt, value);
+                }
+                Instruction::Sw(rt, offset, base) => {
+                    let address = self.get(base).wrapping_add(offset as u32);
+                    self.store(address, self.get(rt))?;
+                }
+                Instruction::Sb(rt, offset, base) => {
+                    let address = self.get(base).wrapping_add(offset as u32);
+                    let value = self.get(rt) as u8;
+                    self.memory(address, 1)?[0] = value;
+                }
+                Instruction::Branch(condition, rs, operand, target) => {
+                    let (a, b) = (self.get(rs), self.operand(operand));
+                    let (x, y) = (a as i32, b as i32);
+                    let taken = match condition {
+                        Condition::Eq => a == b,
+                        Condition::Ne => a != b,
+                        Condition::Lt => x < y,
+                        Condition::Le => x <= y,
+                        Condition::Gt => x > y,
+                        Condition::Ge => x >= y,
+                        Condition::LtUnsigned => a < b,
+                        Condition::LeUnsigned => a <= b,
+                        Condition::GtUnsigned => a > b,
+                        Condition::GeUnsigned => a >= b,
+                    };
+                    if taken {
+                        next = target;
+                    }
+                }
+                Instruction::Jump(target) => next = target,
+                Instruction::Jal(target) => {
+                    self.registers[RA] = TEXT_BASE + 4 * next as u32;
+                    next = target;
+                }
+                Instruction::Jr(rs) => next = self.jump_target(self.get(rs))?,
+                Instruction::Jalr(rd, rs) => {
+                    let target = self.jump_target(self.get(rs))?;
+                    self.set(rd, TEXT_BASE + 4 * next as u32);
+                    next = target;
+                }
+                Instruction::Syscall => {
+                    if let Some(status) = self.syscall()? {
+                        return Ok(status);
+                    }
+                }
+                Instruction::Break => return Err(FaultKind::DivisionByZero),
+                Instruction::Nop => {}
+            }
+            if self.registers[SP] < STACK_TOP - STACK_SIZE {
+                return Err(FaultKind::StackOverflow);
+            }
+            self.pc = next;
+        }
+    }
+
+    // Read the null-terminated string at an address.
+    fn string(&mut self, mut address: u32) -> Result<Vec<u8>, FaultKind> {
+        let mut string = Vec::new();
+        loop {
+            match self.load_byte(address)? {
+                0 => return Ok(string),
+                byte => string.push(byte),
+            }
+            address = address.wrapping_add(1);
+        }
+    }
+
+    fn read_line(&mut self) -> Result<String, FaultKind> {
+        self.output.flush().map_err(FaultKind::Io)?;
+        let mut line = String::new();
+        self.input.read_line(&mut line).map_err(FaultKind::Io)?;
+        Ok(line)
+    }
+
+    // Run the system call selected by $v0, returning the exit status if the
+    // program exits.
+    fn syscall(&mut self) -> Result<Option<i32>, FaultKind> {
+        let (a0, a1, a2) =
+            (self.registers[A0], self.registers[A1], self.registers[A2]);
+        match self.registers[V0] {
+            1 => write!(self.output, "{}", a0 as i32).map_err(FaultKind::Io)?,
+            4 => {
+                let string = self.string(a0)?;
+                self.output.write_all(&string).map_err(FaultKind::Io)?;
+            }
+            5 => {
+                let line = self.read_line()?;
+                self.registers[V0] = parse_int(&line) as u32;
+            }
+            8 => {
+                // Read at most a1 - 1 characters, keeping the newline.
+                let line = self.read_line()?;
+                let length = (a1 as usize).saturating_sub(1).min(line.len());
+                let mut bytes = line.as_bytes()[..length].to_vec();
+                bytes.push(0);
+                for (offset, byte) in bytes.into_iter().enumerate() {
+                    let address = a0.wrapping_add(offset as u32);
+                    self.memory(address, 1)?[0] = byte;
+                }
+            }
+            9 => {
+                // The break is kept word aligned.
+                let size = (a0 as i32).max(0) as usize;
+                let size = (size + 3) & !3;
+                let address = DATA_BASE + self.heap.len() as u32;
+                let length = self.heap.len() + size;
+                if length > HEAP_LIMIT as usize {
+                    return Err(FaultKind::OutOfMemory);
+                }
+                self.heap.resize(length, 0);
+                self.registers[V0] = address;
+            }
+            10 => return Ok(Some(0)),
+            11 => self.output.write_all(&[a0 as u8]).map_err(FaultKind::Io)?,
+            15 => {
+                let mut bytes = Vec::with_capacity(a2 as usize);
+                for offset in 0..a2 {
+                    bytes.push(self.load_byte(a1.wrapping_add(offset))?);
+                }
+                let written = match a0 {
+                    1 => self.output.write_all(&bytes),
+                    2 => {
+                        self.output.flush().map_err(FaultKind::Io)?;
+                        self.errors.write_all(&bytes)
+                    }
+                    _ => {
+                        self.registers[V0] = -1_i32 as u32;
+                        return Ok(None);
+                    }
+                };
+                written.map_err(FaultKind::Io)?;
+                self.registers[V0] = a2;
+            }
+            17 => return Ok(Some(a0 as i32)),
+            code => return Err(FaultKind::UnknownSyscall(code)),
+        }
+        Ok(None)
+    }
+}
+
+fn alu(op: AluOp, a: u32, b: u32) -> Result<u32, FaultKind> {
+    let (x, y) = (a as i32, b as i32);
+    Ok(match op {
+        AluOp::Add => x.checked_add(y).ok_or(FaultKind::Overflow)? as u32,
+        AluOp::Addu => a.wrapping_add(b),
+        AluOp::Sub => x.checked_sub(y).ok_or(FaultKind::Overflow)? as u32,
+        AluOp::Subu => a.wrapping_sub(b),
+        AluOp::Mul => x.wrapping_mul(y) as u32,
+        AluOp::Div | AluOp::Rem if y == 0 => {
+            return Err(FaultKind::DivisionByZero)
+        }
+        AluOp::Div => x.wrapping_div(y) as u32,
+        AluOp::Rem => x.wrapping_rem(y) as u32,
+        AluOp::And => a & b,
+        AluOp::Or => a | b,
+        AluOp::Xor => a ^ b,
+        AluOp::Nor => !(a | b),
+        AluOp::Slt => (x < y) as u32,
+        AluOp::Sltu => (a < b) as u32,
+        AluOp::Sll => a << (b & 31),
+        AluOp::Srl => a >> (b & 31),
+        AluOp::Sra => (x >> (b & 31)) as u32,
+    })
+}
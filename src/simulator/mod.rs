@@ -0,0 +1,187 @@
+//! A simulator for the subset of MIPS32 assembly emitted by the code
+//! generator, so that compiled programs run without SPIM.
+//!
+//! Assembly is assembled into an [`Image`] of decoded instructions and an
+//! initialised data segment, laid out at the addresses SPIM uses. Pseudo
+//! instructions such as `la`, `blt` or three operand `div` are kept as single
+//! instructions. The [`Simulator`] implements the SPIM system calls used by
+//! the bundled runtime, [`RUNTIME`], which provides the interface of the
+//! course's `trap.handler`: the basic class methods, `equality_test`, the
+//! abort routines and the collector entry points. The bundled runtime never
+//! collects garbage, so both collectors are accepted.
+
+mod assembler;
+mod machine;
+
+pub use self::assembler::assemble;
+pub use self::machine::Simulator;
+use std::fmt::{Display, Formatter};
+
+#[cfg(test)]
+mod tests;
+
+/// The runtime system linked with generated programs.
+pub const RUNTIME: &str = include_str!("runtime.s");
+
+pub const TEXT_BASE: u32 = 0x0040_0000;
+pub const DATA_BASE: u32 = 0x1001_0000;
+/// The stack grows down from here.
+pub const STACK_TOP: u32 = 0x8000_0000;
+pub const STACK_SIZE: u32 = 64 << 20;
+/// The most the heap may grow to with sbrk.
+pub const HEAP_LIMIT: u32 = 1 << 30;
+
+pub type Register = u8;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operand {
+    Register(Register),
+    Immediate(i32),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AluOp {
+    /// Signed addition, which traps on overflow.
+    Add,
+    Addu,
+    Sub,
+    Subu,
+    Mul,
+    /// Signed division, which traps on a zero divisor.
+    Div,
+    Rem,
+    And,
+    Or,
+    Xor,
+    Nor,
+    Slt,
+    Sltu,
+    Sll,
+    Srl,
+    Sra,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Condition {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    LtUnsigned,
+    LeUnsigned,
+    GtUnsigned,
+    GeUnsigned,
+}
+
+/// Instructions are numbered from the start of the text segment, and each
+/// takes a word.
+pub type Target = usize;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Instruction {
+    /// `rd <- rs op operand`.
+    Alu(AluOp, Register, Register, Operand),
+    /// Load a constant or an address.
+    Li(Register, u32),
+    /// Load a word, a signed byte or an unsigned byte from `offset(base)`.
+    Lw(Register, i32, Register),
+    Lb(Register, i32, Register),
+    Lbu(Register, i32, Register),
+    Sw(Register, i32, Register),
+    Sb(Register, i32, Register),
+    Branch(Condition, Register, Operand, Target),
+    Jump(Target),
+    Jal(Target),
+    Jr(Register),
+    /// Jump to the address in a register, linking in another.
+    Jalr(Register, Register),
+    Syscall,
+    Break,
+    Nop,
+}
+
+/// Assembled code and data, ready to run.
+#[derive(Debug)]
+pub struct Image {
+    pub text: Vec<Instruction>,
+    /// Initial contents of the data segment, from `DATA_BASE`.
+    pub data: Vec<u8>,
+    /// The index of the instruction labelled `__start`.
+    pub entry: Target,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct AssemblyError {
+    pub source: String,
+    pub line: usize,
+    pub message: String,
+}
+
+impl Display for AssemblyError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}: {}", self.source, self.line, self.message)
+    }
+}
+
+#[derive(Debug)]
+pub enum FaultKind {
+    /// A load or store outside of memory or not aligned.
+    BadAddress(u32),
+    BadJump(u32),
+    Overflow,
+    DivisionByZero,
+    StackOverflow,
+    OutOfMemory,
+    UnknownSyscall(u32),
+    Io(std::io::Error),
+}
+
+impl FaultKind {
+    /// Exit statuses follow those of the interpreter where they match, and
+    /// use 19 for faults the interpreter cannot have.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Self::DivisionByZero => 9,
+            Self::Io(_) => 12,
+            _ => 19,
+        }
+    }
+}
+
+impl Display for FaultKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::BadAddress(address) => {
+                write!(f, "Bad address 0x{address:08x}")
+            }
+            Self::BadJump(address) => write!(f, "Bad jump to 0x{address:08x}"),
+            Self::Overflow => write!(f, "Arithmetic overflow"),
+            Self::DivisionByZero => write!(f, "Division by zero"),
+            Self::StackOverflow => write!(f, "Stack overflow"),
+            Self::OutOfMemory => write!(f, "Out of memory"),
+            Self::UnknownSyscall(code) => write!(f, "Unknown syscall {code}"),
+            Self::Io(err) => write!(f, "I/O error: {err}"),
+        }
+    }
+}
+
+/// An exception raised by the instruction at `address`.
+#[derive(Debug)]
+pub struct Fault {
+    pub kind: FaultKind,
+    pub address: u32,
+}
+
+impl Fault {
+    pub fn exit_code(&self) -> i32 {
+        self.kind.exit_code()
+    }
+}
+
+impl Display for Fault {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Exception at 0x{:08x}: {}.", self.address, self.kind)
+    }
+}
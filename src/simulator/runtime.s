@@ -0,0 +1,447 @@
+# The runtime system for Cool programs compiled to MIPS, with the interface
+# of the course's trap.handler. Objects are allocated with sbrk and never
+# collected, so the collector entry points do nothing.
+#
+# Methods take self in $a0 and pop their arguments, and may change the
+# temporary registers and $a1 to $a3. Errors are written to standard error
+# and exit with the status the interpreter uses for them.
+
+	.data
+	.align	2
+_abort_msg:
+	.asciiz	"Abort called from class "
+_dispatch_msg:
+	.asciiz	": Dispatch to void calling method "
+_static_dispatch_msg:
+	.asciiz	": Static dispatch to void calling method "
+_case_void_msg:
+	.asciiz	": Match on void in case statement.\n"
+_case_msg:
+	.asciiz	"No match in case statement for Class "
+_substr_msg:
+	.asciiz	"Index out of range in substr.\n"
+_colon:
+	.asciiz	":"
+_period:
+	.asciiz	".\n"
+_newline:
+	.asciiz	"\n"
+	.align	2
+_digits:
+	.space	12
+_in_buffer:
+	.space	1025
+
+	.text
+	.globl	__start
+__start:
+	la	$t0 _MemMgr_INITIALIZER
+	lw	$t0 0($t0)
+	jalr	$t0
+	la	$a0 Main_protObj
+	jal	Object.copy
+	jal	Main_init
+	jal	Main.main
+	li	$v0 10
+	syscall
+
+# Memory management. The generated code may select either collector.
+	.globl	_NoGC_Init
+	.globl	_NoGC_Collect
+	.globl	_GenGC_Init
+	.globl	_GenGC_Collect
+	.globl	_GenGC_Assign
+	.globl	_gc_check
+_NoGC_Init:
+_NoGC_Collect:
+_GenGC_Init:
+_GenGC_Collect:
+_GenGC_Assign:
+_gc_check:
+	jr	$ra
+
+# Allocate $a0 bytes preceded by the -1 eye catcher, returning the address
+# after it in $v0.
+_alloc:
+	addiu	$a0 $a0 4
+	li	$v0 9
+	syscall
+	li	$a0 -1
+	sw	$a0 0($v0)
+	addiu	$v0 $v0 4
+	jr	$ra
+
+# Copy $a3 bytes from $a1 to $a2, leaving $a2 after the last byte copied.
+_copy_bytes:
+	beqz	$a3 _copy_bytes_done
+	lbu	$t0 0($a1)
+	sb	$t0 0($a2)
+	addiu	$a1 $a1 1
+	addiu	$a2 $a2 1
+	addiu	$a3 $a3 -1
+	b	_copy_bytes
+_copy_bytes_done:
+	jr	$ra
+
+# Allocate a String of $a0 characters, with its length and terminator set.
+_alloc_string:
+	addiu	$sp $sp -12
+	sw	$ra 12($sp)
+	sw	$a0 8($sp)
+	la	$a0 Int_protObj
+	jal	Object.copy
+	lw	$t0 8($sp)
+	sw	$t0 12($a0)
+	sw	$a0 4($sp)
+	addiu	$t1 $t0 4
+	srl	$t1 $t1 2
+	addiu	$t1 $t1 4
+	sll	$a0 $t1 2
+	jal	_alloc
+	la	$t2 _string_tag
+	lw	$t2 0($t2)
+	sw	$t2 0($v0)
+	sw	$t1 4($v0)
+	la	$t2 String_dispTab
+	sw	$t2 8($v0)
+	lw	$t2 4($sp)
+	sw	$t2 12($v0)
+	addu	$t2 $v0 $t0
+	sb	$zero 16($t2)
+	move	$a0 $v0
+	lw	$ra 12($sp)
+	addiu	$sp $sp 12
+	jr	$ra
+
+# Write the null-terminated string at $a0 to standard error.
+_eprint:
+	move	$a1 $a0
+	move	$a2 $zero
+_eprint_length:
+	addu	$t0 $a1 $a2
+	lbu	$t0 0($t0)
+	beqz	$t0 _eprint_write
+	addiu	$a2 $a2 1
+	b	_eprint_length
+_eprint_write:
+	li	$a0 2
+	li	$v0 15
+	syscall
+	jr	$ra
+
+# Write the non-negative integer in $a0 to standard error.
+_eprint_int:
+	la	$t0 _digits
+	addiu	$t0 $t0 11
+	sb	$zero 0($t0)
+	li	$t2 10
+_eprint_digit:
+	addiu	$t0 $t0 -1
+	rem	$t3 $a0 $t2
+	addiu	$t3 $t3 48
+	sb	$t3 0($t0)
+	div	$a0 $a0 $t2
+	bnez	$a0 _eprint_digit
+	move	$a0 $t0
+	b	_eprint
+
+# Write the location of an error, from a file name String in $a0 and a
+# line in $t1, then the message in $a2.
+_eprint_location:
+	move	$s1 $t1
+	move	$s2 $a2
+	move	$s3 $ra
+	addiu	$a0 $a0 16
+	jal	_eprint
+	la	$a0 _colon
+	jal	_eprint
+	move	$a0 $s1
+	jal	_eprint_int
+	move	$a0 $s2
+	jal	_eprint
+	jr	$s3
+
+# Write the name of the class of the object in $a0 to standard error.
+_eprint_class:
+	lw	$t0 0($a0)
+	sll	$t0 $t0 2
+	la	$t1 class_nameTab
+	addu	$t1 $t1 $t0
+	lw	$a0 0($t1)
+	addiu	$a0 $a0 16
+	b	_eprint
+
+# Besides the location, takes the name of the method as a String in $t2,
+# and in $t3 whether the dispatch is static.
+	.globl	_dispatch_abort
+_dispatch_abort:
+	move	$s4 $t2
+	li	$s5 5
+	la	$a2 _dispatch_msg
+	beqz	$t3 _dispatch_abort_report
+	li	$s5 6
+	la	$a2 _static_dispatch_msg
+_dispatch_abort_report:
+	jal	_eprint_location
+	addiu	$a0 $s4 16
+	jal	_eprint
+	la	$a0 _period
+	jal	_eprint
+	move	$a0 $s5
+	li	$v0 17
+	syscall
+
+	.globl	_case_abort2
+_case_abort2:
+	la	$a2 _case_void_msg
+	jal	_eprint_location
+	li	$a0 7
+	li	$v0 17
+	syscall
+
+	.globl	_case_abort
+_case_abort:
+	move	$s1 $a0
+	la	$a0 _case_msg
+	jal	_eprint
+	move	$a0 $s1
+	jal	_eprint_class
+	la	$a0 _period
+	jal	_eprint
+	li	$a0 8
+	li	$v0 17
+	syscall
+
+# Leave $a0 if the objects in $t1 and $t2 are equal Ints, Bools or Strings,
+# and $a1 otherwise.
+	.globl	equality_test
+equality_test:
+	beqz	$t1 _equality_false
+	beqz	$t2 _equality_false
+	lw	$t3 0($t1)
+	lw	$t4 0($t2)
+	bne	$t3 $t4 _equality_false
+	la	$t5 _int_tag
+	lw	$t5 0($t5)
+	beq	$t3 $t5 _equality_value
+	la	$t5 _bool_tag
+	lw	$t5 0($t5)
+	beq	$t3 $t5 _equality_value
+	la	$t5 _string_tag
+	lw	$t5 0($t5)
+	bne	$t3 $t5 _equality_false
+	lw	$t3 12($t1)
+	lw	$t3 12($t3)
+	lw	$t4 12($t2)
+	lw	$t4 12($t4)
+	bne	$t3 $t4 _equality_false
+	addiu	$t1 $t1 16
+	addiu	$t2 $t2 16
+_equality_chars:
+	beqz	$t3 _equality_true
+	lbu	$t4 0($t1)
+	lbu	$t5 0($t2)
+	bne	$t4 $t5 _equality_false
+	addiu	$t1 $t1 1
+	addiu	$t2 $t2 1
+	addiu	$t3 $t3 -1
+	b	_equality_chars
+_equality_value:
+	lw	$t3 12($t1)
+	lw	$t4 12($t2)
+	bne	$t3 $t4 _equality_false
+_equality_true:
+	jr	$ra
+_equality_false:
+	move	$a0 $a1
+	jr	$ra
+
+	.globl	Object.abort
+Object.abort:
+	move	$s1 $a0
+	la	$a0 _abort_msg
+	jal	_eprint
+	move	$a0 $s1
+	jal	_eprint_class
+	la	$a0 _newline
+	jal	_eprint
+	li	$a0 11
+	li	$v0 17
+	syscall
+
+	.globl	Object.type_name
+Object.type_name:
+	lw	$t0 0($a0)
+	sll	$t0 $t0 2
+	la	$t1 class_nameTab
+	addu	$t1 $t1 $t0
+	lw	$a0 0($t1)
+	jr	$ra
+
+	.globl	Object.copy
+Object.copy:
+	addiu	$sp $sp -8
+	sw	$ra 8($sp)
+	sw	$a0 4($sp)
+	lw	$a0 4($a0)
+	sll	$a0 $a0 2
+	jal	_alloc
+	lw	$a1 4($sp)
+	move	$a2 $v0
+	lw	$a3 4($a1)
+	sll	$a3 $a3 2
+	jal	_copy_bytes
+	move	$a0 $v0
+	lw	$ra 8($sp)
+	addiu	$sp $sp 8
+	jr	$ra
+
+	.globl	IO.out_string
+IO.out_string:
+	move	$t0 $a0
+	lw	$a0 4($sp)
+	addiu	$a0 $a0 16
+	li	$v0 4
+	syscall
+	move	$a0 $t0
+	addiu	$sp $sp 4
+	jr	$ra
+
+	.globl	IO.out_int
+IO.out_int:
+	move	$t0 $a0
+	lw	$a0 4($sp)
+	lw	$a0 12($a0)
+	li	$v0 1
+	syscall
+	move	$a0 $t0
+	addiu	$sp $sp 4
+	jr	$ra
+
+	.globl	IO.in_int
+IO.in_int:
+	addiu	$sp $sp -4
+	sw	$ra 4($sp)
+	la	$a0 Int_protObj
+	jal	Object.copy
+	move	$t0 $a0
+	li	$v0 5
+	syscall
+	sw	$v0 12($t0)
+	move	$a0 $t0
+	lw	$ra 4($sp)
+	addiu	$sp $sp 4
+	jr	$ra
+
+# Read a line, without its terminator.
+	.globl	IO.in_string
+IO.in_string:
+	addiu	$sp $sp -4
+	sw	$ra 4($sp)
+	la	$a0 _in_buffer
+	li	$a1 1025
+	li	$v0 8
+	syscall
+	la	$a1 _in_buffer
+	move	$a3 $zero
+_in_string_length:
+	addu	$t0 $a1 $a3
+	lbu	$t0 0($t0)
+	beqz	$t0 _in_string_copy
+	li	$t1 10
+	beq	$t0 $t1 _in_string_copy
+	addiu	$a3 $a3 1
+	b	_in_string_length
+_in_string_copy:
+	# Drop the carriage return of a CRLF terminator.
+	beqz	$t0 _in_string_alloc
+	beqz	$a3 _in_string_alloc
+	addu	$t0 $a1 $a3
+	lbu	$t0 -1($t0)
+	li	$t1 13
+	bne	$t0 $t1 _in_string_alloc
+	addiu	$a3 $a3 -1
+_in_string_alloc:
+	move	$a0 $a3
+	jal	_alloc_string
+	la	$a1 _in_buffer
+	addiu	$a2 $a0 16
+	lw	$a3 12($a0)
+	lw	$a3 12($a3)
+	jal	_copy_bytes
+	lw	$ra 4($sp)
+	addiu	$sp $sp 4
+	jr	$ra
+
+	.globl	String.length
+String.length:
+	lw	$a0 12($a0)
+	jr	$ra
+
+	.globl	String.concat
+String.concat:
+	addiu	$sp $sp -12
+	sw	$ra 12($sp)
+	sw	$a0 8($sp)
+	lw	$t0 12($a0)
+	lw	$t0 12($t0)
+	lw	$t1 16($sp)
+	lw	$t1 12($t1)
+	lw	$t1 12($t1)
+	addu	$a0 $t0 $t1
+	jal	_alloc_string
+	sw	$a0 4($sp)
+	addiu	$a2 $a0 16
+	lw	$a1 8($sp)
+	lw	$a3 12($a1)
+	lw	$a3 12($a3)
+	addiu	$a1 $a1 16
+	jal	_copy_bytes
+	lw	$a1 16($sp)
+	lw	$a3 12($a1)
+	lw	$a3 12($a3)
+	addiu	$a1 $a1 16
+	jal	_copy_bytes
+	lw	$a0 4($sp)
+	lw	$ra 12($sp)
+	addiu	$sp $sp 16
+	jr	$ra
+
+# The start is pushed before the length.
+	.globl	String.substr
+String.substr:
+	addiu	$sp $sp -12
+	sw	$ra 12($sp)
+	sw	$a0 8($sp)
+	lw	$t0 20($sp)
+	lw	$t0 12($t0)
+	lw	$t1 16($sp)
+	lw	$t1 12($t1)
+	lw	$t2 12($a0)
+	lw	$t2 12($t2)
+	bltz	$t0 _substr_abort
+	bltz	$t1 _substr_abort
+	addu	$t3 $t0 $t1
+	bgtu	$t3 $t2 _substr_abort
+	move	$a0 $t1
+	jal	_alloc_string
+	sw	$a0 4($sp)
+	addiu	$a2 $a0 16
+	lw	$a1 8($sp)
+	lw	$t0 20($sp)
+	lw	$t0 12($t0)
+	addu	$a1 $a1 $t0
+	addiu	$a1 $a1 16
+	lw	$a3 16($sp)
+	lw	$a3 12($a3)
+	jal	_copy_bytes
+	lw	$a0 4($sp)
+	lw	$ra 12($sp)
+	addiu	$sp $sp 20
+	jr	$ra
+_substr_abort:
+	la	$a0 _substr_msg
+	jal	_eprint
+	li	$a0 10
+	li	$v0 17
+	syscall
@@ -0,0 +1,159 @@
+use super::*;
+use crate::codegen::{emit_mips, Collector};
+use crate::lexer::lex_tokens;
+use crate::parser::parse_program;
+use crate::semant::check_program;
+
+// Runs assembly with the given input, returning the exit status or fault,
+// the output and the errors written.
+fn simulate(
+    sources: &[(&str, &str)],
+    input: &str,
+) -> (Result<i32, String>, String, String) {
+    let image = assemble(sources).unwrap();
+    let (mut output, mut errors) = (Vec::new(), Vec::new());
+    let status = Simulator::new(
+        &image,
+        Box::new(input.as_bytes()),
+        Box::new(&mut output),
+        Box::new(&mut errors),
+    )
+    .run()
+    .map_err(|fault| fault.to_string());
+    (
+        status,
+        String::from_utf8(output).unwrap(),
+        String::from_utf8(errors).unwrap(),
+    )
+}
+
+fn run_cool(
+    source: &str,
+    input: &str,
+) -> (Result<i32, String>, String, String) {
+    let (_, tokens) = lex_tokens(source, "test.cl").unwrap();
+    let (_, mut program) = parse_program(&tokens).unwrap();
+    let hierarchy = check_program(&mut program).unwrap();
+    let assembly = emit_mips(&program, &hierarchy, Collector::None);
+    simulate(&[("runtime.s", RUNTIME), ("test.s", &assembly)], input)
+}
+
+#[test]
+fn test_syscalls() {
+    let source = "
+        .data
+    prompt: .asciiz \"n? \"
+        .text
+    __start:
+        la $a0, prompt
+        li $v0, 4
+        syscall
+        li $v0, 5
+        syscall
+        mul $a0, $v0, $v0
+        li $v0, 1
+        syscall
+        li $a0, 0x2a
+        li $v0, 17
+        syscall
+    ";
+    let (status, output, _) = simulate(&[("test.s", source)], " -12\n");
+    assert_eq!(status, Ok(42));
+    assert_eq!(output, "n? 144");
+}
+
+#[test]
+fn test_sbrk_and_memory() {
+    let source = "
+    __start:
+        li $a0 6
+        li $v0 9
+        syscall
+        move $t0 $v0
+        li $a0 4
+        li $v0 9
+        syscall
+        subu $a0 $v0 $t0
+        li $v0 1
+        syscall
+        lw $t1 2($t0)
+    ";
+    let (status, output, _) = simulate(&[("test.s", source)], "");
+    assert_eq!(output, "8");
+    assert_eq!(
+        status,
+        Err("Exception at 0x00400028: Bad address 0x10010002.".into())
+    );
+}
+
+#[test]
+fn test_assembly_errors() {
+    let error = |source| assemble(&[("test.s", source)]).unwrap_err();
+    assert_eq!(
+        error("__start:\n  jal nowhere\n").to_string(),
+        "test.s:2: Undefined label nowhere"
+    );
+    assert_eq!(
+        error("__start: lw $t9 0($t10)").message,
+        "Invalid memory operand 0($t10)"
+    );
+    assert_eq!(error("a:\na: nop").message, "Label a is defined twice");
+    assert_eq!(error("nop").message, "No __start label in the text segment");
+}
+
+#[test]
+fn test_program() {
+    let source = "class Main inherits IO { main() : Object {{
+        out_string(in_string().concat(\"!\\n\"));
+        out_int(in_int() / 3);
+        out_string(\"hello\".substr(1, 3));
+        if \"ab\" = \"a\".concat(\"b\") then out_int(1) else out_int(0) fi;
+        case 3 of
+            s : String => 0;
+            o : Object => out_string(o.type_name());
+        esac;
+    }}; };";
+    let (status, output, _) = run_cool(source, "hi\r\n 22\n");
+    assert_eq!(status, Ok(0));
+    assert_eq!(output, "hi!\n7ell1Int");
+}
+
+#[test]
+fn test_runtime_errors() {
+    let main = |body: &str| {
+        format!(
+            "class A {{ f() : Int {{ 0 }}; }};
+             class Main inherits IO {{ a : A; main() : Object {{ {body} }}; }};"
+        )
+    };
+    let (status, output, errors) =
+        run_cool(&main("{ out_int(1); a.f(); }"), "");
+    assert_eq!(
+        (status, output, errors),
+        (
+            Ok(5),
+            "1".into(),
+            "test.cl:2: Dispatch to void calling method f.\n".into()
+        )
+    );
+    let (status, _, errors) = run_cool(&main("a@A.f()"), "");
+    assert_eq!(status, Ok(6));
+    assert_eq!(
+        errors,
+        "test.cl:2: Static dispatch to void calling method f.\n"
+    );
+    let (status, _, errors) = run_cool(&main("case a of x : A => 0; esac"), "");
+    assert_eq!(status, Ok(7));
+    assert_eq!(errors, "test.cl:2: Match on void in case statement.\n");
+    let (status, _, errors) = run_cool(&main("case 0 of x : A => 0; esac"), "");
+    assert_eq!(status, Ok(8));
+    assert_eq!(errors, "No match in case statement for Class Int.\n");
+    let (status, _, errors) = run_cool(&main("abort()"), "");
+    assert_eq!(status, Ok(11));
+    assert_eq!(errors, "Abort called from class Main\n");
+    let (status, _, errors) = run_cool(&main("\"abc\".substr(2, 2)"), "");
+    assert_eq!(status, Ok(10));
+    assert_eq!(errors, "Index out of range in substr.\n");
+    let (status, _, _) = run_cool(&main("1 / 0"), "");
+    assert!(status.unwrap_err().ends_with("Division by zero."));
+}
@@ -0,0 +1,147 @@
+//! A tracer for the tree-walking interpreter, which prints the dispatches,
+//! returns and attribute assignments of a running program:
+//!
+//! ```text
+//! call Main.main()
+//!   call A.set(3)
+//!     set A.n <- 3
+//!   return <A object>
+//!   call Main.out_int(3) defined in IO
+//!   return <Main object>
+//! return <Main object>
+//! ```
+//!
+//! Lines are indented by call depth. Filters limit the trace to some classes
+//! or methods.
+
+use crate::interpreter::*;
+use std::io::Write;
+
+#[cfg(test)]
+mod tests;
+
+/// The classes and methods to trace. Empty lists trace everything.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TraceFilter {
+    /// Classes of receivers, or classes that define the dispatched methods.
+    pub classes: Vec<String>,
+    pub methods: Vec<String>,
+}
+
+impl TraceFilter {
+    fn matches_class(&self, classes: &[&str]) -> bool {
+        self.classes.is_empty()
+            || classes
+                .iter()
+                .any(|class| self.classes.iter().any(|c| c == class))
+    }
+
+    fn matches_method(&self, method: &str) -> bool {
+        self.methods.is_empty() || self.methods.iter().any(|m| m == method)
+    }
+}
+
+pub struct Tracer<'a> {
+    output: Box<dyn Write + 'a>,
+    filter: TraceFilter,
+    /// Whether each active dispatch is traced, the innermost last.
+    calls: Vec<bool>,
+}
+
+impl<'a> Tracer<'a> {
+    pub fn new(output: Box<dyn Write + 'a>, filter: TraceFilter) -> Self {
+        Self {
+            output,
+            filter,
+            calls: Vec::new(),
+        }
+    }
+
+    fn print<'p>(
+        &mut self,
+        depth: usize,
+        line: std::fmt::Arguments,
+    ) -> Result<(), RuntimeError<'p>> {
+        writeln!(self.output, "{:indent$}{line}", "", indent = 2 * depth)?;
+        Ok(())
+    }
+}
+
+fn class_name<'i>(interpreter: &'i Interpreter, value: &Value) -> &'i str {
+    match interpreter.class_of(value) {
+        Some(class_id) => &interpreter.class(class_id).name,
+        None => "void",
+    }
+}
+
+impl<'p> Observer<'p> for Tracer<'_> {
+    fn call(
+        &mut self,
+        interpreter: &Interpreter<'p>,
+        call: &Call,
+    ) -> Result<(), RuntimeError<'p>> {
+        let class = class_name(interpreter, call.receiver);
+        let owner = &interpreter.class(call.owner).name;
+        let traced = self.filter.matches_class(&[class, owner])
+            && self.filter.matches_method(call.method);
+        let depth = self.calls.len();
+        self.calls.push(traced);
+        if !traced {
+            return Ok(());
+        }
+        let args: Vec<String> = call
+            .args
+            .iter()
+            .map(|arg| interpreter.describe(arg))
+            .collect();
+        let args = args.join(", ");
+        if class == owner {
+            self.print(
+                depth,
+                format_args!("call {class}.{}({args})", call.method),
+            )
+        } else {
+            self.print(
+                depth,
+                format_args!(
+                    "call {class}.{}({args}) defined in {owner}",
+                    call.method
+                ),
+            )
+        }
+    }
+
+    fn returned(
+        &mut self,
+        interpreter: &Interpreter<'p>,
+        value: &Value,
+    ) -> Result<(), RuntimeError<'p>> {
+        let traced = self.calls.pop().unwrap_or_default();
+        if traced {
+            let value = interpreter.describe(value);
+            self.print(self.calls.len(), format_args!("return {value}"))?;
+        }
+        Ok(())
+    }
+
+    fn assigned(
+        &mut self,
+        interpreter: &Interpreter<'p>,
+        attribute: &'p str,
+        value: &Value,
+    ) -> Result<(), RuntimeError<'p>> {
+        let frame = interpreter.frames().last().expect("no active frame");
+        let class = class_name(interpreter, &frame.self_value);
+        let owner = &interpreter.class(frame.class).name;
+        if self.filter.matches_class(&[class, owner])
+            && self.filter.matches_method(frame.method)
+        {
+            let value = interpreter.describe(value);
+            self.print(
+                self.calls.len(),
+                format_args!("set {class}.{attribute} <- {value}"),
+            )?;
+        }
+        Ok(())
+    }
+}
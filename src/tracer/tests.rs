@@ -0,0 +1,82 @@
+use super::*;
+use crate::lexer::lex_tokens;
+use crate::parser::parse_program;
+use crate::semant::check_program;
+
+const SOURCE: &str = "\
+class A {
+  n : Int;
+  set(x : Int) : SELF_TYPE {{ n <- x; self; }};
+  get() : Int { n };
+};
+class B inherits A {};
+class Main inherits IO {
+  main() : Object {
+    out_int((new B).set(3).get())
+  };
+};
+";
+
+// Runs SOURCE under the tracer and returns the trace.
+fn trace(classes: &[&str], methods: &[&str]) -> String {
+    let (_, tokens) = lex_tokens(SOURCE, "test.cl").unwrap();
+    let (_, mut program) = parse_program(&tokens).unwrap();
+    let hierarchy = check_program(&mut program).unwrap();
+    let filter = TraceFilter {
+        classes: classes.iter().map(|class| class.to_string()).collect(),
+        methods: methods.iter().map(|method| method.to_string()).collect(),
+    };
+    let mut output = Vec::new();
+    let mut interpreter = Interpreter::new(
+        &program,
+        &hierarchy,
+        Box::new("".as_bytes()),
+        Box::new(Vec::new()),
+    );
+    interpreter.set_observer(Box::new(Tracer::new(
+        Box::new(&mut output),
+        filter,
+    )));
+    interpreter.run().unwrap();
+    drop(interpreter);
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn test_trace() {
+    assert_eq!(
+        trace(&[], &[]),
+        "call Main.main()\n\
+         \x20 call B.set(3) defined in A\n\
+         \x20   set B.n <- 3\n\
+         \x20 return <B object>\n\
+         \x20 call B.get() defined in A\n\
+         \x20 return 3\n\
+         \x20 call Main.out_int(3) defined in IO\n\
+         \x20 return <Main object>\n\
+         return <Main object>\n"
+    );
+}
+
+#[test]
+fn test_filters() {
+    assert_eq!(
+        trace(&["B"], &[]),
+        "  call B.set(3) defined in A\n\
+         \x20   set B.n <- 3\n\
+         \x20 return <B object>\n\
+         \x20 call B.get() defined in A\n\
+         \x20 return 3\n"
+    );
+    assert_eq!(
+        trace(&["IO", "Main"], &["out_int"]),
+        "  call Main.out_int(3) defined in IO\n\
+         \x20 return <Main object>\n"
+    );
+    assert_eq!(
+        trace(&[], &["set"]),
+        "  call B.set(3) defined in A\n\
+         \x20   set B.n <- 3\n\
+         \x20 return <B object>\n"
+    );
+}
@@ -0,0 +1,27 @@
+mod common;
+
+use common::{check_examples, with_checked};
+use coolc::bytecode::{compile, Machine};
+
+// The expected outputs are those of the tree-walking interpreter, which the
+// virtual machine must reproduce.
+fn run(source_code: &str, filename: &str, input: &str) -> String {
+    with_checked(source_code, filename, |program, hierarchy| {
+        let module = compile(program, hierarchy);
+        let mut output = Vec::new();
+        let _ = Machine::new(
+            &module,
+            Box::new(input.as_bytes()),
+            Box::new(&mut output),
+        )
+        .run();
+        String::from_utf8(output).unwrap()
+    })
+}
+
+#[test]
+fn test_files() {
+    check_examples(|example| {
+        run(&example.source_code, example.name(), &example.input)
+    });
+}
@@ -0,0 +1,37 @@
+mod common;
+
+use common::{available, check_examples, run_command, temp_path, with_checked};
+use coolc::codegen::emit_c;
+use std::fs::{remove_file, write};
+use std::process::Command;
+
+// Translates a program to C, compiles and runs it, returning its output.
+fn run(source_code: &str, filename: &str, input: &str) -> String {
+    let code = with_checked(source_code, filename, emit_c);
+    let executable = temp_path("c", filename);
+    let c_file = executable.with_extension("c");
+    write(&c_file, code).unwrap();
+    let status = Command::new("cc")
+        .args(["-std=c99", "-pedantic", "-Werror", "-o"])
+        .arg(&executable)
+        .arg(&c_file)
+        .status()
+        .unwrap();
+    remove_file(&c_file).unwrap();
+    assert!(status.success(), "cc failed for {filename}");
+
+    let (output, _, _) = run_command(&mut Command::new(&executable), input);
+    remove_file(&executable).unwrap();
+    output
+}
+
+#[test]
+fn test_files() {
+    if !available("cc") {
+        eprintln!("Skipping C tests: no C compiler.");
+        return;
+    }
+    check_examples(|example| {
+        run(&example.source_code, example.name(), &example.input)
+    });
+}
@@ -0,0 +1,142 @@
+//! The harness shared by the tests that compile and run Cool programs: the
+//! examples with their input and expected output, checking a program before
+//! handing it to a backend, and running a program as a process.
+
+// Each test crate uses only part of the harness.
+#![allow(dead_code)]
+
+pub use coolc::interpreter::STACK_SIZE;
+
+use coolc::hierarchy::ClassHierarchy;
+use coolc::lexer::lex_tokens;
+use coolc::parser::parse_program;
+use coolc::ptree::Program;
+use coolc::semant::check_program;
+use std::fs::{read_dir, read_to_string};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+
+/// An example program in `tests/resources`, with the input it reads and the
+/// output it is expected to write.
+pub struct Example {
+    pub source_filename: PathBuf,
+    pub source_code: String,
+    pub input: String,
+    pub expected: String,
+}
+
+impl Example {
+    /// The file name of the source, which locations in it refer to.
+    pub fn name(&self) -> &str {
+        self.source_filename.file_name().unwrap().to_str().unwrap()
+    }
+}
+
+/// The examples with expected output, by file name.
+pub fn examples() -> Vec<Example> {
+    let mut dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    dir.push("tests/resources");
+    let mut filenames: Vec<PathBuf> = read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|filename| filename.extension().unwrap() == "out")
+        .collect();
+    filenames.sort();
+    filenames
+        .into_iter()
+        .map(|filename| Example {
+            source_filename: filename.with_extension("cool"),
+            source_code: read_to_string(filename.with_extension("cool"))
+                .unwrap(),
+            input: read_to_string(filename.with_extension("in"))
+                .unwrap_or_default(),
+            expected: read_to_string(&filename).unwrap(),
+        })
+        .collect()
+}
+
+/// Run every example and compare its output with that expected.
+pub fn check_examples(mut run: impl FnMut(&Example) -> String) {
+    for example in examples() {
+        let produced = run(&example);
+        assert_eq!(
+            example.expected,
+            produced,
+            "Output mismatch, source: {}",
+            example.source_filename.display()
+        );
+    }
+}
+
+/// Lex, parse and check a program, and pass its parse tree and class
+/// hierarchy to `f`, since both borrow from the source.
+pub fn with_checked<T>(
+    source_code: &str,
+    filename: &str,
+    f: impl FnOnce(&Program, &ClassHierarchy) -> T,
+) -> T {
+    let (_, tokens) = lex_tokens(source_code, filename).unwrap();
+    let (_, mut program) = parse_program(&tokens).unwrap();
+    let hierarchy = check_program(&mut program).unwrap();
+    f(&program, &hierarchy)
+}
+
+/// A program whose `main` evaluates `body` on line 4, next to a class `A`
+/// with a method `f`, for tests of runtime errors.
+pub fn error_program(body: &str) -> String {
+    format!(
+        "class A {{ f() : Int {{ 0 }}; }};\n\
+         class Main {{\n  main() : Object {{\n    {body}\n  }};\n}};\n"
+    )
+}
+
+/// A path in the temporary directory for a file a test generates from a
+/// source file, which is distinct for each test process and backend.
+pub fn temp_path(backend: &str, filename: &str) -> PathBuf {
+    let stem = Path::new(filename).file_stem().unwrap().to_str().unwrap();
+    std::env::temp_dir()
+        .join(format!("coolc-{backend}-{}-{stem}", std::process::id()))
+}
+
+/// Run a command with input on its standard input, returning its output,
+/// error output and exit status.
+pub fn run_command(
+    command: &mut Command,
+    input: &str,
+) -> (String, String, i32) {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // Programs may exit before reading all of their input.
+    let _ = child.stdin.take().unwrap().write_all(input.as_bytes());
+    let output = child.wait_with_output().unwrap();
+    (
+        String::from_utf8(output.stdout).unwrap(),
+        String::from_utf8(output.stderr).unwrap(),
+        output.status.code().unwrap(),
+    )
+}
+
+/// Whether a tool can be run, for tests that skip backends whose tools are
+/// not installed.
+pub fn available(tool: &str) -> bool {
+    Command::new(tool).arg("--version").output().is_ok()
+}
+
+/// Call `f` on a thread with the stack `coolc` runs programs with, since
+/// the examples recurse deeper than the default test thread stack allows.
+pub fn on_large_stack<T: Send>(f: impl FnOnce() -> T + Send) -> T {
+    thread::scope(|scope| {
+        thread::Builder::new()
+            .stack_size(STACK_SIZE)
+            .spawn_scoped(scope, f)
+            .unwrap()
+            .join()
+            .unwrap()
+    })
+}
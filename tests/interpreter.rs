@@ -0,0 +1,29 @@
+mod common;
+
+use common::{check_examples, on_large_stack, with_checked};
+use coolc::interpreter::Interpreter;
+
+fn run(source_code: &str, filename: &str, input: &str) -> String {
+    with_checked(source_code, filename, |program, hierarchy| {
+        let mut output = Vec::new();
+        // Runtime errors such as abort() are part of the expected behaviour
+        // of some programs; only their output is compared.
+        let _ = Interpreter::new(
+            program,
+            hierarchy,
+            Box::new(input.as_bytes()),
+            Box::new(&mut output),
+        )
+        .run();
+        String::from_utf8(output).unwrap()
+    })
+}
+
+#[test]
+fn test_files() {
+    check_examples(|example| {
+        on_large_stack(|| {
+            run(&example.source_code, example.name(), &example.input)
+        })
+    });
+}
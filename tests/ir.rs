@@ -0,0 +1,142 @@
+mod common;
+
+use common::{examples, on_large_stack, with_checked, Example};
+use coolc::ir::{
+    evaluate, lower, parse_module, verify, Dumps, Module, Pass, Pipeline,
+};
+use std::path::Path;
+use std::process::Command;
+
+fn lower_example(example: &Example) -> Module {
+    with_checked(&example.source_code, example.name(), lower)
+}
+
+// Check that a module is valid and reads back as printed. Registers that
+// passes leave unused are not printed, so only the text is compared.
+fn check(module: &Module, filename: &Path) -> Module {
+    if let Err(err) = verify(module) {
+        panic!("Invalid module, source: {}: {err}", filename.display());
+    }
+    let text = module.to_string();
+    let parsed = match parse_module(&text) {
+        Ok(parsed) => parsed,
+        Err(err) => {
+            panic!("Parse error, source: {}: {err}", filename.display())
+        }
+    };
+    assert!(
+        parsed.to_string() == text,
+        "Round trip mismatch, source: {}",
+        filename.display()
+    );
+    parsed
+}
+
+// Run a module on the input of an example and compare the output with that
+// expected. Runtime errors such as abort() are part of the expected
+// behaviour of some programs; only their output is compared.
+fn check_output(module: &Module, example: &Example, pipeline: &str) {
+    let produced = on_large_stack(|| {
+        let mut output = Vec::new();
+        let _ = evaluate(module, &mut example.input.as_bytes(), &mut output);
+        String::from_utf8(output).unwrap()
+    });
+    assert!(
+        produced == example.expected,
+        "Output mismatch at {pipeline}, source: {}",
+        example.source_filename.display()
+    );
+}
+
+#[test]
+fn test_files() {
+    for example in examples() {
+        let module = lower_example(&example);
+        let filename = &example.source_filename;
+        assert!(
+            check(&module, filename) == module,
+            "Round trip mismatch, source: {}",
+            filename.display()
+        );
+        check_output(&module, &example, "-O0");
+    }
+}
+
+#[test]
+fn test_optimised_files() {
+    for example in examples() {
+        for level in 1..=2 {
+            let mut module = lower_example(&example);
+            Pipeline::level(level).run(
+                &mut module,
+                &Dumps::default(),
+                &mut |_| {},
+            );
+            let module = check(&module, &example.source_filename);
+            for function in module.functions.iter() {
+                assert!(function.is_ssa(), "{}", function.name);
+            }
+            check_output(&module, &example, &format!("-O{level}"));
+        }
+    }
+}
+
+// Each pass keeps the behaviour of programs on its own, after conversion to
+// SSA form.
+#[test]
+fn test_single_passes() {
+    for example in examples() {
+        for pass in Pass::ALL {
+            let mut module = lower_example(&example);
+            Pipeline::new(vec![pass]).run(
+                &mut module,
+                &Dumps::default(),
+                &mut |_| {},
+            );
+            let module = check(&module, &example.source_filename);
+            check_output(&module, &example, pass.name());
+        }
+    }
+}
+
+#[test]
+fn test_command_line() {
+    let source = examples().remove(0).source_filename;
+    let output = Command::new(env!("CARGO_BIN_EXE_coolc"))
+        .arg(&source)
+        .args(["--emit", "ir", "--pass", "copy", "--pass", "dce"])
+        .args(["--dump-before", "copy", "--dump-after", "all"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let module = String::from_utf8(output.stdout).unwrap();
+    let dumps = String::from_utf8(output.stderr).unwrap();
+    assert!(parse_module(&module).is_ok());
+    let functions = module.matches("\nfunction ").count();
+    assert_eq!(dumps.matches("; before copy\n").count(), functions);
+    assert_eq!(dumps.matches("; after copy\n").count(), functions);
+    assert_eq!(dumps.matches("; after dce\n").count(), functions);
+    assert!(!dumps.contains("; after gvn"));
+
+    let optimised = Command::new(env!("CARGO_BIN_EXE_coolc"))
+        .arg(&source)
+        .args(["--emit", "ir", "-O", "2", "--pass-stats"])
+        .output()
+        .unwrap();
+    assert!(optimised.status.success());
+    assert!(optimised.stdout.len() < module.len());
+    let statistics = String::from_utf8(optimised.stderr).unwrap();
+    assert!(statistics.contains(" call sites, leaving "));
+    assert!(statistics.contains("\nInlined "));
+
+    for options in [&["-O", "2"][..], &["--pass", "dce"], &["--pass-stats"]] {
+        let rejected = Command::new(env!("CARGO_BIN_EXE_coolc"))
+            .arg(&source)
+            .args(["--emit", "c"])
+            .args(options)
+            .output()
+            .unwrap();
+        assert!(!rejected.status.success(), "Accepted {options:?}");
+        assert!(rejected.stdout.is_empty());
+    }
+}
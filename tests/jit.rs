@@ -0,0 +1,171 @@
+mod common;
+
+use common::{
+    check_examples, error_program, examples, on_large_stack, run_command,
+    temp_path, with_checked,
+};
+use coolc::jit::Jit;
+use std::fs::{remove_file, write};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+// Compiles and runs a program in-process, returning its output and the
+// error it fails with and its exit status, if it does.
+fn run(
+    source_code: &str,
+    filename: &str,
+    input: &str,
+) -> (String, Option<(String, i32)>) {
+    on_large_stack(|| {
+        with_checked(source_code, filename, |program, hierarchy| {
+            let jit = Jit::new(program, hierarchy).unwrap();
+            let mut output = Vec::new();
+            let result = jit.run(&mut input.as_bytes(), &mut output);
+            let error =
+                result.err().map(|err| (err.to_string(), err.exit_code()));
+            (String::from_utf8(output).unwrap(), error)
+        })
+    })
+}
+
+// Runs a program with `coolc run --jit`, returning its output, error output
+// and exit status.
+fn run_with(
+    source_filename: &Path,
+    input: &str,
+    options: &[&str],
+) -> (String, String, i32) {
+    run_command(
+        Command::new(env!("CARGO_BIN_EXE_coolc"))
+            .args(["run", "--jit"])
+            .args(options)
+            .arg(source_filename),
+        input,
+    )
+}
+
+#[test]
+fn test_files() {
+    check_examples(|example| {
+        run(&example.source_code, example.name(), &example.input).0
+    });
+}
+
+#[test]
+fn test_runtime_errors() {
+    for (body, status, error) in [
+        (
+            "let a : A in a.f()",
+            5,
+            "errors.cl:4: Dispatch to void calling method f.",
+        ),
+        (
+            "let a : A in a@A.f()",
+            6,
+            "errors.cl:4: Static dispatch to void calling method f.",
+        ),
+        (
+            "case let a : A in a of o : Object => 0; esac",
+            7,
+            "errors.cl:4: Match on void in case statement.",
+        ),
+        (
+            "case self of a : A => 0; esac",
+            8,
+            "errors.cl:4: No match in case statement for Class Main.",
+        ),
+        ("1 / 0", 9, "errors.cl:4: Division by zero."),
+        (
+            "\"abc\".substr(2, 2)",
+            10,
+            "errors.cl:4: Index out of range in substr.",
+        ),
+        ("abort()", 11, "Abort called from class Main"),
+        ("main()", 20, "Stack overflow."),
+    ] {
+        let (_, error_status) = run(&error_program(body), "errors.cl", "");
+        let (produced, produced_status) = error_status.expect(body);
+        assert_eq!(produced_status, status, "Exit status of {body}");
+        assert!(produced.ends_with(error), "Error of {body}: {produced}");
+    }
+
+    // The process exits with the status of the error.
+    let source_filename = temp_path("jit", "errors.cl").with_extension("cl");
+    write(&source_filename, error_program("1 / 0")).unwrap();
+    let (_, produced, status) = run_with(&source_filename, "", &[]);
+    remove_file(&source_filename).unwrap();
+    assert_eq!(status, 9);
+    assert!(produced.ends_with(":4: Division by zero.\n"), "{produced}");
+}
+
+#[test]
+fn test_input() {
+    let source_filename = temp_path("jit", "input.cl").with_extension("cl");
+    write(
+        &source_filename,
+        "\
+class Main inherits IO {
+  main() : Object {
+    let s : String <- in_string(), i : Int <- in_int() in {
+      out_string(s.concat(\"|\"));
+      out_int(i * 2);
+      out_string(\"|\".concat(in_string()).concat(\"|\"));
+    }
+  };
+};
+",
+    )
+    .unwrap();
+    let (output, _, status) =
+        run_with(&source_filename, "line one\r\n  -21 x\n", &[]);
+    remove_file(&source_filename).unwrap();
+    assert_eq!(status, 0);
+    assert_eq!(output, "line one|-42||");
+}
+
+#[test]
+fn test_interpreter_fallback() {
+    let mut source_filename = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    source_filename.push("tests/resources/hello_world.cool");
+    let output = Command::new(env!("CARGO_BIN_EXE_coolc"))
+        .args(["run", "--jit", "--max-steps", "1000"])
+        .arg(&source_filename)
+        .output()
+        .unwrap();
+    let error = String::from_utf8(output.stderr).unwrap();
+    assert!(error.contains("interpreting instead"), "{error}");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "Hello, World.\n");
+}
+
+// Boxing every Int and Bool gives the same output, and makes the arithmetic
+// of arith.cool allocate.
+#[test]
+fn test_boxed() {
+    let allocations = |error: &str| -> u64 {
+        let line = error.lines().find(|line| line.starts_with("Allocated "));
+        let words: Vec<&str> = line.expect("allocations").split(' ').collect();
+        words[1].parse().unwrap()
+    };
+
+    for example in examples() {
+        let source_filename = &example.source_filename;
+        let (unboxed, unboxed_error, _) =
+            run_with(source_filename, &example.input, &["--jit-allocations"]);
+        let (boxed, boxed_error, _) = run_with(
+            source_filename,
+            &example.input,
+            &["--jit-allocations", "--jit-boxed"],
+        );
+        assert_eq!(
+            unboxed,
+            boxed,
+            "Output mismatch, source: {}",
+            source_filename.display()
+        );
+        if example.name() == "arith.cool" {
+            let unboxed = allocations(&unboxed_error);
+            let boxed = allocations(&boxed_error);
+            assert!(10 * unboxed < boxed, "{unboxed} and {boxed}");
+        }
+    }
+}
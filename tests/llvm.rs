@@ -0,0 +1,59 @@
+mod common;
+
+use common::{available, check_examples, run_command, temp_path, with_checked};
+use coolc::codegen::emit_llvm;
+use std::fs::{remove_file, write};
+use std::process::Command;
+
+// The major version of the LLVM tools, from `opt --version`.
+fn llvm_version() -> Option<u32> {
+    let output = Command::new("opt").arg("--version").output().ok()?;
+    let text = String::from_utf8_lossy(&output.stdout);
+    let version = text.split("LLVM version ").nth(1)?;
+    version.split('.').next()?.trim().parse().ok()
+}
+
+// Compiles a program to LLVM IR, verifies and runs it with lli, returning
+// its output. The flags make the tools read opaque pointers.
+fn run(
+    source_code: &str,
+    filename: &str,
+    input: &str,
+    flags: &[&str],
+) -> String {
+    let module = temp_path("llvm", filename).with_extension("ll");
+    write(&module, with_checked(source_code, filename, emit_llvm)).unwrap();
+    let status = Command::new("opt")
+        .args(flags)
+        .args(["-verify", "-disable-output"])
+        .arg(&module)
+        .status()
+        .unwrap();
+    assert!(status.success(), "invalid module for {filename}");
+
+    let mut lli = Command::new("lli");
+    lli.args(flags).arg(&module);
+    let (output, _, _) = run_command(&mut lli, input);
+    remove_file(&module).unwrap();
+    output
+}
+
+#[test]
+fn test_files() {
+    if !available("opt") || !available("lli") {
+        eprintln!("Skipping LLVM tests: no opt or lli.");
+        return;
+    }
+    // Opaque pointers are the default from LLVM 15.
+    let flags: &[&str] = match llvm_version() {
+        Some(14) => &["-opaque-pointers"],
+        Some(version) if version > 14 => &[],
+        _ => {
+            eprintln!("Skipping LLVM tests: LLVM 14 or later is needed.");
+            return;
+        }
+    };
+    check_examples(|example| {
+        run(&example.source_code, example.name(), &example.input, flags)
+    });
+}
@@ -0,0 +1,84 @@
+mod common;
+
+use common::{
+    available, check_examples, error_program, run_command, temp_path,
+    with_checked,
+};
+use coolc::codegen::{emit_x86_64, link_x86_64, Representation};
+use std::fs::remove_file;
+use std::process::Command;
+
+// Compiles a program to an executable and runs it, returning its output,
+// error output and exit status.
+fn run(
+    source_code: &str,
+    filename: &str,
+    input: &str,
+    representation: Representation,
+) -> (String, String, i32) {
+    let assembly = with_checked(source_code, filename, |program, hierarchy| {
+        emit_x86_64(program, hierarchy, representation)
+    });
+    let executable = temp_path("native", filename);
+    link_x86_64(&assembly, &executable).unwrap();
+    let result = run_command(&mut Command::new(&executable), input);
+    remove_file(&executable).unwrap();
+    result
+}
+
+fn can_link() -> bool {
+    if !cfg!(all(target_arch = "x86_64", target_os = "linux"))
+        || !available("cc")
+    {
+        eprintln!("Skipping native tests: no x86-64 Linux C compiler.");
+        return false;
+    }
+    true
+}
+
+#[test]
+fn test_files() {
+    if !can_link() {
+        return;
+    }
+    for representation in [Representation::Boxed, Representation::Unboxed] {
+        check_examples(|example| {
+            run(
+                &example.source_code,
+                example.name(),
+                &example.input,
+                representation,
+            )
+            .0
+        });
+    }
+}
+
+#[test]
+fn test_runtime_errors() {
+    if !can_link() {
+        return;
+    }
+    for (body, status, error) in [
+        (
+            "let a : A in a.f()",
+            5,
+            "4: Dispatch to void calling method f.\n",
+        ),
+        (
+            "let a : A in a@A.f()",
+            6,
+            "4: Static dispatch to void calling method f.\n",
+        ),
+        ("1 / 0", 9, "4: Division by zero.\n"),
+    ] {
+        let (_, produced, produced_status) = run(
+            &error_program(body),
+            "errors.cl",
+            "",
+            Representation::Unboxed,
+        );
+        assert_eq!(produced_status, status, "Exit status of {body}");
+        assert!(produced.ends_with(error), "Error of {body}: {produced}");
+    }
+}
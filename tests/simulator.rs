@@ -0,0 +1,46 @@
+mod common;
+
+use common::{check_examples, with_checked};
+use coolc::codegen::{emit_mips, Collector};
+use coolc::simulator::{assemble, Simulator, RUNTIME};
+
+// Compiles a program to MIPS and runs it on the simulator, returning its
+// output.
+fn run(
+    source_code: &str,
+    filename: &str,
+    input: &str,
+    collector: Collector,
+) -> String {
+    let assembly = with_checked(source_code, filename, |program, hierarchy| {
+        emit_mips(program, hierarchy, collector)
+    });
+    let image =
+        assemble(&[("runtime.s", RUNTIME), ("program.s", &assembly)]).unwrap();
+
+    let mut output = Vec::new();
+    // Runtime errors such as abort() are part of the expected behaviour of
+    // some programs; only their output is compared.
+    let _ = Simulator::new(
+        &image,
+        Box::new(input.as_bytes()),
+        Box::new(&mut output),
+        Box::new(Vec::new()),
+    )
+    .run();
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn test_files() {
+    for collector in [Collector::None, Collector::Generational] {
+        check_examples(|example| {
+            run(
+                &example.source_code,
+                example.name(),
+                &example.input,
+                collector,
+            )
+        });
+    }
+}
@@ -0,0 +1,67 @@
+mod common;
+
+use common::{on_large_stack, with_checked};
+use coolc::bytecode::{compile, Machine};
+use coolc::hierarchy::ClassHierarchy;
+use coolc::interpreter::{Interpreter, Transcript};
+use coolc::ptree::Program;
+use std::fs::{read_dir, read_to_string};
+use std::path::{Path, PathBuf};
+
+// Replays a transcript with both engines, returning their errors.
+fn replay(source_filename: &Path, transcript: &Transcript) -> [String; 2] {
+    let source_code = read_to_string(source_filename).unwrap();
+    let filename = source_filename.file_name().unwrap().to_str().unwrap();
+    with_checked(&source_code, filename, |program, hierarchy| {
+        replay_checked(program, hierarchy, transcript)
+    })
+}
+
+fn replay_checked(
+    program: &Program,
+    hierarchy: &ClassHierarchy,
+    transcript: &Transcript,
+) -> [String; 2] {
+    let mut interpreter = Interpreter::new(
+        program,
+        hierarchy,
+        Box::new("".as_bytes()),
+        Box::new(Vec::new()),
+    );
+    interpreter.replay(transcript.clone());
+    let interpreted = interpreter.run().err();
+
+    let module = compile(program, hierarchy);
+    let mut machine =
+        Machine::new(&module, Box::new("".as_bytes()), Box::new(Vec::new()));
+    machine.replay(transcript.clone());
+    let executed = machine.run().err();
+
+    [interpreted, executed].map(|err| match err {
+        Some(err) => err.to_string(),
+        None => String::new(),
+    })
+}
+
+#[test]
+fn test_transcripts() {
+    let mut dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+    dir.push("tests/resources");
+
+    for entry in read_dir(dir).unwrap() {
+        let filename = entry.unwrap().path();
+        if filename.extension().unwrap() == "transcript" {
+            let source_filename = filename.with_extension("cool");
+            let transcript: Transcript =
+                read_to_string(&filename).unwrap().parse().unwrap();
+            let errors =
+                on_large_stack(|| replay(&source_filename, &transcript));
+            assert_eq!(
+                errors,
+                ["", ""],
+                "Transcript mismatch, source: {}",
+                source_filename.display()
+            );
+        }
+    }
+}
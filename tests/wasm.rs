@@ -0,0 +1,211 @@
+mod common;
+
+use common::{check_examples, error_program, with_checked};
+use coolc::codegen::{assemble_wasm, emit_wasm};
+use wasmi::core::Trap;
+use wasmi::{
+    Caller, Config, Engine, Extern, Linker, Module, StackLimits, Store,
+};
+
+// The state of a program behind the WASI functions it imports.
+struct Host {
+    input: Vec<u8>,
+    read: usize,
+    output: Vec<u8>,
+    error: Vec<u8>,
+}
+
+fn memory(caller: &Caller<Host>) -> wasmi::Memory {
+    caller
+        .get_export("memory")
+        .and_then(Extern::into_memory)
+        .unwrap()
+}
+
+fn word(caller: &Caller<Host>, address: i32) -> usize {
+    let mut bytes = [0; 4];
+    memory(caller)
+        .read(caller, address as usize, &mut bytes)
+        .unwrap();
+    u32::from_le_bytes(bytes) as usize
+}
+
+fn set_word(caller: &mut Caller<Host>, address: i32, value: usize) {
+    let bytes = (value as u32).to_le_bytes();
+    memory(caller)
+        .write(caller, address as usize, &bytes)
+        .unwrap();
+}
+
+// Implements fd_write, fd_read and proc_exit of WASI preview 1, collecting
+// standard output and standard error.
+fn linker(engine: &Engine) -> Linker<Host> {
+    let mut linker = Linker::new(engine);
+    linker
+        .func_wrap(
+            "wasi_snapshot_preview1",
+            "fd_write",
+            |mut caller: Caller<Host>,
+             fd: i32,
+             iovs: i32,
+             count: i32,
+             written: i32|
+             -> i32 {
+                let mut total = 0;
+                for iov in 0..count {
+                    let address = word(&caller, iovs + 8 * iov);
+                    let length = word(&caller, iovs + 8 * iov + 4);
+                    let mut bytes = vec![0; length];
+                    memory(&caller).read(&caller, address, &mut bytes).unwrap();
+                    match fd {
+                        1 => caller.data_mut().output.extend(bytes),
+                        _ => caller.data_mut().error.extend(bytes),
+                    }
+                    total += length;
+                }
+                set_word(&mut caller, written, total);
+                0
+            },
+        )
+        .unwrap();
+    linker
+        .func_wrap(
+            "wasi_snapshot_preview1",
+            "fd_read",
+            |mut caller: Caller<Host>,
+             _fd: i32,
+             iovs: i32,
+             count: i32,
+             read: i32|
+             -> i32 {
+                let mut total = 0;
+                for iov in 0..count {
+                    let address = word(&caller, iovs + 8 * iov);
+                    let length = word(&caller, iovs + 8 * iov + 4);
+                    let host = caller.data();
+                    let end = host.input.len().min(host.read + length);
+                    let bytes = host.input[host.read..end].to_vec();
+                    caller.data_mut().read = end;
+                    memory(&caller)
+                        .write(&mut caller, address, &bytes)
+                        .unwrap();
+                    total += bytes.len();
+                }
+                set_word(&mut caller, read, total);
+                0
+            },
+        )
+        .unwrap();
+    linker
+        .func_wrap(
+            "wasi_snapshot_preview1",
+            "proc_exit",
+            |status: i32| -> Result<(), Trap> { Err(Trap::i32_exit(status)) },
+        )
+        .unwrap();
+    linker
+}
+
+// Compiles a program to a module and runs it with wasmi, returning its
+// output, error output and exit status.
+fn run(
+    source_code: &str,
+    filename: &str,
+    input: &str,
+) -> (String, String, i32) {
+    let wasm = assemble_wasm(&with_checked(source_code, filename, emit_wasm));
+
+    // Some examples recurse deeply.
+    let mut config = Config::default();
+    config
+        .set_stack_limits(StackLimits::new(1 << 10, 1 << 24, 1 << 20).unwrap());
+    let engine = Engine::new(&config);
+    let module = Module::new(&engine, &wasm[..]).unwrap();
+    let host = Host {
+        input: input.as_bytes().to_vec(),
+        read: 0,
+        output: Vec::new(),
+        error: Vec::new(),
+    };
+    let mut store = Store::new(&engine, host);
+    let instance = linker(&engine)
+        .instantiate(&mut store, &module)
+        .unwrap()
+        .start(&mut store)
+        .unwrap();
+    let start = instance.get_typed_func::<(), ()>(&store, "_start").unwrap();
+    let status = match start.call(&mut store, ()) {
+        Ok(()) => 0,
+        Err(trap) => trap.i32_exit_status().expect("exit, not a trap"),
+    };
+    let host = store.into_data();
+    (
+        String::from_utf8(host.output).unwrap(),
+        String::from_utf8(host.error).unwrap(),
+        status,
+    )
+}
+
+#[test]
+fn test_files() {
+    check_examples(|example| {
+        run(&example.source_code, example.name(), &example.input).0
+    });
+}
+
+#[test]
+fn test_runtime_errors() {
+    for (body, status, error) in [
+        (
+            "let a : A in a.f()",
+            5,
+            "test.cl:4: Dispatch to void calling method f.\n",
+        ),
+        (
+            "let a : A in a@A.f()",
+            6,
+            "test.cl:4: Static dispatch to void calling method f.\n",
+        ),
+        (
+            "case let a : A in a of o : Object => 0; esac",
+            7,
+            "test.cl:4: Match on void in case statement.\n",
+        ),
+        (
+            "case self of a : A => 0; esac",
+            8,
+            "No match in case statement for Class Main.\n",
+        ),
+        ("1 / 0", 9, "test.cl:4: Division by zero.\n"),
+        (
+            "\"abc\".substr(2, 2)",
+            10,
+            "Index out of range in substr.\n",
+        ),
+        ("abort()", 11, "Abort called from class Main\n"),
+    ] {
+        let (_, produced, produced_status) =
+            run(&error_program(body), "test.cl", "");
+        assert_eq!(produced_status, status, "Exit status of {body}");
+        assert_eq!(produced, error, "Error of {body}");
+    }
+}
+
+#[test]
+fn test_input() {
+    let source_code = "\
+class Main inherits IO {
+  main() : Object {
+    let s : String <- in_string(), i : Int <- in_int() in {
+      out_string(s.concat(\"|\"));
+      out_int(i * 2);
+      out_string(\"|\".concat(in_string()).concat(\"|\"));
+    }
+  };
+};
+";
+    let (output, _, status) =
+        run(source_code, "test.cl", "line one\r\n  -21 x\n");
+    assert_eq!(status, 0);
+    assert_eq!(output, "line one|-42||");
+}